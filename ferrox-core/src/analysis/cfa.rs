@@ -0,0 +1,438 @@
+//! Control flow analysis, responsible for finding functions and splitting them up into basic blocks.
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::analysis::frame::Frame;
+use crate::format::Permissions;
+use crate::processor::gekko::Field::*;
+use crate::processor::gekko::Flow;
+use crate::program::{BasicBlock, Edge, EdgeKind, Function, JumpTable, Program, Xref, XrefKind};
+
+/// Runs control flow analysis over every executable segment, replacing any existing functions.
+pub fn analyze(program: &mut Program) {
+    program.functions.clear();
+
+    // Seed with everything we're confident is a function start: the entry point, anything with an exception
+    // table, any names we've been given, and every `bl` target. Knowing these up front means tail calls don't
+    // depend on analysis order.
+    let mut starts = BTreeSet::new();
+    starts.extend(program.entry_point);
+    starts.extend(program.exception_tables.keys().copied().filter(|&address| program.is_code(address)));
+    starts.extend(program.names.keys().copied().filter(|&address| program.is_code(address)));
+    for (start, end) in code_ranges(program) {
+        for address in (start..end).step_by(4) {
+            if let Some(Flow::Branch { target, link: true, .. }) =
+                program.instruction(address).map(|instruction| instruction.flow())
+            {
+                if program.is_code(target) {
+                    starts.insert(target);
+                }
+            }
+        }
+    }
+
+    let mut covered = BTreeMap::new();
+    let mut worklist: Vec<u32> = starts.iter().copied().collect();
+    drain(program, &mut worklist, &mut starts, &mut covered);
+
+    // Anything left over that still looks like code is most likely only called through a pointer, so treat
+    // the first valid instruction after each gap as a new function.
+    for (start, end) in code_ranges(program) {
+        let mut address = start;
+        while address < end {
+            // Exception tables give the exact size, including anything only the unwinder reaches
+            if let Some((_, table)) = program.exception_tables.range(..=address).next_back() {
+                if address < table.function + table.size {
+                    address = table.function + table.size;
+                    continue;
+                }
+            }
+            if let Some((_, &block_end)) = covered.range(..=address).next_back() {
+                if address < block_end {
+                    address = block_end;
+                    continue;
+                }
+            }
+            // Gaps between a function's blocks, like a `nop` after a branch, still belong to it
+            if let Some((_, function)) = program.functions.range(..=address).next_back() {
+                if address < function.end {
+                    address = function.end;
+                    continue;
+                }
+            }
+            // Skip over alignment padding and anything that isn't an instruction
+            if program.read_u32(address).is_some_and(|code| code != 0)
+                && program.instruction(address).is_some()
+            {
+                starts.insert(address);
+                worklist.push(address);
+                drain(program, &mut worklist, &mut starts, &mut covered);
+                continue;
+            }
+            address += 4;
+        }
+    }
+}
+
+/// Explores every function in the worklist, along with anything they call that we haven't seen yet.
+fn drain(
+    program: &mut Program, worklist: &mut Vec<u32>, starts: &mut BTreeSet<u32>,
+    covered: &mut BTreeMap<u32, u32>,
+) {
+    while let Some(start) = worklist.pop() {
+        if program.functions.contains_key(&start) {
+            continue;
+        }
+        let (function, xrefs) = explore(program, start, starts);
+        for (to, xref) in xrefs {
+            if xref.kind == XrefKind::Call && program.is_code(to) && starts.insert(to) {
+                worklist.push(to);
+            }
+            program.add_xref(to, xref);
+        }
+        covered.extend(function.blocks.values().map(|block| (block.start, block.end)));
+        program.functions.insert(start, function);
+    }
+}
+
+fn code_ranges(program: &Program) -> Vec<(u32, u32)> {
+    program
+        .segments
+        .iter()
+        .filter(|segment| segment.permissions.contains(Permissions::EXECUTE))
+        .map(|segment| (segment.address, segment.address + segment.size))
+        .collect()
+}
+
+/// Follows control flow from `start`, stopping at returns, tail calls and other known functions.
+fn explore(program: &Program, start: u32, starts: &BTreeSet<u32>) -> (Function, Vec<(u32, Xref)>) {
+    let mut visited = BTreeMap::new();
+    let mut leaders = BTreeSet::from([start]);
+    let mut worklist = vec![start];
+    // Catch blocks are only ever jumped to by the unwinder, so nothing else leads to them
+    if let Some(table) = program.exception_tables.get(&start) {
+        for handler in table.handlers().filter(|&handler| program.is_code(handler)) {
+            leaders.insert(handler);
+            worklist.push(handler);
+        }
+    }
+    let mut xrefs = Vec::new();
+    let mut jump_tables = BTreeMap::new();
+    let is_other_function = |address: u32| address != start && starts.contains(&address);
+
+    while let Some(mut address) = worklist.pop() {
+        loop {
+            if visited.contains_key(&address) {
+                // Fell into code we've already seen, so it has to start a block
+                leaders.insert(address);
+                break;
+            }
+            let Some(instruction) = program.instruction(address) else {
+                break;
+            };
+            let flow = instruction.flow();
+            visited.insert(address, flow);
+            let next = address + 4;
+
+            match flow {
+                Flow::Normal | Flow::Indirect { link: true, .. } => (),
+                Flow::Branch { target, link: true, .. } => {
+                    xrefs.push((target, Xref { from: address, kind: XrefKind::Call }));
+                }
+                Flow::Branch { target, conditional, link: false } => {
+                    xrefs.push((target, Xref { from: address, kind: XrefKind::Jump }));
+                    if !is_other_function(target) && program.is_code(target) {
+                        leaders.insert(target);
+                        worklist.push(target);
+                    }
+                    if conditional {
+                        leaders.insert(next);
+                        worklist.push(next);
+                    }
+                    break;
+                }
+                Flow::Indirect { conditional: false, link: false } => {
+                    if let Some(table) = jump_table(program, address) {
+                        for &target in &table.targets {
+                            xrefs.push((target, Xref { from: address, kind: XrefKind::Jump }));
+                            if !is_other_function(target) {
+                                leaders.insert(target);
+                                worklist.push(target);
+                            }
+                        }
+                        jump_tables.insert(address, table);
+                    }
+                    break;
+                }
+                Flow::Indirect { conditional, link: false } | Flow::Return { conditional } => {
+                    if conditional {
+                        leaders.insert(next);
+                        worklist.push(next);
+                    }
+                    break;
+                }
+            }
+
+            // Running into another function means this one tail calls it by falling through
+            if is_other_function(next) {
+                break;
+            }
+            address = next;
+        }
+    }
+
+    // Now that we know every leader, cut the visited instructions up into blocks
+    let mut blocks = BTreeMap::new();
+    let mut current: Option<BasicBlock> = None;
+    let addresses: Vec<(u32, Flow)> = visited.iter().map(|(&address, &flow)| (address, flow)).collect();
+    for (n, &(address, flow)) in addresses.iter().enumerate() {
+        let block = current.get_or_insert_with(|| BasicBlock {
+            start: address,
+            end: address,
+            successors: Vec::new(),
+        });
+        block.end = address + 4;
+
+        let next = address + 4;
+        let next_is_leader =
+            addresses.get(n + 1).is_none_or(|&(following, _)| following != next || leaders.contains(&next));
+        let ends_block = match flow {
+            Flow::Normal | Flow::Indirect { link: true, .. } | Flow::Branch { link: true, .. } => {
+                if next_is_leader && visited.contains_key(&next) {
+                    block.successors.push(Edge { target: next, kind: EdgeKind::Unconditional });
+                }
+                next_is_leader
+            }
+            Flow::Branch { target, conditional, link: false } => {
+                let kind = if conditional {
+                    EdgeKind::True
+                } else {
+                    EdgeKind::Unconditional
+                };
+                if visited.contains_key(&target) && !is_other_function(target) {
+                    block.successors.push(Edge { target, kind });
+                }
+                if conditional && visited.contains_key(&next) {
+                    block.successors.push(Edge { target: next, kind: EdgeKind::False });
+                }
+                true
+            }
+            Flow::Indirect { conditional: false, link: false } if jump_tables.contains_key(&address) => {
+                let table = &jump_tables[&address];
+                for &target in &table.targets {
+                    let edge = Edge { target, kind: EdgeKind::Switch };
+                    if visited.contains_key(&target)
+                        && !is_other_function(target)
+                        && !block.successors.contains(&edge)
+                    {
+                        block.successors.push(edge);
+                    }
+                }
+                true
+            }
+            Flow::Indirect { conditional, link: false } | Flow::Return { conditional } => {
+                if conditional && visited.contains_key(&next) {
+                    block.successors.push(Edge { target: next, kind: EdgeKind::False });
+                }
+                true
+            }
+        };
+
+        if ends_block {
+            let block = current.take().unwrap();
+            blocks.insert(block.start, block);
+        }
+    }
+
+    let end = match program.exception_tables.get(&start) {
+        Some(table) => table.function + table.size,
+        None => blocks.values().map(|block| block.end).max().unwrap_or(start),
+    };
+    (
+        Function { address: start, end, blocks, jump_tables, frame: Frame::default() },
+        xrefs,
+    )
+}
+
+/// Recognizes the jump table CodeWarrior compiles a `switch` into, working backwards from its `bctr`:
+///
+/// ```text
+/// cmplwi  r0, 5
+/// bgt     default
+/// lis     r3, table@ha
+/// slwi    r0, r0, 2
+/// addi    r3, r3, table@l
+/// lwzx    r0, r3, r0
+/// mtctr   r0
+/// bctr
+/// ```
+///
+/// The instructions in the middle can come in any order, as long as they're all in a straight line.
+fn jump_table(program: &Program, bctr: u32) -> Option<JumpTable> {
+    let (mut target, mut base, mut offset, mut index) = (None, None, None, None);
+    let (mut low, mut high, mut inclusive) = (None, None, true);
+    for address in (bctr.saturating_sub(64)..bctr).step_by(4).rev() {
+        let instruction = program.instruction(address)?;
+        let (rd, ra, rb) = (
+            instruction.field(RD),
+            instruction.field(RA),
+            instruction.field(RB),
+        );
+        match (instruction.form.mnemonic, instruction.flow()) {
+            (_, Flow::Branch { conditional: true, link: false, .. }) if index.is_some() => {
+                // `bge` leaves out the limit itself, `bgt` doesn't
+                let (bo, bi) = (instruction.field(BO), instruction.field(BI));
+                inclusive = !(bi % 4 == 0 && bo & 0x08 == 0);
+            }
+            (_, Flow::Normal) => (),
+            _ => return None,
+        }
+        match instruction.form.mnemonic {
+            "mtspr" if instruction.field(SPR) == 9 && target.is_none() => target = Some(rd),
+            "lwzx" if target == Some(rd) && base.is_none() => (base, offset) = (Some(ra), Some(rb)),
+            "rlwinm"
+                if offset == Some(ra)
+                    && index.is_none()
+                    && (
+                        instruction.field(SH),
+                        instruction.field(MB),
+                        instruction.field(ME),
+                    ) == (2, 0, 29) =>
+            {
+                index = Some(instruction.field(RS));
+            }
+            "addi" if base == Some(rd) && ra == rd && low.is_none() => {
+                low = Some(instruction.field(SIMM) as u16 as i16 as i32 as u32);
+            }
+            "addis" if base == Some(rd) && ra == 0 && high.is_none() => {
+                high = Some(instruction.field(UIMM) << 16)
+            }
+            "cmplwi" if index == Some(ra) => {
+                let table = high?.wrapping_add(low?);
+                let count = instruction.field(UIMM) + inclusive as u32;
+                let targets: Option<Vec<u32>> = (0..count.min(1024))
+                    .map(|n| program.read_u32(table + n * 4).filter(|&target| program.is_code(target)))
+                    .collect();
+                return Some(JumpTable {
+                    address: table,
+                    compare: address,
+                    index: ra as u8,
+                    targets: targets?,
+                });
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{program, TEXT};
+
+    /// A block as `(start, end, successors)`, all relative to [`TEXT`].
+    type Block = (u32, u32, Vec<(u32, EdgeKind)>);
+
+    /// Blocks of the function at `address`.
+    fn blocks(program: &Program, address: u32) -> Vec<Block> {
+        program.functions[&address]
+            .blocks
+            .values()
+            .map(|block| {
+                let successors =
+                    block.successors.iter().map(|edge| (edge.target - TEXT, edge.kind)).collect();
+                (block.start - TEXT, block.end - TEXT, successors)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_blocks_at_branches_and_their_targets() {
+        let program = program(
+            "
+            cmpwi r3, 0
+            beq other
+            li r3, 1
+            b exit
+        other:
+            li r3, 2
+        loop:
+            addi r3, r3, -1
+            cmpwi r3, 0
+            bne loop
+        exit:
+            blr
+            ",
+            &[],
+        );
+        use EdgeKind::*;
+        assert_eq!(
+            blocks(&program, TEXT),
+            [
+                (0x00, 0x08, vec![(0x10, True), (0x08, False)]),
+                (0x08, 0x10, vec![(0x20, Unconditional)]),
+                (0x10, 0x14, vec![(0x14, Unconditional)]),
+                (0x14, 0x20, vec![(0x14, True), (0x20, False)]),
+                (0x20, 0x24, vec![]),
+            ]
+        );
+        assert_eq!(program.functions[&TEXT].end, TEXT + 0x24);
+    }
+
+    #[test]
+    fn finds_calls_and_tail_calls() {
+        let program = program(
+            "
+            mflr r0
+            bl callee
+            mtlr r0
+            b callee
+        callee:
+            b inside
+        inside:
+            blr
+            ",
+            &[],
+        );
+        let starts: Vec<u32> = program.functions.keys().map(|address| address - TEXT).collect();
+        assert_eq!(starts, [0x00, 0x10]);
+        // Calls don't end a block, and branching to another function is a tail call rather than an edge
+        assert_eq!(blocks(&program, TEXT), [(0x00, 0x10, vec![])]);
+        // Branching anywhere else stays inside the function
+        assert_eq!(
+            blocks(&program, TEXT + 0x10),
+            [
+                (0x10, 0x14, vec![(0x14, EdgeKind::Unconditional)]),
+                (0x14, 0x18, vec![])
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_unreachable_code_inside_its_function() {
+        // The `nop` after the `b` is never reached, but it's inside the function rather than the start of
+        // another one
+        let program = program(
+            "
+            cmpwi r3, 0
+            beq other
+            li r3, 1
+            b exit
+            nop
+        other:
+            li r3, 2
+        exit:
+            blr
+        after:
+            li r3, 3
+            blr
+            ",
+            &[],
+        );
+        let function = &program.functions[&TEXT];
+        assert_eq!(function.end, TEXT + 0x1C);
+        let starts: Vec<u32> = program.functions.keys().map(|address| address - TEXT).collect();
+        // Code after the function that nothing reaches still becomes one of its own
+        assert_eq!(starts, [0x00, 0x1C]);
+    }
+}
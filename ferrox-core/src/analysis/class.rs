@@ -0,0 +1,600 @@
+//! C++ class recovery for CodeWarrior binaries. Every class with virtual functions has a vtable, and the only
+//! code that stores its address is the class's own constructors and destructor, which set the vtable pointer
+//! of the object they get in r3:
+//!
+//! ```text
+//! lis     r4, __vt__9CMyObject@ha
+//! addi    r0, r4, __vt__9CMyObject@l
+//! stw     r0, 0(r31)
+//! ```
+//!
+//! A constructor calls the constructors of its bases first, with r3 pointing at where each base is in the
+//! object, which gives the hierarchy. Where the RTTI is linked in, the vtable also leads to the class's name
+//! and its bases.
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use crate::analysis::data::{self, Registers};
+use crate::analysis::frame;
+use crate::demangle;
+use crate::processor::gekko::{Flow, Operand};
+use crate::program::{Function, Program};
+use crate::registry::{Member, Parameter, TypeInfo};
+
+/// A class recovered from its vtable.
+#[derive(Debug, Clone)]
+pub struct Class {
+    pub name: String,
+    /// Address of its vtable
+    pub vtable: u32,
+    /// Offset of the vtable pointer in objects of the class
+    pub vtable_offset: u32,
+    pub rtti: Option<u32>,
+    /// Classes it derives from, by name, with their offsets in objects of this class
+    pub bases: Vec<(String, u32)>,
+    pub constructors: Vec<u32>,
+    pub destructor: Option<u32>,
+    /// Implementation of each virtual function, in slot order
+    pub virtual_functions: Vec<u32>,
+    /// Bytes up to the end of the last member that's been seen used
+    pub size: u32,
+}
+
+impl Class {
+    /// Name of the struct type describing the class's vtable.
+    pub fn vtable_type(&self) -> String {
+        format!("{}_vtbl", self.name)
+    }
+}
+
+/// What a function does with the object it's given in r3.
+#[derive(Default)]
+struct Usage {
+    /// Vtables stored into the object, with the offset they're stored at
+    vtables: Vec<(u32, u32)>,
+    /// Functions called with r3 pointing into the object, with the offset it points at
+    calls: Vec<(u32, u32)>,
+    /// Everything loaded or stored through the object: bytes accessed and whether it's a float, by offset
+    fields: BTreeMap<u32, (u32, bool)>,
+}
+
+/// Recovers every class with a vtable, defining a struct type for it and its vtable and typing `this` in its
+/// constructors, destructor and virtual functions.
+pub fn analyze(program: &mut Program) {
+    program.classes.clear();
+    let bases = data::small_data_bases(program);
+    let usages: BTreeMap<u32, Usage> = program
+        .functions
+        .values()
+        .map(|function| (function.address, usage(program, function, bases)))
+        .collect();
+
+    // Whichever vtable goes at the lowest offset is the class's own, any others are for its secondary bases
+    let mut classes: BTreeMap<u32, Class> = BTreeMap::new();
+    for (&function, usage) in &usages {
+        let Some(&(vtable_offset, address)) = usage.vtables.iter().min() else {
+            continue;
+        };
+        let Some(vtable) = data::read_vtable(program, address) else {
+            continue;
+        };
+        let class = classes.entry(address).or_insert_with(|| Class {
+            name: String::new(),
+            vtable: address,
+            vtable_offset,
+            rtti: vtable.rtti,
+            bases: Vec::new(),
+            constructors: Vec::new(),
+            destructor: None,
+            virtual_functions: vtable.functions.clone(),
+            size: 0,
+        });
+        // Destructors are virtual, so they're the one function that both sets the vtable and is in it
+        match vtable.functions.contains(&function) {
+            true => class.destructor = Some(function),
+            false => class.constructors.push(function),
+        }
+    }
+
+    for class in classes.values_mut() {
+        class.name = class_name(program, class);
+    }
+    let names: HashMap<u32, String> = classes
+        .values()
+        .flat_map(|class| class.constructors.iter().map(|&ctor| (ctor, class.name.clone())))
+        .collect();
+    for class in classes.values_mut() {
+        class.bases = find_bases(program, class, &usages, &names);
+    }
+
+    // Bases go first, so their sizes are known by the time anything derived from them is laid out
+    let mut depths: HashMap<String, usize> = HashMap::new();
+    let by_name: HashMap<String, u32> =
+        classes.values().map(|class| (class.name.clone(), class.vtable)).collect();
+    for class in classes.values() {
+        depth(&class.name, &classes, &by_name, &mut depths, &mut BTreeSet::new());
+    }
+    let mut order: Vec<u32> = classes.keys().copied().collect();
+    order.sort_by_key(|vtable| depths.get(&classes[vtable].name).copied().unwrap_or_default());
+
+    // Virtual functions belong to the most basic class that has them, the rest just inherit them
+    let mut owners: HashMap<u32, String> = HashMap::new();
+    for vtable in &order {
+        let class = &classes[vtable];
+        for &function in class.constructors.iter().chain(&class.destructor).chain(&class.virtual_functions) {
+            owners.entry(function).or_insert_with(|| class.name.clone());
+        }
+    }
+
+    for vtable in order {
+        let class = classes.get_mut(&vtable).unwrap();
+        let owned = owners.iter().filter(|(_, owner)| **owner == class.name).map(|(&function, _)| function);
+        let mut fields = BTreeMap::new();
+        for function in owned.collect::<BTreeSet<_>>() {
+            if let Some(usage) = usages.get(&function) {
+                for (&offset, &field) in &usage.fields {
+                    fields.entry(offset).or_insert(field);
+                }
+            }
+        }
+        define_types(program, class, &fields);
+        name_vtable(program, class);
+    }
+    for (function, owner) in owners {
+        type_this(program, function, &owner);
+    }
+    program.classes = classes;
+}
+
+/// Follows what a function does with r3, and anything copied or offset from it.
+fn usage(program: &Program, function: &Function, bases: Registers) -> Usage {
+    let mut usage = Usage::default();
+    // Registers pointing into the object, with the offset they point at
+    let mut this: HashMap<u8, u32> = HashMap::from([(3, 0)]);
+    for block in function.blocks.values() {
+        // Addresses don't carry over between blocks, but the object pointer is usually set up once at the start
+        // and kept in a saved register, so that does
+        let mut registers = bases;
+        for address in (block.start..block.end).step_by(4) {
+            let Some(instruction) = program.instruction(address) else {
+                continue;
+            };
+            let mnemonic = instruction.form.mnemonic;
+            match instruction.operands[..] {
+                [Operand::Gpr(register), Operand::Offset { disp, base }, ..] if this.contains_key(&base) => {
+                    let offset = this[&base].wrapping_add_signed(disp);
+                    let vtable = registers
+                        .get(register.into())
+                        .filter(|&value| mnemonic == "stw" && data::read_vtable(program, value).is_some());
+                    match vtable {
+                        Some(vtable) => usage.vtables.push((offset, vtable)),
+                        None if !mnemonic.ends_with('u') => {
+                            let size = frame::access_size(mnemonic, &instruction.operands[0]);
+                            usage.fields.entry(offset).or_insert((size, false));
+                        }
+                        None => (),
+                    }
+                }
+                [Operand::Fpr(register), Operand::Offset { disp, base }, ..] if this.contains_key(&base) => {
+                    let offset = this[&base].wrapping_add_signed(disp);
+                    let size = frame::access_size(mnemonic, &Operand::Fpr(register));
+                    // Single precision loads and stores don't say so in `access_size`
+                    let size = if mnemonic.starts_with("lfs") || mnemonic.starts_with("stfs") {
+                        4
+                    } else {
+                        size
+                    };
+                    usage.fields.entry(offset).or_insert((size, true));
+                }
+                _ => (),
+            }
+
+            // Work out what the instruction does to the object pointers before the generic tracking forgets
+            let copied = match (mnemonic, &instruction.operands[..]) {
+                ("addi", [Operand::Gpr(rd), Operand::Gpr(ra), Operand::Simm(simm)])
+                    if this.contains_key(ra) =>
+                {
+                    Some((*rd, this[ra].wrapping_add_signed(*simm)))
+                }
+                ("or", [Operand::Gpr(ra), Operand::Gpr(rs), Operand::Gpr(rb)])
+                    if rs == rb && this.contains_key(rs) =>
+                {
+                    Some((*ra, this[rs]))
+                }
+                _ => None,
+            };
+            if let Flow::Branch { target, link: true, .. } = instruction.flow() {
+                if let Some(&offset) = this.get(&3) {
+                    usage.calls.push((offset, target));
+                }
+            }
+
+            registers.step(&instruction);
+            match instruction.operands.first() {
+                Some(Operand::Gpr(register)) if !mnemonic.starts_with("st") => {
+                    this.remove(register);
+                }
+                _ => (),
+            }
+            if mnemonic.ends_with('u') || mnemonic.ends_with("ux") {
+                if let Some(Operand::Offset { base, .. }) = instruction.operands.get(1) {
+                    this.remove(base);
+                }
+            }
+            if instruction.link() {
+                this.retain(|&register, _| !(register == 0 || (3..=12).contains(&register)));
+            }
+            if let Some((register, offset)) = copied {
+                this.insert(register, offset);
+            }
+        }
+    }
+    usage
+}
+
+/// Names a class after its RTTI, or else the demangled names of its constructors, destructor or vtable.
+fn class_name(program: &Program, class: &Class) -> String {
+    if let Some(name) = class.rtti.and_then(|rtti| rtti_name(program, rtti)) {
+        return name;
+    }
+    let symbols = class.constructors.iter().chain(&class.destructor).chain([&class.vtable]);
+    for &address in symbols {
+        if let Some(demangled) = program.names.get(&address).and_then(|name| demangle::demangle(name)) {
+            if !demangled.scope.is_empty() {
+                return demangled.scope.join("::");
+            }
+        }
+    }
+    format!("class_{:08X}", class.vtable)
+}
+
+/// Reads the class name out of CodeWarrior's RTTI, which starts with a pointer to it.
+fn rtti_name(program: &Program, rtti: u32) -> Option<String> {
+    let address = program.read_u32(rtti)?;
+    let segment = program.segment_at(address)?;
+    let bytes = program.bytes(address, (segment.address + segment.size - address).min(256))?;
+    let length = bytes.iter().position(|&byte| byte == 0)?;
+    let name = std::str::from_utf8(&bytes[..length]).ok()?;
+    let valid = |c: char| c.is_ascii_alphanumeric() || "_:<>,* ".contains(c);
+    (!name.is_empty() && name.chars().all(valid)).then(|| name.to_owned())
+}
+
+/// Finds a class's direct bases from the base constructors its constructors call. Classes whose constructors
+/// were inlined don't call any, so fall back to the RTTI, which lists every base along with its offset.
+fn find_bases(
+    program: &Program, class: &Class, usages: &BTreeMap<u32, Usage>, names: &HashMap<u32, String>,
+) -> Vec<(String, u32)> {
+    let mut bases = Vec::new();
+    for constructor in &class.constructors {
+        for (offset, target) in usages.get(constructor).map_or(&[][..], |usage| &usage.calls) {
+            match names.get(target) {
+                Some(base) if *base != class.name && !bases.contains(&(base.clone(), *offset)) => {
+                    bases.push((base.clone(), *offset));
+                }
+                _ => (),
+            }
+        }
+    }
+    if !bases.is_empty() {
+        return bases;
+    }
+
+    // The RTTI lists indirect bases too, so leave out anything another base already derives from
+    let Some(listed) = class.rtti.map(|rtti| rtti_bases(program, rtti)) else {
+        return bases;
+    };
+    let indirect: BTreeSet<u32> =
+        listed.iter().flat_map(|&(rtti, _)| rtti_bases(program, rtti)).map(|(rtti, _)| rtti).collect();
+    listed
+        .into_iter()
+        .filter(|(rtti, _)| !indirect.contains(rtti))
+        .filter_map(|(rtti, offset)| Some((rtti_name(program, rtti)?, offset)))
+        .collect()
+}
+
+/// Bases listed in CodeWarrior's RTTI, which has a pointer to them after the name: pairs of each base's RTTI
+/// and its offset, up to a null RTTI.
+fn rtti_bases(program: &Program, rtti: u32) -> Vec<(u32, u32)> {
+    let mut bases = Vec::new();
+    let Some(list) = program.read_u32(rtti + 4).filter(|&list| list != 0) else {
+        return bases;
+    };
+    for entry in (0..16).map(|index| list + index * 8) {
+        match (program.read_u32(entry), program.read_u32(entry + 4)) {
+            (Some(base), Some(offset)) if base != 0 && program.segment_at(base).is_some() => {
+                bases.push((base, offset));
+            }
+            _ => break,
+        }
+    }
+    bases
+}
+
+/// How many levels of bases a class has, with classes that turn out to derive from themselves counting as none.
+fn depth(
+    name: &str, classes: &BTreeMap<u32, Class>, by_name: &HashMap<String, u32>,
+    depths: &mut HashMap<String, usize>, visiting: &mut BTreeSet<String>,
+) -> usize {
+    if let Some(&depth) = depths.get(name) {
+        return depth;
+    }
+    let Some(class) = by_name.get(name).map(|vtable| &classes[vtable]) else {
+        return 0;
+    };
+    if !visiting.insert(name.to_owned()) {
+        return 0;
+    }
+    let depth = class
+        .bases
+        .iter()
+        .map(|(base, _)| depth(base, classes, by_name, depths, visiting) + 1)
+        .max()
+        .unwrap_or_default();
+    visiting.remove(name);
+    depths.insert(name.to_owned(), depth);
+    depth
+}
+
+/// Defines the struct types for a class and its vtable, unless something else already defined them. Bases
+/// come first, then the vtable pointer, then every other member that's used, named after its offset.
+fn define_types(program: &mut Program, class: &mut Class, fields: &BTreeMap<u32, (u32, bool)>) {
+    let mut members: Vec<Member> = Vec::new();
+    let overlaps = |members: &[Member], offset: u64, size: u64| {
+        members.iter().any(|member| {
+            let member_size = program.types.size_of(&member.type_info).max(1);
+            offset < member.offset + member_size && member.offset < offset + size.max(1)
+        })
+    };
+
+    for (base, offset) in &class.bases {
+        let type_info = TypeInfo::Named { name: base.clone() };
+        if !overlaps(&members, (*offset).into(), program.types.size_of(&type_info)) {
+            let name = match offset {
+                0 => "base".to_owned(),
+                offset => format!("base_{offset:X}"),
+            };
+            members.push(Member { name, offset: (*offset).into(), type_info });
+        }
+    }
+    let vtable_pointer = TypeInfo::pointer_to(TypeInfo::Named { name: class.vtable_type() });
+    if !overlaps(&members, class.vtable_offset.into(), 4) {
+        members.push(Member {
+            name: "__vtable".to_owned(),
+            offset: class.vtable_offset.into(),
+            type_info: vtable_pointer,
+        });
+    }
+    for (&offset, &(size, float)) in fields {
+        let type_info = match (size, float) {
+            (4 | 8, true) => TypeInfo::Float { bits: size * 8 },
+            (1 | 2 | 4, false) => TypeInfo::Integer { bits: size * 8, signed: false },
+            _ => continue,
+        };
+        if !overlaps(&members, offset.into(), size.into()) {
+            members.push(Member { name: format!("field_{offset:X}"), offset: offset.into(), type_info });
+        }
+    }
+    members.sort_by_key(|member| member.offset);
+
+    let end = members
+        .iter()
+        .map(|member| member.offset + program.types.size_of(&member.type_info))
+        .max()
+        .unwrap_or_default();
+    class.size = (end as u32).next_multiple_of(4);
+    if program.types.definition(&class.name).is_none() {
+        let type_info = TypeInfo::Struct { name: class.name.clone(), size: class.size.into(), members };
+        program.types.define(class.name.clone(), type_info);
+    }
+
+    let vtable_type = class.vtable_type();
+    if program.types.definition(&vtable_type).is_none() {
+        let header = match data::read_vtable(program, class.vtable) {
+            Some(vtable) if vtable.header > 0 => vec![
+                Member { name: "rtti".to_owned(), offset: 0, type_info: TypeInfo::pointer() },
+                Member {
+                    name: "offset".to_owned(),
+                    offset: 4,
+                    type_info: TypeInfo::Integer { bits: 32, signed: true },
+                },
+            ],
+            _ => Vec::new(),
+        };
+        let start = header.len() as u64 * 4;
+        let slots = class.virtual_functions.iter().enumerate().map(|(index, &function)| Member {
+            name: format!("vfunc_{index}"),
+            offset: start + index as u64 * 4,
+            type_info: TypeInfo::pointer_to(TypeInfo::Function {
+                name: program.raw_name(function),
+                is_extern: false,
+            }),
+        });
+        let members: Vec<Member> = header.into_iter().chain(slots).collect();
+        let size = start + class.virtual_functions.len() as u64 * 4;
+        program.types.define(
+            vtable_type.clone(),
+            TypeInfo::Struct { name: vtable_type, size, members },
+        );
+    }
+}
+
+/// Gives the vtable and RTTI the names CodeWarrior would have, like `__vt__9CMyObject`, if they only have
+/// automatic ones.
+fn name_vtable(program: &mut Program, class: &Class) {
+    let Some(mangled) = demangle::mangle_class(&class.name) else {
+        return;
+    };
+    let mut symbols = vec![(class.vtable, format!("__vt__{mangled}"))];
+    symbols.extend(class.rtti.map(|rtti| (rtti, format!("__RTTI__{mangled}"))));
+    for (address, name) in symbols {
+        // Automatic names from data analysis end with the address, like `vtbl_80212340`
+        let suffix = format!("_{address:08X}");
+        let automatic = program.names.get(&address).is_none_or(|old| old.ends_with(&suffix));
+        if automatic && !program.names.values().any(|other| *other == name) {
+            program.names.insert(address, name);
+        }
+    }
+}
+
+/// Types the first parameter of a member function as a pointer to its class, if analysis took it for one.
+fn type_this(program: &mut Program, function: u32, class: &str) {
+    let Some(mut prototype) = program.types.prototype(function).cloned() else {
+        return;
+    };
+    let Some(first) = prototype.parameters.first() else {
+        return;
+    };
+    // Floats aren't objects, and named pointers are already typed, usually by a demangled name
+    match &first.type_info {
+        TypeInfo::Float { .. } => return,
+        TypeInfo::Pointer { target: Some(target) } if matches!(**target, TypeInfo::Named { .. }) => return,
+        _ => (),
+    }
+    let this = TypeInfo::pointer_to(TypeInfo::Named { name: class.to_owned() });
+    prototype.parameters[0] = Parameter { name: "this".to_owned(), type_info: this };
+    program.types.set_prototype(function, Some(prototype));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DATA, TEXT};
+
+    const BASE_CTOR: u32 = TEXT + 0x08;
+    const BASE_DTOR: u32 = TEXT + 0x18;
+    const BASE_F: u32 = TEXT + 0x28;
+    const DERIVED_CTOR: u32 = TEXT + 0x30;
+    const DERIVED_DTOR: u32 = TEXT + 0x6C;
+    const DERIVED_F: u32 = TEXT + 0x7C;
+
+    const BASE_RTTI: u32 = DATA + 0x10;
+    const DERIVED_RTTI: u32 = DATA + 0x18;
+    const BASE_VTABLE: u32 = DATA + 0x30;
+    const DERIVED_VTABLE: u32 = DATA + 0x40;
+
+    /// `Derived` derives from `Base`, and both have a constructor, a destructor and one other virtual function.
+    const SOURCE: &str = "
+        bl derived_ctor
+        blr
+    base_ctor:
+        lis r4, 0x80004030@ha
+        addi r4, r4, 0x80004030@l
+        stw r4, 0(r3)
+        blr
+    base_dtor:
+        lis r4, 0x80004030@ha
+        addi r4, r4, 0x80004030@l
+        stw r4, 0(r3)
+        blr
+    base_f:
+        lwz r4, 4(r3)
+        blr
+    derived_ctor:
+        stwu r1, -0x10(r1)
+        mflr r0
+        stw r0, 0x14(r1)
+        stw r31, 0xC(r1)
+        mr r31, r3
+        bl base_ctor
+        lis r4, 0x80004040@ha
+        addi r4, r4, 0x80004040@l
+        stw r4, 0(r31)
+        lwz r5, 8(r31)
+        lwz r0, 0x14(r1)
+        mtlr r0
+        lwz r31, 0xC(r1)
+        addi r1, r1, 0x10
+        blr
+    derived_dtor:
+        lis r4, 0x80004040@ha
+        addi r4, r4, 0x80004040@l
+        stw r4, 0(r3)
+        blr
+    derived_f:
+        blr
+    ";
+
+    /// Names, RTTI and vtables for both classes. Without `rtti`, the vtables have a null RTTI pointer instead.
+    fn data(rtti: bool) -> Vec<u8> {
+        let mut data = vec![0; 0x50];
+        data[..5].copy_from_slice(b"Base\0");
+        data[0x08..0x10].copy_from_slice(b"Derived\0");
+        let mut words = |at: usize, words: &[u32]| {
+            for (index, word) in words.iter().enumerate() {
+                data[at + index * 4..at + index * 4 + 4].copy_from_slice(&word.to_be_bytes());
+            }
+        };
+        words(0x10, &[DATA, 0]);
+        words(0x18, &[DATA + 0x08, DATA + 0x20]);
+        words(0x20, &[BASE_RTTI, 0]);
+        let (base_rtti, derived_rtti) = if rtti { (BASE_RTTI, DERIVED_RTTI) } else { (0, 0) };
+        words(0x30, &[base_rtti, 0, BASE_DTOR, BASE_F]);
+        words(0x40, &[derived_rtti, 0, DERIVED_DTOR, DERIVED_F]);
+        data
+    }
+
+    #[test]
+    fn recovers_classes_from_vtables() {
+        let program = testing::program(SOURCE, &data(true));
+        assert_eq!(program.classes.len(), 2);
+
+        let base = &program.classes[&BASE_VTABLE];
+        assert_eq!(base.name, "Base");
+        assert_eq!(base.vtable_offset, 0);
+        assert_eq!(base.rtti, Some(BASE_RTTI));
+        assert_eq!(base.constructors, [BASE_CTOR]);
+        assert_eq!(base.destructor, Some(BASE_DTOR));
+        assert_eq!(base.virtual_functions, [BASE_DTOR, BASE_F]);
+        assert!(base.bases.is_empty());
+        assert_eq!(base.size, 8);
+
+        let derived = &program.classes[&DERIVED_VTABLE];
+        assert_eq!(derived.name, "Derived");
+        assert_eq!(derived.rtti, Some(DERIVED_RTTI));
+        assert_eq!(derived.constructors, [DERIVED_CTOR]);
+        assert_eq!(derived.destructor, Some(DERIVED_DTOR));
+        assert_eq!(derived.virtual_functions, [DERIVED_DTOR, DERIVED_F]);
+        assert_eq!(derived.bases, [("Base".to_owned(), 0)]);
+        assert_eq!(derived.size, 0xC);
+    }
+
+    #[test]
+    fn names_vtables_and_rtti() {
+        let program = testing::program(SOURCE, &data(true));
+        assert_eq!(program.names[&BASE_VTABLE], "__vt__4Base");
+        assert_eq!(program.names[&BASE_RTTI], "__RTTI__4Base");
+        assert_eq!(program.names[&DERIVED_VTABLE], "__vt__7Derived");
+        assert_eq!(program.names[&DERIVED_RTTI], "__RTTI__7Derived");
+    }
+
+    #[test]
+    fn defines_class_and_vtable_types() {
+        let program = testing::program(SOURCE, &data(true));
+        let Some(TypeInfo::Struct { size, members, .. }) = program.types.definition("Derived") else {
+            panic!("Derived isn't a struct");
+        };
+        assert_eq!(*size, 0xC);
+        let members: Vec<(&str, u64)> =
+            members.iter().map(|member| (member.name.as_str(), member.offset)).collect();
+        assert_eq!(members, [("base", 0), ("field_8", 8)]);
+
+        let Some(TypeInfo::Struct { members, .. }) = program.types.definition("Base_vtbl") else {
+            panic!("Base_vtbl isn't a struct");
+        };
+        let members: Vec<&str> = members.iter().map(|member| member.name.as_str()).collect();
+        assert_eq!(members, ["rtti", "offset", "vfunc_0", "vfunc_1"]);
+    }
+
+    #[test]
+    fn finds_bases_from_constructor_calls_without_rtti() {
+        let mut program = testing::program(SOURCE, &data(false));
+        let derived = &program.classes[&DERIVED_VTABLE];
+        assert_eq!(derived.rtti, None);
+        assert_eq!(derived.bases, [(format!("class_{BASE_VTABLE:08X}"), 0)]);
+
+        // Demangled constructor names stand in for the RTTI's
+        program.names.insert(BASE_CTOR, "__ct__4BaseFv".to_owned());
+        analyze(&mut program);
+        assert_eq!(program.classes[&BASE_VTABLE].name, "Base");
+        assert_eq!(program.classes[&DERIVED_VTABLE].bases, [("Base".to_owned(), 0)]);
+    }
+}
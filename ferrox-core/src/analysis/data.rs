@@ -0,0 +1,538 @@
+//! Data discovery, responsible for finding strings, float constants, pointer tables and vtables in data
+//! segments. Runs after control flow analysis, since it relies on knowing where functions are.
+use std::collections::BTreeSet;
+
+use crate::format::Permissions;
+use crate::processor::gekko::{Field, Instruction, Operand};
+use crate::program::{Program, Xref, XrefKind};
+use crate::registry::TypeInfo;
+use crate::text::TextEncoding;
+
+/// Strings shorter than this are too likely to just be random bytes.
+const MIN_STRING_LENGTH: usize = 4;
+
+/// How an instruction uses an address it computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Access {
+    /// Just calculates the address, usually to pass it somewhere
+    Address,
+    Load,
+    Store,
+    /// Loads a float of this many bits
+    LoadFloat(u32),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Reference {
+    from: u32,
+    to: u32,
+    access: Access,
+}
+
+/// Runs data discovery over every data segment, registering everything found in the type registry.
+pub fn analyze(program: &mut Program) {
+    let bases = small_data_bases(program);
+    let (references, stored) = find_references(program, bases);
+    for reference in &references {
+        if program.segment_at(reference.to).is_some() {
+            program.add_xref(reference.to, Xref { from: reference.from, kind: XrefKind::Data });
+        }
+    }
+
+    // Floats first, since loads tell us their size for certain
+    for reference in &references {
+        if let Access::LoadFloat(bits) = reference.access {
+            if is_data(program, reference.to) && reference.to.is_multiple_of(4) {
+                let prefix = if bits == 32 { "flt" } else { "dbl" };
+                define(program, reference.to, bits / 8, TypeInfo::Float { bits }, prefix);
+            }
+        }
+    }
+
+    find_strings(program);
+    for &address in &stored {
+        find_vtable(program, address);
+    }
+    find_pointer_tables(program);
+}
+
+fn is_data(program: &Program, address: u32) -> bool {
+    program.segment_at(address).is_some_and(|segment| {
+        !segment.permissions.intersects(Permissions::EXECUTE | Permissions::UNINITIALIZED)
+    })
+}
+
+/// Whether any part of `address..address + size` already has a type.
+fn is_defined(program: &Program, address: u32, size: u32) -> bool {
+    let (start, end) = (u64::from(address), u64::from(address) + u64::from(size));
+    program.types.item_containing(start).is_some()
+        || program.types.next_start(start).is_some_and(|next| next < end)
+}
+
+/// Registers a typed item, giving it an automatic name like `str_8032A1B0` unless it already has one.
+fn define(program: &mut Program, address: u32, size: u32, type_info: TypeInfo, prefix: &str) -> bool {
+    if size == 0 || is_defined(program, address, size) {
+        return false;
+    }
+    let start = u64::from(address);
+    program.types.insert(start..start + u64::from(size), type_info);
+    program.names.entry(address).or_insert_with(|| format!("{prefix}_{address:08X}"));
+    true
+}
+
+/// A register's value, and where its high half came from if it was just set by `lis`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Value {
+    pub value: u32,
+    pub high: Option<u32>,
+}
+
+/// An address an instruction calculated or accessed memory at.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Computed {
+    pub address: u32,
+    pub access: Access,
+    /// Register the address was built from
+    pub register: u32,
+    /// What that register held beforehand
+    pub source: Value,
+}
+
+/// Known register values while walking through a function. Only tracks what's needed to resolve addresses
+/// built with `lis`/`addi`/`ori` or relative to the small data bases. Relocation recovery walks code with this
+/// as well, using where high halves came from to pair each `lis` with its low half.
+#[derive(Clone, Copy)]
+pub(crate) struct Registers([Option<Value>; 32]);
+
+impl Registers {
+    pub(crate) fn get(&self, register: u32) -> Option<u32> {
+        self.value(register).map(|value| value.value)
+    }
+
+    pub(crate) fn value(&self, register: u32) -> Option<Value> {
+        self.0[register as usize]
+    }
+
+    fn set(&mut self, register: u32, value: Option<Value>) {
+        self.0[register as usize] = value;
+    }
+
+    /// Keeps the small data bases, even if something we don't understand wrote to them.
+    pub(crate) fn keep_bases(&mut self, bases: &Registers) {
+        for register in [2, 13] {
+            if self.value(register).is_none() {
+                self.set(register, bases.value(register));
+            }
+        }
+    }
+
+    /// Updates the registers for one instruction, returning the address it references if any.
+    pub(crate) fn step(&mut self, instruction: &Instruction) -> Option<Computed> {
+        let rd = instruction.field(Field::RD);
+        let ra = instruction.field(Field::RA);
+        let simm = instruction.field(Field::SIMM) as u16 as i16 as i32;
+        let uimm = instruction.field(Field::UIMM);
+
+        match instruction.form.mnemonic {
+            "addis" => {
+                let high = (simm << 16) as u32;
+                let value = match ra {
+                    0 => Some(Value { value: high, high: Some(instruction.address) }),
+                    _ => self.get(ra).map(|base| Value { value: base.wrapping_add(high), high: None }),
+                };
+                self.set(rd, value);
+                None
+            }
+            "addi" if ra != 0 => {
+                let source = self.value(ra);
+                let value = source.map(|source| source.value.wrapping_add_signed(simm));
+                self.set(rd, value.map(|value| Value { value, high: None }));
+                Some(Computed {
+                    address: value?,
+                    access: Access::Address,
+                    register: ra,
+                    source: source?,
+                })
+            }
+            // Destination and source are swapped for logical operations
+            "ori" => {
+                let source = self.value(rd);
+                let value = source.map(|source| source.value | uimm);
+                self.set(ra, value.map(|value| Value { value, high: None }));
+                Some(Computed {
+                    address: value?,
+                    access: Access::Address,
+                    register: rd,
+                    source: source?,
+                })
+            }
+            "or" if rd == instruction.field(Field::RB) => {
+                self.set(ra, self.value(rd));
+                None
+            }
+            _ => {
+                let reference = instruction.operands.iter().find_map(|operand| match *operand {
+                    Operand::Offset { disp, base } if base != 0 => {
+                        let source = self.value(base.into())?;
+                        let access = match instruction.form.mnemonic {
+                            "lfs" | "lfsu" => Access::LoadFloat(32),
+                            "lfd" | "lfdu" => Access::LoadFloat(64),
+                            mnemonic if mnemonic.starts_with("st") => Access::Store,
+                            _ => Access::Load,
+                        };
+                        let address = source.value.wrapping_add_signed(disp);
+                        Some(Computed { address, access, register: base.into(), source })
+                    }
+                    _ => None,
+                });
+
+                // Anything else that writes a register makes it unknown. Stores don't write their first
+                // operand, but update forms do write their base.
+                let mnemonic = instruction.form.mnemonic;
+                if !mnemonic.starts_with("st") {
+                    if let Some(Operand::Gpr(register)) = instruction.operands.first() {
+                        self.set((*register).into(), None);
+                    }
+                }
+                if mnemonic.ends_with('u') || mnemonic.ends_with("ux") {
+                    self.set(ra, None);
+                }
+                // Calls clobber every volatile register
+                if instruction.link() {
+                    for register in [0].into_iter().chain(3..=12) {
+                        self.set(register, None);
+                    }
+                }
+                reference
+            }
+        }
+    }
+}
+
+/// Finds the values of r2 and r13, which point to the middle of `.sdata2` and `.sdata`. They're set once at
+/// startup, usually in `__init_registers`, so just look for the first time they're given a full address.
+pub(crate) fn small_data_bases(program: &Program) -> Registers {
+    let mut bases = Registers([None; 32]);
+    for function in program.functions.values() {
+        let mut registers = Registers([None; 32]);
+        for address in (function.address..function.end).step_by(4) {
+            let Some(instruction) = program.instruction(address) else {
+                continue;
+            };
+            registers.step(&instruction);
+            // Only the low half finishes the address, `lis` on its own isn't enough
+            if !matches!(instruction.form.mnemonic, "addi" | "ori") {
+                continue;
+            }
+            for register in [2, 13] {
+                let value = registers.get(register);
+                // The bases point 0x8000 past the start of their section, so they may be outside of it
+                let near_segment = |value: u32| {
+                    program.segments.iter().any(|segment| {
+                        value.wrapping_sub(segment.address).wrapping_add(0x8000) < segment.size + 0x10000
+                    })
+                };
+                if bases.get(register).is_none() && value.is_some_and(near_segment) {
+                    bases.set(register, value.map(|value| Value { value, high: None }));
+                }
+            }
+        }
+    }
+    bases
+}
+
+/// Values of `r13` and `r2`, the pointers into `.sdata` and `.sdata2`, if the startup code sets them.
+pub fn small_data_pointers(program: &Program) -> (Option<u32>, Option<u32>) {
+    let bases = small_data_bases(program);
+    (bases.get(13), bases.get(2))
+}
+
+/// Walks every function, returning all references to addresses and every address that was stored to memory
+/// (which is how constructors set up vtable pointers).
+fn find_references(program: &Program, bases: Registers) -> (Vec<Reference>, BTreeSet<u32>) {
+    let mut references = Vec::new();
+    let mut stored = BTreeSet::new();
+    for function in program.functions.values() {
+        for block in function.blocks.values() {
+            // Values don't carry over between blocks, since we don't know which way we came in
+            let mut registers = bases;
+            for address in (block.start..block.end).step_by(4) {
+                let Some(instruction) = program.instruction(address) else {
+                    continue;
+                };
+                if instruction.form.mnemonic == "stw" {
+                    if let Some(value) = registers.get(instruction.field(Field::RS)) {
+                        stored.insert(value);
+                    }
+                }
+                if let Some(computed) = registers.step(&instruction) {
+                    references.push(Reference {
+                        from: address,
+                        to: computed.address,
+                        access: computed.access,
+                    });
+                }
+                registers.keep_bases(&bases);
+            }
+        }
+    }
+    (references, stored)
+}
+
+/// Tries to read a null-terminated string at `address`, returning its encoding and length without the
+/// terminator.
+fn read_string(data: &[u8]) -> Option<(TextEncoding, usize)> {
+    let length = data.iter().position(|&byte| byte == 0)?;
+    let bytes = &data[..length];
+    if bytes.is_empty() {
+        return None;
+    }
+
+    let printable = |c: char| !c.is_control() || matches!(c, '\n' | '\r' | '\t');
+    if bytes.is_ascii() {
+        let text = TextEncoding::Ascii.decode(bytes)?;
+        return (length >= MIN_STRING_LENGTH && text.chars().all(printable))
+            .then_some((TextEncoding::Ascii, length));
+    }
+
+    // Only accept characters that actually show up in Japanese text, since plenty of random bytes happen to
+    // be valid Shift-JIS. Half-width katakana are allowed, but there has to be at least one full-width
+    // character since they're single bytes that are easily mistaken for anything else.
+    let text = TextEncoding::ShiftJis.decode(bytes)?;
+    let full_width =
+        |c: char| matches!(c, '\u{3000}'..='\u{30FF}' | '\u{4E00}'..='\u{9FFF}' | '\u{FF01}'..='\u{FF5E}');
+    let half_width = |c: char| matches!(c, '\u{FF61}'..='\u{FF9F}');
+    let symbol = |c: char| {
+        matches!(
+            c,
+            '…' | '※' | '○' | '●' | '×' | '→' | '←' | '↑' | '↓' | '☆' | '★' | '♪'
+        )
+    };
+    let valid =
+        text.chars().all(|c| (c.is_ascii() && printable(c)) || full_width(c) || half_width(c) || symbol(c));
+    (valid && length >= MIN_STRING_LENGTH && text.chars().any(full_width))
+        .then_some((TextEncoding::ShiftJis, length))
+}
+
+fn find_strings(program: &mut Program) {
+    let segments: Vec<(u32, u32)> = program
+        .segments
+        .iter()
+        .filter(|segment| !segment.permissions.intersects(Permissions::EXECUTE | Permissions::UNINITIALIZED))
+        .map(|segment| (segment.address, segment.size))
+        .collect();
+
+    for (start, size) in segments {
+        let Some(data) = program.bytes(start, size).map(<[u8]>::to_vec) else {
+            continue;
+        };
+        let mut offset = 0;
+        while offset < data.len() {
+            // Strings only start after another string, padding, or somewhere that's referenced
+            let address = start + offset as u32;
+            let boundary = offset == 0 || data[offset - 1] == 0 || program.xrefs.contains_key(&address);
+            if boundary && data[offset] != 0 {
+                if let Some((encoding, length)) = read_string(&data[offset..]) {
+                    let size = length as u32 + 1;
+                    if define(
+                        program,
+                        address,
+                        size,
+                        TypeInfo::String { encoding, length: size.into() },
+                        "str",
+                    ) {
+                        offset += size as usize;
+                        continue;
+                    }
+                }
+            }
+            offset += 1;
+        }
+    }
+}
+
+fn is_function(program: &Program, address: u32) -> bool {
+    program.functions.contains_key(&address)
+}
+
+/// Checks whether a stored address is a vtable, and gives it a type if it is.
+fn find_vtable(program: &mut Program, address: u32) {
+    let Some(vtable) = read_vtable(program, address) else {
+        return;
+    };
+    let count = vtable.header + vtable.functions.len() as u32;
+    let element_type = Box::new(TypeInfo::pointer());
+    define(
+        program,
+        address,
+        count * 4,
+        TypeInfo::Array { element_type, count: count.into() },
+        "vtbl",
+    );
+}
+
+/// What's in a vtable.
+pub(crate) struct Vtable {
+    /// Words before the first virtual function: 2 if it starts with the RTTI pointer and offset, else 0
+    pub header: u32,
+    /// Pointer to the RTTI, if there's a header and the class has any
+    pub rtti: Option<u32>,
+    /// Implementation of every virtual function, in slot order
+    pub functions: Vec<u32>,
+}
+
+/// Reads the vtable at `address`, if that's what it looks like. CodeWarrior lays them out as a pointer to the
+/// RTTI (or 0), the offset of this class in the complete object, and then every virtual function.
+pub(crate) fn read_vtable(program: &Program, address: u32) -> Option<Vtable> {
+    if !is_data(program, address) || !address.is_multiple_of(4) {
+        return None;
+    }
+    let header = (program.read_u32(address), program.read_u32(address + 4));
+    let (header, rtti) = match header {
+        (Some(rtti), Some(0)) if rtti == 0 || is_data(program, rtti) => {
+            (2, Some(rtti).filter(|&rtti| rtti != 0))
+        }
+        _ => (0, None),
+    };
+
+    let mut functions = Vec::new();
+    while let Some(entry) = program
+        .read_u32(address + (header + functions.len() as u32) * 4)
+        .filter(|&entry| is_function(program, entry))
+    {
+        functions.push(entry);
+    }
+    if functions.is_empty() || (header == 0 && functions.len() < 2) {
+        return None;
+    }
+    Some(Vtable { header, rtti, functions })
+}
+
+/// Finds runs of words that all point somewhere in the program, starting at a referenced address. Jump tables
+/// for switch statements show up as these too.
+fn find_pointer_tables(program: &mut Program) {
+    let candidates: Vec<u32> = program
+        .xrefs
+        .keys()
+        .copied()
+        .filter(|&address| address.is_multiple_of(4) && is_data(program, address))
+        .collect();
+
+    for address in candidates {
+        let mut count = 0;
+        loop {
+            let entry = address + count * 4;
+            let points_somewhere = program
+                .read_u32(entry)
+                .is_some_and(|value| value != 0 && program.segment_at(value).is_some());
+            // Stop at the next referenced address, since that's most likely something else
+            if !points_somewhere
+                || (count > 0 && program.xrefs.contains_key(&entry))
+                || is_defined(program, entry, 4)
+            {
+                break;
+            }
+            count += 1;
+        }
+        if count >= 2 {
+            let element_type = Box::new(TypeInfo::pointer());
+            define(
+                program,
+                address,
+                count * 4,
+                TypeInfo::Array { element_type, count: count.into() },
+                "off",
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::program::XrefKind;
+    use crate::testing::{self, DATA, TEXT};
+
+    const HELPER: u32 = TEXT + 0x20;
+
+    /// A program loading a float, a double and a pointer table, with strings and an unused pointer table too.
+    fn program() -> Program {
+        let mut data = vec![0; 0x50];
+        data[..12].copy_from_slice(b"hello world\0");
+        data[0x0C..0x0F].copy_from_slice(b"ab\0");
+        // テスト
+        data[0x10..0x17].copy_from_slice(&[0x83, 0x65, 0x83, 0x58, 0x83, 0x67, 0]);
+        data[0x20..0x24].copy_from_slice(&1.5f32.to_be_bytes());
+        data[0x28..0x30].copy_from_slice(&2.25f64.to_be_bytes());
+        for (index, pointer) in [TEXT, HELPER, DATA, 0, TEXT, HELPER].iter().enumerate() {
+            data[0x30 + index * 4..0x34 + index * 4].copy_from_slice(&pointer.to_be_bytes());
+        }
+        testing::program(
+            "
+            lis r3, 0x80004020@ha
+            lfs f1, 0x80004020@l(r3)
+            lis r4, 0x80004028@ha
+            lfd f2, 0x80004028@l(r4)
+            lis r5, 0x80004030@ha
+            addi r5, r5, 0x80004030@l
+            lwz r6, 0(r5)
+            blr
+        helper:
+            blr
+            ",
+            &data,
+        )
+    }
+
+    fn item(program: &Program, address: u32) -> Option<(u32, &TypeInfo)> {
+        let (end, type_info) = program.types.item_at(address.into())?;
+        Some(((end - u64::from(address)) as u32, type_info))
+    }
+
+    #[test]
+    fn finds_strings() {
+        let program = program();
+        assert_eq!(
+            item(&program, DATA),
+            Some((
+                12,
+                &TypeInfo::String { encoding: TextEncoding::Ascii, length: 12 }
+            ))
+        );
+        assert_eq!(program.names[&DATA], "str_80004000");
+        assert_eq!(item(&program, DATA + 0x0C), None, "too short to be a string");
+        assert_eq!(
+            item(&program, DATA + 0x10),
+            Some((
+                7,
+                &TypeInfo::String { encoding: TextEncoding::ShiftJis, length: 7 }
+            ))
+        );
+    }
+
+    #[test]
+    fn finds_floats_from_how_they_are_loaded() {
+        let program = program();
+        assert_eq!(
+            item(&program, DATA + 0x20),
+            Some((4, &TypeInfo::Float { bits: 32 }))
+        );
+        assert_eq!(program.names[&(DATA + 0x20)], "flt_80004020");
+        assert_eq!(
+            item(&program, DATA + 0x28),
+            Some((8, &TypeInfo::Float { bits: 64 }))
+        );
+        assert_eq!(program.names[&(DATA + 0x28)], "dbl_80004028");
+        assert!(program.xrefs[&(DATA + 0x20)].contains(&Xref { from: TEXT + 4, kind: XrefKind::Data }));
+    }
+
+    #[test]
+    fn finds_pointer_tables() {
+        let program = program();
+        let pointers = TypeInfo::Array { element_type: Box::new(TypeInfo::pointer()), count: 3 };
+        assert_eq!(item(&program, DATA + 0x30), Some((12, &pointers)));
+        assert_eq!(program.names[&(DATA + 0x30)], "off_80004030");
+        // Pointers nothing refers to could be anything
+        assert_eq!(item(&program, DATA + 0x40), None);
+    }
+}
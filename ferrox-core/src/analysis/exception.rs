@@ -0,0 +1,544 @@
+//! CodeWarrior's C++ exception tables. `extabindex` has a 12 byte entry for every function that can be unwound
+//! through: where it starts, how big it is and where its record in `extab` is. The record says which registers
+//! the function saves, so the unwinder can restore them, and lists the ranges of code that need something done
+//! when an exception passes through them: destroying locals that are still alive, or jumping to a catch block.
+//!
+//! DOLs don't keep section names, so `extabindex` is found by its contents instead: a sorted run of entries
+//! that all point at code and at records somewhere in the data.
+use crate::analysis::frame::slot_name;
+use crate::demangle;
+use crate::format::Permissions;
+use crate::program::Program;
+
+/// What `extab` says about one function.
+#[derive(Debug, Clone)]
+pub struct ExceptionTable {
+    pub function: u32,
+    pub size: u32,
+    /// Address of the record in `extab`
+    pub record: u32,
+    /// Callee-saved GPRs the function saves, counting down from r31
+    pub saved_gprs: u8,
+    pub saved_fprs: u8,
+    pub saved_cr: bool,
+    pub has_frame_pointer: bool,
+    pub large_frame: bool,
+    pub ranges: Vec<ExceptionRange>,
+}
+
+impl ExceptionTable {
+    /// Where catch blocks start. Nothing branches to them, the unwinder jumps there.
+    pub fn handlers(&self) -> impl Iterator<Item = u32> + '_ {
+        self.ranges.iter().flat_map(|range| &range.actions).filter_map(|action| match *action {
+            Action::Catch { handler, .. } | Action::Specification { handler, .. } => Some(handler),
+            _ => None,
+        })
+    }
+}
+
+/// A run of code and what has to happen if an exception is thrown while it's running, in order.
+#[derive(Debug, Clone)]
+pub struct ExceptionRange {
+    pub start: u32,
+    pub end: u32,
+    pub actions: Vec<Action>,
+}
+
+/// Where an object an action works on lives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    /// Offset from the stack pointer after the prologue
+    Stack(i16),
+    /// A callee-saved register holding a pointer to it
+    Register(u8),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    DestroyLocal {
+        local: Location,
+        destructor: u32,
+    },
+    /// Destroys the local only if the flag at `condition` is set, for objects constructed conditionally
+    DestroyLocalCond {
+        condition: Location,
+        local: Location,
+        destructor: u32,
+    },
+    DestroyLocalPointer {
+        pointer: Location,
+        destructor: u32,
+    },
+    DestroyLocalArray {
+        array: Location,
+        elements: u16,
+        element_size: u16,
+        destructor: u32,
+    },
+    DestroyBase {
+        object: Location,
+        offset: u32,
+        destructor: u32,
+    },
+    DestroyMember {
+        object: Location,
+        offset: u32,
+        destructor: u32,
+    },
+    DestroyMemberCond {
+        condition: Location,
+        object: Location,
+        offset: u32,
+        destructor: u32,
+    },
+    DestroyMemberArray {
+        object: Location,
+        offset: u32,
+        elements: u32,
+        element_size: u32,
+        destructor: u32,
+    },
+    /// Frees memory from a `new` whose constructor threw
+    DeletePointer {
+        object: Location,
+        function: u32,
+    },
+    DeletePointerCond {
+        condition: Location,
+        object: Location,
+        function: u32,
+    },
+    /// A `catch` block, with the address of the name of the type it catches, or `None` for `catch (...)`
+    Catch {
+        type_name: Option<u32>,
+        handler: u32,
+    },
+    /// Inside a catch block, so the exception being handled has to be cleaned up
+    ActiveCatch {
+        info: i16,
+    },
+    Terminate,
+    /// An exception specification, `throw (A, B)`, with the names of the types it allows
+    Specification {
+        types: Vec<u32>,
+        handler: u32,
+    },
+}
+
+/// Finds the exception tables and keeps a parsed copy of every record.
+pub fn analyze(program: &mut Program) {
+    program.exception_tables = find_index(program)
+        .into_iter()
+        .map(|(function, size, record)| (function, parse_record(program, function, size, record)))
+        .collect();
+}
+
+/// Finds `extabindex` and reads its entries: each function's address, size and record.
+fn find_index(program: &Program) -> Vec<(u32, u32, u32)> {
+    let is_data = |address: u32| {
+        program.segment_at(address).is_some_and(|segment| {
+            !segment.permissions.contains(Permissions::EXECUTE)
+                && !segment.permissions.contains(Permissions::UNINITIALIZED)
+        })
+    };
+    for segment in &program.segments {
+        if segment.permissions.contains(Permissions::EXECUTE)
+            || segment.permissions.contains(Permissions::UNINITIALIZED)
+        {
+            continue;
+        }
+        let mut entries: Vec<(u32, u32, u32)> = Vec::new();
+        let mut valid = true;
+        let mut address = segment.address;
+        // The segment can be padded out past the last entry, but only with zeroes
+        let mut padding = false;
+        while address + 12 <= segment.address + segment.size {
+            let words = [0, 4, 8].map(|offset| program.read_u32(address + offset).unwrap_or(0));
+            address += 12;
+            if padding || words == [0; 3] {
+                padding = true;
+                valid &= words == [0; 3];
+                continue;
+            }
+            let [function, size, record] = words;
+            let previous_end = entries.last().map_or(0, |&(start, size, _)| start + size);
+            valid &= function >= previous_end
+                && size > 0
+                && size.is_multiple_of(4)
+                && program.is_code(function)
+                && function.checked_add(size).is_some_and(|end| program.is_code(end - 4))
+                && is_data(record)
+                && program.segment_at(record).is_some_and(|extab| extab.address != segment.address);
+            if !valid {
+                break;
+            }
+            entries.push((function, size, record));
+        }
+        if valid && !entries.is_empty() {
+            return entries;
+        }
+    }
+    Vec::new()
+}
+
+/// Reads big endian values out of a record, failing if it runs out of the segment.
+struct Reader<'a> {
+    program: &'a Program,
+    address: u32,
+}
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        let bytes = self.program.bytes(self.address, N as u32)?.try_into().ok()?;
+        self.address += N as u32;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes::<1>().map(|[byte]| byte)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.bytes().map(u16::from_be_bytes)
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        self.bytes().map(i16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.bytes().map(u32::from_be_bytes)
+    }
+}
+
+/// Parses a record, which starts with a halfword of flags:
+///
+/// ```text
+/// 15-11  saved GPRs
+/// 10-6   saved FPRs
+/// 5      saved CR
+/// 3      large frame
+/// 2      frame pointer
+/// ```
+///
+/// then lists its ranges as a start offset, a size and an offset to the range's actions, all halfwords and
+/// ending with a zero halfword. Anything that doesn't parse is left out rather than guessed at.
+fn parse_record(program: &Program, function: u32, size: u32, record: u32) -> ExceptionTable {
+    let mut reader = Reader { program, address: record };
+    let flags = reader.u16().unwrap_or(0);
+    let mut table = ExceptionTable {
+        function,
+        size,
+        record,
+        saved_gprs: (flags >> 11) as u8,
+        saved_fprs: ((flags >> 6) & 0x1F) as u8,
+        saved_cr: flags & 0x20 != 0,
+        has_frame_pointer: flags & 0x04 != 0,
+        large_frame: flags & 0x08 != 0,
+        ranges: Vec::new(),
+    };
+    // Functions that only need their registers restored don't have any ranges
+    // Nothing can go wrong in the prologue, so no range starts at 0 and a zero start ends the list
+    while let Some(start) = reader.u16().filter(|&start| start != 0) {
+        let (Some(length), Some(actions)) = (reader.u16(), reader.u16()) else {
+            break;
+        };
+        let start = function + u32::from(start);
+        let end = start + u32::from(length);
+        if end > function + size {
+            break;
+        }
+        let actions = parse_actions(program, function, record, record + u32::from(actions));
+        table.ranges.push(ExceptionRange { start, end, actions });
+    }
+    table
+}
+
+/// Action types, in the low 7 bits of an action's first byte. The top bit marks the last action in a list.
+const BRANCH: u8 = 1;
+const DESTROY_LOCAL: u8 = 2;
+const DESTROY_LOCAL_COND: u8 = 3;
+const DESTROY_LOCAL_POINTER: u8 = 4;
+const DESTROY_LOCAL_ARRAY: u8 = 5;
+const DESTROY_BASE: u8 = 6;
+const DESTROY_MEMBER: u8 = 7;
+const DESTROY_MEMBER_COND: u8 = 8;
+const DESTROY_MEMBER_ARRAY: u8 = 9;
+const DELETE_POINTER: u8 = 10;
+const DELETE_POINTER_COND: u8 = 11;
+const CATCH_BLOCK: u8 = 12;
+const ACTIVE_CATCH_BLOCK: u8 = 13;
+const TERMINATE: u8 = 14;
+const SPECIFICATION: u8 = 15;
+const CATCH_BLOCK_32: u8 = 16;
+
+/// Reads the list of actions at `address`. Each starts with its type and a byte of flags, where the top bit
+/// says the object is held in a register rather than on the stack. A branch carries on at another offset in
+/// the record, which is how ranges share the tail of their lists.
+fn parse_actions(program: &Program, function: u32, record: u32, address: u32) -> Vec<Action> {
+    let mut actions = Vec::new();
+    let mut reader = Reader { program, address };
+    // Branches only go forwards, but a corrupt table could loop
+    for _ in 0..256 {
+        let (Some(kind), Some(flags)) = (reader.u8(), reader.u8()) else {
+            break;
+        };
+        if kind & 0x7F == BRANCH {
+            match reader.u16() {
+                Some(target) => reader.address = record + u32::from(target),
+                None => break,
+            }
+            continue;
+        }
+        let Some(action) = read_action(&mut reader, kind & 0x7F, flags, function) else {
+            break;
+        };
+        actions.push(action);
+        if kind & 0x80 != 0 {
+            break;
+        }
+    }
+    actions
+}
+
+fn read_action(reader: &mut Reader, kind: u8, flags: u8, function: u32) -> Option<Action> {
+    let location = |value: i16| match flags & 0x80 {
+        0 => Location::Stack(value),
+        _ => Location::Register(value as u8),
+    };
+    let action = match kind {
+        DESTROY_LOCAL => {
+            Action::DestroyLocal { local: Location::Stack(reader.i16()?), destructor: reader.u32()? }
+        }
+        DESTROY_LOCAL_COND => Action::DestroyLocalCond {
+            condition: Location::Stack(reader.i16()?),
+            local: Location::Stack(reader.i16()?),
+            destructor: reader.u32()?,
+        },
+        DESTROY_LOCAL_POINTER => {
+            Action::DestroyLocalPointer { pointer: location(reader.i16()?), destructor: reader.u32()? }
+        }
+        DESTROY_LOCAL_ARRAY => Action::DestroyLocalArray {
+            array: Location::Stack(reader.i16()?),
+            elements: reader.u16()?,
+            element_size: reader.u16()?,
+            destructor: reader.u32()?,
+        },
+        DESTROY_BASE => Action::DestroyBase {
+            object: location(reader.i16()?),
+            offset: reader.u32()?,
+            destructor: reader.u32()?,
+        },
+        DESTROY_MEMBER => Action::DestroyMember {
+            object: location(reader.i16()?),
+            offset: reader.u32()?,
+            destructor: reader.u32()?,
+        },
+        DESTROY_MEMBER_COND => Action::DestroyMemberCond {
+            condition: Location::Stack(reader.i16()?),
+            object: location(reader.i16()?),
+            offset: reader.u32()?,
+            destructor: reader.u32()?,
+        },
+        DESTROY_MEMBER_ARRAY => Action::DestroyMemberArray {
+            object: location(reader.i16()?),
+            offset: reader.u32()?,
+            elements: reader.u32()?,
+            element_size: reader.u32()?,
+            destructor: reader.u32()?,
+        },
+        DELETE_POINTER => Action::DeletePointer { object: location(reader.i16()?), function: reader.u32()? },
+        DELETE_POINTER_COND => Action::DeletePointerCond {
+            condition: Location::Stack(reader.i16()?),
+            object: location(reader.i16()?),
+            function: reader.u32()?,
+        },
+        CATCH_BLOCK | CATCH_BLOCK_32 => {
+            let type_name = reader.u32()?;
+            // The short form keeps the handler's offset and the catch info in halfwords
+            let handler = match kind {
+                CATCH_BLOCK => u32::from(reader.u16()?),
+                _ => reader.u32()?,
+            };
+            match kind {
+                CATCH_BLOCK => reader.u16()?.into(),
+                _ => reader.u32()?,
+            };
+            Action::Catch {
+                type_name: (type_name != 0).then_some(type_name),
+                handler: function.wrapping_add(handler),
+            }
+        }
+        ACTIVE_CATCH_BLOCK => Action::ActiveCatch { info: reader.i16()? },
+        TERMINATE => Action::Terminate,
+        SPECIFICATION => {
+            let count = reader.u16()?;
+            let handler = reader.u16()?;
+            reader.u16()?;
+            let types = (0..count.min(64)).map(|_| reader.u32()).collect::<Option<Vec<u32>>>()?;
+            Action::Specification { types, handler: function + u32::from(handler) }
+        }
+        _ => return None,
+    };
+    Some(action)
+}
+
+/// Reads the name of a type out of a catch clause or exception specification. CodeWarrior stores the mangled
+/// type, with class names between `!`s, which is shown demangled where it can be.
+fn type_name(program: &Program, address: u32) -> String {
+    let name = program.segment_at(address).and_then(|segment| {
+        let bytes = program.bytes(address, (segment.address + segment.size - address).min(256))?;
+        let length = bytes.iter().position(|&byte| byte == 0)?;
+        std::str::from_utf8(&bytes[..length]).ok().filter(|name| !name.is_empty())
+    });
+    match name {
+        Some(name) => {
+            let name = name.replace('!', "");
+            demangle::demangle_type(&name).map_or(name, |demangled| demangled.to_string())
+        }
+        None => format!("0x{address:08X}"),
+    }
+}
+
+impl Action {
+    /// Describes the action for the listing, e.g. `destroy var_10 with CFoo::~CFoo()`.
+    pub fn describe(&self, program: &Program, function: u32) -> String {
+        let frame_size = program.functions.get(&function).map_or(0, |function| function.frame.size as i32);
+        let location = |location: &Location| match *location {
+            Location::Stack(offset) => slot_name(i32::from(offset) - frame_size),
+            Location::Register(register) => format!("r{register}"),
+        };
+        let name = |address: u32| program.display_name(address);
+        match self {
+            Action::DestroyLocal { local, destructor } => {
+                format!("destroy {} with {}", location(local), name(*destructor))
+            }
+            Action::DestroyLocalCond { condition, local, destructor } => format!(
+                "destroy {} with {} if {}",
+                location(local),
+                name(*destructor),
+                location(condition)
+            ),
+            Action::DestroyLocalPointer { pointer, destructor } => {
+                format!("destroy *{} with {}", location(pointer), name(*destructor))
+            }
+            Action::DestroyLocalArray { array, elements, destructor, .. } => {
+                format!(
+                    "destroy {}[{elements}] with {}",
+                    location(array),
+                    name(*destructor)
+                )
+            }
+            Action::DestroyBase { object, offset, destructor } => {
+                format!(
+                    "destroy base {}+0x{offset:X} with {}",
+                    location(object),
+                    name(*destructor)
+                )
+            }
+            Action::DestroyMember { object, offset, destructor } => {
+                format!(
+                    "destroy member {}+0x{offset:X} with {}",
+                    location(object),
+                    name(*destructor)
+                )
+            }
+            Action::DestroyMemberCond { condition, object, offset, destructor } => format!(
+                "destroy member {}+0x{offset:X} with {} if {}",
+                location(object),
+                name(*destructor),
+                location(condition)
+            ),
+            Action::DestroyMemberArray { object, offset, elements, destructor, .. } => format!(
+                "destroy member {}+0x{offset:X}[{elements}] with {}",
+                location(object),
+                name(*destructor)
+            ),
+            Action::DeletePointer { object, function } => {
+                format!("delete {} with {}", location(object), name(*function))
+            }
+            Action::DeletePointerCond { condition, object, function } => format!(
+                "delete {} with {} if {}",
+                location(object),
+                name(*function),
+                location(condition)
+            ),
+            Action::Catch { type_name: Some(address), handler } => {
+                format!(
+                    "catch ({}) at {}",
+                    type_name(program, *address),
+                    program.describe(*handler)
+                )
+            }
+            Action::Catch { type_name: None, handler } => {
+                format!("catch (...) at {}", program.describe(*handler))
+            }
+            Action::ActiveCatch { .. } => "end active catch".to_owned(),
+            Action::Terminate => "terminate".to_owned(),
+            Action::Specification { types, handler } => {
+                let types: Vec<String> = types.iter().map(|&address| type_name(program, address)).collect();
+                format!("throw ({}) else {}", types.join(", "), program.describe(*handler))
+            }
+        }
+    }
+}
+
+/// Comment for the listing to show before the instruction at `address`, if an exception range or a catch
+/// block starts there.
+pub fn annotation(program: &Program, address: u32) -> Option<String> {
+    let (_, table) = program.exception_tables.range(..=address).next_back()?;
+    if address >= table.function + table.size {
+        return None;
+    }
+    let mut parts = Vec::new();
+    for range in table.ranges.iter().filter(|range| range.start == address) {
+        let actions: Vec<String> =
+            range.actions.iter().map(|action| action.describe(program, table.function)).collect();
+        let kind = match range.actions.iter().any(|action| matches!(action, Action::Catch { .. })) {
+            true => "try",
+            false => "cleanup",
+        };
+        parts.push(format!(
+            "{kind} until 0x{:08X}: {}",
+            range.end,
+            actions.join("; ")
+        ));
+    }
+    // Several ranges can share a catch block, so only mention it once
+    let mut handlers: Vec<String> = table
+        .ranges
+        .iter()
+        .flat_map(|range| &range.actions)
+        .filter_map(|action| match action {
+            Action::Catch { type_name: Some(name), handler } if *handler == address => {
+                Some(format!("catch ({}) handler", type_name(program, *name)))
+            }
+            Action::Catch { type_name: None, handler } if *handler == address => {
+                Some("catch (...) handler".to_owned())
+            }
+            Action::Specification { handler, .. } if *handler == address => {
+                Some("unexpected exception handler".to_owned())
+            }
+            _ => None,
+        })
+        .collect();
+    handlers.dedup();
+    parts.extend(handlers);
+    (!parts.is_empty()).then(|| parts.join(" | "))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{program, TEXT};
+
+    #[test]
+    fn ignores_pointers_that_arent_an_index() {
+        // Read as an index entry, the second pointer is a size that runs past the end of the address space
+        let mut data = Vec::new();
+        for pointer in [TEXT, TEXT + 4, TEXT] {
+            data.extend_from_slice(&pointer.to_be_bytes());
+        }
+        let program = program("blr\nblr", &data);
+        assert!(program.exception_tables.is_empty());
+    }
+}
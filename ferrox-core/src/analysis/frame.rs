@@ -0,0 +1,330 @@
+//! Stack frame recovery. EABI functions set up their frame in a fixed pattern: `stwu r1, -size(r1)` allocates
+//! it and stores the back chain, LR is saved in the caller's frame at `size + 4`, and callee-saved registers go
+//! at the top of the new frame, either one at a time, with `stmw`, or through the `_savegpr_`/`_savefpr_`
+//! helpers. Everything else the function keeps on the stack is a local.
+use std::collections::{BTreeMap, HashMap};
+
+use crate::ir::{self, Callee, Op, Operand, Register, Statement};
+use crate::processor::gekko::{Field, Flow, Operand as InstructionOperand};
+use crate::program::{Function, Program};
+
+/// Where a function's frame keeps things. Offsets are from the stack pointer after the prologue, the same
+/// way instructions in the body of the function address them.
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    /// Bytes the prologue allocates, 0 for functions that don't have a frame
+    pub size: u32,
+    /// Address of the `stwu` that allocates the frame
+    pub allocation: Option<u32>,
+    pub lr: Option<i32>,
+    pub cr: Option<i32>,
+    /// Callee-saved registers, with where each one is kept until the epilogue restores it
+    pub gprs: BTreeMap<u8, i32>,
+    pub fprs: BTreeMap<u8, i32>,
+    /// Every other slot the function reads, writes or takes the address of, with the most bytes accessed at
+    /// once (0 if its address is only ever taken)
+    pub locals: BTreeMap<i32, u32>,
+}
+
+impl Frame {
+    /// Offset from the stack pointer on entry of an `disp(r1)` operand in the instruction at `address`.
+    pub fn entry_offset(&self, address: u32, disp: i32) -> i32 {
+        match self.allocation {
+            Some(allocation) if address > allocation => disp - self.size as i32,
+            _ => disp,
+        }
+    }
+
+    /// Whether the slot at `offset` holds a saved register rather than a local.
+    pub fn is_saved(&self, offset: i32) -> bool {
+        [self.lr, self.cr].contains(&Some(offset))
+            || self.gprs.values().chain(self.fprs.values()).any(|&saved| saved == offset)
+    }
+}
+
+/// Name of the stack slot at `offset` from the stack pointer on entry: `var_8` for slots in the function's own
+/// frame, counting down from the top, and `arg_8` for slots in its caller's.
+pub fn slot_name(offset: i32) -> String {
+    match offset {
+        ..0 => format!("var_{:X}", offset.unsigned_abs()),
+        _ => format!("arg_{offset:X}"),
+    }
+}
+
+/// What a register holds while walking through the prologue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Value {
+    /// An address in the stack, relative to the stack pointer on entry
+    Stack(i32),
+    Lr,
+    Cr,
+    /// Something the prologue computed, so storing it isn't saving anything
+    Unknown,
+}
+
+/// Recovers the frame of every function.
+pub fn analyze(program: &mut Program) {
+    let frames: Vec<(u32, Frame)> = program
+        .functions
+        .values()
+        .map(|function| (function.address, find_frame(program, function)))
+        .collect();
+    for (address, frame) in frames {
+        if let Some(function) = program.functions.get_mut(&address) {
+            function.frame = frame;
+        }
+    }
+}
+
+fn find_frame(program: &Program, function: &Function) -> Frame {
+    let mut frame = Frame::default();
+    let Some(entry) = function.blocks.get(&function.address) else {
+        return frame;
+    };
+    for address in (entry.start..entry.end).step_by(4) {
+        let Some(instruction) = program.instruction(address) else {
+            continue;
+        };
+        if let ("stwu", [InstructionOperand::Gpr(1), InstructionOperand::Offset { disp: ..0, base: 1 }, ..]) =
+            (instruction.form.mnemonic, &instruction.operands[..])
+        {
+            if let InstructionOperand::Offset { disp, .. } = instruction.operands[1] {
+                frame.size = disp.unsigned_abs();
+                frame.allocation = Some(address);
+            }
+            break;
+        }
+    }
+    if frame.allocation.is_none() {
+        return frame;
+    }
+
+    if let Some(lifted) = ir::lift(program, function.address) {
+        find_saves(program, &lifted.blocks[0].statements, &mut frame);
+    }
+    find_locals(program, function, &mut frame);
+    frame
+}
+
+/// Follows the entry block to see which registers get saved where, storing the offsets in `frame` relative to
+/// the stack pointer after the prologue.
+fn find_saves(program: &Program, statements: &[Statement], frame: &mut Frame) {
+    let size = frame.size as i32;
+    let mut values: HashMap<Register, Value> = HashMap::from([(Register::Gpr(1), Value::Stack(0))]);
+    // Registers that still hold whatever they did on entry have no entry in `values`
+    let value = |values: &HashMap<Register, Value>, operand: Operand| match operand {
+        Operand::Variable(variable) => values.get(&variable.register).copied(),
+        Operand::Constant(_) => Some(Value::Unknown),
+    };
+    let callee_saved = |register: u8| (14..32).contains(&register);
+
+    for statement in statements {
+        match statement {
+            Statement::Assign { dest, op: Op::Copy, args } => {
+                let copied = match args[..] {
+                    [Operand::Variable(source)] if source.register == Register::Lr => Value::Lr,
+                    [operand] => value(&values, operand).unwrap_or(Value::Unknown),
+                    _ => Value::Unknown,
+                };
+                values.insert(dest.register, copied);
+            }
+            Statement::Assign { dest, op: Op::Add, args } => {
+                let sum = match (value(&values, args[0]), args.get(1)) {
+                    (Some(Value::Stack(base)), Some(&Operand::Constant(offset))) => {
+                        Value::Stack(base.wrapping_add(offset as i32))
+                    }
+                    _ => Value::Unknown,
+                };
+                values.insert(dest.register, sum);
+            }
+            Statement::Intrinsic { name: "mfcr", results, .. } => {
+                for result in results {
+                    values.insert(result.register, Value::Cr);
+                }
+            }
+            Statement::Store { address, value: stored, .. } => {
+                let Some(Value::Stack(offset)) = value(&values, *address) else {
+                    continue;
+                };
+                let offset = offset + size;
+                match (value(&values, *stored), stored) {
+                    (Some(Value::Lr), _) => frame.lr = Some(offset),
+                    (Some(Value::Cr), _) => frame.cr = Some(offset),
+                    (None, Operand::Variable(variable)) => match variable.register {
+                        Register::Gpr(n) if callee_saved(n) => {
+                            frame.gprs.entry(n).or_insert(offset);
+                        }
+                        Register::Fpr(n) if callee_saved(n) => {
+                            frame.fprs.entry(n).or_insert(offset);
+                        }
+                        _ => (),
+                    },
+                    _ => (),
+                }
+            }
+            Statement::Call { callee: Callee::Direct(target), .. } => {
+                if let Some(Value::Stack(base)) = values.get(&Register::Gpr(11)).copied() {
+                    for (register, disp) in helper_saves(program, *target) {
+                        let offset = base + disp + size;
+                        match register {
+                            Register::Gpr(n) => frame.gprs.entry(n).or_insert(offset),
+                            Register::Fpr(n) => frame.fprs.entry(n).or_insert(offset),
+                            _ => continue,
+                        };
+                    }
+                }
+            }
+            _ => (),
+        }
+        // Whatever else a statement defines no longer holds its entry value
+        for variable in statement.definitions() {
+            values.entry(variable.register).or_insert(Value::Unknown);
+        }
+    }
+}
+
+/// Registers a `_savegpr_`/`_savefpr_` style helper at `address` saves, with their offsets from r11. These
+/// are recognised by what they do rather than by name, since they usually haven't been named yet: nothing but
+/// stores of callee-saved registers relative to r11, then a return. Calls can enter them part way through, to
+/// save fewer registers.
+fn helper_saves(program: &Program, address: u32) -> Vec<(Register, i32)> {
+    let mut saves = Vec::new();
+    for address in (address..).step_by(4).take(36) {
+        let Some(instruction) = program.instruction(address) else {
+            break;
+        };
+        if let Flow::Return { conditional: false } = instruction.flow() {
+            return saves;
+        }
+        let register = match (instruction.form.mnemonic, instruction.operands[0]) {
+            ("stw", InstructionOperand::Gpr(n)) => Register::Gpr(n),
+            ("stfd", InstructionOperand::Fpr(n)) => Register::Fpr(n),
+            _ => break,
+        };
+        match instruction.operands[1] {
+            InstructionOperand::Offset { disp, base: 11 }
+                if (14..32).contains(&(instruction.field(Field::RS) as u8)) =>
+            {
+                saves.push((register, disp));
+            }
+            _ => break,
+        }
+    }
+    Vec::new()
+}
+
+/// Collects every other stack slot the function accesses through r1, apart from the back chain at 0.
+fn find_locals(program: &Program, function: &Function, frame: &mut Frame) {
+    for block in function.blocks.values() {
+        for address in (block.start..block.end).step_by(4) {
+            if Some(address) == frame.allocation {
+                continue;
+            }
+            let Some(instruction) = program.instruction(address) else {
+                continue;
+            };
+            let mnemonic = instruction.form.mnemonic;
+            let (disp, size) = match instruction.operands[..] {
+                // Reloading the back chain, which some epilogues do instead of adding the size back
+                [InstructionOperand::Gpr(1), ..] => continue,
+                [register, InstructionOperand::Offset { disp, base: 1 }, ..] => {
+                    (disp, access_size(mnemonic, &register))
+                }
+                // Taking the address of a slot, usually to pass it somewhere
+                [_, InstructionOperand::Gpr(1), InstructionOperand::Simm(disp)] if mnemonic == "addi" => {
+                    (disp, 0)
+                }
+                _ => continue,
+            };
+            let offset = frame.entry_offset(address, disp) + frame.size as i32;
+            if offset == 0 || frame.is_saved(offset) {
+                continue;
+            }
+            let largest = frame.locals.entry(offset).or_insert(size);
+            *largest = (*largest).max(size);
+        }
+    }
+}
+
+/// Bytes a load or store accesses.
+pub(crate) fn access_size(mnemonic: &str, register: &InstructionOperand) -> u32 {
+    match mnemonic.trim_end_matches(['u', 'x']) {
+        "lbz" | "stb" => 1,
+        "lhz" | "lha" | "sth" => 2,
+        "lfd" | "stfd" | "psq_l" | "psq_st" => 8,
+        "lmw" | "stmw" => match *register {
+            InstructionOperand::Gpr(first) => (32 - u32::from(first)) * 4,
+            _ => 4,
+        },
+        _ => 4,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listing;
+    use crate::testing::{self, TEXT};
+
+    const SOURCE: &str = "
+        stwu r1, -0x20(r1)
+        mflr r0
+        stw r0, 0x24(r1)
+        stmw r29, 0x14(r1)
+        stw r3, 0x8(r1)
+        lfd f1, 0xC(r1)
+        addi r3, r1, 0x8
+        bl leaf
+        lmw r29, 0x14(r1)
+        lwz r0, 0x24(r1)
+        mtlr r0
+        addi r1, r1, 0x20
+        blr
+    leaf:
+        stw r3, 0x8(r1)
+        addi r3, r3, 1
+        blr
+    ";
+
+    const LEAF: u32 = TEXT + 0x34;
+
+    #[test]
+    fn reads_the_prologue() {
+        let program = testing::program(SOURCE, &[]);
+        let frame = &program.functions[&TEXT].frame;
+        assert_eq!(frame.size, 0x20);
+        assert_eq!(frame.allocation, Some(TEXT));
+        assert_eq!(frame.lr, Some(0x24));
+        assert_eq!(frame.cr, None);
+        assert_eq!(frame.gprs, BTreeMap::from([(29, 0x14), (30, 0x18), (31, 0x1C)]));
+        assert!(frame.fprs.is_empty());
+        assert_eq!(frame.locals, BTreeMap::from([(0x8, 4), (0xC, 8)]));
+        assert!(frame.is_saved(0x18));
+        assert!(!frame.is_saved(0x8));
+    }
+
+    #[test]
+    fn names_slots_in_the_listing() {
+        let program = testing::program(SOURCE, &[]);
+        let format = |address| listing::format_instruction(&program, &program.instruction(address).unwrap());
+        // The allocation itself keeps its offset
+        assert_eq!(format(TEXT), "stwu      r1, -0x20(r1)");
+        assert_eq!(format(TEXT + 0x10), "stw       r3, var_18(r1)");
+        assert_eq!(format(TEXT + 0x14), "lfd       f1, var_14(r1)");
+        assert_eq!(format(TEXT + 0x18), "addi      r3, r1, var_18");
+        assert_eq!(format(TEXT + 0x24), "lwz       r0, arg_4(r1)");
+    }
+
+    #[test]
+    fn leaves_leaf_functions_without_a_frame() {
+        let program = testing::program(SOURCE, &[]);
+        let frame = &program.functions[&LEAF].frame;
+        assert_eq!(frame.size, 0);
+        assert_eq!(frame.allocation, None);
+        assert_eq!(frame.lr, None);
+        assert!(frame.gprs.is_empty() && frame.locals.is_empty());
+        // Without a frame of its own, r1 still points at the caller's
+        let format = |address| listing::format_instruction(&program, &program.instruction(address).unwrap());
+        assert_eq!(format(LEAF), "stw       r3, 8(r1)");
+    }
+}
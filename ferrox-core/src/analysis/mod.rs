@@ -0,0 +1,19 @@
+pub mod cfa;
+pub mod class;
+pub mod data;
+pub mod exception;
+pub mod frame;
+pub mod prototype;
+pub mod relocation;
+
+use crate::program::Program;
+
+/// Runs every analysis pass, in the order they depend on each other.
+pub fn analyze(program: &mut Program) {
+    exception::analyze(program);
+    cfa::analyze(program);
+    data::analyze(program);
+    frame::analyze(program);
+    prototype::analyze(program);
+    class::analyze(program);
+}
@@ -0,0 +1,79 @@
+//! Prototypes for functions that don't have one yet, worked out from how they use the EABI argument and
+//! return registers, along with the parameter types in their name if it's a mangled C++ one. Callees go
+//! before their callers, so calls already know how many arguments they pass.
+use std::collections::BTreeSet;
+
+use crate::processor::gekko::Flow;
+use crate::program::Program;
+use crate::registry::Prototype;
+use crate::{decompile, demangle};
+
+/// Gives every function without a prototype the one its code suggests. These go straight into the type
+/// registry rather than the history, the same as anything else analysis finds.
+pub fn analyze(program: &mut Program) {
+    let mut visited = BTreeSet::new();
+    let mut order = Vec::new();
+    let functions: Vec<u32> = program.functions.keys().copied().collect();
+    for address in functions {
+        post_order(program, address, &mut visited, &mut order);
+    }
+    for address in order {
+        if program.types.prototype(address).is_some() {
+            continue;
+        }
+        let inferred = decompile::infer_prototype(program, address);
+        let prototype = demangled_prototype(program, address, inferred.as_ref()).or(inferred);
+        if prototype.is_some() {
+            program.types.set_prototype(address, prototype);
+        }
+    }
+}
+
+/// Prototype the mangled name of the function at `address` gives it, with `inferred` filling in what the
+/// name leaves out.
+pub fn demangled_prototype(
+    program: &Program, address: u32, inferred: Option<&Prototype>,
+) -> Option<Prototype> {
+    demangle::demangle(program.names.get(&address)?)?.prototype(inferred)
+}
+
+/// Lists functions so every one comes after everything it calls, apart from recursion.
+fn post_order(program: &Program, start: u32, visited: &mut BTreeSet<u32>, order: &mut Vec<u32>) {
+    if !visited.insert(start) {
+        return;
+    }
+    // Iterative, since call chains can get deep. Each entry is a function and the callees left to visit
+    let mut stack = vec![(start, callees(program, start))];
+    while let Some((function, remaining)) = stack.last_mut() {
+        match remaining.pop() {
+            Some(callee) if visited.insert(callee) => {
+                let callees = callees(program, callee);
+                stack.push((callee, callees));
+            }
+            Some(_) => (),
+            None => {
+                order.push(*function);
+                stack.pop();
+            }
+        }
+    }
+}
+
+fn callees(program: &Program, address: u32) -> Vec<u32> {
+    let Some(function) = program.functions.get(&address) else {
+        return Vec::new();
+    };
+    let mut callees = Vec::new();
+    for block in function.blocks.values() {
+        for address in (block.start..block.end).step_by(4) {
+            let flow = program.instruction(address).map(|instruction| instruction.flow());
+            if let Some(Flow::Branch { target, .. }) = flow {
+                // Tail calls branch to other functions without linking
+                if target != function.address && program.functions.contains_key(&target) {
+                    callees.push(target);
+                }
+            }
+        }
+    }
+    callees
+}
@@ -0,0 +1,199 @@
+//! Relocation recovery, working out which instruction fields and data words hold addresses so they can be
+//! written back out symbolically. Nothing here is stored in the program, since it's only needed when exporting
+//! and is cheap enough to redo each time.
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use super::data::{small_data_bases, Computed, Registers};
+use crate::format::Permissions;
+use crate::processor::gekko::{Field, Operand};
+use crate::program::{OperandFormat, Program};
+use crate::registry::TypeInfo;
+
+/// The PowerPC relocation types Ferrox can recover, named after their `R_PPC_*` equivalents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RelocationKind {
+    /// Full address in a data word
+    Addr32,
+    /// Low half of an address, `@l`
+    Addr16Lo,
+    /// High half of an address for use with `ori`, `@h`
+    Addr16Hi,
+    /// High half of an address adjusted for a signed low half, `@ha`
+    Addr16Ha,
+    /// Target of `b`/`bl`
+    Rel24,
+    /// Target of a conditional branch
+    Rel14,
+    /// Offset from `r2` or `r13` into one of the small data sections, `@sda21`
+    EmbSda21,
+}
+
+impl RelocationKind {
+    /// The `R_PPC_*` number used in ELF files.
+    pub fn elf_type(self) -> u8 {
+        match self {
+            Self::Addr32 => 1,
+            Self::Addr16Lo => 4,
+            Self::Addr16Hi => 5,
+            Self::Addr16Ha => 6,
+            Self::Rel24 => 10,
+            Self::Rel14 => 11,
+            Self::EmbSda21 => 109,
+        }
+    }
+
+    /// Where the patched field starts, relative to the instruction or word. The 16-bit ones point straight at
+    /// the low half of the instruction.
+    pub fn field_offset(self) -> u32 {
+        match self {
+            Self::Addr16Lo | Self::Addr16Hi | Self::Addr16Ha => 2,
+            _ => 0,
+        }
+    }
+}
+
+/// An address stored somewhere in the program, keyed by where it's stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+    pub kind: RelocationKind,
+    pub target: u32,
+}
+
+/// Finds every relocation in the program, keyed by the address of the instruction or word that holds it.
+pub fn find(program: &Program) -> BTreeMap<u32, Relocation> {
+    let mut relocations = BTreeMap::new();
+    find_branches(program, &mut relocations);
+    let bases = small_data_bases(program);
+    for function in program.functions.values() {
+        for block in function.blocks.values() {
+            find_addresses(program, block.start..block.end, &bases, &mut relocations);
+        }
+    }
+    find_pointers(program, &mut relocations);
+    relocations
+}
+
+/// Relative branches anywhere in code, whether or not they're part of a function.
+fn find_branches(program: &Program, relocations: &mut BTreeMap<u32, Relocation>) {
+    for segment in &program.segments {
+        if !segment.permissions.contains(Permissions::EXECUTE) {
+            continue;
+        }
+        for address in (segment.address..segment.address + segment.size).step_by(4) {
+            let Some(instruction) = program.instruction(address) else {
+                continue;
+            };
+            let kind = match instruction.form.mnemonic {
+                "b" => RelocationKind::Rel24,
+                "bc" => RelocationKind::Rel14,
+                _ => continue,
+            };
+            let target = instruction.operands.iter().find_map(|operand| match *operand {
+                Operand::Target(target) => Some(target),
+                _ => None,
+            });
+            if let Some(target) = target.filter(|&target| program.segment_at(target).is_some()) {
+                // Absolute branches (AA set) already encode the address itself
+                if instruction.code & 2 == 0 {
+                    relocations.insert(address, Relocation { kind, target });
+                }
+            }
+        }
+    }
+}
+
+/// Addresses built with `lis` and a low half, or relative to the small data bases, within one basic block.
+fn find_addresses(
+    program: &Program, range: Range<u32>, bases: &Registers, relocations: &mut BTreeMap<u32, Relocation>,
+) {
+    let mut registers = *bases;
+    for address in range.step_by(4) {
+        let Some(instruction) = program.instruction(address) else {
+            continue;
+        };
+        let computed = registers.step(&instruction);
+        registers.keep_bases(bases);
+        let Some(computed) = computed else {
+            continue;
+        };
+        match instruction.form.mnemonic {
+            // `ori` only ever finishes an address from `lis`, it can't be small data relative
+            "ori" if computed.source.high.is_none() => (),
+            "ori" => record(program, relocations, address, computed, false),
+            // Paired single offsets are only 12 bits, too small for an address
+            mnemonic if mnemonic.starts_with("psq") => (),
+            _ => record(program, relocations, address, computed, true),
+        }
+    }
+}
+
+/// Records the relocations for an address that was just finished off at `address` using `register`. Only
+/// addresses inside the program count, and the `lis` has to actually encode the target's high half, otherwise
+/// a symbol wouldn't reassemble to the same bytes.
+fn record(
+    program: &Program, relocations: &mut BTreeMap<u32, Relocation>, address: u32, computed: Computed,
+    signed: bool,
+) {
+    let target = computed.address;
+    if program.segment_at(target).is_none() {
+        return;
+    }
+    match computed.source.high {
+        Some(high) => {
+            let Some(instruction) = program.instruction(high) else {
+                return;
+            };
+            let immediate = instruction.field(Field::SIMM);
+            let kind = match signed {
+                true if immediate == (target.wrapping_add(0x8000) >> 16) => RelocationKind::Addr16Ha,
+                false if immediate == target >> 16 => RelocationKind::Addr16Hi,
+                _ => return,
+            };
+            relocations.entry(high).or_insert(Relocation { kind, target });
+            relocations.insert(address, Relocation { kind: RelocationKind::Addr16Lo, target });
+        }
+        None if matches!(computed.register, 2 | 13) => {
+            relocations.insert(address, Relocation { kind: RelocationKind::EmbSda21, target });
+        }
+        None => (),
+    }
+}
+
+/// Words in data segments that point somewhere in the program, unless they're part of something that's
+/// clearly not a pointer (a string, a float, ...) or the user picked a format for them.
+fn find_pointers(program: &Program, relocations: &mut BTreeMap<u32, Relocation>) {
+    for segment in &program.segments {
+        if segment.permissions.intersects(Permissions::EXECUTE | Permissions::UNINITIALIZED) {
+            continue;
+        }
+        let start = (segment.address + 3) & !3;
+        for address in (start..segment.address + segment.size).step_by(4) {
+            let pointer_type = match program.types.item_containing(address.into()) {
+                None => true,
+                Some((_, _, type_info)) => may_hold_pointers(type_info),
+            };
+            let format = program.operand_format(address, 0);
+            if !pointer_type || !matches!(format, OperandFormat::Default | OperandFormat::Offset) {
+                continue;
+            }
+            let Some(target) = program.read_u32(address).filter(|&value| value != 0) else {
+                continue;
+            };
+            if program.segment_at(target).is_some() {
+                relocations.insert(address, Relocation { kind: RelocationKind::Addr32, target });
+            }
+        }
+    }
+}
+
+fn may_hold_pointers(type_info: &TypeInfo) -> bool {
+    match type_info {
+        TypeInfo::Pointer { .. }
+        | TypeInfo::Struct { .. }
+        | TypeInfo::Union { .. }
+        | TypeInfo::Named { .. } => true,
+        TypeInfo::Array { element_type, .. } => may_hold_pointers(element_type),
+        _ => false,
+    }
+}
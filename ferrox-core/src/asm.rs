@@ -0,0 +1,600 @@
+//! Assembler-ready source, for decomp projects that need to build parts of a binary they haven't decompiled
+//! yet. Addresses are replaced by symbols wherever relocations were recovered, so the output can be assembled
+//! with `powerpc-eabi-as -mgekko` and linked back into the same bytes. Symbols are written with the
+//! `.fn`/`.obj`/`.sym` macros decomp projects use, which each file defines itself so no include is needed.
+use std::fmt::Write;
+
+use crate::analysis::relocation::{Relocation, RelocationKind};
+use crate::demangle;
+use crate::format::Permissions;
+use crate::processor::gekko::{Instruction, Operand};
+use crate::program::Program;
+use crate::registry::TypeInfo;
+use crate::split::SplitRange;
+use crate::symbols::{LabelKind, SymbolTable};
+use crate::text::TextEncoding;
+
+/// Writes ranges of a program as assembly. Recovering relocations means looking at the whole program, so
+/// this does it once up front and can then export any number of ranges with the same symbols.
+pub struct AsmExporter<'a> {
+    program: &'a Program,
+    symbols: SymbolTable,
+}
+
+impl<'a> AsmExporter<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        Self { program, symbols: SymbolTable::new(program) }
+    }
+
+    /// Symbol expression for an address: its label, or the nearest label before it plus an offset.
+    fn symbol(&self, address: u32) -> String {
+        match self.symbols.resolve(address) {
+            Some((label, 0)) => quote(&label.name),
+            Some((label, offset)) => format!("{}+0x{offset:X}", quote(&label.name)),
+            None => format!("0x{address:08X}"),
+        }
+    }
+
+    /// Writes a complete file holding the given ranges, in order.
+    pub fn export(&self, ranges: &[SplitRange]) -> String {
+        let mut output = String::new();
+        let file_name = self.program.path.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        writeln!(output, "# Generated by Ferrox from {file_name}").unwrap();
+        writeln!(output, "# Assemble with powerpc-eabi-as -mgekko").unwrap();
+        output.push_str(MACROS);
+        for range in ranges {
+            self.write_range(&mut output, range);
+        }
+        output
+    }
+
+    fn write_range(&self, output: &mut String, range: &SplitRange) {
+        let Some(segment) = self.program.segment_at(range.start) else {
+            return;
+        };
+        let end = range.end.min(segment.address + segment.size);
+        let flags = match segment.permissions {
+            permissions if permissions.contains(Permissions::EXECUTE) => "\"ax\"",
+            permissions if permissions.contains(Permissions::UNINITIALIZED) => "\"wa\", @nobits",
+            permissions if permissions.contains(Permissions::WRITE) => "\"wa\"",
+            _ => "\"a\"",
+        };
+        writeln!(
+            output,
+            "\n.section {}, {flags} # 0x{:08X} - 0x{end:08X}",
+            range.section, range.start
+        )
+        .unwrap();
+
+        if segment.permissions.contains(Permissions::EXECUTE) {
+            self.write_code(output, range.start, end);
+        } else {
+            self.write_data(
+                output,
+                range.start,
+                end,
+                segment.permissions.contains(Permissions::UNINITIALIZED),
+            );
+        }
+    }
+
+    fn write_code(&self, output: &mut String, start: u32, end: u32) {
+        let mut address = start;
+        let mut open = None;
+        while address + 4 <= end {
+            if let Some(label) = self.symbols.labels.get(&address) {
+                match label.kind {
+                    LabelKind::Function => {
+                        if let Some(name) = open.take() {
+                            end_symbol(output, name, ".endfn");
+                        }
+                        begin_symbol(output, &label.name, ".fn");
+                        open = self.program.functions.get(&address).map(|_| label.name.as_str());
+                    }
+                    LabelKind::Global => writeln!(output, ".sym {}, global", quote(&label.name)).unwrap(),
+                    LabelKind::Local => writeln!(output, "{}:", label.name).unwrap(),
+                }
+            }
+
+            let code = self.program.read_u32(address).unwrap_or_default();
+            let text = match self.program.instruction(address) {
+                Some(instruction) => self.instruction_text(&instruction),
+                None => None,
+            };
+            let text = text.unwrap_or_else(|| format!(".4byte    0x{code:08X}"));
+            writeln!(output, "/* {address:08X} {code:08X} */  {text}").unwrap();
+
+            address += 4;
+            if self
+                .program
+                .functions
+                .range(..address)
+                .next_back()
+                .is_some_and(|(_, function)| function.end == address)
+            {
+                if let Some(name) = open.take() {
+                    end_symbol(output, name, ".endfn");
+                }
+            }
+        }
+        if let Some(name) = open {
+            end_symbol(output, name, ".endfn");
+        }
+        if address < end {
+            self.write_bytes(output, address, end);
+        }
+    }
+
+    /// Formats an instruction with its relocation applied, or `None` if it would only reassemble to the same
+    /// bytes as raw data (e.g. a relative branch outside the program).
+    fn instruction_text(&self, instruction: &Instruction) -> Option<String> {
+        let relocation = self.symbols.relocations.get(&instruction.address);
+        let suffix = |relocation: &Relocation| match relocation.kind {
+            RelocationKind::Addr16Lo => "@l",
+            RelocationKind::Addr16Hi => "@h",
+            RelocationKind::Addr16Ha => "@ha",
+            RelocationKind::EmbSda21 => "@sda21",
+            _ => "",
+        };
+        let relative = instruction.is_branch() && instruction.code & 2 == 0;
+
+        let (mnemonic, operands) = instruction.simplified();
+        // Only the first operand that holds a number is relocated, e.g. not the quantization fields of `psq_l`
+        let mut relocation = relocation;
+        let mut texts = Vec::new();
+        for operand in operands.iter() {
+            let number = matches!(
+                operand,
+                Operand::Target(_) | Operand::Simm(_) | Operand::Uimm(_) | Operand::Offset { .. }
+            );
+            let relocated = if number { relocation.take() } else { None };
+            texts.push(match (*operand, relocated) {
+                (Operand::Target(_), Some(relocation)) => self.symbol(relocation.target),
+                (Operand::Target(_), None) if relative => return None,
+                (Operand::Target(target), None) => format!("0x{target:08X}"),
+                (Operand::Simm(_) | Operand::Uimm(_), Some(relocation)) => {
+                    format!("{}{}", self.symbol(relocation.target), suffix(relocation))
+                }
+                (Operand::Offset { base, .. }, Some(relocation)) => {
+                    format!(
+                        "{}{}(r{base})",
+                        self.symbol(relocation.target),
+                        suffix(relocation)
+                    )
+                }
+                // Assemblers don't agree on the names, but they all take the number
+                (Operand::Spr(spr), _) => spr.to_string(),
+                (operand, _) => operand.to_string(),
+            });
+        }
+        Some(match texts.is_empty() {
+            true => mnemonic,
+            false => format!("{mnemonic:<9} {}", texts.join(", ")),
+        })
+    }
+
+    fn write_data(&self, output: &mut String, start: u32, end: u32, uninitialized: bool) {
+        let mut address = start;
+        let mut open: Option<&str> = None;
+        while address < end {
+            if let Some(label) = self.symbols.labels.get(&address) {
+                if let Some(name) = open.take() {
+                    end_symbol(output, name, ".endobj");
+                }
+                begin_symbol(output, &label.name, ".obj");
+                open = Some(&label.name);
+            }
+
+            let next_label =
+                self.symbols.labels.range(address + 1..).next().map_or(end, |(&next, _)| next.min(end));
+            if uninitialized {
+                writeln!(output, "    .skip 0x{:X}", next_label - address).unwrap();
+                address = next_label;
+                continue;
+            }
+            address = self.write_item(output, address, next_label);
+        }
+        if let Some(name) = open {
+            end_symbol(output, name, ".endobj");
+        }
+    }
+
+    /// Writes one directive's worth of data at `address`, never going past `limit`. Returns where it stopped.
+    fn write_item(&self, output: &mut String, address: u32, limit: u32) -> u32 {
+        let program = self.program;
+        if let Some(relocation) = self
+            .symbols
+            .relocations
+            .get(&address)
+            .filter(|relocation| relocation.kind == RelocationKind::Addr32)
+        {
+            if address + 4 <= limit {
+                writeln!(output, "    .4byte {}", self.symbol(relocation.target)).unwrap();
+                return address + 4;
+            }
+        }
+
+        if let Some((item_end, type_info)) = program.types.item_at(address.into()) {
+            let size = (item_end - u64::from(address)) as u32;
+            if address + size <= limit {
+                if let Some(text) = typed_directive(program, address, size, type_info) {
+                    writeln!(output, "    {text}").unwrap();
+                    return address + size;
+                }
+            }
+        }
+
+        // Plain words where possible, with bytes around them to get aligned or fill up to the next thing
+        let next_relocation =
+            self.symbols.relocations.range(address + 1..).next().map_or(limit, |(&next, _)| next);
+        let next_item =
+            program.types.next_start(address.into()).map_or(limit, |next| next.min(limit.into()) as u32);
+        let limit = limit.min(next_relocation).min(next_item);
+        if address.is_multiple_of(4) && address + 4 <= limit {
+            writeln!(
+                output,
+                "    .4byte 0x{:08X}",
+                program.read_u32(address).unwrap_or_default()
+            )
+            .unwrap();
+            return address + 4;
+        }
+        let end = ((address | 3) + 1).min(limit);
+        self.write_bytes(output, address, end);
+        end
+    }
+
+    fn write_bytes(&self, output: &mut String, start: u32, end: u32) {
+        let bytes = self.program.bytes(start, end - start).unwrap_or_default();
+        let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{byte:02X}")).collect();
+        writeln!(output, "    .byte {}", bytes.join(", ")).unwrap();
+    }
+}
+
+/// The directive for a typed item, if there's one that reassembles to exactly the same bytes.
+fn typed_directive(program: &Program, address: u32, size: u32, type_info: &TypeInfo) -> Option<String> {
+    match type_info {
+        TypeInfo::String { encoding: TextEncoding::Ascii | TextEncoding::ShiftJis, .. } => {
+            let (last, text) = program.bytes(address, size)?.split_last()?;
+            (*last == 0).then(|| format!(".string \"{}\"", escape(text)))
+        }
+        TypeInfo::Float { bits: 32 } if size == 4 => {
+            let value = f32::from_bits(program.read_u32(address)?);
+            (value.is_normal() || value == 0.0).then(|| format!(".float {value:?}"))
+        }
+        TypeInfo::Float { bits: 64 } if size == 8 => {
+            let value = f64::from_be_bytes(program.bytes(address, 8)?.try_into().ok()?);
+            (value.is_normal() || value == 0.0).then(|| format!(".double {value:?}"))
+        }
+        _ => None,
+    }
+}
+
+/// Escapes bytes for a string directive. Anything that isn't printable ASCII is written as a three digit octal
+/// escape, since hex escapes would swallow any hex digits after them.
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => write!(escaped, "\\{byte:03o}").unwrap(),
+        }
+    }
+    escaped
+}
+
+/// Quotes a symbol name if the assembler wouldn't take it as is, like CodeWarrior's mangled names.
+fn quote(name: &str) -> String {
+    let plain = name.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || matches!(c, '_' | '.'))
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'));
+    match plain {
+        true => name.to_owned(),
+        false => format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+/// Definitions of the symbol macros, matching the `macros.inc` decomp projects share. `.fn` and `.obj` start a
+/// function or object and `.endfn`/`.endobj` give it its size, while `.sym` is a plain label inside one.
+const MACROS: &str = r#"
+.macro .fn name, visibility=global
+.\visibility "\name"
+.type "\name", @function
+"\name":
+.endm
+
+.macro .endfn name
+.size "\name", . - "\name"
+.endm
+
+.macro .obj name, visibility=global
+.\visibility "\name"
+.type "\name", @object
+"\name":
+.endm
+
+.macro .endobj name
+.size "\name", . - "\name"
+.endm
+
+.macro .sym name, visibility=global
+.\visibility "\name"
+"\name":
+.endm
+"#;
+
+fn begin_symbol(output: &mut String, name: &str, directive: &str) {
+    // Mangled names have to stay as they are for the linker, so say what they mean alongside them
+    let comment = demangle::demangle(name).map(|demangled| format!(" # {demangled}")).unwrap_or_default();
+    writeln!(output, "\n{directive} {}, global{comment}", quote(name)).unwrap();
+}
+
+fn end_symbol(output: &mut String, name: &str, directive: &str) {
+    writeln!(output, "{directive} {}", quote(name)).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::processor::assembler;
+    use crate::testing::{self, DATA, TEXT};
+
+    /// Reads a symbol expression the exporter wrote, like `name`, `"quoted"+0x10` or `0x80003100`.
+    fn resolve(labels: &BTreeMap<String, u32>, expression: &str) -> Option<u32> {
+        let (name, offset) = match expression.rsplit_once('+') {
+            Some((name, offset)) => (name, crate::text::parse_number(offset)? as u32),
+            None => (expression, 0),
+        };
+        let name = name.trim().trim_matches('"');
+        let base =
+            labels.get(name).copied().or_else(|| crate::text::parse_number(name).map(|value| value as u32));
+        Some(base? + offset)
+    }
+
+    /// Assembles exported source with Ferrox's own assembler, returning the bytes of every section by address.
+    /// The linker would resolve `@sda21` against whichever base the section uses, which is always r13 here.
+    fn reassemble(source: &str, r13: u32) -> BTreeMap<u32, Vec<u8>> {
+        // Everything's the same size however its symbols resolve, so the first pass only has to find labels
+        let mut labels = BTreeMap::new();
+        let mut sections = BTreeMap::new();
+        for pass in 0..2 {
+            let mut address = 0;
+            let mut in_macro = false;
+            for line in source.lines().map(str::trim) {
+                if in_macro || line.starts_with(".macro") {
+                    in_macro = line != ".endm";
+                    continue;
+                }
+                if let Some(range) = line.strip_prefix(".section").and_then(|line| line.split_once("# ")) {
+                    address =
+                        u32::from_str_radix(range.1.split(' ').next().unwrap().trim_start_matches("0x"), 16)
+                            .unwrap();
+                    sections.entry(address).or_insert_with(Vec::new);
+                    continue;
+                }
+                if line.is_empty() || line.starts_with('#') || line.starts_with(".end") {
+                    continue;
+                }
+                let symbol =
+                    [".fn ", ".obj ", ".sym "].iter().find_map(|macro_name| line.strip_prefix(macro_name));
+                if let Some(label) =
+                    symbol.and_then(|symbol| symbol.split_once(", global")).map(|(name, _)| name)
+                {
+                    labels.insert(label.trim_matches('"').to_owned(), address);
+                    continue;
+                }
+                if let Some(label) = line.strip_suffix(':') {
+                    labels.insert(label.trim_matches('"').to_owned(), address);
+                    continue;
+                }
+
+                let bytes = match line.strip_prefix("/*") {
+                    Some(code) => {
+                        let text = code.split_once("*/").unwrap().1.trim();
+                        let word = match text.strip_prefix(".4byte") {
+                            Some(value) => resolve(&labels, value.trim()).unwrap(),
+                            None if pass == 0 => 0,
+                            None => {
+                                let text = match text.split_once("@sda21") {
+                                    Some((before, after)) => {
+                                        let (before, symbol) = before.rsplit_once(", ").unwrap();
+                                        let offset =
+                                            resolve(&labels, symbol).unwrap().wrapping_sub(r13) as i32;
+                                        format!("{before}, {offset}{after}")
+                                    }
+                                    None => text.to_owned(),
+                                };
+                                assembler::assemble(address, &text, |name| resolve(&labels, name))
+                                    .unwrap_or_else(|error| panic!("{text}: {error}"))
+                            }
+                        };
+                        word.to_be_bytes().to_vec()
+                    }
+                    None => {
+                        let (directive, value) = line.split_once(' ').unwrap();
+                        let value = value.trim();
+                        match directive {
+                            ".4byte" => resolve(&labels, value).unwrap_or_default().to_be_bytes().to_vec(),
+                            ".byte" => value
+                                .split(", ")
+                                .map(|byte| crate::text::parse_number(byte).unwrap() as u8)
+                                .collect(),
+                            ".float" => value.parse::<f32>().unwrap().to_be_bytes().to_vec(),
+                            ".double" => value.parse::<f64>().unwrap().to_be_bytes().to_vec(),
+                            ".skip" => vec![0; crate::text::parse_number(value).unwrap() as usize],
+                            ".string" => {
+                                let mut bytes = value.trim_matches('"').replace("\\\"", "\"").into_bytes();
+                                bytes.push(0);
+                                bytes
+                            }
+                            _ => panic!("unexpected line {line}"),
+                        }
+                    }
+                };
+                let start = *sections.range(..=address).next_back().unwrap().0;
+                let section = sections.get_mut(&start).unwrap();
+                if pass == 1 {
+                    section.extend_from_slice(&bytes);
+                }
+                address += bytes.len() as u32;
+            }
+        }
+        sections
+    }
+
+    /// A program using every kind of relocation, with a string, a float and a pointer table nothing refers
+    /// to in its data.
+    fn sample() -> Program {
+        let mut data = vec![0; 0x40];
+        data[..4].copy_from_slice(&(TEXT + 0x44).to_be_bytes());
+        data[0x10..0x16].copy_from_slice(b"hello\0");
+        data[0x18..0x1C].copy_from_slice(&TEXT.to_be_bytes());
+        data[0x1C..0x20].copy_from_slice(&(TEXT + 0x40).to_be_bytes());
+        data[0x20..0x24].copy_from_slice(&1.5f32.to_be_bytes());
+        let source = "
+            lis r13, 0x8000C000@ha
+            addi r13, r13, 0x8000C000@l
+            lis r3, 0x80004010@ha
+            addi r3, r3, 0x80004010@l
+            lis r4, 0x80004010@h
+            ori r4, r4, 0x80004010@l
+            lfs f1, -0x7FE0(r13)
+            lis r6, 0x80004004@ha
+            lwz r7, 0x80004004@l(r6)
+            bl helper
+            cmpwi r3, 0
+            beq skip
+            li r3, 1
+        skip:
+            b done
+        done:
+            blr
+            nop
+        helper:
+            li r3, 0
+            blr
+        ";
+        testing::program(source, &data)
+    }
+
+    /// Exports every segment, as `.text` or `.data`.
+    fn export_all(program: &Program) -> String {
+        let ranges: Vec<SplitRange> = program
+            .segments
+            .iter()
+            .map(|segment| SplitRange {
+                section: if segment.permissions.contains(Permissions::EXECUTE) {
+                    ".text"
+                } else {
+                    ".data"
+                }
+                .to_owned(),
+                start: segment.address,
+                end: segment.address + segment.size,
+            })
+            .collect();
+        AsmExporter::new(program).export(&ranges)
+    }
+
+    #[test]
+    fn reassembles_byte_identically() {
+        let program = sample();
+        let relocations = crate::analysis::relocation::find(&program);
+        let kinds: Vec<_> =
+            relocations.iter().map(|(&address, relocation)| (address - TEXT, relocation.kind)).collect();
+        assert!(kinds.contains(&(0x08, RelocationKind::Addr16Ha)));
+        assert!(kinds.contains(&(0x0C, RelocationKind::Addr16Lo)));
+        assert!(kinds.contains(&(0x10, RelocationKind::Addr16Hi)));
+        assert!(kinds.contains(&(0x18, RelocationKind::EmbSda21)));
+        assert!(kinds.contains(&(0x24, RelocationKind::Rel24)));
+        assert_eq!(relocations[&DATA].target, TEXT + 0x44);
+
+        let source = export_all(&program);
+        assert!(source.contains(".fn __start, global"), "{source}");
+        assert!(source.contains(".endfn __start"), "{source}");
+        assert!(source.contains("@sda21(r13)"), "{source}");
+        assert!(source.contains(".string \"hello\""), "{source}");
+        assert!(source.contains(".float 1.5"), "{source}");
+        // The pointer table after the string is its own object rather than part of the string
+        assert!(
+            source.contains(".endobj str_80004010\n\n.obj lbl_80004018, global"),
+            "{source}"
+        );
+
+        let sections = reassemble(&source, 0x8000_C000);
+        for segment in &program.segments {
+            let original = program.bytes(segment.address, segment.size).unwrap();
+            assert_eq!(
+                sections[&segment.address], original,
+                "{} differs\n{source}",
+                segment.name
+            );
+        }
+    }
+
+    /// Path to a devkitPPC tool, if it's installed.
+    fn devkit_tool(name: &str) -> Option<std::path::PathBuf> {
+        let devkit = std::env::var_os("DEVKITPPC").map(|devkit| std::path::Path::new(&devkit).join("bin"));
+        let path = std::env::var_os("PATH").unwrap_or_default();
+        devkit
+            .into_iter()
+            .chain(std::env::split_paths(&path))
+            .map(|directory| directory.join(name))
+            .find(|tool| tool.is_file())
+    }
+
+    /// Assembles the export with GNU as itself, when it's installed. Relocated fields are left for the linker
+    /// to fill in, so every other word has to come out the same as the original.
+    #[test]
+    fn assembles_with_gnu_as() {
+        let (Some(assembler), Some(objcopy)) = (
+            devkit_tool("powerpc-eabi-as"),
+            devkit_tool("powerpc-eabi-objcopy"),
+        ) else {
+            eprintln!("powerpc-eabi-as isn't installed, skipping");
+            return;
+        };
+        let program = sample();
+        let directory = std::env::temp_dir().join(format!("ferrox-asm-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let (source, object) = (directory.join("sample.s"), directory.join("sample.o"));
+        std::fs::write(&source, export_all(&program)).unwrap();
+
+        let run = |command: &mut std::process::Command| {
+            let output = command.output().unwrap();
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        };
+        run(std::process::Command::new(assembler).arg("-mgekko").arg("-o").arg(&object).arg(&source));
+
+        let symbols = SymbolTable::new(&program);
+        for segment in &program.segments {
+            let section = if segment.permissions.contains(Permissions::EXECUTE) {
+                ".text"
+            } else {
+                ".data"
+            };
+            let binary = directory.join(format!("{section}.bin"));
+            run(std::process::Command::new(&objcopy)
+                .args(["-O", "binary", "-j", section])
+                .arg(&object)
+                .arg(&binary));
+            let assembled = std::fs::read(&binary).unwrap();
+            let original = program.bytes(segment.address, segment.size).unwrap();
+            assert_eq!(assembled.len(), original.len(), "{section} has the wrong size");
+            for (offset, (assembled, original)) in assembled.chunks(4).zip(original.chunks(4)).enumerate() {
+                let address = segment.address + offset as u32 * 4;
+                if !symbols.relocations.contains_key(&address) {
+                    assert_eq!(assembled, original, "{section} differs at 0x{address:08X}");
+                }
+            }
+        }
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,537 @@
+//! C declarations of types and function prototypes: printing them the way the pseudocode shows them, and
+//! parsing what the user types in. Only the parts of C that describe data are understood, which is enough for
+//! `struct`, `union`, `enum` and `typedef` definitions, plus prototypes like `s32 strlen(const char *s)`.
+//!
+//! Integers print as the `u8`/`s32`/`f64` style typedefs most GameCube decompilation projects use, but the
+//! standard names parse too.
+use crate::error::{FerroxError, InvalidDeclarationSnafu};
+use crate::registry::{Member, Parameter, Prototype, TypeInfo, TypeRegistry};
+use crate::text::TextEncoding;
+
+/// Declares `name` as a value of the given type, e.g. `char *name` or `u8 buffer[16]`. An empty name gives
+/// the type on its own, like a cast would need.
+pub fn declare(type_info: &TypeInfo, name: &str) -> String {
+    let (base, declarator) = split(type_info, name.to_owned());
+    match declarator.is_empty() {
+        true => base,
+        false => format!("{base} {declarator}"),
+    }
+}
+
+/// The type on its own, e.g. `struct Node *`.
+pub fn type_name(type_info: &TypeInfo) -> String {
+    declare(type_info, "")
+}
+
+/// Same as [`type_name`], but for something that might be `void`.
+pub fn return_type_name(type_info: Option<&TypeInfo>) -> String {
+    type_info.map_or_else(|| "void".to_owned(), type_name)
+}
+
+/// Declares a function with this prototype, e.g. `s32 strlen(char *s)`.
+pub fn declare_prototype(prototype: &Prototype, name: &str) -> String {
+    let parameters: Vec<String> = prototype
+        .parameters
+        .iter()
+        .map(|parameter| declare(&parameter.type_info, &parameter.name))
+        .collect();
+    let parameters = match parameters.is_empty() {
+        true => "void".to_owned(),
+        false => parameters.join(", "),
+    };
+    let return_type = return_type_name(prototype.return_type.as_ref());
+    format!("{return_type} {name}({parameters})")
+}
+
+/// Splits a declaration into the base type and everything that wraps the name, so pointers and arrays end up
+/// on the right side of it.
+fn split(type_info: &TypeInfo, declarator: String) -> (String, String) {
+    match type_info {
+        TypeInfo::Pointer { target } => {
+            let declarator = format!("*{declarator}");
+            match target.as_deref() {
+                Some(target @ TypeInfo::Array { .. }) => split(target, format!("({declarator})")),
+                Some(target) => split(target, declarator),
+                None => ("void".to_owned(), declarator),
+            }
+        }
+        TypeInfo::Array { element_type, count } => split(element_type, format!("{declarator}[{count}]")),
+        TypeInfo::String { encoding, length } => {
+            let base = match encoding {
+                TextEncoding::Utf16Be => "wchar_t",
+                _ => "char",
+            };
+            (base.to_owned(), format!("{declarator}[{length}]"))
+        }
+        TypeInfo::Function { .. } => ("void".to_owned(), format!("{declarator}()")),
+        TypeInfo::Integer { bits, signed } => {
+            let prefix = if *signed { 's' } else { 'u' };
+            (format!("{prefix}{bits}"), declarator)
+        }
+        TypeInfo::Float { bits } => (format!("f{bits}"), declarator),
+        TypeInfo::Struct { name, .. }
+        | TypeInfo::Union { name, .. }
+        | TypeInfo::Enum { name, .. }
+        | TypeInfo::Named { name } => (name.clone(), declarator),
+    }
+}
+
+/// Parses a type on its own, like `struct Node *` or `u8[16]`.
+pub fn parse_type(registry: &TypeRegistry, text: &str) -> Result<TypeInfo, FerroxError> {
+    let mut parser = Parser::new(registry, text)?;
+    let base = parser.specifiers()?;
+    let (name, type_info) = parser.declarator(base)?;
+    parser.end()?;
+    match (name, type_info) {
+        (None, Some(type_info)) => Ok(type_info),
+        (Some(name), _) => parser.fail(format!("didn't expect a name, but found \"{name}\"")),
+        (None, None) => parser.fail("void isn't a type of value"),
+    }
+}
+
+/// Parses a function prototype, returning the function's name along with it.
+pub fn parse_prototype(registry: &TypeRegistry, text: &str) -> Result<(String, Prototype), FerroxError> {
+    let mut parser = Parser::new(registry, text)?;
+    let base = parser.specifiers()?;
+    let (name, return_type) = parser.declarator(base)?;
+    let Some(name) = name else {
+        return parser.fail("the function needs a name");
+    };
+    parser.expect('(')?;
+    let mut parameters = Vec::new();
+    if !parser.eat(')') {
+        loop {
+            if parser.eat_ellipsis() {
+                parser.expect(')')?;
+                break;
+            }
+            let base = parser.specifiers()?;
+            let (parameter, type_info) = parser.declarator(base)?;
+            match type_info {
+                Some(type_info) => parameters.push(Parameter {
+                    name: parameter.unwrap_or_else(|| format!("arg{}", parameters.len())),
+                    type_info,
+                }),
+                // `(void)` is the same as no parameters at all
+                None if parameters.is_empty() && parser.peek(')') => (),
+                None => return parser.fail("parameters can't be void"),
+            }
+            if parser.eat(')') {
+                break;
+            }
+            parser.expect(',')?;
+        }
+    }
+    parser.eat(';');
+    parser.end()?;
+    Ok((name, Prototype { return_type, parameters }))
+}
+
+/// Parses a `struct`, `union`, `enum` or `typedef` definition, returning the name it defines.
+pub fn parse_definition(registry: &TypeRegistry, text: &str) -> Result<(String, TypeInfo), FerroxError> {
+    let mut parser = Parser::new(registry, text)?;
+    let definition = match parser.eat_word("typedef") {
+        true => {
+            // `typedef struct { ... } Name;` defines the struct and names it at the same time
+            let base = match parser.aggregate()? {
+                Some((_, type_info)) => Base::Type(type_info),
+                None => parser.specifiers()?,
+            };
+            let (name, type_info) = parser.declarator(base)?;
+            match (name, type_info) {
+                (Some(name), Some(type_info)) => (name.clone(), rename(type_info, name)),
+                (None, _) => return parser.fail("the typedef needs a name"),
+                (Some(_), None) => return parser.fail("can't typedef void"),
+            }
+        }
+        false => match parser.aggregate()? {
+            Some((Some(name), type_info)) => (name, type_info),
+            Some((None, _)) => return parser.fail("the definition needs a name"),
+            None => return parser.fail("expected a struct, union, enum or typedef"),
+        },
+    };
+    parser.eat(';');
+    parser.end()?;
+    Ok(definition)
+}
+
+/// Gives an anonymous struct the name it's being typedef'd to.
+fn rename(type_info: TypeInfo, new: String) -> TypeInfo {
+    match type_info {
+        TypeInfo::Struct { name, size, members } if name.is_empty() => {
+            TypeInfo::Struct { name: new, size, members }
+        }
+        TypeInfo::Union { name, size, members } if name.is_empty() => {
+            TypeInfo::Union { name: new, size, members }
+        }
+        TypeInfo::Enum { name, size, members } if name.is_empty() => {
+            TypeInfo::Enum { name: new, size, members }
+        }
+        type_info => type_info,
+    }
+}
+
+/// Built in names for integer and float types, on top of the standard C ones.
+fn builtin(name: &str) -> Option<TypeInfo> {
+    let integer = |bits, signed| Some(TypeInfo::Integer { bits, signed });
+    match name {
+        "u8" | "uint8_t" | "bool" | "_Bool" => integer(8, false),
+        "s8" | "int8_t" => integer(8, true),
+        "u16" | "uint16_t" | "wchar_t" => integer(16, false),
+        "s16" | "int16_t" => integer(16, true),
+        "u32" | "uint32_t" | "size_t" | "uintptr_t" => integer(32, false),
+        "s32" | "int32_t" | "BOOL" => integer(32, true),
+        "u64" | "uint64_t" => integer(64, false),
+        "s64" | "int64_t" => integer(64, true),
+        "f32" => Some(TypeInfo::Float { bits: 32 }),
+        "f64" => Some(TypeInfo::Float { bits: 64 }),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number(u64),
+    Punct(char),
+    Ellipsis,
+}
+
+/// What the specifiers at the start of a declaration name.
+enum Base {
+    Void,
+    Type(TypeInfo),
+}
+
+struct Parser<'a> {
+    registry: &'a TypeRegistry,
+    text: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(registry: &'a TypeRegistry, text: &'a str) -> Result<Self, FerroxError> {
+        let mut tokens = Vec::new();
+        let mut rest = text;
+        loop {
+            rest = rest.trim_start();
+            if let Some(comment) = rest.strip_prefix("/*") {
+                rest = comment.split_once("*/").map_or("", |(_, rest)| rest);
+                continue;
+            }
+            if let Some(comment) = rest.strip_prefix("//") {
+                rest = comment.split_once('\n').map_or("", |(_, rest)| rest);
+                continue;
+            }
+            let Some(first) = rest.chars().next() else {
+                break;
+            };
+            if first.is_ascii_alphabetic() || first == '_' {
+                let word_end = |text: &str| text.find(|c: char| !c.is_ascii_alphanumeric() && c != '_');
+                let mut end = word_end(rest).unwrap_or(rest.len());
+                // C++ names from demangled symbols, like `foo::Bar::~Bar`
+                while let Some(scoped) = rest[end..].strip_prefix("::") {
+                    let name = scoped.strip_prefix('~').unwrap_or(scoped);
+                    let length = word_end(name).unwrap_or(name.len());
+                    if length == 0 {
+                        break;
+                    }
+                    end = rest.len() - name.len() + length;
+                }
+                tokens.push(Token::Word(rest[..end].to_owned()));
+                rest = &rest[end..];
+            } else if first.is_ascii_digit() {
+                let end = rest.find(|c: char| !c.is_ascii_alphanumeric()).unwrap_or(rest.len());
+                let number = &rest[..end];
+                let Some(value) =
+                    crate::text::parse_number(number).and_then(|value| u64::try_from(value).ok())
+                else {
+                    return InvalidDeclarationSnafu { text, reason: format!("\"{number}\" isn't a number") }
+                        .fail();
+                };
+                tokens.push(Token::Number(value));
+                rest = &rest[end..];
+            } else if let Some(after) = rest.strip_prefix("...") {
+                tokens.push(Token::Ellipsis);
+                rest = after;
+            } else if "*,()[]{};=-".contains(first) {
+                tokens.push(Token::Punct(first));
+                rest = &rest[1..];
+            } else {
+                return InvalidDeclarationSnafu { text, reason: format!("unexpected '{first}'") }.fail();
+            }
+        }
+        Ok(Self { registry, text, tokens, position: 0 })
+    }
+
+    fn fail<T>(&self, reason: impl Into<String>) -> Result<T, FerroxError> {
+        InvalidDeclarationSnafu { text: self.text, reason: reason.into() }.fail()
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self, punct: char) -> bool {
+        self.tokens.get(self.position) == Some(&Token::Punct(punct))
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.tokens.get(self.position) {
+            Some(Token::Word(word)) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, punct: char) -> bool {
+        let found = self.peek(punct);
+        self.position += found as usize;
+        found
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let found = self.peek_word() == Some(word);
+        self.position += found as usize;
+        found
+    }
+
+    fn eat_ellipsis(&mut self) -> bool {
+        let found = self.tokens.get(self.position) == Some(&Token::Ellipsis);
+        self.position += found as usize;
+        found
+    }
+
+    fn expect(&mut self, punct: char) -> Result<(), FerroxError> {
+        match self.eat(punct) {
+            true => Ok(()),
+            false => self.fail(format!("expected '{punct}'")),
+        }
+    }
+
+    fn identifier(&mut self) -> Option<String> {
+        let word = self.peek_word()?.to_owned();
+        self.position += 1;
+        Some(word)
+    }
+
+    fn number(&mut self) -> Result<u64, FerroxError> {
+        match self.next() {
+            Some(Token::Number(value)) => Ok(value),
+            _ => self.fail("expected a number"),
+        }
+    }
+
+    fn end(&self) -> Result<(), FerroxError> {
+        match self.tokens.get(self.position) {
+            None => Ok(()),
+            Some(token) => self.fail(format!("unexpected {token:?} at the end")),
+        }
+    }
+
+    /// Parses the type specifiers at the start of a declaration, like `const unsigned long` or `struct Node`.
+    fn specifiers(&mut self) -> Result<Base, FerroxError> {
+        let mut signed = None;
+        let (mut bits, mut float, mut void, mut named) = (None, false, false, None);
+        let mut longs = 0;
+        while let Some(word) = self.peek_word().map(str::to_owned) {
+            match word.as_str() {
+                "const" | "volatile" | "static" | "extern" | "inline" | "register" => (),
+                "signed" => signed = Some(true),
+                "unsigned" => signed = Some(false),
+                "char" => bits = Some(8),
+                "short" => bits = Some(16),
+                "int" => bits = Some(bits.unwrap_or(32)),
+                "long" => {
+                    longs += 1;
+                    bits = Some(if longs > 1 { 64 } else { 32 });
+                }
+                "float" => (bits, float) = (Some(32), true),
+                "double" => (bits, float) = (Some(64), true),
+                "void" => void = true,
+                "struct" | "union" | "enum" => {
+                    self.position += 1;
+                    let Some(name) = self.identifier() else {
+                        return self.fail(format!("expected a name after {word}"));
+                    };
+                    named = Some(TypeInfo::Named { name });
+                    continue;
+                }
+                _ if bits.is_none() && signed.is_none() && named.is_none() && !void => {
+                    if let Some(type_info) = builtin(&word) {
+                        named = Some(type_info);
+                    } else if self.registry.definition(&word).is_some()
+                        || word.contains("::")
+                        || self.tokens.get(self.position + 1) == Some(&Token::Punct('*'))
+                    {
+                        // Pointers can be to types that haven't been defined yet, like the classes of
+                        // demangled C++ functions, the same as `struct Name *` can
+                        named = Some(TypeInfo::Named { name: word });
+                    } else {
+                        break;
+                    }
+                }
+                _ => break,
+            }
+            self.position += 1;
+        }
+
+        Ok(match (named, bits, void) {
+            (Some(type_info), _, _) => Base::Type(type_info),
+            (None, Some(bits), _) if float => Base::Type(TypeInfo::Float { bits }),
+            // Plain `char` is signed for CodeWarrior
+            (None, Some(bits), _) => Base::Type(TypeInfo::Integer { bits, signed: signed.unwrap_or(true) }),
+            (None, None, true) => Base::Void,
+            (None, None, false) if signed.is_some() => {
+                Base::Type(TypeInfo::Integer { bits: 32, signed: signed.unwrap() })
+            }
+            (None, None, false) => match self.peek_word() {
+                Some(word) => return self.fail(format!("unknown type \"{word}\"")),
+                None => return self.fail("expected a type"),
+            },
+        })
+    }
+
+    /// Parses pointers, the name (if there is one) and array sizes. `None` means the result is `void`.
+    fn declarator(&mut self, base: Base) -> Result<(Option<String>, Option<TypeInfo>), FerroxError> {
+        let mut type_info = match base {
+            Base::Void => None,
+            Base::Type(type_info) => Some(type_info),
+        };
+        while self.eat('*') {
+            type_info = Some(match type_info {
+                Some(target) => TypeInfo::pointer_to(target),
+                None => TypeInfo::pointer(),
+            });
+            while self.eat_word("const") || self.eat_word("volatile") {}
+        }
+
+        // Function pointers only keep their name, the pointer is left untyped
+        if self.peek('(') && self.tokens.get(self.position + 1) == Some(&Token::Punct('*')) {
+            self.position += 2;
+            let name = self.identifier();
+            self.expect(')')?;
+            self.skip_group('(', ')')?;
+            return Ok((name, Some(TypeInfo::pointer())));
+        }
+
+        let name = self.identifier();
+        let mut counts = Vec::new();
+        while self.eat('[') {
+            counts.push(self.number()?);
+            self.expect(']')?;
+        }
+        if !counts.is_empty() {
+            let Some(mut element_type) = type_info else {
+                return self.fail("can't have an array of void");
+            };
+            for count in counts.into_iter().rev() {
+                element_type = TypeInfo::Array { element_type: Box::new(element_type), count };
+            }
+            type_info = Some(element_type);
+        }
+        Ok((name, type_info))
+    }
+
+    /// Skips a bracketed group, including any groups inside it.
+    fn skip_group(&mut self, open: char, close: char) -> Result<(), FerroxError> {
+        self.expect(open)?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Some(Token::Punct(punct)) if punct == open => depth += 1,
+                Some(Token::Punct(punct)) if punct == close => depth -= 1,
+                Some(_) => (),
+                None => return self.fail(format!("missing '{close}'")),
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a `struct`, `union` or `enum` with a body. Returns `None` without consuming anything if there
+    /// isn't one here.
+    fn aggregate(&mut self) -> Result<Option<(Option<String>, TypeInfo)>, FerroxError> {
+        let start = self.position;
+        let Some(keyword) = self.peek_word().map(str::to_owned) else {
+            return Ok(None);
+        };
+        if !matches!(keyword.as_str(), "struct" | "union" | "enum") {
+            return Ok(None);
+        }
+        self.position += 1;
+        let name = self.identifier();
+        if !self.peek('{') {
+            self.position = start;
+            return Ok(None);
+        }
+        self.position += 1;
+
+        if keyword == "enum" {
+            let mut members = Vec::new();
+            let mut value = 0i64;
+            while !self.eat('}') {
+                let Some(member) = self.identifier() else {
+                    return self.fail("expected the name of an enum member");
+                };
+                if self.eat('=') {
+                    let negative = self.eat('-');
+                    let magnitude = self.number()? as i64;
+                    value = if negative { -magnitude } else { magnitude };
+                }
+                members.push((member, value));
+                value += 1;
+                if !self.eat(',') {
+                    self.expect('}')?;
+                    break;
+                }
+            }
+            let name = name.clone().unwrap_or_default();
+            return Ok(Some((
+                Some(name.clone()).filter(|name| !name.is_empty()),
+                TypeInfo::Enum { name, size: 4, members },
+            )));
+        }
+
+        let union = keyword == "union";
+        let (mut members, mut size, mut align) = (Vec::new(), 0u64, 1);
+        while !self.eat('}') {
+            let base = match self.aggregate()? {
+                Some((_, type_info)) => Base::Type(type_info),
+                None => self.specifiers()?,
+            };
+            let base = match base {
+                Base::Type(type_info) => type_info,
+                Base::Void => TypeInfo::Integer { bits: 8, signed: false },
+            };
+            loop {
+                let (member, type_info) = self.declarator(Base::Type(base.clone()))?;
+                let Some(type_info) = type_info else {
+                    return self.fail("struct members can't be void");
+                };
+                let member_align = self.registry.align_of(&type_info);
+                let offset = match union {
+                    true => 0,
+                    false => size.next_multiple_of(member_align),
+                };
+                let end = offset + self.registry.size_of(&type_info);
+                size = size.max(end);
+                align = align.max(member_align);
+                let name = member.unwrap_or_else(|| format!("field_{offset:X}"));
+                members.push(Member { name, offset, type_info });
+                if !self.eat(',') {
+                    break;
+                }
+            }
+            self.expect(';')?;
+        }
+        let size = size.next_multiple_of(align);
+        let type_name = name.clone().unwrap_or_default();
+        let type_info = match union {
+            true => TypeInfo::Union { name: type_name, size, members },
+            false => TypeInfo::Struct { name: type_name, size, members },
+        };
+        Ok(Some((name, type_info)))
+    }
+}
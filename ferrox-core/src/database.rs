@@ -0,0 +1,593 @@
+//! Ferrox databases (`.frx`), which store the original binary along with the full edit log. Analysis is
+//! deterministic, so loading one just means analyzing the binary again and replaying every edit.
+//!
+//! Everything is big endian, strings are a u32 length followed by UTF-8, and `Option`s are a u8 flag followed
+//! by the value if it's set.
+use std::collections::BTreeMap;
+
+use orthrus_core::prelude::*;
+use snafu::ensure;
+
+use crate::analysis;
+use crate::error::{FerroxError, InvalidDatabaseSnafu};
+use crate::format::compression::Compression;
+use crate::format::BinaryFormat;
+use crate::history::{Edit, History};
+use crate::program::{OperandFormat, Program};
+use crate::registry::{Member, Parameter, Prototype, TypeInfo};
+use crate::signature::{self, LibraryConflict, LibraryMatch};
+use crate::text::TextEncoding;
+
+const MAGIC: &[u8; 4] = b"FRX\0";
+/// Version 2 added library matches, which version 1 databases just don't have. Version 3 added patches, which
+/// older versions wouldn't know how to replay. Version 4 added struct members, pointer targets, named types,
+/// prototypes and variable names, which are all new tags so older databases still read the same. Version 5
+/// records whether the binary was compressed
+const VERSION: u32 = 5;
+
+/// Checks whether some data looks like a database rather than a binary to import.
+pub fn is_database(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Serializes a program into a database.
+pub fn save(program: &Program) -> Result<Vec<u8>, FerroxError> {
+    let mut writer = Writer(DataStream::new(Vec::new(), Endian::Big));
+    writer.0.extend_from_slice(MAGIC);
+    writer.0.write_u32(VERSION)?;
+
+    writer.0.write_u8(match program.format {
+        BinaryFormat::BinaryFile => 0,
+        BinaryFormat::GameCubeDOL => 1,
+        BinaryFormat::GameCubeREL => 2,
+    })?;
+    writer.string(&program.path.to_string_lossy())?;
+    // Patches are edits like everything else, so the binary is stored as it was before any of them
+    writer.bytes(&program.original_data())?;
+    writer.0.write_u8(match program.compression {
+        None => 0,
+        Some(Compression::Yaz0) => 1,
+        Some(Compression::Yay0) => 2,
+    })?;
+
+    let edits = program.history.edits();
+    writer.0.write_u32(edits.len() as u32)?;
+    writer.0.write_u32(program.history.position() as u32)?;
+    for edit in edits {
+        writer.edit(edit)?;
+    }
+
+    // Signature files might not be around when this is loaded again, so the matches are stored as-is
+    writer.0.write_u32(program.library_functions.len() as u32)?;
+    for (address, library_match) in &program.library_functions {
+        writer.0.write_u32(*address)?;
+        writer.library_match(library_match)?;
+    }
+    writer.0.write_u32(program.library_conflicts.len() as u32)?;
+    for conflict in &program.library_conflicts {
+        writer.0.write_u32(conflict.address)?;
+        writer.0.write_u32(conflict.candidates.len() as u32)?;
+        for candidate in &conflict.candidates {
+            writer.library_match(candidate)?;
+        }
+    }
+
+    Ok(std::mem::take(&mut *writer.0))
+}
+
+/// Loads a database back into a program, with all of its edits (and its undo history) restored.
+pub fn load(data: &[u8]) -> Result<Program, FerroxError> {
+    ensure!(
+        is_database(data),
+        InvalidDatabaseSnafu { reason: "missing FRX header" }
+    );
+    let mut reader = Reader(DataCursorRef::new(data, Endian::Big));
+    reader.0.set_position(MAGIC.len() as u64)?;
+    let version = reader.0.read_u32()?;
+    ensure!(
+        (1..=VERSION).contains(&version),
+        InvalidDatabaseSnafu { reason: format!("unsupported version {version}") }
+    );
+
+    let format = match reader.0.read_u8()? {
+        0 => BinaryFormat::BinaryFile,
+        1 => BinaryFormat::GameCubeDOL,
+        2 => BinaryFormat::GameCubeREL,
+        format => return InvalidDatabaseSnafu { reason: format!("unknown binary format {format}") }.fail(),
+    };
+    let path = reader.string()?.into();
+    let binary = reader.bytes()?;
+    let compression = match version {
+        ..5 => None,
+        _ => match reader.0.read_u8()? {
+            0 => None,
+            1 => Some(Compression::Yaz0),
+            2 => Some(Compression::Yay0),
+            compression => {
+                return InvalidDatabaseSnafu { reason: format!("unknown compression {compression}") }.fail()
+            }
+        },
+    };
+
+    let count = reader.0.read_u32()?;
+    let position = reader.0.read_u32()? as usize;
+    let mut edits = Vec::new();
+    for _ in 0..count {
+        edits.push(reader.edit()?);
+    }
+
+    let mut matches = BTreeMap::new();
+    let mut conflicts = Vec::new();
+    if version >= 2 {
+        for _ in 0..reader.0.read_u32()? {
+            matches.insert(reader.0.read_u32()?, reader.library_match()?);
+        }
+        for _ in 0..reader.0.read_u32()? {
+            let address = reader.0.read_u32()?;
+            let mut candidates = Vec::new();
+            for _ in 0..reader.0.read_u32()? {
+                candidates.push(reader.library_match()?);
+            }
+            conflicts.push(LibraryConflict { address, candidates });
+        }
+    }
+
+    let mut program = Program::load(path, binary, format)?;
+    program.compression = compression;
+    analysis::analyze(&mut program);
+    signature::apply(&mut program, matches, conflicts);
+    for edit in edits.iter().take(position) {
+        program.apply(edit, false);
+    }
+    program.history = History::from_parts(edits, position);
+    Ok(program)
+}
+
+struct Writer(DataStream<Vec<u8>>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) -> Result<(), FerroxError> {
+        self.0.write_u32(bytes.len() as u32)?;
+        self.0.extend_from_slice(bytes);
+        Ok(())
+    }
+
+    fn string(&mut self, string: &str) -> Result<(), FerroxError> {
+        self.bytes(string.as_bytes())
+    }
+
+    fn optional_string(&mut self, string: &Option<String>) -> Result<(), FerroxError> {
+        self.0.write_u8(string.is_some() as u8)?;
+        match string {
+            Some(string) => self.string(string),
+            None => Ok(()),
+        }
+    }
+
+    fn library_match(&mut self, library_match: &LibraryMatch) -> Result<(), FerroxError> {
+        self.string(&library_match.name)?;
+        self.string(&library_match.library)?;
+        self.0.write_f32(library_match.confidence)?;
+        Ok(())
+    }
+
+    fn operand_format(&mut self, format: &OperandFormat) -> Result<(), FerroxError> {
+        match format {
+            OperandFormat::Default => self.0.write_u8(0)?,
+            OperandFormat::Hex => self.0.write_u8(1)?,
+            OperandFormat::Decimal => self.0.write_u8(2)?,
+            OperandFormat::Char => self.0.write_u8(3)?,
+            OperandFormat::Offset => self.0.write_u8(4)?,
+            OperandFormat::Enum(name) => {
+                self.0.write_u8(5)?;
+                self.string(name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn type_info(&mut self, type_info: &TypeInfo) -> Result<(), FerroxError> {
+        match type_info {
+            TypeInfo::Function { name, is_extern } => {
+                self.0.write_u8(0)?;
+                self.string(name)?;
+                self.0.write_u8(*is_extern as u8)?;
+            }
+            TypeInfo::Integer { bits, signed } => {
+                self.0.write_u8(1)?;
+                self.0.write_u32(*bits)?;
+                self.0.write_u8(*signed as u8)?;
+            }
+            TypeInfo::Struct { name, size, members } | TypeInfo::Union { name, size, members } => {
+                // Tags 2 and 3 are the same without any members
+                self.0.write_u8(if matches!(type_info, TypeInfo::Struct { .. }) {
+                    9
+                } else {
+                    10
+                })?;
+                self.string(name)?;
+                self.0.write_u64(*size)?;
+                self.0.write_u32(members.len() as u32)?;
+                for member in members {
+                    self.string(&member.name)?;
+                    self.0.write_u64(member.offset)?;
+                    self.type_info(&member.type_info)?;
+                }
+            }
+            TypeInfo::Array { element_type, count } => {
+                self.0.write_u8(4)?;
+                self.type_info(element_type)?;
+                self.0.write_u64(*count)?;
+            }
+            TypeInfo::Enum { name, size, members } => {
+                self.0.write_u8(5)?;
+                self.string(name)?;
+                self.0.write_u64(*size)?;
+                self.0.write_u32(members.len() as u32)?;
+                for (member, value) in members {
+                    self.string(member)?;
+                    self.0.write_i64(*value)?;
+                }
+            }
+            // Tag 6 is a pointer without a target
+            TypeInfo::Pointer { target } => {
+                self.0.write_u8(11)?;
+                self.0.write_u8(target.is_some() as u8)?;
+                if let Some(target) = target {
+                    self.type_info(target)?;
+                }
+            }
+            TypeInfo::Named { name } => {
+                self.0.write_u8(12)?;
+                self.string(name)?;
+            }
+            TypeInfo::Float { bits } => {
+                self.0.write_u8(7)?;
+                self.0.write_u32(*bits)?;
+            }
+            TypeInfo::String { encoding, length } => {
+                self.0.write_u8(8)?;
+                self.0.write_u8(match encoding {
+                    TextEncoding::Ascii => 0,
+                    TextEncoding::ShiftJis => 1,
+                    TextEncoding::Utf16Be => 2,
+                })?;
+                self.0.write_u64(*length)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn optional_type_info(&mut self, type_info: &Option<TypeInfo>) -> Result<(), FerroxError> {
+        self.0.write_u8(type_info.is_some() as u8)?;
+        match type_info {
+            Some(type_info) => self.type_info(type_info),
+            None => Ok(()),
+        }
+    }
+
+    fn optional_prototype(&mut self, prototype: &Option<Prototype>) -> Result<(), FerroxError> {
+        self.0.write_u8(prototype.is_some() as u8)?;
+        let Some(prototype) = prototype else {
+            return Ok(());
+        };
+        self.optional_type_info(&prototype.return_type)?;
+        self.0.write_u32(prototype.parameters.len() as u32)?;
+        for parameter in &prototype.parameters {
+            self.string(&parameter.name)?;
+            self.type_info(&parameter.type_info)?;
+        }
+        Ok(())
+    }
+
+    fn edit(&mut self, edit: &Edit) -> Result<(), FerroxError> {
+        match edit {
+            Edit::Rename { address, old, new } => {
+                self.0.write_u8(0)?;
+                self.0.write_u32(*address)?;
+                self.optional_string(old)?;
+                self.optional_string(new)?;
+            }
+            Edit::Comment { address, repeatable, old, new } => {
+                self.0.write_u8(1)?;
+                self.0.write_u32(*address)?;
+                self.0.write_u8(*repeatable as u8)?;
+                self.optional_string(old)?;
+                self.optional_string(new)?;
+            }
+            Edit::OperandFormat { address, operand, old, new } => {
+                self.0.write_u8(2)?;
+                self.0.write_u32(*address)?;
+                self.0.write_u32(*operand as u32)?;
+                self.operand_format(old)?;
+                self.operand_format(new)?;
+            }
+            Edit::DefineType { name, old, new } => {
+                self.0.write_u8(3)?;
+                self.string(name)?;
+                self.optional_type_info(old)?;
+                self.optional_type_info(new)?;
+            }
+            Edit::Patch { address, old, new } => {
+                self.0.write_u8(5)?;
+                self.0.write_u32(*address)?;
+                self.bytes(old)?;
+                self.bytes(new)?;
+            }
+            Edit::Prototype { address, old, new } => {
+                self.0.write_u8(6)?;
+                self.0.write_u32(*address)?;
+                self.optional_prototype(old)?;
+                self.optional_prototype(new)?;
+            }
+            Edit::RenameVariable { function, variable, old, new } => {
+                self.0.write_u8(7)?;
+                self.0.write_u32(*function)?;
+                self.string(variable)?;
+                self.optional_string(old)?;
+                self.optional_string(new)?;
+            }
+            Edit::Batch { description, edits } => {
+                self.0.write_u8(4)?;
+                self.string(description)?;
+                self.0.write_u32(edits.len() as u32)?;
+                for edit in edits {
+                    self.edit(edit)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Reader<'a>(DataCursorRef<'a>);
+
+impl Reader<'_> {
+    fn bytes(&mut self) -> Result<Vec<u8>, FerroxError> {
+        let length = self.0.read_u32()? as usize;
+        Ok(self.0.read_slice(length)?.to_vec())
+    }
+
+    fn string(&mut self) -> Result<String, FerroxError> {
+        let length = self.0.read_u32()? as usize;
+        Ok(self.0.read_string(length)?.into_owned())
+    }
+
+    fn bool(&mut self) -> Result<bool, FerroxError> {
+        Ok(self.0.read_u8()? != 0)
+    }
+
+    fn optional_string(&mut self) -> Result<Option<String>, FerroxError> {
+        Ok(match self.bool()? {
+            true => Some(self.string()?),
+            false => None,
+        })
+    }
+
+    fn library_match(&mut self) -> Result<LibraryMatch, FerroxError> {
+        Ok(LibraryMatch {
+            name: self.string()?,
+            library: self.string()?,
+            confidence: self.0.read_f32()?,
+        })
+    }
+
+    fn operand_format(&mut self) -> Result<OperandFormat, FerroxError> {
+        Ok(match self.0.read_u8()? {
+            0 => OperandFormat::Default,
+            1 => OperandFormat::Hex,
+            2 => OperandFormat::Decimal,
+            3 => OperandFormat::Char,
+            4 => OperandFormat::Offset,
+            5 => OperandFormat::Enum(self.string()?),
+            tag => return InvalidDatabaseSnafu { reason: format!("unknown operand format {tag}") }.fail(),
+        })
+    }
+
+    fn type_info(&mut self) -> Result<TypeInfo, FerroxError> {
+        Ok(match self.0.read_u8()? {
+            0 => TypeInfo::Function { name: self.string()?, is_extern: self.bool()? },
+            1 => TypeInfo::Integer { bits: self.0.read_u32()?, signed: self.bool()? },
+            2 => TypeInfo::Struct { name: self.string()?, size: self.0.read_u64()?, members: Vec::new() },
+            3 => TypeInfo::Union { name: self.string()?, size: self.0.read_u64()?, members: Vec::new() },
+            4 => TypeInfo::Array { element_type: Box::new(self.type_info()?), count: self.0.read_u64()? },
+            5 => {
+                let name = self.string()?;
+                let size = self.0.read_u64()?;
+                let count = self.0.read_u32()?;
+                let mut members = Vec::new();
+                for _ in 0..count {
+                    members.push((self.string()?, self.0.read_i64()?));
+                }
+                TypeInfo::Enum { name, size, members }
+            }
+            6 => TypeInfo::pointer(),
+            7 => TypeInfo::Float { bits: self.0.read_u32()? },
+            8 => {
+                let encoding = match self.0.read_u8()? {
+                    0 => TextEncoding::Ascii,
+                    1 => TextEncoding::ShiftJis,
+                    2 => TextEncoding::Utf16Be,
+                    tag => return InvalidDatabaseSnafu { reason: format!("unknown encoding {tag}") }.fail(),
+                };
+                TypeInfo::String { encoding, length: self.0.read_u64()? }
+            }
+            tag @ (9 | 10) => {
+                let name = self.string()?;
+                let size = self.0.read_u64()?;
+                let mut members = Vec::new();
+                for _ in 0..self.0.read_u32()? {
+                    members.push(Member {
+                        name: self.string()?,
+                        offset: self.0.read_u64()?,
+                        type_info: self.type_info()?,
+                    });
+                }
+                match tag {
+                    9 => TypeInfo::Struct { name, size, members },
+                    _ => TypeInfo::Union { name, size, members },
+                }
+            }
+            11 => TypeInfo::Pointer {
+                target: match self.bool()? {
+                    true => Some(Box::new(self.type_info()?)),
+                    false => None,
+                },
+            },
+            12 => TypeInfo::Named { name: self.string()? },
+            tag => return InvalidDatabaseSnafu { reason: format!("unknown type {tag}") }.fail(),
+        })
+    }
+
+    fn optional_type_info(&mut self) -> Result<Option<TypeInfo>, FerroxError> {
+        Ok(match self.bool()? {
+            true => Some(self.type_info()?),
+            false => None,
+        })
+    }
+
+    fn optional_prototype(&mut self) -> Result<Option<Prototype>, FerroxError> {
+        if !self.bool()? {
+            return Ok(None);
+        }
+        let return_type = self.optional_type_info()?;
+        let mut parameters = Vec::new();
+        for _ in 0..self.0.read_u32()? {
+            parameters.push(Parameter { name: self.string()?, type_info: self.type_info()? });
+        }
+        Ok(Some(Prototype { return_type, parameters }))
+    }
+
+    fn edit(&mut self) -> Result<Edit, FerroxError> {
+        Ok(match self.0.read_u8()? {
+            0 => Edit::Rename {
+                address: self.0.read_u32()?,
+                old: self.optional_string()?,
+                new: self.optional_string()?,
+            },
+            1 => Edit::Comment {
+                address: self.0.read_u32()?,
+                repeatable: self.bool()?,
+                old: self.optional_string()?,
+                new: self.optional_string()?,
+            },
+            2 => Edit::OperandFormat {
+                address: self.0.read_u32()?,
+                operand: self.0.read_u32()? as usize,
+                old: self.operand_format()?,
+                new: self.operand_format()?,
+            },
+            3 => Edit::DefineType {
+                name: self.string()?,
+                old: self.optional_type_info()?,
+                new: self.optional_type_info()?,
+            },
+            4 => {
+                let description = self.string()?;
+                let mut edits = Vec::new();
+                for _ in 0..self.0.read_u32()? {
+                    edits.push(self.edit()?);
+                }
+                Edit::Batch { description, edits }
+            }
+            5 => Edit::Patch { address: self.0.read_u32()?, old: self.bytes()?, new: self.bytes()? },
+            6 => Edit::Prototype {
+                address: self.0.read_u32()?,
+                old: self.optional_prototype()?,
+                new: self.optional_prototype()?,
+            },
+            7 => Edit::RenameVariable {
+                function: self.0.read_u32()?,
+                variable: self.string()?,
+                old: self.optional_string()?,
+                new: self.optional_string()?,
+            },
+            tag => return InvalidDatabaseSnafu { reason: format!("unknown edit {tag}") }.fail(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TEXT};
+
+    /// A program with a bit of everything saved in a database, and its last edit undone.
+    fn edited() -> Program {
+        let mut program = testing::program(
+            "
+            li r3, 0
+            blr
+        helper:
+            li r3, 1
+            blr
+            ",
+            &[],
+        );
+        program.compression = Some(Compression::Yaz0);
+        program.rename(TEXT + 8, "helper").unwrap();
+        program.set_comment(TEXT, "entry", false);
+        program.set_comment(TEXT + 8, "shared", true);
+        program.assemble(TEXT + 8, "li r3, 2").unwrap();
+        program.set_operand_format(TEXT + 8, 1, OperandFormat::Hex);
+        program.rename_variable(TEXT + 8, "r3", "result").unwrap();
+        program.rename(TEXT + 8, "calc__3FooFi").unwrap();
+        program.undo();
+        program.library_functions.insert(
+            TEXT,
+            LibraryMatch {
+                name: "__start".to_owned(),
+                library: "Dolphin SDK 1.0".to_owned(),
+                confidence: 0.75,
+            },
+        );
+        program
+    }
+
+    #[test]
+    fn round_trips_programs() {
+        let program = edited();
+        let data = save(&program).unwrap();
+        assert!(is_database(&data));
+        let mut loaded = load(&data).unwrap();
+
+        assert_eq!(loaded.path, program.path);
+        assert_eq!(loaded.compression, Some(Compression::Yaz0));
+        assert_eq!(loaded.names, program.names);
+        assert_eq!(loaded.names[&(TEXT + 8)], "helper");
+        assert_eq!(loaded.comments, program.comments);
+        assert_eq!(loaded.repeatable_comments, program.repeatable_comments);
+        assert_eq!(loaded.data, program.data);
+        assert_eq!(loaded.read_u32(TEXT + 8), Some(0x3860_0002));
+        assert_eq!(loaded.patches[&(TEXT + 8)].original, 0x3860_0001u32.to_be_bytes());
+        assert_eq!(loaded.operand_format(TEXT + 8, 1), &OperandFormat::Hex);
+        assert_eq!(loaded.variable_name(TEXT + 8, "r3"), "result");
+        assert_eq!(loaded.library_functions, program.library_functions);
+
+        // The undone rename is still there to redo
+        assert_eq!(loaded.history.position(), 6);
+        assert_eq!(loaded.history.edits().len(), 7);
+        assert!(loaded.redo());
+        assert_eq!(loaded.names[&(TEXT + 8)], "calc__3FooFi");
+        assert!(loaded.types.prototype(TEXT + 8).is_some());
+    }
+
+    #[test]
+    fn reads_older_versions() {
+        let program = edited();
+        let mut data = save(&program).unwrap();
+        // Version 4 is the same without the compression byte after the binary
+        let path = program.path.to_string_lossy().len();
+        let compression = MAGIC.len() + 4 + 1 + 4 + path + 4 + program.data.len();
+        assert_eq!(data[compression], 1, "Yaz0");
+        data.remove(compression);
+        data[4..8].copy_from_slice(&4u32.to_be_bytes());
+        let loaded = load(&data).unwrap();
+        assert_eq!(loaded.compression, None);
+        assert_eq!(loaded.names, program.names);
+
+        data[4..8].copy_from_slice(&(VERSION + 1).to_be_bytes());
+        assert!(load(&data).is_err());
+        assert!(load(b"FRX").is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! The C syntax tree the pseudocode is built out of, before it gets printed.
+use crate::registry::TypeInfo;
+
+/// Index into [`super::Pseudocode::variables`].
+pub type VariableId = usize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    /// `~`
+    BitNot,
+    /// `!`
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    And,
+    Or,
+    Xor,
+    Shl,
+    Shr,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LogicalAnd,
+    LogicalOr,
+}
+
+impl BinaryOp {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Sub => "-",
+            Self::Mul => "*",
+            Self::Div => "/",
+            Self::And => "&",
+            Self::Or => "|",
+            Self::Xor => "^",
+            Self::Shl => "<<",
+            Self::Shr => ">>",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::LogicalAnd => "&&",
+            Self::LogicalOr => "||",
+        }
+    }
+
+    /// How tightly the operator binds, higher binds tighter.
+    pub fn precedence(self) -> u8 {
+        match self {
+            Self::Mul | Self::Div => 10,
+            Self::Add | Self::Sub => 9,
+            Self::Shl | Self::Shr => 8,
+            Self::Lt | Self::Le | Self::Gt | Self::Ge => 7,
+            Self::Eq | Self::Ne => 6,
+            Self::And => 5,
+            Self::Xor => 4,
+            Self::Or => 3,
+            Self::LogicalAnd => 2,
+            Self::LogicalOr => 1,
+        }
+    }
+
+    /// The comparison that's true exactly when this one is false.
+    pub fn inverse(self) -> Option<Self> {
+        Some(match self {
+            Self::Eq => Self::Ne,
+            Self::Ne => Self::Eq,
+            Self::Lt => Self::Ge,
+            Self::Le => Self::Gt,
+            Self::Gt => Self::Le,
+            Self::Ge => Self::Lt,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Variable(VariableId),
+    /// An integer, shown as signed if it's a small negative number
+    Constant(u32),
+    Float(f64),
+    String(String),
+    /// Something at a fixed address, by name
+    Global(u32),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    Cast(TypeInfo, Box<Expr>),
+    /// `*(type *)address`, or just `*address` if it already points to the right type
+    Deref(Option<TypeInfo>, Box<Expr>),
+    /// `base` followed by a member access or index, like `->next` or `[2].x`
+    Member(Box<Expr>, String),
+    /// `base[index]`, for an element of whatever `base` points to
+    Index(Box<Expr>, Box<Expr>),
+    AddressOf(Box<Expr>),
+    /// A call to a function at a known address
+    Call(u32, Vec<Expr>),
+    IndirectCall(Box<Expr>, Vec<Expr>),
+    /// Compiler intrinsics, and anything else without a C equivalent
+    Intrinsic(String, Vec<Expr>),
+}
+
+impl Expr {
+    pub fn binary(op: BinaryOp, left: Expr, right: Expr) -> Self {
+        Self::Binary(op, Box::new(left), Box::new(right))
+    }
+
+    /// `!self`, flipping comparisons rather than wrapping them where possible.
+    pub fn not(self) -> Self {
+        match self {
+            Self::Binary(op, left, right) if op.inverse().is_some() => {
+                Self::Binary(op.inverse().unwrap(), left, right)
+            }
+            Self::Binary(BinaryOp::LogicalAnd, left, right) => {
+                Self::binary(BinaryOp::LogicalOr, left.not(), right.not())
+            }
+            Self::Binary(BinaryOp::LogicalOr, left, right) => {
+                Self::binary(BinaryOp::LogicalAnd, left.not(), right.not())
+            }
+            Self::Unary(UnaryOp::Not, inner) => *inner,
+            expr => Self::Unary(UnaryOp::Not, Box::new(expr)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Expr(Expr),
+    Assign(Expr, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    While(Expr, Vec<Stmt>),
+    DoWhile(Vec<Stmt>, Expr),
+    For(Box<Stmt>, Expr, Box<Stmt>, Vec<Stmt>),
+    /// Each case lists the values that lead to it
+    Switch(Expr, Vec<(Vec<u32>, Vec<Stmt>)>, Option<Vec<Stmt>>),
+    Break,
+    Continue,
+    Return(Option<Expr>),
+    /// Goes to the label of a block
+    Goto(usize),
+    Label(usize),
+    Comment(String),
+}
+
+/// A statement, along with the address of the code it came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub address: u32,
+    pub kind: StmtKind,
+}
+
+impl Stmt {
+    pub fn new(address: u32, kind: StmtKind) -> Self {
+        Self { address, kind }
+    }
+
+    /// Whether control never carries on to whatever comes after this statement.
+    pub fn is_jump(&self) -> bool {
+        match &self.kind {
+            StmtKind::Break | StmtKind::Continue | StmtKind::Return(_) | StmtKind::Goto(_) => true,
+            StmtKind::If(_, then, otherwise) => {
+                then.last().is_some_and(Stmt::is_jump) && otherwise.last().is_some_and(Stmt::is_jump)
+            }
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,1694 @@
+//! Getting a function out of SSA form and into C: working out what it takes and returns, dropping the stack
+//! frame's bookkeeping, merging SSA versions back into variables, and folding values that are only used once
+//! into whatever uses them.
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use super::ast::{BinaryOp, Expr, Stmt, StmtKind, UnaryOp, VariableId};
+use super::{Variable as CVariable, VariableKind};
+use crate::analysis::data::small_data_pointers;
+use crate::analysis::frame;
+use crate::ir::{
+    self, lift, ssa, Access, AccessKind, Block, BlockId, Callee, IrFunction, Op, Operand, Register,
+    Statement, Terminator, Variable,
+};
+use crate::program::Program;
+use crate::registry::{Parameter, Prototype, TypeInfo, TypeRegistry};
+
+/// The stack pointer on entry, which every stack address is relative to once SSA has folded them.
+const STACK: Variable = Variable { register: Register::Gpr(1), version: 0 };
+
+/// Registers the EABI passes each parameter in, `None` for ones that don't fit and go on the stack. 64-bit
+/// integers take an odd/even pair of GPRs, and structs are passed as a pointer to a copy.
+pub fn parameter_registers(registry: &TypeRegistry, parameters: &[Parameter]) -> Vec<Option<Register>> {
+    let (mut gpr, mut fpr) = (3, 1);
+    parameters
+        .iter()
+        .map(|parameter| match registry.resolve(&parameter.type_info) {
+            Some(TypeInfo::Float { .. }) => (fpr <= 8).then(|| {
+                fpr += 1;
+                Register::Fpr(fpr - 1)
+            }),
+            Some(TypeInfo::Integer { bits: 64, .. }) => {
+                gpr += 1 - gpr % 2;
+                let register = (gpr < 10).then_some(Register::Gpr(gpr));
+                gpr += 2;
+                register
+            }
+            _ => (gpr <= 10).then(|| {
+                gpr += 1;
+                Register::Gpr(gpr - 1)
+            }),
+        })
+        .collect()
+}
+
+/// Register a value of this type gets returned in, `None` for `void`.
+pub fn return_register(registry: &TypeRegistry, type_info: Option<&TypeInfo>) -> Option<Register> {
+    match registry.resolve(type_info?) {
+        Some(TypeInfo::Float { .. }) => Some(Register::Fpr(1)),
+        _ => Some(Register::Gpr(3)),
+    }
+}
+
+/// A function's blocks as C statements, still as a control flow graph. Block 0 is the entry.
+pub(super) struct Body {
+    pub blocks: Vec<CBlock>,
+    pub variables: Vec<CVariable>,
+    pub parameters: Vec<VariableId>,
+    pub return_type: Option<TypeInfo>,
+}
+
+impl Body {
+    /// The signature the function was decompiled with.
+    pub fn prototype(&self) -> Prototype {
+        let parameters = self.parameters.iter().map(|&parameter| {
+            let variable = &self.variables[parameter];
+            Parameter { name: variable.name.clone(), type_info: variable.type_info.clone() }
+        });
+        Prototype {
+            return_type: self.return_type.clone(),
+            parameters: parameters.collect(),
+        }
+    }
+}
+
+pub(super) struct CBlock {
+    pub address: u32,
+    pub statements: Vec<Stmt>,
+    pub exit: Exit,
+}
+
+/// How control leaves a [`CBlock`].
+#[derive(Clone)]
+pub(super) enum Exit {
+    Jump(BlockId),
+    /// Goes to the first block if the condition holds, otherwise the second
+    Branch(Expr, BlockId, BlockId),
+    Switch(Expr, Vec<BlockId>),
+    Return(Option<Expr>),
+    /// Jumps somewhere only known at runtime, or runs into something that couldn't be lifted
+    Unknown(Option<Expr>),
+}
+
+impl Exit {
+    pub fn successors(&self) -> Vec<BlockId> {
+        let mut successors = Vec::new();
+        let targets = match self {
+            Self::Jump(target) => vec![*target],
+            Self::Branch(_, taken, not_taken) => vec![*taken, *not_taken],
+            Self::Switch(_, targets) => targets.clone(),
+            Self::Return(_) | Self::Unknown(_) => Vec::new(),
+        };
+        for target in targets {
+            if !successors.contains(&target) {
+                successors.push(target);
+            }
+        }
+        successors
+    }
+}
+
+/// Builds the C body of the function at `address`, going by `prototype` for its signature if there is one.
+pub(super) fn build(program: &Program, address: u32, prototype: Option<Prototype>) -> Option<Body> {
+    let mut function = ir::lift(program, address)?;
+    let setups = argument_setups(&function);
+    ssa::build(&mut function);
+    let (sda, sda2) = small_data_pointers(program);
+    substitute_bases(&mut function, sda, sda2);
+    ssa::optimize(&mut function);
+
+    let version = function
+        .blocks
+        .iter()
+        .flat_map(|block| &block.statements)
+        .flat_map(Statement::definitions)
+        .map(|variable| variable.version)
+        .max()
+        .unwrap_or(0);
+    let mut builder = Builder {
+        program,
+        function,
+        setups,
+        sda2,
+        prototype,
+        definitions: HashMap::new(),
+        version: version + 1,
+        return_register: None,
+        return_type: None,
+        parameters: Vec::new(),
+        webs: HashMap::new(),
+        members: HashMap::new(),
+        types: HashMap::new(),
+        variables: Vec::new(),
+        ids: HashMap::new(),
+        slots: HashMap::new(),
+    };
+    builder.calls();
+    builder.returns();
+    builder.remove_frame();
+    builder.find_parameters();
+    builder.split_critical_edges();
+    builder.merge_webs();
+    builder.find_types();
+    Some(builder.emit())
+}
+
+/// Argument registers each call looks like it sets up, keyed by the address of its block and how many calls
+/// come before it there. Without a prototype the best guess is whichever ones were written since the previous
+/// call. This has to be done before SSA, while copies still show which registers they go into.
+fn argument_setups(function: &IrFunction) -> HashMap<(u32, usize), BTreeSet<Register>> {
+    let mut setups = HashMap::new();
+    for block in &function.blocks {
+        let mut written = BTreeSet::new();
+        let mut calls = 0;
+        for statement in &block.statements {
+            if let Statement::Call { .. } = statement {
+                setups.insert((block.address, calls), std::mem::take(&mut written));
+                calls += 1;
+            } else {
+                written.extend(statement.definitions().into_iter().map(|variable| variable.register));
+            }
+        }
+        if let Terminator::TailCall { .. } = block.terminator {
+            setups.insert((block.address, calls), written);
+        }
+    }
+    setups
+}
+
+/// Arguments are handed out in order, so anything below the highest register that's set up counts too.
+fn used_arguments(written: &BTreeSet<Register>) -> Vec<Register> {
+    let gprs = (3..=10).rev().find(|&n| written.contains(&Register::Gpr(n))).unwrap_or(2);
+    let fprs = (1..=8).rev().find(|&n| written.contains(&Register::Fpr(n))).unwrap_or(0);
+    (3..=gprs).map(Register::Gpr).chain((1..=fprs).map(Register::Fpr)).collect()
+}
+
+/// Swaps the small data base registers for their values, so everything addressed off of them folds into a
+/// plain address.
+fn substitute_bases(function: &mut IrFunction, sda: Option<u32>, sda2: Option<u32>) {
+    for (register, value) in [(13, sda), (2, sda2)] {
+        let Some(value) = value else {
+            continue;
+        };
+        let base = Operand::Variable(Variable::new(Register::Gpr(register)));
+        for block in &mut function.blocks {
+            let operands =
+                block.statements.iter_mut().flat_map(Statement::uses_mut).chain(block.terminator.uses_mut());
+            for operand in operands {
+                if *operand == base {
+                    *operand = Operand::Constant(value);
+                }
+            }
+        }
+    }
+}
+
+fn retarget(terminator: &mut Terminator, from: BlockId, to: BlockId) {
+    let targets = match terminator {
+        Terminator::Jump(target) => vec![target],
+        Terminator::Branch { taken, not_taken, .. } => vec![taken, not_taken],
+        Terminator::Switch { targets, .. } => targets.iter_mut().collect(),
+        _ => Vec::new(),
+    };
+    for target in targets {
+        if *target == from {
+            *target = to;
+        }
+    }
+}
+
+fn access_type(access: Access) -> TypeInfo {
+    let bits = u32::from(access.size) * 8;
+    match access.kind {
+        AccessKind::Float => TypeInfo::Float { bits },
+        // Nothing gets extended when loading a whole word, so it may as well be the default
+        AccessKind::Unsigned if bits == 32 => TypeInfo::Integer { bits, signed: true },
+        kind => TypeInfo::Integer { bits, signed: kind == AccessKind::Signed },
+    }
+}
+
+fn int() -> TypeInfo {
+    TypeInfo::Integer { bits: 32, signed: true }
+}
+
+fn uint() -> TypeInfo {
+    TypeInfo::Integer { bits: 32, signed: false }
+}
+
+/// How much freedom there is to move a value's expression somewhere else.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Motion {
+    /// Only depends on its operands
+    Pure,
+    /// Reads memory, so can't move past anything that might write it
+    Load,
+    /// Has side effects, so can't move past anything that isn't pure
+    Call,
+}
+
+/// Strength of what a variable's type is based on, so better evidence replaces worse.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Evidence {
+    Access,
+    Address,
+    Declared,
+}
+
+struct Builder<'a> {
+    program: &'a Program,
+    function: IrFunction,
+    setups: HashMap<(u32, usize), BTreeSet<Register>>,
+    /// Base of `.sdata2`, where CodeWarrior keeps floating point constants
+    sda2: Option<u32>,
+    prototype: Option<Prototype>,
+    /// Where each variable is defined, as (block, statement)
+    definitions: HashMap<Variable, (BlockId, usize)>,
+    /// Next version that's free for a new variable
+    version: u32,
+    return_register: Option<Register>,
+    return_type: Option<TypeInfo>,
+    /// Register, declared name and type of each parameter. The name is empty if there isn't a prototype
+    parameters: Vec<(Option<Register>, String, TypeInfo)>,
+    /// Union-find parents, for SSA variables that share a C variable
+    webs: HashMap<Variable, Variable>,
+    members: HashMap<Variable, Vec<Variable>>,
+    /// Type of each web, by its root
+    types: HashMap<Variable, (Evidence, TypeInfo)>,
+    variables: Vec<CVariable>,
+    /// C variable of each web that's been given one, by its root
+    ids: HashMap<Variable, VariableId>,
+    /// C variable of each stack slot, by offset
+    slots: HashMap<i32, VariableId>,
+}
+
+impl Builder<'_> {
+    fn index(&mut self) {
+        self.definitions.clear();
+        for (id, block) in self.function.blocks.iter().enumerate() {
+            for (index, statement) in block.statements.iter().enumerate() {
+                for variable in statement.definitions() {
+                    self.definitions.insert(variable, (id, index));
+                }
+            }
+        }
+    }
+
+    fn definition(&self, variable: Variable) -> Option<&Statement> {
+        let &(block, index) = self.definitions.get(&variable)?;
+        self.function.blocks[block].statements.get(index)
+    }
+
+    fn fresh(&mut self, register: Register) -> Variable {
+        self.version += 1;
+        Variable { register, version: self.version - 1 }
+    }
+
+    /// Trims every call's arguments down to the ones it actually takes, and its results down to the one it
+    /// returns, going by the callee's prototype if it has one.
+    fn calls(&mut self) {
+        self.index();
+        let registers = lift::call_arguments();
+        let program = self.program;
+        let registry = &program.types;
+        for id in 0..self.function.blocks.len() {
+            let address = self.function.blocks[id].address;
+            let mut ordinal = 0;
+            for index in 0..self.function.blocks[id].statements.len() {
+                let Statement::Call { callee, arguments, .. } = &self.function.blocks[id].statements[index]
+                else {
+                    continue;
+                };
+                let prototype = match callee {
+                    Callee::Direct(target) => registry.prototype(*target),
+                    Callee::Indirect(_) => None,
+                };
+                let (used, result) = match prototype {
+                    Some(prototype) => (
+                        parameter_registers(registry, &prototype.parameters).into_iter().flatten().collect(),
+                        return_register(registry, prototype.return_type.as_ref()),
+                    ),
+                    None => {
+                        let mut written = self.setups.get(&(address, ordinal)).cloned().unwrap_or_default();
+                        // Arguments set on the way into the block show up as phis
+                        if ordinal == 0 {
+                            for (register, argument) in registers.iter().zip(arguments) {
+                                let phi =
+                                    argument.variable().and_then(|variable| self.definitions.get(&variable));
+                                if phi.is_some_and(|&(block, index)| {
+                                    block == id
+                                        && matches!(
+                                            self.function.blocks[id].statements[index],
+                                            Statement::Phi { .. }
+                                        )
+                                }) {
+                                    written.insert(*register);
+                                }
+                            }
+                        }
+                        (used_arguments(&written), None)
+                    }
+                };
+                ordinal += 1;
+                let Statement::Call { arguments, results, .. } =
+                    &mut self.function.blocks[id].statements[index]
+                else {
+                    unreachable!();
+                };
+                *arguments = used
+                    .iter()
+                    .filter_map(|register| registers.iter().position(|other| other == register))
+                    .map(|position| arguments[position])
+                    .collect();
+                let result = result.or_else(|| {
+                    [Register::Gpr(3), Register::Fpr(1)]
+                        .into_iter()
+                        .find(|&register| results.iter().any(|variable| variable.register == register))
+                });
+                results.retain(|variable| Some(variable.register) == result);
+            }
+        }
+        self.index();
+    }
+
+    /// Works out what the function returns, then trims every return down to that value and turns tail calls
+    /// into a call followed by a return.
+    fn returns(&mut self) {
+        let program = self.program;
+        let registry = &program.types;
+        match &self.prototype {
+            Some(prototype) => {
+                self.return_register = return_register(registry, prototype.return_type.as_ref());
+                self.return_type = prototype.return_type.clone();
+            }
+            None => {
+                self.return_register = self.guess_return();
+                self.return_type = match self.return_register {
+                    Some(Register::Fpr(_)) => Some(TypeInfo::Float { bits: 64 }),
+                    Some(_) => Some(int()),
+                    None => None,
+                };
+            }
+        }
+
+        let registers = lift::call_arguments();
+        for id in 0..self.function.blocks.len() {
+            let block = &self.function.blocks[id];
+            match block.terminator.clone() {
+                Terminator::Return { values } => {
+                    self.function.blocks[id].terminator =
+                        Terminator::Return { values: self.return_values(&values) };
+                }
+                Terminator::TailCall { target, values } => {
+                    let used: Vec<Register> = match registry.prototype(target) {
+                        Some(prototype) => parameter_registers(registry, &prototype.parameters)
+                            .into_iter()
+                            .flatten()
+                            .collect(),
+                        None => {
+                            let calls = block
+                                .statements
+                                .iter()
+                                .filter(|s| matches!(s, Statement::Call { .. }))
+                                .count();
+                            let mut written =
+                                self.setups.get(&(block.address, calls)).cloned().unwrap_or_default();
+                            written.extend(
+                                values
+                                    .iter()
+                                    .filter(|(register, value)| {
+                                        registers.contains(register) && *value != Operand::from(*register)
+                                    })
+                                    .map(|(register, _)| *register),
+                            );
+                            used_arguments(&written)
+                        }
+                    };
+                    let value = |register: &Register| {
+                        values
+                            .iter()
+                            .find(|(other, _)| other == register)
+                            .map_or((*register).into(), |(_, v)| *v)
+                    };
+                    let arguments = used.iter().map(value).collect();
+                    let result = self.return_register.map(|register| self.fresh(register));
+                    let block = &mut self.function.blocks[id];
+                    block.statements.push(Statement::Call {
+                        callee: Callee::Direct(target),
+                        condition: None,
+                        arguments,
+                        results: result.into_iter().collect(),
+                    });
+                    let values = result.map(|result| (result.register, Operand::Variable(result)));
+                    block.terminator = Terminator::Return { values: values.into_iter().collect() };
+                }
+                _ => (),
+            }
+        }
+        self.index();
+    }
+
+    fn return_values(&self, values: &[(Register, Operand)]) -> Vec<(Register, Operand)> {
+        let value = |register: Register| {
+            values.iter().find(|(other, _)| *other == register).map_or(register.into(), |(_, value)| *value)
+        };
+        self.return_register.map(|register| (register, value(register))).into_iter().collect()
+    }
+
+    /// Without a prototype, a function returns something if it hands back a value it worked out itself, rather
+    /// than whatever a call happened to leave behind.
+    fn guess_return(&self) -> Option<Register> {
+        [Register::Gpr(3), Register::Fpr(1)].into_iter().find(|&register| {
+            self.function.blocks.iter().any(|block| match &block.terminator {
+                Terminator::Return { values } => values
+                    .iter()
+                    .any(|(other, value)| *other == register && self.computed(*value, &mut HashSet::new())),
+                _ => false,
+            })
+        })
+    }
+
+    fn computed(&self, operand: Operand, visited: &mut HashSet<Variable>) -> bool {
+        let Operand::Variable(variable) = operand else {
+            return true;
+        };
+        if variable.version == 0 || !visited.insert(variable) {
+            return false;
+        }
+        match self.definition(variable) {
+            Some(Statement::Call { .. }) | None => false,
+            Some(Statement::Phi { sources, .. }) => {
+                sources.iter().any(|(_, source)| self.computed(*source, visited))
+            }
+            Some(_) => true,
+        }
+    }
+
+    /// Offset from the stack pointer on entry, if the operand is an address in the stack frame.
+    fn stack_offset(&self, operand: Operand) -> Option<i32> {
+        let variable = operand.variable()?;
+        if variable == STACK {
+            return Some(0);
+        }
+        match self.definition(variable)? {
+            Statement::Assign { op: Op::Add, args, .. } => match args[..] {
+                [Operand::Variable(STACK), Operand::Constant(offset)] => Some(offset as i32),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Removes the stack frame's bookkeeping: saving and restoring registers, the back chain and the return
+    /// address. Loading from the stack can't have side effects, so unused loads go like any other value, and
+    /// stores only stay if something reads them back or the frame's address gets passed somewhere.
+    fn remove_frame(&mut self) {
+        loop {
+            let mut loads = Vec::new();
+            let mut escapes = false;
+            for block in &self.function.blocks {
+                for statement in &block.statements {
+                    let allowed = match statement {
+                        Statement::Load { access, address, .. } => {
+                            if let Some(offset) = self.stack_offset(*address) {
+                                loads.push((offset, i32::from(access.size)));
+                            }
+                            vec![*address]
+                        }
+                        // The back chain
+                        Statement::Store { address, value: Operand::Variable(STACK), .. } => {
+                            vec![*address, Operand::Variable(STACK)]
+                        }
+                        Statement::Store { address, .. } => vec![*address],
+                        Statement::Assign { dest, .. }
+                            if self.stack_offset(Operand::Variable(*dest)).is_some() =>
+                        {
+                            vec![Operand::Variable(STACK)]
+                        }
+                        _ => Vec::new(),
+                    };
+                    let mut uses = statement.clone();
+                    escapes |= uses
+                        .uses_mut()
+                        .into_iter()
+                        .any(|operand| !allowed.contains(operand) && self.stack_offset(*operand).is_some());
+                }
+                let mut terminator = block.terminator.clone();
+                escapes |=
+                    terminator.uses_mut().into_iter().any(|operand| self.stack_offset(*operand).is_some());
+            }
+
+            let arguments = lift::call_arguments();
+            let needed = |statement: &Statement| match statement {
+                Statement::Load { address, .. } if self.stack_offset(*address).is_some() => false,
+                Statement::Store { access, address, value } => match self.stack_offset(*address) {
+                    None => true,
+                    // The back chain is only there for unwinding
+                    Some(_) if *value == Operand::Variable(STACK) => false,
+                    Some(offset) => {
+                        let saved = value.variable().is_some_and(|variable| {
+                            variable.version == 0 && !arguments.contains(&variable.register)
+                        });
+                        let size = i32::from(access.size);
+                        let read = loads
+                            .iter()
+                            .any(|&(load, length)| load < offset + size && offset < load + length);
+                        !saved && (escapes || read)
+                    }
+                },
+                statement => statement.has_effects(),
+            };
+
+            let mut live = HashSet::new();
+            let mut worklist = Vec::new();
+            for block in &self.function.blocks {
+                for statement in block.statements.iter().filter(|statement| needed(statement)) {
+                    worklist.extend(
+                        statement.clone().uses_mut().into_iter().filter_map(|operand| operand.variable()),
+                    );
+                }
+                worklist.extend(block.terminator.clone().uses_mut().into_iter().filter_map(|o| o.variable()));
+            }
+            while let Some(variable) = worklist.pop() {
+                if !live.insert(variable) {
+                    continue;
+                }
+                if let Some(statement) = self.definition(variable).filter(|statement| !needed(statement)) {
+                    worklist.extend(
+                        statement.clone().uses_mut().into_iter().filter_map(|operand| operand.variable()),
+                    );
+                }
+            }
+
+            let keep: Vec<Vec<bool>> = self
+                .function
+                .blocks
+                .iter()
+                .map(|block| {
+                    block
+                        .statements
+                        .iter()
+                        .map(|statement| {
+                            needed(statement)
+                                || statement.definitions().iter().any(|variable| live.contains(variable))
+                        })
+                        .collect()
+                })
+                .collect();
+            let mut changed = false;
+            for (block, keep) in self.function.blocks.iter_mut().zip(keep) {
+                let mut keep = keep.into_iter();
+                let length = block.statements.len();
+                block.statements.retain(|_| keep.next().unwrap());
+                changed |= block.statements.len() != length;
+            }
+            self.index();
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    /// Parameters come from the prototype if there is one. Otherwise they're whichever argument registers get
+    /// read before being written, plus every one below them.
+    fn find_parameters(&mut self) {
+        let program = self.program;
+        let registry = &program.types;
+        if let Some(prototype) = &self.prototype {
+            let registers = parameter_registers(registry, &prototype.parameters);
+            self.parameters = registers
+                .into_iter()
+                .zip(&prototype.parameters)
+                .map(|(register, parameter)| (register, parameter.name.clone(), parameter.type_info.clone()))
+                .collect();
+            return;
+        }
+        let mut read = BTreeSet::new();
+        for block in &mut self.function.blocks {
+            let operands =
+                block.statements.iter_mut().flat_map(Statement::uses_mut).chain(block.terminator.uses_mut());
+            read.extend(
+                operands
+                    .filter_map(|operand| operand.variable())
+                    .filter(|v| v.version == 0)
+                    .map(|v| v.register),
+            );
+        }
+        self.parameters = used_arguments(&read)
+            .into_iter()
+            .map(|register| {
+                let type_info = match register {
+                    Register::Fpr(_) => TypeInfo::Float { bits: 64 },
+                    _ => int(),
+                };
+                (Some(register), String::new(), type_info)
+            })
+            .collect();
+    }
+
+    /// Gives every edge from a block with several successors into a block with phis a block of its own, so
+    /// there's somewhere to put the copies the phis turn into.
+    fn split_critical_edges(&mut self) {
+        let predecessors = self.function.predecessors();
+        for id in 0..self.function.blocks.len() {
+            let successors = self.function.blocks[id].terminator.successors();
+            if successors.len() < 2 {
+                continue;
+            }
+            for successor in successors {
+                let has_phis = matches!(
+                    self.function.blocks[successor].statements.first(),
+                    Some(Statement::Phi { .. })
+                );
+                if predecessors[successor].len() < 2 || !has_phis {
+                    continue;
+                }
+                let new = self.function.blocks.len();
+                self.function.blocks.push(Block {
+                    address: self.function.blocks[successor].address,
+                    statements: Vec::new(),
+                    terminator: Terminator::Jump(successor),
+                });
+                retarget(&mut self.function.blocks[id].terminator, successor, new);
+                for statement in &mut self.function.blocks[successor].statements {
+                    if let Statement::Phi { sources, .. } = statement {
+                        for (block, _) in sources.iter_mut().filter(|(block, _)| *block == id) {
+                            *block = new;
+                        }
+                    }
+                }
+            }
+        }
+        self.index();
+    }
+
+    /// Operands read by phis in `successor` when coming from `block`.
+    fn phi_sources(&self, block: BlockId, successor: BlockId) -> Vec<(Variable, Operand)> {
+        self.function.blocks[successor]
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Phi { dest, sources } => sources
+                    .iter()
+                    .find(|(predecessor, _)| *predecessor == block)
+                    .map(|(_, source)| (*dest, *source)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn web(&self, variable: Variable) -> Variable {
+        let mut variable = variable;
+        while let Some(&parent) = self.webs.get(&variable) {
+            variable = parent;
+        }
+        variable
+    }
+
+    fn web_members(&self, root: Variable) -> Vec<Variable> {
+        self.members.get(&root).cloned().unwrap_or_else(|| vec![root])
+    }
+
+    /// Variables that are live into each block. Phis read their sources at the end of the predecessor, so
+    /// those count as live out of it instead.
+    fn live_out(&self) -> Vec<HashSet<Variable>> {
+        let count = self.function.blocks.len();
+        let mut live_in: Vec<HashSet<Variable>> = vec![HashSet::new(); count];
+        let mut live_out: Vec<HashSet<Variable>> = vec![HashSet::new(); count];
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for id in (0..count).rev() {
+                let block = &self.function.blocks[id];
+                let mut out = HashSet::new();
+                for successor in block.terminator.successors() {
+                    let phis: HashSet<Variable> = self.function.blocks[successor]
+                        .statements
+                        .iter()
+                        .filter(|statement| matches!(statement, Statement::Phi { .. }))
+                        .flat_map(Statement::definitions)
+                        .collect();
+                    out.extend(live_in[successor].iter().filter(|variable| !phis.contains(variable)));
+                    out.extend(
+                        self.phi_sources(id, successor)
+                            .into_iter()
+                            .filter_map(|(_, source)| source.variable()),
+                    );
+                }
+                let mut live = out.clone();
+                live.extend(
+                    block.terminator.clone().uses_mut().into_iter().filter_map(|operand| operand.variable()),
+                );
+                for statement in block.statements.iter().rev().filter(|s| !matches!(s, Statement::Phi { .. }))
+                {
+                    for variable in statement.definitions() {
+                        live.remove(&variable);
+                    }
+                    live.extend(
+                        statement.clone().uses_mut().into_iter().filter_map(|operand| operand.variable()),
+                    );
+                }
+                if live != live_in[id] || out != live_out[id] {
+                    live_in[id] = live;
+                    live_out[id] = out;
+                    changed = true;
+                }
+            }
+        }
+        live_out
+    }
+
+    /// Pairs of variables that are alive at the same time, so can't share a C variable.
+    fn interference(&self) -> HashSet<(Variable, Variable)> {
+        let mut pairs = HashSet::new();
+        let mut interfere = |variable: Variable, live: &HashSet<Variable>| {
+            for &other in live.iter().filter(|&&other| other != variable) {
+                pairs.insert((variable.min(other), variable.max(other)));
+            }
+        };
+        for (id, (block, out)) in self.function.blocks.iter().zip(self.live_out()).enumerate() {
+            let mut live = out;
+            live.extend(
+                block.terminator.clone().uses_mut().into_iter().filter_map(|operand| operand.variable()),
+            );
+            let mut phis = Vec::new();
+            for statement in block.statements.iter().rev() {
+                if let Statement::Phi { dest, .. } = statement {
+                    phis.push(*dest);
+                    continue;
+                }
+                for variable in statement.definitions() {
+                    interfere(variable, &live);
+                    live.remove(&variable);
+                }
+                live.extend(
+                    statement.clone().uses_mut().into_iter().filter_map(|operand| operand.variable()),
+                );
+            }
+            // Phis all happen at once at the start of the block, and the entry values all exist from the start
+            live.extend(phis.iter().copied());
+            if id == 0 {
+                live.extend(self.parameters.iter().filter_map(|(register, ..)| *register).map(Variable::new));
+            }
+            let starting: Vec<Variable> = match id {
+                0 => live.iter().copied().collect(),
+                _ => phis,
+            };
+            for variable in starting {
+                interfere(variable, &live);
+            }
+        }
+        pairs
+    }
+
+    /// Merges each phi with its sources where their lifetimes don't overlap, so they can share a variable and
+    /// the copies between them disappear.
+    fn merge_webs(&mut self) {
+        let interference = self.interference();
+        let phis: Vec<(Variable, Vec<Variable>)> = self
+            .function
+            .blocks
+            .iter()
+            .flat_map(|block| &block.statements)
+            .filter_map(|statement| match statement {
+                Statement::Phi { dest, sources } => Some((
+                    *dest,
+                    sources.iter().filter_map(|(_, source)| source.variable()).collect(),
+                )),
+                _ => None,
+            })
+            .collect();
+        for (dest, sources) in phis {
+            for source in sources {
+                let (root, other) = (self.web(dest), self.web(source));
+                if root == other {
+                    continue;
+                }
+                let (ours, theirs) = (self.web_members(root), self.web_members(other));
+                let overlaps = ours
+                    .iter()
+                    .any(|&a| theirs.iter().any(|&b| interference.contains(&(a.min(b), a.max(b)))));
+                if !overlaps {
+                    self.webs.insert(other, root);
+                    self.members.remove(&other);
+                    self.members.insert(root, ours.into_iter().chain(theirs).collect());
+                }
+            }
+        }
+    }
+
+    fn add_type(&mut self, variable: Variable, evidence: Evidence, type_info: TypeInfo) {
+        let root = self.web(variable);
+        match self.types.get(&root) {
+            Some((existing, _)) if *existing >= evidence => (),
+            _ => {
+                self.types.insert(root, (evidence, type_info));
+            }
+        }
+    }
+
+    /// Marks whatever an address is based on as a pointer.
+    fn add_address(&mut self, address: Operand) {
+        let Some(variable) = address.variable() else {
+            return;
+        };
+        if self.stack_offset(address).is_some() {
+            return;
+        }
+        let base = match self.definition(variable) {
+            Some(Statement::Assign { op: Op::Add, args, .. }) => match args[..] {
+                [Operand::Variable(base), Operand::Constant(_)] => base,
+                _ => variable,
+            },
+            _ => variable,
+        };
+        self.add_type(base, Evidence::Address, TypeInfo::pointer());
+    }
+
+    /// Types every web going by how it's used, preferring what prototypes say.
+    fn find_types(&mut self) {
+        let program = self.program;
+        let registry = &program.types;
+        let mut evidence = Vec::new();
+        let mut addresses = Vec::new();
+        for block in &self.function.blocks {
+            for statement in &block.statements {
+                match statement {
+                    Statement::Load { dest, access, address } => {
+                        evidence.push((*dest, Evidence::Access, access_type(*access)));
+                        addresses.push(*address);
+                    }
+                    Statement::Store { address, .. } => addresses.push(*address),
+                    Statement::Assign { dest, op: Op::FloatRound, .. } => {
+                        evidence.push((*dest, Evidence::Access, TypeInfo::Float { bits: 32 }));
+                    }
+                    Statement::Call { callee: Callee::Direct(target), arguments, results, .. } => {
+                        let Some(prototype) = registry.prototype(*target) else {
+                            continue;
+                        };
+                        if let (Some(result), Some(type_info)) = (results.first(), &prototype.return_type) {
+                            evidence.push((*result, Evidence::Declared, type_info.clone()));
+                        }
+                        let parameters = prototype
+                            .parameters
+                            .iter()
+                            .zip(parameter_registers(registry, &prototype.parameters));
+                        let passed = parameters
+                            .filter(|(_, register)| register.is_some())
+                            .map(|(parameter, _)| parameter);
+                        for (parameter, argument) in passed.zip(arguments) {
+                            if let Some(variable) = argument.variable() {
+                                evidence.push((variable, Evidence::Declared, parameter.type_info.clone()));
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        if self.prototype.is_some() {
+            for (register, _, type_info) in &self.parameters {
+                if let Some(register) = register {
+                    evidence.push((Variable::new(*register), Evidence::Declared, type_info.clone()));
+                }
+            }
+        }
+        for (variable, strength, type_info) in evidence {
+            self.add_type(variable, strength, type_info);
+        }
+        for address in addresses {
+            self.add_address(address);
+        }
+    }
+
+    fn type_of_web(&self, root: Variable) -> TypeInfo {
+        match self.types.get(&root) {
+            Some((_, type_info)) => type_info.clone(),
+            None => match root.register {
+                Register::Fpr(_) | Register::Ps1(_) => TypeInfo::Float { bits: 64 },
+                _ => int(),
+            },
+        }
+    }
+
+    fn add_variable(
+        &mut self, name: String, key: String, type_info: TypeInfo, kind: VariableKind,
+    ) -> VariableId {
+        let name =
+            self.program.variable_names.get(&(self.function.address, key.clone())).cloned().unwrap_or(name);
+        let mut unique = name.clone();
+        let mut n = 2;
+        while self.variables.iter().any(|variable| variable.name == unique) {
+            unique = format!("{name}_{n}");
+            n += 1;
+        }
+        self.variables.push(CVariable { name: unique, key, type_info, kind });
+        self.variables.len() - 1
+    }
+
+    /// The C variable for an SSA variable's web, making one up the first time it's needed.
+    fn variable(&mut self, variable: Variable) -> VariableId {
+        let root = self.web(variable);
+        if let Some(&id) = self.ids.get(&root) {
+            return id;
+        }
+        let key = root.register.to_string();
+        let name = match root.register {
+            Register::Temp(n) => format!("temp_{n}"),
+            _ => format!("var_{}", key.replace('.', "_")),
+        };
+        let id = self.add_variable(name, key, self.type_of_web(root), VariableKind::Register);
+        self.ids.insert(root, id);
+        id
+    }
+
+    fn slot(&mut self, offset: i32, type_info: TypeInfo) -> VariableId {
+        if let Some(&id) = self.slots.get(&offset) {
+            return id;
+        }
+        let key = frame::slot_name(offset);
+        let id = self.add_variable(key.clone(), key, type_info, VariableKind::Stack(offset));
+        self.slots.insert(offset, id);
+        id
+    }
+
+    /// Type of an expression, as far as it can be told.
+    fn expr_type(&self, expr: &Expr) -> Option<TypeInfo> {
+        let registry = &self.program.types;
+        match expr {
+            Expr::Variable(id) => Some(self.variables[*id].type_info.clone()),
+            Expr::Float(_) => Some(TypeInfo::Float { bits: 64 }),
+            Expr::String(_) => Some(TypeInfo::pointer_to(TypeInfo::Integer { bits: 8, signed: true })),
+            Expr::Cast(type_info, _) | Expr::Deref(Some(type_info), _) => Some(type_info.clone()),
+            Expr::Deref(None, address) | Expr::Index(address, _) => match self.expr_type(address)? {
+                TypeInfo::Pointer { target } => target.map(|target| *target),
+                _ => None,
+            },
+            Expr::Call(target, _) => registry.prototype(*target)?.return_type.clone(),
+            Expr::Unary(UnaryOp::Not, _) => Some(int()),
+            Expr::Unary(_, inner) => self.expr_type(inner),
+            Expr::Binary(op, left, _) => match op {
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => self.expr_type(left),
+                BinaryOp::And | BinaryOp::Or | BinaryOp::Xor | BinaryOp::Shl | BinaryOp::Shr => {
+                    self.expr_type(left).filter(|type_info| matches!(type_info, TypeInfo::Integer { .. }))
+                }
+                _ => Some(int()),
+            },
+            Expr::Ternary(_, then, _) => self.expr_type(then),
+            Expr::AddressOf(inner) => self.expr_type(inner).map(TypeInfo::pointer_to),
+            _ => None,
+        }
+    }
+
+    fn is_unsigned(&self, expr: &Expr) -> bool {
+        match self.expr_type(expr) {
+            Some(TypeInfo::Integer { signed, .. }) => !signed,
+            Some(TypeInfo::Pointer { .. }) => true,
+            _ => matches!(expr, Expr::Constant(_)),
+        }
+    }
+
+    /// Reinterprets an integer as unsigned (or signed), unless it already is.
+    fn signedness(&self, expr: Expr, signed: bool) -> Expr {
+        let wide_unsigned = matches!(
+            self.expr_type(&expr),
+            Some(TypeInfo::Integer { bits: 32, signed: false })
+        );
+        match (signed, &expr) {
+            (_, Expr::Constant(_)) => expr,
+            (true, _) if wide_unsigned || matches!(self.expr_type(&expr), Some(TypeInfo::Pointer { .. })) => {
+                Expr::Cast(int(), Box::new(expr))
+            }
+            (false, _) if !self.is_unsigned(&expr) => Expr::Cast(uint(), Box::new(expr)),
+            _ => expr,
+        }
+    }
+
+    /// A constant, which might be the address of something the program knows about.
+    fn constant(&self, value: u32) -> Expr {
+        let program = self.program;
+        if program.segment_at(value).is_none() || value < 0x1000 {
+            return Expr::Constant(value);
+        }
+        if program.functions.contains_key(&value) {
+            return Expr::Global(value);
+        }
+        if let Some((end, TypeInfo::String { encoding, .. })) = program.types.item_at(value.into()) {
+            let text = program.bytes(value, (end - u64::from(value)) as u32).and_then(|bytes| {
+                let length = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+                encoding.decode(&bytes[..length])
+            });
+            if let Some(text) = text {
+                return Expr::String(text);
+            }
+        }
+        Expr::AddressOf(Box::new(Expr::Global(value)))
+    }
+
+    fn operand(&mut self, operand: Operand, pending: &mut HashMap<Variable, Expr>) -> Expr {
+        if let Some(offset) = self.stack_offset(operand) {
+            let id = self.slot(offset, int());
+            return Expr::AddressOf(Box::new(Expr::Variable(id)));
+        }
+        match operand {
+            Operand::Constant(value) => self.constant(value),
+            Operand::Variable(variable) => match pending.remove(&variable) {
+                Some(expr) => expr,
+                None => Expr::Variable(self.variable(variable)),
+            },
+        }
+    }
+
+    /// Follows a member path through a type, e.g. `.pos.x` or `[3]`, as long as it ends at something the
+    /// same size as the access.
+    fn member_path(&self, type_info: &TypeInfo, offset: u64, size: u64) -> Option<String> {
+        let registry = &self.program.types;
+        let type_info = registry.resolve(type_info)?;
+        if offset == 0 && registry.size_of(type_info) == size && !matches!(type_info, TypeInfo::Struct { .. })
+        {
+            return Some(String::new());
+        }
+        match type_info {
+            TypeInfo::Struct { members, .. } | TypeInfo::Union { members, .. } => {
+                members.iter().find_map(|member| {
+                    let length = registry.size_of(&member.type_info);
+                    if offset < member.offset || offset >= member.offset + length.max(1) {
+                        return None;
+                    }
+                    let path = self.member_path(&member.type_info, offset - member.offset, size)?;
+                    Some(format!(".{}{path}", member.name))
+                })
+            }
+            TypeInfo::Array { element_type, count } => {
+                let length = registry.size_of(element_type);
+                if length == 0 || offset / length >= *count {
+                    return None;
+                }
+                let path = self.member_path(element_type, offset % length, size)?;
+                Some(format!("[{}]{path}", offset / length))
+            }
+            _ => None,
+        }
+    }
+
+    /// Something in memory accessed at `address`, as an lvalue.
+    fn memory(&mut self, address: Operand, access: Access, pending: &mut HashMap<Variable, Expr>) -> Expr {
+        let type_info = access_type(access);
+        let size = u64::from(access.size);
+        if let Some(offset) = self.stack_offset(address) {
+            return Expr::Variable(self.slot(offset, type_info));
+        }
+        if let Operand::Constant(value) = address {
+            return self.global(value, type_info, size);
+        }
+
+        let address = self.operand(address, pending);
+        if let Some(element) = self.element(&address, size) {
+            return element;
+        }
+        let (base, offset) = match &address {
+            Expr::Binary(BinaryOp::Add, base, offset) => match **offset {
+                Expr::Constant(offset) => ((**base).clone(), offset as i32),
+                _ => (address.clone(), 0),
+            },
+            Expr::Binary(BinaryOp::Sub, base, offset) => match **offset {
+                Expr::Constant(offset) => ((**base).clone(), (offset as i32).wrapping_neg()),
+                _ => (address.clone(), 0),
+            },
+            _ => (address.clone(), 0),
+        };
+        let target = match self.expr_type(&base) {
+            Some(TypeInfo::Pointer { target: Some(target) }) => Some(*target),
+            _ => None,
+        };
+        if let (Some(target), Ok(offset)) = (&target, u64::try_from(offset)) {
+            if let Some(path) = self.member_path(target, offset, size) {
+                return match path.strip_prefix('.') {
+                    Some(member) => Expr::Member(Box::new(base), format!("->{member}")),
+                    None if path.is_empty() => Expr::Deref(None, Box::new(base)),
+                    None => Expr::Member(Box::new(base), path),
+                };
+            }
+        }
+        // Pointer arithmetic would be scaled by whatever the base points to, so go through bytes
+        let scaled = target.is_some_and(|target| self.program.types.size_of(&target) != 1);
+        let address = match (scaled, offset) {
+            (true, 0) => base,
+            (true, offset) => {
+                let bytes = Expr::Cast(
+                    TypeInfo::pointer_to(TypeInfo::Integer { bits: 8, signed: false }),
+                    Box::new(base),
+                );
+                Expr::binary(BinaryOp::Add, bytes, Expr::Constant(offset as u32))
+            }
+            (false, _) => address,
+        };
+        Expr::Deref(Some(type_info), Box::new(address))
+    }
+
+    /// `base[index]` for an address that adds a scaled index to a pointer, when the access fits the element.
+    fn element(&self, address: &Expr, size: u64) -> Option<Expr> {
+        let Expr::Binary(BinaryOp::Add, base, index) = address else {
+            return None;
+        };
+        let Some(TypeInfo::Pointer { target: Some(target) }) = self.expr_type(base) else {
+            return None;
+        };
+        let stride = self.program.types.size_of(&target);
+        let index = match &**index {
+            _ if stride == 1 => (**index).clone(),
+            Expr::Binary(BinaryOp::Shl, index, shift)
+                if **shift == Expr::Constant(stride.trailing_zeros()) && stride.is_power_of_two() =>
+            {
+                (**index).clone()
+            }
+            Expr::Binary(BinaryOp::Mul, index, scale) if **scale == Expr::Constant(stride as u32) => {
+                (**index).clone()
+            }
+            _ => return None,
+        };
+        let path = self.member_path(&target, 0, size)?;
+        let element = Expr::Index(base.clone(), Box::new(index));
+        Some(match path.is_empty() {
+            true => element,
+            false => Expr::Member(Box::new(element), path),
+        })
+    }
+
+    fn global(&self, address: u32, type_info: TypeInfo, size: u64) -> Expr {
+        let registry = &self.program.types;
+        match registry.item_containing(address.into()) {
+            None => Expr::Global(address),
+            Some((start, _, _)) if start == u64::from(address) => Expr::Global(address),
+            Some((start, _, item)) => match self.member_path(item, u64::from(address) - start, size) {
+                Some(path) => Expr::Member(Box::new(Expr::Global(start as u32)), path),
+                None => {
+                    let bytes = TypeInfo::pointer_to(TypeInfo::Integer { bits: 8, signed: false });
+                    let base = Expr::Cast(
+                        bytes,
+                        Box::new(Expr::AddressOf(Box::new(Expr::Global(start as u32)))),
+                    );
+                    let address = Expr::binary(BinaryOp::Add, base, Expr::Constant(address - start as u32));
+                    Expr::Deref(Some(type_info), Box::new(address))
+                }
+            },
+        }
+    }
+
+    /// A float constant CodeWarrior put in `.sdata2`, which is read-only.
+    fn float_constant(&self, address: u32, access: Access) -> Option<Expr> {
+        let base = self.sda2?;
+        if access.kind != AccessKind::Float || address.wrapping_sub(base).wrapping_add(0x8000) >= 0x10000 {
+            return None;
+        }
+        match access.size {
+            4 => Some(Expr::Float(
+                f32::from_bits(self.program.read_u32(address)?).into(),
+            )),
+            _ => {
+                let high = u64::from(self.program.read_u32(address)?);
+                let low = u64::from(self.program.read_u32(address + 4)?);
+                Some(Expr::Float(f64::from_bits(high << 32 | low)))
+            }
+        }
+    }
+
+    fn value(&mut self, statement: &Statement, pending: &mut HashMap<Variable, Expr>) -> Expr {
+        match statement {
+            Statement::Assign { op, args, .. } => {
+                let args: Vec<Expr> = args.iter().map(|&arg| self.operand(arg, pending)).collect();
+                self.operation(*op, args)
+            }
+            Statement::Load { access, address, .. } => match address {
+                Operand::Constant(constant) => match self.float_constant(*constant, *access) {
+                    Some(value) => value,
+                    None => self.memory(*address, *access, pending),
+                },
+                _ => self.memory(*address, *access, pending),
+            },
+            Statement::Call { callee, arguments, .. } => {
+                let arguments = arguments.iter().map(|&argument| self.operand(argument, pending)).collect();
+                match callee {
+                    Callee::Direct(target) => Expr::Call(*target, arguments),
+                    Callee::Indirect(target) => {
+                        let target = self.operand(*target, pending);
+                        Expr::IndirectCall(Box::new(target), arguments)
+                    }
+                }
+            }
+            Statement::Intrinsic { name, arguments, .. } => {
+                let arguments = arguments.iter().map(|&argument| self.operand(argument, pending)).collect();
+                Expr::Intrinsic(format!("__{name}"), arguments)
+            }
+            Statement::Store { .. } | Statement::Phi { .. } => unreachable!(),
+        }
+    }
+
+    fn operation(&self, op: Op, args: Vec<Expr>) -> Expr {
+        let is_condition = |expr: &Expr| match expr {
+            Expr::Binary(op, ..) => {
+                op.inverse().is_some() || matches!(op, BinaryOp::LogicalAnd | BinaryOp::LogicalOr)
+            }
+            Expr::Unary(UnaryOp::Not, _) => true,
+            _ => false,
+        };
+        let mut args = args.into_iter();
+        let mut next = || args.next().unwrap_or(Expr::Constant(0));
+        let binary = |op: BinaryOp, left: Expr, right: Expr| Expr::binary(op, left, right);
+        let intrinsic = |name: &str, args: Vec<Expr>| Expr::Intrinsic(name.to_owned(), args);
+        let cast = |type_info: TypeInfo, expr: Expr| Expr::Cast(type_info, Box::new(expr));
+        match op {
+            Op::Copy => next(),
+            Op::Add => match (next(), next()) {
+                (left, Expr::Constant(right)) if (right as i32) < 0 && (right as i32) > -0x10000 => {
+                    binary(BinaryOp::Sub, left, Expr::Constant(right.wrapping_neg()))
+                }
+                (left, right) => binary(BinaryOp::Add, left, right),
+            },
+            Op::Sub => binary(BinaryOp::Sub, next(), next()),
+            Op::Mul => binary(BinaryOp::Mul, next(), next()),
+            Op::MulHighSigned => intrinsic("__mulhw", vec![next(), next()]),
+            Op::MulHighUnsigned => intrinsic("__mulhwu", vec![next(), next()]),
+            Op::DivSigned => binary(BinaryOp::Div, next(), next()),
+            Op::DivUnsigned => {
+                let (left, right) = (next(), next());
+                binary(
+                    BinaryOp::Div,
+                    self.signedness(left, false),
+                    self.signedness(right, false),
+                )
+            }
+            Op::And => match (next(), next()) {
+                (left, right) if is_condition(&left) && is_condition(&right) => {
+                    binary(BinaryOp::LogicalAnd, left, right)
+                }
+                (left, right) => binary(BinaryOp::And, left, right),
+            },
+            Op::Or => match (next(), next()) {
+                (left, right) if is_condition(&left) && is_condition(&right) => {
+                    binary(BinaryOp::LogicalOr, left, right)
+                }
+                (left, right) => binary(BinaryOp::Or, left, right),
+            },
+            Op::Xor => match (next(), next()) {
+                (left, Expr::Constant(1)) if is_condition(&left) => left.not(),
+                (left, right) => binary(BinaryOp::Xor, left, right),
+            },
+            Op::Not => Expr::Unary(UnaryOp::BitNot, Box::new(next())),
+            Op::Neg => Expr::Unary(UnaryOp::Neg, Box::new(next())),
+            Op::ShiftLeft => binary(BinaryOp::Shl, next(), next()),
+            Op::ShiftRightUnsigned => {
+                let left = next();
+                binary(BinaryOp::Shr, self.signedness(left, false), next())
+            }
+            Op::ShiftRightSigned => {
+                let left = next();
+                binary(BinaryOp::Shr, self.signedness(left, true), next())
+            }
+            Op::RotateLeft => intrinsic("__rotl", vec![next(), next()]),
+            Op::CountLeadingZeros => intrinsic("__cntlzw", vec![next()]),
+            Op::SignExtend8 => cast(TypeInfo::Integer { bits: 8, signed: true }, next()),
+            Op::SignExtend16 => cast(TypeInfo::Integer { bits: 16, signed: true }, next()),
+            Op::ByteSwap16 => intrinsic("__bswap16", vec![next()]),
+            Op::ByteSwap32 => intrinsic("__bswap32", vec![next()]),
+            Op::Equal | Op::FloatEqual => binary(BinaryOp::Eq, next(), next()),
+            Op::NotEqual => binary(BinaryOp::Ne, next(), next()),
+            Op::LessSigned | Op::GreaterSigned | Op::LessUnsigned | Op::GreaterUnsigned => {
+                let signed = matches!(op, Op::LessSigned | Op::GreaterSigned);
+                let (left, right) = (next(), next());
+                let (left, right) = match (&left, &right) {
+                    // Only one side needs a cast to make the comparison the right kind
+                    (_, Expr::Constant(_)) => (self.signedness(left, signed), right),
+                    _ => (self.signedness(left, signed), self.signedness(right, signed)),
+                };
+                let less = matches!(op, Op::LessSigned | Op::LessUnsigned);
+                binary(if less { BinaryOp::Lt } else { BinaryOp::Gt }, left, right)
+            }
+            Op::FloatLess => binary(BinaryOp::Lt, next(), next()),
+            Op::FloatGreater => binary(BinaryOp::Gt, next(), next()),
+            Op::Carry
+            | Op::AddOverflow
+            | Op::MulOverflow
+            | Op::DivOverflow
+            | Op::ShiftCarry
+            | Op::FloatUnordered => {
+                let args: Vec<Expr> = std::iter::from_fn(|| args.next()).collect();
+                Expr::Intrinsic(format!("__{}", op.name()), args)
+            }
+            Op::FloatAdd => binary(BinaryOp::Add, next(), next()),
+            Op::FloatSub => binary(BinaryOp::Sub, next(), next()),
+            Op::FloatMul => binary(BinaryOp::Mul, next(), next()),
+            Op::FloatDiv => binary(BinaryOp::Div, next(), next()),
+            Op::FloatMulAdd => {
+                let product = binary(BinaryOp::Mul, next(), next());
+                binary(BinaryOp::Add, product, next())
+            }
+            Op::FloatNeg => Expr::Unary(UnaryOp::Neg, Box::new(next())),
+            Op::FloatAbs => intrinsic("__fabs", vec![next()]),
+            Op::FloatRound => cast(TypeInfo::Float { bits: 32 }, next()),
+            Op::FloatToInt => intrinsic("__fctiw", vec![next()]),
+            Op::FloatToIntTruncate => cast(int(), next()),
+            Op::FloatReciprocal => intrinsic("__fres", vec![next()]),
+            Op::FloatReciprocalSqrt => intrinsic("__frsqrte", vec![next()]),
+            Op::FloatSelect => {
+                let condition = binary(BinaryOp::Ge, next(), Expr::Float(0.0));
+                Expr::Ternary(Box::new(condition), Box::new(next()), Box::new(next()))
+            }
+        }
+    }
+
+    /// Which definitions can be folded into the single place they're used, because they're in the same block
+    /// and moving them there can't change what they evaluate to.
+    fn inlined(&self) -> HashSet<Variable> {
+        // Where each variable is used, as (block, position). Copies for phis come after the statements, and
+        // the terminator after that
+        let mut uses: HashMap<Variable, Vec<(BlockId, usize)>> = HashMap::new();
+        for (id, block) in self.function.blocks.iter().enumerate() {
+            let count = block.statements.len();
+            for (index, statement) in block.statements.iter().enumerate() {
+                if !matches!(statement, Statement::Phi { .. }) {
+                    for variable in
+                        statement.clone().uses_mut().into_iter().filter_map(|operand| operand.variable())
+                    {
+                        uses.entry(variable).or_default().push((id, index));
+                    }
+                }
+            }
+            for (n, (_, source)) in self.copies(id).into_iter().enumerate() {
+                if let Some(variable) = source.variable() {
+                    uses.entry(variable).or_default().push((id, count + n));
+                }
+            }
+            for variable in
+                block.terminator.clone().uses_mut().into_iter().filter_map(|operand| operand.variable())
+            {
+                uses.entry(variable).or_default().push((id, usize::MAX));
+            }
+        }
+
+        let mut inlined = HashSet::new();
+        // Webs each inlined expression reads, and how freely it can move
+        let mut reads: HashMap<Variable, (HashSet<Variable>, Motion)> = HashMap::new();
+        for (id, block) in self.function.blocks.iter().enumerate() {
+            let copies = self.copies(id);
+            for (index, statement) in block.statements.iter().enumerate() {
+                let (dest, motion) = match statement {
+                    Statement::Assign { dest, .. } => (*dest, Motion::Pure),
+                    Statement::Load { dest, .. } => (*dest, Motion::Load),
+                    Statement::Call { condition: None, results, .. }
+                    | Statement::Intrinsic { results, .. }
+                        if results.len() == 1 =>
+                    {
+                        (results[0], Motion::Call)
+                    }
+                    _ => continue,
+                };
+                if self.stack_offset(Operand::Variable(dest)).is_some()
+                    || self.web_members(self.web(dest)).len() > 1
+                {
+                    continue;
+                }
+                let Some(&[(block_id, position)]) = uses.get(&dest).map(Vec::as_slice) else {
+                    continue;
+                };
+                if block_id != id || position <= index {
+                    continue;
+                }
+
+                let mut read = HashSet::new();
+                let mut motion = motion;
+                for variable in
+                    statement.clone().uses_mut().into_iter().filter_map(|operand| operand.variable())
+                {
+                    match reads.get(&variable) {
+                        Some((inner, inner_motion)) => {
+                            read.extend(inner.iter().copied());
+                            motion = motion.max(*inner_motion);
+                        }
+                        None => {
+                            read.insert(self.web(variable));
+                        }
+                    }
+                }
+
+                let end = position.min(block.statements.len());
+                let statements_between = block.statements[index + 1..end].iter();
+                let blocked = statements_between.into_iter().any(|other| {
+                    let writes =
+                        other.definitions().iter().any(|variable| read.contains(&self.web(*variable)));
+                    writes
+                        || match (motion, other) {
+                            (Motion::Pure, _) => false,
+                            (
+                                Motion::Load,
+                                Statement::Store { .. }
+                                | Statement::Call { .. }
+                                | Statement::Intrinsic { .. },
+                            ) => true,
+                            (Motion::Load, _) => false,
+                            (Motion::Call, other) => {
+                                !matches!(other, Statement::Assign { .. } | Statement::Phi { .. })
+                            }
+                        }
+                });
+                let copies_between = position.saturating_sub(block.statements.len()).min(copies.len());
+                let overwritten =
+                    copies[..copies_between].iter().any(|(dest, _)| read.contains(&self.web(*dest)));
+                if !blocked && !overwritten {
+                    inlined.insert(dest);
+                    reads.insert(dest, (read, motion));
+                }
+            }
+        }
+        inlined
+    }
+
+    /// Copies the phis in the block's successor turn into at the end of it, as (destination, source). Critical
+    /// edges have been split, so only blocks with a single successor have any.
+    fn copies(&self, block: BlockId) -> Vec<(Variable, Operand)> {
+        match self.function.blocks[block].terminator {
+            Terminator::Jump(successor) => self
+                .phi_sources(block, successor)
+                .into_iter()
+                .filter(|(dest, source)| {
+                    source.variable().is_none_or(|source| self.web(source) != self.web(*dest))
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn emit(mut self) -> Body {
+        for (index, (register, name, type_info)) in self.parameters.clone().into_iter().enumerate() {
+            let key = format!("arg{index}");
+            let name = match name.is_empty() {
+                true => key.clone(),
+                false => name,
+            };
+            let type_info = match (&self.prototype, register) {
+                (None, Some(register)) => self.type_of_web(self.web(Variable::new(register))),
+                _ => type_info,
+            };
+            let id = self.add_variable(name, key, type_info, VariableKind::Parameter(index));
+            if let Some(register) = register {
+                self.ids.insert(self.web(Variable::new(register)), id);
+            }
+        }
+        let parameters = (0..self.variables.len()).collect();
+
+        let inlined = self.inlined();
+        let mut used = HashSet::new();
+        for block in &self.function.blocks {
+            let mut block = block.clone();
+            let operands =
+                block.statements.iter_mut().flat_map(Statement::uses_mut).chain(block.terminator.uses_mut());
+            used.extend(operands.filter_map(|operand| operand.variable()));
+        }
+        let mut blocks = Vec::new();
+        for id in 0..self.function.blocks.len() {
+            let block = self.function.blocks[id].clone();
+            let mut pending = HashMap::new();
+            let mut statements = Vec::new();
+            for statement in &block.statements {
+                let address = block.address;
+                match statement {
+                    Statement::Phi { .. } => (),
+                    Statement::Store { access, address: target, value } => {
+                        let value = self.operand(*value, &mut pending);
+                        let target = self.memory(*target, *access, &mut pending);
+                        statements.push(Stmt::new(address, StmtKind::Assign(target, value)));
+                    }
+                    Statement::Assign { dest, op: Op::Copy, args }
+                        if args[0].variable().is_some_and(|source| self.web(source) == self.web(*dest)) => {}
+                    Statement::Call { condition: Some(condition), results, .. } => {
+                        let condition = self.operand(*condition, &mut pending);
+                        let call = self.value(statement, &mut pending);
+                        let kind = match results.first() {
+                            Some(&result) => StmtKind::Assign(Expr::Variable(self.variable(result)), call),
+                            None => StmtKind::Expr(call),
+                        };
+                        let then = vec![Stmt::new(address, kind)];
+                        statements.push(Stmt::new(address, StmtKind::If(condition, then, Vec::new())));
+                    }
+                    Statement::Intrinsic { results, .. } if results.len() > 1 => {
+                        let Expr::Intrinsic(name, mut arguments) = self.value(statement, &mut pending) else {
+                            unreachable!();
+                        };
+                        for &result in results {
+                            arguments.push(Expr::AddressOf(Box::new(Expr::Variable(self.variable(result)))));
+                        }
+                        statements.push(Stmt::new(
+                            address,
+                            StmtKind::Expr(Expr::Intrinsic(name, arguments)),
+                        ));
+                    }
+                    statement => {
+                        let dest = statement.definitions().first().copied();
+                        if dest.is_some_and(|dest| self.stack_offset(Operand::Variable(dest)).is_some()) {
+                            continue;
+                        }
+                        let value = self.value(statement, &mut pending);
+                        match dest {
+                            Some(dest) if inlined.contains(&dest) => {
+                                pending.insert(dest, value);
+                            }
+                            Some(dest) if used.contains(&dest) => {
+                                let variable = Expr::Variable(self.variable(dest));
+                                statements.push(Stmt::new(address, StmtKind::Assign(variable, value)));
+                            }
+                            // Nothing reads it, and reading memory is only worth showing if it changes something
+                            _ if matches!(statement, Statement::Load { .. }) => (),
+                            _ => statements.push(Stmt::new(address, StmtKind::Expr(value))),
+                        }
+                    }
+                }
+            }
+            self.emit_copies(id, block.address, &mut pending, &mut statements);
+            let exit = match &block.terminator {
+                Terminator::Jump(target) => Exit::Jump(*target),
+                Terminator::Branch { condition, taken, not_taken } => {
+                    Exit::Branch(self.operand(*condition, &mut pending), *taken, *not_taken)
+                }
+                Terminator::Switch { value, targets } => {
+                    Exit::Switch(self.operand(*value, &mut pending), targets.clone())
+                }
+                Terminator::Return { values } => {
+                    Exit::Return(values.first().map(|&(_, value)| self.operand(value, &mut pending)))
+                }
+                Terminator::IndirectJump { target } => {
+                    Exit::Unknown(Some(self.operand(*target, &mut pending)))
+                }
+                Terminator::TailCall { .. } | Terminator::Unknown => Exit::Unknown(None),
+            };
+            blocks.push(CBlock { address: block.address, statements, exit });
+        }
+        Body {
+            blocks,
+            variables: self.variables,
+            parameters,
+            return_type: self.return_type,
+        }
+    }
+
+    /// Turns the block's phi copies into assignments. They all happen at once, so any copy that would
+    /// overwrite something another one still needs waits for it, and cycles go through a temporary.
+    fn emit_copies(
+        &mut self, block: BlockId, address: u32, pending: &mut HashMap<Variable, Expr>,
+        statements: &mut Vec<Stmt>,
+    ) {
+        let mut copies: Vec<(VariableId, Expr)> = Vec::new();
+        for (dest, source) in self.copies(block) {
+            let source = self.operand(source, pending);
+            copies.push((self.variable(dest), source));
+        }
+        while !copies.is_empty() {
+            let ready = copies.iter().position(|(dest, _)| {
+                !copies.iter().any(|(other, source)| other != dest && reads(source, *dest))
+            });
+            let index = match ready {
+                Some(index) => index,
+                None => {
+                    // Every remaining copy overwrites something another needs, so save one of them first
+                    let dest = copies[0].0;
+                    let variable = &self.variables[dest];
+                    let (key, type_info) = (format!("{}_old", variable.key), variable.type_info.clone());
+                    let temp = self.add_variable(
+                        format!("temp_{}", variable.name),
+                        key,
+                        type_info,
+                        VariableKind::Register,
+                    );
+                    statements.push(Stmt::new(
+                        address,
+                        StmtKind::Assign(Expr::Variable(temp), Expr::Variable(dest)),
+                    ));
+                    for (_, source) in &mut copies {
+                        replace(source, dest, temp);
+                    }
+                    0
+                }
+            };
+            let (dest, source) = copies.remove(index);
+            statements.push(Stmt::new(address, StmtKind::Assign(Expr::Variable(dest), source)));
+        }
+    }
+}
+
+/// Whether an expression reads a variable.
+fn reads(expr: &Expr, variable: VariableId) -> bool {
+    let mut found = false;
+    visit(expr, &mut |expr| found |= *expr == Expr::Variable(variable));
+    found
+}
+
+fn replace(expr: &mut Expr, from: VariableId, to: VariableId) {
+    visit_mut(expr, &mut |expr| {
+        if *expr == Expr::Variable(from) {
+            *expr = Expr::Variable(to);
+        }
+    });
+}
+
+fn children(expr: &Expr) -> Vec<&Expr> {
+    match expr {
+        Expr::Unary(_, inner)
+        | Expr::Cast(_, inner)
+        | Expr::Deref(_, inner)
+        | Expr::Member(inner, _)
+        | Expr::AddressOf(inner) => vec![inner],
+        Expr::Binary(_, left, right) | Expr::Index(left, right) => vec![left, right],
+        Expr::Ternary(condition, then, otherwise) => vec![condition, then, otherwise],
+        Expr::Call(_, arguments) | Expr::Intrinsic(_, arguments) => arguments.iter().collect(),
+        Expr::IndirectCall(target, arguments) => std::iter::once(&**target).chain(arguments).collect(),
+        Expr::Variable(_) | Expr::Constant(_) | Expr::Float(_) | Expr::String(_) | Expr::Global(_) => {
+            Vec::new()
+        }
+    }
+}
+
+fn visit(expr: &Expr, f: &mut impl FnMut(&Expr)) {
+    f(expr);
+    for child in children(expr) {
+        visit(child, f);
+    }
+}
+
+fn visit_mut(expr: &mut Expr, f: &mut impl FnMut(&mut Expr)) {
+    f(expr);
+    let children: Vec<&mut Expr> = match expr {
+        Expr::Unary(_, inner)
+        | Expr::Cast(_, inner)
+        | Expr::Deref(_, inner)
+        | Expr::Member(inner, _)
+        | Expr::AddressOf(inner) => vec![inner],
+        Expr::Binary(_, left, right) | Expr::Index(left, right) => vec![left, right],
+        Expr::Ternary(condition, then, otherwise) => vec![condition, then, otherwise],
+        Expr::Call(_, arguments) | Expr::Intrinsic(_, arguments) => arguments.iter_mut().collect(),
+        Expr::IndirectCall(target, arguments) => std::iter::once(&mut **target).chain(arguments).collect(),
+        Expr::Variable(_) | Expr::Constant(_) | Expr::Float(_) | Expr::String(_) | Expr::Global(_) => {
+            Vec::new()
+        }
+    };
+    for child in children {
+        visit_mut(child, f);
+    }
+}
@@ -0,0 +1,296 @@
+//! C pseudocode for a function, built on the optimized SSA form from [`crate::ir`]. [`build`] gets the IR back
+//! out of SSA form into typed variables and expressions, [`structure`] recovers loops, conditionals and
+//! switches from the control flow graph, and [`print`] lays it all out as tokens the UI can colour and click.
+//!
+//! Everything is worked out fresh each time, using whatever names, prototypes and types the program has at
+//! the time, so edits show up the next time a function is decompiled.
+mod ast;
+mod build;
+mod print;
+mod structure;
+
+use core::fmt;
+
+use crate::program::Program;
+use crate::registry::{Prototype, TypeInfo};
+
+pub use build::{parameter_registers, return_register};
+
+/// What a variable stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableKind {
+    /// The function's parameter at this index
+    Parameter(usize),
+    /// Something kept in a register
+    Register,
+    /// A slot in the stack frame, at this offset from the stack pointer on entry
+    Stack(i32),
+}
+
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name: String,
+    /// Automatic name, which is what renames are stored under in [`Program::variable_names`]. For registers
+    /// this is the register itself (e.g. `r31`), so the assembly view can show the same name
+    pub key: String,
+    pub type_info: TypeInfo,
+    pub kind: VariableKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Plain,
+    Keyword,
+    Type,
+    Number,
+    String,
+    Comment,
+    Label,
+    /// Index into [`Pseudocode::variables`]
+    Variable(usize),
+    /// A function or global, by address
+    Address(u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub text: String,
+    pub kind: TokenKind,
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    /// Where the code this line came from starts, if it came from anywhere in particular
+    pub address: Option<u32>,
+    pub indent: usize,
+    pub tokens: Vec<Token>,
+}
+
+/// A decompiled function.
+#[derive(Debug, Clone)]
+pub struct Pseudocode {
+    pub function: u32,
+    pub lines: Vec<Line>,
+    pub variables: Vec<Variable>,
+    /// The signature it was decompiled with, either the one the program has or what was worked out from the code
+    pub prototype: Prototype,
+}
+
+impl fmt::Display for Pseudocode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            let text: String = line.tokens.iter().map(|token| token.text.as_str()).collect();
+            writeln!(f, "{}{text}", "    ".repeat(line.indent))?;
+        }
+        Ok(())
+    }
+}
+
+/// Decompiles the function starting at `address`, which analysis has to have found already.
+pub fn decompile(program: &Program, address: u32) -> Option<Pseudocode> {
+    let body = build::build(program, address, program.types.prototype(address).cloned())?;
+    let statements = structure::structure(&body);
+    Some(print::print(program, address, &body, &statements))
+}
+
+/// Works out a prototype for a function from its code alone, ignoring any it already has. Parameters are the
+/// argument registers it reads before writing, and it returns whatever it computes into r3 or f1. Calls go by
+/// their callees' prototypes, so this works best on callees first.
+pub fn infer_prototype(program: &Program, address: u32) -> Option<Prototype> {
+    build::build(program, address, None).map(|body| body.prototype())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{program, TEXT};
+
+    /// Decompiles the function at the start of `source`, without the indentation on blank lines.
+    fn decompiled(source: &str, data: &[u8]) -> String {
+        let pseudocode = decompile(&program(source, data), TEXT).unwrap().to_string();
+        pseudocode.lines().map(|line| format!("{}\n", line.trim_end())).collect()
+    }
+
+    #[test]
+    fn recovers_if_else() {
+        let source = "
+            cmpwi r3, 0
+            beq other
+            li r4, 1
+            b join
+        other:
+            li r4, 2
+        join:
+            stw r4, 0(r5)
+            blr
+        ";
+        assert_eq!(
+            decompiled(source, &[]),
+            "\
+void __start(s32 arg0, s32 arg1, void *arg2)
+{
+    s32 var_r4;
+
+    if (arg0 == 0) {
+        var_r4 = 2;
+    } else {
+        var_r4 = 1;
+    }
+    *(s32 *)arg2 = var_r4;
+}
+"
+        );
+    }
+
+    #[test]
+    fn recovers_while_loops() {
+        let source = "
+            b check
+        loop:
+            lwz r3, 0(r3)
+        check:
+            cmpwi r3, 0
+            bne loop
+            blr
+        ";
+        assert_eq!(
+            decompiled(source, &[]),
+            "\
+s32 __start(void *arg0)
+{
+    while (arg0 != 0) {
+        arg0 = *(s32 *)arg0;
+    }
+    return arg0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn recovers_do_while_loops() {
+        let source = "
+        loop:
+            stw r4, 0(r3)
+            addi r3, r3, 4
+            cmplw r3, r5
+            blt loop
+            blr
+        ";
+        assert_eq!(
+            decompiled(source, &[]),
+            "\
+s32 __start(void *arg0, s32 arg1, s32 arg2)
+{
+    do {
+        *(s32 *)arg0 = arg1;
+        arg0 += 4;
+    } while (arg0 < (u32)arg2);
+    return arg0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn recovers_break_and_continue() {
+        let source = "
+        loop:
+            lwz r6, 0(r3)
+            cmpwi r6, 0
+            beq done
+            addi r3, r3, 4
+            cmpwi r6, 1
+            beq loop
+            stw r6, 0(r4)
+            cmpwi r6, 2
+            bne loop
+        done:
+            blr
+        ";
+        assert_eq!(
+            decompiled(source, &[]),
+            "\
+s32 __start(void *arg0, void *arg1)
+{
+    s32 var_r6;
+
+    while (1) {
+        var_r6 = *(s32 *)arg0;
+        if (var_r6 != 0) {
+            arg0 += 4;
+            if (var_r6 == 1) {
+                continue;
+            }
+            *(s32 *)arg1 = var_r6;
+            if (var_r6 != 2) {
+                continue;
+            }
+        }
+        break;
+    }
+    return arg0;
+}
+"
+        );
+    }
+
+    #[test]
+    fn recovers_switches_from_jump_tables() {
+        let source = "
+            cmplwi r3, 3
+            bgt default
+            # The table is at the start of the data
+            lis r5, 0x8000
+            addi r5, r5, 0x4000
+            rlwinm r0, r3, 2, 0, 29
+            lwzx r0, r5, r0
+            mtctr r0
+            bctr
+        case0:
+            li r3, 10
+            b done
+        case1:
+            li r3, 11
+            b done
+        case2:
+            li r3, 12
+            b done
+        default:
+            li r3, 0
+        done:
+            blr
+        ";
+        // Case 3 shares its code with case 0
+        let mut table = Vec::new();
+        for case in [0x20, 0x28, 0x30, 0x20] {
+            table.extend_from_slice(&(TEXT + case).to_be_bytes());
+        }
+        assert_eq!(
+            decompiled(source, &table),
+            "\
+s32 __start(s32 arg0)
+{
+    s32 var_r3;
+
+    switch (arg0) {
+    case 0:
+    case 3:
+        var_r3 = 0xA;
+        break;
+    case 1:
+        var_r3 = 0xB;
+        break;
+    case 2:
+        var_r3 = 0xC;
+        break;
+    default:
+        var_r3 = 0;
+        break;
+    }
+    return var_r3;
+}
+"
+        );
+    }
+}
@@ -0,0 +1,439 @@
+//! Lays structured statements out as lines of tokens, so the UI can colour them and tell what was clicked.
+use super::ast::{BinaryOp, Expr, Stmt, StmtKind, UnaryOp};
+use super::build::Body;
+use super::{Line, Pseudocode, Token, TokenKind, VariableKind};
+use crate::ctype;
+use crate::processor::gekko::format_immediate;
+use crate::program::Program;
+use crate::registry::TypeInfo;
+use crate::text;
+
+/// Binds tighter than any binary operator, for unary operators and casts.
+const UNARY: u8 = 13;
+/// Binds tightest of all, for calls and member accesses.
+const POSTFIX: u8 = 14;
+
+struct Printer<'a> {
+    program: &'a Program,
+    body: &'a Body,
+    lines: Vec<Line>,
+    tokens: Vec<Token>,
+    indent: usize,
+    address: Option<u32>,
+}
+
+pub(super) fn print(program: &Program, function: u32, body: &Body, statements: &[Stmt]) -> Pseudocode {
+    let mut printer = Printer {
+        program,
+        body,
+        lines: Vec::new(),
+        tokens: Vec::new(),
+        indent: 0,
+        address: Some(function),
+    };
+
+    // Signature
+    printer.declaration(
+        body.return_type.as_ref(),
+        TokenKind::Address(function),
+        &program.short_name(function),
+    );
+    printer.plain("(");
+    if body.parameters.is_empty() {
+        printer.token("void", TokenKind::Type);
+    }
+    for (n, &parameter) in body.parameters.iter().enumerate() {
+        if n > 0 {
+            printer.plain(", ");
+        }
+        let variable = &body.variables[parameter];
+        printer.declaration(
+            Some(&variable.type_info),
+            TokenKind::Variable(parameter),
+            &variable.name,
+        );
+    }
+    printer.plain(")");
+    printer.newline();
+    printer.plain("{");
+    printer.newline();
+
+    // Locals, in the order they first show up
+    printer.indent = 1;
+    let mut declared = false;
+    for (id, variable) in body.variables.iter().enumerate() {
+        if matches!(variable.kind, VariableKind::Parameter(_)) {
+            continue;
+        }
+        printer.declaration(Some(&variable.type_info), TokenKind::Variable(id), &variable.name);
+        printer.plain(";");
+        if let VariableKind::Stack(offset) = variable.kind {
+            printer.token(
+                &format!(" // {}(r1)", format_immediate(offset.into())),
+                TokenKind::Comment,
+            );
+        }
+        printer.newline();
+        declared = true;
+    }
+    if declared {
+        printer.newline();
+    }
+
+    printer.statements(statements);
+    printer.indent = 0;
+    printer.address = None;
+    printer.plain("}");
+    printer.newline();
+    Pseudocode {
+        function,
+        lines: printer.lines,
+        variables: body.variables.clone(),
+        prototype: body.prototype(),
+    }
+}
+
+impl Printer<'_> {
+    fn token(&mut self, text: &str, kind: TokenKind) {
+        self.tokens.push(Token { text: text.to_owned(), kind });
+    }
+
+    fn plain(&mut self, text: &str) {
+        self.token(text, TokenKind::Plain);
+    }
+
+    fn keyword(&mut self, text: &str) {
+        self.token(text, TokenKind::Keyword);
+    }
+
+    fn newline(&mut self) {
+        let tokens = std::mem::take(&mut self.tokens);
+        self.lines.push(Line { address: self.address, indent: self.indent, tokens });
+    }
+
+    /// Declares a name with a type, splitting up the C declaration so the name gets its own token.
+    fn declaration(&mut self, type_info: Option<&TypeInfo>, kind: TokenKind, name: &str) {
+        let Some(type_info) = type_info else {
+            self.token("void", TokenKind::Type);
+            self.plain(" ");
+            self.token(name, kind);
+            return;
+        };
+        let declaration = ctype::declare(type_info, "\0");
+        let (before, after) = declaration.split_once('\0').unwrap_or((&declaration, ""));
+        let (base, pointers) = before.split_at(before.trim_end_matches(['*', '(', ' ']).len());
+        self.token(base, TokenKind::Type);
+        self.plain(pointers);
+        self.token(name, kind);
+        self.plain(after);
+    }
+
+    fn type_name(&mut self, type_info: &TypeInfo) {
+        self.token(&ctype::type_name(type_info), TokenKind::Type);
+    }
+
+    fn label(&self, block: usize) -> String {
+        let address = self.body.blocks[block].address;
+        let shared = self.body.blocks.iter().filter(|other| other.address == address).count() > 1;
+        match shared {
+            true => format!("loc_{address:08X}_{block}"),
+            false => format!("loc_{address:08X}"),
+        }
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn block(&mut self, statements: &[Stmt]) {
+        self.indent += 1;
+        self.statements(statements);
+        self.indent -= 1;
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        self.address = Some(statement.address);
+        match &statement.kind {
+            StmtKind::Expr(expr) => {
+                self.expr(expr, 0);
+                self.plain(";");
+            }
+            StmtKind::Assign(..) => {
+                self.simple(statement);
+                self.plain(";");
+            }
+            StmtKind::If(condition, then, otherwise) => {
+                self.keyword("if");
+                self.plain(" (");
+                self.expr(condition, 0);
+                self.plain(") {");
+                self.newline();
+                self.block(then);
+                let mut otherwise = otherwise;
+                // else-if chains stay flat instead of nesting deeper each time
+                while let [Stmt { kind: StmtKind::If(condition, then, next), address }] = &otherwise[..] {
+                    self.address = Some(*address);
+                    self.plain("} ");
+                    self.keyword("else if");
+                    self.plain(" (");
+                    self.expr(condition, 0);
+                    self.plain(") {");
+                    self.newline();
+                    self.block(then);
+                    otherwise = next;
+                }
+                if !otherwise.is_empty() {
+                    self.plain("} ");
+                    self.keyword("else");
+                    self.plain(" {");
+                    self.newline();
+                    self.block(otherwise);
+                }
+                self.plain("}");
+            }
+            StmtKind::While(condition, body) => {
+                self.keyword("while");
+                self.plain(" (");
+                self.expr(condition, 0);
+                self.plain(") {");
+                self.newline();
+                self.block(body);
+                self.plain("}");
+            }
+            StmtKind::DoWhile(body, condition) => {
+                self.keyword("do");
+                self.plain(" {");
+                self.newline();
+                self.block(body);
+                self.plain("} ");
+                self.keyword("while");
+                self.plain(" (");
+                self.expr(condition, 0);
+                self.plain(");");
+            }
+            StmtKind::For(init, condition, step, body) => {
+                self.keyword("for");
+                self.plain(" (");
+                self.simple(init);
+                self.plain("; ");
+                self.expr(condition, 0);
+                self.plain("; ");
+                self.simple(step);
+                self.plain(") {");
+                self.newline();
+                self.block(body);
+                self.plain("}");
+            }
+            StmtKind::Switch(value, cases, default) => {
+                self.keyword("switch");
+                self.plain(" (");
+                self.expr(value, 0);
+                self.plain(") {");
+                self.newline();
+                for (values, body) in cases {
+                    for value in values {
+                        self.keyword("case");
+                        self.plain(" ");
+                        self.token(&value.to_string(), TokenKind::Number);
+                        self.plain(":");
+                        self.newline();
+                    }
+                    self.block(body);
+                }
+                if let Some(body) = default {
+                    self.keyword("default");
+                    self.plain(":");
+                    self.newline();
+                    self.block(body);
+                }
+                self.plain("}");
+            }
+            StmtKind::Break => self.keyword("break;"),
+            StmtKind::Continue => self.keyword("continue;"),
+            StmtKind::Return(value) => {
+                self.keyword("return");
+                if let Some(value) = value {
+                    self.plain(" ");
+                    self.expr(value, 0);
+                }
+                self.plain(";");
+            }
+            StmtKind::Goto(block) => {
+                self.keyword("goto");
+                self.plain(" ");
+                self.token(&self.label(*block), TokenKind::Label);
+                self.plain(";");
+            }
+            StmtKind::Label(block) => {
+                let indent = self.indent;
+                self.indent = indent.saturating_sub(1);
+                self.token(&self.label(*block), TokenKind::Label);
+                self.plain(":");
+                self.newline();
+                self.indent = indent;
+                return;
+            }
+            StmtKind::Comment(comment) => self.token(&format!("// {comment}"), TokenKind::Comment),
+        }
+        self.newline();
+    }
+
+    /// Assignments without the semicolon, which `for` needs. Updates to a variable use the compound
+    /// operators.
+    fn simple(&mut self, statement: &Stmt) {
+        match &statement.kind {
+            StmtKind::Assign(target, Expr::Binary(op, left, right))
+                if **left == *target
+                    && matches!(
+                        op,
+                        BinaryOp::Add
+                            | BinaryOp::Sub
+                            | BinaryOp::Mul
+                            | BinaryOp::Div
+                            | BinaryOp::And
+                            | BinaryOp::Or
+                            | BinaryOp::Xor
+                            | BinaryOp::Shl
+                            | BinaryOp::Shr
+                    ) =>
+            {
+                self.expr(target, 0);
+                match (op, &**right) {
+                    (BinaryOp::Add, Expr::Constant(1)) => self.plain("++"),
+                    (BinaryOp::Sub, Expr::Constant(1)) => self.plain("--"),
+                    _ => {
+                        self.plain(&format!(" {}= ", op.symbol()));
+                        self.expr(right, 0);
+                    }
+                }
+            }
+            StmtKind::Assign(target, value) => {
+                self.expr(target, 0);
+                self.plain(" = ");
+                self.expr(value, 0);
+            }
+            StmtKind::Expr(expr) => self.expr(expr, 0),
+            _ => (),
+        }
+    }
+
+    fn arguments(&mut self, arguments: &[Expr]) {
+        self.plain("(");
+        for (n, argument) in arguments.iter().enumerate() {
+            if n > 0 {
+                self.plain(", ");
+            }
+            self.expr(argument, 0);
+        }
+        self.plain(")");
+    }
+
+    /// Prints an expression, in parentheses if it binds looser than `precedence`.
+    fn expr(&mut self, expr: &Expr, precedence: u8) {
+        let own = match expr {
+            Expr::Binary(op, ..) => op.precedence(),
+            Expr::Ternary(..) => 0,
+            Expr::Unary(..) | Expr::Cast(..) | Expr::Deref(..) | Expr::AddressOf(..) => UNARY,
+            Expr::Constant(value) if (*value as i32) < 0 && (*value as i32) > -0x10000 => UNARY,
+            Expr::Float(value) if value.is_sign_negative() => UNARY,
+            _ => POSTFIX,
+        };
+        let parenthesize = own < precedence;
+        if parenthesize {
+            self.plain("(");
+        }
+        match expr {
+            Expr::Variable(id) => {
+                let name = self.body.variables[*id].name.clone();
+                self.token(&name, TokenKind::Variable(*id));
+            }
+            Expr::Constant(value) => {
+                let value = match *value as i32 {
+                    -0xFFFF..0 => (*value as i32).into(),
+                    _ => i64::from(*value),
+                };
+                self.token(&format_immediate(value), TokenKind::Number);
+            }
+            Expr::Float(value) => {
+                // Show single precision constants the way they'd have been written
+                let single = f64::from(*value as f32) == *value;
+                let text = match single {
+                    true => format!("{:?}f", *value as f32),
+                    false => format!("{value:?}"),
+                };
+                self.token(&text, TokenKind::Number);
+            }
+            Expr::String(string) => self.token(&format!("\"{}\"", text::escape(string)), TokenKind::String),
+            Expr::Global(address) => {
+                self.token(&self.program.short_name(*address), TokenKind::Address(*address))
+            }
+            Expr::Unary(op, inner) => {
+                self.plain(match op {
+                    UnaryOp::Neg => "-",
+                    UnaryOp::BitNot => "~",
+                    UnaryOp::Not => "!",
+                });
+                self.expr(inner, UNARY);
+            }
+            Expr::Binary(op, left, right) => {
+                let own = op.precedence();
+                self.expr(left, own);
+                self.plain(&format!(" {} ", op.symbol()));
+                self.expr(right, own + 1);
+            }
+            Expr::Ternary(condition, then, otherwise) => {
+                self.expr(condition, 1);
+                self.plain(" ? ");
+                self.expr(then, 1);
+                self.plain(" : ");
+                self.expr(otherwise, 1);
+            }
+            Expr::Cast(type_info, inner) => {
+                self.plain("(");
+                self.type_name(type_info);
+                self.plain(")");
+                self.expr(inner, UNARY);
+            }
+            Expr::Deref(type_info, inner) => {
+                self.plain("*");
+                if let Some(type_info) = type_info {
+                    self.plain("(");
+                    self.type_name(&TypeInfo::pointer_to(type_info.clone()));
+                    self.plain(")");
+                }
+                self.expr(inner, UNARY);
+            }
+            Expr::Member(base, path) => {
+                self.expr(base, POSTFIX);
+                self.plain(path);
+            }
+            Expr::Index(base, index) => {
+                self.expr(base, POSTFIX);
+                self.plain("[");
+                self.expr(index, 0);
+                self.plain("]");
+            }
+            Expr::AddressOf(inner) => {
+                self.plain("&");
+                self.expr(inner, UNARY);
+            }
+            Expr::Call(target, arguments) => {
+                self.token(&self.program.short_name(*target), TokenKind::Address(*target));
+                self.arguments(arguments);
+            }
+            Expr::IndirectCall(target, arguments) => {
+                self.expr(target, POSTFIX);
+                self.arguments(arguments);
+            }
+            Expr::Intrinsic(name, arguments) => {
+                self.token(name, TokenKind::Plain);
+                self.arguments(arguments);
+            }
+        }
+        if parenthesize {
+            self.plain(")");
+        }
+    }
+}
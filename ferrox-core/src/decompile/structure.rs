@@ -0,0 +1,755 @@
+//! Recovering structured control flow from a [`Body`]'s control flow graph. Loops come from back edges to a
+//! block that dominates them, conditionals and switches end at their block's immediate post-dominator, and
+//! anything that doesn't fit either pattern falls back to a `goto`. The result is then cleaned up into the
+//! forms a person would have written, like `while (cond)`, `do { } while (cond)` and `for`.
+use std::collections::{BTreeMap, HashSet};
+
+use super::ast::{BinaryOp, Expr, Stmt, StmtKind, VariableId};
+use super::build::{Body, Exit};
+
+/// Immediate dominator of every node reachable from `entry`, using Cooper, Harvey and Kennedy's iterative
+/// algorithm. Works the same for post-dominators given the reversed graph.
+fn dominators(entry: usize, successors: &[Vec<usize>]) -> Vec<Option<usize>> {
+    let count = successors.len();
+    let mut predecessors = vec![Vec::new(); count];
+    for (node, targets) in successors.iter().enumerate() {
+        for &target in targets {
+            predecessors[target].push(node);
+        }
+    }
+    let order = reverse_postorder(entry, successors);
+    let mut position = vec![usize::MAX; count];
+    for (n, &node) in order.iter().enumerate() {
+        position[node] = n;
+    }
+
+    let mut idom = vec![None; count];
+    idom[entry] = Some(entry);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &node in &order[1..] {
+            let mut new = None;
+            for &predecessor in predecessors[node].iter().filter(|&&predecessor| idom[predecessor].is_some())
+            {
+                new = Some(match new {
+                    None => predecessor,
+                    Some(mut other) => {
+                        let mut finger = predecessor;
+                        while finger != other {
+                            while position[finger] > position[other] {
+                                finger = idom[finger].unwrap();
+                            }
+                            while position[other] > position[finger] {
+                                other = idom[other].unwrap();
+                            }
+                        }
+                        finger
+                    }
+                });
+            }
+            if new != idom[node] {
+                idom[node] = new;
+                changed = true;
+            }
+        }
+    }
+    idom
+}
+
+fn reverse_postorder(entry: usize, successors: &[Vec<usize>]) -> Vec<usize> {
+    let mut visited = vec![false; successors.len()];
+    let mut order = Vec::new();
+    let mut stack = vec![(entry, 0)];
+    visited[entry] = true;
+    while let Some((node, next)) = stack.pop() {
+        match successors[node].get(next) {
+            Some(&successor) => {
+                stack.push((node, next + 1));
+                if !visited[successor] {
+                    visited[successor] = true;
+                    stack.push((successor, 0));
+                }
+            }
+            None => order.push(node),
+        }
+    }
+    order.reverse();
+    order
+}
+
+struct Loop {
+    blocks: HashSet<usize>,
+    /// Where control goes when the loop is done, if there's one obvious place
+    follow: Option<usize>,
+}
+
+/// Where reaching a block means something other than carrying on with it.
+#[derive(Clone, Copy, Default)]
+struct Context {
+    /// End of the enclosing region, where the code after it takes over
+    stop: Option<usize>,
+    /// Reaching this is a `break`
+    exit: Option<usize>,
+    /// Reaching this is a `continue`
+    next: Option<usize>,
+    /// The next case of a switch, which this one falls through into
+    fallthrough: Option<usize>,
+    /// Header of the innermost loop, since code after an `if` can't carry on outside of it
+    body: Option<usize>,
+}
+
+struct Structurer<'a> {
+    body: &'a Body,
+    exits: Vec<Exit>,
+    successors: Vec<Vec<usize>>,
+    predecessors: Vec<Vec<usize>>,
+    idom: Vec<Option<usize>>,
+    ipdom: Vec<Option<usize>>,
+    loops: BTreeMap<usize, Loop>,
+    emitted: Vec<bool>,
+}
+
+/// Structures a function's body into C statements.
+pub(super) fn structure(body: &Body) -> Vec<Stmt> {
+    let mut exits: Vec<Exit> = body.blocks.iter().map(|block| block.exit.clone()).collect();
+    let empty: Vec<bool> = body.blocks.iter().map(|block| block.statements.is_empty()).collect();
+    skip_empty_jumps(&mut exits, &empty);
+    merge_conditions(&mut exits, &empty);
+
+    let count = exits.len();
+    let successors: Vec<Vec<usize>> = exits.iter().map(Exit::successors).collect();
+    let mut predecessors = vec![Vec::new(); count];
+    for (block, targets) in successors.iter().enumerate() {
+        for &target in targets {
+            predecessors[target].push(block);
+        }
+    }
+    let idom = dominators(0, &successors);
+
+    // Post-dominators come from the reversed graph, with an extra node everything that leaves the function
+    // goes to
+    let mut reversed = vec![Vec::new(); count + 1];
+    for (block, targets) in successors.iter().enumerate() {
+        if idom[block].is_none() {
+            continue;
+        }
+        for &target in targets {
+            reversed[target].push(block);
+        }
+        if targets.is_empty() {
+            reversed[count].push(block);
+        }
+    }
+    let ipdom =
+        dominators(count, &reversed).into_iter().map(|ipdom| ipdom.filter(|&node| node < count)).collect();
+
+    let mut structurer = Structurer {
+        body,
+        exits,
+        successors,
+        predecessors,
+        idom,
+        ipdom,
+        loops: BTreeMap::new(),
+        emitted: vec![false; count],
+    };
+    structurer.find_loops();
+    let mut statements = Vec::new();
+    structurer.sequence(0, Context::default(), &mut statements);
+
+    let mut labels = HashSet::new();
+    find_labels(&statements, &mut labels);
+    let mut statements = clean_up(statements, &labels, false);
+    make_for_loops(&mut statements);
+    if body.return_type.is_none() {
+        if let Some(Stmt { kind: StmtKind::Return(None), .. }) = statements.last() {
+            statements.pop();
+        }
+    }
+    statements
+}
+
+/// Points branches straight past empty blocks that only jump somewhere else, like the ones splitting edges
+/// for phi copies that turned out not to be needed. Otherwise two ways out of a loop to the same place look
+/// like different places.
+fn skip_empty_jumps(exits: &mut [Exit], empty: &[bool]) {
+    let destinations: Vec<usize> = (0..exits.len())
+        .map(|mut block| {
+            // An empty block jumping to itself is an infinite loop, and has to stay one
+            for _ in 0..exits.len() {
+                match exits[block] {
+                    Exit::Jump(target) if empty[block] && target != block => block = target,
+                    _ => break,
+                }
+            }
+            block
+        })
+        .collect();
+    let skip = |block: &mut usize| *block = destinations[*block];
+    for exit in exits {
+        match exit {
+            Exit::Jump(target) => skip(target),
+            Exit::Branch(_, taken, not_taken) => {
+                skip(taken);
+                skip(not_taken);
+            }
+            Exit::Switch(_, targets) => targets.iter_mut().for_each(skip),
+            Exit::Return(_) | Exit::Unknown(_) => (),
+        }
+    }
+}
+
+/// Folds branches into empty blocks that only branch again into a single condition, which is how `&&` and
+/// `||` get compiled.
+fn merge_conditions(exits: &mut [Exit], empty: &[bool]) {
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let mut predecessors = vec![0; exits.len()];
+        for exit in exits.iter() {
+            for successor in exit.successors() {
+                predecessors[successor] += 1;
+            }
+        }
+        for block in 0..exits.len() {
+            let Exit::Branch(first, taken, not_taken) = &exits[block] else {
+                continue;
+            };
+            let (taken, not_taken) = (*taken, *not_taken);
+            for (inner, other, inner_taken) in [(taken, not_taken, true), (not_taken, taken, false)] {
+                if inner == block || !empty[inner] || predecessors[inner] != 1 {
+                    continue;
+                }
+                let Exit::Branch(second, second_taken, second_not_taken) = &exits[inner] else {
+                    continue;
+                };
+                let (second_taken, second_not_taken) = (*second_taken, *second_not_taken);
+                let (first, second) = (first.clone(), second.clone());
+                let merged = match (inner_taken, second_taken == other, second_not_taken == other) {
+                    // if (a) { if (b) X else C } else C
+                    (true, false, true) => Exit::Branch(
+                        Expr::binary(BinaryOp::LogicalAnd, first, second),
+                        second_taken,
+                        other,
+                    ),
+                    (true, true, false) => Exit::Branch(
+                        Expr::binary(BinaryOp::LogicalAnd, first, second.not()),
+                        second_not_taken,
+                        other,
+                    ),
+                    // if (a) C else { if (b) C else X }
+                    (false, true, false) => Exit::Branch(
+                        Expr::binary(BinaryOp::LogicalOr, first, second),
+                        other,
+                        second_not_taken,
+                    ),
+                    (false, false, true) => Exit::Branch(
+                        Expr::binary(BinaryOp::LogicalOr, first, second.not()),
+                        other,
+                        second_taken,
+                    ),
+                    _ => continue,
+                };
+                exits[block] = merged;
+                exits[inner] = Exit::Unknown(None);
+                changed = true;
+                break;
+            }
+            if changed {
+                break;
+            }
+        }
+    }
+}
+
+impl Structurer<'_> {
+    fn dominates(&self, dominator: usize, mut block: usize) -> bool {
+        loop {
+            if block == dominator {
+                return true;
+            }
+            match self.idom[block] {
+                Some(parent) if parent != block => block = parent,
+                _ => return false,
+            }
+        }
+    }
+
+    /// Natural loops, one per header, with every back edge to the same header merged.
+    fn find_loops(&mut self) {
+        for block in 0..self.exits.len() {
+            if self.idom[block].is_none() {
+                continue;
+            }
+            for &header in &self.successors[block] {
+                if !self.dominates(header, block) {
+                    continue;
+                }
+                let blocks = &mut self
+                    .loops
+                    .entry(header)
+                    .or_insert_with(|| Loop { blocks: HashSet::new(), follow: None })
+                    .blocks;
+                blocks.insert(header);
+                let mut worklist = vec![block];
+                while let Some(block) = worklist.pop() {
+                    if blocks.insert(block) {
+                        worklist.extend(self.predecessors[block].iter().copied());
+                    }
+                }
+            }
+        }
+
+        let headers: Vec<usize> = self.loops.keys().copied().collect();
+        for header in headers {
+            let blocks = &self.loops[&header].blocks;
+            let outside = |block: &usize| !blocks.contains(block);
+            let exits: Vec<usize> = blocks
+                .iter()
+                .flat_map(|&block| self.successors[block].iter().copied())
+                .filter(outside)
+                .collect();
+            // The header or the latch testing the condition is the usual way out
+            let latches = self.predecessors[header].iter().filter(|block| blocks.contains(block));
+            let follow = std::iter::once(&header)
+                .chain(latches)
+                .find_map(|&block| self.successors[block].iter().copied().find(outside))
+                .or_else(|| {
+                    let mut unique = exits.clone();
+                    unique.sort_unstable();
+                    unique.dedup();
+                    (unique.len() == 1).then(|| unique[0])
+                });
+            self.loops.get_mut(&header).unwrap().follow = follow;
+        }
+    }
+
+    /// Emits blocks one after another starting from `start`, until reaching the end of the region or
+    /// somewhere that has to be jumped to.
+    fn sequence(&mut self, start: usize, context: Context, out: &mut Vec<Stmt>) {
+        let mut current = Some(start);
+        while let Some(block) = current {
+            let address = self.body.blocks[block].address;
+            if Some(block) == context.stop || Some(block) == context.fallthrough {
+                return;
+            }
+            if Some(block) == context.exit {
+                out.push(Stmt::new(address, StmtKind::Break));
+                return;
+            }
+            if Some(block) == context.next {
+                out.push(Stmt::new(address, StmtKind::Continue));
+                return;
+            }
+            if self.emitted[block] {
+                out.push(Stmt::new(address, StmtKind::Goto(block)));
+                return;
+            }
+            current = match self.loops.contains_key(&block) {
+                true => self.emit_loop(block, out),
+                false => self.block(block, context, out),
+            };
+        }
+    }
+
+    fn emit_loop(&mut self, header: usize, out: &mut Vec<Stmt>) -> Option<usize> {
+        let follow = self.loops[&header].follow;
+        let address = self.body.blocks[header].address;
+        let context = Context {
+            stop: None,
+            exit: follow,
+            next: Some(header),
+            fallthrough: None,
+            body: Some(header),
+        };
+        let mut body = Vec::new();
+        if let Some(next) = self.block(header, context, &mut body) {
+            self.sequence(next, context, &mut body);
+        }
+        out.push(Stmt::new(address, StmtKind::While(Expr::Constant(1), body)));
+        follow
+    }
+
+    /// Whether the code after a conditional can carry on inside the loop it's in.
+    fn in_loop(&self, context: Context, block: usize) -> bool {
+        context.body.is_none_or(|header| self.loops[&header].blocks.contains(&block))
+            || Some(block) == context.exit
+    }
+
+    /// Emits a single block along with whatever its exit branches to, returning the block that comes after it.
+    fn block(&mut self, block: usize, context: Context, out: &mut Vec<Stmt>) -> Option<usize> {
+        self.emitted[block] = true;
+        let address = self.body.blocks[block].address;
+        out.push(Stmt::new(address, StmtKind::Label(block)));
+        out.extend(self.body.blocks[block].statements.iter().cloned());
+
+        match self.exits[block].clone() {
+            Exit::Jump(target) => Some(target),
+            Exit::Return(value) => {
+                out.push(Stmt::new(address, StmtKind::Return(value)));
+                None
+            }
+            Exit::Unknown(target) => {
+                let kind = match target {
+                    Some(target) => StmtKind::Expr(Expr::Intrinsic("__jump".to_owned(), vec![target])),
+                    None => StmtKind::Comment("Couldn't decompile past here".to_owned()),
+                };
+                out.push(Stmt::new(address, kind));
+                None
+            }
+            Exit::Branch(condition, taken, not_taken) => {
+                if let Some(next) = self.bounded_switch(block, &condition, taken, not_taken, context, out) {
+                    return next;
+                }
+                let follow = self.ipdom[block].filter(|&follow| self.in_loop(context, follow));
+                let inner = Context { stop: follow.or(context.stop), ..context };
+                let mut then = Vec::new();
+                let mut otherwise = Vec::new();
+                self.sequence(taken, inner, &mut then);
+                self.sequence(not_taken, inner, &mut otherwise);
+                let kind = match (then.is_empty(), otherwise.is_empty()) {
+                    (true, true) if has_effects(&condition) => Some(StmtKind::Expr(condition)),
+                    (true, true) => None,
+                    (true, false) => Some(StmtKind::If(condition.not(), otherwise, then)),
+                    _ => Some(StmtKind::If(condition, then, otherwise)),
+                };
+                out.extend(kind.map(|kind| Stmt::new(address, kind)));
+                follow
+            }
+            Exit::Switch(value, targets) => {
+                let follow = self.ipdom[block].filter(|&follow| self.in_loop(context, follow));
+                self.switch(address, value, &targets, None, follow, context, out);
+                follow
+            }
+        }
+    }
+
+    /// A switch along with the range check in front of it, which goes to the `default` case.
+    fn bounded_switch(
+        &mut self, block: usize, condition: &Expr, taken: usize, not_taken: usize, context: Context,
+        out: &mut Vec<Stmt>,
+    ) -> Option<Option<usize>> {
+        let is_range_check = matches!(
+            condition,
+            Expr::Binary(BinaryOp::Gt | BinaryOp::Ge | BinaryOp::Lt | BinaryOp::Le, ..)
+        );
+        let (switch, default) =
+            [(taken, not_taken), (not_taken, taken)].into_iter().find(|&(switch, _)| {
+                is_range_check
+                    && switch != block
+                    && self.body.blocks[switch].statements.is_empty()
+                    && self.predecessors[switch] == [block]
+                    && matches!(self.exits[switch], Exit::Switch(..))
+            })?;
+        let Exit::Switch(value, targets) = self.exits[switch].clone() else {
+            unreachable!();
+        };
+        self.emitted[switch] = true;
+        let address = self.body.blocks[block].address;
+        let follow = self.ipdom[block].filter(|&follow| self.in_loop(context, follow));
+        self.switch(address, value, &targets, Some(default), follow, context, out);
+        Some(follow)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn switch(
+        &mut self, address: u32, value: Expr, targets: &[usize], default: Option<usize>,
+        follow: Option<usize>, context: Context, out: &mut Vec<Stmt>,
+    ) {
+        // One arm per target, in the order they appear in the binary so fallthrough reads naturally
+        let mut arms: Vec<(usize, Vec<u32>)> = Vec::new();
+        for (n, &target) in targets.iter().enumerate() {
+            match arms.iter_mut().find(|(other, _)| *other == target) {
+                Some((_, values)) => values.push(n as u32),
+                None => arms.push((target, vec![n as u32])),
+            }
+        }
+        let default = default.filter(|&default| Some(default) != follow);
+        match default {
+            // Values the table sends to the same place as the range check only need the `default` label
+            Some(default) => arms.retain(|&(target, _)| target != default),
+            None => arms.retain(|&(target, _)| Some(target) != follow),
+        }
+        let mut starts: Vec<usize> = arms.iter().map(|(target, _)| *target).chain(default).collect();
+        starts.sort_by_key(|&target| self.body.blocks[target].address);
+
+        let mut cases = Vec::new();
+        let mut default_body = None;
+        for (n, &start) in starts.iter().enumerate() {
+            let inner = Context {
+                stop: None,
+                exit: follow,
+                fallthrough: starts.get(n + 1).copied(),
+                next: context.next,
+                body: context.body,
+            };
+            let mut body = Vec::new();
+            if Some(start) != follow {
+                self.sequence(start, inner, &mut body);
+            } else {
+                body.push(Stmt::new(address, StmtKind::Break));
+            }
+            match arms.iter().find(|(target, _)| *target == start) {
+                Some((_, values)) => cases.push((values.clone(), body)),
+                None => default_body = Some(body),
+            }
+        }
+        out.push(Stmt::new(address, StmtKind::Switch(value, cases, default_body)));
+    }
+}
+
+fn has_effects(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call(..) | Expr::IndirectCall(..) | Expr::Intrinsic(..) => true,
+        Expr::Unary(_, inner)
+        | Expr::Cast(_, inner)
+        | Expr::Deref(_, inner)
+        | Expr::Member(inner, _)
+        | Expr::AddressOf(inner) => has_effects(inner),
+        Expr::Binary(_, left, right) | Expr::Index(left, right) => has_effects(left) || has_effects(right),
+        Expr::Ternary(a, b, c) => has_effects(a) || has_effects(b) || has_effects(c),
+        _ => false,
+    }
+}
+
+fn find_labels(statements: &[Stmt], labels: &mut HashSet<usize>) {
+    for statement in statements {
+        for inner in nested(statement) {
+            find_labels(inner, labels);
+        }
+        if let StmtKind::Goto(block) = statement.kind {
+            labels.insert(block);
+        }
+    }
+}
+
+fn nested(statement: &Stmt) -> Vec<&Vec<Stmt>> {
+    match &statement.kind {
+        StmtKind::If(_, then, otherwise) => vec![then, otherwise],
+        StmtKind::While(_, body) | StmtKind::DoWhile(body, _) | StmtKind::For(_, _, _, body) => vec![body],
+        StmtKind::Switch(_, cases, default) => cases.iter().map(|(_, body)| body).chain(default).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether a loop body has a `continue` for that loop, as opposed to one nested inside it.
+fn has_continue(statements: &[Stmt]) -> bool {
+    statements.iter().any(|statement| match &statement.kind {
+        StmtKind::Continue => true,
+        StmtKind::If(_, then, otherwise) => has_continue(then) || has_continue(otherwise),
+        StmtKind::Switch(_, cases, default) => {
+            cases.iter().any(|(_, body)| has_continue(body)) || default.as_deref().is_some_and(has_continue)
+        }
+        _ => false,
+    })
+}
+
+/// Tidies up what structuring produced: drops labels nobody jumps to and redundant jumps, and turns
+/// `while (1)` loops into the form they were most likely written as. `in_loop` says whether a trailing
+/// `continue` is redundant here.
+fn clean_up(statements: Vec<Stmt>, labels: &HashSet<usize>, in_loop: bool) -> Vec<Stmt> {
+    let mut out: Vec<Stmt> = Vec::new();
+    for statement in statements {
+        let Stmt { address, kind } = statement;
+        match kind {
+            StmtKind::Label(block) if !labels.contains(&block) => (),
+            StmtKind::If(condition, then, otherwise) => {
+                let then = clean_up(then, labels, false);
+                let otherwise = clean_up(otherwise, labels, false);
+                let (condition, then, otherwise) = match (then.is_empty(), otherwise.is_empty()) {
+                    (true, false) => (condition.not(), otherwise, then),
+                    _ => (condition, then, otherwise),
+                };
+                // An else after a branch that always jumps away doesn't need to be nested
+                let jumps = then.last().is_some_and(Stmt::is_jump);
+                if jumps && !otherwise.is_empty() {
+                    out.push(Stmt::new(address, StmtKind::If(condition, then, Vec::new())));
+                    out.extend(otherwise);
+                } else if then.is_empty() && otherwise.is_empty() {
+                    if has_effects(&condition) {
+                        out.push(Stmt::new(address, StmtKind::Expr(condition)));
+                    }
+                } else {
+                    out.push(Stmt::new(address, StmtKind::If(condition, then, otherwise)));
+                }
+            }
+            StmtKind::While(condition, body) => out.push(clean_loop(address, condition, body, labels)),
+            StmtKind::Switch(value, cases, default) => {
+                let cases =
+                    cases.into_iter().map(|(values, body)| (values, clean_up(body, labels, false))).collect();
+                let default = default.map(|body| clean_up(body, labels, false));
+                out.push(Stmt::new(address, StmtKind::Switch(value, cases, default)));
+            }
+            kind => out.push(Stmt::new(address, kind)),
+        }
+        // Nothing after a jump can run, unless something jumps to it
+        if out.len() >= 2
+            && out[out.len() - 2].is_jump()
+            && !matches!(out.last().unwrap().kind, StmtKind::Label(_))
+        {
+            out.pop();
+        }
+    }
+    if in_loop && matches!(out.last(), Some(Stmt { kind: StmtKind::Continue, .. })) {
+        out.pop();
+    }
+    out
+}
+
+fn clean_loop(address: u32, condition: Expr, body: Vec<Stmt>, labels: &HashSet<usize>) -> Stmt {
+    let mut body = clean_up(body, labels, true);
+    if condition != Expr::Constant(1) {
+        return Stmt::new(address, StmtKind::While(condition, body));
+    }
+    let breaks = |then: &[Stmt]| matches!(then, [Stmt { kind: StmtKind::Break, .. }]);
+
+    // while (1) { if (c) break; ... } is while (!c) { ... }
+    if let Some(Stmt { kind: StmtKind::If(test, then, otherwise), .. }) = body.first() {
+        if otherwise.is_empty() && breaks(then) {
+            let test = test.clone().not();
+            body.remove(0);
+            return Stmt::new(address, StmtKind::While(test, body));
+        }
+    }
+    // while (1) { if (c) { ...; continue; } break; } is while (c) { ... }
+    if let [Stmt { kind: StmtKind::If(test, then, otherwise), .. }, Stmt { kind: StmtKind::Break, .. }] =
+        &body[..]
+    {
+        if otherwise.is_empty()
+            && then.len() > 1
+            && matches!(then.last(), Some(Stmt { kind: StmtKind::Continue, .. }))
+        {
+            let test = test.clone();
+            let mut then = then.clone();
+            then.pop();
+            return Stmt::new(address, StmtKind::While(test, then));
+        }
+    }
+    // Testing at the end, with either `if (c) continue; break;` or `if (c) break;`, is a do-while
+    let test = match &body[..] {
+        [.., Stmt { kind: StmtKind::If(test, then, otherwise), .. }, Stmt { kind: StmtKind::Break, .. }]
+            if otherwise.is_empty() && matches!(then[..], [Stmt { kind: StmtKind::Continue, .. }]) =>
+        {
+            Some((test.clone(), 2))
+        }
+        [.., Stmt { kind: StmtKind::If(test, then, otherwise), .. }]
+            if otherwise.is_empty() && breaks(then) =>
+        {
+            Some((test.clone().not(), 1))
+        }
+        _ => None,
+    };
+    if let Some((test, trailing)) = test {
+        // A `continue` would skip the test in a do-while, rather than going back to the top
+        if !has_continue(&body[..body.len() - trailing]) {
+            body.truncate(body.len() - trailing);
+            return Stmt::new(address, StmtKind::DoWhile(body, test));
+        }
+    }
+    Stmt::new(address, StmtKind::While(condition, body))
+}
+
+/// Turns `i = a; while (c) { ...; i = i + 1; }` into a `for` loop, when `c` tests `i` and nothing in the body
+/// would skip the step.
+fn make_for_loops(statements: &mut Vec<Stmt>) {
+    let mut index = 0;
+    while index < statements.len() {
+        for body in nested_mut(&mut statements[index]) {
+            make_for_loops(body);
+        }
+        // The initialization can be a little further up, as long as what's in between could just as well go first
+        let mut start = index;
+        while start > 0 && assigned(&statements[start - 1]).is_some() {
+            start -= 1;
+            let init = &statements[start];
+            let movable = |other: &Stmt| match (&init.kind, assigned(init), &other.kind, assigned(other)) {
+                (
+                    StmtKind::Assign(_, value),
+                    Some(variable),
+                    StmtKind::Assign(_, other_value),
+                    Some(other_variable),
+                ) => {
+                    !mentions(other_value, variable)
+                        && !mentions(value, other_variable)
+                        && !has_effects(value)
+                        && !has_effects(other_value)
+                }
+                _ => false,
+            };
+            if !statements[start + 1..index].iter().all(movable) {
+                break;
+            }
+            if let Some(replacement) = as_for(init, &statements[index]) {
+                statements[index] = replacement;
+                statements.remove(start);
+                index -= 1;
+                break;
+            }
+        }
+        index += 1;
+    }
+}
+
+fn nested_mut(statement: &mut Stmt) -> Vec<&mut Vec<Stmt>> {
+    match &mut statement.kind {
+        StmtKind::If(_, then, otherwise) => vec![then, otherwise],
+        StmtKind::While(_, body) | StmtKind::DoWhile(body, _) | StmtKind::For(_, _, _, body) => vec![body],
+        StmtKind::Switch(_, cases, default) => {
+            cases.iter_mut().map(|(_, body)| body).chain(default).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn assigned(statement: &Stmt) -> Option<VariableId> {
+    match &statement.kind {
+        StmtKind::Assign(Expr::Variable(variable), _) => Some(*variable),
+        _ => None,
+    }
+}
+
+fn mentions(expr: &Expr, variable: VariableId) -> bool {
+    match expr {
+        Expr::Variable(other) => *other == variable,
+        Expr::Unary(_, inner)
+        | Expr::Cast(_, inner)
+        | Expr::Deref(_, inner)
+        | Expr::Member(inner, _)
+        | Expr::AddressOf(inner) => mentions(inner, variable),
+        Expr::Binary(_, left, right) | Expr::Index(left, right) => {
+            mentions(left, variable) || mentions(right, variable)
+        }
+        Expr::Ternary(a, b, c) => mentions(a, variable) || mentions(b, variable) || mentions(c, variable),
+        Expr::Call(_, arguments) | Expr::Intrinsic(_, arguments) => {
+            arguments.iter().any(|argument| mentions(argument, variable))
+        }
+        Expr::IndirectCall(target, arguments) => {
+            mentions(target, variable) || arguments.iter().any(|argument| mentions(argument, variable))
+        }
+        _ => false,
+    }
+}
+
+fn as_for(init: &Stmt, statement: &Stmt) -> Option<Stmt> {
+    let StmtKind::While(condition, body) = &statement.kind else {
+        return None;
+    };
+    let variable = assigned(init)?;
+    let step = body.last()?;
+    let StmtKind::Assign(_, update) = &step.kind else {
+        return None;
+    };
+    let is_step = matches!(update, Expr::Binary(BinaryOp::Add | BinaryOp::Sub, left, _) if **left == Expr::Variable(variable));
+    if assigned(step) != Some(variable) || !is_step || !mentions(condition, variable) || has_continue(body) {
+        return None;
+    }
+    let body = body[..body.len() - 1].to_vec();
+    let kind = StmtKind::For(
+        Box::new(init.clone()),
+        condition.clone(),
+        Box::new(step.clone()),
+        body,
+    );
+    Some(Stmt::new(init.address, kind))
+}
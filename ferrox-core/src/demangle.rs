@@ -0,0 +1,663 @@
+//! Demangling the names CodeWarrior gives C++ symbols, like `update__Q23foo3BarFf` for
+//! `foo::Bar::update(float)`. CodeWarrior has its own scheme, descended from cfront's rather than the Itanium
+//! one other compilers use: the name comes first, then `__`, then the class it's in and the parameter types.
+//!
+//! ```text
+//! update__Q23foo3BarFf
+//! ^^^^^^  ^^^^^^^^^^ ^^
+//! name    foo::Bar   (float)
+//! ```
+//!
+//! Return types are only mangled for template functions, and nothing says whether a member function is
+//! static, so those have to be worked out from the code instead.
+use std::fmt::{self, Display, Formatter};
+
+use crate::registry::{Parameter, Prototype, TypeInfo};
+
+/// A type from a mangled name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    /// Built in type, like `int` or `unsigned char`. `...` counts as one too.
+    Builtin(&'static str),
+    /// Class, struct or enum, with its scope and any template arguments, e.g. `foo::Bar<int>`
+    Named(String),
+    Pointer(Box<Type>),
+    Reference(Box<Type>),
+    Const(Box<Type>),
+    Volatile(Box<Type>),
+    Array(u64, Box<Type>),
+    Function {
+        parameters: Vec<Type>,
+        return_type: Box<Type>,
+    },
+    /// Pointer to a member of `class`, usually a member function
+    MemberPointer {
+        class: String,
+        target: Box<Type>,
+    },
+}
+
+impl Type {
+    /// Type registry equivalent of the type, `None` for `void` and `...`, which aren't values.
+    pub fn type_info(&self) -> Option<TypeInfo> {
+        let integer = |bits, signed| Some(TypeInfo::Integer { bits, signed });
+        match self {
+            Type::Builtin(name) => match *name {
+                "bool" | "unsigned char" => integer(8, false),
+                "char" | "signed char" => integer(8, true),
+                "wchar_t" | "unsigned short" => integer(16, false),
+                "short" => integer(16, true),
+                "int" | "long" => integer(32, true),
+                "unsigned int" | "unsigned long" => integer(32, false),
+                "long long" => integer(64, true),
+                "unsigned long long" => integer(64, false),
+                "float" => Some(TypeInfo::Float { bits: 32 }),
+                "double" | "long double" => Some(TypeInfo::Float { bits: 64 }),
+                _ => None,
+            },
+            Type::Named(name) => Some(TypeInfo::Named { name: name.clone() }),
+            Type::Array(count, element) => {
+                Some(TypeInfo::Array { element_type: Box::new(element.type_info()?), count: *count })
+            }
+            Type::Pointer(target) | Type::Reference(target) => match target.as_ref() {
+                Type::Function { .. } => Some(TypeInfo::pointer()),
+                target => Some(target.type_info().map_or_else(TypeInfo::pointer, TypeInfo::pointer_to)),
+            },
+            Type::Const(inner) | Type::Volatile(inner) => inner.type_info(),
+            Type::Function { .. } | Type::MemberPointer { .. } => Some(TypeInfo::pointer()),
+        }
+    }
+
+    /// Splits the type into the base and everything that goes around where a name would be, the same way C
+    /// declarations work, so pointers to functions and arrays come out right.
+    fn split(&self, declarator: String) -> (String, String) {
+        match self {
+            Type::Builtin(name) => ((*name).to_owned(), declarator),
+            Type::Named(name) => (name.clone(), declarator),
+            Type::Const(inner) | Type::Volatile(inner) => {
+                let qualifier = match self {
+                    Type::Const(_) => "const",
+                    _ => "volatile",
+                };
+                match inner.as_ref() {
+                    // Qualifying the pointer itself rather than what it points to
+                    Type::Pointer(_) | Type::Reference(_) | Type::MemberPointer { .. } => {
+                        inner.split(format!(" {qualifier}{declarator}"))
+                    }
+                    _ => {
+                        let (base, declarator) = inner.split(declarator);
+                        (format!("{qualifier} {base}"), declarator)
+                    }
+                }
+            }
+            Type::Pointer(target) | Type::Reference(target) => {
+                let symbol = match self {
+                    Type::Pointer(_) => '*',
+                    _ => '&',
+                };
+                let declarator = format!("{symbol}{declarator}");
+                match target.as_ref() {
+                    Type::Function { .. } | Type::Array(..) => target.split(format!("({declarator})")),
+                    _ => target.split(declarator),
+                }
+            }
+            Type::MemberPointer { class, target } => {
+                let declarator = format!("{class}::*{declarator}");
+                match target.as_ref() {
+                    Type::Function { .. } | Type::Array(..) => target.split(format!("({declarator})")),
+                    _ => target.split(format!(" {declarator}")),
+                }
+            }
+            Type::Array(count, element) => element.split(format!("{declarator}[{count}]")),
+            Type::Function { parameters, return_type } => {
+                return_type.split(format!("{declarator}({})", parameter_list(parameters)))
+            }
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (base, declarator) = self.split(String::new());
+        match declarator.starts_with('(') {
+            true => write!(f, "{base} {declarator}"),
+            false => write!(f, "{base}{declarator}"),
+        }
+    }
+}
+
+fn parameter_list(parameters: &[Type]) -> String {
+    parameters.iter().map(Type::to_string).collect::<Vec<_>>().join(", ")
+}
+
+/// Everything a mangled name says about a symbol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Demangled {
+    /// Namespaces and classes the symbol is in, outermost first
+    pub scope: Vec<String>,
+    /// Name inside the scope, with constructors, destructors and operators spelled the way C++ writes them
+    pub name: String,
+    /// `None` for data, like static members and vtables
+    pub parameters: Option<Vec<Type>>,
+    /// Only template functions have theirs mangled
+    pub return_type: Option<Type>,
+    /// Member function that can be called on a `const` object
+    pub is_const: bool,
+}
+
+impl Demangled {
+    /// Name with its scope but without the parameters, e.g. `foo::Bar::update`.
+    pub fn qualified_name(&self) -> String {
+        let mut parts = self.scope.clone();
+        parts.push(self.name.clone());
+        parts.join("::")
+    }
+
+    fn is_constructor(&self) -> bool {
+        self.scope.last().is_some_and(|class| without_arguments(class) == self.name)
+    }
+
+    fn is_destructor(&self) -> bool {
+        self.name.starts_with('~')
+    }
+
+    /// The prototype the mangled parameters give the function, or `None` if it isn't one. `inferred` is what
+    /// its code suggests, which fills in the return type and decides whether a function in a class takes
+    /// `this`: constructors, destructors and `const` functions always do, `operator new` and `delete` never do,
+    /// and anything else only does if it reads more GPR arguments than its parameters need.
+    pub fn prototype(&self, inferred: Option<&Prototype>) -> Option<Prototype> {
+        let types = self.parameters.as_ref()?;
+        let mut parameters = Vec::new();
+        for parameter in types {
+            // Nothing past a `...` has a fixed place to be passed in
+            let Some(type_info) = parameter.type_info() else {
+                break;
+            };
+            // Arrays can only be parameters by decaying into a pointer to their first element
+            parameters.push(match type_info {
+                TypeInfo::Array { element_type, .. } => TypeInfo::pointer_to(*element_type),
+                type_info => type_info,
+            });
+        }
+
+        let gprs = |parameters: &mut dyn Iterator<Item = &TypeInfo>| {
+            parameters
+                .map(|type_info| match type_info {
+                    TypeInfo::Float { .. } => 0,
+                    TypeInfo::Integer { bits: 64, .. } => 2,
+                    _ => 1,
+                })
+                .sum::<usize>()
+        };
+        let static_operator =
+            ["operator new", "operator delete"].iter().any(|name| self.name.starts_with(name));
+        let takes_this = !self.scope.is_empty()
+            && !static_operator
+            && match inferred {
+                _ if self.is_constructor() || self.is_destructor() || self.is_const => true,
+                Some(inferred) => {
+                    let inferred =
+                        gprs(&mut inferred.parameters.iter().map(|parameter| &parameter.type_info));
+                    inferred > gprs(&mut parameters.iter())
+                }
+                None => true,
+            };
+        if takes_this {
+            let class = TypeInfo::Named { name: self.scope.join("::") };
+            parameters.insert(0, TypeInfo::pointer_to(class));
+        }
+
+        let parameters = parameters
+            .into_iter()
+            .enumerate()
+            .map(|(index, type_info)| Parameter {
+                name: match index == 0 && takes_this {
+                    true => "this".to_owned(),
+                    false => format!("arg{index}"),
+                },
+                type_info,
+            })
+            .collect();
+        let return_type = match &self.return_type {
+            Some(return_type) => return_type.type_info(),
+            None => inferred.and_then(|inferred| inferred.return_type.clone()),
+        };
+        Some(Prototype { return_type, parameters })
+    }
+}
+
+impl Display for Demangled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(return_type) = &self.return_type {
+            write!(f, "{return_type} ")?;
+        }
+        write!(f, "{}", self.qualified_name())?;
+        if let Some(parameters) = &self.parameters {
+            write!(f, "({})", parameter_list(parameters))?;
+        }
+        if self.is_const {
+            write!(f, " const")?;
+        }
+        Ok(())
+    }
+}
+
+/// Demangles a CodeWarrior C++ name, or returns `None` if it isn't one.
+pub fn demangle(name: &str) -> Option<Demangled> {
+    // The name itself can contain `__` too, especially special ones like `__ct`, so try every split until the
+    // rest parses. Anything starting with `__` is looked for past its first character, so there's a name.
+    let mut start = 1;
+    while let Some(found) = name.get(start..)?.find("__") {
+        let split = start + found;
+        if let Some(demangled) = demangle_at(&name[..split], &name[split + 2..]) {
+            return Some(demangled);
+        }
+        start = split + 1;
+    }
+    None
+}
+
+/// Demangles `name` as the part before the `__`, and `rest` as its scope and parameters.
+fn demangle_at(name: &str, rest: &str) -> Option<Demangled> {
+    let mut parser = Parser { text: rest, position: 0 };
+    let scope = match parser.peek() {
+        Some('Q' | '0'..='9') => parser.qualified_name()?,
+        _ => Vec::new(),
+    };
+    let is_const = parser.eat_str("CF");
+    let (parameters, return_type) = match is_const || parser.eat('F') {
+        true => {
+            let parameters = parser.parameters()?;
+            let return_type = match parser.eat('_') {
+                true => Some(parser.parse_type()?),
+                false => None,
+            };
+            (Some(parameters), return_type)
+        }
+        // A static data member, or something like a vtable that belongs to a class
+        false if !scope.is_empty() => (None, None),
+        false => return None,
+    };
+    if !parser.at_end() {
+        return None;
+    }
+
+    let class = scope.last().map(|class| without_arguments(class).to_owned());
+    let name = match (name, class) {
+        ("__ct", Some(class)) => class,
+        ("__dt", Some(class)) => format!("~{class}"),
+        _ => match name.strip_prefix("__op").and_then(demangle_type) {
+            Some(converted) => format!("operator {converted}"),
+            None => {
+                operator(name).map_or_else(|| template_name(name), |operator| format!("operator{operator}"))
+            }
+        },
+    };
+    Some(Demangled { scope, name, parameters, return_type, is_const })
+}
+
+/// Mangles a class name the way it appears in symbols, like `Q23foo3Bar` for `foo::Bar`, or `None` if it
+/// has template arguments or anything else that doesn't go in a plain name.
+pub fn mangle_class(name: &str) -> Option<String> {
+    let parts: Vec<&str> = name.split("::").collect();
+    let valid = |part: &&str| {
+        !part.is_empty()
+            && !part.starts_with(|c: char| c.is_ascii_digit())
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    };
+    if !parts.iter().all(valid) || parts.len() > 9 {
+        return None;
+    }
+    let mangled: String = parts.iter().map(|part| format!("{}{part}", part.len())).collect();
+    Some(match parts.len() {
+        1 => mangled,
+        count => format!("Q{count}{mangled}"),
+    })
+}
+
+/// Demangles a type on its own, like the one a conversion operator converts to.
+pub fn demangle_type(text: &str) -> Option<Type> {
+    let mut parser = Parser { text, position: 0 };
+    let parsed = parser.parse_type()?;
+    parser.at_end().then_some(parsed)
+}
+
+/// Operator a special name stands for, like `==` for `__eq`.
+fn operator(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "__nw" => " new",
+        "__nwa" => " new[]",
+        "__dl" => " delete",
+        "__dla" => " delete[]",
+        "__pl" => "+",
+        "__mi" => "-",
+        "__ml" => "*",
+        "__dv" => "/",
+        "__md" => "%",
+        "__er" => "^",
+        "__ad" => "&",
+        "__or" => "|",
+        "__co" => "~",
+        "__nt" => "!",
+        "__as" => "=",
+        "__lt" => "<",
+        "__gt" => ">",
+        "__apl" => "+=",
+        "__ami" => "-=",
+        "__amu" => "*=",
+        "__adv" => "/=",
+        "__amd" => "%=",
+        "__aer" => "^=",
+        "__aad" => "&=",
+        "__aor" => "|=",
+        "__ls" => "<<",
+        "__rs" => ">>",
+        "__als" => "<<=",
+        "__ars" => ">>=",
+        "__eq" => "==",
+        "__ne" => "!=",
+        "__le" => "<=",
+        "__ge" => ">=",
+        "__aa" => "&&",
+        "__oo" => "||",
+        "__pp" => "++",
+        "__mm" => "--",
+        "__cm" => ",",
+        "__rm" => "->*",
+        "__rf" => "->",
+        "__cl" => "()",
+        "__vc" => "[]",
+        _ => return None,
+    })
+}
+
+/// A class or function name with the `<...>` its template arguments come in removed.
+fn without_arguments(name: &str) -> &str {
+    name.split_once('<').map_or(name, |(base, _)| base)
+}
+
+/// Demangles the template arguments in a name like `Vector<Q23foo3Bar,4>`, which are mangled types apart from
+/// constants. Anything that doesn't parse stays the way it was.
+fn template_name(name: &str) -> String {
+    let Some((base, arguments)) = name.split_once('<') else {
+        return name.to_owned();
+    };
+    let Some(arguments) = arguments.strip_suffix('>') else {
+        return name.to_owned();
+    };
+    let mut parser = Parser { text: arguments, position: 0 };
+    let mut demangled = Vec::new();
+    while !parser.at_end() {
+        let start = parser.position;
+        match parser.parse_type() {
+            Some(argument) if parser.at_end() || parser.peek() == Some(',') => {
+                demangled.push(argument.to_string())
+            }
+            // Constants, which are written out as they are
+            _ => {
+                parser.position = start;
+                let end = parser.rest().find(',').map_or(parser.text.len(), |end| start + end);
+                demangled.push(parser.text[start..end].to_owned());
+                parser.position = end;
+            }
+        }
+        parser.eat(',');
+    }
+    format!("{base}<{}>", demangled.join(", "))
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn at_end(&self) -> bool {
+        self.position >= self.text.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let next = self.peek()?;
+        self.position += next.len_utf8();
+        Some(next)
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        let found = self.peek() == Some(expected);
+        self.position += found as usize;
+        found
+    }
+
+    fn eat_str(&mut self, expected: &str) -> bool {
+        let found = self.rest().starts_with(expected);
+        self.position += expected.len() * found as usize;
+        found
+    }
+
+    fn number(&mut self) -> Option<u64> {
+        let digits = self.rest().find(|c: char| !c.is_ascii_digit()).unwrap_or(self.rest().len());
+        let number = self.rest()[..digits].parse().ok()?;
+        self.position += digits;
+        Some(number)
+    }
+
+    fn digit(&mut self) -> Option<usize> {
+        let digit = self.peek()?.to_digit(10)?;
+        self.position += 1;
+        Some(digit as usize)
+    }
+
+    /// A name prefixed with its length, like `3Bar`.
+    fn name(&mut self) -> Option<String> {
+        let length = self.number()? as usize;
+        let name = self.rest().get(..length).filter(|name| !name.is_empty())?;
+        self.position += length;
+        Some(template_name(name))
+    }
+
+    /// A name on its own, or `Q` followed by how many names make up a scoped one, like `Q23foo3Bar`.
+    fn qualified_name(&mut self) -> Option<Vec<String>> {
+        match self.eat('Q') {
+            true => {
+                let count = self.digit()?;
+                (0..count).map(|_| self.name()).collect()
+            }
+            false => Some(vec![self.name()?]),
+        }
+    }
+
+    fn parse_type(&mut self) -> Option<Type> {
+        let builtin = |name| Some(Type::Builtin(name));
+        match self.next()? {
+            'C' => Some(Type::Const(Box::new(self.parse_type()?))),
+            'V' => Some(Type::Volatile(Box::new(self.parse_type()?))),
+            'P' => Some(Type::Pointer(Box::new(self.parse_type()?))),
+            'R' => Some(Type::Reference(Box::new(self.parse_type()?))),
+            'U' => match self.next()? {
+                'c' => builtin("unsigned char"),
+                's' => builtin("unsigned short"),
+                'i' => builtin("unsigned int"),
+                'l' => builtin("unsigned long"),
+                'x' => builtin("unsigned long long"),
+                _ => None,
+            },
+            'S' => match self.next()? {
+                'c' => builtin("signed char"),
+                _ => None,
+            },
+            'v' => builtin("void"),
+            'b' => builtin("bool"),
+            'c' => builtin("char"),
+            'w' => builtin("wchar_t"),
+            's' => builtin("short"),
+            'i' => builtin("int"),
+            'l' => builtin("long"),
+            'x' => builtin("long long"),
+            'f' => builtin("float"),
+            'd' => builtin("double"),
+            'r' => builtin("long double"),
+            'e' => builtin("..."),
+            'Q' | '0'..='9' => {
+                self.position -= 1;
+                Some(Type::Named(self.qualified_name()?.join("::")))
+            }
+            'F' => {
+                let parameters = self.parameters()?;
+                match self.eat('_') {
+                    true => Some(Type::Function { parameters, return_type: Box::new(self.parse_type()?) }),
+                    false => None,
+                }
+            }
+            'M' => {
+                let class = self.qualified_name()?.join("::");
+                Some(Type::MemberPointer { class, target: Box::new(self.parse_type()?) })
+            }
+            'A' => {
+                let count = self.number()?;
+                match self.eat('_') {
+                    true => Some(Type::Array(count, Box::new(self.parse_type()?))),
+                    false => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Parameter types, up to the `_` before a return type or the end. `T` repeats an earlier parameter and
+    /// `N` repeats one several times, both counting parameters from 1.
+    fn parameters(&mut self) -> Option<Vec<Type>> {
+        let mut parameters: Vec<Type> = Vec::new();
+        while !self.at_end() && self.peek() != Some('_') {
+            if self.eat('T') {
+                let repeated = parameters.get(self.digit()?.checked_sub(1)?)?.clone();
+                parameters.push(repeated);
+            } else if self.eat('N') {
+                let count = self.digit()?;
+                let repeated = parameters.get(self.digit()?.checked_sub(1)?)?.clone();
+                parameters.extend(std::iter::repeat_n(repeated, count));
+            } else {
+                parameters.push(self.parse_type()?);
+            }
+        }
+        // `(void)` is how a function without parameters is written, so the list is never empty
+        match parameters.as_slice() {
+            [] => return None,
+            [Type::Builtin("void")] => parameters.clear(),
+            _ => (),
+        }
+        Some(parameters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demangles_names() {
+        let cases = [
+            // Constructors and destructors
+            ("__ct__9CMyObjectFv", "CMyObject::CMyObject()"),
+            ("__dt__9CMyObjectFv", "CMyObject::~CMyObject()"),
+            ("__ct__Q23foo3BarFRCQ23foo3Bar", "foo::Bar::Bar(const foo::Bar&)"),
+            ("__dt__Q23foo3BarFv", "foo::Bar::~Bar()"),
+            // Free functions and qualified names
+            ("main__Fv", "main()"),
+            ("OSReport__FPCce", "OSReport(const char*, ...)"),
+            ("update__Q23foo3BarFf", "foo::Bar::update(float)"),
+            ("calc__3FooFiUlPv", "Foo::calc(int, unsigned long, void*)"),
+            ("__some_name__3FooFv", "Foo::__some_name()"),
+            // Const methods
+            ("getX__6VectorCFv", "Vector::getX() const"),
+            ("find__Q23foo3MapCFRCi", "foo::Map::find(const int&) const"),
+            // Templates
+            ("push__10Stack<int>Fi", "Stack<int>::push(int)"),
+            ("__ct__10Stack<int>Fv", "Stack<int>::Stack()"),
+            ("max<f>__Fff_f", "float max<float>(float, float)"),
+            // Operators
+            (
+                "__eq__6VectorCFRC6Vector",
+                "Vector::operator==(const Vector&) const",
+            ),
+            ("__as__6VectorFRC6Vector", "Vector::operator=(const Vector&)"),
+            ("__nw__FUl", "operator new(unsigned long)"),
+            ("__dla__FPv", "operator delete[](void*)"),
+            ("__opb__6VectorCFv", "Vector::operator bool() const"),
+            // Data
+            ("__vt__9CMyObject", "CMyObject::__vt"),
+            ("sInstance__Q23foo3Bar", "foo::Bar::sInstance"),
+        ];
+        for (mangled, expected) in cases {
+            let demangled = demangle(mangled).unwrap_or_else(|| panic!("{mangled} didn't demangle"));
+            assert_eq!(demangled.to_string(), expected, "{mangled}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_names() {
+        let cases = [
+            "",
+            "__",
+            "main",
+            "main__",
+            "update__Q23foo",
+            "update__Q93fooFv",
+            "update__Q03fooFv",
+            "update__99FooFv",
+            "update__3FooF",
+            "update__3FooFP",
+            "update__3FooFA",
+            "update__3FooFA5",
+            "update__3FooFA5_",
+            "update__3FooFM3Foo",
+            "update__3FooFQ2",
+            "update__3FooFiz",
+            "update__3FooFv_",
+            "push__10Stack<int",
+            "update__3FooF4294967296Foo",
+            "update__3FooFé",
+        ];
+        for mangled in cases {
+            assert_eq!(demangle(mangled), None, "{mangled}");
+        }
+
+        // Cutting a name off anywhere mustn't panic, whether or not what's left still means something
+        for mangled in [
+            "__ct__Q23foo3BarFRCQ23foo3Bar",
+            "max<f>__Fff_f",
+            "__opb__6VectorCFv",
+            "f__FPFi_vA4_fM3FooFf_v",
+        ] {
+            for end in 0..mangled.len() {
+                demangle(&mangled[..end]);
+            }
+        }
+    }
+
+    #[test]
+    fn demangles_types() {
+        let cases = [
+            ("i", "int"),
+            ("PCc", "const char*"),
+            ("RQ23foo3Bar", "foo::Bar&"),
+            ("A4_f", "float[4]"),
+            ("PFi_v", "void (*)(int)"),
+            ("M6VectorFf_v", "void (Vector::*)(float)"),
+        ];
+        for (mangled, expected) in cases {
+            let parsed = demangle_type(mangled).unwrap_or_else(|| panic!("{mangled} didn't demangle"));
+            assert_eq!(parsed.to_string(), expected, "{mangled}");
+        }
+        for malformed in ["", "P", "Z", "ii", "A4f", "F_v"] {
+            assert_eq!(demangle_type(malformed), None, "{malformed}");
+        }
+    }
+}
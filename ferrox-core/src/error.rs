@@ -0,0 +1,74 @@
+use std::path::PathBuf;
+
+use orthrus_core::data::DataError;
+use snafu::prelude::*;
+
+#[derive(Debug, Snafu)]
+#[snafu(visibility(pub))]
+pub enum FerroxError {
+    #[snafu(display("Error when reading/writing a data stream: {source}"))]
+    DataError { source: DataError },
+
+    #[snafu(display("\"{name}\" is not a valid name: {reason}"))]
+    InvalidName { name: String, reason: &'static str },
+
+    #[snafu(display("\"{name}\" is already used at 0x{address:08X}"))]
+    NameInUse { name: String, address: u32 },
+
+    #[snafu(display("Can't go to \"{expression}\": {reason}"))]
+    InvalidExpression { expression: String, reason: String },
+
+    #[snafu(display("Can't assemble \"{text}\": {reason}"))]
+    InvalidAssembly { text: String, reason: String },
+
+    #[snafu(display("Can't parse \"{text}\": {reason}"))]
+    InvalidDeclaration { text: String, reason: String },
+
+    #[snafu(display("Can't patch 0x{address:08X}: {reason}"))]
+    InvalidPatch { address: u32, reason: String },
+
+    #[snafu(display("Invalid search: {reason}"))]
+    InvalidSearch { reason: String },
+
+    #[snafu(display("Invalid Ferrox database: {reason}"))]
+    InvalidDatabase { reason: String },
+
+    #[snafu(display("Can't write DOL: {reason}"))]
+    InvalidDol { reason: String },
+
+    #[snafu(display("Invalid REL: {reason}"))]
+    InvalidRel { reason: String },
+
+    #[snafu(display("Can't decompress: {reason}"))]
+    InvalidCompression { reason: String },
+
+    #[snafu(display("Invalid disc image: {reason}"))]
+    InvalidDisc { reason: String },
+
+    #[snafu(display("Invalid file system table: {reason}"))]
+    InvalidFst { reason: String },
+
+    #[snafu(display("Invalid ELF file: {reason}"))]
+    InvalidElf { reason: String },
+
+    #[snafu(display("Invalid archive: {reason}"))]
+    InvalidArchive { reason: String },
+
+    #[snafu(display("Invalid signature file: {reason}"))]
+    InvalidSignatures { reason: String },
+
+    #[snafu(display("Invalid splits on line {line}: {reason}"))]
+    InvalidSplits { line: usize, reason: String },
+
+    #[snafu(display("Validation failed: {reason}"))]
+    Validation { reason: String },
+
+    #[snafu(display("Couldn't access {}: {source}", path.display()))]
+    FileError { path: PathBuf, source: std::io::Error },
+}
+
+impl From<DataError> for FerroxError {
+    fn from(source: DataError) -> Self {
+        FerroxError::DataError { source }
+    }
+}
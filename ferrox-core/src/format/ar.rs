@@ -0,0 +1,78 @@
+use snafu::ensure;
+
+use crate::error::{FerroxError, InvalidArchiveSnafu};
+
+const MAGIC: &[u8; 8] = b"!<arch>\n";
+const HEADER_SIZE: usize = 60;
+
+/// A Unix `ar` archive (`.a`), which is how the SDK and runtime libraries ship. Both the GNU and BSD ways of
+/// storing long names are supported.
+pub struct Archive;
+
+impl Archive {
+    pub fn is_archive(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    /// Returns the name and contents of every member, skipping the symbol index.
+    pub fn members(data: &[u8]) -> Result<Vec<(String, &[u8])>, FerroxError> {
+        ensure!(
+            Self::is_archive(data),
+            InvalidArchiveSnafu { reason: "missing !<arch> header" }
+        );
+
+        let mut members = Vec::new();
+        let mut long_names: &[u8] = &[];
+        let mut offset = MAGIC.len();
+        while offset + HEADER_SIZE <= data.len() {
+            let at = offset;
+            let header = &data[at..at + HEADER_SIZE];
+            ensure!(
+                &header[58..60] == b"`\n",
+                InvalidArchiveSnafu { reason: format!("bad member header at 0x{at:X}") }
+            );
+            let field = |range: core::ops::Range<usize>| {
+                String::from_utf8_lossy(&header[range]).trim_end().to_owned()
+            };
+            let name = field(0..16);
+            let size: usize = field(48..58).parse().map_err(|_| FerroxError::InvalidArchive {
+                reason: format!("bad member size at 0x{at:X}"),
+            })?;
+            let start = at + HEADER_SIZE;
+            let mut contents = data.get(start..start + size).ok_or_else(|| FerroxError::InvalidArchive {
+                reason: format!("member at 0x{at:X} runs past the end"),
+            })?;
+            // Members are aligned to 2 bytes
+            offset = start + size + size % 2;
+
+            let name = match name.as_str() {
+                // Symbol index, GNU and BSD
+                "/" | "/SYM64/" | "__.SYMDEF" | "__.SYMDEF SORTED" => continue,
+                "//" => {
+                    long_names = contents;
+                    continue;
+                }
+                name if name.starts_with("#1/") => {
+                    // BSD puts long names right before the contents
+                    let length: usize = name[3..].parse().map_err(|_| FerroxError::InvalidArchive {
+                        reason: format!("bad name length at 0x{at:X}"),
+                    })?;
+                    let (name, rest) = contents.split_at(length.min(contents.len()));
+                    contents = rest;
+                    String::from_utf8_lossy(name).trim_end_matches('\0').to_owned()
+                }
+                name if name.starts_with('/') => {
+                    let index: usize = name[1..].parse().map_err(|_| FerroxError::InvalidArchive {
+                        reason: format!("bad long name \"{name}\""),
+                    })?;
+                    let name = long_names.get(index..).unwrap_or_default();
+                    let end = name.iter().position(|&byte| byte == b'\n').unwrap_or(name.len());
+                    String::from_utf8_lossy(&name[..end]).trim_end_matches('/').to_owned()
+                }
+                name => name.trim_end_matches('/').to_owned(),
+            };
+            members.push((name, contents));
+        }
+        Ok(members)
+    }
+}
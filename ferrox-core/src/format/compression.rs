@@ -0,0 +1,336 @@
+use snafu::ensure;
+
+use crate::error::{FerroxError, InvalidCompressionSnafu};
+
+/// Nintendo's LZ77 variants, which games use for RELs, archives and most other data files. Both start with a
+/// 4 byte magic and the decompressed size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Yaz0,
+    Yay0,
+}
+
+impl Compression {
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        match data.get(..4)? {
+            b"Yaz0" => Some(Compression::Yaz0),
+            b"Yay0" => Some(Compression::Yay0),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Yaz0 => "Yaz0",
+            Compression::Yay0 => "Yay0",
+        }
+    }
+
+    pub fn decompress(self, data: &[u8]) -> Result<Vec<u8>, FerroxError> {
+        ensure!(
+            data.len() >= 16,
+            InvalidCompressionSnafu { reason: format!("{} header is cut off", self.name()) }
+        );
+        let word =
+            |at: usize| data.get(at..at + 4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()));
+        let size = word(4).unwrap() as usize;
+        let decompressed = match self {
+            Compression::Yaz0 => yaz0(data, size),
+            Compression::Yay0 => yay0(data, size, word(8).unwrap() as usize, word(12).unwrap() as usize),
+        };
+        decompressed.ok_or_else(|| FerroxError::InvalidCompression {
+            reason: format!("{} data runs out before 0x{size:X} bytes", self.name()),
+        })
+    }
+}
+
+/// Decompresses `data` if it starts with either magic, saying which one it was. Anything else comes back
+/// untouched.
+pub fn decompress(data: Vec<u8>) -> Result<(Vec<u8>, Option<Compression>), FerroxError> {
+    match Compression::detect(&data) {
+        Some(compression) => Ok((compression.decompress(&data)?, Some(compression))),
+        None => Ok((data, None)),
+    }
+}
+
+/// Somewhere to decompress `size` bytes into. The size comes from the header, which could be anything in a
+/// corrupt file, so only as much is reserved up front as the data could reasonably expand to.
+fn output(data: &[u8], size: usize) -> Vec<u8> {
+    Vec::with_capacity(size.min(data.len().saturating_mul(8)))
+}
+
+/// Copies a run of `length` bytes from `distance` back. Runs can overlap what they're writing, which repeats
+/// the last `distance` bytes, so those have to go a byte at a time.
+fn copy_back(output: &mut Vec<u8>, distance: usize, length: usize) -> Option<()> {
+    let start = output.len().checked_sub(distance)?;
+    if distance >= length {
+        output.extend_from_within(start..start + length);
+    } else {
+        for index in start..start + length {
+            output.push(output[index]);
+        }
+    }
+    Some(())
+}
+
+/// Yaz0 keeps everything in one stream: a byte of flags, then for each of its bits (highest first) either a
+/// literal byte or a back reference. References are two bytes holding the length minus 2 and the distance
+/// minus 1, with a third byte for the length minus 0x12 when the first nibble is 0.
+fn yaz0(data: &[u8], size: usize) -> Option<Vec<u8>> {
+    let mut output = output(data, size);
+    let mut input = data.get(16..)?.iter().copied();
+    while output.len() < size {
+        let flags = input.next()?;
+        for bit in (0..8).rev() {
+            if output.len() >= size {
+                break;
+            }
+            if flags & (1 << bit) != 0 {
+                output.push(input.next()?);
+                continue;
+            }
+            let (first, second) = (input.next()?, input.next()?);
+            let distance = ((usize::from(first) & 0xF) << 8 | usize::from(second)) + 1;
+            let length = match first >> 4 {
+                0 => usize::from(input.next()?) + 0x12,
+                length => usize::from(length) + 2,
+            };
+            let length = length.min(size - output.len());
+            copy_back(&mut output, distance, length)?;
+        }
+    }
+    Some(output)
+}
+
+/// Yay0 splits the same thing into three streams: 32 bit words of flags from 0x10, halfword back references
+/// from `links`, and literal bytes (plus the extra length bytes) from `chunks`.
+fn yay0(data: &[u8], size: usize, links: usize, chunks: usize) -> Option<Vec<u8>> {
+    let mut output = output(data, size);
+    let (mut flags_at, mut links_at, mut chunks_at) = (16, links, chunks);
+    let mut next_chunk = || {
+        let byte = data.get(chunks_at).copied();
+        chunks_at += 1;
+        byte
+    };
+    while output.len() < size {
+        let flags = u32::from_be_bytes(data.get(flags_at..flags_at + 4)?.try_into().unwrap());
+        flags_at += 4;
+        for bit in (0..32).rev() {
+            if output.len() >= size {
+                break;
+            }
+            if flags & (1 << bit) != 0 {
+                output.push(next_chunk()?);
+                continue;
+            }
+            let link = u16::from_be_bytes(data.get(links_at..links_at + 2)?.try_into().unwrap());
+            links_at += 2;
+            let distance = usize::from(link & 0xFFF) + 1;
+            let length = match link >> 12 {
+                0 => usize::from(next_chunk()?) + 0x12,
+                length => usize::from(length) + 2,
+            };
+            let length = length.min(size - output.len());
+            copy_back(&mut output, distance, length)?;
+        }
+    }
+    Some(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    enum Token {
+        Literal(u8),
+        /// Distance back, then length
+        Copy(usize, usize),
+    }
+    use Token::*;
+
+    fn header(magic: &[u8], size: usize) -> Vec<u8> {
+        let mut data = magic.to_vec();
+        data.extend_from_slice(&(size as u32).to_be_bytes());
+        data.resize(16, 0);
+        data
+    }
+
+    /// A reference as Yaz0 and Yay0 both write it, with the length in the top nibble unless it needs a byte
+    /// of its own.
+    fn reference(distance: usize, length: usize) -> (u16, Option<u8>) {
+        assert!((3..=0x111).contains(&length) && (1..=0x1000).contains(&distance));
+        let distance = (distance - 1) as u16;
+        match length {
+            0x12.. => (distance, Some((length - 0x12) as u8)),
+            _ => ((length as u16 - 2) << 12 | distance, None),
+        }
+    }
+
+    fn yaz0(size: usize, tokens: &[Token]) -> Vec<u8> {
+        let mut data = header(b"Yaz0", size);
+        for group in tokens.chunks(8) {
+            let flags = data.len();
+            data.push(0);
+            for (n, token) in group.iter().enumerate() {
+                match *token {
+                    Literal(byte) => {
+                        data[flags] |= 0x80 >> n;
+                        data.push(byte);
+                    }
+                    Copy(distance, length) => {
+                        let (link, extra) = reference(distance, length);
+                        data.extend_from_slice(&link.to_be_bytes());
+                        data.extend(extra);
+                    }
+                }
+            }
+        }
+        data
+    }
+
+    fn yay0(size: usize, tokens: &[Token]) -> Vec<u8> {
+        let (mut flags, mut links, mut chunks) = (Vec::new(), Vec::new(), Vec::new());
+        for group in tokens.chunks(32) {
+            let mut word = 0u32;
+            for (n, token) in group.iter().enumerate() {
+                match *token {
+                    Literal(byte) => {
+                        word |= 0x8000_0000 >> n;
+                        chunks.push(byte);
+                    }
+                    Copy(distance, length) => {
+                        let (link, extra) = reference(distance, length);
+                        links.extend_from_slice(&link.to_be_bytes());
+                        chunks.extend(extra);
+                    }
+                }
+            }
+            flags.extend_from_slice(&word.to_be_bytes());
+        }
+        let mut data = header(b"Yay0", size);
+        let links_at = 16 + flags.len();
+        data[8..12].copy_from_slice(&(links_at as u32).to_be_bytes());
+        data[12..16].copy_from_slice(&((links_at + links.len()) as u32).to_be_bytes());
+        data.extend(flags);
+        data.extend(links);
+        data.extend(chunks);
+        data
+    }
+
+    /// Encodes `tokens` both ways, and checks both decompress to `expected`.
+    fn check(tokens: &[Token], expected: &[u8]) {
+        for data in [yaz0(expected.len(), tokens), yay0(expected.len(), tokens)] {
+            let (decompressed, compression) = decompress(data).unwrap();
+            assert!(compression.is_some());
+            assert_eq!(decompressed, expected);
+        }
+    }
+
+    #[test]
+    fn decompresses_literals() {
+        let tokens: Vec<Token> =
+            b"Hello, world! This takes more than one flag byte.".iter().map(|&byte| Literal(byte)).collect();
+        check(&tokens, b"Hello, world! This takes more than one flag byte.");
+        check(&[], b"");
+    }
+
+    /// What `tokens` decompress to, copying a byte at a time.
+    fn expand(tokens: &[Token]) -> Vec<u8> {
+        let mut output = Vec::new();
+        for token in tokens {
+            match *token {
+                Literal(byte) => output.push(byte),
+                Copy(distance, length) => {
+                    for _ in 0..length {
+                        output.push(output[output.len() - distance]);
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn decompresses_short_references() {
+        let tokens = [Literal(b'a'), Literal(b'b'), Literal(b'c'), Copy(3, 3)];
+        assert_eq!(yaz0(6, &tokens), b"Yaz0\0\0\0\x06\0\0\0\0\0\0\0\0\xE0abc\x10\x02");
+        assert_eq!(
+            yay0(6, &tokens),
+            b"Yay0\0\0\0\x06\0\0\0\x14\0\0\0\x16\xE0\0\0\0\x10\x02abc"
+        );
+        check(&tokens, b"abcabc");
+        let tokens = [
+            Literal(b'a'),
+            Literal(b'b'),
+            Literal(b'c'),
+            Copy(3, 3),
+            Literal(b'd'),
+            Copy(4, 0x11),
+        ];
+        check(&tokens, b"abcabcdabcdabcdabcdabcd");
+    }
+
+    #[test]
+    fn decompresses_long_references() {
+        // The shortest and longest a reference can be, from as far back as it can reach
+        let mut tokens: Vec<Token> = (0..0x1000).map(|n| Literal(n as u8 ^ (n >> 8) as u8)).collect();
+        tokens.extend([Copy(0x1000, 0x12), Copy(0x1000, 0x111), Copy(0x80, 0x30)]);
+        check(&tokens, &expand(&tokens));
+    }
+
+    #[test]
+    fn decompresses_overlapping_copies() {
+        check(&[Literal(b'a'), Literal(b'b'), Copy(2, 6)], b"abababab");
+        check(&[Literal(b'x'), Copy(1, 0x111)], &[b'x'; 0x112]);
+    }
+
+    #[test]
+    fn stops_at_the_size() {
+        // The last reference is cut short once the output is full
+        let data = yaz0(5, &[Literal(b'a'), Copy(1, 10)]);
+        assert_eq!(Compression::Yaz0.decompress(&data).unwrap(), b"aaaaa");
+        let data = yay0(5, &[Literal(b'a'), Copy(1, 10)]);
+        assert_eq!(Compression::Yay0.decompress(&data).unwrap(), b"aaaaa");
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let tokens = [Literal(b'a'), Literal(b'b'), Copy(2, 0x20), Literal(b'c')];
+        for (compression, data) in [
+            (Compression::Yaz0, yaz0(0x23, &tokens)),
+            (Compression::Yay0, yay0(0x23, &tokens)),
+        ] {
+            for end in 0..data.len() {
+                assert!(
+                    compression.decompress(&data[..end]).is_err(),
+                    "{} cut off at {end}",
+                    compression.name()
+                );
+            }
+            assert!(compression.decompress(&data).is_ok());
+        }
+    }
+
+    #[test]
+    fn rejects_references_before_the_start() {
+        let data = yaz0(4, &[Literal(b'a'), Copy(2, 3)]);
+        assert!(Compression::Yaz0.decompress(&data).is_err());
+        let data = yay0(4, &[Literal(b'a'), Copy(2, 3)]);
+        assert!(Compression::Yay0.decompress(&data).is_err());
+    }
+
+    #[test]
+    fn doesnt_trust_the_header_size() {
+        // Without a cap on what's reserved, this would try to allocate 4 GiB before finding the data runs out
+        let data = yaz0(u32::MAX as usize, &[Literal(b'a'), Copy(1, 0x111)]);
+        assert!(Compression::Yaz0.decompress(&data).is_err());
+        let data = yay0(u32::MAX as usize, &[Literal(b'a'), Copy(1, 0x111)]);
+        assert!(Compression::Yay0.decompress(&data).is_err());
+    }
+
+    #[test]
+    fn leaves_uncompressed_data_alone() {
+        assert_eq!(decompress(b"RARC".to_vec()).unwrap(), (b"RARC".to_vec(), None));
+        assert_eq!(decompress(Vec::new()).unwrap(), (Vec::new(), None));
+    }
+}
@@ -0,0 +1,490 @@
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use aes::cipher::{BlockDecrypt, KeyInit};
+use aes::Aes128;
+use orthrus_core::prelude::*;
+use snafu::{ensure, OptionExt};
+
+use super::dol::{DolBinary, HEADER_SIZE as DOL_HEADER_SIZE};
+use super::{fst, Entry};
+use crate::error::{FerroxError, InvalidDiscSnafu};
+
+const GAMECUBE_MAGIC: u32 = 0xC2339F3D;
+const WII_MAGIC: u32 = 0x5D1C9EA3;
+/// `boot.bin` is the disc header, and `bi2.bin` straight after it holds settings for the debug monitor
+const BOOT_SIZE: u32 = 0x440;
+const BI2_SIZE: u32 = 0x2000;
+const APPLOADER_OFFSET: u32 = BOOT_SIZE + BI2_SIZE;
+const APPLOADER_HEADER_SIZE: u32 = 0x20;
+/// Where Wii discs list their partitions: four groups, each a count and the offset of their entries
+const PARTITION_TABLE: u64 = 0x40000;
+/// Wii partitions are stored as clusters of hashes followed by the data they cover, encrypted separately
+const CLUSTER_SIZE: u64 = 0x8000;
+const CLUSTER_HASHES_SIZE: u64 = 0x400;
+const CLUSTER_DATA_SIZE: u64 = CLUSTER_SIZE - CLUSTER_HASHES_SIZE;
+
+/// What the disc header says about the game.
+#[derive(Debug, Clone)]
+pub struct DiscHeader {
+    /// Like `GALE`, the first four characters of the game ID
+    pub game_code: String,
+    pub maker_code: String,
+    pub disc_number: u8,
+    pub version: u8,
+    pub title: String,
+    /// Where `main.dol` and the FST are, inside the game partition on Wii discs
+    pub dol_offset: u64,
+    pub fst_offset: u64,
+    pub fst_size: u64,
+}
+
+/// The apploader, which the IPL runs to load the DOL and FST into memory.
+#[derive(Debug, Clone)]
+pub struct Apploader {
+    /// Build date, like `2001/11/14`
+    pub date: String,
+    pub entry_point: u32,
+    pub size: u32,
+    pub trailer_size: u32,
+}
+
+/// The keys Wii title keys are encrypted with, in the order tickets pick them by: the common key and the Korean
+/// one. They can't be shipped, so they're read from files dumped from a console.
+#[derive(Debug, Clone, Default)]
+pub struct CommonKeys(pub [Option<[u8; 16]>; 2]);
+
+impl CommonKeys {
+    const FILE_NAMES: [&'static str; 2] = ["common-key.bin", "korean-key.bin"];
+
+    /// Loads the keys from the `keys` folder, either next to the executable or in the working directory.
+    pub fn load_default() -> Self {
+        let mut directories = vec![PathBuf::from("keys")];
+        if let Some(directory) =
+            std::env::current_exe().ok().and_then(|path| path.parent().map(|dir| dir.join("keys")))
+        {
+            directories.push(directory);
+        }
+        let mut keys = Self::default();
+        for (key, name) in keys.0.iter_mut().zip(Self::FILE_NAMES) {
+            *key = directories
+                .iter()
+                .find_map(|directory| std::fs::read(directory.join(name)).ok()?.try_into().ok());
+        }
+        keys
+    }
+}
+
+/// The game partition of a Wii disc, which is decrypted a cluster at a time as it's read.
+#[derive(Debug, Clone)]
+pub struct WiiPartition {
+    /// Where its first cluster is in the image
+    pub data_offset: u64,
+    /// Size once decrypted, without the hashes
+    pub size: u64,
+    title_key: [u8; 16],
+}
+
+impl WiiPartition {
+    /// Finds the game partition, decrypting its title key with whichever common key its ticket asks for.
+    fn find(data: &[u8], keys: &CommonKeys) -> Result<Self, FerroxError> {
+        let mut cursor = DataCursorRef::new(data, Endian::Big);
+        let mut partition = None;
+        for group in 0..4 {
+            cursor.set_position(PARTITION_TABLE + group * 8)?;
+            let count = cursor.read_u32()?;
+            let table = u64::from(cursor.read_u32()?) << 2;
+            for index in 0..u64::from(count) {
+                cursor.set_position(table + index * 8)?;
+                let offset = u64::from(cursor.read_u32()?) << 2;
+                // The game itself is in the data partition, the others only hold updates and channels
+                if cursor.read_u32()? == 0 {
+                    partition = partition.or(Some(offset));
+                }
+            }
+        }
+        let partition = partition.context(InvalidDiscSnafu { reason: "there's no game partition" })?;
+
+        // The ticket at the start of the partition holds the title key, encrypted with the title ID as the IV
+        let ticket = usize::try_from(partition)
+            .ok()
+            .and_then(|start| data.get(start..start.checked_add(0x2C0)?))
+            .context(InvalidDiscSnafu { reason: "the game partition is past the end of the image" })?;
+        let index = ticket[0x1F1] as usize;
+        let name = CommonKeys::FILE_NAMES.get(index).context(InvalidDiscSnafu {
+            reason: format!("the ticket uses common key {index}, which isn't for discs"),
+        })?;
+        let common_key = keys.0[index].context(InvalidDiscSnafu {
+            reason: format!("Wii discs are encrypted, put {name} dumped from a console in the keys folder"),
+        })?;
+        let mut iv = [0; 16];
+        iv[..8].copy_from_slice(&ticket[0x1DC..0x1E4]);
+        let title_key = decrypt(&common_key, iv, &ticket[0x1BF..0x1CF]).try_into().unwrap();
+
+        let word = |at: usize| u64::from(u32::from_be_bytes(ticket[at..at + 4].try_into().unwrap())) << 2;
+        let clusters = word(0x2BC) / CLUSTER_SIZE;
+        Ok(Self {
+            data_offset: partition + word(0x2B8),
+            size: clusters * CLUSTER_DATA_SIZE,
+            title_key,
+        })
+    }
+
+    /// Reads `size` bytes from `offset` in the decrypted partition.
+    pub fn read(&self, image: &[u8], offset: u64, size: u64) -> Option<Vec<u8>> {
+        let end = offset.checked_add(size).filter(|&end| end <= self.size)?;
+        let mut output = Vec::with_capacity(size as usize);
+        let mut position = offset;
+        while position < end {
+            let (cluster, within) = (position / CLUSTER_DATA_SIZE, position % CLUSTER_DATA_SIZE);
+            let start = usize::try_from(self.data_offset + cluster * CLUSTER_SIZE).ok()?;
+            let cluster = image.get(start..start.checked_add(CLUSTER_SIZE as usize)?)?;
+            // Data is encrypted with the end of the encrypted hashes as its IV
+            let iv = cluster[0x3D0..0x3E0].try_into().unwrap();
+            let data = decrypt(&self.title_key, iv, &cluster[CLUSTER_HASHES_SIZE as usize..]);
+            let length = (CLUSTER_DATA_SIZE - within).min(end - position);
+            output.extend_from_slice(&data[within as usize..(within + length) as usize]);
+            position += length;
+        }
+        Some(output)
+    }
+}
+
+/// Decrypts AES-128-CBC, which is what everything on Wii discs is encrypted with.
+fn decrypt(key: &[u8; 16], iv: [u8; 16], data: &[u8]) -> Vec<u8> {
+    let cipher = Aes128::new(key.into());
+    let mut previous = iv;
+    let mut output = Vec::with_capacity(data.len());
+    for block in data.chunks_exact(16) {
+        let mut decrypted = aes::Block::clone_from_slice(block);
+        cipher.decrypt_block(&mut decrypted);
+        output.extend(decrypted.iter().zip(previous).map(|(byte, iv)| byte ^ iv));
+        previous.copy_from_slice(block);
+    }
+    output
+}
+
+/// A GameCube (`.gcm`/`.iso`) or Wii disc image. Its files are laid out the way Dolphin extracts them: what
+/// the system needs to boot under `sys/`, and everything in the FST under `files/`. Only the game partition of
+/// Wii discs is shown, since the others are system updates.
+#[derive(Debug, Clone)]
+pub struct Disc {
+    pub header: DiscHeader,
+    pub apploader: Apploader,
+    pub root: Vec<Entry>,
+    /// Where the files are on Wii discs, which their offsets are relative to
+    pub partition: Option<WiiPartition>,
+    /// Anything wrong with the image that didn't stop the rest of it from being read
+    pub warnings: Vec<String>,
+}
+
+impl Disc {
+    pub fn is_disc(data: &[u8]) -> bool {
+        let magic =
+            |at: usize| data.get(at..at + 4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()));
+        magic(0x1C) == Some(GAMECUBE_MAGIC) || magic(0x18) == Some(WII_MAGIC)
+    }
+
+    /// Reads a disc image, using the common keys in the `keys` folder for Wii discs.
+    pub fn parse(data: &[u8]) -> Result<Self, FerroxError> {
+        let is_wii = data.get(0x18..0x1C).is_some_and(|magic| magic == WII_MAGIC.to_be_bytes());
+        let keys = if is_wii {
+            CommonKeys::load_default()
+        } else {
+            CommonKeys::default()
+        };
+        Self::parse_with_keys(data, &keys)
+    }
+
+    pub fn parse_with_keys(data: &[u8], keys: &CommonKeys) -> Result<Self, FerroxError> {
+        let mut cursor = DataCursorRef::new(data, Endian::Big);
+        cursor.set_position(0x18)?;
+        let partition = match cursor.read_u32()? {
+            WII_MAGIC => Some(WiiPartition::find(data, keys)?),
+            _ => {
+                ensure!(
+                    cursor.read_u32()? == GAMECUBE_MAGIC,
+                    InvalidDiscSnafu { reason: "missing GameCube disc magic" }
+                );
+                None
+            }
+        };
+        // Wii discs store offsets divided by 4 so they can go past 4 GiB
+        let shift = if partition.is_some() { 2 } else { 0 };
+        let size = partition.as_ref().map_or(data.len() as u64, |partition| partition.size);
+        let read = |offset: u64, size: u64| read_image(data, partition.as_ref(), offset, size);
+
+        let boot = read(0, u64::from(APPLOADER_OFFSET + APPLOADER_HEADER_SIZE))
+            .context(InvalidDiscSnafu { reason: "too short to hold the disc header and apploader" })?;
+        let text = |range: std::ops::Range<usize>| {
+            let bytes = &boot[range];
+            let end = bytes.iter().position(|&byte| byte == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..end]).trim().to_owned()
+        };
+        let mut cursor = DataCursorRef::new(&boot, Endian::Big);
+        cursor.set_position(0x420)?;
+        let header = DiscHeader {
+            game_code: text(0..4),
+            maker_code: text(4..6),
+            disc_number: boot[6],
+            version: boot[7],
+            title: text(0x20..0x400),
+            dol_offset: u64::from(cursor.read_u32()?) << shift,
+            fst_offset: u64::from(cursor.read_u32()?) << shift,
+            fst_size: u64::from(cursor.read_u32()?) << shift,
+        };
+
+        cursor.set_position(u64::from(APPLOADER_OFFSET + 0x10))?;
+        let apploader = Apploader {
+            date: text(APPLOADER_OFFSET as usize..APPLOADER_OFFSET as usize + 0x10),
+            entry_point: cursor.read_u32()?,
+            size: cursor.read_u32()?,
+            trailer_size: cursor.read_u32()?,
+        };
+
+        let mut warnings = Vec::new();
+        let mut system = vec![
+            file("boot.bin", 0, BOOT_SIZE),
+            file("bi2.bin", BOOT_SIZE, BI2_SIZE),
+            file(
+                "apploader.img",
+                APPLOADER_OFFSET,
+                APPLOADER_HEADER_SIZE.saturating_add(apploader.size).saturating_add(apploader.trailer_size),
+            ),
+        ];
+        // A broken DOL still leaves everything else readable, so it's only left out
+        let dol = match u32::try_from(header.dol_offset) {
+            Ok(offset) if header.dol_offset < size => {
+                let length = u64::from(DOL_HEADER_SIZE).min(size - header.dol_offset);
+                read(header.dol_offset, length).map(|dol| (offset, dol))
+            }
+            _ => None,
+        };
+        match dol {
+            None => warnings.push("main.dol offset past end of image".to_owned()),
+            Some((offset, dol)) => match DolBinary::file_size(&dol) {
+                Ok(dol_size) => system.push(file("main.dol", offset, dol_size)),
+                Err(error) => warnings.push(format!("main.dol is invalid: {error}")),
+            },
+        }
+
+        let (Ok(fst_offset), Ok(fst_size), Some(fst)) = (
+            u32::try_from(header.fst_offset),
+            u32::try_from(header.fst_size),
+            read(header.fst_offset, header.fst_size),
+        ) else {
+            return InvalidDiscSnafu { reason: "FST runs past the end of the disc" }.fail();
+        };
+        system.push(file("fst.bin", fst_offset, fst_size));
+        let mut files = fst::parse(&fst)?;
+        if shift != 0 {
+            let mut skipped = 0;
+            files = shift_offsets(files, shift, &mut skipped);
+            if skipped > 0 {
+                warnings.push(format!(
+                    "{skipped} files are past 4 GiB into the partition, which isn't supported"
+                ));
+            }
+        }
+
+        let root = vec![
+            Entry::Directory { name: "sys".to_owned(), children: system },
+            Entry::Directory { name: "files".to_owned(), children: files },
+        ];
+        Ok(Self { header, apploader, root, partition, warnings })
+    }
+
+    /// Contents of a file, decrypting it first on Wii discs. `image` is the whole disc image.
+    pub fn contents<'a>(&self, image: &'a [u8], entry: &Entry) -> Option<Cow<'a, [u8]>> {
+        match (entry, &self.partition) {
+            (Entry::File { offset, size, .. }, Some(partition)) => {
+                partition.read(image, u64::from(*offset), u64::from(*size)).map(Cow::Owned)
+            }
+            _ => entry.contents(image).map(Cow::Borrowed),
+        }
+    }
+}
+
+fn file(name: &str, offset: u32, size: u32) -> Entry {
+    Entry::File { name: name.to_owned(), offset, size }
+}
+
+/// Reads from a GameCube disc, or the decrypted game partition of a Wii disc.
+fn read_image<'a>(
+    image: &'a [u8], partition: Option<&WiiPartition>, offset: u64, size: u64,
+) -> Option<Cow<'a, [u8]>> {
+    match partition {
+        Some(partition) => partition.read(image, offset, size).map(Cow::Owned),
+        None => {
+            let start = usize::try_from(offset).ok()?;
+            image.get(start..start.checked_add(usize::try_from(size).ok()?)?).map(Cow::Borrowed)
+        }
+    }
+}
+
+/// Multiplies out the file offsets of a Wii FST, leaving out any that don't fit in 32 bits.
+fn shift_offsets(entries: Vec<Entry>, shift: u32, skipped: &mut usize) -> Vec<Entry> {
+    entries
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Entry::File { name, offset, size } => {
+                let offset = u32::try_from(u64::from(offset) << shift).ok();
+                *skipped += usize::from(offset.is_none());
+                Some(Entry::File { name, offset: offset?, size })
+            }
+            Entry::Directory { name, children } => {
+                Some(Entry::Directory { name, children: shift_offsets(children, shift, skipped) })
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use aes::cipher::BlockEncrypt;
+
+    use super::*;
+    use crate::testing::{self, TEXT};
+
+    const COMMON_KEY: [u8; 16] = *b"test common key!";
+    const TITLE_KEY: [u8; 16] = *b"test title key!!";
+    /// Where the test image's DOL goes
+    const DOL: u32 = 0x2480;
+    /// A file that straddles the first two clusters of a Wii partition
+    const BIG: u32 = 0x7BF0;
+
+    /// Lays out the system files and FST the way a GameCube disc or the game partition of a Wii disc has them.
+    /// Offsets in the header and FST are divided by `1 << shift`.
+    fn layout(dol_offset: u32, shift: u32) -> Vec<u8> {
+        let mut image = vec![0; 0x8000];
+        image[..6].copy_from_slice(b"GALE01");
+        image[0x1C..0x20].copy_from_slice(&GAMECUBE_MAGIC.to_be_bytes());
+        image[0x20..0x2A].copy_from_slice(b"Test Title");
+        let apploader = APPLOADER_OFFSET as usize;
+        image[apploader..apploader + 10].copy_from_slice(b"2001/11/14");
+
+        let dol = testing::dol(&testing::assemble("blr", TEXT), &[], 0);
+        image[DOL as usize..DOL as usize + dol.len()].copy_from_slice(&dol);
+        image[0x2E00..0x2E04].copy_from_slice(b"bnr!");
+        image[0x2E10..0x2E14].copy_from_slice(b"rel!");
+        for (index, byte) in image[BIG as usize..BIG as usize + 0x20].iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        let mut fst = testing::fst(&[
+            (false, "opening.bnr", 0x2E00 >> shift, 4),
+            (true, "RELS", 0, 4),
+            (false, "a.rel", 0x2E10 >> shift, 4),
+            (false, "big.bin", BIG >> shift, 0x20),
+        ]);
+        fst.resize(fst.len().next_multiple_of(4), 0);
+        image[0x2C00..0x2C00 + fst.len()].copy_from_slice(&fst);
+
+        for (at, value) in [(0x420, dol_offset), (0x424, 0x2C00), (0x428, fst.len() as u32)] {
+            image[at..at + 4].copy_from_slice(&(value >> shift).to_be_bytes());
+        }
+        image
+    }
+
+    fn encrypt(key: &[u8; 16], iv: &[u8], data: &[u8]) -> Vec<u8> {
+        let cipher = Aes128::new(key.into());
+        let mut previous: [u8; 16] = iv.try_into().unwrap();
+        let mut output = Vec::new();
+        for block in data.chunks_exact(16) {
+            let mut block = aes::Block::clone_from_slice(block);
+            block.iter_mut().zip(previous).for_each(|(byte, iv)| *byte ^= iv);
+            cipher.encrypt_block(&mut block);
+            previous.copy_from_slice(&block);
+            output.extend_from_slice(&block);
+        }
+        output
+    }
+
+    /// A Wii disc with `partition` as its game partition, encrypted the same way retail discs are.
+    fn wii(partition: &[u8]) -> Vec<u8> {
+        const PARTITION: usize = 0x50000;
+        const DATA: usize = 0x20000;
+        let clusters = partition.len().div_ceil(CLUSTER_DATA_SIZE as usize);
+        let mut image = vec![0; PARTITION + DATA + clusters * CLUSTER_SIZE as usize];
+        image[..6].copy_from_slice(b"RABC01");
+        image[0x18..0x1C].copy_from_slice(&WII_MAGIC.to_be_bytes());
+        let mut word = |at: usize, value: u32| image[at..at + 4].copy_from_slice(&value.to_be_bytes());
+        word(0x40000, 1);
+        word(0x40004, 0x40020 >> 2);
+        word(0x40020, (PARTITION >> 2) as u32);
+        word(PARTITION + 0x2B8, (DATA >> 2) as u32);
+        word(PARTITION + 0x2BC, (clusters as u32 * CLUSTER_SIZE as u32) >> 2);
+
+        let ticket = &mut image[PARTITION..];
+        ticket[0x1DC..0x1E4].copy_from_slice(b"\0\x01\0\0RABC");
+        let iv = [&ticket[0x1DC..0x1E4], &[0; 8]].concat();
+        ticket[0x1BF..0x1CF].copy_from_slice(&encrypt(&COMMON_KEY, &iv, &TITLE_KEY));
+
+        for (index, data) in partition.chunks(CLUSTER_DATA_SIZE as usize).enumerate() {
+            let start = PARTITION + DATA + index * CLUSTER_SIZE as usize;
+            let cluster = &mut image[start..start + CLUSTER_SIZE as usize];
+            // The hashes aren't checked, so anything will do as long as the IV is taken from them
+            cluster[..CLUSTER_HASHES_SIZE as usize].fill(index as u8 + 1);
+            let mut data = data.to_vec();
+            data.resize(CLUSTER_DATA_SIZE as usize, 0);
+            let encrypted = encrypt(&TITLE_KEY, &cluster[0x3D0..0x3E0], &data);
+            cluster[CLUSTER_HASHES_SIZE as usize..].copy_from_slice(&encrypted);
+        }
+        image
+    }
+
+    fn contents(disc: &Disc, image: &[u8], path: &str) -> Option<Vec<u8>> {
+        Some(disc.contents(image, Entry::find(&disc.root, path)?)?.into_owned())
+    }
+
+    #[test]
+    fn reads_gamecube_discs() {
+        let image = layout(DOL, 0);
+        let disc = Disc::parse(&image).unwrap();
+        assert_eq!(disc.header.game_code, "GALE");
+        assert_eq!(disc.header.title, "Test Title");
+        assert_eq!(disc.apploader.date, "2001/11/14");
+        assert!(disc.warnings.is_empty(), "{:?}", disc.warnings);
+        let dol = contents(&disc, &image, "sys/main.dol").unwrap();
+        assert_eq!(
+            DolBinary::entry_point(&dol).unwrap(),
+            DolBinary::entry_point(&image[DOL as usize..]).unwrap()
+        );
+        assert_eq!(contents(&disc, &image, "files/RELS/a.rel").unwrap(), b"rel!");
+    }
+
+    #[test]
+    fn keeps_the_files_when_main_dol_is_broken() {
+        let image = layout(0x10_0000, 0);
+        let disc = Disc::parse(&image).unwrap();
+        assert_eq!(disc.warnings, ["main.dol offset past end of image"]);
+        assert!(Entry::find(&disc.root, "sys/main.dol").is_none());
+        assert_eq!(contents(&disc, &image, "files/opening.bnr").unwrap(), b"bnr!");
+
+        // Somewhere inside the image that's too short to be a DOL
+        let image = layout(0x8000 - 0x10, 0);
+        let disc = Disc::parse(&image).unwrap();
+        assert!(
+            disc.warnings[0].starts_with("main.dol is invalid"),
+            "{:?}",
+            disc.warnings
+        );
+        assert!(Entry::find(&disc.root, "files/RELS/a.rel").is_some());
+    }
+
+    #[test]
+    fn decrypts_wii_discs() {
+        let image = wii(&layout(DOL, 2));
+        let keys = CommonKeys([Some(COMMON_KEY), None]);
+        let disc = Disc::parse_with_keys(&image, &keys).unwrap();
+        assert!(disc.warnings.is_empty(), "{:?}", disc.warnings);
+        assert_eq!(disc.header.game_code, "GALE");
+        assert_eq!(disc.header.dol_offset, u64::from(DOL));
+        assert!(contents(&disc, &image, "sys/main.dol").is_some());
+        assert_eq!(contents(&disc, &image, "files/RELS/a.rel").unwrap(), b"rel!");
+        let big: Vec<u8> = (0..0x20).collect();
+        assert_eq!(contents(&disc, &image, "files/big.bin").unwrap(), big);
+
+        let error = Disc::parse_with_keys(&image, &CommonKeys::default()).unwrap_err();
+        assert!(error.to_string().contains("common-key.bin"), "{error}");
+    }
+}
@@ -0,0 +1,487 @@
+use std::collections::BTreeSet;
+
+use orthrus_core::prelude::*;
+use snafu::ensure;
+
+use super::{Permissions, Segment};
+use crate::error::{FerroxError, InvalidDolSnafu};
+
+pub(crate) const HEADER_SIZE: u32 = 0x100;
+const TEXT_SLOTS: usize = 7;
+const DATA_SLOTS: usize = 11;
+/// Segments have to be loaded somewhere in the 24MB of main memory
+const MEMORY: std::ops::Range<u32> = 0x80000000..0x81800000;
+
+pub struct DolBinary;
+
+impl DolBinary {
+    /// Reads the segments out of a DOL's header, checking that each one is in the file and in main memory.
+    pub fn segments(data: &[u8]) -> Result<Vec<Segment<u32>>, FerroxError> {
+        let file_size = data.len();
+        let mut data = DataCursorRef::new(data, Endian::Big);
+        // Maximum we can have is 18 segments (7 text, 11 data), with bss overlapping the entire range
+        let mut segments = Vec::with_capacity(37);
+
+        let mut offsets = [0u32; 18];
+        for offset in &mut offsets {
+            *offset = data.read_u32()?;
+        }
+
+        let mut addresses = [0u32; 18];
+        for address in &mut addresses {
+            *address = data.read_u32()?;
+        }
+
+        let mut sizes = [0u32; 18];
+        for size in &mut sizes {
+            *size = data.read_u32()?;
+        }
+
+        // Now we need to actually create segments
+        for n in 0..TEXT_SLOTS + DATA_SLOTS {
+            if sizes[n] == 0 {
+                continue;
+            }
+            let (name, permissions) = match n {
+                // Code segments
+                // TODO: this is technically RWX since the SDK modifies some assembly when installing
+                // exceptions, do we bother enforcing that? "Real" programs will treat it as RX
+                ..TEXT_SLOTS => (format!("text{n}"), Permissions::READ | Permissions::EXECUTE),
+                // Data segments. We don't have nearly enough information to assume specific segments, so just
+                // set everything to RW. Analysis finds MWCC's extab/extabindex by their contents instead
+                _ => (
+                    format!("data{}", n - TEXT_SLOTS),
+                    Permissions::READ | Permissions::WRITE,
+                ),
+            };
+            ensure!(
+                offsets[n].checked_add(sizes[n]).is_some_and(|end| end as usize <= file_size),
+                InvalidDolSnafu { reason: format!("{name} runs past the end of the file") }
+            );
+            Self::check_in_memory(&name, addresses[n], sizes[n])?;
+            segments.push(Segment {
+                name,
+                address: addresses[n],
+                size: sizes[n],
+                offset: offsets[n],
+                permissions,
+            });
+        }
+
+        let bss_address = data.read_u32()?;
+        let bss_size = data.read_u32()?;
+        if bss_size > 0 {
+            Self::check_in_memory("bss", bss_address, bss_size)?;
+        }
+        Self::calculate_unique_bss(&mut segments, bss_address, bss_size);
+
+        // TODO: store this in a BTreeMap proper
+        segments.sort_by_key(|segment| segment.address);
+
+        Ok(segments)
+    }
+
+    /// Size of a DOL as far as its header goes: up to the end of whichever segment comes last in the file, or
+    /// the header itself. Discs only store where the DOL starts, so this is how much of it to read.
+    pub fn file_size(data: &[u8]) -> Result<u32, FerroxError> {
+        let mut data = DataCursorRef::new(data, Endian::Big);
+        let mut offsets = [0u32; 18];
+        for offset in &mut offsets {
+            *offset = data.read_u32()?;
+        }
+        data.set_position(0x90)?;
+        let mut end = HEADER_SIZE;
+        for offset in offsets {
+            let size = data.read_u32()?;
+            if size > 0 {
+                end = end.max(offset.saturating_add(size));
+            }
+        }
+        Ok(end)
+    }
+
+    /// Reads the address execution starts at, usually `__start`.
+    pub fn entry_point(data: &[u8]) -> Result<u32, FerroxError> {
+        let mut data = DataCursorRef::new(data, Endian::Big);
+        data.set_position(0xE0)?;
+        Ok(data.read_u32()?)
+    }
+
+    /// Writes a DOL holding every initialized segment, reading their contents from `data` at their offsets so
+    /// any patched bytes are kept. Segments named `textN`/`dataN` go back in the same slot and stay at the same
+    /// file offset, so a file that's read and written again comes out identical. Uninitialized segments are
+    /// merged into the one BSS range the header has room for.
+    ///
+    /// `original` is the DOL the segments were read from, if they were. Its BSS range often overlaps the small
+    /// data segments, so it's kept as long as it still leaves the same memory uninitialized, and so are the
+    /// unused bytes at the end of its header.
+    pub fn write(
+        segments: &[Segment<u32>], data: &[u8], entry_point: u32, original: Option<&[u8]>,
+    ) -> Result<Vec<u8>, FerroxError> {
+        let mut text = [None; TEXT_SLOTS];
+        let mut initialized = [None; DATA_SLOTS];
+        let mut bss: Option<(u32, u32)> = None;
+        for segment in segments {
+            Self::check_in_memory(&segment.name, segment.address, segment.size)?;
+            if segment.permissions.contains(Permissions::UNINITIALIZED) {
+                let end = segment.address + segment.size;
+                bss = Some(bss.map_or((segment.address, end), |(start, bss_end)| {
+                    (start.min(segment.address), bss_end.max(end))
+                }));
+                continue;
+            }
+            ensure!(
+                segment.offset.checked_add(segment.size).is_some_and(|end| end as usize <= data.len()),
+                InvalidDolSnafu { reason: format!("{} runs past the end of the data", segment.name) }
+            );
+
+            let code = segment.permissions.contains(Permissions::EXECUTE);
+            let (slots, prefix): (&mut [Option<&Segment<u32>>], _) = match code {
+                true => (&mut text, "text"),
+                false => (&mut initialized, "data"),
+            };
+            if code {
+                ensure!(
+                    segment.address.is_multiple_of(4) && segment.size.is_multiple_of(4),
+                    InvalidDolSnafu { reason: format!("{} isn't aligned to instructions", segment.name) }
+                );
+            }
+            let wanted = segment.name.strip_prefix(prefix).and_then(|slot| slot.parse::<usize>().ok());
+            let slot = wanted
+                .filter(|&slot| slots.get(slot).is_some_and(Option::is_none))
+                .or_else(|| slots.iter().position(Option::is_none));
+            let Some(slot) = slot else {
+                return InvalidDolSnafu {
+                    reason: format!("DOLs only have room for {} {prefix} segments", slots.len()),
+                }
+                .fail();
+            };
+            slots[slot] = Some(segment);
+        }
+
+        let mut loaded: Vec<&Segment<u32>> = text.iter().chain(&initialized).flatten().copied().collect();
+        loaded.sort_by_key(|segment| segment.address);
+        for pair in loaded.windows(2) {
+            ensure!(
+                pair[0].address + pair[0].size <= pair[1].address,
+                InvalidDolSnafu { reason: format!("{} overlaps {}", pair[0].name, pair[1].name) }
+            );
+        }
+        ensure!(
+            text.iter().flatten().any(|segment| segment.contains(entry_point)),
+            InvalidDolSnafu {
+                reason: format!("entry point 0x{entry_point:08X} isn't in a text segment")
+            }
+        );
+
+        // Keep every segment where it already is if it doesn't overlap the header or anything else, and put
+        // the rest after everything, aligned the way the SDK's tools do
+        let mut offsets = [0u32; TEXT_SLOTS + DATA_SLOTS];
+        let mut placed: Vec<(u32, u32)> = Vec::new();
+        let mut moved = Vec::new();
+        for (slot, segment) in text.iter().chain(&initialized).enumerate() {
+            let Some(segment) = segment else {
+                continue;
+            };
+            let (start, end) = (segment.offset, segment.offset + segment.size);
+            if start >= HEADER_SIZE
+                && placed.iter().all(|&(other, other_end)| end <= other || other_end <= start)
+            {
+                offsets[slot] = start;
+                placed.push((start, end));
+            } else {
+                moved.push((slot, segment));
+            }
+        }
+        let mut next = placed.iter().map(|&(_, end)| end).max().unwrap_or(HEADER_SIZE);
+        for (slot, segment) in moved {
+            next = next.next_multiple_of(32);
+            offsets[slot] = next;
+            next += segment.size;
+        }
+
+        let mut header = DataStream::new(Vec::with_capacity(HEADER_SIZE as usize), Endian::Big);
+        let slots: Vec<Option<&Segment<u32>>> = text.iter().chain(&initialized).copied().collect();
+        for (slot, segment) in slots.iter().enumerate() {
+            header.write_u32(segment.map_or(0, |_| offsets[slot]))?;
+        }
+        for segment in &slots {
+            header.write_u32(segment.map_or(0, |segment| segment.address))?;
+        }
+        for segment in &slots {
+            header.write_u32(segment.map_or(0, |segment| segment.size))?;
+        }
+        let (bss_start, bss_size) = match original.and_then(|original| Self::bss(original).ok()) {
+            Some(range) if Self::uninitialized(&loaded, range) == Self::uninitialized(segments, (0, 0)) => {
+                range
+            }
+            _ => bss.map_or((0, 0), |(start, end)| (start, end - start)),
+        };
+        header.write_u32(bss_start)?;
+        header.write_u32(bss_size)?;
+        header.write_u32(entry_point)?;
+
+        let end = slots
+            .iter()
+            .enumerate()
+            .filter_map(|(slot, segment)| segment.map(|segment| offsets[slot] + segment.size))
+            .fold(HEADER_SIZE, u32::max);
+        let mut file = vec![0; end as usize];
+        file[..header.len()].copy_from_slice(&header);
+        let padding = header.len()..HEADER_SIZE as usize;
+        if let Some(original) = original.and_then(|original| original.get(padding.clone())) {
+            file[padding].copy_from_slice(original);
+        }
+        for (slot, segment) in slots.iter().enumerate() {
+            if let Some(segment) = segment {
+                let source = &data[segment.offset as usize..(segment.offset + segment.size) as usize];
+                let offset = offsets[slot] as usize;
+                file[offset..offset + source.len()].copy_from_slice(source);
+            }
+        }
+        Ok(file)
+    }
+
+    /// Reads the BSS address and size from a DOL's header.
+    fn bss(data: &[u8]) -> Result<(u32, u32), FerroxError> {
+        let mut data = DataCursorRef::new(data, Endian::Big);
+        data.set_position(0xD8)?;
+        Ok((data.read_u32()?, data.read_u32()?))
+    }
+
+    /// The uninitialized parts of memory once `bss` is laid over `segments`, which can already hold some.
+    fn uninitialized(
+        segments: &[impl std::borrow::Borrow<Segment<u32>>], bss: (u32, u32),
+    ) -> Vec<(u32, u32)> {
+        let mut segments: Vec<Segment<u32>> =
+            segments.iter().map(|segment| segment.borrow().clone()).collect();
+        Self::calculate_unique_bss(&mut segments, bss.0, bss.1);
+        let mut ranges: Vec<(u32, u32)> = segments
+            .iter()
+            .filter(|segment| segment.permissions.contains(Permissions::UNINITIALIZED))
+            .map(|segment| (segment.address, segment.size))
+            .collect();
+        ranges.sort();
+        ranges
+    }
+
+    /// Fails unless `size` bytes at `address` all fit in main memory.
+    fn check_in_memory(name: &str, address: u32, size: u32) -> Result<(), FerroxError> {
+        let end = address.checked_add(size).filter(|end| *end <= MEMORY.end);
+        ensure!(
+            MEMORY.contains(&address) && end.is_some(),
+            InvalidDolSnafu { reason: format!("{name} at 0x{address:08X} is outside of main memory") }
+        );
+        Ok(())
+    }
+
+    fn calculate_unique_bss(segments: &mut Vec<Segment<u32>>, bss_address: u32, bss_size: u32) {
+        // If the file somehow doesn't have a bss section, we can skip this whole thing
+        if bss_size == 0 {
+            return;
+        }
+
+        // Modeling this bss overlay as a series of transitions through other memory regions
+        let mut transitions = BTreeSet::new();
+        let bss_end = bss_address.saturating_add(bss_size);
+
+        // Add existing segment transitions
+        for seg in segments.iter() {
+            let seg_end = seg.address.saturating_add(seg.size);
+            transitions.insert((seg.address, 1)); // 1 = start
+            transitions.insert((seg_end, -1)); // -1 = end
+        }
+
+        // Add BSS boundaries if they don't already exist
+        transitions.insert((bss_address, 0)); // 0 = BSS boundary
+        transitions.insert((bss_end, 0));
+
+        let mut in_existing = 0i32;
+        let mut last_point = None;
+
+        for &(point, transition) in transitions.iter() {
+            // If we have a valid previous point and we're in BSS range
+            if let Some(start) = last_point.filter(|&start| {
+                point > start && in_existing == 0 && start >= bss_address && point <= bss_end
+            }) {
+                segments.push(Segment {
+                    name: "bss".to_owned(),
+                    address: start,
+                    size: point - start,
+                    offset: 0,
+                    permissions: Permissions::READ | Permissions::WRITE | Permissions::UNINITIALIZED,
+                });
+            }
+
+            // Update segment counter before processing next point
+            in_existing += transition;
+            last_point = Some(point);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DATA, TEXT};
+
+    /// Builds a DOL from `(slot, address, contents)`, with every segment stored one after the other.
+    fn build(segments: &[(usize, u32, &[u8])], bss: (u32, u32), entry_point: u32) -> Vec<u8> {
+        let mut file = vec![0; HEADER_SIZE as usize];
+        for &(slot, address, contents) in segments {
+            let offset = file.len() as u32;
+            file[slot * 4..slot * 4 + 4].copy_from_slice(&offset.to_be_bytes());
+            file[0x48 + slot * 4..0x4C + slot * 4].copy_from_slice(&address.to_be_bytes());
+            file[0x90 + slot * 4..0x94 + slot * 4].copy_from_slice(&(contents.len() as u32).to_be_bytes());
+            file.extend_from_slice(contents);
+        }
+        file[0xD8..0xDC].copy_from_slice(&bss.0.to_be_bytes());
+        file[0xDC..0xE0].copy_from_slice(&bss.1.to_be_bytes());
+        file[0xE0..0xE4].copy_from_slice(&entry_point.to_be_bytes());
+        file
+    }
+
+    fn rewrite(file: &[u8]) -> Vec<u8> {
+        let segments = DolBinary::segments(file).unwrap();
+        DolBinary::write(&segments, file, DolBinary::entry_point(file).unwrap(), Some(file)).unwrap()
+    }
+
+    fn segment(name: &str, address: u32, size: u32, permissions: Permissions) -> Segment<u32> {
+        Segment { name: name.to_owned(), address, size, offset: HEADER_SIZE, permissions }
+    }
+
+    #[test]
+    fn writes_what_it_read() {
+        let file = testing::dol(&testing::assemble("li r3, 0\nblr", TEXT), &[1; 0x40], 0x80);
+        assert_eq!(rewrite(&file), file);
+
+        // Out of order slots and gaps between segments stay where they were
+        let mut file = build(
+            &[
+                (1, TEXT, &[0x60, 0, 0, 0]),
+                (0, TEXT + 0x100, &[0x4E, 0x80, 0, 0x20]),
+                (9, DATA, &[2; 0x24]),
+            ],
+            (0, 0),
+            TEXT,
+        );
+        file.extend_from_slice(&[0; 0x10]);
+        let rewritten = rewrite(&file);
+        assert_eq!(rewritten, file[..rewritten.len()]);
+    }
+
+    #[test]
+    fn keeps_bss_that_overlaps_data() {
+        // BSS runs from the end of data0 into data1, the way it covers .sdata and .sbss in real games
+        let mut file = build(
+            &[
+                (0, TEXT, &[0x4E, 0x80, 0, 0x20]),
+                (7, DATA, &[1; 0x100]),
+                (8, DATA + 0x1000, &[2; 0x40]),
+            ],
+            (DATA + 0x100, 0xF20),
+            TEXT,
+        );
+        file[0xE4..0x100].copy_from_slice(&[0xAB; 0x1C]);
+        assert_eq!(rewrite(&file), file);
+
+        // If the uninitialized memory changed, the range is worked out again from what's left
+        let file = build(&[(0, TEXT, &[0x4E, 0x80, 0, 0x20])], (DATA, 0x100), TEXT);
+        let mut segments = DolBinary::segments(&file).unwrap();
+        segments.retain(|segment| !segment.permissions.contains(Permissions::UNINITIALIZED));
+        segments.push(segment(
+            "bss",
+            DATA + 0x200,
+            0x80,
+            Permissions::READ | Permissions::UNINITIALIZED,
+        ));
+        let written = DolBinary::write(&segments, &file, TEXT, Some(&file)).unwrap();
+        assert_eq!(DolBinary::bss(&written).unwrap(), (DATA + 0x200, 0x80));
+    }
+
+    #[test]
+    fn keeps_patched_bytes() {
+        let mut file = testing::dol(&testing::assemble("li r3, 0\nblr", TEXT), &[], 0);
+        let original = file.clone();
+        file[0x100..0x104].copy_from_slice(&0x3860_0001u32.to_be_bytes());
+        let segments = DolBinary::segments(&original).unwrap();
+        assert_eq!(
+            DolBinary::write(&segments, &file, TEXT, Some(&original)).unwrap(),
+            file
+        );
+    }
+
+    #[test]
+    fn rejects_bad_segment_tables() {
+        let file = build(
+            &[(0, TEXT, &[0x4E, 0x80, 0, 0x20]), (7, DATA, &[1; 0x20])],
+            (0, 0),
+            TEXT,
+        );
+        let error = |patch: &dyn Fn(&mut Vec<u8>)| {
+            let mut file = file.clone();
+            patch(&mut file);
+            match DolBinary::segments(&file) {
+                Err(FerroxError::InvalidDol { reason }) => reason,
+                other => panic!("expected an error, got {other:?}"),
+            }
+        };
+        let word = |file: &mut Vec<u8>, at: usize, value: u32| {
+            file[at..at + 4].copy_from_slice(&value.to_be_bytes())
+        };
+
+        assert!(DolBinary::segments(&file).is_ok());
+        assert_eq!(
+            error(&|file| file.truncate(file.len() - 1)),
+            "data0 runs past the end of the file"
+        );
+        assert_eq!(
+            error(&|file| word(file, 0x1C, u32::MAX)),
+            "data0 runs past the end of the file"
+        );
+        assert_eq!(
+            error(&|file| word(file, 0x48, 0x8180_0000)),
+            "text0 at 0x81800000 is outside of main memory"
+        );
+        assert_eq!(
+            error(&|file| word(file, 0x64, 0x817F_FFF0)),
+            "data0 at 0x817FFFF0 is outside of main memory"
+        );
+        assert_eq!(
+            error(&|file| {
+                word(file, 0xD8, DATA);
+                word(file, 0xDC, u32::MAX);
+            }),
+            "bss at 0x80004000 is outside of main memory"
+        );
+    }
+
+    #[test]
+    fn checks_the_format_limits() {
+        let text = Permissions::READ | Permissions::EXECUTE;
+        let data = Permissions::READ | Permissions::WRITE;
+        let file = vec![0; 0x200];
+        let error = |segments: &[Segment<u32>]| match DolBinary::write(segments, &file, TEXT, None) {
+            Err(FerroxError::InvalidDol { reason }) => reason,
+            other => panic!("expected an error, got {other:?}"),
+        };
+
+        let texts: Vec<_> = (0..8).map(|n| segment(&format!("text{n}"), TEXT + n * 0x10, 4, text)).collect();
+        assert!(error(&texts).contains("7 text segments"));
+        let mut datas: Vec<_> =
+            (0..12).map(|n| segment(&format!("data{n}"), DATA + n * 0x10, 4, data)).collect();
+        datas.push(segment("text0", TEXT, 4, text));
+        assert!(error(&datas).contains("11 data segments"));
+
+        assert!(error(&[segment("text0", TEXT + 2, 4, text)]).contains("aligned"));
+        assert!(error(&[segment("text0", TEXT, 6, text)]).contains("aligned"));
+        assert!(error(&[segment("text0", 0x7FFF_FF00, 4, text)]).contains("main memory"));
+        assert!(error(&[segment("text0", TEXT, 0x200, text)]).contains("past the end"));
+        assert!(error(&[segment("text0", TEXT + 0x10, 4, text)]).contains("entry point"));
+        let overlapping = [
+            segment("text0", TEXT, 8, text),
+            segment("data0", TEXT + 4, 4, data),
+        ];
+        assert!(error(&overlapping).contains("overlaps"));
+    }
+}
@@ -0,0 +1,483 @@
+use orthrus_core::prelude::*;
+use snafu::ensure;
+
+use super::{Permissions, Segment};
+use crate::error::{FerroxError, InvalidElfSnafu};
+
+const MAGIC: &[u8; 4] = b"\x7FELF";
+const MACHINE_PPC: u16 = 20;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_WRITE: u32 = 1 << 0;
+const SHF_ALLOC: u32 = 1 << 1;
+const SHF_EXECINSTR: u32 = 1 << 2;
+
+/// Section index symbols use when they aren't defined in this file.
+pub const SHN_UNDEF: u16 = 0;
+/// Section index for symbols that aren't in any section, like the file name.
+pub const SHN_ABS: u16 = 0xFFF1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    None,
+    Object,
+    Function,
+    Section,
+    File,
+    Other(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolBinding {
+    Local,
+    Global,
+    Weak,
+    Other(u8),
+}
+
+#[derive(Debug, Clone)]
+pub struct ElfSymbol {
+    pub name: String,
+    pub value: u32,
+    pub size: u32,
+    pub kind: SymbolKind,
+    pub binding: SymbolBinding,
+    /// Index of the section this is defined in, or [`SHN_UNDEF`]
+    pub section: u16,
+}
+
+/// A `.rela` entry, already resolved to the section it applies to.
+#[derive(Debug, Clone, Copy)]
+pub struct ElfRelocation {
+    /// Index of the section being patched
+    pub section: usize,
+    pub offset: u32,
+    /// Raw `R_PPC_*` type
+    pub kind: u8,
+    /// Index into the symbol table
+    pub symbol: u32,
+    pub addend: i32,
+}
+
+/// A section to be written by [`write_object`].
+#[derive(Debug, Clone)]
+pub struct ObjectSection {
+    pub name: String,
+    pub permissions: Permissions,
+    pub alignment: u32,
+    /// Contents, left empty for [`Permissions::UNINITIALIZED`] sections
+    pub data: Vec<u8>,
+    pub size: u32,
+}
+
+/// A 32-bit big endian PowerPC ELF, which is what every GameCube/Wii toolchain outputs. Only the parts needed
+/// for relocatable objects are read: sections, symbols and relocations.
+pub struct ElfObject<'a> {
+    data: &'a [u8],
+    /// Every section, in section header order so symbol and relocation indices line up. Sections that don't
+    /// get loaded have no permissions.
+    pub sections: Vec<Segment<u32>>,
+    pub symbols: Vec<ElfSymbol>,
+    pub relocations: Vec<ElfRelocation>,
+}
+
+impl<'a> ElfObject<'a> {
+    pub fn is_elf(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    pub fn parse(data: &'a [u8]) -> Result<Self, FerroxError> {
+        ensure!(
+            Self::is_elf(data),
+            InvalidElfSnafu { reason: "missing ELF header" }
+        );
+        ensure!(
+            data.get(4) == Some(&1) && data.get(5) == Some(&2),
+            InvalidElfSnafu { reason: "only 32-bit big endian files are supported" }
+        );
+        let mut cursor = DataCursorRef::new(data, Endian::Big);
+        cursor.set_position(0x12)?;
+        let machine = cursor.read_u16()?;
+        ensure!(
+            machine == MACHINE_PPC,
+            InvalidElfSnafu { reason: format!("machine {machine} isn't PowerPC") }
+        );
+        cursor.set_position(0x20)?;
+        let section_offset = cursor.read_u32()?;
+        cursor.set_position(0x2E)?;
+        let section_size = cursor.read_u16()?;
+        let section_count = cursor.read_u16()?;
+        let names_index = cursor.read_u16()? as usize;
+
+        // Read all of the headers first, since names live in a section of their own
+        let mut headers = Vec::with_capacity(section_count as usize);
+        for index in 0..section_count as u32 {
+            cursor.set_position((section_offset + index * section_size as u32) as u64)?;
+            headers.push(SectionHeader {
+                name: cursor.read_u32()?,
+                kind: cursor.read_u32()?,
+                flags: cursor.read_u32()?,
+                address: cursor.read_u32()?,
+                offset: cursor.read_u32()?,
+                size: cursor.read_u32()?,
+                link: cursor.read_u32()?,
+                info: cursor.read_u32()?,
+            });
+        }
+        let strings = |section: usize| -> Result<&'a [u8], FerroxError> {
+            let header = headers.get(section).ok_or_else(|| FerroxError::InvalidElf {
+                reason: format!("string table {section} doesn't exist"),
+            })?;
+            section_data(data, header)
+        };
+        let section_names = strings(names_index)?;
+
+        let mut object = Self {
+            data,
+            sections: Vec::new(),
+            symbols: Vec::new(),
+            relocations: Vec::new(),
+        };
+        for header in &headers {
+            let mut permissions = Permissions::empty();
+            if header.flags & SHF_ALLOC != 0 {
+                permissions |= Permissions::READ;
+                if header.flags & SHF_WRITE != 0 {
+                    permissions |= Permissions::WRITE;
+                }
+                if header.flags & SHF_EXECINSTR != 0 {
+                    permissions |= Permissions::EXECUTE;
+                }
+                if header.kind == SHT_NOBITS {
+                    permissions |= Permissions::UNINITIALIZED;
+                }
+            }
+            object.sections.push(Segment {
+                name: string_at(section_names, header.name),
+                address: header.address,
+                size: header.size,
+                offset: header.offset,
+                permissions,
+            });
+        }
+
+        for (index, header) in headers.iter().enumerate() {
+            match header.kind {
+                // Relocatable objects only ever have the one symbol table
+                SHT_SYMTAB if object.symbols.is_empty() => {
+                    let names = strings(header.link as usize)?;
+                    let mut symbols = DataCursorRef::new(section_data(data, header)?, Endian::Big);
+                    for _ in 0..header.size / 16 {
+                        let name = symbols.read_u32()?;
+                        let value = symbols.read_u32()?;
+                        let size = symbols.read_u32()?;
+                        let info = symbols.read_u8()?;
+                        let _other = symbols.read_u8()?;
+                        let section = symbols.read_u16()?;
+                        object.symbols.push(ElfSymbol {
+                            name: string_at(names, name),
+                            value,
+                            size,
+                            kind: match info & 0xF {
+                                0 => SymbolKind::None,
+                                1 => SymbolKind::Object,
+                                2 => SymbolKind::Function,
+                                3 => SymbolKind::Section,
+                                4 => SymbolKind::File,
+                                kind => SymbolKind::Other(kind),
+                            },
+                            binding: match info >> 4 {
+                                0 => SymbolBinding::Local,
+                                1 => SymbolBinding::Global,
+                                2 => SymbolBinding::Weak,
+                                binding => SymbolBinding::Other(binding),
+                            },
+                            section,
+                        });
+                    }
+                }
+                SHT_RELA => {
+                    ensure!(
+                        (header.info as usize) < headers.len(),
+                        InvalidElfSnafu {
+                            reason: format!("relocation section {index} patches a missing section")
+                        }
+                    );
+                    let mut relocations = DataCursorRef::new(section_data(data, header)?, Endian::Big);
+                    for _ in 0..header.size / 12 {
+                        let offset = relocations.read_u32()?;
+                        let info = relocations.read_u32()?;
+                        object.relocations.push(ElfRelocation {
+                            section: header.info as usize,
+                            offset,
+                            kind: info as u8,
+                            symbol: info >> 8,
+                            addend: relocations.read_i32()?,
+                        });
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(object)
+    }
+
+    /// Contents of a section, if it has any in the file.
+    pub fn section_data(&self, section: usize) -> Option<&'a [u8]> {
+        let segment = self.sections.get(section)?;
+        if segment.permissions.contains(Permissions::UNINITIALIZED) {
+            return None;
+        }
+        self.data.get(segment.offset as usize..(segment.offset + segment.size) as usize)
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u32,
+    address: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+}
+
+fn section_data<'a>(data: &'a [u8], header: &SectionHeader) -> Result<&'a [u8], FerroxError> {
+    match header.kind {
+        SHT_NOBITS => Ok(&[]),
+        _ => data.get(header.offset as usize..(header.offset as usize + header.size as usize)).ok_or_else(
+            || FerroxError::InvalidElf {
+                reason: format!("section at 0x{:X} runs past the end", header.offset),
+            },
+        ),
+    }
+}
+
+/// Reads a NUL terminated string out of a string table, empty if it's out of bounds.
+fn string_at(table: &[u8], offset: u32) -> String {
+    let Some(string) = table.get(offset as usize..) else {
+        return String::new();
+    };
+    let end = string.iter().position(|&byte| byte == 0).unwrap_or(string.len());
+    String::from_utf8_lossy(&string[..end]).into_owned()
+}
+
+/// Writes a relocatable object. Section indices in symbols and relocations count from 1 in the order the
+/// sections are given, and symbol indices in relocations count from 1 in the order the symbols are given, since
+/// index 0 is always the null entry. Symbols can be in any order, local ones get moved to the front as ELF
+/// requires.
+pub fn write_object(
+    sections: &[ObjectSection], symbols: &[ElfSymbol], relocations: &[ElfRelocation],
+) -> Result<Vec<u8>, FerroxError> {
+    let mut order: Vec<usize> = (0..symbols.len()).collect();
+    order.sort_by_key(|&index| symbols[index].binding != SymbolBinding::Local);
+    let mut new_index = vec![0; symbols.len()];
+    for (position, &index) in order.iter().enumerate() {
+        new_index[index] = position as u32 + 1;
+    }
+    let first_global =
+        order.iter().take_while(|&&index| symbols[index].binding == SymbolBinding::Local).count();
+
+    let mut names = StringTable::default();
+    let mut symbol_table = DataStream::new(vec![0; 16], Endian::Big);
+    for &index in &order {
+        let symbol = &symbols[index];
+        symbol_table.write_u32(names.add(&symbol.name))?;
+        symbol_table.write_u32(symbol.value)?;
+        symbol_table.write_u32(symbol.size)?;
+        let kind = match symbol.kind {
+            SymbolKind::None => 0,
+            SymbolKind::Object => 1,
+            SymbolKind::Function => 2,
+            SymbolKind::Section => 3,
+            SymbolKind::File => 4,
+            SymbolKind::Other(kind) => kind,
+        };
+        let binding = match symbol.binding {
+            SymbolBinding::Local => 0,
+            SymbolBinding::Global => 1,
+            SymbolBinding::Weak => 2,
+            SymbolBinding::Other(binding) => binding,
+        };
+        symbol_table.write_u8(binding << 4 | kind)?;
+        symbol_table.write_u8(0)?;
+        symbol_table.write_u16(symbol.section)?;
+    }
+
+    // Everything after the sections that were given: the symbol table, its names, relocations, and section names
+    let symtab_index = sections.len() as u32 + 1;
+    let mut tables = vec![
+        Table {
+            name: ".symtab".into(),
+            kind: SHT_SYMTAB,
+            data: std::mem::take(&mut *symbol_table),
+            link: symtab_index + 1,
+            info: first_global as u32 + 1,
+            entry_size: 16,
+        },
+        Table {
+            name: ".strtab".into(),
+            kind: SHT_STRTAB,
+            data: names.data,
+            link: 0,
+            info: 0,
+            entry_size: 0,
+        },
+    ];
+    for (index, section) in sections.iter().enumerate() {
+        let mut rela = DataStream::new(Vec::new(), Endian::Big);
+        for relocation in relocations.iter().filter(|relocation| relocation.section == index + 1) {
+            let symbol = new_index.get(relocation.symbol as usize - 1).copied().unwrap_or_default();
+            rela.write_u32(relocation.offset)?;
+            rela.write_u32(symbol << 8 | u32::from(relocation.kind))?;
+            rela.write_i32(relocation.addend)?;
+        }
+        if !rela.is_empty() {
+            tables.push(Table {
+                name: format!(".rela{}", section.name),
+                kind: SHT_RELA,
+                data: std::mem::take(&mut *rela),
+                link: symtab_index,
+                info: index as u32 + 1,
+                entry_size: 12,
+            });
+        }
+    }
+
+    let mut section_names = StringTable::default();
+    let mut headers = DataStream::new(vec![0; 40], Endian::Big);
+    let mut file = DataStream::new(vec![0; 52], Endian::Big);
+    for section in sections {
+        let uninitialized = section.permissions.contains(Permissions::UNINITIALIZED);
+        let offset = pad(&mut file, section.alignment);
+        file.extend_from_slice(&section.data);
+        let mut flags = SHF_ALLOC;
+        if section.permissions.contains(Permissions::WRITE) {
+            flags |= SHF_WRITE;
+        }
+        if section.permissions.contains(Permissions::EXECUTE) {
+            flags |= SHF_EXECINSTR;
+        }
+        let kind = if uninitialized { SHT_NOBITS } else { SHT_PROGBITS };
+        let header = [
+            section_names.add(&section.name),
+            kind,
+            flags,
+            0,
+            offset,
+            section.size,
+            0,
+            0,
+        ];
+        write_header(&mut headers, header, section.alignment, 0)?;
+    }
+    // Section names go last, so they include their own
+    let shstrtab_index = symtab_index + tables.len() as u32;
+    tables.push(Table {
+        name: ".shstrtab".into(),
+        kind: SHT_STRTAB,
+        data: Vec::new(),
+        link: 0,
+        info: 0,
+        entry_size: 0,
+    });
+    let names: Vec<u32> = tables.iter().map(|table| section_names.add(&table.name)).collect();
+    tables.last_mut().unwrap().data = section_names.data;
+    for (table, name) in tables.iter().zip(names) {
+        let offset = pad(&mut file, 4);
+        file.extend_from_slice(&table.data);
+        let header = [
+            name,
+            table.kind,
+            0,
+            0,
+            offset,
+            table.data.len() as u32,
+            table.link,
+            table.info,
+        ];
+        let alignment = if table.kind == SHT_STRTAB { 1 } else { 4 };
+        write_header(&mut headers, header, alignment, table.entry_size)?;
+    }
+    let header_offset = pad(&mut file, 4);
+    file.extend_from_slice(&headers);
+
+    let section_count = shstrtab_index as u16 + 1;
+    let mut header = DataStream::new(Vec::with_capacity(52), Endian::Big);
+    header.extend_from_slice(MAGIC);
+    // 32-bit, big endian, version 1
+    header.extend_from_slice(&[1, 2, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    // Relocatable, PowerPC, version 1, no entry point or program headers
+    header.write_u16(1)?;
+    header.write_u16(MACHINE_PPC)?;
+    header.write_u32(1)?;
+    header.write_u32(0)?;
+    header.write_u32(0)?;
+    header.write_u32(header_offset)?;
+    header.write_u32(0)?;
+    header.write_u16(52)?;
+    header.write_u16(0)?;
+    header.write_u16(0)?;
+    header.write_u16(40)?;
+    header.write_u16(section_count)?;
+    header.write_u16(shstrtab_index as u16)?;
+    file[..52].copy_from_slice(&header);
+    Ok(std::mem::take(&mut *file))
+}
+
+/// A section [`write_object`] adds on its own.
+struct Table {
+    name: String,
+    kind: u32,
+    data: Vec<u8>,
+    link: u32,
+    info: u32,
+    entry_size: u32,
+}
+
+/// Pads the file up to `alignment`, returning the new end.
+fn pad(file: &mut DataStream<Vec<u8>>, alignment: u32) -> u32 {
+    let padding = file.len().next_multiple_of(alignment.max(1) as usize) - file.len();
+    file.extend(std::iter::repeat_n(0, padding));
+    file.len() as u32
+}
+
+fn write_header(
+    headers: &mut DataStream<Vec<u8>>, fields: [u32; 8], alignment: u32, entry_size: u32,
+) -> Result<(), FerroxError> {
+    for field in fields {
+        headers.write_u32(field)?;
+    }
+    headers.write_u32(alignment)?;
+    headers.write_u32(entry_size)?;
+    Ok(())
+}
+
+/// A string table being built, which always starts with an empty string.
+struct StringTable {
+    data: Vec<u8>,
+}
+
+impl Default for StringTable {
+    fn default() -> Self {
+        Self { data: vec![0] }
+    }
+}
+
+impl StringTable {
+    fn add(&mut self, string: &str) -> u32 {
+        if string.is_empty() {
+            return 0;
+        }
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(string.as_bytes());
+        self.data.push(0);
+        offset
+    }
+}
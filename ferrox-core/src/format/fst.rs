@@ -0,0 +1,210 @@
+use orthrus_core::prelude::*;
+use snafu::ensure;
+
+use super::Entry;
+use crate::error::{FerroxError, InvalidFstSnafu};
+
+const ENTRY_SIZE: u32 = 12;
+
+/// Reads a file system table, which is how both discs and U8 archives list their files. Every entry is a flag
+/// saying whether it's a folder, the offset of its name in the string table that follows the entries, then
+/// either a file's offset and size, or a folder's parent and the index of the first entry after everything in
+/// it. The first entry is the root folder, whose end is the number of entries.
+pub fn parse(fst: &[u8]) -> Result<Vec<Entry>, FerroxError> {
+    let size = fst.len() as u32;
+    let mut cursor = DataCursorRef::new(fst, Endian::Big);
+    cursor.set_position(8)?;
+    let count = cursor.read_u32()?;
+    ensure!(
+        count > 0 && count.checked_mul(ENTRY_SIZE).is_some_and(|entries| entries <= size),
+        InvalidFstSnafu { reason: format!("can't hold {count} entries") }
+    );
+
+    let strings = &fst[(count * ENTRY_SIZE) as usize..];
+    let mut entries = Vec::with_capacity(count as usize);
+    cursor.set_position(0)?;
+    for _ in 0..count {
+        let word = cursor.read_u32()?;
+        let name = strings.get((word & 0x00FF_FFFF) as usize..).unwrap_or_default();
+        let end = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+        let name = String::from_utf8_lossy(&name[..end]).into_owned();
+        entries.push((word >> 24 != 0, name, cursor.read_u32()?, cursor.read_u32()?));
+    }
+    directory(&entries, 1, count)
+}
+
+/// Builds the tree for the entries from `start` up to `end`.
+fn directory(entries: &[(bool, String, u32, u32)], start: u32, end: u32) -> Result<Vec<Entry>, FerroxError> {
+    let mut children = Vec::new();
+    let mut index = start;
+    while index < end {
+        let (is_directory, name, offset, size) = &entries[index as usize];
+        if *is_directory {
+            // Folders always end after they start, and inside the one they're in
+            ensure!(
+                *size > index && *size <= end,
+                InvalidFstSnafu { reason: format!("folder {name} ends at entry {size}") }
+            );
+            let contents = directory(entries, index + 1, *size)?;
+            children.push(Entry::Directory { name: name.clone(), children: contents });
+            index = *size;
+        } else {
+            children.push(Entry::File { name: name.clone(), offset: *offset, size: *size });
+            index += 1;
+        }
+    }
+    Ok(children)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fst;
+
+    fn tree() -> Vec<Entry> {
+        parse(&fst(&[
+            (false, "opening.bnr", 0x100, 0x20),
+            (true, "RELS", 0, 5),
+            (false, "d_a_obj.rel", 0x200, 0x40),
+            (true, "empty", 2, 5),
+            (false, "main.dol", 0x300, 0x80),
+        ]))
+        .unwrap()
+    }
+
+    fn file(entry: Option<&Entry>) -> Option<(&str, u32, u32)> {
+        match *entry? {
+            Entry::File { ref name, offset, size } => Some((name, offset, size)),
+            Entry::Directory { .. } => None,
+        }
+    }
+
+    #[test]
+    fn builds_the_tree() {
+        let root = tree();
+        let names: Vec<&str> = root.iter().map(Entry::name).collect();
+        assert_eq!(names, ["opening.bnr", "RELS", "main.dol"]);
+        let Entry::Directory { children, .. } = &root[1] else {
+            panic!("RELS isn't a folder");
+        };
+        let names: Vec<&str> = children.iter().map(Entry::name).collect();
+        assert_eq!(names, ["d_a_obj.rel", "empty"]);
+        assert!(matches!(&children[1], Entry::Directory { children, .. } if children.is_empty()));
+    }
+
+    #[test]
+    fn finds_entries() {
+        let root = tree();
+        assert_eq!(
+            file(Entry::find(&root, "main.dol")),
+            Some(("main.dol", 0x300, 0x80))
+        );
+        assert_eq!(
+            file(Entry::find(&root, "/main.dol")),
+            Some(("main.dol", 0x300, 0x80))
+        );
+        assert_eq!(
+            file(Entry::find(&root, "RELS/d_a_obj.rel")),
+            Some(("d_a_obj.rel", 0x200, 0x40))
+        );
+        assert_eq!(
+            file(Entry::find(&root, "/RELS/d_a_obj.rel")),
+            Some(("d_a_obj.rel", 0x200, 0x40))
+        );
+        assert_eq!(
+            file(Entry::find(&root, "rels/D_A_OBJ.REL")),
+            Some(("d_a_obj.rel", 0x200, 0x40))
+        );
+        assert!(matches!(
+            Entry::find(&root, "RELS"),
+            Some(Entry::Directory { .. })
+        ));
+        assert!(matches!(
+            Entry::find(&root, "/RELS/empty"),
+            Some(Entry::Directory { .. })
+        ));
+
+        assert!(Entry::find(&root, "missing.rel").is_none());
+        assert!(Entry::find(&root, "RELS/missing.rel").is_none());
+        assert!(Entry::find(&root, "main.dol/inside").is_none());
+        assert!(Entry::find(&root, "RELS/empty/anything").is_none());
+        assert!(Entry::find(&root, "").is_none());
+    }
+
+    #[test]
+    fn reads_contents() {
+        let root = tree();
+        let data: Vec<u8> = (0..=0xFF).cycle().take(0x380).collect();
+        let contents = Entry::find(&root, "main.dol").unwrap().contents(&data).unwrap();
+        assert_eq!(contents.len(), 0x80);
+        assert_eq!(contents[0], 0x00);
+        assert_eq!(Entry::find(&root, "RELS").unwrap().contents(&data), None);
+        assert_eq!(
+            Entry::find(&root, "main.dol").unwrap().contents(&data[..0x37F]),
+            None
+        );
+    }
+
+    fn reason(table: &[u8]) -> String {
+        match parse(table) {
+            Err(FerroxError::InvalidFst { reason }) => reason,
+            other => panic!("{:?}", other.map(|entries| entries.len())),
+        }
+    }
+
+    #[test]
+    fn rejects_folders_that_dont_end_after_they_start() {
+        // Ending where they start, or before, would mean reading the same entries over and over
+        assert_eq!(reason(&fst(&[(true, "sub", 0, 1)])), "folder sub ends at entry 1");
+        assert_eq!(
+            reason(&fst(&[(false, "a.rel", 0, 0), (true, "sub", 0, 1)])),
+            "folder sub ends at entry 1"
+        );
+        // Ending past the folder they're in
+        assert_eq!(reason(&fst(&[(true, "sub", 0, 9)])), "folder sub ends at entry 9");
+        assert_eq!(
+            reason(&fst(&[
+                (true, "outer", 0, 3),
+                (true, "inner", 1, 4),
+                (false, "a.rel", 0, 0)
+            ])),
+            "folder inner ends at entry 4"
+        );
+    }
+
+    #[test]
+    fn rejects_tables_that_cant_hold_their_entries() {
+        let count = |count: u32| {
+            let mut table = fst(&[(false, "a.rel", 0, 0)]);
+            table[8..12].copy_from_slice(&count.to_be_bytes());
+            table
+        };
+        assert_eq!(reason(&count(0)), "can't hold 0 entries");
+        assert_eq!(reason(&count(3)), "can't hold 3 entries");
+        // Enough entries that their size overflows
+        assert_eq!(reason(&count(0x1555_5556)), "can't hold 357913942 entries");
+        assert_eq!(reason(&count(u32::MAX)), "can't hold 4294967295 entries");
+    }
+
+    #[test]
+    fn rejects_truncated_tables() {
+        let table = fst(&[
+            (false, "a.rel", 0x100, 0x20),
+            (true, "RELS", 0, 4),
+            (false, "b.rel", 0x200, 0x40),
+        ]);
+        for end in 0..table.len() {
+            // Names can be cut off without losing the tree, but the entries can't
+            let result = parse(&table[..end]);
+            assert_eq!(
+                result.is_ok(),
+                end >= 4 * ENTRY_SIZE as usize,
+                "cut off at 0x{end:X}"
+            );
+        }
+        // Names past the end of the strings come out empty
+        let mut table = fst(&[(false, "a.rel", 0, 0)]);
+        table[13] = 0xFF;
+        assert_eq!(parse(&table).unwrap()[0].name(), "");
+    }
+}
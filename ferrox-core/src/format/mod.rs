@@ -0,0 +1,116 @@
+pub mod ar;
+pub mod compression;
+pub mod disc;
+pub mod dol;
+pub mod elf;
+pub mod fst;
+pub mod rarc;
+pub mod rel;
+pub mod u8_archive;
+use bitflags::bitflags;
+
+/// All supported file types.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub enum BinaryFormat {
+    BinaryFile,
+    #[default]
+    GameCubeDOL,
+    GameCubeREL,
+}
+
+impl BinaryFormat {
+    /// Guesses the format of a file from its name, for files picked out of a disc or an archive. Compressed
+    /// files are named after what's inside, like `d_a_obj.rel.szs`.
+    pub fn from_name(name: &str) -> Self {
+        let lowercase = name.to_ascii_lowercase();
+        let name = [".szs", ".yaz0", ".yay0", ".szp"]
+            .iter()
+            .find_map(|extension| lowercase.strip_suffix(extension))
+            .unwrap_or(&lowercase);
+        match name.rsplit_once('.').map(|(_, extension)| extension) {
+            Some("dol") => BinaryFormat::GameCubeDOL,
+            Some("rel") => BinaryFormat::GameCubeREL,
+            _ => BinaryFormat::BinaryFile,
+        }
+    }
+}
+
+/// A file or folder inside a disc image or an archive, with where its contents are in the whole image.
+#[derive(Debug, Clone)]
+pub enum Entry {
+    File { name: String, offset: u32, size: u32 },
+    Directory { name: String, children: Vec<Entry> },
+}
+
+impl Entry {
+    pub fn name(&self) -> &str {
+        match self {
+            Entry::File { name, .. } | Entry::Directory { name, .. } => name,
+        }
+    }
+
+    /// Finds the entry at a `/` separated path, like `files/RELS/d_a_obj.rel`.
+    pub fn find<'a>(entries: &'a [Entry], path: &str) -> Option<&'a Entry> {
+        let path = path.trim_start_matches('/');
+        let (name, rest) = path.split_once('/').unwrap_or((path, ""));
+        let entry = entries.iter().find(|entry| entry.name().eq_ignore_ascii_case(name))?;
+        match (entry, rest) {
+            (_, "") => Some(entry),
+            (Entry::Directory { children, .. }, rest) => Self::find(children, rest),
+            (Entry::File { .. }, _) => None,
+        }
+    }
+
+    /// Contents of a file, if it's inside `data`.
+    pub fn contents<'a>(&self, data: &'a [u8]) -> Option<&'a [u8]> {
+        match *self {
+            Entry::File { offset, size, .. } => data.get(offset as usize..offset as usize + size as usize),
+            Entry::Directory { .. } => None,
+        }
+    }
+}
+
+bitflags! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Permissions: u32 {
+        const READ = 1 << 0;
+        const WRITE = 1 << 1;
+        const EXECUTE = 1 << 2;
+        const UNINITIALIZED = 1 << 3;
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+    impl Sealed for u16 {}
+    impl Sealed for u32 {}
+    impl Sealed for u64 {}
+}
+
+pub trait ValidSegmentSize: sealed::Sealed + Copy + Into<u64> {}
+impl ValidSegmentSize for u16 {}
+impl ValidSegmentSize for u32 {}
+impl ValidSegmentSize for u64 {}
+
+#[derive(Debug, Clone)]
+pub struct Segment<T: ValidSegmentSize> {
+    /// Display name for this `Segment`, based on where it came from in the original file
+    pub name: String,
+    /// The virtual address this `Segment` starts at
+    pub address: T,
+    /// The size in bytes that this `Segment` takes up
+    pub size: T,
+    /// The file offset this Segment's data is at, if not [`Permissions::UNINITIALIZED`]
+    // TODO: make this more flexible once we can store multiple binaries and modify existing code?
+    pub offset: T,
+    /// The permissions this `Segment` is tied to
+    pub permissions: Permissions,
+}
+
+impl<T: ValidSegmentSize> Segment<T> {
+    /// Returns whether `address` falls inside of this `Segment`.
+    pub fn contains(&self, address: T) -> bool {
+        let (address, start) = (address.into(), self.address.into());
+        address >= start && address - start < self.size.into()
+    }
+}
@@ -0,0 +1,360 @@
+use orthrus_core::prelude::*;
+use snafu::ensure;
+
+use super::Entry;
+use crate::error::{FerroxError, InvalidArchiveSnafu};
+
+const MAGIC: &[u8; 4] = b"RARC";
+/// Offsets in the archive are from the end of the header, where the info block starts
+const HEADER_SIZE: u32 = 0x20;
+const NODE_SIZE: u32 = 0x10;
+const ENTRY_SIZE: u32 = 0x14;
+const DIRECTORY: u8 = 0x02;
+
+/// A RARC archive (`.arc`), which GameCube games pack their resources and RELs into. Folders are nodes that own
+/// a run of entries, and each entry is either a file in the data block or a link to another node. Every folder
+/// also links to itself and its parent as `.` and `..`, which are left out of the tree.
+pub struct RarcArchive;
+
+/// A folder, with its name and the entries it owns.
+struct Node {
+    name: String,
+    count: u16,
+    first: u32,
+}
+
+/// A file or a link to a folder, before it's been put in the tree.
+struct RawEntry {
+    flags: u8,
+    name: String,
+    /// Offset of a file in the data block, or the node a folder links to
+    target: u32,
+    size: u32,
+}
+
+impl RarcArchive {
+    pub fn is_archive(data: &[u8]) -> bool {
+        data.starts_with(MAGIC)
+    }
+
+    /// Reads the tree of files in the archive, which has the root node as its only folder.
+    pub fn entries(data: &[u8]) -> Result<Vec<Entry>, FerroxError> {
+        ensure!(
+            Self::is_archive(data),
+            InvalidArchiveSnafu { reason: "missing RARC magic" }
+        );
+        let mut cursor = DataCursorRef::new(data, Endian::Big);
+        // Offsets that run off the end of the address space stay there, so the checks below catch them
+        cursor.set_position(0x0C)?;
+        let data_offset = HEADER_SIZE.saturating_add(cursor.read_u32()?);
+        cursor.set_position(u64::from(HEADER_SIZE))?;
+        let node_count = cursor.read_u32()?;
+        let node_offset = HEADER_SIZE.saturating_add(cursor.read_u32()?);
+        let entry_count = cursor.read_u32()?;
+        let entry_offset = HEADER_SIZE.saturating_add(cursor.read_u32()?);
+        cursor.read_u32()?;
+        let strings = HEADER_SIZE.saturating_add(cursor.read_u32()?) as usize;
+        let fits = |offset: u32, count: u32, size: u32| {
+            count
+                .checked_mul(size)
+                .and_then(|size| size.checked_add(offset))
+                .is_some_and(|end| end as usize <= data.len())
+        };
+        ensure!(
+            node_count > 0 && fits(node_offset, node_count, NODE_SIZE),
+            InvalidArchiveSnafu { reason: format!("RARC can't hold {node_count} folders") }
+        );
+        ensure!(
+            fits(entry_offset, entry_count, ENTRY_SIZE),
+            InvalidArchiveSnafu { reason: format!("RARC can't hold {entry_count} entries") }
+        );
+        let name = |offset: u32| {
+            let name = data.get(strings + offset as usize..).unwrap_or_default();
+            let end = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+            String::from_utf8_lossy(&name[..end]).into_owned()
+        };
+
+        let mut nodes = Vec::with_capacity(node_count as usize);
+        cursor.set_position(u64::from(node_offset))?;
+        for _ in 0..node_count {
+            cursor.read_u32()?;
+            let name = name(cursor.read_u32()?);
+            cursor.read_u16()?;
+            nodes.push(Node { name, count: cursor.read_u16()?, first: cursor.read_u32()? });
+        }
+
+        let mut entries = Vec::with_capacity(entry_count as usize);
+        cursor.set_position(u64::from(entry_offset))?;
+        for _ in 0..entry_count {
+            cursor.read_u32()?;
+            let word = cursor.read_u32()?;
+            let (target, size) = (cursor.read_u32()?, cursor.read_u32()?);
+            cursor.read_u32()?;
+            entries.push(RawEntry {
+                flags: (word >> 24) as u8,
+                name: name(word & 0x00FF_FFFF),
+                target,
+                size,
+            });
+        }
+
+        let mut visited = vec![false; nodes.len()];
+        let children = Self::directory(&nodes, &entries, 0, data_offset, &mut visited)?;
+        Ok(vec![Entry::Directory { name: nodes[0].name.clone(), children }])
+    }
+
+    /// Builds the tree for a node. Every node can only be in the tree once, so links that loop back round are
+    /// caught.
+    fn directory(
+        nodes: &[Node], entries: &[RawEntry], index: usize, data_offset: u32, visited: &mut [bool],
+    ) -> Result<Vec<Entry>, FerroxError> {
+        ensure!(
+            !std::mem::replace(&mut visited[index], true),
+            InvalidArchiveSnafu {
+                reason: format!("RARC folder {} is in more than one place", nodes[index].name)
+            }
+        );
+        let node = &nodes[index];
+        let owned =
+            entries.get(node.first as usize..node.first as usize + node.count as usize).ok_or_else(|| {
+                FerroxError::InvalidArchive {
+                    reason: format!("RARC folder {} runs past the last entry", node.name),
+                }
+            })?;
+
+        let mut children = Vec::new();
+        for entry in owned {
+            if entry.flags & DIRECTORY == 0 {
+                children.push(Entry::File {
+                    name: entry.name.clone(),
+                    offset: data_offset.wrapping_add(entry.target),
+                    size: entry.size,
+                });
+                continue;
+            }
+            if entry.name == "." || entry.name == ".." {
+                continue;
+            }
+            ensure!(
+                (entry.target as usize) < nodes.len(),
+                InvalidArchiveSnafu { reason: format!("RARC folder {} links to a missing node", entry.name) }
+            );
+            let contents = Self::directory(nodes, entries, entry.target as usize, data_offset, visited)?;
+            children.push(Entry::Directory { name: entry.name.clone(), children: contents });
+        }
+        Ok(children)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A folder: its name, how many entries it owns and the first of them.
+    type RawNode<'a> = (&'a str, u16, u32);
+    /// An entry: its flags, name, and either a file's offset and size or the node a folder links to.
+    type RawLink<'a> = (u8, &'a str, u32, u32);
+
+    const FILE: u8 = 0x01;
+
+    /// Builds an archive with the info block, nodes, entries, strings and `contents` one after another.
+    fn rarc(nodes: &[RawNode], entries: &[RawLink], contents: &[u8]) -> Vec<u8> {
+        let mut strings = Vec::new();
+        let mut string = |name: &str| {
+            let offset = strings.len() as u32;
+            strings.extend_from_slice(name.as_bytes());
+            strings.push(0);
+            offset
+        };
+        let mut table = Vec::new();
+        for &(name, count, first) in nodes {
+            table.extend_from_slice(b"NODE");
+            table.extend_from_slice(&string(name).to_be_bytes());
+            table.extend_from_slice(&[0, 0]);
+            table.extend_from_slice(&count.to_be_bytes());
+            table.extend_from_slice(&first.to_be_bytes());
+        }
+        for &(flags, name, target, size) in entries {
+            table.extend_from_slice(&[0xFF, 0xFF, 0, 0]);
+            table.extend_from_slice(&((u32::from(flags) << 24) | string(name)).to_be_bytes());
+            table.extend_from_slice(&target.to_be_bytes());
+            table.extend_from_slice(&size.to_be_bytes());
+            table.extend_from_slice(&[0; 4]);
+        }
+
+        // Offsets from here on are from the start of the info block
+        let node_offset = 0x20u32;
+        let entry_offset = node_offset + nodes.len() as u32 * NODE_SIZE;
+        let string_offset = entry_offset + entries.len() as u32 * ENTRY_SIZE;
+        let data_offset = string_offset + strings.len() as u32;
+        let mut data = MAGIC.to_vec();
+        let words = [
+            HEADER_SIZE + data_offset + contents.len() as u32,
+            HEADER_SIZE,
+            data_offset,
+            contents.len() as u32,
+            contents.len() as u32,
+            0,
+            0,
+            nodes.len() as u32,
+            node_offset,
+            entries.len() as u32,
+            entry_offset,
+            strings.len() as u32,
+            string_offset,
+            0,
+            0,
+        ];
+        for word in words {
+            data.extend_from_slice(&word.to_be_bytes());
+        }
+        data.extend(table);
+        data.extend(strings);
+        data.extend_from_slice(contents);
+        data
+    }
+
+    /// An archive with a file in the root and another in a folder.
+    fn archive() -> Vec<u8> {
+        rarc(
+            &[("root", 4, 0), ("sub", 3, 4)],
+            &[
+                (FILE, "a.rel", 0, 4),
+                (DIRECTORY, "sub", 1, 0x10),
+                (DIRECTORY, ".", 0, 0x10),
+                (DIRECTORY, "..", u32::MAX, 0x10),
+                (FILE, "b.rel", 4, 2),
+                (DIRECTORY, ".", 1, 0x10),
+                (DIRECTORY, "..", 0, 0x10),
+            ],
+            b"AAAABB",
+        )
+    }
+
+    fn reason(result: Result<Vec<Entry>, FerroxError>) -> String {
+        match result {
+            Err(FerroxError::InvalidArchive { reason }) => reason,
+            other => panic!("{:?}", other.map(|entries| entries.len())),
+        }
+    }
+
+    #[test]
+    fn reads_the_tree() {
+        let data = archive();
+        let root = RarcArchive::entries(&data).unwrap();
+        assert_eq!(root.len(), 1);
+        assert_eq!(root[0].name(), "root");
+        let contents = |path| Entry::find(&root, path).and_then(|entry| entry.contents(&data));
+        assert_eq!(contents("root/a.rel"), Some(&b"AAAA"[..]));
+        assert_eq!(contents("root/sub/b.rel"), Some(&b"BB"[..]));
+        // `.` and `..` aren't part of the tree
+        let Some(Entry::Directory { children, .. }) = Entry::find(&root, "root/sub") else {
+            panic!("sub isn't a folder");
+        };
+        assert_eq!(children.len(), 1);
+    }
+
+    #[test]
+    fn rejects_truncated_archives() {
+        let data = archive();
+        // The info block and tables hold 0x40 + 2 nodes + 7 entries
+        let tables = (0x40 + 2 * NODE_SIZE + 7 * ENTRY_SIZE) as usize;
+        for end in 0..data.len() {
+            // Without the names, `.` and `..` look like any other link, so cutting the strings off can go
+            // either way. It just mustn't panic.
+            let result = RarcArchive::entries(&data[..end]);
+            if end < tables {
+                assert!(result.is_err(), "cut off at 0x{end:X}");
+            } else if end >= data.len() - 6 {
+                assert!(result.is_ok(), "cut off at 0x{end:X}");
+            }
+        }
+        assert!(RarcArchive::entries(b"ARC0").is_err());
+    }
+
+    #[test]
+    fn rejects_folders_that_loop() {
+        // A folder linking back to the root
+        let data = rarc(
+            &[("root", 1, 0), ("sub", 1, 1)],
+            &[(DIRECTORY, "sub", 1, 0x10), (DIRECTORY, "back", 0, 0x10)],
+            &[],
+        );
+        assert_eq!(
+            reason(RarcArchive::entries(&data)),
+            "RARC folder root is in more than one place"
+        );
+
+        // A folder linking to itself
+        let data = rarc(&[("root", 1, 0)], &[(DIRECTORY, "again", 0, 0x10)], &[]);
+        assert_eq!(
+            reason(RarcArchive::entries(&data)),
+            "RARC folder root is in more than one place"
+        );
+
+        // Two links to the same folder
+        let data = rarc(
+            &[("root", 2, 0), ("sub", 0, 2)],
+            &[(DIRECTORY, "one", 1, 0x10), (DIRECTORY, "two", 1, 0x10)],
+            &[],
+        );
+        assert_eq!(
+            reason(RarcArchive::entries(&data)),
+            "RARC folder sub is in more than one place"
+        );
+    }
+
+    #[test]
+    fn rejects_broken_tables() {
+        let data = rarc(&[("root", 1, 0)], &[(DIRECTORY, "sub", 7, 0x10)], &[]);
+        assert_eq!(
+            reason(RarcArchive::entries(&data)),
+            "RARC folder sub links to a missing node"
+        );
+
+        let data = rarc(&[("root", 3, 0)], &[(FILE, "a.rel", 0, 0)], &[]);
+        assert_eq!(
+            reason(RarcArchive::entries(&data)),
+            "RARC folder root runs past the last entry"
+        );
+        let data = rarc(&[("root", 1, u32::MAX)], &[(FILE, "a.rel", 0, 0)], &[]);
+        assert_eq!(
+            reason(RarcArchive::entries(&data)),
+            "RARC folder root runs past the last entry"
+        );
+
+        let data = rarc(&[], &[], &[]);
+        assert_eq!(reason(RarcArchive::entries(&data)), "RARC can't hold 0 folders");
+
+        // Counts and offsets that overflow
+        let word = |mut data: Vec<u8>, at: usize, value: u32| {
+            data[at..at + 4].copy_from_slice(&value.to_be_bytes());
+            data
+        };
+        let data = word(archive(), 0x20, 0x1000_0000);
+        assert_eq!(
+            reason(RarcArchive::entries(&data)),
+            "RARC can't hold 268435456 folders"
+        );
+        let data = word(archive(), 0x24, u32::MAX);
+        assert_eq!(reason(RarcArchive::entries(&data)), "RARC can't hold 2 folders");
+        let data = word(archive(), 0x28, 0x0CCC_CCCD);
+        assert_eq!(
+            reason(RarcArchive::entries(&data)),
+            "RARC can't hold 214748365 entries"
+        );
+        let data = word(archive(), 0x2C, u32::MAX - 0x10);
+        assert_eq!(reason(RarcArchive::entries(&data)), "RARC can't hold 7 entries");
+
+        // Names and files past the end of the archive don't stop the tree being read
+        let data = rarc(&[("root", 1, 0)], &[(FILE, "a.rel", 0, 4)], b"AAAA");
+        let data = word(word(data, 0x34, u32::MAX), 0x0C, u32::MAX);
+        let root = RarcArchive::entries(&data).unwrap();
+        let [Entry::Directory { name, children }] = &root[..] else {
+            panic!("there's no root folder");
+        };
+        assert_eq!(name, "");
+        assert!(matches!(children[..], [Entry::File { size: 4, .. }]));
+        assert!(children[0].contents(&data).is_none());
+    }
+}
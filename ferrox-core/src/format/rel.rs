@@ -0,0 +1,215 @@
+use orthrus_core::prelude::*;
+use snafu::ensure;
+
+use super::{Permissions, Segment};
+use crate::error::{FerroxError, InvalidRelSnafu};
+
+/// Sections have to start after the header, which is 0x40 bytes in the first version
+const MINIMUM_HEADER_SIZE: u32 = 0x40;
+
+// Relocation types, `R_PPC_*` and Dolphin's own ones for walking through the list
+const R_PPC_ADDR32: u8 = 1;
+const R_PPC_ADDR24: u8 = 2;
+const R_PPC_ADDR16: u8 = 3;
+const R_PPC_ADDR16_LO: u8 = 4;
+const R_PPC_ADDR16_HI: u8 = 5;
+const R_PPC_ADDR16_HA: u8 = 6;
+const R_PPC_ADDR14: u8 = 7;
+const R_PPC_ADDR14_BRTAKEN: u8 = 8;
+const R_PPC_ADDR14_BRNTAKEN: u8 = 9;
+const R_PPC_REL24: u8 = 10;
+const R_PPC_REL14: u8 = 11;
+const R_PPC_REL14_BRTAKEN: u8 = 12;
+const R_PPC_REL14_BRNTAKEN: u8 = 13;
+const R_DOLPHIN_NOP: u8 = 201;
+const R_DOLPHIN_SECTION: u8 = 202;
+const R_DOLPHIN_END: u8 = 203;
+
+/// A REL, the relocatable modules GameCube and Wii games load on top of their DOL. Nothing fixes where a module
+/// goes in memory, so each section is mapped at its offset in the file, and BSS goes after the end of it. That
+/// keeps addresses and file offsets the same.
+pub struct RelBinary;
+
+/// What the header says about a module.
+struct Header {
+    id: u32,
+    sections: Vec<(u32, u32)>,
+    bss_size: u32,
+    imports: u32,
+    imports_size: u32,
+    prolog: (u8, u32),
+}
+
+impl RelBinary {
+    fn header(data: &[u8]) -> Result<Header, FerroxError> {
+        let mut cursor = DataCursorRef::new(data, Endian::Big);
+        let id = cursor.read_u32()?;
+        cursor.set_position(0x0C)?;
+        let count = cursor.read_u32()?;
+        let table = cursor.read_u32()?;
+        cursor.set_position(0x1C)?;
+        let version = cursor.read_u32()?;
+        let bss_size = cursor.read_u32()?;
+        cursor.set_position(0x28)?;
+        let imports = cursor.read_u32()?;
+        let imports_size = cursor.read_u32()?;
+        let prolog_section = cursor.read_u8()?;
+        cursor.set_position(0x34)?;
+        let prolog = cursor.read_u32()?;
+        ensure!(
+            (1..=3).contains(&version),
+            InvalidRelSnafu { reason: format!("unknown version {version}") }
+        );
+        ensure!(
+            (1..=256).contains(&count) && table >= MINIMUM_HEADER_SIZE,
+            InvalidRelSnafu { reason: format!("{count} sections at 0x{table:X} can't be right") }
+        );
+
+        cursor.set_position(u64::from(table))?;
+        let mut sections = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            sections.push((cursor.read_u32()?, cursor.read_u32()?));
+        }
+        Ok(Header {
+            id,
+            sections,
+            bss_size,
+            imports,
+            imports_size,
+            prolog: (prolog_section, prolog),
+        })
+    }
+
+    /// Address each section is mapped at, with the low bit of the offset (which marks executable sections)
+    /// cleared. Unused sections and BSS don't have an offset, so BSS is placed after the file.
+    fn addresses(header: &Header, data: &[u8]) -> Vec<u32> {
+        let bss = (data.len() as u32).next_multiple_of(32);
+        header
+            .sections
+            .iter()
+            .map(|&(offset, size)| match offset & !1 {
+                0 if size > 0 => bss,
+                offset => offset,
+            })
+            .collect()
+    }
+
+    pub fn segments(data: &[u8]) -> Result<Vec<Segment<u32>>, FerroxError> {
+        let header = Self::header(data)?;
+        let addresses = Self::addresses(&header, data);
+        let mut segments = Vec::new();
+        for (n, (&(offset, size), &address)) in header.sections.iter().zip(&addresses).enumerate() {
+            if size == 0 {
+                continue;
+            }
+            let segment = match offset {
+                0 => Segment {
+                    name: format!("bss{n}"),
+                    address,
+                    size: size.max(header.bss_size),
+                    offset: 0,
+                    permissions: Permissions::READ | Permissions::WRITE | Permissions::UNINITIALIZED,
+                },
+                _ => {
+                    ensure!(
+                        (offset & !1).checked_add(size).is_some_and(|end| end as usize <= data.len()),
+                        InvalidRelSnafu { reason: format!("section {n} runs past the end of the file") }
+                    );
+                    let (name, permissions) = match offset & 1 {
+                        1 => (format!("text{n}"), Permissions::READ | Permissions::EXECUTE),
+                        _ => (format!("data{n}"), Permissions::READ | Permissions::WRITE),
+                    };
+                    Segment { name, address, size, offset: offset & !1, permissions }
+                }
+            };
+            segments.push(segment);
+        }
+        segments.sort_by_key(|segment| segment.address);
+        Ok(segments)
+    }
+
+    /// Reads the address of `_prolog`, which the game calls once the module is linked.
+    pub fn entry_point(data: &[u8]) -> Result<Option<u32>, FerroxError> {
+        let header = Self::header(data)?;
+        let addresses = Self::addresses(&header, data);
+        let (section, offset) = header.prolog;
+        Ok(addresses.get(section as usize).filter(|_| section != 0).map(|address| address + offset))
+    }
+
+    /// Applies the relocations the module makes against itself and against the DOL, as the game would when it
+    /// links the module in, so branches and pointers lead somewhere. Relocations against other modules are left
+    /// alone, since they aren't loaded. Every relocation overwrites its field rather than adding to it, so
+    /// doing this again to data that's already been relocated changes nothing.
+    pub fn relocate(mut data: Vec<u8>) -> Result<Vec<u8>, FerroxError> {
+        let header = Self::header(&data)?;
+        let addresses = Self::addresses(&header, &data);
+
+        let mut imports = Vec::new();
+        let mut cursor = DataCursorRef::new(&data, Endian::Big);
+        cursor.set_position(u64::from(header.imports))?;
+        for _ in 0..header.imports_size / 8 {
+            imports.push((cursor.read_u32()?, cursor.read_u32()?));
+        }
+
+        let mut fixups = Vec::new();
+        for (module, offset) in imports {
+            if module != header.id && module != 0 {
+                continue;
+            }
+            cursor.set_position(u64::from(offset))?;
+            let mut position = 0u32;
+            loop {
+                let delta = cursor.read_u16()?;
+                let kind = cursor.read_u8()?;
+                let section = cursor.read_u8()?;
+                let addend = cursor.read_u32()?;
+                match kind {
+                    R_DOLPHIN_END => break,
+                    R_DOLPHIN_SECTION => {
+                        position = addresses.get(section as usize).copied().unwrap_or_default();
+                        continue;
+                    }
+                    _ => position = position.wrapping_add(u32::from(delta)),
+                }
+                if kind == R_DOLPHIN_NOP {
+                    continue;
+                }
+                let target = match module {
+                    0 => addend,
+                    _ => addresses.get(section as usize).copied().unwrap_or_default().wrapping_add(addend),
+                };
+                fixups.push((position, kind, target));
+            }
+        }
+
+        for (position, kind, target) in fixups {
+            let at = position as usize;
+            let Some(bytes) = data.get_mut(at..at + 4) else {
+                continue;
+            };
+            let word = u32::from_be_bytes(bytes.try_into().unwrap());
+            let relative = target.wrapping_sub(position);
+            let (word, halfword) = match kind {
+                R_PPC_ADDR32 => (Some(target), None),
+                R_PPC_ADDR24 => (Some(word & !0x03FF_FFFC | target & 0x03FF_FFFC), None),
+                R_PPC_ADDR16 | R_PPC_ADDR16_LO => (None, Some(target as u16)),
+                R_PPC_ADDR16_HI => (None, Some((target >> 16) as u16)),
+                R_PPC_ADDR16_HA => (None, Some((target.wrapping_add(0x8000) >> 16) as u16)),
+                R_PPC_ADDR14 | R_PPC_ADDR14_BRTAKEN | R_PPC_ADDR14_BRNTAKEN => {
+                    (Some(word & !0xFFFC | target & 0xFFFC), None)
+                }
+                R_PPC_REL24 => (Some(word & !0x03FF_FFFC | relative & 0x03FF_FFFC), None),
+                R_PPC_REL14 | R_PPC_REL14_BRTAKEN | R_PPC_REL14_BRNTAKEN => {
+                    (Some(word & !0xFFFC | relative & 0xFFFC), None)
+                }
+                _ => (None, None),
+            };
+            if let Some(word) = word {
+                bytes.copy_from_slice(&word.to_be_bytes());
+            } else if let Some(halfword) = halfword {
+                bytes[..2].copy_from_slice(&halfword.to_be_bytes());
+            }
+        }
+        Ok(data)
+    }
+}
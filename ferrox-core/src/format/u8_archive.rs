@@ -0,0 +1,114 @@
+use orthrus_core::prelude::*;
+use snafu::ensure;
+
+use super::{fst, Entry};
+use crate::error::{FerroxError, InvalidArchiveSnafu};
+
+const MAGIC: u32 = 0x55AA382D;
+
+/// A U8 archive (`.arc`), which Wii games pack their RELs and resources into. Its files are listed with the same
+/// FST a disc uses, and their offsets are from the start of the archive.
+pub struct U8Archive;
+
+impl U8Archive {
+    pub fn is_archive(data: &[u8]) -> bool {
+        data.starts_with(&MAGIC.to_be_bytes())
+    }
+
+    /// Reads the tree of files in the archive.
+    pub fn entries(data: &[u8]) -> Result<Vec<Entry>, FerroxError> {
+        ensure!(
+            Self::is_archive(data),
+            InvalidArchiveSnafu { reason: "missing U8 magic" }
+        );
+        let mut cursor = DataCursorRef::new(data, Endian::Big);
+        cursor.set_position(4)?;
+        let (offset, size) = (cursor.read_u32()? as usize, cursor.read_u32()? as usize);
+        let table = data.get(offset..offset + size).ok_or_else(|| FerroxError::InvalidArchive {
+            reason: "U8 file table runs past the end of the archive".to_owned(),
+        })?;
+        fst::parse(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fst;
+
+    /// Builds an archive with its table at 0x20, followed by `contents`. File offsets are from the start of
+    /// the archive, so they have to allow for the table.
+    fn archive(table: &[u8], contents: &[u8]) -> Vec<u8> {
+        let mut data = MAGIC.to_be_bytes().to_vec();
+        let data_offset = (0x20 + table.len() as u32).next_multiple_of(0x20);
+        for word in [0x20, table.len() as u32, data_offset] {
+            data.extend_from_slice(&word.to_be_bytes());
+        }
+        data.resize(0x20, 0);
+        data.extend_from_slice(table);
+        data.resize(data_offset as usize, 0);
+        data.extend_from_slice(contents);
+        data
+    }
+
+    fn reason(result: Result<Vec<Entry>, FerroxError>) -> String {
+        match result {
+            Err(FerroxError::InvalidArchive { reason } | FerroxError::InvalidFst { reason }) => reason,
+            other => panic!("{:?}", other.map(|entries| entries.len())),
+        }
+    }
+
+    #[test]
+    fn reads_the_tree() {
+        // The table takes up 4 entries and 17 bytes of names, so the data starts at 0x80
+        let table = fst(&[
+            (false, "a.rel", 0x80, 4),
+            (true, "sub", 0, 4),
+            (false, "b.rel", 0x84, 2),
+        ]);
+        let data = archive(&table, b"AAAABB");
+        let root = U8Archive::entries(&data).unwrap();
+        let contents = |path| Entry::find(&root, path).and_then(|entry| entry.contents(&data));
+        assert_eq!(contents("a.rel"), Some(&b"AAAA"[..]));
+        assert_eq!(contents("sub/b.rel"), Some(&b"BB"[..]));
+    }
+
+    #[test]
+    fn rejects_truncated_archives() {
+        let table = fst(&[
+            (false, "a.rel", 0x80, 4),
+            (true, "sub", 0, 4),
+            (false, "b.rel", 0x84, 2),
+        ]);
+        let data = archive(&table, b"AAAABB");
+        for end in 0..data.len() {
+            let result = U8Archive::entries(&data[..end]);
+            assert_eq!(result.is_ok(), end >= 0x20 + table.len(), "cut off at 0x{end:X}");
+        }
+        assert_eq!(reason(U8Archive::entries(b"U8")), "missing U8 magic");
+    }
+
+    #[test]
+    fn rejects_broken_tables() {
+        let word = |mut data: Vec<u8>, at: usize, value: u32| {
+            data[at..at + 4].copy_from_slice(&value.to_be_bytes());
+            data
+        };
+        let data = archive(&fst(&[(false, "a.rel", 0, 0)]), &[]);
+        let expected = "U8 file table runs past the end of the archive";
+        assert_eq!(
+            reason(U8Archive::entries(&word(data.clone(), 0x04, u32::MAX))),
+            expected
+        );
+        assert_eq!(
+            reason(U8Archive::entries(&word(data.clone(), 0x08, u32::MAX))),
+            expected
+        );
+
+        // A folder that ends where it starts would be read forever
+        let data = archive(&fst(&[(true, "sub", 0, 1)]), &[]);
+        assert_eq!(reason(U8Archive::entries(&data)), "folder sub ends at entry 1");
+        let data = word(archive(&fst(&[]), &[]), 0x28, 0);
+        assert_eq!(reason(U8Archive::entries(&data)), "can't hold 0 entries");
+    }
+}
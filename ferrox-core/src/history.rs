@@ -0,0 +1,210 @@
+//! Undo/redo support. Every user edit to a [`Program`](crate::program::Program) is recorded as an [`Edit`]
+//! holding both the old and new state, so it can be applied in either direction.
+use crate::program::OperandFormat;
+use crate::registry::{Prototype, TypeInfo};
+
+/// A single reversible change made by the user.
+#[derive(Debug, Clone)]
+pub enum Edit {
+    Rename {
+        address: u32,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    Comment {
+        address: u32,
+        repeatable: bool,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    OperandFormat {
+        address: u32,
+        operand: usize,
+        old: OperandFormat,
+        new: OperandFormat,
+    },
+    DefineType {
+        name: String,
+        old: Option<TypeInfo>,
+        new: Option<TypeInfo>,
+    },
+    Prototype {
+        address: u32,
+        old: Option<Prototype>,
+        new: Option<Prototype>,
+    },
+    /// Name for a register or stack variable inside one function, which `variable` is the automatic name of
+    RenameVariable {
+        function: u32,
+        variable: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    /// Bytes written over the binary, e.g. by assembling an instruction
+    Patch {
+        address: u32,
+        old: Vec<u8>,
+        new: Vec<u8>,
+    },
+    /// Several edits that are undone together, like importing a map
+    Batch { description: String, edits: Vec<Edit> },
+}
+
+impl Edit {
+    /// Short summary for the history panel.
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Rename { address, new: Some(name), .. } => format!("Rename 0x{address:08X} to {name}"),
+            Self::Rename { address, new: None, .. } => format!("Reset name of 0x{address:08X}"),
+            Self::Comment { address, repeatable, new, .. } => {
+                let kind = if *repeatable {
+                    "repeatable comment"
+                } else {
+                    "comment"
+                };
+                match new {
+                    Some(_) => format!("Set {kind} at 0x{address:08X}"),
+                    None => format!("Remove {kind} at 0x{address:08X}"),
+                }
+            }
+            Self::OperandFormat { address, operand, new, .. } => {
+                format!("Show operand {operand} at 0x{address:08X} as {new:?}")
+            }
+            Self::DefineType { name, new: Some(_), old: None } => format!("Create type {name}"),
+            Self::DefineType { name, new: Some(_), .. } => format!("Edit type {name}"),
+            Self::DefineType { name, new: None, .. } => format!("Delete type {name}"),
+            Self::Prototype { address, new: Some(_), .. } => format!("Set prototype of 0x{address:08X}"),
+            Self::Prototype { address, new: None, .. } => format!("Reset prototype of 0x{address:08X}"),
+            Self::RenameVariable { function, variable, new: Some(name), .. } => {
+                format!("Rename {variable} in 0x{function:08X} to {name}")
+            }
+            Self::RenameVariable { function, variable, new: None, .. } => {
+                format!("Reset name of {variable} in 0x{function:08X}")
+            }
+            Self::Patch { address, new, .. } => format!("Patch {} bytes at 0x{address:08X}", new.len()),
+            Self::Batch { description, .. } => description.clone(),
+        }
+    }
+
+    /// Whether applying this edit wouldn't actually change anything.
+    pub fn is_noop(&self) -> bool {
+        match self {
+            Self::Rename { old, new, .. }
+            | Self::Comment { old, new, .. }
+            | Self::RenameVariable { old, new, .. } => old == new,
+            Self::OperandFormat { old, new, .. } => old == new,
+            Self::Patch { old, new, .. } => old == new,
+            Self::DefineType { old, new, .. } => old == new,
+            Self::Prototype { old, new, .. } => old == new,
+            Self::Batch { edits, .. } => edits.iter().all(Edit::is_noop),
+        }
+    }
+}
+
+/// Linear edit log, with everything past `position` being available to redo.
+#[derive(Debug, Default)]
+pub struct History {
+    edits: Vec<Edit>,
+    position: usize,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuilds a log that was saved to disk, with the first `position` edits already applied.
+    pub fn from_parts(edits: Vec<Edit>, position: usize) -> Self {
+        let position = position.min(edits.len());
+        Self { edits, position }
+    }
+
+    /// Records a new edit, throwing away anything that could've been redone.
+    pub fn push(&mut self, edit: Edit) {
+        self.edits.truncate(self.position);
+        self.edits.push(edit);
+        self.position += 1;
+    }
+
+    /// Steps back one edit, returning it so it can be reverted.
+    pub fn undo(&mut self) -> Option<&Edit> {
+        self.position = self.position.checked_sub(1)?;
+        self.edits.get(self.position)
+    }
+
+    /// Steps forward one edit, returning it so it can be applied again.
+    pub fn redo(&mut self) -> Option<&Edit> {
+        let edit = self.edits.get(self.position)?;
+        self.position += 1;
+        Some(edit)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.position > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.position < self.edits.len()
+    }
+
+    /// Number of edits currently applied.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename(address: u32) -> Edit {
+        Edit::Rename { address, old: None, new: Some(format!("lbl_{address:08X}")) }
+    }
+
+    #[test]
+    fn steps_through_edits() {
+        let mut history = History::new();
+        assert!(history.undo().is_none() && history.redo().is_none());
+        history.push(rename(1));
+        history.push(rename(2));
+        assert!(matches!(history.undo(), Some(Edit::Rename { address: 2, .. })));
+        assert!(matches!(history.undo(), Some(Edit::Rename { address: 1, .. })));
+        assert!(!history.can_undo());
+        assert!(matches!(history.redo(), Some(Edit::Rename { address: 1, .. })));
+
+        // A new edit replaces everything that could've been redone
+        history.push(rename(3));
+        assert!(!history.can_redo());
+        assert_eq!(history.position(), 2);
+        assert!(matches!(
+            history.edits(),
+            [Edit::Rename { address: 1, .. }, Edit::Rename { address: 3, .. }]
+        ));
+    }
+
+    #[test]
+    fn rebuilds_saved_logs() {
+        let history = History::from_parts(vec![rename(1), rename(2)], 5);
+        assert_eq!(history.position(), 2);
+        let mut history = History::from_parts(vec![rename(1), rename(2)], 1);
+        assert!(history.can_undo() && history.can_redo());
+        assert!(matches!(history.redo(), Some(Edit::Rename { address: 2, .. })));
+    }
+
+    #[test]
+    fn describes_edits() {
+        assert_eq!(
+            rename(0x8000_3100).describe(),
+            "Rename 0x80003100 to lbl_80003100"
+        );
+        let patch = Edit::Patch { address: 0x8000_3100, old: vec![0; 4], new: vec![0; 4] };
+        assert!(patch.is_noop());
+        let batch = Edit::Batch { description: "Import map".to_owned(), edits: vec![patch, rename(1)] };
+        assert!(!batch.is_noop());
+        assert_eq!(batch.describe(), "Import map");
+    }
+}
@@ -0,0 +1,1260 @@
+//! Lifting decoded Gekko instructions into the IR, one basic block at a time. Calls and returns follow the
+//! EABI: a call reads the argument registers and clobbers every volatile one, and returning hands back every
+//! register the caller is allowed to rely on.
+use std::collections::BTreeMap;
+
+use super::{
+    Access, AccessKind, Block, BlockId, Callee, IrFunction, Op, Operand, Register, Statement, Terminator,
+    Variable,
+};
+use crate::processor::gekko::Field::*;
+use crate::processor::gekko::{self, Flow, Instruction, Suffix};
+use crate::program::{BasicBlock, EdgeKind, JumpTable, Program};
+
+/// Lifts the function starting at `address`, which analysis has to have found already. Nothing gets simplified
+/// here, [`super::ssa`] takes care of that.
+pub fn lift(program: &Program, address: u32) -> Option<IrFunction> {
+    let function = program.functions.get(&address)?;
+    if !function.blocks.contains_key(&address) {
+        return None;
+    }
+
+    // The entry block comes first, everything else stays in address order
+    let mut order = vec![address];
+    order.extend(function.blocks.keys().copied().filter(|&start| start != address));
+    let ids = order.iter().enumerate().map(|(id, &start)| (start, id)).collect();
+
+    let mut lifter = Lifter {
+        program,
+        ids,
+        jump_tables: &function.jump_tables,
+        switches: BTreeMap::new(),
+        statements: Vec::new(),
+        synthetic: Vec::new(),
+        temps: 0,
+    };
+    let mut blocks = Vec::new();
+    for start in order {
+        let block = &function.blocks[&start];
+        let mut terminator = Terminator::Unknown;
+        for address in (block.start..block.end).step_by(4) {
+            let Some(instruction) = program.instruction(address) else {
+                break;
+            };
+            // The index a switch uses usually gets clobbered before the `bctr`, so hold on to it where it's
+            // checked against the size of the table
+            for (&bctr, table) in lifter.jump_tables {
+                if table.compare == address {
+                    let value = lifter.temp(Op::Copy, &[gpr(table.index.into())]);
+                    lifter.switches.insert(bctr, value);
+                }
+            }
+            if address + 4 == block.end {
+                terminator = lifter.terminator(&instruction, block);
+            } else {
+                lifter.instruction(&instruction);
+            }
+        }
+        blocks.push(Block {
+            address: start,
+            statements: std::mem::take(&mut lifter.statements),
+            terminator,
+        });
+    }
+    blocks.append(&mut lifter.synthetic);
+    Some(IrFunction { address, blocks })
+}
+
+/// Registers a call reads: the stack pointer, both small data bases, and the argument registers.
+pub(crate) fn call_arguments() -> Vec<Register> {
+    let mut registers: Vec<Register> = [1, 2, 13].into_iter().chain(3..=10).map(Register::Gpr).collect();
+    registers.extend((1..=8).map(Register::Fpr));
+    registers
+}
+
+/// Registers a callee is free to change.
+fn volatile_registers() -> Vec<Register> {
+    let mut registers: Vec<Register> = [0].into_iter().chain(3..=12).map(Register::Gpr).collect();
+    registers.extend((0..=13).map(Register::Fpr));
+    registers.extend((0..=13).map(Register::Ps1));
+    registers
+        .extend([0, 1, 5, 6, 7].into_iter().flat_map(|field| field * 4..field * 4 + 4).map(Register::CrBit));
+    registers.extend([
+        Register::Lr,
+        Register::Ctr,
+        Register::Carry,
+        Register::Overflow,
+        Register::SummaryOverflow,
+    ]);
+    registers
+}
+
+/// Registers the caller can see after a return: the return values, and everything non-volatile.
+fn return_registers() -> Vec<Register> {
+    let mut registers: Vec<Register> = [1, 2, 3, 4].into_iter().chain(13..=31).map(Register::Gpr).collect();
+    registers.extend([1].into_iter().chain(14..=31).map(Register::Fpr));
+    registers.extend((14..=31).map(Register::Ps1));
+    registers.extend((8..20).map(Register::CrBit));
+    registers
+}
+
+fn gpr(n: u32) -> Operand {
+    Register::Gpr(n as u8).into()
+}
+
+fn fpr(n: u32) -> Operand {
+    Register::Fpr(n as u8).into()
+}
+
+/// One half of a paired single.
+fn lane(n: u32, lane: usize) -> Operand {
+    match lane {
+        0 => Register::Fpr(n as u8).into(),
+        _ => Register::Ps1(n as u8).into(),
+    }
+}
+
+fn cr_bit(field: u32, bit: u32) -> Register {
+    Register::CrBit((field * 4 + bit) as u8)
+}
+
+/// Mask `rlwinm` and friends use, which wraps around if `mb > me`.
+fn rotate_mask(mb: u32, me: u32) -> u32 {
+    let (start, end) = (u32::MAX >> mb, u32::MAX << (31 - me));
+    if mb <= me {
+        start & end
+    } else {
+        start | end
+    }
+}
+
+struct Lifter<'a> {
+    program: &'a Program,
+    /// Block each instruction address starts
+    ids: BTreeMap<u32, BlockId>,
+    jump_tables: &'a BTreeMap<u32, JumpTable>,
+    /// Index each jump table is switching on, keyed by the address of its `bctr`
+    switches: BTreeMap<u32, Operand>,
+    /// Statements for the block being lifted
+    statements: Vec<Statement>,
+    /// Empty blocks made up to hold a terminator, for conditional returns and the like
+    synthetic: Vec<Block>,
+    temps: u32,
+}
+
+impl Lifter<'_> {
+    fn assign(&mut self, dest: Register, op: Op, args: &[Operand]) {
+        self.statements.push(Statement::Assign { dest: Variable::new(dest), op, args: args.to_vec() });
+    }
+
+    /// Assigns to a new temporary and returns it.
+    fn temp(&mut self, op: Op, args: &[Operand]) -> Operand {
+        let dest = Register::Temp(self.temps);
+        self.temps += 1;
+        self.assign(dest, op, args);
+        dest.into()
+    }
+
+    fn not(&mut self, bit: Operand) -> Operand {
+        self.temp(Op::Xor, &[bit, Operand::Constant(1)])
+    }
+
+    fn intrinsic(&mut self, name: &'static str, arguments: &[Operand], results: &[Register]) {
+        self.statements.push(Statement::Intrinsic {
+            name,
+            arguments: arguments.to_vec(),
+            results: results.iter().copied().map(Variable::new).collect(),
+        });
+    }
+
+    fn load(&mut self, dest: Register, size: u8, kind: AccessKind, address: Operand) {
+        let access = Access { size, kind };
+        self.statements.push(Statement::Load { dest: Variable::new(dest), access, address });
+    }
+
+    fn store(&mut self, size: u8, kind: AccessKind, address: Operand, value: Operand) {
+        self.statements.push(Statement::Store { access: Access { size, kind }, address, value });
+    }
+
+    /// Sets a condition register field from comparing two integers, with `so` copied out of XER.
+    fn compare(&mut self, field: u32, a: Operand, b: Operand, signed: bool) {
+        let (less, greater) = match signed {
+            true => (Op::LessSigned, Op::GreaterSigned),
+            false => (Op::LessUnsigned, Op::GreaterUnsigned),
+        };
+        self.assign(cr_bit(field, 0), less, &[a, b]);
+        self.assign(cr_bit(field, 1), greater, &[a, b]);
+        self.assign(cr_bit(field, 2), Op::Equal, &[a, b]);
+        self.assign(cr_bit(field, 3), Op::Copy, &[Register::SummaryOverflow.into()]);
+    }
+
+    fn float_compare(&mut self, field: u32, a: Operand, b: Operand) {
+        self.assign(cr_bit(field, 0), Op::FloatLess, &[a, b]);
+        self.assign(cr_bit(field, 1), Op::FloatGreater, &[a, b]);
+        self.assign(cr_bit(field, 2), Op::FloatEqual, &[a, b]);
+        self.assign(cr_bit(field, 3), Op::FloatUnordered, &[a, b]);
+    }
+
+    /// The `.` suffix on a floating point instruction copies FPSCR's exception summary into cr1.
+    fn record_float(&mut self, instruction: &Instruction) {
+        if instruction.record() {
+            let results: Vec<Register> = (0..4).map(|bit| cr_bit(1, bit)).collect();
+            self.intrinsic("fpscr_exceptions", &[], &results);
+        }
+    }
+
+    /// Effective address of a `d(rA)` access. r0 reads as zero here, unless the instruction updates it.
+    fn displacement(&mut self, ra: u32, displacement: i32, update: bool) -> Operand {
+        match (ra, displacement) {
+            (0, _) if !update => Operand::Constant(displacement as u32),
+            (_, 0) => gpr(ra),
+            _ => self.temp(Op::Add, &[gpr(ra), Operand::Constant(displacement as u32)]),
+        }
+    }
+
+    /// Effective address of an `rA, rB` access, with the same rule for r0.
+    fn indexed(&mut self, ra: u32, rb: u32, update: bool) -> Operand {
+        match ra {
+            0 if !update => gpr(rb),
+            _ => self.temp(Op::Add, &[gpr(ra), gpr(rb)]),
+        }
+    }
+
+    fn offset(&mut self, base: Operand, offset: u32) -> Operand {
+        match base {
+            _ if offset == 0 => base,
+            Operand::Constant(address) => Operand::Constant(address.wrapping_add(offset)),
+            Operand::Variable(_) => self.temp(Op::Add, &[base, Operand::Constant(offset)]),
+        }
+    }
+
+    /// Works out whether a conditional branch is taken, decrementing CTR first if the branch uses it. Returns
+    /// `None` if the branch is always taken.
+    fn condition(&mut self, instruction: &Instruction) -> Option<Operand> {
+        let (bo, bi) = (instruction.field(BO), instruction.field(BI));
+        let mut conditions = Vec::new();
+        if bo & 0x04 == 0 {
+            let ctr = Operand::from(Register::Ctr);
+            self.assign(Register::Ctr, Op::Sub, &[ctr, Operand::Constant(1)]);
+            let op = if bo & 0x02 != 0 { Op::Equal } else { Op::NotEqual };
+            conditions.push(self.temp(op, &[ctr, Operand::Constant(0)]));
+        }
+        if bo & 0x10 == 0 {
+            let bit = Register::CrBit(bi as u8).into();
+            conditions.push(if bo & 0x08 != 0 { bit } else { self.not(bit) });
+        }
+        conditions.into_iter().reduce(|a, b| self.temp(Op::And, &[a, b]))
+    }
+
+    fn call(&mut self, callee: Callee, condition: Option<Operand>) {
+        self.statements.push(Statement::Call {
+            callee,
+            condition,
+            arguments: call_arguments().into_iter().map(Operand::from).collect(),
+            results: volatile_registers().into_iter().map(Variable::new).collect(),
+        });
+    }
+
+    /// Makes up an empty block that ends with `terminator`.
+    fn synthetic(&mut self, address: u32, terminator: Terminator) -> BlockId {
+        self.synthetic.push(Block { address, statements: Vec::new(), terminator });
+        self.ids.len() + self.synthetic.len() - 1
+    }
+
+    /// How control leaves the function when it goes to `target`, which isn't one of its blocks.
+    fn leave(&self, target: u32) -> Terminator {
+        if !self.program.functions.contains_key(&target) {
+            return Terminator::Unknown;
+        }
+        let mut registers = call_arguments();
+        let arguments = registers.clone();
+        registers.extend(return_registers().into_iter().filter(|register| !arguments.contains(register)));
+        let values = registers.into_iter().map(|register| (register, register.into())).collect();
+        Terminator::TailCall { target, values }
+    }
+
+    /// The switch a `bctr` makes through a jump table, if all of the table's targets are in this function.
+    fn switch(&self, bctr: u32) -> Option<Terminator> {
+        let value = *self.switches.get(&bctr)?;
+        let targets: Option<Vec<BlockId>> =
+            self.jump_tables[&bctr].targets.iter().map(|target| self.ids.get(target).copied()).collect();
+        Some(Terminator::Switch { value, targets: targets? })
+    }
+
+    /// Lifts the last instruction of a block, along with how control leaves it.
+    fn terminator(&mut self, instruction: &Instruction, block: &BasicBlock) -> Terminator {
+        let address = instruction.address;
+        let next = address + 4;
+        let edge =
+            |kind| block.successors.iter().find(|edge| edge.kind == kind).map(|edge| self.ids[&edge.target]);
+        let (unconditional, taken, not_taken) = (
+            edge(EdgeKind::Unconditional),
+            edge(EdgeKind::True),
+            edge(EdgeKind::False),
+        );
+
+        let flow = instruction.flow();
+        let (exit, condition) = match flow {
+            Flow::Branch { target, conditional: false, link: false } => {
+                return unconditional.map_or_else(|| self.leave(target), Terminator::Jump);
+            }
+            Flow::Branch { target, conditional: true, link: false } => {
+                let condition = self.condition(instruction);
+                let exit = taken.ok_or_else(|| self.leave(target));
+                (exit, condition)
+            }
+            Flow::Return { conditional } | Flow::Indirect { conditional, link: false } => {
+                let exit = match flow {
+                    Flow::Return { .. } => Terminator::Return {
+                        values: return_registers()
+                            .into_iter()
+                            .map(|register| (register, register.into()))
+                            .collect(),
+                    },
+                    _ => self
+                        .switch(address)
+                        .unwrap_or(Terminator::IndirectJump { target: Register::Ctr.into() }),
+                };
+                match conditional {
+                    true => (Err(exit), self.condition(instruction)),
+                    false => return exit,
+                }
+            }
+            _ => {
+                self.instruction(instruction);
+                return unconditional.map_or_else(|| self.leave(next), Terminator::Jump);
+            }
+        };
+
+        // Only conditional branches make it this far, so there's always a condition
+        let condition = condition.unwrap_or(Operand::Constant(1));
+        let taken = exit.unwrap_or_else(|exit| self.synthetic(address, exit));
+        let not_taken = not_taken.unwrap_or_else(|| {
+            let exit = self.leave(next);
+            self.synthetic(address, exit)
+        });
+        Terminator::Branch { condition, taken, not_taken }
+    }
+
+    /// Lifts an instruction that doesn't end its block, which includes calls.
+    fn instruction(&mut self, instruction: &Instruction) {
+        match instruction.flow() {
+            // `bcl 20, 31, $+4` only exists to read the program counter
+            Flow::Branch { target, conditional: false, link: true } if target == instruction.address + 4 => {
+                self.assign(Register::Lr, Op::Copy, &[Operand::Constant(target)]);
+            }
+            Flow::Branch { target, conditional, link: true } => {
+                let condition = if conditional {
+                    self.condition(instruction)
+                } else {
+                    None
+                };
+                self.call(Callee::Direct(target), condition);
+            }
+            Flow::Indirect { conditional, link: true } => {
+                let condition = if conditional {
+                    self.condition(instruction)
+                } else {
+                    None
+                };
+                let target = match instruction.form.mnemonic {
+                    "bclr" => Register::Lr,
+                    _ => Register::Ctr,
+                };
+                self.call(Callee::Indirect(target.into()), condition);
+            }
+            _ => {
+                if !(self.memory(instruction) || self.float(instruction) || self.paired(instruction)) {
+                    self.integer(instruction);
+                }
+            }
+        }
+    }
+
+    /// Loads, stores and cache control.
+    fn memory(&mut self, instruction: &Instruction) -> bool {
+        let mnemonic = instruction.form.mnemonic;
+        let field = |field| instruction.field(field);
+        let (rd, ra, rb) = (field(RD), field(RA), field(RB));
+        let displacement = field(D) as u16 as i16 as i32;
+
+        // Most of them are the same few sizes with optional update (`u`) and indexed (`x`) forms
+        let base = mnemonic.trim_end_matches(['u', 'x']);
+        let access = match base {
+            "lbz" | "stb" => Some((1, AccessKind::Unsigned)),
+            "lhz" | "sth" => Some((2, AccessKind::Unsigned)),
+            "lha" => Some((2, AccessKind::Signed)),
+            "lwz" | "stw" => Some((4, AccessKind::Unsigned)),
+            "lfs" | "stfs" => Some((4, AccessKind::Float)),
+            "lfd" | "stfd" => Some((8, AccessKind::Float)),
+            _ => None,
+        };
+        if let Some((size, kind)) = access {
+            let update = mnemonic[base.len()..].contains('u');
+            let address = match mnemonic.ends_with('x') {
+                true => self.indexed(ra, rb, update),
+                false => self.displacement(ra, displacement, update),
+            };
+            match (base.starts_with('l'), kind) {
+                (true, AccessKind::Float) => {
+                    let frd = field(FRD);
+                    self.load(Register::Fpr(frd as u8), size, kind, address);
+                    // Single precision loads fill in both halves of a paired single
+                    if base == "lfs" {
+                        self.assign(Register::Ps1(frd as u8), Op::Copy, &[fpr(frd)]);
+                    }
+                }
+                (true, _) => self.load(Register::Gpr(rd as u8), size, kind, address),
+                (false, AccessKind::Float) => self.store(size, kind, address, fpr(field(FRS))),
+                (false, _) => self.store(size, kind, address, gpr(field(RS))),
+            }
+            if update {
+                self.assign(Register::Gpr(ra as u8), Op::Copy, &[address]);
+            }
+            return true;
+        }
+
+        match mnemonic {
+            "lmw" | "stmw" => {
+                let address = self.displacement(ra, displacement, false);
+                for (n, register) in (rd..32).enumerate() {
+                    let address = self.offset(address, n as u32 * 4);
+                    match mnemonic {
+                        "lmw" => self.load(Register::Gpr(register as u8), 4, AccessKind::Unsigned, address),
+                        _ => self.store(4, AccessKind::Unsigned, address, gpr(register)),
+                    }
+                }
+            }
+            "lhbrx" | "lwbrx" => {
+                let address = self.indexed(ra, rb, false);
+                let (size, op) = if mnemonic == "lhbrx" {
+                    (2, Op::ByteSwap16)
+                } else {
+                    (4, Op::ByteSwap32)
+                };
+                let value = Register::Temp(self.temps);
+                self.temps += 1;
+                self.load(value, size, AccessKind::Unsigned, address);
+                self.assign(Register::Gpr(rd as u8), op, &[value.into()]);
+            }
+            "sthbrx" | "stwbrx" => {
+                let address = self.indexed(ra, rb, false);
+                let (size, op) = if mnemonic == "sthbrx" {
+                    (2, Op::ByteSwap16)
+                } else {
+                    (4, Op::ByteSwap32)
+                };
+                let value = self.temp(op, &[gpr(field(RS))]);
+                self.store(size, AccessKind::Unsigned, address, value);
+            }
+            "lwarx" => {
+                let address = self.indexed(ra, rb, false);
+                self.intrinsic("reserve", &[address], &[]);
+                self.load(Register::Gpr(rd as u8), 4, AccessKind::Unsigned, address);
+            }
+            "stwcx." => {
+                let address = self.indexed(ra, rb, false);
+                let results: Vec<Register> = (0..4).map(|bit| cr_bit(0, bit)).collect();
+                self.intrinsic(
+                    "store_conditional",
+                    &[address, gpr(field(RS)), Register::SummaryOverflow.into()],
+                    &results,
+                );
+            }
+            "stfiwx" => {
+                let address = self.indexed(ra, rb, false);
+                self.intrinsic("stfiwx", &[address, fpr(field(FRS))], &[]);
+            }
+            "lswi" | "stswi" => {
+                let address = self.displacement(ra, 0, false);
+                let count = match field(NB) {
+                    0 => 32,
+                    count => count,
+                };
+                let registers: Vec<Register> =
+                    (0..count.div_ceil(4)).map(|n| Register::Gpr(((rd + n) % 32) as u8)).collect();
+                match mnemonic {
+                    "lswi" => self.intrinsic("lswi", &[address, Operand::Constant(count)], &registers),
+                    _ => {
+                        let mut arguments = vec![address, Operand::Constant(count)];
+                        arguments.extend(registers.into_iter().map(Operand::from));
+                        self.intrinsic("stswi", &arguments, &[]);
+                    }
+                }
+            }
+            // The byte count comes from XER, which could cover any number of registers
+            "lswx" | "stswx" => {
+                let address = self.indexed(ra, rb, false);
+                let registers: Vec<Register> =
+                    (0..32).map(|n| Register::Gpr(((rd + n) % 32) as u8)).collect();
+                match mnemonic {
+                    "lswx" => self.intrinsic("lswx", &[address, Register::Spr(1).into()], &registers),
+                    _ => {
+                        let mut arguments = vec![address, Register::Spr(1).into()];
+                        arguments.extend(registers.into_iter().map(Operand::from));
+                        self.intrinsic("stswx", &arguments, &[]);
+                    }
+                }
+            }
+            "dcbf" | "dcbi" | "dcbst" | "dcbt" | "dcbtst" | "dcbz" | "dcbz_l" | "icbi" => {
+                let address = self.indexed(ra, rb, false);
+                self.intrinsic(mnemonic, &[address], &[]);
+            }
+            "eciwx" => {
+                let address = self.indexed(ra, rb, false);
+                self.intrinsic("eciwx", &[address], &[Register::Gpr(rd as u8)]);
+            }
+            "ecowx" => {
+                let address = self.indexed(ra, rb, false);
+                self.intrinsic("ecowx", &[address, gpr(field(RS))], &[]);
+            }
+            "psq_l" | "psq_lu" | "psq_lx" | "psq_lux" | "psq_st" | "psq_stu" | "psq_stx" | "psq_stux" => {
+                let update = mnemonic.ends_with('u') || mnemonic.ends_with("ux");
+                let (address, w, i) = match mnemonic.ends_with('x') {
+                    true => (self.indexed(ra, rb, update), field(PsWX), field(PsIX)),
+                    false => {
+                        let displacement = ((field(PsD) << 20) as i32) >> 20;
+                        (
+                            self.displacement(ra, displacement, update),
+                            field(PsW),
+                            field(PsI),
+                        )
+                    }
+                };
+                // Which GQR to use is part of the instruction, so the quantization settings are an argument
+                let (w, gqr) = (Operand::Constant(w), Register::Spr(912 + i as u16).into());
+                let frd = field(FRD);
+                match mnemonic.starts_with("psq_l") {
+                    true => {
+                        let results = [Register::Fpr(frd as u8), Register::Ps1(frd as u8)];
+                        self.intrinsic("psq_l", &[address, w, gqr], &results);
+                    }
+                    false => self.intrinsic("psq_st", &[address, lane(frd, 0), lane(frd, 1), w, gqr], &[]),
+                }
+                if update {
+                    self.assign(Register::Gpr(ra as u8), Op::Copy, &[address]);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Floating point arithmetic and FPSCR access.
+    fn float(&mut self, instruction: &Instruction) -> bool {
+        let mnemonic = instruction.form.mnemonic;
+        let field = |field| instruction.field(field);
+        let (d, a, b, c) = (field(FRD), fpr(field(FRA)), fpr(field(FRB)), fpr(field(FRC)));
+
+        let value = match mnemonic {
+            "fadd" | "fadds" => Some((Op::FloatAdd, vec![a, b])),
+            "fsub" | "fsubs" => Some((Op::FloatSub, vec![a, b])),
+            "fmul" | "fmuls" => Some((Op::FloatMul, vec![a, c])),
+            "fdiv" | "fdivs" => Some((Op::FloatDiv, vec![a, b])),
+            "fmadd" | "fmadds" => Some((Op::FloatMulAdd, vec![a, c, b])),
+            "fmsub" | "fmsubs" => {
+                let negated = self.temp(Op::FloatNeg, &[b]);
+                Some((Op::FloatMulAdd, vec![a, c, negated]))
+            }
+            "fnmadd" | "fnmadds" => {
+                let sum = self.temp(Op::FloatMulAdd, &[a, c, b]);
+                Some((Op::FloatNeg, vec![sum]))
+            }
+            "fnmsub" | "fnmsubs" => {
+                let negated = self.temp(Op::FloatNeg, &[b]);
+                let sum = self.temp(Op::FloatMulAdd, &[a, c, negated]);
+                Some((Op::FloatNeg, vec![sum]))
+            }
+            "fmr" => Some((Op::Copy, vec![b])),
+            "fneg" => Some((Op::FloatNeg, vec![b])),
+            "fabs" => Some((Op::FloatAbs, vec![b])),
+            "fnabs" => {
+                let absolute = self.temp(Op::FloatAbs, &[b]);
+                Some((Op::FloatNeg, vec![absolute]))
+            }
+            "frsp" => Some((Op::FloatRound, vec![b])),
+            "fres" => Some((Op::FloatReciprocal, vec![b])),
+            "frsqrte" => Some((Op::FloatReciprocalSqrt, vec![b])),
+            "fsel" => Some((Op::FloatSelect, vec![a, c, b])),
+            "fctiw" => Some((Op::FloatToInt, vec![b])),
+            "fctiwz" => Some((Op::FloatToIntTruncate, vec![b])),
+            "fcmpu" | "fcmpo" => {
+                self.float_compare(field(CRFD), a, b);
+                None
+            }
+            "mffs" => {
+                self.intrinsic("mffs", &[], &[Register::Fpr(d as u8)]);
+                None
+            }
+            "mcrfs" => {
+                let results: Vec<Register> = (0..4).map(|bit| cr_bit(field(CRFD), bit)).collect();
+                self.intrinsic("mcrfs", &[Operand::Constant(field(CRFS))], &results);
+                None
+            }
+            "mtfsf" => {
+                self.intrinsic("mtfsf", &[Operand::Constant(field(FM)), b], &[]);
+                None
+            }
+            "mtfsfi" => {
+                let arguments = [Operand::Constant(field(CRFD)), Operand::Constant(field(IMM))];
+                self.intrinsic("mtfsfi", &arguments, &[]);
+                None
+            }
+            "mtfsb0" | "mtfsb1" => {
+                self.intrinsic(mnemonic, &[Operand::Constant(field(CRBD))], &[]);
+                None
+            }
+            _ => return false,
+        };
+
+        if let Some((mut op, mut args)) = value {
+            // Single precision results are rounded, and copied into the second half of the paired single
+            let single = matches!(
+                mnemonic,
+                "fadds"
+                    | "fsubs"
+                    | "fmuls"
+                    | "fdivs"
+                    | "fmadds"
+                    | "fmsubs"
+                    | "fnmadds"
+                    | "fnmsubs"
+                    | "fres"
+                    | "frsp"
+            );
+            if single && op != Op::FloatRound {
+                let value = self.temp(op, &args);
+                (op, args) = (Op::FloatRound, vec![value]);
+            }
+            self.assign(Register::Fpr(d as u8), op, &args);
+            if single {
+                self.assign(Register::Ps1(d as u8), Op::Copy, &[fpr(d)]);
+            }
+        }
+        self.record_float(instruction);
+        true
+    }
+
+    /// Paired single arithmetic, lifted as a separate operation on each half.
+    fn paired(&mut self, instruction: &Instruction) -> bool {
+        let mnemonic = instruction.form.mnemonic;
+        if !mnemonic.starts_with("ps_") {
+            return false;
+        }
+        let field = |field| instruction.field(field);
+        let (d, a, b, c) = (field(FRD), field(FRA), field(FRB), field(FRC));
+
+        if let Some(half) = mnemonic.strip_prefix("ps_cmpu").or_else(|| mnemonic.strip_prefix("ps_cmpo")) {
+            let half = if half == "0" { 0 } else { 1 };
+            self.float_compare(field(CRFD), lane(a, half), lane(b, half));
+            return true;
+        }
+
+        let mut values = [Operand::Constant(0); 2];
+        for (half, value) in values.iter_mut().enumerate() {
+            let (a, b, c) = (lane(a, half), lane(b, half), lane(c, half));
+            *value = match mnemonic {
+                "ps_add" => self.rounded(Op::FloatAdd, &[a, b]),
+                "ps_sub" => self.rounded(Op::FloatSub, &[a, b]),
+                "ps_mul" => self.rounded(Op::FloatMul, &[a, c]),
+                "ps_div" => self.rounded(Op::FloatDiv, &[a, b]),
+                "ps_madd" => self.rounded(Op::FloatMulAdd, &[a, c, b]),
+                "ps_msub" => {
+                    let negated = self.temp(Op::FloatNeg, &[b]);
+                    self.rounded(Op::FloatMulAdd, &[a, c, negated])
+                }
+                "ps_nmadd" => {
+                    let sum = self.temp(Op::FloatMulAdd, &[a, c, b]);
+                    self.rounded(Op::FloatNeg, &[sum])
+                }
+                "ps_nmsub" => {
+                    let negated = self.temp(Op::FloatNeg, &[b]);
+                    let sum = self.temp(Op::FloatMulAdd, &[a, c, negated]);
+                    self.rounded(Op::FloatNeg, &[sum])
+                }
+                "ps_muls0" => self.rounded(Op::FloatMul, &[a, lane(field(FRC), 0)]),
+                "ps_muls1" => self.rounded(Op::FloatMul, &[a, lane(field(FRC), 1)]),
+                "ps_madds0" => self.rounded(Op::FloatMulAdd, &[a, lane(field(FRC), 0), b]),
+                "ps_madds1" => self.rounded(Op::FloatMulAdd, &[a, lane(field(FRC), 1), b]),
+                "ps_sum0" | "ps_sum1" => match (mnemonic == "ps_sum0") == (half == 0) {
+                    true => self.rounded(Op::FloatAdd, &[lane(field(FRA), 0), lane(field(FRB), 1)]),
+                    false => c,
+                },
+                "ps_neg" => self.temp(Op::FloatNeg, &[b]),
+                "ps_abs" => self.temp(Op::FloatAbs, &[b]),
+                "ps_nabs" => {
+                    let absolute = self.temp(Op::FloatAbs, &[b]);
+                    self.temp(Op::FloatNeg, &[absolute])
+                }
+                "ps_mr" => b,
+                "ps_res" => self.rounded(Op::FloatReciprocal, &[b]),
+                "ps_rsqrte" => self.temp(Op::FloatReciprocalSqrt, &[b]),
+                "ps_sel" => self.temp(Op::FloatSelect, &[a, c, b]),
+                "ps_merge00" | "ps_merge01" | "ps_merge10" | "ps_merge11" => {
+                    // The two digits say which half of frA and frB to take
+                    let halves = mnemonic.as_bytes();
+                    match half {
+                        0 => lane(field(FRA), usize::from(halves[8] - b'0')),
+                        _ => lane(field(FRB), usize::from(halves[9] - b'0')),
+                    }
+                }
+                _ => return false,
+            };
+        }
+
+        // The first half gets written first, so the second can't read it anymore
+        if values[1] == fpr(d) {
+            values[1] = self.temp(Op::Copy, &[values[1]]);
+        }
+        self.assign(Register::Fpr(d as u8), Op::Copy, &[values[0]]);
+        self.assign(Register::Ps1(d as u8), Op::Copy, &[values[1]]);
+        self.record_float(instruction);
+        true
+    }
+
+    fn rounded(&mut self, op: Op, args: &[Operand]) -> Operand {
+        let value = self.temp(op, args);
+        self.temp(Op::FloatRound, &[value])
+    }
+
+    /// Integer arithmetic, condition register logic, special purpose registers, and anything else left over.
+    fn integer(&mut self, instruction: &Instruction) {
+        let mnemonic = instruction.form.mnemonic;
+        let field = |field| instruction.field(field);
+        let (rd, rs, ra, rb) = (field(RD), field(RS), field(RA), field(RB));
+        let simm = Operand::Constant(field(SIMM) as u16 as i16 as u32);
+        let uimm = field(UIMM);
+
+        let result = match mnemonic {
+            "addi" | "addis" => {
+                let value = match mnemonic {
+                    "addis" => Operand::Constant(uimm << 16),
+                    _ => simm,
+                };
+                match ra {
+                    0 => self.assign(Register::Gpr(rd as u8), Op::Copy, &[value]),
+                    _ => self.assign(Register::Gpr(rd as u8), Op::Add, &[gpr(ra), value]),
+                }
+                Some(rd)
+            }
+            "addic" | "addic." => {
+                let args = [gpr(ra), simm];
+                let sum = self.temp(Op::Add, &args);
+                self.assign(Register::Carry, Op::Carry, &args);
+                self.assign(Register::Gpr(rd as u8), Op::Copy, &[sum]);
+                Some(rd)
+            }
+            "subfic" => {
+                let difference = self.temp(Op::Sub, &[simm, gpr(ra)]);
+                let inverted = self.temp(Op::Not, &[gpr(ra)]);
+                self.assign(
+                    Register::Carry,
+                    Op::Carry,
+                    &[inverted, simm, Operand::Constant(1)],
+                );
+                self.assign(Register::Gpr(rd as u8), Op::Copy, &[difference]);
+                None
+            }
+            "mulli" => {
+                self.assign(Register::Gpr(rd as u8), Op::Mul, &[gpr(ra), simm]);
+                None
+            }
+            "add" | "addc" | "adde" | "addze" | "addme" | "subf" | "subfc" | "subfe" | "subfze"
+            | "subfme" | "neg" => {
+                self.add(instruction);
+                Some(rd)
+            }
+            "mullw" | "mulhw" | "mulhwu" | "divw" | "divwu" => {
+                let args = [gpr(ra), gpr(rb)];
+                let op = match mnemonic {
+                    "mullw" => Op::Mul,
+                    "mulhw" => Op::MulHighSigned,
+                    "mulhwu" => Op::MulHighUnsigned,
+                    "divw" => Op::DivSigned,
+                    _ => Op::DivUnsigned,
+                };
+                match instruction.has_suffix(Suffix::OE) {
+                    true => {
+                        let value = self.temp(op, &args);
+                        match mnemonic {
+                            "mullw" => self.assign(Register::Overflow, Op::MulOverflow, &args),
+                            "divw" => self.assign(Register::Overflow, Op::DivOverflow, &args),
+                            _ => self.assign(Register::Overflow, Op::Equal, &[gpr(rb), Operand::Constant(0)]),
+                        }
+                        self.overflow();
+                        self.assign(Register::Gpr(rd as u8), Op::Copy, &[value]);
+                    }
+                    false => self.assign(Register::Gpr(rd as u8), op, &args),
+                }
+                Some(rd)
+            }
+            "and" | "andc" | "or" | "orc" | "xor" | "nand" | "nor" | "eqv" => {
+                let dest = Register::Gpr(ra as u8);
+                let (s, b) = (gpr(rs), gpr(rb));
+                match mnemonic {
+                    "and" => self.assign(dest, Op::And, &[s, b]),
+                    // `mr` and `not`
+                    "or" if rs == rb => self.assign(dest, Op::Copy, &[s]),
+                    "nor" if rs == rb => self.assign(dest, Op::Not, &[s]),
+                    "or" => self.assign(dest, Op::Or, &[s, b]),
+                    "xor" => self.assign(dest, Op::Xor, &[s, b]),
+                    "andc" | "orc" => {
+                        let inverted = self.temp(Op::Not, &[b]);
+                        let op = if mnemonic == "andc" { Op::And } else { Op::Or };
+                        self.assign(dest, op, &[s, inverted]);
+                    }
+                    _ => {
+                        let op = match mnemonic {
+                            "nand" => Op::And,
+                            "nor" => Op::Or,
+                            _ => Op::Xor,
+                        };
+                        let value = self.temp(op, &[s, b]);
+                        self.assign(dest, Op::Not, &[value]);
+                    }
+                }
+                Some(ra)
+            }
+            "andi." | "andis." | "ori" | "oris" | "xori" | "xoris" => {
+                let value = if mnemonic.contains("is") { uimm << 16 } else { uimm };
+                let op = match mnemonic.as_bytes()[0] {
+                    b'a' => Op::And,
+                    b'o' => Op::Or,
+                    _ => Op::Xor,
+                };
+                match value {
+                    0 if op != Op::And => self.assign(Register::Gpr(ra as u8), Op::Copy, &[gpr(rs)]),
+                    _ => self.assign(Register::Gpr(ra as u8), op, &[gpr(rs), Operand::Constant(value)]),
+                }
+                Some(ra)
+            }
+            "extsb" | "extsh" | "cntlzw" => {
+                let op = match mnemonic {
+                    "extsb" => Op::SignExtend8,
+                    "extsh" => Op::SignExtend16,
+                    _ => Op::CountLeadingZeros,
+                };
+                self.assign(Register::Gpr(ra as u8), op, &[gpr(rs)]);
+                Some(ra)
+            }
+            "slw" | "srw" | "sraw" | "srawi" => {
+                let amount = match mnemonic {
+                    "srawi" => Operand::Constant(field(SH)),
+                    _ => self.temp(Op::And, &[gpr(rb), Operand::Constant(0x3F)]),
+                };
+                let dest = Register::Gpr(ra as u8);
+                match mnemonic {
+                    "slw" => self.assign(dest, Op::ShiftLeft, &[gpr(rs), amount]),
+                    "srw" => self.assign(dest, Op::ShiftRightUnsigned, &[gpr(rs), amount]),
+                    _ => {
+                        let value = self.temp(Op::ShiftRightSigned, &[gpr(rs), amount]);
+                        self.assign(Register::Carry, Op::ShiftCarry, &[gpr(rs), amount]);
+                        self.assign(dest, Op::Copy, &[value]);
+                    }
+                }
+                Some(ra)
+            }
+            "rlwinm" | "rlwnm" | "rlwimi" => {
+                self.rotate(instruction);
+                Some(ra)
+            }
+            "cmpw" | "cmplw" | "cmpwi" | "cmplwi" => {
+                let b = match mnemonic {
+                    "cmpw" | "cmplw" => gpr(rb),
+                    "cmpwi" => simm,
+                    _ => Operand::Constant(uimm),
+                };
+                self.compare(field(CRFD), gpr(ra), b, !mnemonic.starts_with("cmpl"));
+                None
+            }
+            "tw" | "twi" => {
+                let (a, b) = (gpr(ra), if mnemonic == "tw" { gpr(rb) } else { simm });
+                let to = field(TO);
+                let condition = match to {
+                    0x1F => Operand::Constant(1),
+                    _ => [
+                        (0x10, Op::LessSigned),
+                        (0x08, Op::GreaterSigned),
+                        (0x04, Op::Equal),
+                        (0x02, Op::LessUnsigned),
+                        (0x01, Op::GreaterUnsigned),
+                    ]
+                    .into_iter()
+                    .filter(|&(bit, _)| to & bit != 0)
+                    .map(|(_, op)| self.temp(op, &[a, b]))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .reduce(|a, b| self.temp(Op::Or, &[a, b]))
+                    .unwrap_or(Operand::Constant(0)),
+                };
+                self.intrinsic("trap", &[condition], &[]);
+                None
+            }
+            "crand" | "cror" | "crxor" | "crnand" | "crnor" | "creqv" | "crandc" | "crorc" => {
+                let dest = Register::CrBit(field(CRBD) as u8);
+                let (a, b) = (
+                    Register::CrBit(field(CRBA) as u8).into(),
+                    Register::CrBit(field(CRBB) as u8).into(),
+                );
+                match mnemonic {
+                    "crand" => self.assign(dest, Op::And, &[a, b]),
+                    "cror" => self.assign(dest, Op::Or, &[a, b]),
+                    "crxor" => self.assign(dest, Op::Xor, &[a, b]),
+                    "crandc" | "crorc" => {
+                        let inverted = self.not(b);
+                        let op = if mnemonic == "crandc" { Op::And } else { Op::Or };
+                        self.assign(dest, op, &[a, inverted]);
+                    }
+                    _ => {
+                        let op = match mnemonic {
+                            "crnand" => Op::And,
+                            "crnor" => Op::Or,
+                            _ => Op::Xor,
+                        };
+                        let value = self.temp(op, &[a, b]);
+                        self.assign(dest, Op::Xor, &[value, Operand::Constant(1)]);
+                    }
+                }
+                None
+            }
+            "mcrf" => {
+                for bit in 0..4 {
+                    self.assign(
+                        cr_bit(field(CRFD), bit),
+                        Op::Copy,
+                        &[cr_bit(field(CRFS), bit).into()],
+                    );
+                }
+                None
+            }
+            "mcrxr" => {
+                let field = field(CRFD);
+                self.assign(cr_bit(field, 0), Op::Copy, &[Register::SummaryOverflow.into()]);
+                self.assign(cr_bit(field, 1), Op::Copy, &[Register::Overflow.into()]);
+                self.assign(cr_bit(field, 2), Op::Copy, &[Register::Carry.into()]);
+                self.assign(cr_bit(field, 3), Op::Copy, &[Operand::Constant(0)]);
+                for register in [Register::SummaryOverflow, Register::Overflow, Register::Carry] {
+                    self.assign(register, Op::Copy, &[Operand::Constant(0)]);
+                }
+                None
+            }
+            "mtcrf" => {
+                let mask = field(CRM);
+                for bit in (0..32).filter(|bit| mask & (0x80 >> (bit / 4)) != 0) {
+                    let shifted = self.temp(Op::ShiftRightUnsigned, &[gpr(rs), Operand::Constant(31 - bit)]);
+                    self.assign(
+                        Register::CrBit(bit as u8),
+                        Op::And,
+                        &[shifted, Operand::Constant(1)],
+                    );
+                }
+                None
+            }
+            "mfcr" => {
+                let bits: Vec<Operand> = (0..32).map(|bit| Register::CrBit(bit).into()).collect();
+                self.intrinsic("mfcr", &bits, &[Register::Gpr(rd as u8)]);
+                None
+            }
+            "mfspr" => {
+                let dest = Register::Gpr(rd as u8);
+                match field(SPR) {
+                    // The byte count `lswx` and `stswx` use isn't modelled
+                    1 => {
+                        let so = self.temp(
+                            Op::ShiftLeft,
+                            &[Register::SummaryOverflow.into(), Operand::Constant(31)],
+                        );
+                        let ov =
+                            self.temp(Op::ShiftLeft, &[Register::Overflow.into(), Operand::Constant(30)]);
+                        let ca = self.temp(Op::ShiftLeft, &[Register::Carry.into(), Operand::Constant(29)]);
+                        let high = self.temp(Op::Or, &[so, ov]);
+                        self.assign(dest, Op::Or, &[high, ca]);
+                    }
+                    spr => self.assign(dest, Op::Copy, &[special_register(spr).into()]),
+                }
+                None
+            }
+            "mtspr" => {
+                match field(SPR) {
+                    1 => {
+                        for (register, bit) in [
+                            (Register::SummaryOverflow, 31),
+                            (Register::Overflow, 30),
+                            (Register::Carry, 29),
+                        ] {
+                            let shifted =
+                                self.temp(Op::ShiftRightUnsigned, &[gpr(rs), Operand::Constant(bit)]);
+                            self.assign(register, Op::And, &[shifted, Operand::Constant(1)]);
+                        }
+                    }
+                    spr => self.assign(special_register(spr), Op::Copy, &[gpr(rs)]),
+                }
+                None
+            }
+            "mftb" => {
+                self.intrinsic(
+                    "mftb",
+                    &[Operand::Constant(field(TBR))],
+                    &[Register::Gpr(rd as u8)],
+                );
+                None
+            }
+            "mfmsr" => {
+                self.intrinsic("mfmsr", &[], &[Register::Gpr(rd as u8)]);
+                None
+            }
+            "mfsr" => {
+                self.intrinsic(
+                    "mfsr",
+                    &[Operand::Constant(field(SR))],
+                    &[Register::Gpr(rd as u8)],
+                );
+                None
+            }
+            "mfsrin" => {
+                self.intrinsic("mfsrin", &[gpr(rb)], &[Register::Gpr(rd as u8)]);
+                None
+            }
+            "mtmsr" => {
+                self.intrinsic("mtmsr", &[gpr(rs)], &[]);
+                None
+            }
+            "mtsr" => {
+                self.intrinsic("mtsr", &[Operand::Constant(field(SR)), gpr(rs)], &[]);
+                None
+            }
+            "mtsrin" => {
+                self.intrinsic("mtsrin", &[gpr(rs), gpr(rb)], &[]);
+                None
+            }
+            // `sync`, `isync`, `sc`, `tlbie` and so on, which only matter for their side effects
+            _ => {
+                let arguments: Vec<Operand> = instruction
+                    .operands
+                    .iter()
+                    .filter_map(|operand| match *operand {
+                        gekko::Operand::Gpr(n) => Some(gpr(n.into())),
+                        _ => None,
+                    })
+                    .collect();
+                self.intrinsic(mnemonic, &arguments, &[]);
+                None
+            }
+        };
+
+        if let Some(result) = result.filter(|_| instruction.record() || mnemonic.ends_with('.')) {
+            self.compare(0, gpr(result), Operand::Constant(0), true);
+        }
+    }
+
+    /// The `add` and `subf` families. Subtracting is done as adding rA's complement plus one, which is how
+    /// the carry and overflow bits are defined.
+    fn add(&mut self, instruction: &Instruction) {
+        let mnemonic = instruction.form.mnemonic;
+        let (rd, ra, rb) = (
+            instruction.field(RD),
+            instruction.field(RA),
+            instruction.field(RB),
+        );
+        let carries = !matches!(mnemonic, "add" | "subf" | "neg");
+        let overflows = instruction.has_suffix(Suffix::OE);
+        let direct = match mnemonic {
+            "add" | "addc" => Some((Op::Add, vec![gpr(ra), gpr(rb)])),
+            "subf" | "subfc" => Some((Op::Sub, vec![gpr(rb), gpr(ra)])),
+            "neg" => Some((Op::Neg, vec![gpr(ra)])),
+            _ => None,
+        };
+        if let Some((op, args)) = direct.as_ref().filter(|_| !carries && !overflows) {
+            self.assign(Register::Gpr(rd as u8), *op, args);
+            return;
+        }
+
+        let subtract = mnemonic.starts_with("subf") || mnemonic == "neg";
+        let mut terms = vec![if subtract {
+            self.temp(Op::Not, &[gpr(ra)])
+        } else {
+            gpr(ra)
+        }];
+        match mnemonic {
+            "add" | "addc" | "adde" | "subf" | "subfc" | "subfe" => terms.push(gpr(rb)),
+            "addme" | "subfme" => terms.push(Operand::Constant(u32::MAX)),
+            _ => (),
+        }
+        match mnemonic {
+            "adde" | "addze" | "addme" | "subfe" | "subfze" | "subfme" => terms.push(Register::Carry.into()),
+            "subf" | "subfc" | "neg" => terms.push(Operand::Constant(1)),
+            _ => (),
+        }
+
+        let value = match direct {
+            Some((op, args)) => self.temp(op, &args),
+            None => terms.clone().into_iter().reduce(|a, b| self.temp(Op::Add, &[a, b])).unwrap(),
+        };
+        if carries {
+            self.assign(Register::Carry, Op::Carry, &terms);
+        }
+        if overflows {
+            self.assign(Register::Overflow, Op::AddOverflow, &terms);
+            self.overflow();
+        }
+        self.assign(Register::Gpr(rd as u8), Op::Copy, &[value]);
+    }
+
+    /// Sets XER[SO] once XER[OV] has been set.
+    fn overflow(&mut self) {
+        let args = [Register::SummaryOverflow.into(), Register::Overflow.into()];
+        self.assign(Register::SummaryOverflow, Op::Or, &args);
+    }
+
+    /// `rlwinm`, `rlwnm` and `rlwimi`, using plain shifts wherever the mask allows it.
+    fn rotate(&mut self, instruction: &Instruction) {
+        let mnemonic = instruction.form.mnemonic;
+        let field = |field| instruction.field(field);
+        let (dest, rs, sh) = (Register::Gpr(field(RA) as u8), gpr(field(RS)), field(SH));
+        let mask = rotate_mask(field(MB), field(ME));
+
+        match mnemonic {
+            "rlwinm" => {
+                let (op, args, bits) = match sh {
+                    0 => (Op::Copy, vec![rs], u32::MAX),
+                    _ if mask & !(u32::MAX << sh) == 0 => {
+                        (Op::ShiftLeft, vec![rs, Operand::Constant(sh)], u32::MAX << sh)
+                    }
+                    _ if mask & !(u32::MAX >> (32 - sh)) == 0 => (
+                        Op::ShiftRightUnsigned,
+                        vec![rs, Operand::Constant(32 - sh)],
+                        u32::MAX >> (32 - sh),
+                    ),
+                    _ => (Op::RotateLeft, vec![rs, Operand::Constant(sh)], u32::MAX),
+                };
+                match (mask == bits, op) {
+                    (true, _) => self.assign(dest, op, &args),
+                    (false, Op::Copy) => self.assign(dest, Op::And, &[rs, Operand::Constant(mask)]),
+                    (false, _) => {
+                        let value = self.temp(op, &args);
+                        self.assign(dest, Op::And, &[value, Operand::Constant(mask)]);
+                    }
+                }
+            }
+            "rlwnm" => {
+                let amount = self.temp(Op::And, &[gpr(field(RB)), Operand::Constant(0x1F)]);
+                match mask {
+                    u32::MAX => self.assign(dest, Op::RotateLeft, &[rs, amount]),
+                    _ => {
+                        let value = self.temp(Op::RotateLeft, &[rs, amount]);
+                        self.assign(dest, Op::And, &[value, Operand::Constant(mask)]);
+                    }
+                }
+            }
+            _ => {
+                let rotated = match sh {
+                    0 => rs,
+                    _ => self.temp(Op::RotateLeft, &[rs, Operand::Constant(sh)]),
+                };
+                let inserted = self.temp(Op::And, &[rotated, Operand::Constant(mask)]);
+                let kept = self.temp(Op::And, &[dest.into(), Operand::Constant(!mask)]);
+                self.assign(dest, Op::Or, &[inserted, kept]);
+            }
+        }
+    }
+}
+
+/// Where a special purpose register lives in the IR, other than XER which is split into its bits.
+fn special_register(spr: u32) -> Register {
+    match spr {
+        8 => Register::Lr,
+        9 => Register::Ctr,
+        spr => Register::Spr(spr as u16),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{program, TEXT};
+
+    fn statements(block: &Block) -> Vec<String> {
+        block.statements.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn lifts_memory_accesses() {
+        let program = program(
+            "
+            lwz r4, 8(r3)
+            stw r4, 0xC(r3)
+            cmplwi r4, 10
+            bgt done
+            lbz r5, 1(r3)
+            extsb r5, r5
+            stb r5, 2(r3)
+        done:
+            blr
+            ",
+            &[],
+        );
+        let function = lift(&program, TEXT).unwrap();
+        assert_eq!(function.blocks.len(), 3);
+        assert_eq!(
+            statements(&function.blocks[0]),
+            [
+                "t0 = add r3, 8",
+                "r4 = load.u32 [t0]",
+                "t1 = add r3, 0xC",
+                "store.u32 [t1], r4",
+                "cr0.lt = ltu r4, 0xA",
+                "cr0.gt = gtu r4, 0xA",
+                "cr0.eq = eq r4, 0xA",
+                "cr0.so = xer.so",
+            ]
+        );
+        assert_eq!(
+            function.blocks[0].terminator.to_string(),
+            "if cr0.gt goto 2 else 1"
+        );
+        assert_eq!(
+            statements(&function.blocks[1]),
+            [
+                "t2 = add r3, 1",
+                "r5 = load.u8 [t2]",
+                "r5 = sext8 r5",
+                "t3 = add r3, 2",
+                "store.u8 [t3], r5"
+            ]
+        );
+        assert_eq!(function.blocks[1].terminator, Terminator::Jump(2));
+    }
+
+    #[test]
+    fn lifts_calls_and_returns() {
+        let program = program(
+            "
+            bl callee
+            blr
+        callee:
+            blr
+            ",
+            &[],
+        );
+        let function = lift(&program, TEXT).unwrap();
+        let [Statement::Call { callee, condition, arguments, results }] = &function.blocks[0].statements[..]
+        else {
+            panic!("{function}");
+        };
+        assert_eq!(*callee, Callee::Direct(TEXT + 8));
+        assert_eq!(*condition, None);
+        let registers: Vec<Register> = arguments
+            .iter()
+            .filter_map(|argument| argument.variable())
+            .map(|variable| variable.register)
+            .collect();
+        assert_eq!(registers, call_arguments());
+        assert!(results.iter().any(|result| result.register == Register::Gpr(3)));
+        assert!(!results.iter().any(|result| result.register == Register::Gpr(31)));
+        let Terminator::Return { values } = &function.blocks[0].terminator else {
+            panic!("{function}");
+        };
+        assert!(values.contains(&(Register::Gpr(3), Register::Gpr(3).into())));
+        assert!(values.contains(&(Register::Gpr(1), Register::Gpr(1).into())));
+    }
+}
@@ -0,0 +1,518 @@
+//! Intermediate representation for decompiling, independent of the instruction set. Functions are lifted
+//! into basic blocks of three-address statements, where every side effect an instruction has is spelled out:
+//! each condition register bit, XER's carry and overflow bits, and every memory access. [`ssa`] then turns
+//! that into SSA form and cleans it up, which is what later stages (like C output) build on.
+//!
+//! Integer values are all 32 bits wide, with comparisons giving 0 or 1. Floating point values are doubles,
+//! since that's how the FPRs hold them.
+pub mod lift;
+pub mod ssa;
+
+use core::fmt;
+
+use crate::processor::gekko::format_immediate;
+
+pub use lift::lift;
+
+/// Index of a block in [`IrFunction::blocks`].
+pub type BlockId = usize;
+
+/// Somewhere a value can be stored. These map straight onto machine registers, plus temporaries the lifter
+/// uses to break instructions down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Register {
+    Gpr(u8),
+    /// An FPR, or the first half of a paired single
+    Fpr(u8),
+    /// Second half of a paired single
+    Ps1(u8),
+    /// A single condition register bit, four per field
+    CrBit(u8),
+    Lr,
+    Ctr,
+    /// XER[CA]
+    Carry,
+    /// XER[OV]
+    Overflow,
+    /// XER[SO]
+    SummaryOverflow,
+    /// Any other special purpose register, by number
+    Spr(u16),
+    Temp(u32),
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const BITS: [&str; 4] = ["lt", "gt", "eq", "so"];
+        match *self {
+            Self::Gpr(n) => write!(f, "r{n}"),
+            Self::Fpr(n) => write!(f, "f{n}"),
+            Self::Ps1(n) => write!(f, "f{n}.ps1"),
+            Self::CrBit(n) => write!(f, "cr{}.{}", n / 4, BITS[(n % 4) as usize]),
+            Self::Lr => f.write_str("lr"),
+            Self::Ctr => f.write_str("ctr"),
+            Self::Carry => f.write_str("xer.ca"),
+            Self::Overflow => f.write_str("xer.ov"),
+            Self::SummaryOverflow => f.write_str("xer.so"),
+            Self::Spr(n) => write!(f, "spr{n}"),
+            Self::Temp(n) => write!(f, "t{n}"),
+        }
+    }
+}
+
+/// A register at one point in the program. Before SSA every version is 0, afterwards each definition gets its
+/// own version, and version 0 is whatever the register held when the function was entered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Variable {
+    pub register: Register,
+    pub version: u32,
+}
+
+impl Variable {
+    pub fn new(register: Register) -> Self {
+        Self { register, version: 0 }
+    }
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            0 => write!(f, "{}", self.register),
+            version => write!(f, "{}_{version}", self.register),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operand {
+    Variable(Variable),
+    Constant(u32),
+}
+
+impl Operand {
+    pub fn variable(&self) -> Option<Variable> {
+        match *self {
+            Self::Variable(variable) => Some(variable),
+            Self::Constant(_) => None,
+        }
+    }
+}
+
+impl From<Register> for Operand {
+    fn from(register: Register) -> Self {
+        Self::Variable(Variable::new(register))
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Variable(variable) => write!(f, "{variable}"),
+            // Small negative numbers are usually offsets, anything bigger is more likely an address or a mask
+            Self::Constant(value) => match value as i32 {
+                -0x8000..0 => f.write_str(&format_immediate((value as i32).into())),
+                _ => f.write_str(&format_immediate(value.into())),
+            },
+        }
+    }
+}
+
+/// A pure operation, which only depends on its arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Op {
+    Copy,
+    Add,
+    Sub,
+    Mul,
+    /// High 32 bits of the signed 64-bit product
+    MulHighSigned,
+    MulHighUnsigned,
+    DivSigned,
+    DivUnsigned,
+    And,
+    Or,
+    Xor,
+    Not,
+    Neg,
+    /// Shifts take the amount modulo 64, anything from 32 up shifts every bit out
+    ShiftLeft,
+    ShiftRightUnsigned,
+    ShiftRightSigned,
+    RotateLeft,
+    CountLeadingZeros,
+    SignExtend8,
+    SignExtend16,
+    ByteSwap16,
+    ByteSwap32,
+    Equal,
+    NotEqual,
+    LessSigned,
+    LessUnsigned,
+    GreaterSigned,
+    GreaterUnsigned,
+    /// Unsigned carry out of adding all the arguments together
+    Carry,
+    /// Signed overflow from adding all the arguments together
+    AddOverflow,
+    MulOverflow,
+    DivOverflow,
+    /// Whether an arithmetic right shift dropped any set bits of a negative number
+    ShiftCarry,
+    FloatAdd,
+    FloatSub,
+    FloatMul,
+    FloatDiv,
+    /// `a * b + c`, without rounding in between
+    FloatMulAdd,
+    FloatNeg,
+    FloatAbs,
+    /// Rounds to single precision
+    FloatRound,
+    FloatToInt,
+    FloatToIntTruncate,
+    FloatReciprocal,
+    FloatReciprocalSqrt,
+    /// `a >= 0 ? b : c`
+    FloatSelect,
+    FloatLess,
+    FloatGreater,
+    FloatEqual,
+    FloatUnordered,
+}
+
+impl Op {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Copy => "copy",
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::MulHighSigned => "mulhs",
+            Self::MulHighUnsigned => "mulhu",
+            Self::DivSigned => "divs",
+            Self::DivUnsigned => "divu",
+            Self::And => "and",
+            Self::Or => "or",
+            Self::Xor => "xor",
+            Self::Not => "not",
+            Self::Neg => "neg",
+            Self::ShiftLeft => "shl",
+            Self::ShiftRightUnsigned => "shru",
+            Self::ShiftRightSigned => "shrs",
+            Self::RotateLeft => "rotl",
+            Self::CountLeadingZeros => "clz",
+            Self::SignExtend8 => "sext8",
+            Self::SignExtend16 => "sext16",
+            Self::ByteSwap16 => "bswap16",
+            Self::ByteSwap32 => "bswap32",
+            Self::Equal => "eq",
+            Self::NotEqual => "ne",
+            Self::LessSigned => "lts",
+            Self::LessUnsigned => "ltu",
+            Self::GreaterSigned => "gts",
+            Self::GreaterUnsigned => "gtu",
+            Self::Carry => "carry",
+            Self::AddOverflow => "addov",
+            Self::MulOverflow => "mulov",
+            Self::DivOverflow => "divov",
+            Self::ShiftCarry => "shrcarry",
+            Self::FloatAdd => "fadd",
+            Self::FloatSub => "fsub",
+            Self::FloatMul => "fmul",
+            Self::FloatDiv => "fdiv",
+            Self::FloatMulAdd => "fmadd",
+            Self::FloatNeg => "fneg",
+            Self::FloatAbs => "fabs",
+            Self::FloatRound => "fround",
+            Self::FloatToInt => "ftoi",
+            Self::FloatToIntTruncate => "ftoiz",
+            Self::FloatReciprocal => "frecip",
+            Self::FloatReciprocalSqrt => "frsqrt",
+            Self::FloatSelect => "fsel",
+            Self::FloatLess => "flt",
+            Self::FloatGreater => "fgt",
+            Self::FloatEqual => "feq",
+            Self::FloatUnordered => "funord",
+        }
+    }
+}
+
+/// How a load or store treats the bytes it accesses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    /// Zero extended when loaded
+    Unsigned,
+    /// Sign extended when loaded
+    Signed,
+    /// A float or double, converted to and from a double in the register
+    Float,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Access {
+    /// Size in bytes
+    pub size: u8,
+    pub kind: AccessKind,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            AccessKind::Unsigned => "u",
+            AccessKind::Signed => "s",
+            AccessKind::Float => "f",
+        };
+        write!(f, "{kind}{}", u32::from(self.size) * 8)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Callee {
+    Direct(u32),
+    Indirect(Operand),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    Assign {
+        dest: Variable,
+        op: Op,
+        args: Vec<Operand>,
+    },
+    Load {
+        dest: Variable,
+        access: Access,
+        address: Operand,
+    },
+    Store {
+        access: Access,
+        address: Operand,
+        value: Operand,
+    },
+    /// A call, which reads the argument registers and clobbers everything the callee is allowed to
+    Call {
+        callee: Callee,
+        /// Only makes the call if this is non-zero
+        condition: Option<Operand>,
+        arguments: Vec<Operand>,
+        results: Vec<Variable>,
+    },
+    /// Anything with effects the IR doesn't model, like cache control or the quantized loads
+    Intrinsic {
+        name: &'static str,
+        arguments: Vec<Operand>,
+        results: Vec<Variable>,
+    },
+    /// Picks the value from whichever predecessor control came from, only exists in SSA form
+    Phi {
+        dest: Variable,
+        sources: Vec<(BlockId, Operand)>,
+    },
+}
+
+impl Statement {
+    /// Every variable this statement defines.
+    pub fn definitions(&self) -> Vec<Variable> {
+        match self {
+            Self::Assign { dest, .. } | Self::Load { dest, .. } | Self::Phi { dest, .. } => vec![*dest],
+            Self::Call { results, .. } | Self::Intrinsic { results, .. } => results.clone(),
+            Self::Store { .. } => Vec::new(),
+        }
+    }
+
+    /// Every operand this statement reads, mutably so passes can rewrite them.
+    pub fn uses_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Self::Assign { args, .. } => args.iter_mut().collect(),
+            Self::Load { address, .. } => vec![address],
+            Self::Store { address, value, .. } => vec![address, value],
+            Self::Call { callee, condition, arguments, .. } => {
+                let mut uses: Vec<&mut Operand> = arguments.iter_mut().collect();
+                uses.extend(condition.as_mut());
+                if let Callee::Indirect(target) = callee {
+                    uses.push(target);
+                }
+                uses
+            }
+            Self::Intrinsic { arguments, .. } => arguments.iter_mut().collect(),
+            Self::Phi { sources, .. } => sources.iter_mut().map(|(_, operand)| operand).collect(),
+        }
+    }
+
+    /// Whether removing this statement could change behaviour even if nothing uses what it defines.
+    pub fn has_effects(&self) -> bool {
+        // Loads stay too, since they might be reading a hardware register
+        !matches!(self, Self::Assign { .. } | Self::Phi { .. })
+    }
+}
+
+fn list<T: fmt::Display>(items: &[T]) -> String {
+    items.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ")
+}
+
+fn assignments(values: &[(Register, Operand)]) -> String {
+    values
+        .iter()
+        .map(|(register, value)| format!("{register} = {value}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl fmt::Display for Statement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Assign { dest, op: Op::Copy, args } => write!(f, "{dest} = {}", list(args)),
+            Self::Assign { dest, op, args } => write!(f, "{dest} = {} {}", op.name(), list(args)),
+            Self::Load { dest, access, address } => write!(f, "{dest} = load.{access} [{address}]"),
+            Self::Store { access, address, value } => write!(f, "store.{access} [{address}], {value}"),
+            Self::Call { callee, condition, arguments, results } => {
+                if let Some(condition) = condition {
+                    write!(f, "if {condition} ")?;
+                }
+                match callee {
+                    Callee::Direct(target) => write!(f, "call 0x{target:08X}")?,
+                    Callee::Indirect(target) => write!(f, "call [{target}]")?,
+                }
+                write!(f, "({}) -> ({})", list(arguments), list(results))
+            }
+            Self::Intrinsic { name, arguments, results } if results.is_empty() => {
+                write!(f, "{name}({})", list(arguments))
+            }
+            Self::Intrinsic { name, arguments, results } => {
+                write!(f, "{} = {name}({})", list(results), list(arguments))
+            }
+            Self::Phi { dest, sources } => {
+                let sources: Vec<String> =
+                    sources.iter().map(|(block, operand)| format!("{operand} from {block}")).collect();
+                write!(f, "{dest} = phi {}", sources.join(", "))
+            }
+        }
+    }
+}
+
+/// How control leaves a block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Goes to `taken` if the condition is non-zero
+    Branch {
+        condition: Operand,
+        taken: BlockId,
+        not_taken: BlockId,
+    },
+    /// Returns to the caller. Lists the value of every register the caller can see afterwards, except for
+    /// ones that still hold what they did on entry.
+    Return {
+        values: Vec<(Register, Operand)>,
+    },
+    /// Jumps to the start of another function, with registers listed the same way as for [`Self::Return`]
+    TailCall {
+        target: u32,
+        values: Vec<(Register, Operand)>,
+    },
+    /// Goes to `targets[value]`, from a jump table. The value is always in range, since the code before it
+    /// checks that
+    Switch {
+        value: Operand,
+        targets: Vec<BlockId>,
+    },
+    /// Jumps somewhere only known at runtime
+    IndirectJump {
+        target: Operand,
+    },
+    /// Runs into something that couldn't be lifted
+    Unknown,
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Self::Jump(target) => vec![*target],
+            Self::Branch { taken, not_taken, .. } => vec![*taken, *not_taken],
+            Self::Switch { targets, .. } => {
+                let mut successors = Vec::new();
+                for &target in targets {
+                    if !successors.contains(&target) {
+                        successors.push(target);
+                    }
+                }
+                successors
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn uses_mut(&mut self) -> Vec<&mut Operand> {
+        match self {
+            Self::Branch { condition, .. } => vec![condition],
+            Self::Return { values } | Self::TailCall { values, .. } => {
+                values.iter_mut().map(|(_, value)| value).collect()
+            }
+            Self::Switch { value, .. } => vec![value],
+            Self::IndirectJump { target } => vec![target],
+            Self::Jump(_) | Self::Unknown => Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Terminator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Jump(target) => write!(f, "goto {target}"),
+            Self::Branch { condition, taken, not_taken } => {
+                write!(f, "if {condition} goto {taken} else {not_taken}")
+            }
+            Self::Return { values } => write!(f, "return ({})", assignments(values)),
+            Self::TailCall { target, values } => {
+                write!(f, "tailcall 0x{target:08X}({})", assignments(values))
+            }
+            Self::Switch { value, targets } => write!(f, "switch {value} [{}]", list(targets)),
+            Self::IndirectJump { target } => write!(f, "goto [{target}]"),
+            Self::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Block {
+    /// Address of the first instruction lifted into this block
+    pub address: u32,
+    pub statements: Vec<Statement>,
+    pub terminator: Terminator,
+}
+
+/// A lifted function. Block 0 is always the entry.
+#[derive(Debug, Clone)]
+pub struct IrFunction {
+    pub address: u32,
+    pub blocks: Vec<Block>,
+}
+
+impl IrFunction {
+    /// Predecessors of every block, without duplicates.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (id, block) in self.blocks.iter().enumerate() {
+            for successor in block.terminator.successors() {
+                if !predecessors[successor].contains(&id) {
+                    predecessors[successor].push(id);
+                }
+            }
+        }
+        predecessors
+    }
+}
+
+impl fmt::Display for IrFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "function 0x{:08X}", self.address)?;
+        for (id, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "\n{id}: # 0x{:08X}", block.address)?;
+            for statement in &block.statements {
+                writeln!(f, "    {statement}")?;
+            }
+            writeln!(f, "    {}", block.terminator)?;
+        }
+        Ok(())
+    }
+}
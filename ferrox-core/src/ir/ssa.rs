@@ -0,0 +1,756 @@
+//! Static single assignment form, and the clean up passes that rely on it. [`build`] gives every definition its
+//! own version and inserts phis where control flow merges, then [`optimize`] folds constants, propagates copies,
+//! drops branches that always go the same way and removes anything whose result is never used.
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+use super::{Block, BlockId, IrFunction, Op, Operand, Register, Statement, Terminator, Variable};
+
+/// Converts a freshly lifted function into SSA form.
+pub fn build(function: &mut IrFunction) {
+    remove_unreachable(function);
+    // Phis in the entry block would have nowhere to take the entry values from, so give loops back to the
+    // start of the function a new entry block to come from
+    if !function.predecessors()[0].is_empty() {
+        let ids: Vec<Option<BlockId>> = (1..=function.blocks.len()).map(Some).collect();
+        for block in &mut function.blocks {
+            renumber(block, &ids);
+        }
+        let entry = Block {
+            address: function.address,
+            statements: Vec::new(),
+            terminator: Terminator::Jump(1),
+        };
+        function.blocks.insert(0, entry);
+    }
+    let predecessors = function.predecessors();
+    let idom = dominators(function, &predecessors);
+    let frontiers = frontiers(function, &predecessors, &idom);
+
+    // Only registers that are read before being written in some block can need a phi, which rules out temps
+    let mut globals = BTreeSet::new();
+    let mut definitions: HashMap<Register, BTreeSet<BlockId>> = HashMap::new();
+    for (id, block) in function.blocks.iter_mut().enumerate() {
+        let mut defined = HashSet::new();
+        for statement in &mut block.statements {
+            for operand in statement.uses_mut() {
+                if let Some(variable) =
+                    operand.variable().filter(|variable| !defined.contains(&variable.register))
+                {
+                    globals.insert(variable.register);
+                }
+            }
+            for variable in statement.definitions() {
+                defined.insert(variable.register);
+                definitions.entry(variable.register).or_default().insert(id);
+            }
+        }
+        for operand in block.terminator.uses_mut() {
+            if let Some(variable) =
+                operand.variable().filter(|variable| !defined.contains(&variable.register))
+            {
+                globals.insert(variable.register);
+            }
+        }
+    }
+
+    // Phis go on the iterated dominance frontier of every block that defines the register
+    for register in globals {
+        let Some(defined) = definitions.get(&register) else {
+            continue;
+        };
+        let mut placed = BTreeSet::new();
+        let mut worklist: Vec<BlockId> = defined.iter().copied().collect();
+        while let Some(block) = worklist.pop() {
+            for &frontier in &frontiers[block] {
+                if placed.insert(frontier) {
+                    let sources = predecessors[frontier]
+                        .iter()
+                        .map(|&predecessor| (predecessor, register.into()))
+                        .collect();
+                    function.blocks[frontier]
+                        .statements
+                        .insert(0, Statement::Phi { dest: Variable::new(register), sources });
+                    if !defined.contains(&frontier) {
+                        worklist.push(frontier);
+                    }
+                }
+            }
+        }
+    }
+
+    rename(function, &idom);
+}
+
+/// Simplifies a function in SSA form until nothing else changes.
+pub fn optimize(function: &mut IrFunction) {
+    loop {
+        let mut changed = fold(function);
+        changed |= propagate(function);
+        changed |= fold_branches(function);
+        changed |= eliminate_dead_code(function);
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// Blocks in reverse postorder, starting from the entry.
+fn reverse_postorder(function: &IrFunction) -> Vec<BlockId> {
+    let mut visited = vec![false; function.blocks.len()];
+    let mut order = Vec::new();
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    while let Some((block, next)) = stack.pop() {
+        let successors = function.blocks[block].terminator.successors();
+        match successors.get(next) {
+            Some(&successor) => {
+                stack.push((block, next + 1));
+                if !visited[successor] {
+                    visited[successor] = true;
+                    stack.push((successor, 0));
+                }
+            }
+            None => order.push(block),
+        }
+    }
+    order.reverse();
+    order
+}
+
+/// Immediate dominator of every block, using Cooper, Harvey and Kennedy's iterative algorithm. The entry is
+/// its own dominator, and unreachable blocks don't have one.
+fn dominators(function: &IrFunction, predecessors: &[Vec<BlockId>]) -> Vec<Option<BlockId>> {
+    let order = reverse_postorder(function);
+    let mut position = vec![usize::MAX; function.blocks.len()];
+    for (n, &block) in order.iter().enumerate() {
+        position[block] = n;
+    }
+
+    let mut idom = vec![None; function.blocks.len()];
+    idom[0] = Some(0);
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &block in &order[1..] {
+            let mut new = None;
+            for &predecessor in &predecessors[block] {
+                if idom[predecessor].is_none() {
+                    continue;
+                }
+                new = Some(match new {
+                    None => predecessor,
+                    Some(mut other) => {
+                        let mut finger = predecessor;
+                        while finger != other {
+                            while position[finger] > position[other] {
+                                finger = idom[finger].unwrap();
+                            }
+                            while position[other] > position[finger] {
+                                other = idom[other].unwrap();
+                            }
+                        }
+                        finger
+                    }
+                });
+            }
+            if new != idom[block] {
+                idom[block] = new;
+                changed = true;
+            }
+        }
+    }
+    idom
+}
+
+fn frontiers(
+    function: &IrFunction, predecessors: &[Vec<BlockId>], idom: &[Option<BlockId>],
+) -> Vec<BTreeSet<BlockId>> {
+    let mut frontiers = vec![BTreeSet::new(); function.blocks.len()];
+    for (block, predecessors) in predecessors.iter().enumerate() {
+        let Some(dominator) = idom[block] else {
+            continue;
+        };
+        if predecessors.len() < 2 {
+            continue;
+        }
+        for &predecessor in predecessors {
+            let mut runner = predecessor;
+            while runner != dominator && idom[runner].is_some() {
+                frontiers[runner].insert(block);
+                runner = idom[runner].unwrap();
+            }
+        }
+    }
+    frontiers
+}
+
+/// Walks the dominator tree giving each definition a new version, and pointing every use at the one that
+/// reaches it.
+fn rename(function: &mut IrFunction, idom: &[Option<BlockId>]) {
+    let mut children = vec![Vec::new(); function.blocks.len()];
+    for (block, dominator) in idom.iter().enumerate().skip(1) {
+        if let Some(dominator) = *dominator {
+            children[dominator].push(block);
+        }
+    }
+
+    enum Step {
+        Enter(BlockId),
+        Leave(Vec<Register>),
+    }
+    let mut versions: HashMap<Register, u32> = HashMap::new();
+    let mut current: HashMap<Register, Vec<u32>> = HashMap::new();
+    let mut steps = vec![Step::Enter(0)];
+    while let Some(step) = steps.pop() {
+        let id = match step {
+            Step::Enter(id) => id,
+            Step::Leave(defined) => {
+                for register in defined {
+                    current.get_mut(&register).unwrap().pop();
+                }
+                continue;
+            }
+        };
+
+        let reaching = |current: &HashMap<Register, Vec<u32>>, operand: &mut Operand| {
+            if let Operand::Variable(variable) = operand {
+                variable.version =
+                    current.get(&variable.register).and_then(|stack| stack.last()).copied().unwrap_or(0);
+            }
+        };
+        let mut defined = Vec::new();
+        let block = &mut function.blocks[id];
+        for statement in &mut block.statements {
+            if !matches!(statement, Statement::Phi { .. }) {
+                for operand in statement.uses_mut() {
+                    reaching(&current, operand);
+                }
+            }
+            let mut define = |variable: &mut Variable| {
+                let version = versions.entry(variable.register).or_insert(0);
+                *version += 1;
+                variable.version = *version;
+                current.entry(variable.register).or_default().push(*version);
+                defined.push(variable.register);
+            };
+            match statement {
+                Statement::Assign { dest, .. }
+                | Statement::Load { dest, .. }
+                | Statement::Phi { dest, .. } => define(dest),
+                Statement::Call { results, .. } | Statement::Intrinsic { results, .. } => {
+                    results.iter_mut().for_each(define)
+                }
+                Statement::Store { .. } => (),
+            }
+        }
+        for operand in block.terminator.uses_mut() {
+            reaching(&current, operand);
+        }
+
+        // Fill in what this block passes to the phis of each successor
+        for successor in block.terminator.successors() {
+            for statement in &mut function.blocks[successor].statements {
+                let Statement::Phi { dest, sources } = statement else {
+                    break;
+                };
+                for (source, operand) in sources.iter_mut() {
+                    if *source == id {
+                        *operand = Operand::Variable(Variable::new(dest.register));
+                        reaching(&current, operand);
+                    }
+                }
+            }
+        }
+
+        steps.push(Step::Leave(defined));
+        steps.extend(children[id].iter().rev().map(|&child| Step::Enter(child)));
+    }
+}
+
+/// Evaluates an integer operation on constants. Returns `None` for anything that can't be folded, like
+/// dividing by zero or floating point.
+fn evaluate(op: Op, args: &[u32]) -> Option<u32> {
+    let signed = |n: usize| i64::from(args[n] as i32);
+    let sum: u64 = args.iter().map(|&arg| u64::from(arg)).sum();
+    let signed_sum: i64 = args.iter().map(|&arg| i64::from(arg as i32)).sum();
+    let shift = || args[1] % 64;
+    Some(match op {
+        Op::Copy => args[0],
+        Op::Add => sum as u32,
+        Op::Sub => args[0].wrapping_sub(args[1]),
+        Op::Mul => args[0].wrapping_mul(args[1]),
+        Op::MulHighSigned => ((signed(0) * signed(1)) >> 32) as u32,
+        Op::MulHighUnsigned => ((u64::from(args[0]) * u64::from(args[1])) >> 32) as u32,
+        Op::DivSigned => (args[0] as i32).checked_div(args[1] as i32)? as u32,
+        Op::DivUnsigned => args[0].checked_div(args[1])?,
+        Op::And => args[0] & args[1],
+        Op::Or => args[0] | args[1],
+        Op::Xor => args[0] ^ args[1],
+        Op::Not => !args[0],
+        Op::Neg => args[0].wrapping_neg(),
+        Op::ShiftLeft => args[0].checked_shl(shift()).unwrap_or(0),
+        Op::ShiftRightUnsigned => args[0].checked_shr(shift()).unwrap_or(0),
+        Op::ShiftRightSigned => (args[0] as i32).checked_shr(shift()).unwrap_or(args[0] as i32 >> 31) as u32,
+        Op::RotateLeft => args[0].rotate_left(args[1]),
+        Op::CountLeadingZeros => args[0].leading_zeros(),
+        Op::SignExtend8 => args[0] as i8 as u32,
+        Op::SignExtend16 => args[0] as i16 as u32,
+        Op::ByteSwap16 => u32::from((args[0] as u16).swap_bytes()),
+        Op::ByteSwap32 => args[0].swap_bytes(),
+        Op::Equal => u32::from(args[0] == args[1]),
+        Op::NotEqual => u32::from(args[0] != args[1]),
+        Op::LessSigned => u32::from((args[0] as i32) < args[1] as i32),
+        Op::LessUnsigned => u32::from(args[0] < args[1]),
+        Op::GreaterSigned => u32::from(args[0] as i32 > args[1] as i32),
+        Op::GreaterUnsigned => u32::from(args[0] > args[1]),
+        Op::Carry => u32::from(sum > u64::from(u32::MAX)),
+        Op::AddOverflow => u32::from(i32::try_from(signed_sum).is_err()),
+        Op::MulOverflow => u32::from(i32::try_from(signed(0) * signed(1)).is_err()),
+        Op::DivOverflow => u32::from(args[1] == 0 || (args[0] == 0x80000000 && args[1] == u32::MAX)),
+        Op::ShiftCarry => {
+            let dropped = match shift() {
+                32.. => args[0],
+                shift => args[0] & ((1 << shift) - 1),
+            };
+            u32::from((args[0] as i32) < 0 && dropped != 0)
+        }
+        _ => return None,
+    })
+}
+
+/// Simplifies an operation where only some of its arguments are known, returning what it's equal to.
+fn simplify(op: Op, args: &[Operand]) -> Option<Operand> {
+    let constant = |n: usize| match args.get(n) {
+        Some(Operand::Constant(value)) => Some(*value),
+        _ => None,
+    };
+    let same = args.len() == 2 && args[0] == args[1];
+    match op {
+        Op::Add
+        | Op::Sub
+        | Op::Or
+        | Op::Xor
+        | Op::ShiftLeft
+        | Op::ShiftRightUnsigned
+        | Op::ShiftRightSigned
+        | Op::RotateLeft
+            if args.len() == 2 && constant(1) == Some(0) =>
+        {
+            Some(args[0])
+        }
+        Op::Add | Op::Or | Op::Xor if args.len() == 2 && constant(0) == Some(0) => Some(args[1]),
+        Op::Mul if constant(1) == Some(1) => Some(args[0]),
+        Op::Mul if constant(0) == Some(1) => Some(args[1]),
+        Op::Mul | Op::And if constant(0) == Some(0) || constant(1) == Some(0) => Some(Operand::Constant(0)),
+        Op::And if constant(1) == Some(u32::MAX) => Some(args[0]),
+        Op::And | Op::Or if same => Some(args[0]),
+        Op::Sub | Op::Xor if same => Some(Operand::Constant(0)),
+        Op::Equal if same => Some(Operand::Constant(1)),
+        Op::NotEqual | Op::LessSigned | Op::LessUnsigned | Op::GreaterSigned | Op::GreaterUnsigned
+            if same =>
+        {
+            Some(Operand::Constant(0))
+        }
+        _ => None,
+    }
+}
+
+/// Folds constant expressions into copies, and turns phis where every source is the same into copies.
+fn fold(function: &mut IrFunction) -> bool {
+    // Adding constants one after another, like the stack pointer going down and back up, becomes one add
+    let mut offsets = HashMap::new();
+    for block in &function.blocks {
+        for statement in &block.statements {
+            if let Statement::Assign { dest, op: Op::Add, args } = statement {
+                if let [base @ Operand::Variable(_), Operand::Constant(offset)] = args[..] {
+                    offsets.insert(*dest, (base, offset));
+                }
+            }
+        }
+    }
+
+    let mut changed = false;
+    for block in &mut function.blocks {
+        for statement in &mut block.statements {
+            let (dest, value) = match statement {
+                Statement::Assign { op: Op::Copy, .. } => continue,
+                Statement::Assign { dest, op, args } => {
+                    if let (Op::Add, [Operand::Variable(inner), Operand::Constant(offset)]) =
+                        (*op, &mut args[..])
+                    {
+                        if let Some(&(base, first)) = offsets.get(inner) {
+                            *args = vec![base, Operand::Constant(first.wrapping_add(*offset))];
+                            changed = true;
+                        }
+                    }
+                    let constants: Option<Vec<u32>> = args
+                        .iter()
+                        .map(|arg| match arg {
+                            Operand::Constant(value) => Some(*value),
+                            Operand::Variable(_) => None,
+                        })
+                        .collect();
+                    let value = constants
+                        .and_then(|constants| evaluate(*op, &constants))
+                        .map(Operand::Constant)
+                        .or_else(|| simplify(*op, args));
+                    (*dest, value)
+                }
+                // Ignoring sources that are the phi itself, which happens in loops
+                Statement::Phi { dest, sources } => {
+                    let mut values = sources
+                        .iter()
+                        .map(|(_, operand)| *operand)
+                        .filter(|operand| operand.variable() != Some(*dest));
+                    let first = values.next();
+                    let value = first.filter(|first| values.all(|operand| operand == *first));
+                    (*dest, value)
+                }
+                _ => continue,
+            };
+            if let Some(value) = value {
+                *statement = Statement::Assign { dest, op: Op::Copy, args: vec![value] };
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+/// Replaces every use of a copy with whatever it copies.
+fn propagate(function: &mut IrFunction) -> bool {
+    let mut copies = HashMap::new();
+    for block in &function.blocks {
+        for statement in &block.statements {
+            if let Statement::Assign { dest, op: Op::Copy, args } = statement {
+                copies.insert(*dest, args[0]);
+            }
+        }
+    }
+    let resolve = |mut operand: Operand| {
+        // Copies can't form a cycle in SSA, except through a phi, so this always ends
+        while let Some(&next) = operand.variable().and_then(|variable| copies.get(&variable)) {
+            operand = next;
+        }
+        operand
+    };
+
+    let mut changed = false;
+    for block in &mut function.blocks {
+        let uses = block
+            .statements
+            .iter_mut()
+            .filter(|statement| !matches!(statement, Statement::Assign { op: Op::Copy, .. }))
+            .flat_map(Statement::uses_mut)
+            .chain(block.terminator.uses_mut());
+        for operand in uses {
+            let resolved = resolve(*operand);
+            if resolved != *operand {
+                *operand = resolved;
+                changed = true;
+            }
+        }
+
+        // Registers that end up holding what they did on entry don't need to be listed
+        if let Terminator::Return { values } | Terminator::TailCall { values, .. } = &mut block.terminator {
+            let length = values.len();
+            values.retain(|(register, value)| *value != Operand::from(*register));
+            changed |= values.len() != length;
+        }
+    }
+    changed
+}
+
+/// Turns branches that always go the same way into jumps, and calls that never happen into nothing, then
+/// removes any blocks that can't be reached anymore.
+fn fold_branches(function: &mut IrFunction) -> bool {
+    let mut changed = false;
+    for block in &mut function.blocks {
+        block.statements.retain(|statement| {
+            let never = matches!(
+                statement,
+                Statement::Call { condition: Some(Operand::Constant(0)), .. }
+            );
+            changed |= never;
+            !never
+        });
+        for statement in &mut block.statements {
+            if let Statement::Call { condition: condition @ Some(Operand::Constant(_)), .. } = statement {
+                *condition = None;
+                changed = true;
+            }
+        }
+
+        let target = match block.terminator {
+            Terminator::Switch { value: Operand::Constant(value), ref targets } => {
+                match targets.get(value as usize) {
+                    Some(&target) => target,
+                    None => continue,
+                }
+            }
+            Terminator::Switch { ref targets, .. } if targets.iter().all(|&target| target == targets[0]) => {
+                targets[0]
+            }
+            Terminator::Branch { condition: Operand::Constant(0), not_taken, .. } => not_taken,
+            Terminator::Branch { condition: Operand::Constant(_), taken, .. } => taken,
+            Terminator::Branch { taken, not_taken, .. } if taken == not_taken => taken,
+            _ => continue,
+        };
+        block.terminator = Terminator::Jump(target);
+        changed = true;
+    }
+
+    changed |= remove_unreachable(function);
+    // Phis can only take values from blocks that still lead to them
+    let predecessors = function.predecessors();
+    for (id, block) in function.blocks.iter_mut().enumerate() {
+        for statement in &mut block.statements {
+            if let Statement::Phi { sources, .. } = statement {
+                let length = sources.len();
+                sources.retain(|(source, _)| predecessors[id].contains(source));
+                changed |= sources.len() != length;
+            }
+        }
+    }
+    changed
+}
+
+/// Drops every block the entry can't reach, and renumbers the rest.
+fn remove_unreachable(function: &mut IrFunction) -> bool {
+    let mut reachable = reverse_postorder(function);
+    if reachable.len() == function.blocks.len() {
+        return false;
+    }
+    reachable.sort_unstable();
+    let mut ids = vec![None; function.blocks.len()];
+    for (new, &old) in reachable.iter().enumerate() {
+        ids[old] = Some(new);
+    }
+
+    let blocks = std::mem::take(&mut function.blocks);
+    function.blocks = blocks
+        .into_iter()
+        .enumerate()
+        .filter(|(old, _)| ids[*old].is_some())
+        .map(|(_, mut block)| {
+            renumber(&mut block, &ids);
+            block
+        })
+        .collect();
+    true
+}
+
+fn renumber(block: &mut Block, ids: &[Option<BlockId>]) {
+    match &mut block.terminator {
+        Terminator::Jump(target) => *target = ids[*target].unwrap(),
+        Terminator::Branch { taken, not_taken, .. } => {
+            *taken = ids[*taken].unwrap();
+            *not_taken = ids[*not_taken].unwrap();
+        }
+        Terminator::Switch { targets, .. } => {
+            targets.iter_mut().for_each(|target| *target = ids[*target].unwrap())
+        }
+        _ => (),
+    }
+    for statement in &mut block.statements {
+        if let Statement::Phi { sources, .. } = statement {
+            sources.retain_mut(|(source, _)| match ids[*source] {
+                Some(id) => {
+                    *source = id;
+                    true
+                }
+                None => false,
+            });
+        }
+    }
+}
+
+/// Removes every statement without side effects whose results are never used, and trims unused results off
+/// of the rest.
+fn eliminate_dead_code(function: &mut IrFunction) -> bool {
+    let mut definitions = HashMap::new();
+    let mut live = HashSet::new();
+    let mut worklist = Vec::new();
+    for (id, block) in function.blocks.iter_mut().enumerate() {
+        for (index, statement) in block.statements.iter_mut().enumerate() {
+            for variable in statement.definitions() {
+                definitions.insert(variable, (id, index));
+            }
+            if statement.has_effects() {
+                worklist.extend(statement.uses_mut().into_iter().filter_map(|operand| operand.variable()));
+            }
+        }
+        worklist.extend(block.terminator.uses_mut().into_iter().filter_map(|operand| operand.variable()));
+    }
+    while let Some(variable) = worklist.pop() {
+        if !live.insert(variable) {
+            continue;
+        }
+        if let Some(&(block, index)) = definitions.get(&variable) {
+            let statement = &mut function.blocks[block].statements[index];
+            if !statement.has_effects() {
+                worklist.extend(statement.uses_mut().into_iter().filter_map(|operand| operand.variable()));
+            }
+        }
+    }
+
+    let mut changed = false;
+    for block in &mut function.blocks {
+        let length = block.statements.len();
+        block.statements.retain(|statement| {
+            statement.has_effects() || statement.definitions().iter().any(|variable| live.contains(variable))
+        });
+        changed |= block.statements.len() != length;
+        for statement in &mut block.statements {
+            if let Statement::Call { results, .. } | Statement::Intrinsic { results, .. } = statement {
+                let length = results.len();
+                results.retain(|variable| live.contains(variable));
+                changed |= results.len() != length;
+            }
+        }
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ir::{lift, Callee};
+    use crate::testing::{program, TEXT};
+
+    /// Lifts the function at the start of `source`, converts it to SSA and optimizes it if asked to.
+    fn function(source: &str, optimized: bool) -> IrFunction {
+        let mut function = lift(&program(source, &[]), TEXT).unwrap();
+        build(&mut function);
+        if optimized {
+            optimize(&mut function);
+        }
+        function
+    }
+
+    fn phis(block: &Block) -> Vec<String> {
+        block
+            .statements
+            .iter()
+            .filter(|statement| matches!(statement, Statement::Phi { .. }))
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn places_phis_at_joins() {
+        let function = function(
+            "
+            cmpwi r3, 0
+            beq other
+            li r4, 1
+            b join
+        other:
+            li r4, 2
+        join:
+            stw r4, 0(r3)
+            blr
+            ",
+            false,
+        );
+        assert!(phis(&function.blocks[0]).is_empty());
+        assert!(phis(&function.blocks[1]).is_empty());
+        assert!(phis(&function.blocks[2]).is_empty());
+        assert_eq!(phis(&function.blocks[3]), ["r4_3 = phi r4_1 from 1, r4_2 from 2"]);
+        assert_eq!(
+            function.blocks[3].statements[1].to_string(),
+            "store.u32 [r3], r4_3"
+        );
+    }
+
+    #[test]
+    fn places_phis_at_loop_headers() {
+        let function = function(
+            "
+            li r4, 0
+        loop:
+            stwx r4, r3, r4
+            addi r4, r4, 4
+            cmpwi r4, 0x40
+            blt loop
+            blr
+            ",
+            false,
+        );
+        // The loop header is the only join, and the counter is the only register changed in the loop
+        let header = &function.blocks[1];
+        assert_eq!(header.address, TEXT + 4);
+        assert_eq!(phis(header)[0], "r4_2 = phi r4_1 from 0, r4_3 from 1");
+        assert!(phis(header).iter().all(|phi| phi.contains(" from 0, ") && phi.ends_with(" from 1")));
+        assert!(phis(&function.blocks[0]).is_empty());
+        assert!(phis(&function.blocks[2]).is_empty());
+    }
+
+    #[test]
+    fn folds_constants() {
+        let function = function(
+            "
+            li r5, 3
+            addi r5, r5, 4
+            mulli r5, r5, 2
+            xori r6, r5, 0xF
+            subf r5, r5, r6
+            stw r5, 0(r3)
+            blr
+            ",
+            true,
+        );
+        let block = &function.blocks[0];
+        assert_eq!(block.statements.len(), 1);
+        assert_eq!(
+            block.statements[0].to_string(),
+            format!("store.u32 [r3], {}", Operand::Constant(-13i32 as u32))
+        );
+    }
+
+    #[test]
+    fn folds_branches_that_always_go_one_way() {
+        let function = function(
+            "
+            li r4, 0
+            cmpwi r4, 0
+            bne skip
+            stw r4, 0(r3)
+        skip:
+            blr
+            ",
+            true,
+        );
+        assert!(function.blocks.iter().all(|block| !matches!(block.terminator, Terminator::Branch { .. })));
+        assert!(function.blocks.iter().any(|block| {
+            block.statements.iter().any(|statement| statement.to_string() == "store.u32 [r3], 0")
+        }));
+    }
+
+    #[test]
+    fn keeps_stores_and_calls() {
+        let function = function(
+            "
+            li r11, 9
+            mullw r12, r11, r11
+            lwz r7, 0(r3)
+            stw r3, 0(r4)
+            bl callee
+            blr
+        callee:
+            blr
+            ",
+            true,
+        );
+        let block = &function.blocks[0];
+        // Nothing reads r11 or r12 before the call clobbers them, but the load might be reading hardware
+        assert!(!block.statements.iter().any(|statement| matches!(statement, Statement::Assign { .. })));
+        assert!(matches!(block.statements[0], Statement::Load { .. }));
+        assert_eq!(block.statements[1].to_string(), "store.u32 [r4], r3");
+        assert!(matches!(
+            block.statements[2],
+            Statement::Call { callee: Callee::Direct(target), .. } if target == TEXT + 0x18
+        ));
+        assert_eq!(block.statements.len(), 3);
+    }
+}
@@ -0,0 +1,41 @@
+//! Everything Ferrox knows about binaries, without any UI: loaders for the formats it supports, the
+//! [`Program`](program::Program) they're loaded into, and the analysis passes that fill it in. The Ferrox window
+//! and command line are both built on top of this, and other tools can use it the same way:
+//!
+//! ```no_run
+//! use ferrox_core::format::BinaryFormat;
+//! use ferrox_core::program::Program;
+//!
+//! let data = std::fs::read("main.dol")?;
+//! let mut program = Program::load("main.dol".into(), data, BinaryFormat::GameCubeDOL)?;
+//! ferrox_core::analysis::analyze(&mut program);
+//! for (address, function) in &program.functions {
+//!     println!("{} at 0x{address:08X}, 0x{:X} bytes", program.display_name(*address), function.end - address);
+//! }
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+pub mod analysis;
+pub mod asm;
+pub mod ctype;
+pub mod database;
+pub mod decompile;
+pub mod demangle;
+pub mod error;
+pub mod format;
+pub mod history;
+pub mod ir;
+pub mod listing;
+pub mod map;
+pub mod navigation;
+pub mod object;
+pub mod patch;
+pub mod processor;
+pub mod program;
+pub mod registry;
+pub mod search;
+pub mod signature;
+pub mod split;
+pub mod symbols;
+#[cfg(test)]
+mod testing;
+pub mod text;
@@ -0,0 +1,387 @@
+//! The disassembly listing as text: the same lines the assembly view shows, for anything that wants them
+//! without a window, like exporting.
+use crate::analysis::exception;
+use crate::analysis::frame::slot_name;
+use crate::ctype;
+use crate::format::Permissions;
+use crate::processor::gekko::{format_immediate, Instruction, Operand};
+use crate::program::{OperandFormat, Program, XrefKind};
+use crate::registry::TypeInfo;
+use crate::text::escape;
+
+#[derive(Debug, Clone, Copy)]
+pub enum LineKind {
+    Segment,
+    FunctionStart,
+    Label,
+    /// Where an exception range or a catch block starts
+    Exception,
+    Instruction,
+    Data,
+    Uninitialized,
+    FunctionEnd,
+    Blank,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Line {
+    pub address: u32,
+    pub kind: LineKind,
+}
+
+/// Formats a number using the display format the user picked for it, falling back to the default if the
+/// value can't be shown that way (e.g. it's not a valid address).
+pub fn format_value(program: &Program, value: i64, format: &OperandFormat) -> String {
+    let formatted = match format {
+        OperandFormat::Default => None,
+        OperandFormat::Hex if value < 0 => Some(format!("-0x{:X}", value.unsigned_abs())),
+        OperandFormat::Hex => Some(format!("0x{value:X}")),
+        OperandFormat::Decimal => Some(value.to_string()),
+        OperandFormat::Char => {
+            let bytes = (value as u32).to_be_bytes();
+            let start = bytes.iter().position(|&byte| byte != 0).unwrap_or(3);
+            let bytes = &bytes[start..];
+            bytes
+                .iter()
+                .all(|byte| byte.is_ascii_graphic() || *byte == b' ')
+                .then(|| format!("'{}'", String::from_utf8_lossy(bytes)))
+        }
+        OperandFormat::Offset => {
+            let address = value as u32;
+            program.segment_at(address).map(|_| program.describe(address))
+        }
+        OperandFormat::Enum(name) => match program.types.definition(name) {
+            Some(TypeInfo::Enum { members, .. }) => {
+                members.iter().find(|(_, member)| *member == value).map(|(member, _)| member.clone())
+            }
+            _ => None,
+        },
+    };
+    formatted.unwrap_or_else(|| format_immediate(value))
+}
+
+/// Index of the operand that format hotkeys apply to: the first one that holds a number.
+pub fn formattable_operand(instruction: &Instruction) -> Option<usize> {
+    let (_, operands) = instruction.simplified();
+    operands.iter().position(|operand| {
+        matches!(
+            operand,
+            Operand::Simm(_) | Operand::Uimm(_) | Operand::Offset { .. }
+        )
+    })
+}
+
+/// Formats an instruction for display, resolving branch targets to names and applying any operand formats.
+pub fn format_instruction(program: &Program, instruction: &Instruction) -> String {
+    let (mnemonic, operands) = instruction.simplified();
+    // Registers show up under whatever they've been named as variables in the function
+    let function = program.function_containing(instruction.address);
+    let register = |register: String| match function {
+        Some(function) => program.variable_name(function.address, &register),
+        None => register,
+    };
+    // Stack slots are named after where they are in the frame, unless it's the `stwu` setting the frame up
+    let frame = function
+        .filter(|function| function.frame.size > 0 && function.frame.allocation != Some(instruction.address));
+    let slot = |disp: i32| {
+        frame.map(|function| {
+            let slot = slot_name(function.frame.entry_offset(instruction.address, disp));
+            program.variable_name(function.address, &slot)
+        })
+    };
+    // `addi rD, r1, disp` takes the address of a slot, as long as it's in this function's frame
+    let address_of = match (mnemonic.as_str(), &operands[..]) {
+        ("addi", [Operand::Gpr(dest), Operand::Gpr(1), Operand::Simm(disp)]) if *dest != 1 => frame
+            .filter(|function| function.frame.entry_offset(instruction.address, *disp) < 0)
+            .and(slot(*disp)),
+        _ => None,
+    };
+    let operands: Vec<String> = operands
+        .iter()
+        .enumerate()
+        .map(|(index, operand)| {
+            let format = program.operand_format(instruction.address, index);
+            match *operand {
+                Operand::Target(target) => program.display_name(target),
+                Operand::Simm(_) if *format == OperandFormat::Default => {
+                    address_of.clone().unwrap_or_else(|| operand.to_string())
+                }
+                Operand::Simm(value) if *format != OperandFormat::Default => {
+                    format_value(program, value.into(), format)
+                }
+                Operand::Uimm(value) if *format != OperandFormat::Default => {
+                    format_value(program, value.into(), format)
+                }
+                Operand::Offset { disp, base } => {
+                    let name = match (base, format) {
+                        (1, OperandFormat::Default) => slot(disp),
+                        _ => None,
+                    };
+                    let disp = name.unwrap_or_else(|| format_value(program, disp.into(), format));
+                    format!("{disp}({})", register(format!("r{base}")))
+                }
+                Operand::Gpr(_) | Operand::Fpr(_) => register(operand.to_string()),
+                operand => operand.to_string(),
+            }
+        })
+        .collect();
+    match operands.is_empty() {
+        true => mnemonic,
+        false => format!("{mnemonic:<9} {}", operands.join(", ")),
+    }
+}
+
+/// Formats the `.word` shown for data and anything that doesn't decode.
+fn format_word(program: &Program, address: u32, value: u32) -> String {
+    match program.operand_format(address, 0) {
+        OperandFormat::Default => format!(".word     0x{value:08X}"),
+        format => format!(".word     {}", format_value(program, value.into(), format)),
+    }
+}
+
+/// Size of the data line at `address`: a whole item, one element of an array, or raw bytes up to the next word
+/// boundary or item, whichever comes first.
+fn data_line_size(program: &Program, address: u32, end: u32) -> u32 {
+    let start = u64::from(address);
+    let size = match program.types.item_containing(start) {
+        Some((item_start, item_end, TypeInfo::Array { count, .. })) => {
+            (item_end - item_start) / (*count).max(1)
+        }
+        Some((_, item_end, _)) => item_end - start,
+        None => {
+            let next = program.types.next_start(start).unwrap_or(u64::MAX);
+            ((start | 3) + 1).min(next).min(end.into()) - start
+        }
+    };
+    size.max(1) as u32
+}
+
+/// Formats a data line according to the type of whatever is there.
+fn format_data(program: &Program, address: u32, size: u32) -> String {
+    let item = program.types.item_containing(address.into()).map(|(_, _, type_info)| type_info);
+    match item {
+        Some(TypeInfo::String { encoding, length }) => {
+            let bytes = program.bytes(address, (*length as u32).saturating_sub(1)).unwrap_or_default();
+            let text = encoding.decode(bytes).unwrap_or_else(|| String::from_utf8_lossy(bytes).into_owned());
+            return format!(".string   \"{}\"", escape(&text));
+        }
+        Some(TypeInfo::Float { bits: 32 }) => {
+            if let Some(value) = program.read_u32(address) {
+                return format!(".float    {:?}", f32::from_bits(value));
+            }
+        }
+        Some(TypeInfo::Float { bits: 64 }) => {
+            if let Some(bytes) = program.bytes(address, 8) {
+                return format!(".double   {:?}", f64::from_be_bytes(bytes.try_into().unwrap()));
+            }
+        }
+        Some(TypeInfo::Pointer { .. }) | Some(TypeInfo::Array { .. }) if size == 4 => {
+            if let Some(value) = program.read_u32(address) {
+                if *program.operand_format(address, 0) == OperandFormat::Default
+                    && program.segment_at(value).is_some()
+                {
+                    return format!(".word     {}", program.describe(value));
+                }
+            }
+        }
+        _ => (),
+    }
+
+    match (size, program.read_u32(address)) {
+        (4, Some(value)) => format_word(program, address, value),
+        _ => match program.bytes(address, size) {
+            Some(bytes) => {
+                let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{byte:02X}")).collect();
+                format!(".byte     {}", bytes.join(", "))
+            }
+            None => ".byte     ?".to_owned(),
+        },
+    }
+}
+
+/// Finds the comment to show at the end of a line: its own comment, or else a repeatable comment from whatever
+/// it references.
+pub fn line_comment(program: &Program, address: u32) -> Option<&str> {
+    if let Some(comment) =
+        program.comments.get(&address).or_else(|| program.repeatable_comments.get(&address))
+    {
+        return Some(comment);
+    }
+    let referenced = match program.instruction(address) {
+        Some(instruction) => {
+            let (_, operands) = instruction.simplified();
+            operands.iter().enumerate().find_map(|(index, operand)| match *operand {
+                Operand::Target(target) => Some(target),
+                Operand::Simm(value) if *program.operand_format(address, index) == OperandFormat::Offset => {
+                    Some(value as u32)
+                }
+                Operand::Uimm(value) if *program.operand_format(address, index) == OperandFormat::Offset => {
+                    Some(value)
+                }
+                _ => None,
+            })
+        }
+        None => {
+            program.read_u32(address).filter(|_| *program.operand_format(address, 0) == OperandFormat::Offset)
+        }
+    };
+    referenced.and_then(|target| program.repeatable_comments.get(&target)).map(String::as_str)
+}
+
+/// Formats the cross-references to an address as an assembly comment, e.g. `# CODE XREF: main+0x50↓j`.
+pub fn format_xrefs(program: &Program, address: u32) -> String {
+    let Some(xrefs) = program.xrefs.get(&address).filter(|xrefs| !xrefs.is_empty()) else {
+        return String::new();
+    };
+    let xref = xrefs[0];
+    let direction = if xref.from < address { '↑' } else { '↓' };
+    let (label, kind) = match xref.kind {
+        XrefKind::Call => ("CODE", 'p'),
+        XrefKind::Jump => ("CODE", 'j'),
+        XrefKind::Data => ("DATA", 'o'),
+    };
+    let more = if xrefs.len() > 1 { " ..." } else { "" };
+    format!(
+        "# {label} XREF: {}{direction}{kind}{more}",
+        program.describe(xref.from)
+    )
+}
+
+/// Builds every line of the listing, in address order.
+pub fn build(program: &Program) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut push = |address, kind| lines.push(Line { address, kind });
+    for segment in &program.segments {
+        push(segment.address, LineKind::Segment);
+        push(segment.address, LineKind::Blank);
+
+        if segment.permissions.contains(Permissions::UNINITIALIZED) {
+            if program.xrefs.contains_key(&segment.address) || program.names.contains_key(&segment.address) {
+                push(segment.address, LineKind::Label);
+            }
+            push(segment.address, LineKind::Uninitialized);
+            push(segment.address, LineKind::Blank);
+            continue;
+        }
+
+        let is_code = segment.permissions.contains(Permissions::EXECUTE);
+        let end = segment.address + segment.size;
+        let mut address = segment.address;
+        while address < end {
+            if is_code && program.functions.contains_key(&address) {
+                push(address, LineKind::FunctionStart);
+                push(address, LineKind::Label);
+            } else if program.xrefs.contains_key(&address) || program.names.contains_key(&address) {
+                push(address, LineKind::Blank);
+                push(address, LineKind::Label);
+            }
+
+            if is_code && exception::annotation(program, address).is_some() {
+                push(address, LineKind::Exception);
+            }
+            let kind = if is_code {
+                LineKind::Instruction
+            } else {
+                LineKind::Data
+            };
+            push(address, kind);
+
+            if is_code
+                && program.function_containing(address).is_some_and(|function| function.end == address + 4)
+            {
+                push(address, LineKind::FunctionEnd);
+                push(address, LineKind::Blank);
+            }
+            address += if is_code {
+                4
+            } else {
+                data_line_size(program, address, end)
+            };
+        }
+        push(end, LineKind::Blank);
+    }
+    lines
+}
+
+/// Text of a single line, everything but the address.
+pub fn line_text(program: &Program, line: Line) -> String {
+    let address = line.address;
+    match line.kind {
+        LineKind::Segment => {
+            let segment = program.segment_at(address).unwrap();
+            format!(
+                "# Segment {} (0x{:08X} - 0x{:08X}), {:?}",
+                segment.name,
+                segment.address,
+                segment.address + segment.size,
+                segment.permissions
+            )
+        }
+        LineKind::FunctionStart => {
+            let declaration = match program.types.prototype(address) {
+                Some(prototype) => ctype::declare_prototype(prototype, &program.short_name(address)),
+                None => program.display_name(address),
+            };
+            format!("# =============== S U B R O U T I N E: {declaration}")
+        }
+        LineKind::Label => {
+            format!(
+                " {:<39} {}",
+                format!("{}:", program.display_name(address)),
+                format_xrefs(program, address)
+            )
+        }
+        LineKind::Exception => match exception::annotation(program, address) {
+            Some(annotation) => format!("                 # {annotation}"),
+            None => String::new(),
+        },
+        LineKind::Instruction | LineKind::Data => {
+            let text = match (line.kind, program.instruction(address), program.read_u32(address)) {
+                (LineKind::Instruction, Some(instruction), _) => format_instruction(program, &instruction),
+                (LineKind::Data, _, _) => {
+                    let end =
+                        program.segment_at(address).map_or(address, |segment| segment.address + segment.size);
+                    format_data(program, address, data_line_size(program, address, end))
+                }
+                (_, _, Some(value)) => format_word(program, address, value),
+                _ => ".byte     ?".to_owned(),
+            };
+            match line_comment(program, address) {
+                Some(comment) => format!("                 {text:<39} # {}", comment.replace('\n', " ")),
+                None => format!("                 {text}"),
+            }
+        }
+        LineKind::Uninitialized => {
+            let segment = program.segment_at(address).unwrap();
+            format!("                 .space    0x{:X}", segment.size)
+        }
+        LineKind::FunctionEnd => match program.function_containing(address) {
+            Some(function) => format!("# End of function {}", program.display_name(function.address)),
+            None => String::new(),
+        },
+        LineKind::Blank => String::new(),
+    }
+}
+
+/// Address column of a line, e.g. `.text0:80003100`.
+pub fn address_text(program: &Program, address: u32) -> String {
+    match program.segment_at(address) {
+        Some(segment) => format!(".{}:{address:08X}", segment.name),
+        None => format!("{address:08X}"),
+    }
+}
+
+/// Renders the whole listing as plain text, exactly as it's shown.
+pub fn export(program: &Program) -> String {
+    let mut listing = String::new();
+    for line in build(program) {
+        let text = format!(
+            "{:<20}{}",
+            address_text(program, line.address),
+            line_text(program, line)
+        );
+        listing.push_str(text.trim_end());
+        listing.push('\n');
+    }
+    listing
+}
@@ -0,0 +1,199 @@
+//! Symbol maps, in the `.text section layout` style that both CodeWarrior's linker and Dolphin write.
+use std::fmt::Write;
+
+use crate::program::Program;
+
+/// One symbol from a map.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapSymbol {
+    /// Section it was listed under, without the leading `.`
+    pub section: String,
+    pub address: u32,
+    pub size: u32,
+    pub name: String,
+}
+
+/// Reads every symbol out of a map. Anything that doesn't look like a symbol line is skipped, since maps are
+/// full of headers and memory maps that don't matter here.
+pub fn parse(text: &str) -> Vec<MapSymbol> {
+    let mut symbols = Vec::new();
+    let mut section = None;
+    for line in text.lines() {
+        let line = line.trim();
+        if let Some(name) = line.strip_suffix(" section layout") {
+            section = Some(name.trim_start_matches('.').to_owned());
+            continue;
+        }
+        let Some(section) = &section else {
+            continue;
+        };
+
+        // `address size virtual-address [alignment] name [object]`, with the object only in linker maps
+        let columns: Vec<&str> = line.split_whitespace().collect();
+        let hex = |index: usize| columns.get(index).and_then(|column| u32::from_str_radix(column, 16).ok());
+        let (Some(_), Some(size), Some(address)) = (hex(0), hex(1), hex(2)) else {
+            continue;
+        };
+        let name = match columns.get(3) {
+            Some(alignment) if alignment.parse::<u32>().is_ok() => columns.get(4),
+            name => name,
+        };
+        // Section symbols and stripped entries aren't worth naming anything after
+        let Some(&name) = name.filter(|name| !name.starts_with('.') && **name != "UNUSED") else {
+            continue;
+        };
+        if address == 0 {
+            continue;
+        }
+        symbols.push(MapSymbol { section: section.clone(), address, size, name: name.to_owned() });
+    }
+    symbols
+}
+
+/// Names every symbol from a map as a single edit, so the whole import can be undone. Returns how many were
+/// applied, names that are invalid or already in use somewhere else are skipped. So are automatic names, like
+/// Dolphin's `zz_80003100_` or our own `sub_80003100`, which only say there's a function there.
+pub fn apply(program: &mut Program, symbols: &[MapSymbol]) -> usize {
+    let names: Vec<(u32, String)> = symbols
+        .iter()
+        .filter(|symbol| program.segment_at(symbol.address).is_some() && !is_automatic(&symbol.name))
+        .map(|symbol| (symbol.address, symbol.name.clone()))
+        .collect();
+    program.rename_all(format!("Import {} names from a map", names.len()), &names)
+}
+
+fn is_automatic(name: &str) -> bool {
+    let address = ["zz_", "sub_", "loc_", "unk_"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .map(|rest| rest.strip_suffix('_').unwrap_or(rest));
+    address.is_some_and(|address| address.len() == 8 && address.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// Writes every function and named piece of data out as a map in the format Dolphin saves and loads, so the
+/// same names show up in its debugger. Names are written the way they're stored, since Dolphin demangles them
+/// itself. Data doesn't have a size of its own, so each symbol runs up to the next one.
+pub fn export(program: &Program) -> String {
+    let mut map = String::from(".text section layout\n");
+    for function in program.functions.values() {
+        let size = function.end - function.address;
+        let _ = writeln!(
+            map,
+            "{0:08x} {size:06x} {0:08x} 0 {1}",
+            function.address,
+            program.raw_name(function.address)
+        );
+    }
+
+    map.push_str("\n.data section layout\n");
+    let data: Vec<u32> = program.names.keys().copied().filter(|&address| !program.is_code(address)).collect();
+    for (index, &address) in data.iter().enumerate() {
+        let Some(segment) = program.segment_at(address) else {
+            continue;
+        };
+        let segment_end = segment.address + segment.size;
+        let end = data.get(index + 1).map_or(segment_end, |&next| next.min(segment_end));
+        let _ = writeln!(
+            map,
+            "{0:08x} {1:06x} {0:08x} 0 {2}",
+            address,
+            end - address,
+            program.raw_name(address)
+        );
+    }
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, DATA, TEXT};
+
+    const SOURCE: &str = "
+        bl helper
+        blr
+    helper:
+        blr
+    ";
+
+    fn symbol(section: &str, address: u32, size: u32, name: &str) -> MapSymbol {
+        MapSymbol { section: section.to_owned(), address, size, name: name.to_owned() }
+    }
+
+    #[test]
+    fn round_trips_exported_maps() {
+        let mut program = testing::program(SOURCE, &[0; 0x10]);
+        program.names.insert(TEXT + 8, "helper".to_owned());
+        program.names.insert(DATA, "gCount".to_owned());
+        program.names.insert(DATA + 8, "gTable".to_owned());
+        // Names outside every segment have nowhere to go
+        program.names.insert(0x9000_0000, "gHardware".to_owned());
+
+        let map = export(&program);
+        assert_eq!(
+            parse(&map),
+            [
+                symbol("text", TEXT, 8, "__start"),
+                symbol("text", TEXT + 8, 4, "helper"),
+                symbol("data", DATA, 8, "gCount"),
+                symbol("data", DATA + 8, 8, "gTable"),
+            ]
+        );
+
+        let mut fresh = testing::program(SOURCE, &[0; 0x10]);
+        assert_eq!(apply(&mut fresh, &parse(&map)), 3);
+        for address in [TEXT, TEXT + 8, DATA, DATA + 8] {
+            assert_eq!(fresh.names.get(&address), program.names.get(&address));
+        }
+    }
+
+    #[test]
+    fn parses_linker_and_dolphin_maps() {
+        let map = "
+            Link map of __start
+            .init section layout
+              Starting        Virtual
+              address  Size   address
+              -----------------------
+              00000000 000094 80003100  4 .init 	os.a __start.c
+              00000000 000094 80003100  4 __start 	os.a __start.c
+              UNUSED   000010 ........ helper os.a
+              00000094 000010 80003194
+              not even close
+            .text section layout
+            80003200 000020 80003200 0 zz_80003200_
+            80003220 000010 80003220 0 main
+            80003230 000010 00000000 0 removed
+            .data section layout
+            80004000 000008 80004000 0 gCount
+            90000000 000004 90000000 0 gHardware
+        ";
+        assert_eq!(
+            parse(map),
+            [
+                symbol("init", 0x8000_3100, 0x94, "__start"),
+                symbol("text", 0x8000_3200, 0x20, "zz_80003200_"),
+                symbol("text", 0x8000_3220, 0x10, "main"),
+                symbol("data", DATA, 8, "gCount"),
+                symbol("data", 0x9000_0000, 4, "gHardware"),
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_only_real_names_inside_the_program() {
+        let mut program = testing::program(SOURCE, &[0; 0x10]);
+        let symbols = [
+            symbol("text", TEXT + 8, 4, "zz_80003108_"),
+            symbol("data", DATA, 8, "gCount"),
+            symbol("data", 0x9000_0000, 4, "gHardware"),
+        ];
+        assert_eq!(apply(&mut program, &symbols), 1);
+        assert_eq!(program.names[&DATA], "gCount");
+        assert!(!program.names.contains_key(&(TEXT + 8)));
+        assert!(!program.names.contains_key(&0x9000_0000));
+        // The whole import is one edit
+        assert!(program.undo());
+        assert!(!program.names.contains_key(&DATA));
+    }
+}
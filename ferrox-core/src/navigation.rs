@@ -0,0 +1,299 @@
+//! Moving around the program: resolving what the user typed into an address, searching symbols, and keeping
+//! track of where they've been so they can go back.
+use snafu::ensure;
+
+use crate::error::{FerroxError, InvalidExpressionSnafu};
+use crate::program::Program;
+use crate::text;
+
+/// Resolves an address expression: a sum of hex numbers, `section:offset` pairs and symbol names, e.g.
+/// `main+0x40`, `.text0:10` or `80003100`. Names can have `+` and `-` in them too, like `operator+`, so the
+/// longest run that names a symbol is taken before anything is treated as an operator.
+pub fn resolve(program: &Program, expression: &str) -> Result<u32, FerroxError> {
+    let expression = expression.trim();
+    ensure!(
+        !expression.is_empty(),
+        InvalidExpressionSnafu { expression, reason: "nothing to go to" }
+    );
+
+    let mut address = 0u32;
+    let mut negative = false;
+    let mut rest = expression;
+    loop {
+        let operators: Vec<usize> = rest.match_indices(['+', '-']).map(|(index, _)| index).collect();
+        let end = match operators.is_empty() {
+            true => rest.len(),
+            false => operators
+                .iter()
+                .copied()
+                .chain([rest.len()])
+                .rev()
+                .find(|&end| symbol(program, rest[..end].trim()).is_some())
+                .unwrap_or(operators[0]),
+        };
+        let term = rest[..end].trim();
+        ensure!(
+            !term.is_empty(),
+            InvalidExpressionSnafu { expression, reason: "missing a value" }
+        );
+        let value = resolve_term(program, term)
+            .map_err(|reason| FerroxError::InvalidExpression { expression: expression.to_owned(), reason })?;
+        address = match negative {
+            true => address.wrapping_sub(value),
+            false => address.wrapping_add(value),
+        };
+
+        match rest[end..].chars().next() {
+            Some(operator) => {
+                negative = operator == '-';
+                rest = &rest[end + 1..];
+            }
+            None => break,
+        }
+    }
+
+    ensure!(
+        program.segment_at(address).is_some(),
+        InvalidExpressionSnafu {
+            expression,
+            reason: format!("0x{address:08X} isn't inside any segment")
+        }
+    );
+    Ok(address)
+}
+
+/// Address of the symbol with this name, or this demangled name with or without its parameters.
+fn symbol(program: &Program, name: &str) -> Option<u32> {
+    if let Some((&address, _)) = program.names.iter().find(|(_, candidate)| *candidate == name) {
+        return Some(address);
+    }
+    program.names.keys().copied().find(|&address| {
+        program
+            .demangled(address)
+            .is_some_and(|demangled| demangled.qualified_name() == name || demangled.to_string() == name)
+    })
+}
+
+/// Parses an address, which is hex whether or not it starts with `0x`, like everywhere addresses show up.
+fn hex(text: &str) -> Option<u32> {
+    let digits = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")).unwrap_or(text);
+    text::parse_number(&format!("0x{digits}")).and_then(|value| u32::try_from(value).ok())
+}
+
+fn resolve_term(program: &Program, term: &str) -> Result<u32, String> {
+    if term.starts_with("0x") || term.starts_with("0X") {
+        return hex(term).ok_or_else(|| format!("\"{term}\" isn't a valid hex number"));
+    }
+    if let Some(address) = symbol(program, term) {
+        return Ok(address);
+    }
+    // Either an offset into the section, or the `.section:address` the listing shows. Scoped names have
+    // colons too, but never just the one
+    if let Some((section, offset)) = term.split_once(':').filter(|_| !term.contains("::")) {
+        let section = section.trim_start_matches('.');
+        let segment = program
+            .segments
+            .iter()
+            .find(|segment| segment.name == section)
+            .ok_or_else(|| format!("there's no section named \"{section}\""))?;
+        let offset = hex(offset).ok_or_else(|| format!("\"{offset}\" isn't a valid offset"))?;
+        return Ok(match segment.contains(offset) {
+            true => offset,
+            false => segment.address.wrapping_add(offset),
+        });
+    }
+
+    // Automatic names aren't stored anywhere, but they're just the address
+    for prefix in ["sub_", "loc_", "unk_"] {
+        if let Some(address) = term.strip_prefix(prefix).and_then(hex) {
+            return Ok(address);
+        }
+    }
+    hex(term).ok_or_else(|| format!("there's no symbol named \"{term}\""))
+}
+
+/// Scores how well `name` matches a fuzzy `query`, where every character of the query has to appear in order.
+/// Higher is better, consecutive characters and matches at the start of words are preferred.
+pub fn fuzzy_score(query: &str, name: &str) -> Option<i32> {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let mut chars = name.char_indices();
+    let mut last_char = None;
+
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let wanted = wanted.to_ascii_lowercase();
+        loop {
+            let (index, c) = chars.next()?;
+            let before = last_char.replace(c);
+            if c.to_ascii_lowercase() != wanted {
+                continue;
+            }
+            score += 1;
+            if previous.is_some_and(|previous| previous + 1 == index) {
+                score += 5;
+            }
+            if index == 0 || before.is_some_and(|before: char| before == '_' || before == ':') {
+                score += 3;
+            }
+            previous = Some(index);
+            break;
+        }
+    }
+    // Shorter names are more likely to be what the user meant
+    Some(score * 4 - name.len() as i32 / 4)
+}
+
+/// Finds the symbols that best match `query`, best match first.
+pub fn search_symbols(program: &Program, query: &str, limit: usize) -> Vec<(u32, String)> {
+    if query.trim().is_empty() {
+        return Vec::new();
+    }
+    let mut matches: Vec<(i32, u32, String)> = program
+        .names
+        .keys()
+        .chain(program.functions.keys().filter(|address| !program.names.contains_key(address)))
+        .filter_map(|&address| {
+            let name = program.display_name(address);
+            fuzzy_score(query, &name).map(|score| (score, address, name))
+        })
+        .collect();
+    matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    matches.into_iter().take(limit).map(|(_, address, name)| (address, name)).collect()
+}
+
+/// Back/forward history, like a web browser.
+#[derive(Debug, Default)]
+pub struct Navigation {
+    back: Vec<u32>,
+    forward: Vec<u32>,
+}
+
+impl Navigation {
+    /// Furthest back we'll remember, so the history can't grow forever.
+    const LIMIT: usize = 256;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that we've left `from`, clearing anything we could have gone forward to.
+    pub fn push(&mut self, from: u32) {
+        if self.back.last() != Some(&from) {
+            self.back.push(from);
+            if self.back.len() > Self::LIMIT {
+                self.back.remove(0);
+            }
+        }
+        self.forward.clear();
+    }
+
+    /// Moves the cursor to `to`, recording where it was.
+    pub fn jump(&mut self, cursor: &mut u32, to: u32) {
+        if *cursor != to {
+            self.push(*cursor);
+            *cursor = to;
+        }
+    }
+
+    pub fn back(&mut self, cursor: &mut u32) -> bool {
+        match self.back.pop() {
+            Some(address) => {
+                self.forward.push(*cursor);
+                *cursor = address;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn forward(&mut self, cursor: &mut u32) -> bool {
+        match self.forward.pop() {
+            Some(address) => {
+                self.back.push(*cursor);
+                *cursor = address;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{program, TEXT};
+
+    #[test]
+    fn resolves_expressions() {
+        let mut program = program(&"blr\n".repeat(8), &[]);
+        program.rename(TEXT, "calc__3FooFv").unwrap();
+        program.rename(TEXT + 4, "update__Q23foo3BarFf").unwrap();
+        program.rename(TEXT + 8, "main").unwrap();
+        program.rename(TEXT + 12, "__pl__6VectorFRC6Vector").unwrap();
+        program.rename(TEXT + 16, "__mi__6VectorFRC6Vector").unwrap();
+        program.rename(TEXT + 20, "load-data").unwrap();
+
+        let cases = [
+            ("80003100", TEXT),
+            ("0x80003104", TEXT + 4),
+            ("sub_80003108", TEXT + 8),
+            ("main", TEXT + 8),
+            ("main+4", TEXT + 12),
+            ("main - 0x8", TEXT),
+            ("text0:4", TEXT + 4),
+            (".text0:0x8", TEXT + 8),
+            (".text0:8000310C", TEXT + 12),
+            ("calc__3FooFv", TEXT),
+            ("Foo::calc", TEXT),
+            ("Foo::calc()", TEXT),
+            ("foo::Bar::update", TEXT + 4),
+            ("foo::Bar::update(float)", TEXT + 4),
+            ("foo::Bar::update+8", TEXT + 12),
+            // Names with operators in them are taken whole before anything is added or subtracted
+            ("Vector::operator+", TEXT + 12),
+            ("Vector::operator+(const Vector&)", TEXT + 12),
+            ("Vector::operator+ + 4", TEXT + 16),
+            ("Vector::operator+-4", TEXT + 8),
+            ("Vector::operator-", TEXT + 16),
+            ("Vector::operator- - 0x10", TEXT),
+            ("load-data", TEXT + 20),
+            ("load-data-4", TEXT + 16),
+            ("main-4+load-data-0x80003114", TEXT + 4),
+        ];
+        for (expression, address) in cases {
+            assert_eq!(resolve(&program, expression).ok(), Some(address), "{expression}");
+        }
+    }
+
+    #[test]
+    fn explains_what_it_cant_resolve() {
+        let program = program("blr", &[]);
+        let cases = [
+            ("", "nothing to go to"),
+            ("80003100+", "missing a value"),
+            ("nowhere", "there's no symbol named \"nowhere\""),
+            (
+                "foo::Bar::nowhere",
+                "there's no symbol named \"foo::Bar::nowhere\"",
+            ),
+            ("data0:10", "there's no section named \"data0\""),
+            ("text0:zz", "\"zz\" isn't a valid offset"),
+            ("0xZZ", "\"0xZZ\" isn't a valid hex number"),
+            ("10", "0x00000010 isn't inside any segment"),
+        ];
+        for (expression, reason) in cases {
+            match resolve(&program, expression) {
+                Err(FerroxError::InvalidExpression { reason: actual, .. }) => assert_eq!(actual, reason),
+                other => panic!("{expression}: {other:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Relocatable objects, one per split, for decomp projects that relink the original binary out of per-file
+//! objects. Each object holds the split's bytes as they are, along with the symbols defined in it and a
+//! relocation for every recovered address, so moving things around while linking still works.
+use std::collections::HashMap;
+
+use crate::error::FerroxError;
+use crate::format::elf::{
+    self, ElfRelocation, ElfSymbol, ObjectSection, SymbolBinding, SymbolKind, SHN_ABS, SHN_UNDEF,
+};
+use crate::format::Permissions;
+use crate::program::Program;
+use crate::split::Split;
+use crate::symbols::{LabelKind, SymbolTable};
+
+/// Writes splits as ELF objects, sharing one symbol table between all of them.
+pub struct ObjectExporter<'a> {
+    program: &'a Program,
+    symbols: SymbolTable,
+}
+
+impl<'a> ObjectExporter<'a> {
+    pub fn new(program: &'a Program) -> Self {
+        Self { program, symbols: SymbolTable::new(program) }
+    }
+
+    /// Builds the object for one split.
+    pub fn export(&self, split: &Split) -> Result<Vec<u8>, FerroxError> {
+        let program = self.program;
+        let mut sections = Vec::new();
+        let mut symbols = vec![ElfSymbol {
+            name: split.name.clone(),
+            value: 0,
+            size: 0,
+            kind: SymbolKind::File,
+            binding: SymbolBinding::Local,
+            section: SHN_ABS,
+        }];
+        // Symbol index of every address defined in this object, counting from 1 like ELF does
+        let mut defined = HashMap::new();
+        let mut ranges = Vec::new();
+
+        for range in &split.ranges {
+            let Some(segment) = program.segment_at(range.start) else {
+                continue;
+            };
+            let end = range.end.min(segment.address + segment.size);
+            let uninitialized = segment.permissions.contains(Permissions::UNINITIALIZED);
+            let alignment = match segment.permissions.contains(Permissions::EXECUTE) {
+                true => 4,
+                // Anything stricter than where it already is would move it
+                false => 1 << range.start.trailing_zeros().min(3),
+            };
+            sections.push(ObjectSection {
+                name: range.section.clone(),
+                permissions: segment.permissions,
+                alignment,
+                data: match uninitialized {
+                    true => Vec::new(),
+                    false => program.bytes(range.start, end - range.start).unwrap_or_default().to_vec(),
+                },
+                size: end - range.start,
+            });
+            let section = sections.len() as u16;
+            ranges.push((range.start, end, section));
+
+            let labels: Vec<_> = self.symbols.labels.range(range.start..end).collect();
+            for (position, &(&address, label)) in labels.iter().enumerate() {
+                let next = labels.get(position + 1).map_or(end, |(&next, _)| next);
+                let (kind, size) = match label.kind {
+                    LabelKind::Function => {
+                        let function_end =
+                            program.functions.get(&address).map_or(next, |function| function.end);
+                        (SymbolKind::Function, function_end.min(end) - address)
+                    }
+                    _ if segment.permissions.contains(Permissions::EXECUTE) => (SymbolKind::None, 0),
+                    _ => (SymbolKind::Object, next - address),
+                };
+                symbols.push(ElfSymbol {
+                    name: label.name.clone(),
+                    value: address - range.start,
+                    size,
+                    kind,
+                    binding: match label.kind {
+                        LabelKind::Local => SymbolBinding::Local,
+                        _ => SymbolBinding::Global,
+                    },
+                    section,
+                });
+                defined.insert(address, symbols.len() as u32);
+            }
+        }
+
+        let mut undefined: HashMap<String, u32> = HashMap::new();
+        let mut relocations = Vec::new();
+        for &(start, end, section) in &ranges {
+            for (&address, relocation) in self.symbols.relocations.range(start..end) {
+                let Some((label, addend)) = self.symbols.resolve(relocation.target) else {
+                    continue;
+                };
+                let label_address = relocation.target - addend;
+                let symbol = match defined.get(&label_address) {
+                    Some(&symbol) => symbol,
+                    // Local labels belong to one function, if that's somewhere else the bytes are left as is
+                    None if label.kind == LabelKind::Local => continue,
+                    None => *undefined.entry(label.name.clone()).or_insert_with(|| {
+                        symbols.push(ElfSymbol {
+                            name: label.name.clone(),
+                            value: 0,
+                            size: 0,
+                            kind: SymbolKind::None,
+                            binding: SymbolBinding::Global,
+                            section: SHN_UNDEF,
+                        });
+                        symbols.len() as u32
+                    }),
+                };
+                relocations.push(ElfRelocation {
+                    section: section as usize,
+                    offset: address - start + relocation.kind.field_offset(),
+                    kind: relocation.kind.elf_type(),
+                    symbol,
+                    addend: addend as i32,
+                });
+            }
+        }
+
+        elf::write_object(&sections, &symbols, &relocations)
+    }
+}
@@ -0,0 +1,71 @@
+//! Exporting patches without the rest of the database, either as Gecko/Ocarina codes that Dolphin or a code
+//! handler applies in memory, or as an IPS patch against the original file. A rewritten binary comes from
+//! [`Program::write_dol`] instead, since `data` already has every patch applied.
+use std::fmt::Write;
+
+use snafu::ensure;
+
+use crate::error::{FerroxError, InvalidPatchSnafu};
+use crate::program::Program;
+
+/// The range of memory the direct write codes can reach.
+const GECKO_RANGE: std::ops::Range<u32> = 0x80000000..0x82000000;
+
+/// Writes every patch as a single Gecko code named `name`, in the `$name` format Dolphin's code lists use.
+/// The direct write codes (`00`/`02`/`04`) work the same way on Ocarina, so the output is valid for both.
+pub fn gecko_codes(program: &Program, name: &str) -> Result<String, FerroxError> {
+    let mut output = format!("${name}\n");
+    for (&address, patch) in &program.patches {
+        let end = address + patch.patched.len() as u32;
+        ensure!(
+            GECKO_RANGE.contains(&address) && end <= GECKO_RANGE.end,
+            InvalidPatchSnafu { address, reason: "Gecko codes can only write to 0x80000000-0x81FFFFFF" }
+        );
+
+        // Words wherever they're aligned, with halfwords and bytes around them
+        let mut offset = 0;
+        while offset < patch.patched.len() {
+            let current = address + offset as u32;
+            let bytes = &patch.patched[offset..];
+            let (kind, size) = match bytes.len() {
+                4.. if current.is_multiple_of(4) => (0x04, 4),
+                2.. if current.is_multiple_of(2) => (0x02, 2),
+                _ => (0x00, 1),
+            };
+            let value = bytes[..size].iter().fold(0u32, |value, &byte| (value << 8) | u32::from(byte));
+            // Bit 24 of the address carries into the code type, e.g. 0x81000000 is written with 05
+            let code = (kind << 24) + (current & 0x01FFFFFF);
+            writeln!(output, "{code:08X} {value:08X}").unwrap();
+            offset += size;
+        }
+    }
+    Ok(output)
+}
+
+/// Writes every patch as an IPS patch, which most patching tools can apply to the original file.
+pub fn ips(program: &Program) -> Result<Vec<u8>, FerroxError> {
+    let mut output = b"PATCH".to_vec();
+    for (&address, patch) in &program.patches {
+        let error = |reason: &'static str| InvalidPatchSnafu { address, reason };
+        let offset = program.file_offset(address).ok_or_else(|| error("it isn't in the file").build())?;
+        let (mut offset, mut bytes) = (offset as usize, patch.patched.clone());
+        // This offset would read as the end of the patch, so start a byte early instead
+        if offset == 0x454F46 {
+            offset -= 1;
+            bytes.insert(0, program.data[offset]);
+        }
+        ensure!(
+            offset < 0x1000000,
+            error("IPS patches can only reach the first 16 MiB of a file")
+        );
+
+        for (index, chunk) in bytes.chunks(0xFFFF).enumerate() {
+            let start = offset + index * 0xFFFF;
+            output.extend_from_slice(&(start as u32).to_be_bytes()[1..]);
+            output.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+            output.extend_from_slice(chunk);
+        }
+    }
+    output.extend_from_slice(b"EOF");
+    Ok(output)
+}
@@ -0,0 +1,472 @@
+//! Assembler for single Gekko/Broadway instructions, the inverse of the [`gekko`](super::gekko) decoder.
+//!
+//! Anything the listing shows can be typed back in, so an instruction can be copied out, edited and patched
+//! over the original. On top of the canonical forms (with their `.`, `o`, `l` and `a` suffixes), this takes the
+//! same simplified mnemonics the decoder produces, including conditional branches with `+`/`-` hints.
+//! Immediates and branch targets can be numbers (decimal, or hex with `0x`) or symbols, and immediates can
+//! take the half of an address they need with `@h`, `@ha` or `@l`.
+use super::gekko::{find_form, spr_name, Field, Form, Instruction, Suffix};
+use crate::error::FerroxError;
+use crate::text::parse_number;
+
+/// Mnemonic endings that set suffix bits, tried in order so forms whose name already ends in `.` (like
+/// `andi.`) are found before `.` is taken to mean Rc.
+const SUFFIXES: [(&str, Suffix); 7] = [
+    ("", Suffix::empty()),
+    (".", Suffix::RC),
+    ("o", Suffix::OE),
+    ("o.", Suffix::OE.union(Suffix::RC)),
+    ("l", Suffix::LK),
+    ("a", Suffix::AA),
+    ("la", Suffix::LK.union(Suffix::AA)),
+];
+
+const CONDITIONS: [&str; 4] = ["lt", "gt", "eq", "so"];
+const NEGATED: [&str; 4] = ["ge", "le", "ne", "ns"];
+
+/// Assembles one instruction as it would be placed at `address`, which is needed for relative branches.
+/// `symbol` resolves any name that isn't a number, e.g. with [`navigation::resolve`](crate::navigation::resolve).
+pub fn assemble(address: u32, text: &str, symbol: impl Fn(&str) -> Option<u32>) -> Result<u32, FerroxError> {
+    let text = text.trim();
+    let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+        Some((mnemonic, operands)) => (mnemonic, split_operands(operands)),
+        None => (text, Vec::new()),
+    };
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    let assembler = Assembler { address, symbol: &symbol };
+    assembler
+        .instruction(&mnemonic, &operands)
+        .map_err(|reason| FerroxError::InvalidAssembly { text: text.to_owned(), reason })
+}
+
+/// Splits operands on commas, trimming each one. Parentheses never contain commas, so there's nothing to skip.
+fn split_operands(operands: &str) -> Vec<String> {
+    let operands = operands.trim();
+    if operands.is_empty() {
+        return Vec::new();
+    }
+    operands.split(',').map(|operand| operand.trim().to_owned()).collect()
+}
+
+struct Assembler<'a> {
+    address: u32,
+    symbol: &'a dyn Fn(&str) -> Option<u32>,
+}
+
+impl Assembler<'_> {
+    fn instruction(&self, mnemonic: &str, operands: &[String]) -> Result<u32, String> {
+        if let Some((mnemonic, operands)) = expand(mnemonic, operands)? {
+            return self.canonical(&mnemonic, &operands);
+        }
+        if let Some((form, suffix)) = canonical_form(mnemonic) {
+            return self.encode(form, suffix, operands);
+        }
+        if let Some(code) = self.branch(mnemonic, operands)? {
+            return Ok(code);
+        }
+        Err(format!("unknown mnemonic \"{mnemonic}\""))
+    }
+
+    fn canonical(&self, mnemonic: &str, operands: &[String]) -> Result<u32, String> {
+        let (form, suffix) =
+            canonical_form(mnemonic).ok_or_else(|| format!("unknown mnemonic \"{mnemonic}\""))?;
+        self.encode(form, suffix, operands)
+    }
+
+    /// Encodes a form with its operands, then decodes the result to catch combinations the decoder wouldn't
+    /// accept (so the listing never shows something other than what was typed).
+    fn encode(&self, form: &'static Form, suffix: Suffix, operands: &[String]) -> Result<u32, String> {
+        if operands.len() != form.fields.len() {
+            return Err(format!(
+                "{} takes {} operands, not {}",
+                form.mnemonic,
+                form.fields.len(),
+                operands.len()
+            ));
+        }
+
+        let mut code = form.bits | suffix.bits();
+        for (&field, operand) in form.fields.iter().zip(operands) {
+            code |= self.field(field, operand, suffix)?;
+        }
+        match Instruction::decode(self.address, code) {
+            Some(instruction) if core::ptr::eq(instruction.form, form) => Ok(code),
+            _ => Err(format!("0x{code:08X} isn't a valid instruction")),
+        }
+    }
+
+    /// Bits for one operand, already shifted into place.
+    fn field(&self, field: Field, operand: &str, suffix: Suffix) -> Result<u32, String> {
+        use Field::*;
+        let bits = match field {
+            RD | RS | RA | RB => field.insert(register(operand, "r")?),
+            FRD | FRS | FRA | FRB | FRC => field.insert(register(operand, "f")?),
+            CRFD | CRFS => field.insert(condition_field(operand)?),
+            CRBD | CRBA | CRBB | BI => field.insert(condition_bit(operand)?),
+            SIMM => field.insert(self.half(operand, -0x8000..=0x7FFF)?),
+            UIMM => field.insert(self.half(operand, 0..=0xFFFF)?),
+            D | PsD => {
+                let (disp, base) = operand
+                    .strip_suffix(')')
+                    .and_then(|operand| operand.split_once('('))
+                    .ok_or_else(|| format!("expected an offset like 8(r3), not \"{operand}\""))?;
+                let disp = match disp.trim() {
+                    "" => 0,
+                    _ if field == PsD => self.number(disp, -0x800..=0x7FF)?,
+                    _ => self.half(disp, -0x8000..=0x7FFF)?,
+                };
+                field.insert(disp) | RA.insert(register(base.trim(), "r")?)
+            }
+            SPR | TBR => field.insert(special_register(operand)?),
+            BD | LI => {
+                let target = self.value(operand)? as u32;
+                let displacement = match suffix.contains(Suffix::AA) {
+                    true => target,
+                    false => target.wrapping_sub(self.address),
+                };
+                let range = if field == BD { 0x8000 } else { 0x200_0000 };
+                let signed = displacement as i32;
+                if !(-range..range).contains(&signed) {
+                    return Err(format!("0x{target:08X} is out of range"));
+                }
+                if displacement & 3 != 0 {
+                    return Err(format!("0x{target:08X} isn't aligned to an instruction"));
+                }
+                field.insert(displacement)
+            }
+            _ => {
+                let value = self.number(operand, 0..=u32::MAX.into())?;
+                if field.extract(field.insert(value)) != value {
+                    return Err(format!("{operand} doesn't fit in {field:?}"));
+                }
+                field.insert(value)
+            }
+        };
+        Ok(bits)
+    }
+
+    /// A 16-bit immediate, either a number within `range` or part of an address picked with `@h`/`@ha`/`@l`.
+    fn half(&self, operand: &str, range: core::ops::RangeInclusive<i64>) -> Result<u32, String> {
+        let halves = [("@ha", 0), ("@h", 1), ("@l", 2)];
+        for (suffix, half) in halves {
+            if let Some(expression) = operand.strip_suffix(suffix) {
+                let value = self.value(expression)? as u32;
+                return Ok(match half {
+                    0 => value.wrapping_add(0x8000) >> 16,
+                    1 => value >> 16,
+                    _ => value & 0xFFFF,
+                });
+            }
+        }
+        self.number(operand, range)
+    }
+
+    /// An immediate within `range`, returned as its raw bits.
+    fn number(&self, operand: &str, range: core::ops::RangeInclusive<i64>) -> Result<u32, String> {
+        let value = self.value(operand)?;
+        match range.contains(&value) {
+            true => Ok(value as u32),
+            false => Err(format!("{operand} is out of range")),
+        }
+    }
+
+    /// A number, or a symbol if it isn't one.
+    fn value(&self, operand: &str) -> Result<i64, String> {
+        let operand = operand.trim();
+        if operand.is_empty() {
+            return Err("missing a value".to_owned());
+        }
+        if let Some(value) = parse_number(operand) {
+            return Ok(value);
+        }
+        (self.symbol)(operand)
+            .map(i64::from)
+            .ok_or_else(|| format!("\"{operand}\" isn't a number or a symbol"))
+    }
+
+    /// Conditional branches written with their simplified mnemonics, e.g. `bne+ cr1, target` or `bdnzlr`.
+    fn branch(&self, mnemonic: &str, operands: &[String]) -> Result<Option<u32>, String> {
+        let Some(mut rest) = mnemonic.strip_prefix('b') else {
+            return Ok(None);
+        };
+        let hint = match rest.chars().last() {
+            Some(hint @ ('+' | '-')) => {
+                rest = &rest[..rest.len() - 1];
+                Some(hint)
+            }
+            _ => None,
+        };
+        let mut suffix = String::new();
+        if let Some(stripped) = rest.strip_suffix('a') {
+            rest = stripped;
+            suffix.insert(0, 'a');
+        }
+        if let Some(stripped) = rest.strip_suffix('l') {
+            rest = stripped;
+            suffix.insert(0, 'l');
+        }
+        let (condition, form) = match (rest.strip_suffix("lr"), rest.strip_suffix("ctr")) {
+            (Some(condition), _) => (condition, "bclr"),
+            (_, Some(condition)) => (condition, "bcctr"),
+            _ => (rest, "bc"),
+        };
+        if form != "bc" && suffix.contains('a') {
+            return Ok(None);
+        }
+
+        let (bo, bit) = match condition {
+            "" if form != "bc" => (0x14, None),
+            "dnz" if form != "bcctr" => (0x10, None),
+            "dz" if form != "bcctr" => (0x12, None),
+            _ => match (
+                CONDITIONS.iter().position(|name| *name == condition),
+                NEGATED.iter().position(|name| *name == condition),
+            ) {
+                (Some(bit), _) => (0x0C, Some(bit as u32)),
+                (_, Some(bit)) => (0x04, Some(bit as u32)),
+                _ if condition == "un" => (0x0C, Some(3)),
+                _ if condition == "nu" => (0x04, Some(3)),
+                _ => return Ok(None),
+            },
+        };
+
+        // The condition register field is optional and defaults to cr0
+        let targets = usize::from(form == "bc");
+        let mut operands = operands.to_vec();
+        let field = match (bit, operands.len() == targets + 1) {
+            (Some(_), true) => condition_field(&operands.remove(0))?,
+            _ => 0,
+        };
+        if operands.len() != targets {
+            return Err(format!(
+                "{mnemonic} takes {} operands, not {}",
+                targets + usize::from(bit.is_some()),
+                operands.len()
+            ));
+        }
+        let bi = bit.map_or(0, |bit| field * 4 + bit);
+        let mut canonical = vec![bo.to_string(), bi.to_string()];
+        canonical.extend(operands);
+        let mut code = self.canonical(&format!("{form}{suffix}"), &canonical)?;
+
+        // The y bit flips the default prediction, which depends on the direction, see `simplified_branch`
+        if let Some(hint) = hint {
+            let backwards = form == "bc" && code & 0x8000 != 0;
+            if (hint == '-') == backwards {
+                code |= Field::BO.insert(1);
+            }
+        }
+        Ok(Some(code))
+    }
+}
+
+/// Finds the form for a canonical mnemonic, along with the suffix bits it asks for.
+fn canonical_form(mnemonic: &str) -> Option<(&'static Form, Suffix)> {
+    SUFFIXES.iter().find_map(|&(ending, suffix)| {
+        let form = find_form(mnemonic.strip_suffix(ending)?)?;
+        let branch = matches!(form.mnemonic, "b" | "bc" | "bclr" | "bcctr");
+        let allowed = match branch {
+            true => ending.chars().all(|c| matches!(c, 'l' | 'a')),
+            false => ending.chars().all(|c| matches!(c, 'o' | '.')),
+        };
+        (allowed && form.suffix.contains(suffix)).then_some((form, suffix))
+    })
+}
+
+/// Rewrites the simplified mnemonics that don't branch into their canonical form. Returns `None` for anything
+/// else, including `mftb` and the compares when they're already written out in full.
+fn expand(mnemonic: &str, operands: &[String]) -> Result<Option<(String, Vec<String>)>, String> {
+    let (base, dot) = match mnemonic.strip_suffix('.') {
+        Some(base) => (base, "."),
+        None => (mnemonic, ""),
+    };
+    let op = |n: usize| operands.get(n).cloned().unwrap_or_default();
+    let count = operands.len();
+    let rewritten = |mnemonic: &str, operands: &[String]| Some((mnemonic.to_owned(), operands.to_vec()));
+    let zero = "0".to_owned();
+
+    Ok(match (base, dot, count) {
+        ("li", "", 2) => rewritten("addi", &[op(0), zero, op(1)]),
+        ("lis", "", 2) => rewritten("addis", &[op(0), zero, op(1)]),
+        ("nop", "", 0) => rewritten("ori", &[zero.clone(), zero.clone(), zero]),
+        ("mr", _, 2) => rewritten(&format!("or{dot}"), &[op(0), op(1), op(1)]),
+        ("not", _, 2) => rewritten(&format!("nor{dot}"), &[op(0), op(1), op(1)]),
+        ("clrlwi" | "rotlwi" | "slwi" | "srwi" | "clrrwi", _, 3) => {
+            let n = parse_number(&op(2))
+                .filter(|n| (0..32).contains(n))
+                .ok_or_else(|| format!("{} isn't a shift amount", op(2)))?;
+            let (sh, mb, me) = match base {
+                "clrlwi" => (0, n, 31),
+                "rotlwi" => (n, 0, 31),
+                "slwi" => (n, 0, 31 - n),
+                "srwi" => ((32 - n) % 32, n, 31),
+                _ => (0, 0, 31 - n),
+            };
+            rewritten(
+                &format!("rlwinm{dot}"),
+                &[op(0), op(1), sh.to_string(), mb.to_string(), me.to_string()],
+            )
+        }
+        ("mfxer" | "mflr" | "mfctr", "", 1) => {
+            rewritten("mfspr", &[op(0), spr_number(&base[2..]).to_string()])
+        }
+        ("mtxer" | "mtlr" | "mtctr", "", 1) => {
+            rewritten("mtspr", &[spr_number(&base[2..]).to_string(), op(0)])
+        }
+        ("mftb", "", 1) => rewritten("mftb", &[op(0), "268".to_owned()]),
+        ("mftbu", "", 1) => rewritten("mftb", &[op(0), "269".to_owned()]),
+        ("mtcr", "", 1) => rewritten("mtcrf", &["0xFF".to_owned(), op(0)]),
+        ("cmpw" | "cmplw" | "cmpwi" | "cmplwi", "", 2) => rewritten(base, &["cr0".to_owned(), op(0), op(1)]),
+        ("crclr", "", 1) => rewritten("crxor", &[op(0), op(0), op(0)]),
+        ("crset", "", 1) => rewritten("creqv", &[op(0), op(0), op(0)]),
+        ("crmove", "", 2) => rewritten("cror", &[op(0), op(1), op(1)]),
+        ("crnot", "", 2) => rewritten("crnor", &[op(0), op(1), op(1)]),
+        ("trap", "", 0) => rewritten("tw", &["31".to_owned(), zero.clone(), zero]),
+        _ => None,
+    })
+}
+
+/// Number of one of the special purpose registers that has its own `mf`/`mt` mnemonic.
+fn spr_number(name: &str) -> u32 {
+    match name {
+        "xer" => 1,
+        "lr" => 8,
+        _ => 9,
+    }
+}
+
+/// A register written with its prefix (`r3`, `f1`) or as a bare number.
+fn register(operand: &str, prefix: &str) -> Result<u32, String> {
+    let lower = operand.to_ascii_lowercase();
+    let value = match (prefix, lower.as_str()) {
+        ("r", "sp") => Some(1),
+        ("r", "rtoc") => Some(2),
+        (_, lower) => lower.strip_prefix(prefix).unwrap_or(lower).parse().ok(),
+    };
+    value.filter(|&value| value < 32).ok_or_else(|| format!("\"{operand}\" isn't a register"))
+}
+
+fn condition_field(operand: &str) -> Result<u32, String> {
+    let lower = operand.to_ascii_lowercase();
+    lower
+        .strip_prefix("cr")
+        .unwrap_or(&lower)
+        .parse()
+        .ok()
+        .or_else(|| parse_number(&lower).and_then(|field| u32::try_from(field).ok()))
+        .filter(|&field| field < 8)
+        .ok_or_else(|| format!("\"{operand}\" isn't a condition register field"))
+}
+
+/// A condition register bit: a number, a condition like `eq`, or a field and condition like `4*cr1+eq`.
+fn condition_bit(operand: &str) -> Result<u32, String> {
+    let lower = operand.to_ascii_lowercase().replace(' ', "");
+    let condition = |name: &str| match name {
+        "un" => Some(3),
+        _ => CONDITIONS.iter().position(|condition| *condition == name).map(|bit| bit as u32),
+    };
+    let bit = match lower.split_once('+') {
+        Some((field, name)) => {
+            let field = field.strip_prefix("4*").unwrap_or(field);
+            condition_field(field).ok().zip(condition(name)).map(|(field, bit)| field * 4 + bit)
+        }
+        None => condition(&lower).or_else(|| parse_number(&lower).and_then(|bit| u32::try_from(bit).ok())),
+    };
+    bit.filter(|&bit| bit < 32).ok_or_else(|| format!("\"{operand}\" isn't a condition register bit"))
+}
+
+/// A special purpose register, by the name the listing uses or by number.
+fn special_register(operand: &str) -> Result<u32, String> {
+    if let Some(value) = parse_number(operand).filter(|value| (0..1024).contains(value)) {
+        return Ok(value as u32);
+    }
+    (0..1024)
+        .find(|&spr| spr_name(spr).is_some_and(|name| name.eq_ignore_ascii_case(operand)))
+        .map(u32::from)
+        .ok_or_else(|| format!("\"{operand}\" isn't a special purpose register"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: u32 = 0x8000_3100;
+
+    fn encode(text: &str) -> Result<u32, FerroxError> {
+        assemble(ADDRESS, text, |name| (name == "target").then_some(0x8000_8000))
+    }
+
+    #[test]
+    fn encodes_instructions() {
+        let cases = [
+            ("addi r3, r3, 0x10", 0x3863_0010),
+            ("addi r3, r3, -0x8000", 0x3863_8000),
+            ("li r3, 0x7FFF", 0x3860_7FFF),
+            ("cmpwi r3, -1", 0x2C03_FFFF),
+            ("lis r3, 0x8000", 0x3C60_8000),
+            ("ori r3, r3, 0xFFFF", 0x6063_FFFF),
+            ("stwu r1, -0x10(r1)", 0x9421_FFF0),
+            ("lwz r0, 0x14(sp)", 0x8001_0014),
+            ("mflr r0", 0x7C08_02A6),
+            ("add. r3, r4, r5", 0x7C64_2A15),
+            ("nop", 0x6000_0000),
+            ("blr", 0x4E80_0020),
+            ("bl 0x80003200", 0x4800_0101),
+            ("b 0x80003000", 0x4BFF_FF00),
+            ("beq 0x80003110", 0x4182_0010),
+            ("bne cr1, 0x80003100", 0x4086_0000),
+            ("psq_l f1, 8(r3), 0, 0", 0xE023_0008),
+            ("psq_l f1, 8(r3), 1, 2", 0xE023_A008),
+            ("ps_add f1, f2, f3", 0x1022_182A),
+            ("lis r3, target@ha", 0x3C60_8001),
+            ("addi r3, r3, target@l", 0x3863_8000),
+            ("lis r3, target@h", 0x3C60_8000),
+        ];
+        for (text, code) in cases {
+            assert_eq!(encode(text).unwrap(), code, "{text}");
+        }
+    }
+
+    #[test]
+    fn decodes_to_what_was_typed() {
+        for text in [
+            "addi r3, r3, 0x10",
+            "stwu r1, -0x10(r1)",
+            "psq_l f1, 8(r3), 1, 2",
+            "ps_add f1, f2, f3",
+        ] {
+            let instruction = Instruction::decode(ADDRESS, encode(text).unwrap()).unwrap();
+            let (mnemonic, _) = instruction.simplified();
+            assert_eq!(mnemonic, text.split(' ').next().unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_what_doesnt_fit() {
+        let cases = [
+            // Signed immediates stop at 0x7FFF, or they'd come back negative
+            ("addi r3, r3, 0x8000", "out of range"),
+            ("li r3, 0xFFFF", "out of range"),
+            ("cmpwi r3, -0x8001", "out of range"),
+            ("ori r3, r3, 0x10000", "out of range"),
+            ("ori r3, r3, -1", "out of range"),
+            ("lwz r3, 0x8000(r4)", "out of range"),
+            ("psq_l f1, 0x800(r3), 0, 0", "out of range"),
+            ("b 0x90000000", "out of range"),
+            ("beq 0x80003102", "aligned"),
+            ("addi r3, r3", "operands"),
+            ("addi r32, r3, 1", "register"),
+            ("lwz r3, 8", "offset"),
+            ("frobnicate r3", "unknown mnemonic"),
+            ("addi r3, r3, missing", "isn't a number or a symbol"),
+        ];
+        for (text, reason) in cases {
+            match encode(text) {
+                Err(FerroxError::InvalidAssembly { reason: error, .. }) => {
+                    assert!(error.contains(reason), "{text}: {error}")
+                }
+                other => panic!("{text}: expected an error, got {other:?}"),
+            }
+        }
+    }
+}
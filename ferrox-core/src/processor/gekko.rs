@@ -0,0 +1,990 @@
+//! Table-driven decoder for the IBM Gekko (GameCube) and Broadway (Wii) PowerPC cores.
+//!
+//! Every instruction is described by a [`Form`], which lists the operand [`Field`]s in display order. The
+//! mask used for matching is derived from those fields, so any bits not covered by a field (or by an
+//! `Rc`/`OE`/`LK`/`AA` suffix) have to match exactly. This keeps decoding strict enough that reserved bits
+//! aren't silently dropped, which matters once we start writing assembly back out.
+use core::fmt;
+use core::ops::Deref;
+use std::sync::OnceLock;
+
+use bitflags::bitflags;
+
+bitflags! {
+    /// Optional bits that modify the mnemonic instead of being shown as an operand.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    pub struct Suffix: u32 {
+        /// Record bit, updates cr0/cr1 (`.`)
+        const RC = 1 << 0;
+        /// Overflow enable, updates XER[SO, OV] (`o`)
+        const OE = 1 << 10;
+        /// Link bit, updates LR (`l`)
+        const LK = 1 << 0;
+        /// Absolute address (`a`)
+        const AA = 1 << 1;
+    }
+}
+
+/// A single operand field inside of an instruction word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Field {
+    RD,
+    RS,
+    RA,
+    RB,
+    FRD,
+    FRS,
+    FRA,
+    FRB,
+    FRC,
+    CRFD,
+    CRFS,
+    CRBD,
+    CRBA,
+    CRBB,
+    SIMM,
+    UIMM,
+    /// `d(rA)`, a signed 16-bit displacement from a base register
+    D,
+    SH,
+    MB,
+    ME,
+    NB,
+    TO,
+    SPR,
+    TBR,
+    SR,
+    CRM,
+    FM,
+    BO,
+    BI,
+    BD,
+    LI,
+    /// mtfsfi immediate
+    IMM,
+    /// `d(rA)` for psq_l/psq_st, a signed 12-bit displacement
+    PsD,
+    /// psq_l/psq_st W bit
+    PsW,
+    /// psq_l/psq_st GQR index
+    PsI,
+    /// psq_lx/psq_stx W bit
+    PsWX,
+    /// psq_lx/psq_stx GQR index
+    PsIX,
+}
+
+impl Field {
+    /// Bits of the instruction word this field occupies.
+    pub const fn mask(self) -> u32 {
+        match self {
+            Self::RD | Self::RS | Self::FRD | Self::FRS | Self::CRBD | Self::TO | Self::BO => 0x03E00000,
+            Self::RA | Self::FRA | Self::CRBA | Self::BI => 0x001F0000,
+            Self::RB | Self::FRB | Self::CRBB | Self::SH | Self::NB => 0x0000F800,
+            Self::FRC | Self::MB => 0x000007C0,
+            Self::ME => 0x0000003E,
+            Self::CRFD => 0x03800000,
+            Self::CRFS => 0x001C0000,
+            Self::SIMM | Self::UIMM => 0x0000FFFF,
+            Self::D => 0x001FFFFF,
+            Self::SPR | Self::TBR => 0x001FF800,
+            Self::SR => 0x000F0000,
+            Self::CRM => 0x000FF000,
+            Self::FM => 0x01FE0000,
+            Self::BD => 0x0000FFFC,
+            Self::LI => 0x03FFFFFC,
+            Self::IMM => 0x0000F000,
+            Self::PsD => 0x001F0FFF,
+            Self::PsW => 0x00008000,
+            Self::PsI => 0x00007000,
+            Self::PsWX => 0x00000400,
+            Self::PsIX => 0x00000380,
+        }
+    }
+
+    /// Raw (unsigned, unshifted into place) value of this field.
+    pub const fn extract(self, code: u32) -> u32 {
+        match self {
+            Self::RD | Self::RS | Self::FRD | Self::FRS | Self::CRBD | Self::TO | Self::BO => {
+                (code >> 21) & 0x1F
+            }
+            Self::RA | Self::FRA | Self::CRBA | Self::BI => (code >> 16) & 0x1F,
+            Self::RB | Self::FRB | Self::CRBB | Self::SH | Self::NB => (code >> 11) & 0x1F,
+            Self::FRC | Self::MB => (code >> 6) & 0x1F,
+            Self::ME => (code >> 1) & 0x1F,
+            Self::CRFD => (code >> 23) & 0x7,
+            Self::CRFS => (code >> 18) & 0x7,
+            Self::SIMM | Self::UIMM => code & 0xFFFF,
+            Self::D => code & 0xFFFF,
+            // The SPR number is stored with its two halves swapped
+            Self::SPR | Self::TBR => ((code >> 16) & 0x1F) | (((code >> 11) & 0x1F) << 5),
+            Self::SR => (code >> 16) & 0xF,
+            Self::CRM => (code >> 12) & 0xFF,
+            Self::FM => (code >> 17) & 0xFF,
+            Self::BD => code & 0xFFFC,
+            Self::LI => code & 0x03FFFFFC,
+            Self::IMM => (code >> 12) & 0xF,
+            Self::PsD => code & 0xFFF,
+            Self::PsW => (code >> 15) & 0x1,
+            Self::PsI => (code >> 12) & 0x7,
+            Self::PsWX => (code >> 10) & 0x1,
+            Self::PsIX => (code >> 7) & 0x7,
+        }
+    }
+
+    /// Places a raw value into this field, the inverse of [`Field::extract`]. Values are truncated to the
+    /// width of the field.
+    pub const fn insert(self, value: u32) -> u32 {
+        match self {
+            Self::RD | Self::RS | Self::FRD | Self::FRS | Self::CRBD | Self::TO | Self::BO => {
+                (value & 0x1F) << 21
+            }
+            Self::RA | Self::FRA | Self::CRBA | Self::BI => (value & 0x1F) << 16,
+            Self::RB | Self::FRB | Self::CRBB | Self::SH | Self::NB => (value & 0x1F) << 11,
+            Self::FRC | Self::MB => (value & 0x1F) << 6,
+            Self::ME => (value & 0x1F) << 1,
+            Self::CRFD => (value & 0x7) << 23,
+            Self::CRFS => (value & 0x7) << 18,
+            Self::SIMM | Self::UIMM | Self::D => value & 0xFFFF,
+            Self::SPR | Self::TBR => ((value & 0x1F) << 16) | (((value >> 5) & 0x1F) << 11),
+            Self::SR => (value & 0xF) << 16,
+            Self::CRM => (value & 0xFF) << 12,
+            Self::FM => (value & 0xFF) << 17,
+            Self::BD => value & 0xFFFC,
+            Self::LI => value & 0x03FFFFFC,
+            Self::IMM => (value & 0xF) << 12,
+            Self::PsD => value & 0xFFF,
+            Self::PsW => (value & 0x1) << 15,
+            Self::PsI => (value & 0x7) << 12,
+            Self::PsWX => (value & 0x1) << 10,
+            Self::PsIX => (value & 0x7) << 7,
+        }
+    }
+}
+
+/// Description of a single instruction encoding.
+#[derive(Debug)]
+pub struct Form {
+    pub mnemonic: &'static str,
+    /// Fixed opcode bits
+    pub bits: u32,
+    /// Bits that have to match [`Form::bits`]
+    pub mask: u32,
+    /// Operand fields, in the order they're displayed
+    pub fields: &'static [Field],
+    /// Which suffix bits are allowed to vary
+    pub suffix: Suffix,
+}
+
+const fn form(mnemonic: &'static str, bits: u32, fields: &'static [Field], suffix: Suffix) -> Form {
+    let mut variable = suffix.bits();
+    let mut n = 0;
+    while n < fields.len() {
+        variable |= fields[n].mask();
+        n += 1;
+    }
+    Form { mnemonic, bits, mask: !variable, fields, suffix }
+}
+
+/// Primary opcode only (D, I and B-forms)
+const fn op(primary: u32) -> u32 {
+    primary << 26
+}
+
+/// Primary and extended opcode (X, XO, XL, A and XFX-forms all store it starting at bit 30)
+const fn xop(primary: u32, extended: u32) -> u32 {
+    (primary << 26) | (extended << 1)
+}
+
+use Field::*;
+
+const NONE: Suffix = Suffix::empty();
+const RC: Suffix = Suffix::RC;
+const OE_RC: Suffix = Suffix::OE.union(Suffix::RC);
+const LK: Suffix = Suffix::LK;
+const AA_LK: Suffix = Suffix::AA.union(Suffix::LK);
+
+#[rustfmt::skip]
+static FORMS: &[Form] = &[
+    // Primary opcodes
+    form("twi", op(3), &[TO, RA, SIMM], NONE),
+    form("mulli", op(7), &[RD, RA, SIMM], NONE),
+    form("subfic", op(8), &[RD, RA, SIMM], NONE),
+    form("cmplwi", op(10), &[CRFD, RA, UIMM], NONE),
+    form("cmpwi", op(11), &[CRFD, RA, SIMM], NONE),
+    form("addic", op(12), &[RD, RA, SIMM], NONE),
+    form("addic.", op(13), &[RD, RA, SIMM], NONE),
+    form("addi", op(14), &[RD, RA, SIMM], NONE),
+    form("addis", op(15), &[RD, RA, UIMM], NONE),
+    form("bc", op(16), &[BO, BI, BD], AA_LK),
+    form("sc", op(17) | 2, &[], NONE),
+    form("b", op(18), &[LI], AA_LK),
+    form("rlwimi", op(20), &[RA, RS, SH, MB, ME], RC),
+    form("rlwinm", op(21), &[RA, RS, SH, MB, ME], RC),
+    form("rlwnm", op(23), &[RA, RS, RB, MB, ME], RC),
+    form("ori", op(24), &[RA, RS, UIMM], NONE),
+    form("oris", op(25), &[RA, RS, UIMM], NONE),
+    form("xori", op(26), &[RA, RS, UIMM], NONE),
+    form("xoris", op(27), &[RA, RS, UIMM], NONE),
+    form("andi.", op(28), &[RA, RS, UIMM], NONE),
+    form("andis.", op(29), &[RA, RS, UIMM], NONE),
+    form("lwz", op(32), &[RD, D], NONE),
+    form("lwzu", op(33), &[RD, D], NONE),
+    form("lbz", op(34), &[RD, D], NONE),
+    form("lbzu", op(35), &[RD, D], NONE),
+    form("stw", op(36), &[RS, D], NONE),
+    form("stwu", op(37), &[RS, D], NONE),
+    form("stb", op(38), &[RS, D], NONE),
+    form("stbu", op(39), &[RS, D], NONE),
+    form("lhz", op(40), &[RD, D], NONE),
+    form("lhzu", op(41), &[RD, D], NONE),
+    form("lha", op(42), &[RD, D], NONE),
+    form("lhau", op(43), &[RD, D], NONE),
+    form("sth", op(44), &[RS, D], NONE),
+    form("sthu", op(45), &[RS, D], NONE),
+    form("lmw", op(46), &[RD, D], NONE),
+    form("stmw", op(47), &[RS, D], NONE),
+    form("lfs", op(48), &[FRD, D], NONE),
+    form("lfsu", op(49), &[FRD, D], NONE),
+    form("lfd", op(50), &[FRD, D], NONE),
+    form("lfdu", op(51), &[FRD, D], NONE),
+    form("stfs", op(52), &[FRS, D], NONE),
+    form("stfsu", op(53), &[FRS, D], NONE),
+    form("stfd", op(54), &[FRS, D], NONE),
+    form("stfdu", op(55), &[FRS, D], NONE),
+    form("psq_l", op(56), &[FRD, PsD, PsW, PsI], NONE),
+    form("psq_lu", op(57), &[FRD, PsD, PsW, PsI], NONE),
+    form("psq_st", op(60), &[FRS, PsD, PsW, PsI], NONE),
+    form("psq_stu", op(61), &[FRS, PsD, PsW, PsI], NONE),
+
+    // Opcode 4: Paired singles
+    form("ps_cmpu0", xop(4, 0), &[CRFD, FRA, FRB], NONE),
+    form("ps_cmpo0", xop(4, 32), &[CRFD, FRA, FRB], NONE),
+    form("ps_cmpu1", xop(4, 64), &[CRFD, FRA, FRB], NONE),
+    form("ps_cmpo1", xop(4, 96), &[CRFD, FRA, FRB], NONE),
+    form("psq_lx", xop(4, 6), &[FRD, RA, RB, PsWX, PsIX], NONE),
+    form("psq_stx", xop(4, 7), &[FRS, RA, RB, PsWX, PsIX], NONE),
+    form("psq_lux", xop(4, 38), &[FRD, RA, RB, PsWX, PsIX], NONE),
+    form("psq_stux", xop(4, 39), &[FRS, RA, RB, PsWX, PsIX], NONE),
+    form("ps_sum0", xop(4, 10), &[FRD, FRA, FRC, FRB], RC),
+    form("ps_sum1", xop(4, 11), &[FRD, FRA, FRC, FRB], RC),
+    form("ps_muls0", xop(4, 12), &[FRD, FRA, FRC], RC),
+    form("ps_muls1", xop(4, 13), &[FRD, FRA, FRC], RC),
+    form("ps_madds0", xop(4, 14), &[FRD, FRA, FRC, FRB], RC),
+    form("ps_madds1", xop(4, 15), &[FRD, FRA, FRC, FRB], RC),
+    form("ps_div", xop(4, 18), &[FRD, FRA, FRB], RC),
+    form("ps_sub", xop(4, 20), &[FRD, FRA, FRB], RC),
+    form("ps_add", xop(4, 21), &[FRD, FRA, FRB], RC),
+    form("ps_sel", xop(4, 23), &[FRD, FRA, FRC, FRB], RC),
+    form("ps_res", xop(4, 24), &[FRD, FRB], RC),
+    form("ps_mul", xop(4, 25), &[FRD, FRA, FRC], RC),
+    form("ps_rsqrte", xop(4, 26), &[FRD, FRB], RC),
+    form("ps_msub", xop(4, 28), &[FRD, FRA, FRC, FRB], RC),
+    form("ps_madd", xop(4, 29), &[FRD, FRA, FRC, FRB], RC),
+    form("ps_nmsub", xop(4, 30), &[FRD, FRA, FRC, FRB], RC),
+    form("ps_nmadd", xop(4, 31), &[FRD, FRA, FRC, FRB], RC),
+    form("ps_neg", xop(4, 40), &[FRD, FRB], RC),
+    form("ps_mr", xop(4, 72), &[FRD, FRB], RC),
+    form("ps_nabs", xop(4, 136), &[FRD, FRB], RC),
+    form("ps_abs", xop(4, 264), &[FRD, FRB], RC),
+    form("ps_merge00", xop(4, 528), &[FRD, FRA, FRB], RC),
+    form("ps_merge01", xop(4, 560), &[FRD, FRA, FRB], RC),
+    form("ps_merge10", xop(4, 592), &[FRD, FRA, FRB], RC),
+    form("ps_merge11", xop(4, 624), &[FRD, FRA, FRB], RC),
+    form("dcbz_l", xop(4, 1014), &[RA, RB], NONE),
+
+    // Opcode 19: Branch and condition register logic
+    form("mcrf", xop(19, 0), &[CRFD, CRFS], NONE),
+    form("bclr", xop(19, 16), &[BO, BI], LK),
+    form("crnor", xop(19, 33), &[CRBD, CRBA, CRBB], NONE),
+    form("rfi", xop(19, 50), &[], NONE),
+    form("crandc", xop(19, 129), &[CRBD, CRBA, CRBB], NONE),
+    form("isync", xop(19, 150), &[], NONE),
+    form("crxor", xop(19, 193), &[CRBD, CRBA, CRBB], NONE),
+    form("crnand", xop(19, 225), &[CRBD, CRBA, CRBB], NONE),
+    form("crand", xop(19, 257), &[CRBD, CRBA, CRBB], NONE),
+    form("creqv", xop(19, 289), &[CRBD, CRBA, CRBB], NONE),
+    form("crorc", xop(19, 417), &[CRBD, CRBA, CRBB], NONE),
+    form("cror", xop(19, 449), &[CRBD, CRBA, CRBB], NONE),
+    form("bcctr", xop(19, 528), &[BO, BI], LK),
+
+    // Opcode 31: Integer arithmetic, logic, indexed memory access and special registers
+    form("cmpw", xop(31, 0), &[CRFD, RA, RB], NONE),
+    form("tw", xop(31, 4), &[TO, RA, RB], NONE),
+    form("subfc", xop(31, 8), &[RD, RA, RB], OE_RC),
+    form("addc", xop(31, 10), &[RD, RA, RB], OE_RC),
+    form("mulhwu", xop(31, 11), &[RD, RA, RB], RC),
+    form("mfcr", xop(31, 19), &[RD], NONE),
+    form("lwarx", xop(31, 20), &[RD, RA, RB], NONE),
+    form("lwzx", xop(31, 23), &[RD, RA, RB], NONE),
+    form("slw", xop(31, 24), &[RA, RS, RB], RC),
+    form("cntlzw", xop(31, 26), &[RA, RS], RC),
+    form("and", xop(31, 28), &[RA, RS, RB], RC),
+    form("cmplw", xop(31, 32), &[CRFD, RA, RB], NONE),
+    form("subf", xop(31, 40), &[RD, RA, RB], OE_RC),
+    form("dcbst", xop(31, 54), &[RA, RB], NONE),
+    form("lwzux", xop(31, 55), &[RD, RA, RB], NONE),
+    form("andc", xop(31, 60), &[RA, RS, RB], RC),
+    form("mulhw", xop(31, 75), &[RD, RA, RB], RC),
+    form("mfmsr", xop(31, 83), &[RD], NONE),
+    form("dcbf", xop(31, 86), &[RA, RB], NONE),
+    form("lbzx", xop(31, 87), &[RD, RA, RB], NONE),
+    form("neg", xop(31, 104), &[RD, RA], OE_RC),
+    form("lbzux", xop(31, 119), &[RD, RA, RB], NONE),
+    form("nor", xop(31, 124), &[RA, RS, RB], RC),
+    form("subfe", xop(31, 136), &[RD, RA, RB], OE_RC),
+    form("adde", xop(31, 138), &[RD, RA, RB], OE_RC),
+    form("mtcrf", xop(31, 144), &[CRM, RS], NONE),
+    form("mtmsr", xop(31, 146), &[RS], NONE),
+    form("stwcx.", xop(31, 150) | 1, &[RS, RA, RB], NONE),
+    form("stwx", xop(31, 151), &[RS, RA, RB], NONE),
+    form("stwux", xop(31, 183), &[RS, RA, RB], NONE),
+    form("subfze", xop(31, 200), &[RD, RA], OE_RC),
+    form("addze", xop(31, 202), &[RD, RA], OE_RC),
+    form("mtsr", xop(31, 210), &[SR, RS], NONE),
+    form("stbx", xop(31, 215), &[RS, RA, RB], NONE),
+    form("subfme", xop(31, 232), &[RD, RA], OE_RC),
+    form("addme", xop(31, 234), &[RD, RA], OE_RC),
+    form("mullw", xop(31, 235), &[RD, RA, RB], OE_RC),
+    form("mtsrin", xop(31, 242), &[RS, RB], NONE),
+    form("dcbtst", xop(31, 246), &[RA, RB], NONE),
+    form("stbux", xop(31, 247), &[RS, RA, RB], NONE),
+    form("add", xop(31, 266), &[RD, RA, RB], OE_RC),
+    form("dcbt", xop(31, 278), &[RA, RB], NONE),
+    form("lhzx", xop(31, 279), &[RD, RA, RB], NONE),
+    form("eqv", xop(31, 284), &[RA, RS, RB], RC),
+    form("tlbie", xop(31, 306), &[RB], NONE),
+    form("eciwx", xop(31, 310), &[RD, RA, RB], NONE),
+    form("lhzux", xop(31, 311), &[RD, RA, RB], NONE),
+    form("xor", xop(31, 316), &[RA, RS, RB], RC),
+    form("mfspr", xop(31, 339), &[RD, SPR], NONE),
+    form("lhax", xop(31, 343), &[RD, RA, RB], NONE),
+    form("mftb", xop(31, 371), &[RD, TBR], NONE),
+    form("lhaux", xop(31, 375), &[RD, RA, RB], NONE),
+    form("sthx", xop(31, 407), &[RS, RA, RB], NONE),
+    form("orc", xop(31, 412), &[RA, RS, RB], RC),
+    form("ecowx", xop(31, 438), &[RS, RA, RB], NONE),
+    form("sthux", xop(31, 439), &[RS, RA, RB], NONE),
+    form("or", xop(31, 444), &[RA, RS, RB], RC),
+    form("divwu", xop(31, 459), &[RD, RA, RB], OE_RC),
+    form("mtspr", xop(31, 467), &[SPR, RS], NONE),
+    form("dcbi", xop(31, 470), &[RA, RB], NONE),
+    form("nand", xop(31, 476), &[RA, RS, RB], RC),
+    form("divw", xop(31, 491), &[RD, RA, RB], OE_RC),
+    form("mcrxr", xop(31, 512), &[CRFD], NONE),
+    form("lswx", xop(31, 533), &[RD, RA, RB], NONE),
+    form("lwbrx", xop(31, 534), &[RD, RA, RB], NONE),
+    form("lfsx", xop(31, 535), &[FRD, RA, RB], NONE),
+    form("srw", xop(31, 536), &[RA, RS, RB], RC),
+    form("tlbsync", xop(31, 566), &[], NONE),
+    form("lfsux", xop(31, 567), &[FRD, RA, RB], NONE),
+    form("mfsr", xop(31, 595), &[RD, SR], NONE),
+    form("lswi", xop(31, 597), &[RD, RA, NB], NONE),
+    form("sync", xop(31, 598), &[], NONE),
+    form("lfdx", xop(31, 599), &[FRD, RA, RB], NONE),
+    form("lfdux", xop(31, 631), &[FRD, RA, RB], NONE),
+    form("mfsrin", xop(31, 659), &[RD, RB], NONE),
+    form("stswx", xop(31, 661), &[RS, RA, RB], NONE),
+    form("stwbrx", xop(31, 662), &[RS, RA, RB], NONE),
+    form("stfsx", xop(31, 663), &[FRS, RA, RB], NONE),
+    form("stfsux", xop(31, 695), &[FRS, RA, RB], NONE),
+    form("stswi", xop(31, 725), &[RS, RA, NB], NONE),
+    form("stfdx", xop(31, 727), &[FRS, RA, RB], NONE),
+    form("stfdux", xop(31, 759), &[FRS, RA, RB], NONE),
+    form("lhbrx", xop(31, 790), &[RD, RA, RB], NONE),
+    form("sraw", xop(31, 792), &[RA, RS, RB], RC),
+    form("srawi", xop(31, 824), &[RA, RS, SH], RC),
+    form("eieio", xop(31, 854), &[], NONE),
+    form("sthbrx", xop(31, 918), &[RS, RA, RB], NONE),
+    form("extsh", xop(31, 922), &[RA, RS], RC),
+    form("extsb", xop(31, 954), &[RA, RS], RC),
+    form("icbi", xop(31, 982), &[RA, RB], NONE),
+    form("stfiwx", xop(31, 983), &[FRS, RA, RB], NONE),
+    form("dcbz", xop(31, 1014), &[RA, RB], NONE),
+
+    // Opcode 59: Single-precision floating point
+    form("fdivs", xop(59, 18), &[FRD, FRA, FRB], RC),
+    form("fsubs", xop(59, 20), &[FRD, FRA, FRB], RC),
+    form("fadds", xop(59, 21), &[FRD, FRA, FRB], RC),
+    form("fres", xop(59, 24), &[FRD, FRB], RC),
+    form("fmuls", xop(59, 25), &[FRD, FRA, FRC], RC),
+    form("fmsubs", xop(59, 28), &[FRD, FRA, FRC, FRB], RC),
+    form("fmadds", xop(59, 29), &[FRD, FRA, FRC, FRB], RC),
+    form("fnmsubs", xop(59, 30), &[FRD, FRA, FRC, FRB], RC),
+    form("fnmadds", xop(59, 31), &[FRD, FRA, FRC, FRB], RC),
+
+    // Opcode 63: Double-precision floating point and FPSCR
+    form("fcmpu", xop(63, 0), &[CRFD, FRA, FRB], NONE),
+    form("frsp", xop(63, 12), &[FRD, FRB], RC),
+    form("fctiw", xop(63, 14), &[FRD, FRB], RC),
+    form("fctiwz", xop(63, 15), &[FRD, FRB], RC),
+    form("fdiv", xop(63, 18), &[FRD, FRA, FRB], RC),
+    form("fsub", xop(63, 20), &[FRD, FRA, FRB], RC),
+    form("fadd", xop(63, 21), &[FRD, FRA, FRB], RC),
+    form("fsel", xop(63, 23), &[FRD, FRA, FRC, FRB], RC),
+    form("fmul", xop(63, 25), &[FRD, FRA, FRC], RC),
+    form("frsqrte", xop(63, 26), &[FRD, FRB], RC),
+    form("fmsub", xop(63, 28), &[FRD, FRA, FRC, FRB], RC),
+    form("fmadd", xop(63, 29), &[FRD, FRA, FRC, FRB], RC),
+    form("fnmsub", xop(63, 30), &[FRD, FRA, FRC, FRB], RC),
+    form("fnmadd", xop(63, 31), &[FRD, FRA, FRC, FRB], RC),
+    form("fcmpo", xop(63, 32), &[CRFD, FRA, FRB], NONE),
+    form("mtfsb1", xop(63, 38), &[CRBD], RC),
+    form("fneg", xop(63, 40), &[FRD, FRB], RC),
+    form("mcrfs", xop(63, 64), &[CRFD, CRFS], NONE),
+    form("mtfsb0", xop(63, 70), &[CRBD], RC),
+    form("fmr", xop(63, 72), &[FRD, FRB], RC),
+    form("mtfsfi", xop(63, 134), &[CRFD, IMM], RC),
+    form("fnabs", xop(63, 136), &[FRD, FRB], RC),
+    form("fabs", xop(63, 264), &[FRD, FRB], RC),
+    form("mffs", xop(63, 583), &[FRD], RC),
+    form("mtfsf", xop(63, 711), &[FM, FRB], RC),
+];
+
+/// Forms grouped by primary opcode, so decoding doesn't have to walk the whole table.
+fn forms_by_primary() -> &'static [Vec<&'static Form>; 64] {
+    static LOOKUP: OnceLock<[Vec<&'static Form>; 64]> = OnceLock::new();
+    LOOKUP.get_or_init(|| {
+        let mut lookup: [Vec<&'static Form>; 64] = core::array::from_fn(|_| Vec::new());
+        for form in FORMS {
+            lookup[(form.bits >> 26) as usize].push(form);
+        }
+        lookup
+    })
+}
+
+/// Looks up an instruction form by its base mnemonic (without any suffixes).
+pub fn find_form(mnemonic: &str) -> Option<&'static Form> {
+    FORMS.iter().find(|form| form.mnemonic == mnemonic)
+}
+
+/// A single decoded operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Gpr(u8),
+    Fpr(u8),
+    /// Condition register field
+    Cr(u8),
+    /// Single condition register bit
+    CrBit(u8),
+    Spr(u16),
+    Sr(u8),
+    Simm(i32),
+    Uimm(u32),
+    /// `disp(base)`
+    Offset {
+        disp: i32,
+        base: u8,
+    },
+    /// Resolved branch destination
+    Target(u32),
+}
+
+impl Default for Operand {
+    fn default() -> Self {
+        Self::Uimm(0)
+    }
+}
+
+/// Formats an immediate the way the listing shows it: small values in decimal, everything else in hex.
+pub fn format_immediate(value: i64) -> String {
+    match value {
+        -9..=9 => format!("{value}"),
+        value if value < 0 => format!("-0x{:X}", value.unsigned_abs()),
+        value => format!("0x{value:X}"),
+    }
+}
+
+/// Friendly names for the special purpose registers that show up in practice.
+pub fn spr_name(spr: u16) -> Option<&'static str> {
+    Some(match spr {
+        1 => "XER",
+        8 => "LR",
+        9 => "CTR",
+        18 => "DSISR",
+        19 => "DAR",
+        22 => "DEC",
+        25 => "SDR1",
+        26 => "SRR0",
+        27 => "SRR1",
+        272 => "SPRG0",
+        273 => "SPRG1",
+        274 => "SPRG2",
+        275 => "SPRG3",
+        282 => "EAR",
+        284 => "TBL",
+        285 => "TBU",
+        287 => "PVR",
+        528 => "IBAT0U",
+        529 => "IBAT0L",
+        530 => "IBAT1U",
+        531 => "IBAT1L",
+        532 => "IBAT2U",
+        533 => "IBAT2L",
+        534 => "IBAT3U",
+        535 => "IBAT3L",
+        536 => "DBAT0U",
+        537 => "DBAT0L",
+        538 => "DBAT1U",
+        539 => "DBAT1L",
+        540 => "DBAT2U",
+        541 => "DBAT2L",
+        542 => "DBAT3U",
+        543 => "DBAT3L",
+        912 => "GQR0",
+        913 => "GQR1",
+        914 => "GQR2",
+        915 => "GQR3",
+        916 => "GQR4",
+        917 => "GQR5",
+        918 => "GQR6",
+        919 => "GQR7",
+        920 => "HID2",
+        921 => "WPAR",
+        922 => "DMA_U",
+        923 => "DMA_L",
+        936 => "UMMCR0",
+        937 => "UPMC1",
+        938 => "UPMC2",
+        939 => "USIA",
+        940 => "UMMCR1",
+        941 => "UPMC3",
+        942 => "UPMC4",
+        952 => "MMCR0",
+        953 => "PMC1",
+        954 => "PMC2",
+        955 => "SIA",
+        956 => "MMCR1",
+        957 => "PMC3",
+        958 => "PMC4",
+        1008 => "HID0",
+        1009 => "HID1",
+        1010 => "IABR",
+        1013 => "DABR",
+        1017 => "L2CR",
+        1019 => "ICTC",
+        1020 => "THRM1",
+        1021 => "THRM2",
+        1022 => "THRM3",
+        _ => return None,
+    })
+}
+
+const CONDITIONS: [&str; 4] = ["lt", "gt", "eq", "so"];
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Gpr(n) => write!(f, "r{n}"),
+            Self::Fpr(n) => write!(f, "f{n}"),
+            Self::Cr(n) => write!(f, "cr{n}"),
+            Self::CrBit(n) if n < 4 => f.write_str(CONDITIONS[n as usize]),
+            Self::CrBit(n) => write!(f, "4*cr{}+{}", n / 4, CONDITIONS[(n % 4) as usize]),
+            Self::Spr(n) => match spr_name(n) {
+                Some(name) => f.write_str(name),
+                None => write!(f, "{n}"),
+            },
+            Self::Sr(n) => write!(f, "{n}"),
+            Self::Simm(value) => f.write_str(&format_immediate(value.into())),
+            Self::Uimm(value) => f.write_str(&format_immediate(value.into())),
+            Self::Offset { disp, base } => write!(f, "{}(r{base})", format_immediate(disp.into())),
+            Self::Target(address) => write!(f, "0x{address:08X}"),
+        }
+    }
+}
+
+/// Fixed-capacity operand list, no instruction has more than five.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Operands {
+    items: [Operand; 5],
+    len: usize,
+}
+
+impl Operands {
+    pub fn push(&mut self, operand: Operand) {
+        self.items[self.len] = operand;
+        self.len += 1;
+    }
+
+    fn from_slice(operands: &[Operand]) -> Self {
+        let mut result = Self::default();
+        for &operand in operands {
+            result.push(operand);
+        }
+        result
+    }
+}
+
+impl Deref for Operands {
+    type Target = [Operand];
+
+    fn deref(&self) -> &Self::Target {
+        &self.items[..self.len]
+    }
+}
+
+/// How an instruction affects control flow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Falls through to the next instruction
+    Normal,
+    /// Direct branch to a known address
+    Branch {
+        target: u32,
+        conditional: bool,
+        link: bool,
+    },
+    /// Branch through CTR
+    Indirect { conditional: bool, link: bool },
+    /// Branch through LR (or `rfi`)
+    Return { conditional: bool },
+}
+
+/// A decoded instruction in its canonical (non-simplified) form.
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction {
+    pub address: u32,
+    pub code: u32,
+    pub form: &'static Form,
+    pub operands: Operands,
+}
+
+impl Instruction {
+    /// Decodes a single instruction word, returns `None` if it isn't a valid Gekko instruction.
+    pub fn decode(address: u32, code: u32) -> Option<Self> {
+        let form =
+            forms_by_primary()[(code >> 26) as usize].iter().find(|form| code & form.mask == form.bits)?;
+
+        let mut operands = Operands::default();
+        for &field in form.fields {
+            let raw = field.extract(code);
+            operands.push(match field {
+                RD | RS | RA | RB => Operand::Gpr(raw as u8),
+                FRD | FRS | FRA | FRB | FRC => Operand::Fpr(raw as u8),
+                CRFD | CRFS => Operand::Cr(raw as u8),
+                CRBD | CRBA | CRBB => Operand::CrBit(raw as u8),
+                SPR | TBR => Operand::Spr(raw as u16),
+                SR => Operand::Sr(raw as u8),
+                SIMM => Operand::Simm(raw as u16 as i16 as i32),
+                D => Operand::Offset { disp: raw as u16 as i16 as i32, base: RA.extract(code) as u8 },
+                PsD => Operand::Offset { disp: ((raw << 20) as i32) >> 20, base: RA.extract(code) as u8 },
+                BD => {
+                    let disp = raw as u16 as i16 as i32;
+                    match code & Suffix::AA.bits() != 0 {
+                        true => Operand::Target(disp as u32),
+                        false => Operand::Target(address.wrapping_add_signed(disp)),
+                    }
+                }
+                LI => {
+                    let disp = ((raw << 6) as i32) >> 6;
+                    match code & Suffix::AA.bits() != 0 {
+                        true => Operand::Target(disp as u32),
+                        false => Operand::Target(address.wrapping_add_signed(disp)),
+                    }
+                }
+                _ => Operand::Uimm(raw),
+            });
+        }
+
+        Some(Self { address, code, form, operands })
+    }
+
+    /// Raw value of a field in this instruction.
+    pub fn field(&self, field: Field) -> u32 {
+        field.extract(self.code)
+    }
+
+    pub fn has_suffix(&self, suffix: Suffix) -> bool {
+        self.form.suffix.contains(suffix) && self.code & suffix.bits() == suffix.bits()
+    }
+
+    /// Base mnemonic with all suffixes applied, e.g. `addo.` or `bla`.
+    pub fn mnemonic(&self) -> String {
+        let mut mnemonic = self.form.mnemonic.to_owned();
+        if self.link() {
+            mnemonic.push('l');
+        }
+        if self.has_suffix(Suffix::AA) {
+            mnemonic.push('a');
+        }
+        if self.has_suffix(Suffix::OE) {
+            mnemonic.push('o');
+        }
+        if self.record() {
+            mnemonic.push('.');
+        }
+        mnemonic
+    }
+
+    /// Whether this is one of the branch forms, which use bit 31 as LK instead of Rc.
+    pub fn is_branch(&self) -> bool {
+        matches!(self.form.mnemonic, "b" | "bc" | "bclr" | "bcctr")
+    }
+
+    /// Whether the record bit is set (the `.` suffix).
+    pub fn record(&self) -> bool {
+        !self.is_branch() && self.has_suffix(Suffix::RC)
+    }
+
+    /// Whether the link bit is set on a branch.
+    pub fn link(&self) -> bool {
+        self.is_branch() && self.has_suffix(Suffix::LK)
+    }
+
+    /// Returns the simplified mnemonic and operands, as they'd be written by hand (`li`, `mr`, `blr`, ...).
+    pub fn simplified(&self) -> (String, Operands) {
+        let ops = &self.operands;
+        let dot = if self.record() { "." } else { "" };
+        let simple =
+            |mnemonic: &str, operands: &[Operand]| (mnemonic.to_owned(), Operands::from_slice(operands));
+
+        match self.form.mnemonic {
+            "addi" if self.field(RA) == 0 => return simple("li", &[ops[0], ops[2]]),
+            "addis" if self.field(RA) == 0 => return simple("lis", &[ops[0], ops[2]]),
+            "ori" if self.code == 0x60000000 => return simple("nop", &[]),
+            "or" if self.field(RS) == self.field(RB) => return simple(&format!("mr{dot}"), &ops[..2]),
+            "nor" if self.field(RS) == self.field(RB) => return simple(&format!("not{dot}"), &ops[..2]),
+            "rlwinm" => {
+                let (sh, mb, me) = (self.field(SH), self.field(MB), self.field(ME));
+                let rotate = |name: &str, value: u32| {
+                    simple(&format!("{name}{dot}"), &[ops[0], ops[1], Operand::Uimm(value)])
+                };
+                if sh == 0 && me == 31 {
+                    return rotate("clrlwi", mb);
+                } else if mb == 0 && me == 31 {
+                    return rotate("rotlwi", sh);
+                } else if mb == 0 && me == 31 - sh {
+                    return rotate("slwi", sh);
+                } else if me == 31 && sh == 32 - mb {
+                    return rotate("srwi", mb);
+                } else if sh == 0 && mb == 0 {
+                    return rotate("clrrwi", 31 - me);
+                }
+            }
+            "mfspr" => match self.field(SPR) {
+                1 => return simple("mfxer", &ops[..1]),
+                8 => return simple("mflr", &ops[..1]),
+                9 => return simple("mfctr", &ops[..1]),
+                _ => (),
+            },
+            "mtspr" => match self.field(SPR) {
+                1 => return simple("mtxer", &ops[1..]),
+                8 => return simple("mtlr", &ops[1..]),
+                9 => return simple("mtctr", &ops[1..]),
+                _ => (),
+            },
+            "mftb" => match self.field(TBR) {
+                268 => return simple("mftb", &ops[..1]),
+                269 => return simple("mftbu", &ops[..1]),
+                _ => (),
+            },
+            "mtcrf" if self.field(CRM) == 0xFF => return simple("mtcr", &ops[1..]),
+            "cmpw" | "cmplw" | "cmpwi" | "cmplwi" if self.field(CRFD) == 0 => {
+                return simple(self.form.mnemonic, &ops[1..]);
+            }
+            "crxor" if self.field(CRBD) == self.field(CRBA) && self.field(CRBA) == self.field(CRBB) => {
+                return simple("crclr", &ops[..1]);
+            }
+            "creqv" if self.field(CRBD) == self.field(CRBA) && self.field(CRBA) == self.field(CRBB) => {
+                return simple("crset", &ops[..1]);
+            }
+            "cror" if self.field(CRBA) == self.field(CRBB) => return simple("crmove", &ops[..2]),
+            "crnor" if self.field(CRBA) == self.field(CRBB) => return simple("crnot", &ops[..2]),
+            "tw" if self.code == 0x7FE00008 => return simple("trap", &[]),
+            "bc" | "bclr" | "bcctr" => {
+                if let Some(result) = self.simplified_branch() {
+                    return result;
+                }
+            }
+            _ => (),
+        }
+
+        (self.mnemonic(), self.operands)
+    }
+
+    fn simplified_branch(&self) -> Option<(String, Operands)> {
+        let (bo, bi) = (self.field(BO), self.field(BI));
+        let kind = match self.form.mnemonic {
+            "bclr" => "lr",
+            "bcctr" => "ctr",
+            _ => "",
+        };
+        let hint = bo & 1 != 0;
+        let mut operands = Operands::default();
+
+        let condition = match bo & 0x1E {
+            // Branch always, plain `bc 20, 0, target` has no simplified form that encodes the same way
+            0x14 if bi == 0 && !kind.is_empty() && !hint => String::new(),
+            // Branch if condition false
+            0x04 => {
+                const FALSE: [&str; 4] = ["ge", "le", "ne", "ns"];
+                FALSE[(bi % 4) as usize].to_owned()
+            }
+            // Branch if condition true
+            0x0C => CONDITIONS[(bi % 4) as usize].to_owned(),
+            // Decrement CTR, branch if non-zero / zero
+            0x10 if bi == 0 && kind != "ctr" => "dnz".to_owned(),
+            0x12 if bi == 0 && kind != "ctr" => "dz".to_owned(),
+            _ => return None,
+        };
+
+        if matches!(bo & 0x1E, 0x04 | 0x0C) && bi / 4 != 0 {
+            operands.push(Operand::Cr((bi / 4) as u8));
+        }
+
+        let mut mnemonic = format!("b{condition}{kind}");
+        if self.link() {
+            mnemonic.push('l');
+        }
+        if self.has_suffix(Suffix::AA) {
+            mnemonic.push('a');
+        }
+        if hint {
+            // The y bit reverses the default prediction (backwards taken, forwards not taken), so which
+            // symbol it represents depends on the branch direction.
+            let backwards = self.form.mnemonic == "bc" && self.code & 0x8000 != 0;
+            mnemonic.push(if backwards { '-' } else { '+' });
+        }
+
+        if self.form.mnemonic == "bc" {
+            operands.push(self.operands[2]);
+        }
+
+        Some((mnemonic, operands))
+    }
+
+    /// Determines how this instruction affects control flow.
+    pub fn flow(&self) -> Flow {
+        // BO bit 2 (0x04) means "ignore CTR", bit 0 (0x10) means "ignore the condition"
+        let conditional = || self.field(BO) & 0x14 != 0x14;
+        match self.form.mnemonic {
+            "b" => match self.operands[0] {
+                Operand::Target(target) => Flow::Branch { target, conditional: false, link: self.link() },
+                _ => unreachable!(),
+            },
+            "bc" => match self.operands[2] {
+                Operand::Target(target) => {
+                    Flow::Branch { target, conditional: conditional(), link: self.link() }
+                }
+                _ => unreachable!(),
+            },
+            "bclr" if self.link() => Flow::Indirect { conditional: conditional(), link: true },
+            "bclr" => Flow::Return { conditional: conditional() },
+            "bcctr" => Flow::Indirect { conditional: conditional(), link: self.link() },
+            "rfi" => Flow::Return { conditional: false },
+            _ => Flow::Normal,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (mnemonic, operands) = self.simplified();
+        if operands.is_empty() {
+            return f.write_str(&mnemonic);
+        }
+        write!(f, "{mnemonic:<9} ")?;
+        for (n, operand) in operands.iter().enumerate() {
+            if n > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{operand}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: u32 = 0x8000_3100;
+
+    fn decode(code: u32) -> Instruction {
+        Instruction::decode(ADDRESS, code).unwrap_or_else(|| panic!("0x{code:08X} didn't decode"))
+    }
+
+    #[test]
+    fn decodes_instructions() {
+        let cases = [
+            (0x9421FFE0, "stwu      r1, -0x20(r1)"),
+            (0x7C0802A6, "mflr      r0"),
+            (0xBFC10018, "stmw      r30, 0x18(r1)"),
+            (0x38600001, "li        r3, 1"),
+            (0x3C608000, "lis       r3, 0x8000"),
+            (0x60000000, "nop"),
+            (0x80630004, "lwz       r3, 4(r3)"),
+            (0xC0228008, "lfs       f1, -0x7FF8(r2)"),
+            (0x2C030000, "cmpwi     r3, 0"),
+            (0x28030003, "cmplwi    r3, 3"),
+            (0x7C632214, "add       r3, r3, r4"),
+            (0x7C632215, "add.      r3, r3, r4"),
+            (0x5460103A, "slwi      r0, r3, 2"),
+            (0x7C0903A6, "mtctr     r0"),
+            (0xFC20081E, "fctiwz    f1, f1"),
+            (0x7C0004AC, "sync"),
+            (0x4C00012C, "isync"),
+            // Paired singles, which only the Gekko has
+            (0xE0230008, "psq_l     f1, 8(r3), 0, 0"),
+            (0x1022182A, "ps_add    f1, f2, f3"),
+        ];
+        for (code, text) in cases {
+            assert_eq!(decode(code).to_string(), text, "0x{code:08X}");
+        }
+        assert_eq!(decode(0x7C632215).mnemonic(), "add.");
+        assert_eq!(decode(0x7C632215).form.mnemonic, "add");
+    }
+
+    #[test]
+    fn decodes_control_flow() {
+        let cases = [
+            (0x7C632214, Flow::Normal),
+            (
+                0x4800000C,
+                Flow::Branch { target: ADDRESS + 0xC, conditional: false, link: false },
+            ),
+            (
+                0x48000011,
+                Flow::Branch { target: ADDRESS + 0x10, conditional: false, link: true },
+            ),
+            (
+                0x4BFFFFFC,
+                Flow::Branch { target: ADDRESS - 4, conditional: false, link: false },
+            ),
+            (
+                0x48000102,
+                Flow::Branch { target: 0x100, conditional: false, link: false },
+            ),
+            (
+                0x4182000C,
+                Flow::Branch { target: ADDRESS + 0xC, conditional: true, link: false },
+            ),
+            (
+                0x4082FFF8,
+                Flow::Branch { target: ADDRESS - 8, conditional: true, link: false },
+            ),
+            (0x4E800020, Flow::Return { conditional: false }),
+            (0x4D820020, Flow::Return { conditional: true }),
+            (0x4E800420, Flow::Indirect { conditional: false, link: false }),
+            (0x4E800421, Flow::Indirect { conditional: false, link: true }),
+            (0x4C000064, Flow::Return { conditional: false }),
+        ];
+        for (code, flow) in cases {
+            assert_eq!(decode(code).flow(), flow, "0x{code:08X} {}", decode(code));
+        }
+        assert_eq!(decode(0x4800000C).to_string(), "b         0x8000310C");
+        assert_eq!(decode(0x4082FFF8).to_string(), "bne       0x800030F8");
+        assert_eq!(decode(0x48000011).to_string(), "bl        0x80003110");
+    }
+
+    #[test]
+    fn rejects_invalid_words() {
+        for code in [0x00000000, 0x04000000, 0x7C000000 | (1 << 1), 0xEC000000] {
+            assert!(
+                Instruction::decode(ADDRESS, code).is_none(),
+                "0x{code:08X} decoded as {:?}",
+                Instruction::decode(ADDRESS, code).map(|instruction| instruction.to_string())
+            );
+        }
+    }
+}
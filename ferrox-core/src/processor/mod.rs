@@ -0,0 +1,9 @@
+pub mod assembler;
+pub mod gekko;
+
+/// All supported architectures.
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub enum ProcessorType {
+    #[default]
+    PowerPCGekko,
+}
@@ -0,0 +1,813 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+
+use snafu::{ensure, OptionExt};
+
+use crate::analysis::class::Class;
+use crate::analysis::exception::ExceptionTable;
+use crate::analysis::frame::Frame;
+use crate::demangle::{self, Demangled};
+use crate::error::{
+    FerroxError, InvalidDolSnafu, InvalidNameSnafu, InvalidPatchSnafu, NameInUseSnafu, ValidationSnafu,
+};
+use crate::format::compression::Compression;
+use crate::format::dol::DolBinary;
+use crate::format::rel::RelBinary;
+use crate::format::{BinaryFormat, Permissions, Segment};
+use crate::history::{Edit, History};
+use crate::navigation;
+use crate::processor::assembler;
+use crate::processor::gekko::Instruction;
+use crate::registry::{Prototype, TypeInfo, TypeRegistry};
+use crate::signature::{LibraryConflict, LibraryMatch};
+
+/// How control flow leaves a basic block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Unconditional branch, or falling through into the next block
+    Unconditional,
+    /// Conditional branch is taken
+    True,
+    /// Conditional branch falls through
+    False,
+    /// One of the targets of a jump table
+    Switch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub target: u32,
+    pub kind: EdgeKind,
+}
+
+/// A run of instructions that's only ever entered at the top and left at the bottom.
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    pub start: u32,
+    /// Address one past the last instruction
+    pub end: u32,
+    pub successors: Vec<Edge>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub address: u32,
+    /// Address one past the last instruction of the last block
+    pub end: u32,
+    pub blocks: BTreeMap<u32, BasicBlock>,
+    /// Jump tables the function uses, keyed by the address of their `bctr`
+    pub jump_tables: BTreeMap<u32, JumpTable>,
+    pub frame: Frame,
+}
+
+/// A `bctr` through a table of addresses, which is what `switch` statements compile to.
+#[derive(Debug, Clone)]
+pub struct JumpTable {
+    /// Address of the table itself
+    pub address: u32,
+    /// The `cmplwi` that makes sure the index is inside the table
+    pub compare: u32,
+    /// GPR holding the index at `compare`
+    pub index: u8,
+    /// Where each index goes
+    pub targets: Vec<u32>,
+}
+
+impl Function {
+    /// Finds the block containing the given address.
+    pub fn block_containing(&self, address: u32) -> Option<&BasicBlock> {
+        self.blocks
+            .range(..=address)
+            .next_back()
+            .map(|(_, block)| block)
+            .filter(|block| address < block.end)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XrefKind {
+    /// `bl` to the start of a function
+    Call,
+    /// Any other branch
+    Jump,
+    /// Address is calculated, loaded from or stored to
+    Data,
+}
+
+/// A reference from one address to another, stored on the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Xref {
+    pub from: u32,
+    pub kind: XrefKind,
+}
+
+/// How the user wants an operand to be displayed, instead of the default for its type.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum OperandFormat {
+    #[default]
+    Default,
+    Hex,
+    Decimal,
+    /// Packed ASCII characters, e.g. `'RIFF'`
+    Char,
+    /// Treat the value as an address and show the name of whatever is there
+    Offset,
+    /// Show the member of the named enum with this value
+    Enum(String),
+}
+
+/// Bytes that have been written over the binary since it was loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    pub original: Vec<u8>,
+    pub patched: Vec<u8>,
+}
+
+/// Everything we know about a loaded binary. Views only ever read from this, analysis passes fill it in.
+pub struct Program {
+    /// Where the binary came from. Files picked out of a disc or archive have their path inside it joined on,
+    /// like `game.iso/files/RELS.arc/rels/d_a_obj.rel`, so it's clear which copy was loaded
+    pub path: PathBuf,
+    pub data: Vec<u8>,
+    /// What the data was loaded as, so it can be loaded the same way again from a database
+    pub format: BinaryFormat,
+    /// How the file was compressed, if it was. `data` is always decompressed
+    pub compression: Option<Compression>,
+    pub segments: Vec<Segment<u32>>,
+    pub entry_point: Option<u32>,
+    /// Names that have been given to addresses, anything else gets an automatic name
+    pub names: BTreeMap<u32, String>,
+    pub functions: BTreeMap<u32, Function>,
+    /// All references, keyed by the address being referenced
+    pub xrefs: BTreeMap<u32, Vec<Xref>>,
+    pub types: TypeRegistry,
+    /// Comments only shown at their own address
+    pub comments: BTreeMap<u32, String>,
+    /// Comments that are also shown anywhere the address is referenced
+    pub repeatable_comments: BTreeMap<u32, String>,
+    /// Names given to registers and stack variables, keyed by function and the variable's automatic name
+    pub variable_names: BTreeMap<(u32, String), String>,
+    /// Display overrides, keyed by address and the index of the (simplified) operand
+    pub operand_formats: BTreeMap<(u32, usize), OperandFormat>,
+    /// Every patched run of bytes, keyed by address. `data` already has them applied
+    pub patches: BTreeMap<u32, Patch>,
+    /// Functions identified by library signatures
+    pub library_functions: BTreeMap<u32, LibraryMatch>,
+    /// Functions that matched several library functions, for the user to sort out
+    pub library_conflicts: Vec<LibraryConflict>,
+    /// C++ classes recovered from their vtables, keyed by vtable address
+    pub classes: BTreeMap<u32, Class>,
+    /// CodeWarrior exception tables, keyed by the function they describe
+    pub exception_tables: BTreeMap<u32, ExceptionTable>,
+    /// Show names the way they're stored instead of demangling C++ ones
+    pub raw_names: bool,
+    /// Bumped on every user edit, so views know when any cached state needs to be rebuilt
+    pub revision: u64,
+    /// Every user edit, for undo/redo
+    pub history: History,
+}
+
+impl Program {
+    pub fn load(path: PathBuf, data: Vec<u8>, format: BinaryFormat) -> Result<Self, FerroxError> {
+        // Modules have to be linked against themselves before there's anything sensible to analyze
+        let data = match format {
+            BinaryFormat::GameCubeREL => RelBinary::relocate(data)?,
+            _ => data,
+        };
+        let (segments, entry_point) = match format {
+            BinaryFormat::GameCubeDOL => (DolBinary::segments(&data)?, Some(DolBinary::entry_point(&data)?)),
+            BinaryFormat::GameCubeREL => (RelBinary::segments(&data)?, RelBinary::entry_point(&data)?),
+            // Without any other information, just map the whole file at 0
+            BinaryFormat::BinaryFile => (
+                vec![Segment {
+                    name: "seg000".to_owned(),
+                    address: 0,
+                    size: data.len() as u32,
+                    offset: 0,
+                    permissions: Permissions::READ | Permissions::WRITE | Permissions::EXECUTE,
+                }],
+                None,
+            ),
+        };
+
+        let mut names = BTreeMap::new();
+        if let Some(entry_point) = entry_point {
+            let name = match format {
+                BinaryFormat::GameCubeREL => "_prolog",
+                _ => "__start",
+            };
+            names.insert(entry_point, name.to_owned());
+        }
+
+        Ok(Self {
+            path,
+            data,
+            format,
+            compression: None,
+            segments,
+            entry_point,
+            names,
+            functions: BTreeMap::new(),
+            xrefs: BTreeMap::new(),
+            types: TypeRegistry::new(),
+            comments: BTreeMap::new(),
+            repeatable_comments: BTreeMap::new(),
+            variable_names: BTreeMap::new(),
+            operand_formats: BTreeMap::new(),
+            patches: BTreeMap::new(),
+            library_functions: BTreeMap::new(),
+            library_conflicts: Vec::new(),
+            classes: BTreeMap::new(),
+            exception_tables: BTreeMap::new(),
+            raw_names: false,
+            revision: 0,
+            history: History::new(),
+        })
+    }
+
+    /// Catches binaries that loaded but can't be right, like a segment pointing past the end of the file.
+    pub fn validate(&self) -> Result<(), FerroxError> {
+        for segment in &self.segments {
+            if segment.permissions.contains(Permissions::UNINITIALIZED) {
+                continue;
+            }
+            ensure!(
+                self.bytes(segment.address, segment.size).is_some(),
+                ValidationSnafu {
+                    reason: format!("segment {} runs past the end of the file", segment.name)
+                }
+            );
+        }
+        if let Some(entry_point) = self.entry_point {
+            ensure!(
+                self.is_code(entry_point),
+                ValidationSnafu {
+                    reason: format!("entry point 0x{entry_point:08X} isn't in a code segment")
+                }
+            );
+        }
+        Ok(())
+    }
+
+    /// Writes the program back out as a DOL, including any bytes that have been patched since it was loaded.
+    pub fn write_dol(&self) -> Result<Vec<u8>, FerroxError> {
+        let entry_point = self.entry_point.context(InvalidDolSnafu { reason: "there's no entry point" })?;
+        let original = (self.format == BinaryFormat::GameCubeDOL).then_some(self.data.as_slice());
+        DolBinary::write(&self.segments, &self.data, entry_point, original)
+    }
+
+    pub fn segment_at(&self, address: u32) -> Option<&Segment<u32>> {
+        self.segments.iter().find(|segment| segment.contains(address))
+    }
+
+    /// Returns the file data backing `length` bytes at `address`, if they're all inside one initialized segment.
+    pub fn bytes(&self, address: u32, length: u32) -> Option<&[u8]> {
+        let segment = self.segment_at(address)?;
+        let end = (address - segment.address).checked_add(length);
+        if segment.permissions.contains(Permissions::UNINITIALIZED)
+            || end.is_none_or(|end| end > segment.size)
+        {
+            return None;
+        }
+        let start = (segment.offset + (address - segment.address)) as usize;
+        self.data.get(start..start + length as usize)
+    }
+
+    /// Offset into the file of the byte loaded at `address`, if it comes from the file at all.
+    pub fn file_offset(&self, address: u32) -> Option<u32> {
+        let segment = self.segment_at(address)?;
+        match segment.permissions.contains(Permissions::UNINITIALIZED) {
+            true => None,
+            false => Some(segment.offset + (address - segment.address)),
+        }
+    }
+
+    pub fn read_u32(&self, address: u32) -> Option<u32> {
+        self.bytes(address, 4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn is_code(&self, address: u32) -> bool {
+        self.segment_at(address).is_some_and(|segment| segment.permissions.contains(Permissions::EXECUTE))
+    }
+
+    /// Decodes the instruction at `address`, if it's in an executable segment.
+    pub fn instruction(&self, address: u32) -> Option<Instruction> {
+        if !self.is_code(address) {
+            return None;
+        }
+        Instruction::decode(address, self.read_u32(address)?)
+    }
+
+    pub fn function_containing(&self, address: u32) -> Option<&Function> {
+        self.functions
+            .range(..=address)
+            .next_back()
+            .map(|(_, function)| function)
+            .filter(|function| address < function.end)
+    }
+
+    /// Name shown for an address. Mangled C++ names are demangled into their full signature, like
+    /// `foo::Bar::update(float)`, unless raw names are being shown.
+    pub fn display_name(&self, address: u32) -> String {
+        match self.demangled(address) {
+            Some(demangled) => demangled.to_string(),
+            None => self.raw_name(address),
+        }
+    }
+
+    /// Same as [`display_name`](Self::display_name), but without the parameters of demangled names, for
+    /// places that show them some other way, like next to a prototype.
+    pub fn short_name(&self, address: u32) -> String {
+        match self.demangled(address) {
+            Some(demangled) => demangled.qualified_name(),
+            None => self.raw_name(address),
+        }
+    }
+
+    /// Demangled form of the name given to an address, if it's a C++ one and raw names aren't being shown.
+    pub fn demangled(&self, address: u32) -> Option<Demangled> {
+        match self.raw_names {
+            true => None,
+            false => demangle::demangle(self.names.get(&address)?),
+        }
+    }
+
+    /// Switches between showing raw and demangled names.
+    pub fn set_raw_names(&mut self, raw_names: bool) {
+        if self.raw_names != raw_names {
+            self.raw_names = raw_names;
+            self.revision += 1;
+        }
+    }
+
+    /// Name given to an address as it's stored, falling back to an automatic one based on what's there. This
+    /// is the one to write out wherever a linker or another tool needs to find the symbol again.
+    pub fn raw_name(&self, address: u32) -> String {
+        if let Some(name) = self.names.get(&address) {
+            return name.clone();
+        }
+        if self.functions.contains_key(&address) {
+            format!("sub_{address:08X}")
+        } else if self.is_code(address) {
+            format!("loc_{address:08X}")
+        } else {
+            format!("unk_{address:08X}")
+        }
+    }
+
+    /// Describes an address relative to the function it's in, e.g. `main+0x50`.
+    pub fn describe(&self, address: u32) -> String {
+        match self.function_containing(address) {
+            Some(function) if function.address != address => {
+                format!(
+                    "{}+0x{:X}",
+                    self.display_name(function.address),
+                    address - function.address
+                )
+            }
+            _ => self.display_name(address),
+        }
+    }
+
+    pub fn add_xref(&mut self, to: u32, xref: Xref) {
+        let xrefs = self.xrefs.entry(to).or_default();
+        if !xrefs.contains(&xref) {
+            xrefs.push(xref);
+        }
+    }
+
+    /// Gives an address a name, or resets it back to an automatic name if `name` is empty.
+    pub fn rename(&mut self, address: u32, name: &str) -> Result<(), FerroxError> {
+        let name = name.trim();
+        if !name.is_empty() {
+            check_name(name)?;
+            if let Some((&other, _)) =
+                self.names.iter().find(|&(&other, other_name)| other != address && other_name == name)
+            {
+                return NameInUseSnafu { name, address: other }.fail();
+            }
+        }
+
+        let new = (!name.is_empty()).then(|| name.to_owned());
+        let rename = Edit::Rename { address, old: self.names.get(&address).cloned(), new };
+        match self.demangled_prototype(address, name) {
+            Some(prototype) => {
+                let description = rename.describe();
+                self.commit(Edit::Batch { description, edits: vec![rename, prototype] });
+            }
+            None => self.commit(rename),
+        }
+        Ok(())
+    }
+
+    /// Edit giving the function at `address` the prototype its new name spells out, if that's a mangled one.
+    /// Whatever prototype it has now is taken as what its code suggests.
+    fn demangled_prototype(&self, address: u32, name: &str) -> Option<Edit> {
+        if !self.functions.contains_key(&address) {
+            return None;
+        }
+        let old = self.types.prototype(address).cloned();
+        let new = demangle::demangle(name)?.prototype(old.as_ref());
+        Some(Edit::Prototype { address, old, new })
+    }
+
+    /// Gives lots of addresses a name at once, as a single edit. Names that aren't valid, or are already used
+    /// somewhere else, are skipped. Functions with mangled names get the prototype they spell out as part of
+    /// the same edit. Returns how many addresses were renamed.
+    pub fn rename_all(&mut self, description: String, names: &[(u32, String)]) -> usize {
+        let mut used: HashMap<String, u32> =
+            self.names.iter().map(|(&address, name)| (name.clone(), address)).collect();
+        let (mut edits, mut count) = (Vec::new(), 0);
+        for (address, name) in names {
+            let name = name.trim();
+            if name.is_empty()
+                || check_name(name).is_err()
+                || used.get(name).is_some_and(|other| other != address)
+                || self.names.get(address).is_some_and(|old| old == name)
+            {
+                continue;
+            }
+            let old = self.names.insert(*address, name.to_owned());
+            if let Some(old) = &old {
+                used.remove(old);
+            }
+            used.insert(name.to_owned(), *address);
+            edits.push(Edit::Rename { address: *address, old, new: Some(name.to_owned()) });
+            count += 1;
+            if let Some(edit) = self.demangled_prototype(*address, name) {
+                self.apply(&edit, false);
+                edits.push(edit);
+            }
+        }
+
+        if count > 0 {
+            self.revision += 1;
+            self.history.push(Edit::Batch { description, edits });
+        }
+        count
+    }
+
+    /// Name shown for a variable in a function, which is its automatic name unless it's been renamed.
+    pub fn variable_name(&self, function: u32, variable: &str) -> String {
+        self.variable_names
+            .get(&(function, variable.to_owned()))
+            .cloned()
+            .unwrap_or_else(|| variable.to_owned())
+    }
+
+    /// Gives a variable in a function a name, or resets it back to its automatic name if `name` is empty.
+    pub fn rename_variable(&mut self, function: u32, variable: &str, name: &str) -> Result<(), FerroxError> {
+        let name = name.trim();
+        if !name.is_empty() && name != variable {
+            check_name(name)?;
+            let taken = self
+                .variable_names
+                .range((function, String::new())..)
+                .take_while(|((other, _), _)| *other == function);
+            if taken.clone().any(|((_, other), other_name)| other != variable && other_name == name) {
+                return NameInUseSnafu { name, address: function }.fail();
+            }
+        }
+
+        let key = (function, variable.to_owned());
+        let new = (!name.is_empty() && name != variable).then(|| name.to_owned());
+        let old = self.variable_names.get(&key).cloned();
+        self.commit(Edit::RenameVariable { function, variable: variable.to_owned(), old, new });
+        Ok(())
+    }
+
+    /// Sets the prototype of the function at `address`, or removes it if `prototype` is `None`.
+    pub fn set_prototype(&mut self, address: u32, prototype: Option<Prototype>) {
+        let old = self.types.prototype(address).cloned();
+        self.commit(Edit::Prototype { address, old, new: prototype });
+    }
+
+    /// Sets the comment at an address, an empty comment removes it.
+    pub fn set_comment(&mut self, address: u32, comment: &str, repeatable: bool) {
+        let comments = if repeatable {
+            &self.repeatable_comments
+        } else {
+            &self.comments
+        };
+        let old = comments.get(&address).cloned();
+        let new = Some(comment.trim_end().to_owned()).filter(|comment| !comment.is_empty());
+        self.commit(Edit::Comment { address, repeatable, old, new });
+    }
+
+    pub fn set_operand_format(&mut self, address: u32, operand: usize, format: OperandFormat) {
+        let old = self.operand_format(address, operand).clone();
+        self.commit(Edit::OperandFormat { address, operand, old, new: format });
+    }
+
+    pub fn operand_format(&self, address: u32, operand: usize) -> &OperandFormat {
+        static DEFAULT: OperandFormat = OperandFormat::Default;
+        self.operand_formats.get(&(address, operand)).unwrap_or(&DEFAULT)
+    }
+
+    /// Adds or replaces a named type, like an enum.
+    pub fn define_type(&mut self, name: String, type_info: TypeInfo) {
+        let old = self.types.definition(&name).cloned();
+        self.commit(Edit::DefineType { name, old, new: Some(type_info) });
+    }
+
+    /// Writes bytes over the binary. Patching the same bytes again updates the existing patch, but patches
+    /// can't partly overlap, since reverting either one would then also undo part of the other.
+    pub fn patch(&mut self, address: u32, bytes: &[u8]) -> Result<(), FerroxError> {
+        let error = |reason: String| InvalidPatchSnafu { address, reason };
+        let old = self
+            .bytes(address, bytes.len() as u32)
+            .context(error(
+                "the bytes aren't all inside one initialized segment".into(),
+            ))?
+            .to_vec();
+        let end = address + bytes.len() as u32;
+        if let Some((&other, _)) = self.patches.range(..end).next_back().filter(|&(&other, patch)| {
+            other + patch.patched.len() as u32 > address
+                && (other != address || patch.patched.len() != bytes.len())
+        }) {
+            return error(format!(
+                "it overlaps the patch at 0x{other:08X}, revert that first"
+            ))
+            .fail();
+        }
+
+        self.commit(Edit::Patch { address, old, new: bytes.to_vec() });
+        Ok(())
+    }
+
+    /// Assembles an instruction and patches it over the one at `address`. Symbols in the operands are resolved
+    /// the same way go to does.
+    pub fn assemble(&mut self, address: u32, text: &str) -> Result<(), FerroxError> {
+        ensure!(
+            self.is_code(address) && address.is_multiple_of(4),
+            InvalidPatchSnafu { address, reason: "instructions can only go in code" }
+        );
+        let code = assembler::assemble(address, text, |name| navigation::resolve(self, name).ok())?;
+        self.patch(address, &code.to_be_bytes())
+    }
+
+    /// Puts the original bytes back for the patch at `address`, returning whether there was one.
+    pub fn revert_patch(&mut self, address: u32) -> bool {
+        let Some(patch) = self.patches.get(&address) else {
+            return false;
+        };
+        let edit = Edit::Patch { address, old: patch.patched.clone(), new: patch.original.clone() };
+        self.commit(edit);
+        true
+    }
+
+    /// The binary as it was loaded, without any patches.
+    pub fn original_data(&self) -> Vec<u8> {
+        let mut data = self.data.clone();
+        for (&address, patch) in &self.patches {
+            if let Some(offset) = self.file_offset(address) {
+                let offset = offset as usize;
+                data[offset..offset + patch.original.len()].copy_from_slice(&patch.original);
+            }
+        }
+        data
+    }
+
+    /// Applies a new edit and records it so it can be undone.
+    fn commit(&mut self, edit: Edit) {
+        if edit.is_noop() {
+            return;
+        }
+        self.apply(&edit, false);
+        self.history.push(edit);
+    }
+
+    /// Reverts the most recent edit, returning whether there was anything to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.history.undo().cloned() {
+            Some(edit) => {
+                self.apply(&edit, true);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Re-applies the most recently undone edit, returning whether there was anything to redo.
+    pub fn redo(&mut self) -> bool {
+        match self.history.redo().cloned() {
+            Some(edit) => {
+                self.apply(&edit, false);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Undoes or redoes edits until exactly `position` of them are applied.
+    pub fn jump_to(&mut self, position: usize) {
+        while self.history.position() > position && self.undo() {}
+        while self.history.position() < position && self.redo() {}
+    }
+
+    /// Sets whichever side of the edit we're moving towards, without recording anything.
+    pub(crate) fn apply(&mut self, edit: &Edit, undo: bool) {
+        fn set<K: Ord, V>(map: &mut BTreeMap<K, V>, key: K, value: Option<V>) {
+            match value {
+                Some(value) => map.insert(key, value),
+                None => map.remove(&key),
+            };
+        }
+
+        match edit.clone() {
+            Edit::Rename { address, old, new } => {
+                set(&mut self.names, address, if undo { old } else { new });
+            }
+            Edit::Comment { address, repeatable, old, new } => {
+                let comments = if repeatable {
+                    &mut self.repeatable_comments
+                } else {
+                    &mut self.comments
+                };
+                set(comments, address, if undo { old } else { new });
+            }
+            Edit::OperandFormat { address, operand, old, new } => {
+                let format =
+                    Some(if undo { old } else { new }).filter(|format| *format != OperandFormat::Default);
+                set(&mut self.operand_formats, (address, operand), format);
+            }
+            Edit::DefineType { name, old, new } => match if undo { old } else { new } {
+                Some(type_info) => self.types.define(name, type_info),
+                None => {
+                    self.types.undefine(&name);
+                }
+            },
+            Edit::Prototype { address, old, new } => {
+                self.types.set_prototype(address, if undo { old } else { new });
+            }
+            Edit::RenameVariable { function, variable, old, new } => {
+                set(
+                    &mut self.variable_names,
+                    (function, variable),
+                    if undo { old } else { new },
+                );
+            }
+            Edit::Patch { address, old, new } => {
+                let (bytes, previous) = if undo { (old, new) } else { (new, old) };
+                if let Some(offset) = self.file_offset(address) {
+                    let offset = offset as usize;
+                    self.data[offset..offset + bytes.len()].copy_from_slice(&bytes);
+                }
+                // Redoing a patch over an existing one keeps the bytes from before either of them
+                let patch = self
+                    .patches
+                    .remove(&address)
+                    .unwrap_or(Patch { original: previous, patched: Vec::new() });
+                if patch.original != bytes {
+                    self.patches.insert(address, Patch { patched: bytes, ..patch });
+                }
+            }
+            Edit::Batch { edits, .. } => match undo {
+                true => edits.iter().rev().for_each(|edit| self.apply(edit, true)),
+                false => edits.iter().for_each(|edit| self.apply(edit, false)),
+            },
+        }
+        self.revision += 1;
+    }
+}
+
+/// Makes sure a name can be shown and typed back in, e.g. in the listing and in go to.
+fn check_name(name: &str) -> Result<(), FerroxError> {
+    ensure!(
+        !name.starts_with(|c: char| c.is_ascii_digit()),
+        InvalidNameSnafu { name, reason: "names can't start with a digit" }
+    );
+    ensure!(
+        !name.contains(|c: char| c.is_whitespace() || c.is_control() || c == '#'),
+        InvalidNameSnafu { name, reason: "names can't contain whitespace or '#'" }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TEXT};
+
+    const HELPER: u32 = TEXT + 8;
+
+    fn program() -> Program {
+        testing::program(
+            "
+            li r3, 0
+            blr
+        helper:
+            li r3, 1
+            blr
+            ",
+            &[],
+        )
+    }
+
+    #[test]
+    fn undoes_and_redoes_renames() {
+        let mut program = program();
+        program.rename(HELPER, "helper").unwrap();
+        assert_eq!(program.names[&HELPER], "helper");
+        assert!(program.rename(TEXT, "helper").is_err(), "names are unique");
+        assert!(program.rename(TEXT, "1st").is_err());
+
+        assert!(program.undo());
+        assert!(!program.names.contains_key(&HELPER));
+        assert!(program.redo());
+        assert_eq!(program.names[&HELPER], "helper");
+
+        // A mangled name sets the prototype in the same edit
+        let inferred = program.types.prototype(HELPER).cloned();
+        program.rename(HELPER, "calc__3FooFi").unwrap();
+        assert_ne!(program.types.prototype(HELPER).cloned(), inferred);
+        assert!(program.undo());
+        assert_eq!(program.names[&HELPER], "helper");
+        assert_eq!(program.types.prototype(HELPER).cloned(), inferred);
+
+        // An empty name goes back to the automatic one, replacing the rename that was undone
+        program.rename(HELPER, "").unwrap();
+        assert!(!program.names.contains_key(&HELPER));
+        assert_eq!(program.history.edits().len(), 2);
+    }
+
+    #[test]
+    fn undoes_and_redoes_comments() {
+        let mut program = program();
+        program.set_comment(TEXT, "returns 0\n", false);
+        program.set_comment(TEXT, "entry", true);
+        assert_eq!(program.comments[&TEXT], "returns 0");
+        assert_eq!(program.repeatable_comments[&TEXT], "entry");
+        // Setting the same comment again isn't an edit
+        program.set_comment(TEXT, "returns 0", false);
+        assert_eq!(program.history.position(), 2);
+
+        assert!(program.undo());
+        assert!(!program.repeatable_comments.contains_key(&TEXT));
+        assert_eq!(program.comments[&TEXT], "returns 0");
+        assert!(program.undo());
+        assert!(program.comments.is_empty());
+        assert!(!program.undo());
+
+        assert!(program.redo());
+        assert!(program.redo());
+        assert!(!program.redo());
+        assert_eq!(program.repeatable_comments[&TEXT], "entry");
+        program.set_comment(TEXT, "", false);
+        assert!(!program.comments.contains_key(&TEXT));
+    }
+
+    #[test]
+    fn undoes_and_redoes_patches() {
+        let mut program = program();
+        let original = program.read_u32(TEXT).unwrap();
+        program.assemble(TEXT, "li r3, 5").unwrap();
+        let patched = program.read_u32(TEXT).unwrap();
+        assert_ne!(patched, original);
+        assert_eq!(program.patches[&TEXT].original, original.to_be_bytes());
+        assert!(
+            program.patch(TEXT + 2, &[0, 0, 0, 0]).is_err(),
+            "patches can't partly overlap"
+        );
+
+        assert!(program.undo());
+        assert_eq!(program.read_u32(TEXT), Some(original));
+        assert!(program.patches.is_empty());
+        assert!(program.redo());
+        assert_eq!(program.read_u32(TEXT), Some(patched));
+
+        // Patching it again keeps the original bytes, and reverting puts them back
+        program.assemble(TEXT, "li r3, 6").unwrap();
+        assert_eq!(program.patches[&TEXT].original, original.to_be_bytes());
+        assert!(program.revert_patch(TEXT));
+        assert_eq!(program.read_u32(TEXT), Some(original));
+        assert!(program.patches.is_empty());
+        assert_eq!(program.original_data(), program.data);
+    }
+
+    #[test]
+    fn new_edits_clear_the_redo_stack() {
+        let mut program = program();
+        program.rename(HELPER, "first").unwrap();
+        program.set_comment(HELPER, "second", false);
+        program.patch(HELPER, &0x3860_0002u32.to_be_bytes()).unwrap();
+        assert!(program.undo());
+        assert!(program.undo());
+        assert!(program.history.can_redo());
+
+        program.set_comment(TEXT, "instead", false);
+        assert!(!program.history.can_redo());
+        assert!(!program.redo());
+        assert_eq!(program.history.edits().len(), 2);
+        assert!(!program.comments.contains_key(&HELPER));
+        assert!(program.patches.is_empty());
+
+        program.jump_to(0);
+        assert!(!program.names.contains_key(&HELPER) && program.comments.is_empty());
+        program.jump_to(2);
+        assert_eq!(
+            (program.names[&HELPER].as_str(), program.comments[&TEXT].as_str()),
+            ("first", "instead")
+        );
+    }
+}
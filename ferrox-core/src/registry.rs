@@ -0,0 +1,224 @@
+use core::ops::Range;
+use std::collections::BTreeMap;
+
+use crate::text::TextEncoding;
+
+// TODO: make this less stupid
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypeInfo {
+    Function {
+        name: String,
+        is_extern: bool,
+    },
+    Integer {
+        bits: u32,
+        signed: bool,
+    },
+    Struct {
+        name: String,
+        size: u64,
+        members: Vec<Member>,
+    },
+    /// Like a struct, except every member starts at offset 0
+    Union {
+        name: String,
+        size: u64,
+        members: Vec<Member>,
+    },
+    Array {
+        element_type: Box<TypeInfo>,
+        count: u64,
+    },
+    Enum {
+        name: String,
+        size: u64,
+        members: Vec<(String, i64)>,
+    },
+    /// Address of something else, `target` is `None` if we don't know what (i.e. `void *`)
+    Pointer {
+        target: Option<Box<TypeInfo>>,
+    },
+    /// Refers to a named type by name, so types can point to each other (or themselves) and pick up edits
+    Named {
+        name: String,
+    },
+    Float {
+        bits: u32,
+    },
+    /// Null-terminated string, `length` includes the terminator
+    String {
+        encoding: TextEncoding,
+        length: u64,
+    },
+}
+
+impl TypeInfo {
+    /// An untyped pointer.
+    pub fn pointer() -> Self {
+        Self::Pointer { target: None }
+    }
+
+    pub fn pointer_to(target: TypeInfo) -> Self {
+        Self::Pointer { target: Some(Box::new(target)) }
+    }
+
+    pub fn is_pointer(&self) -> bool {
+        matches!(self, Self::Pointer { .. })
+    }
+}
+
+/// A field of a struct or union.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Member {
+    pub name: String,
+    /// Offset in bytes from the start of the struct
+    pub offset: u64,
+    pub type_info: TypeInfo,
+}
+
+/// What a function takes and returns. Parameters are passed the EABI way, in order through r3-r10 or f1-f8
+/// depending on whether they're floats.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Prototype {
+    /// `None` for `void`
+    pub return_type: Option<TypeInfo>,
+    pub parameters: Vec<Parameter>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub type_info: TypeInfo,
+}
+
+/// Designed with quickly fetching all types for a given address in mind.
+#[derive(Debug, Default)]
+pub struct TypeRegistry {
+    lookup: BTreeMap<u64, Vec<(u64, TypeInfo)>>,
+    /// Named types that aren't tied to an address, like enums
+    definitions: BTreeMap<String, TypeInfo>,
+    /// Prototypes of functions, keyed by their address
+    prototypes: BTreeMap<u32, Prototype>,
+}
+
+impl TypeRegistry {
+    pub fn new() -> Self {
+        Self {
+            lookup: BTreeMap::new(),
+            definitions: BTreeMap::new(),
+            prototypes: BTreeMap::new(),
+        }
+    }
+
+    /// Adds (or replaces) a named type.
+    pub fn define(&mut self, name: String, type_info: TypeInfo) {
+        self.definitions.insert(name, type_info);
+    }
+
+    pub fn undefine(&mut self, name: &str) -> Option<TypeInfo> {
+        self.definitions.remove(name)
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&TypeInfo> {
+        self.definitions.get(name)
+    }
+
+    pub fn definitions(&self) -> impl Iterator<Item = (&String, &TypeInfo)> {
+        self.definitions.iter()
+    }
+
+    /// Follows [`TypeInfo::Named`] references until reaching an actual type. Returns `None` if the name isn't
+    /// defined, or the references go around in a circle.
+    pub fn resolve<'a>(&'a self, mut type_info: &'a TypeInfo) -> Option<&'a TypeInfo> {
+        for _ in 0..16 {
+            match type_info {
+                TypeInfo::Named { name } => type_info = self.definitions.get(name)?,
+                _ => return Some(type_info),
+            }
+        }
+        None
+    }
+
+    /// Size of a value of this type in bytes, 0 if it can't be worked out.
+    pub fn size_of(&self, type_info: &TypeInfo) -> u64 {
+        match self.resolve(type_info) {
+            Some(TypeInfo::Integer { bits, .. } | TypeInfo::Float { bits }) => u64::from(*bits / 8),
+            Some(
+                TypeInfo::Struct { size, .. } | TypeInfo::Union { size, .. } | TypeInfo::Enum { size, .. },
+            ) => *size,
+            Some(TypeInfo::Array { element_type, count }) => self.size_of(element_type) * count,
+            Some(TypeInfo::Pointer { .. } | TypeInfo::Function { .. }) => 4,
+            Some(TypeInfo::String { length, encoding }) => match encoding {
+                TextEncoding::Utf16Be => length * 2,
+                _ => *length,
+            },
+            Some(TypeInfo::Named { .. }) | None => 0,
+        }
+    }
+
+    /// Alignment of a value of this type, the way CodeWarrior lays out structs.
+    pub fn align_of(&self, type_info: &TypeInfo) -> u64 {
+        match self.resolve(type_info) {
+            Some(TypeInfo::Struct { members, .. } | TypeInfo::Union { members, .. }) => {
+                members.iter().map(|member| self.align_of(&member.type_info)).max().unwrap_or(1)
+            }
+            Some(TypeInfo::Array { element_type, .. }) => self.align_of(element_type),
+            Some(TypeInfo::String { encoding: TextEncoding::Utf16Be, .. }) => 2,
+            Some(TypeInfo::String { .. }) => 1,
+            _ => self.size_of(type_info).clamp(1, 8),
+        }
+    }
+
+    pub fn set_prototype(&mut self, address: u32, prototype: Option<Prototype>) {
+        match prototype {
+            Some(prototype) => self.prototypes.insert(address, prototype),
+            None => self.prototypes.remove(&address),
+        };
+    }
+
+    pub fn prototype(&self, address: u32) -> Option<&Prototype> {
+        self.prototypes.get(&address)
+    }
+
+    pub fn insert(&mut self, range: Range<u64>, type_info: TypeInfo) {
+        self.lookup.entry(range.start).or_default().push((range.end, type_info));
+    }
+
+    /// Finds the item starting exactly at `address`, along with its end.
+    pub fn item_at(&self, address: u64) -> Option<(u64, &TypeInfo)> {
+        self.lookup.get(&address)?.first().map(|(end, type_info)| (*end, type_info))
+    }
+
+    /// Finds the first item that covers `address`, as `(start, end, type)`.
+    pub fn item_containing(&self, address: u64) -> Option<(u64, u64, &TypeInfo)> {
+        self.lookup.range(..=address).rev().find_map(|(&start, ranges)| {
+            ranges.iter().find(|(end, _)| address < *end).map(|(end, type_info)| (start, *end, type_info))
+        })
+    }
+
+    /// Start of the first item after `address`.
+    pub fn next_start(&self, address: u64) -> Option<u64> {
+        self.lookup.range(address + 1..).next().map(|(&start, _)| start)
+    }
+
+    /// Every item with an address, as `(start, end, type)` in address order.
+    pub fn items(&self) -> impl Iterator<Item = (u64, u64, &TypeInfo)> {
+        self.lookup
+            .iter()
+            .flat_map(|(&start, ranges)| ranges.iter().map(move |(end, type_info)| (start, *end, type_info)))
+    }
+
+    pub fn get_at_address(&self, address: u64) -> Vec<&TypeInfo> {
+        let mut results = Vec::new();
+        // Get all types whose start could possibly overlap with the address we're trying to look up.
+        for (_start, ranges) in self.lookup.range(..=address) {
+            // Iterate all types we've found and check which ones may overlap with the current adddress.
+            for (end, type_info) in ranges {
+                if address < *end {
+                    results.push(type_info);
+                }
+            }
+        }
+        results
+    }
+}
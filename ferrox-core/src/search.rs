@@ -0,0 +1,159 @@
+//! Searching the program's address space for byte patterns, strings, immediates and instructions.
+use regex::Regex;
+use snafu::ensure;
+
+use crate::error::{FerroxError, InvalidSearchSnafu};
+use crate::format::Permissions;
+use crate::listing::format_instruction;
+use crate::processor::gekko::Operand;
+use crate::program::Program;
+use crate::text::{self, TextEncoding};
+
+/// Stop collecting results after this many, nobody is going to look through more than that.
+pub const MAX_RESULTS: usize = 10_000;
+
+/// What to look for.
+#[derive(Debug, Clone)]
+pub enum Query {
+    /// Bytes to match, with `None` matching anything
+    Bytes(Vec<Option<u8>>),
+    /// Any instruction with an operand of this value
+    Immediate(i64),
+    /// Instructions whose text matches, using the same format as the listing but with single spaces
+    Instruction(Regex),
+}
+
+impl Query {
+    /// Parses a byte pattern like `94 21 ?? ?? 7C 08 02 A6`. Spaces are optional between bytes.
+    pub fn pattern(text: &str) -> Result<Self, FerroxError> {
+        let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+        ensure!(
+            !digits.is_empty(),
+            InvalidSearchSnafu { reason: "the pattern is empty" }
+        );
+        ensure!(
+            digits.len().is_multiple_of(2),
+            InvalidSearchSnafu { reason: "the pattern has an odd number of digits" }
+        );
+
+        let mut bytes = Vec::with_capacity(digits.len() / 2);
+        for pair in digits.chunks(2) {
+            let byte: String = pair.iter().collect();
+            if byte == "??" {
+                bytes.push(None);
+                continue;
+            }
+            match u8::from_str_radix(&byte, 16) {
+                Ok(byte) => bytes.push(Some(byte)),
+                Err(_) => {
+                    return InvalidSearchSnafu { reason: format!("\"{byte}\" isn't a hex byte") }.fail()
+                }
+            }
+        }
+        ensure!(
+            bytes.iter().any(Option::is_some),
+            InvalidSearchSnafu { reason: "the pattern is only wildcards" }
+        );
+        Ok(Self::Bytes(bytes))
+    }
+
+    pub fn text(text: &str, encoding: TextEncoding) -> Result<Self, FerroxError> {
+        ensure!(
+            !text.is_empty(),
+            InvalidSearchSnafu { reason: "the text is empty" }
+        );
+        Ok(Self::Bytes(
+            encoding.encode(text)?.into_iter().map(Some).collect(),
+        ))
+    }
+
+    /// Parses a decimal or `0x` prefixed hex value, optionally negative.
+    pub fn immediate(text: &str) -> Result<Self, FerroxError> {
+        match text::parse_number(text) {
+            Some(value) => Ok(Self::Immediate(value)),
+            None => InvalidSearchSnafu { reason: format!("\"{}\" isn't a number", text.trim()) }.fail(),
+        }
+    }
+
+    pub fn instruction(text: &str) -> Result<Self, FerroxError> {
+        match Regex::new(text) {
+            Ok(regex) => Ok(Self::Instruction(regex)),
+            Err(error) => InvalidSearchSnafu { reason: error.to_string() }.fail(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub address: u32,
+    /// What was found there, e.g. the matching instruction
+    pub preview: String,
+}
+
+/// Runs a search over every segment, returning results in address order.
+pub fn search(program: &Program, query: &Query) -> Vec<SearchResult> {
+    let mut results = Vec::new();
+    for segment in &program.segments {
+        if segment.permissions.contains(Permissions::UNINITIALIZED) {
+            continue;
+        }
+        let Some(data) = program.bytes(segment.address, segment.size) else {
+            continue;
+        };
+
+        match query {
+            Query::Bytes(pattern) => {
+                for (offset, window) in data.windows(pattern.len()).enumerate() {
+                    let matches = window
+                        .iter()
+                        .zip(pattern)
+                        .all(|(byte, wanted)| wanted.is_none_or(|wanted| *byte == wanted));
+                    if matches {
+                        let preview =
+                            window.iter().map(|byte| format!("{byte:02X}")).collect::<Vec<_>>().join(" ");
+                        results.push(SearchResult { address: segment.address + offset as u32, preview });
+                    }
+                    if results.len() >= MAX_RESULTS {
+                        return results;
+                    }
+                }
+            }
+            Query::Immediate(_) | Query::Instruction(_) => {
+                if !segment.permissions.contains(Permissions::EXECUTE) {
+                    continue;
+                }
+                for address in (segment.address..segment.address + segment.size).step_by(4) {
+                    let Some(instruction) = program.instruction(address) else {
+                        continue;
+                    };
+                    let matches = match query {
+                        Query::Immediate(value) => {
+                            let (_, operands) = instruction.simplified();
+                            operands.iter().any(|operand| match *operand {
+                                Operand::Simm(simm) => i64::from(simm) == *value,
+                                Operand::Uimm(uimm) => i64::from(uimm) == *value,
+                                Operand::Offset { disp, .. } => i64::from(disp) == *value,
+                                _ => false,
+                            })
+                        }
+                        Query::Instruction(regex) => {
+                            let text = format_instruction(program, &instruction);
+                            regex.is_match(&text.split_whitespace().collect::<Vec<_>>().join(" "))
+                        }
+                        Query::Bytes(_) => false,
+                    };
+                    if matches {
+                        results.push(SearchResult {
+                            address,
+                            preview: format_instruction(program, &instruction),
+                        });
+                        if results.len() >= MAX_RESULTS {
+                            return results;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    results
+}
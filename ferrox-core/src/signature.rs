@@ -0,0 +1,618 @@
+//! FLIRT style library signatures. Every game statically links the same SDK and runtime code, so a signature
+//! is just the start of a library function with holes where relocations go, plus a CRC of the bytes after
+//! that. Matching them against a program names all of that code without anyone having to do it by hand.
+//!
+//! Signature files (`.sig`) hold one version of one library. Everything is big endian, and strings are a u32
+//! length followed by UTF-8, the same as databases.
+use core::ops::Range;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use orthrus_core::prelude::*;
+use snafu::{ensure, ResultExt};
+
+use crate::analysis::prototype;
+use crate::error::{FerroxError, FileSnafu, InvalidElfSnafu, InvalidSignaturesSnafu};
+use crate::format::ar::Archive;
+use crate::format::elf::{ElfObject, SymbolBinding, SymbolKind, SHN_UNDEF};
+use crate::format::Permissions;
+use crate::program::Program;
+
+/// How many bytes at the start of a function are compared directly, holes and all.
+pub const PREFIX_LENGTH: usize = 32;
+/// Matches below this are more likely to be a coincidence than the library function.
+pub const MIN_CONFIDENCE: f32 = 0.5;
+/// Bytes that have to be verified before a match is fully trusted, anything less is scaled down.
+const TRUSTED_LENGTH: u32 = 48;
+
+const MAGIC: &[u8; 4] = b"FRXS";
+const VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Signature {
+    pub name: String,
+    /// Size of the whole function
+    pub size: u32,
+    /// The first bytes of the function, with `None` wherever a relocation goes
+    pub prefix: Vec<Option<u8>>,
+    /// How many bytes after the prefix are covered by `crc`, up to the first relocation
+    pub crc_length: u16,
+    pub crc: u16,
+}
+
+impl Signature {
+    /// Builds a signature from the bytes of a function, where `None` is a relocated byte.
+    pub fn new(name: String, bytes: &[Option<u8>]) -> Self {
+        let prefix = bytes[..bytes.len().min(PREFIX_LENGTH)].to_vec();
+        let remainder: Vec<u8> =
+            bytes[prefix.len()..].iter().map_while(|byte| *byte).take(u16::MAX as usize).collect();
+        Self {
+            name,
+            size: bytes.len() as u32,
+            prefix,
+            crc_length: remainder.len() as u16,
+            crc: crc16(&remainder),
+        }
+    }
+
+    /// Checks whether `bytes` (starting at the function) match, returning how many bytes were actually compared.
+    pub fn verify(&self, bytes: &[u8]) -> Option<u32> {
+        let crc_end = self.prefix.len() + self.crc_length as usize;
+        if bytes.len() < (self.size as usize).max(crc_end) {
+            return None;
+        }
+        let mut verified = 0;
+        for (byte, wanted) in bytes.iter().zip(&self.prefix) {
+            match wanted {
+                Some(wanted) if byte != wanted => return None,
+                Some(_) => verified += 1,
+                None => (),
+            }
+        }
+        if crc16(&bytes[self.prefix.len()..crc_end]) != self.crc {
+            return None;
+        }
+        Some(verified + self.crc_length as u32)
+    }
+}
+
+/// Every signature for one version of one library, e.g. "Dolphin SDK" "2001-08".
+#[derive(Debug, Clone, Default)]
+pub struct SignatureLibrary {
+    pub name: String,
+    pub version: String,
+    pub signatures: Vec<Signature>,
+}
+
+impl SignatureLibrary {
+    /// Name and version together, the way it's shown to the user.
+    pub fn title(&self) -> String {
+        format!("{} {}", self.name, self.version)
+    }
+
+    pub fn read(data: &[u8]) -> Result<Self, FerroxError> {
+        ensure!(
+            data.starts_with(MAGIC),
+            InvalidSignaturesSnafu { reason: "missing FRXS header" }
+        );
+        let mut reader = DataCursorRef::new(data, Endian::Big);
+        reader.set_position(MAGIC.len() as u64)?;
+        let version = reader.read_u32()?;
+        ensure!(
+            version == VERSION,
+            InvalidSignaturesSnafu { reason: format!("unsupported version {version}") }
+        );
+
+        let name = read_string(&mut reader)?;
+        let library_version = read_string(&mut reader)?;
+        let count = reader.read_u32()?;
+        let mut signatures = Vec::new();
+        for _ in 0..count {
+            let name = read_string(&mut reader)?;
+            let size = reader.read_u32()?;
+            let length = reader.read_u8()? as usize;
+            ensure!(
+                length <= PREFIX_LENGTH,
+                InvalidSignaturesSnafu { reason: format!("\"{name}\" has a {length} byte prefix") }
+            );
+            let holes = reader.read_u32()?;
+            let prefix = reader
+                .read_slice(length)?
+                .iter()
+                .enumerate()
+                .map(|(index, &byte)| (holes & (1 << index) == 0).then_some(byte))
+                .collect();
+            signatures.push(Signature {
+                name,
+                size,
+                prefix,
+                crc_length: reader.read_u16()?,
+                crc: reader.read_u16()?,
+            });
+        }
+        Ok(Self { name, version: library_version, signatures })
+    }
+
+    pub fn write(&self) -> Result<Vec<u8>, FerroxError> {
+        let mut writer = DataStream::new(Vec::new(), Endian::Big);
+        writer.extend_from_slice(MAGIC);
+        writer.write_u32(VERSION)?;
+        write_string(&mut writer, &self.name)?;
+        write_string(&mut writer, &self.version)?;
+        writer.write_u32(self.signatures.len() as u32)?;
+        for signature in &self.signatures {
+            write_string(&mut writer, &signature.name)?;
+            writer.write_u32(signature.size)?;
+            writer.write_u8(signature.prefix.len() as u8)?;
+            let holes = signature.prefix.iter().enumerate().fold(0u32, |holes, (index, byte)| {
+                holes | (byte.is_none() as u32) << index
+            });
+            writer.write_u32(holes)?;
+            for byte in &signature.prefix {
+                writer.write_u8(byte.unwrap_or_default())?;
+            }
+            writer.write_u16(signature.crc_length)?;
+            writer.write_u16(signature.crc)?;
+        }
+        Ok(std::mem::take(&mut *writer))
+    }
+}
+
+fn read_string(reader: &mut DataCursorRef) -> Result<String, FerroxError> {
+    let length = reader.read_u32()? as usize;
+    Ok(reader.read_string(length)?.into_owned())
+}
+
+fn write_string(writer: &mut DataStream<Vec<u8>>, string: &str) -> Result<(), FerroxError> {
+    writer.write_u32(string.len() as u32)?;
+    writer.extend_from_slice(string.as_bytes());
+    Ok(())
+}
+
+/// Loads every `.sig` file in a directory, which is fine to not exist.
+pub fn load_directory(directory: &Path) -> Result<Vec<SignatureLibrary>, FerroxError> {
+    if !directory.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(directory).context(FileSnafu { path: directory })? {
+        let path = entry.context(FileSnafu { path: directory })?.path();
+        if path.extension().is_some_and(|extension| extension == "sig") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let mut libraries = Vec::new();
+    for path in paths {
+        let data = std::fs::read(&path).context(FileSnafu { path: &path })?;
+        libraries.push(SignatureLibrary::read(&data)?);
+    }
+    Ok(libraries)
+}
+
+/// Loads signature files from the `signatures` folder, both next to the executable and in the working directory.
+pub fn load_default() -> Vec<SignatureLibrary> {
+    let mut directories = vec![PathBuf::from("signatures")];
+    if let Some(directory) =
+        std::env::current_exe().ok().and_then(|path| path.parent().map(|dir| dir.join("signatures")))
+    {
+        directories.push(directory);
+    }
+
+    let mut libraries: Vec<SignatureLibrary> = Vec::new();
+    for directory in directories {
+        match load_directory(&directory) {
+            Ok(loaded) => {
+                for library in loaded {
+                    // Both folders can be the same one, so don't load anything twice
+                    if !libraries.iter().any(|other| other.title() == library.title()) {
+                        libraries.push(library);
+                    }
+                }
+            }
+            Err(error) => eprintln!("Failed to load signatures: {error}"),
+        }
+    }
+    libraries
+}
+
+/// A function that's been identified as part of a library.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryMatch {
+    pub name: String,
+    /// Title of the library it came from
+    pub library: String,
+    /// How sure we are, from [`MIN_CONFIDENCE`] to 1
+    pub confidence: f32,
+}
+
+/// A function that matched several different library functions equally well, so it's been left alone.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryConflict {
+    pub address: u32,
+    pub candidates: Vec<LibraryMatch>,
+}
+
+/// The first word of a function or signature, if none of it is a relocation hole.
+fn leading_word<T: Copy>(bytes: &[T], byte: impl Fn(T) -> Option<u8>) -> Option<[u8; 4]> {
+    let word: Option<Vec<u8>> = bytes.get(..4)?.iter().map(|&value| byte(value)).collect();
+    word?.try_into().ok()
+}
+
+/// Finds every function that matches a signature, along with the ones that are ambiguous.
+pub fn find_matches(
+    program: &Program, libraries: &[SignatureLibrary],
+) -> (BTreeMap<u32, LibraryMatch>, Vec<LibraryConflict>) {
+    let mut matches = BTreeMap::new();
+    let mut conflicts = Vec::new();
+
+    // Signatures by their first word, so each function is only checked against the few that start the same
+    // way. Ones with a relocation in the first word go under `None` and get checked against everything.
+    let mut index: HashMap<Option<[u8; 4]>, Vec<(usize, usize)>> = HashMap::new();
+    for (library_index, library) in libraries.iter().enumerate() {
+        for (signature_index, signature) in library.signatures.iter().enumerate() {
+            let word = leading_word(&signature.prefix, |byte| byte);
+            index.entry(word).or_default().push((library_index, signature_index));
+        }
+    }
+
+    for (&address, function) in &program.functions {
+        let Some(segment) = program.segment_at(address) else {
+            continue;
+        };
+        let Some(bytes) = program.bytes(address, segment.address + segment.size - address) else {
+            continue;
+        };
+
+        let mut possible: Vec<(usize, usize)> = index.get(&None).into_iter().flatten().copied().collect();
+        if let Some(word) = leading_word(bytes, Some) {
+            possible.extend(index.get(&Some(word)).into_iter().flatten());
+        }
+        // Go through them in library order, so the first library wins between identical matches
+        possible.sort_unstable();
+
+        let mut candidates: Vec<LibraryMatch> = Vec::new();
+        for (library_index, signature_index) in possible {
+            let library = &libraries[library_index];
+            let signature = &library.signatures[signature_index];
+            let Some(verified) = signature.verify(bytes) else {
+                continue;
+            };
+            let mut confidence = (verified as f32 / TRUSTED_LENGTH as f32).min(1.0);
+            // Control flow analysis usually finds exactly the same end, so anything else is suspicious
+            if function.end - function.address != signature.size {
+                confidence *= 0.5;
+            }
+            if confidence >= MIN_CONFIDENCE {
+                candidates.push(LibraryMatch {
+                    name: signature.name.clone(),
+                    library: library.title(),
+                    confidence,
+                });
+            }
+        }
+
+        let Some(best) = candidates.iter().map(|candidate| candidate.confidence).reduce(f32::max) else {
+            continue;
+        };
+        candidates.retain(|candidate| candidate.confidence == best);
+        // The same function showing up in several library versions isn't a conflict, just keep the first
+        let mut names: Vec<&str> = candidates.iter().map(|candidate| candidate.name.as_str()).collect();
+        names.sort_unstable();
+        names.dedup();
+        if names.len() == 1 {
+            matches.insert(address, candidates.swap_remove(0));
+        } else {
+            conflicts.push(LibraryConflict { address, candidates });
+        }
+    }
+    (matches, conflicts)
+}
+
+/// Records library matches on the program, naming every match that doesn't already have a name. Mangled names
+/// replace the prototype analysis inferred with the one they spell out.
+pub fn apply(program: &mut Program, matches: BTreeMap<u32, LibraryMatch>, conflicts: Vec<LibraryConflict>) {
+    let mut used: HashSet<String> = program.names.values().cloned().collect();
+    for (&address, library_match) in &matches {
+        if !program.names.contains_key(&address) && used.insert(library_match.name.clone()) {
+            program.names.insert(address, library_match.name.clone());
+            let inferred = program.types.prototype(address);
+            if let Some(prototype) = prototype::demangled_prototype(program, address, inferred) {
+                program.types.set_prototype(address, Some(prototype));
+            }
+        }
+    }
+    program.library_functions = matches;
+    program.library_conflicts = conflicts;
+}
+
+/// Matches every library against the program and names what was found.
+pub fn match_libraries(program: &mut Program, libraries: &[SignatureLibrary]) {
+    let (matches, conflicts) = find_matches(program, libraries);
+    apply(program, matches, conflicts);
+}
+
+/// Functions smaller than this look the same as too many others to be worth a signature.
+const MIN_FUNCTION_SIZE: u32 = 8;
+
+/// Adds a signature for every global function in an ELF object, or in every object of a `.a` archive. Returns
+/// how many signatures were added.
+pub fn add_object(library: &mut SignatureLibrary, data: &[u8]) -> Result<usize, FerroxError> {
+    // Libraries get built from overlapping sets of objects all the time, so skip anything already there
+    let mut known: HashSet<Signature> = library.signatures.iter().cloned().collect();
+    if !Archive::is_archive(data) {
+        return add_elf(library, &mut known, &ElfObject::parse(data)?);
+    }
+    let mut added = 0;
+    for (_, member) in Archive::members(data)? {
+        // Archives can have other things in them, like the odd text file
+        if ElfObject::is_elf(member) {
+            added += add_elf(library, &mut known, &ElfObject::parse(member)?)?;
+        }
+    }
+    Ok(added)
+}
+
+fn add_elf(
+    library: &mut SignatureLibrary, known: &mut HashSet<Signature>, object: &ElfObject,
+) -> Result<usize, FerroxError> {
+    // Which bytes of each section get patched by the linker
+    let mut holes: Vec<Vec<bool>> = (0..object.sections.len())
+        .map(|section| vec![false; object.section_data(section).map_or(0, <[u8]>::len)])
+        .collect();
+    for relocation in &object.relocations {
+        let Some(holes) = holes.get_mut(relocation.section) else {
+            continue;
+        };
+        let range = relocated_bytes(relocation.kind);
+        for offset in relocation.offset + range.start..relocation.offset + range.end {
+            if let Some(hole) = holes.get_mut(offset as usize) {
+                *hole = true;
+            }
+        }
+    }
+
+    let mut added = 0;
+    for symbol in &object.symbols {
+        if symbol.kind != SymbolKind::Function
+            || !matches!(symbol.binding, SymbolBinding::Global | SymbolBinding::Weak)
+            || symbol.section == SHN_UNDEF
+            || symbol.size < MIN_FUNCTION_SIZE
+        {
+            continue;
+        }
+        let section = symbol.section as usize;
+        let Some(data) = object.section_data(section) else {
+            continue;
+        };
+        if !object.sections[section].permissions.contains(Permissions::EXECUTE) {
+            continue;
+        }
+        let range = symbol.value as usize..(symbol.value + symbol.size) as usize;
+        let (Some(bytes), Some(holes)) = (data.get(range.clone()), holes[section].get(range)) else {
+            return InvalidElfSnafu {
+                reason: format!("\"{}\" runs past the end of its section", symbol.name),
+            }
+            .fail();
+        };
+        let masked: Vec<Option<u8>> =
+            bytes.iter().zip(holes).map(|(&byte, &hole)| (!hole).then_some(byte)).collect();
+        let signature = Signature::new(symbol.name.clone(), &masked);
+        if known.insert(signature.clone()) {
+            library.signatures.push(signature);
+            added += 1;
+        }
+    }
+    Ok(added)
+}
+
+/// Bytes (relative to the relocation's offset) that a relocation type overwrites.
+fn relocated_bytes(kind: u8) -> Range<u32> {
+    match kind {
+        // R_PPC_ADDR16, R_PPC_ADDR16_LO, R_PPC_ADDR16_HI, R_PPC_ADDR16_HA, which point at the halfword
+        3..=6 => 0..2,
+        // R_PPC_ADDR14*, R_PPC_REL14*, only the displacement at the bottom of the instruction
+        7..=9 | 11..=13 => 2..4,
+        // R_PPC_EMB_SDA21, which also sets the base register
+        109 => 1..4,
+        // R_PPC_ADDR32, R_PPC_ADDR24, R_PPC_REL24, R_PPC_REL32, and whatever else shows up
+        _ => 0..4,
+    }
+}
+
+/// CRC-16/X-25, the same one FLIRT uses.
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in bytes {
+        let mut byte = byte;
+        for _ in 0..8 {
+            crc = match (crc ^ byte as u16) & 1 {
+                0 => crc >> 1,
+                _ => (crc >> 1) ^ 0x8408,
+            };
+            byte >>= 1;
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, TEXT};
+
+    /// A 64 byte function at [`TEXT`] followed by a short one.
+    const SOURCE: &str = "
+        stwu r1, -0x20(r1)
+        mflr r0
+        stw r0, 0x24(r1)
+        stw r31, 0x1C(r1)
+        mr r31, r3
+        li r4, 0
+        li r5, 1
+        add r3, r4, r5
+        addi r3, r3, 2
+        mulli r3, r3, 3
+        stw r3, 0(r31)
+        lwz r0, 0x24(r1)
+        lwz r31, 0x1C(r1)
+        mtlr r0
+        addi r1, r1, 0x20
+        blr
+    other:
+        li r3, 0
+        blr
+    ";
+
+    /// Signature of `length` bytes from `address`, with holes at the given offsets.
+    fn signature(program: &Program, name: &str, address: u32, length: u32, holes: &[usize]) -> Signature {
+        let mut bytes: Vec<Option<u8>> =
+            program.bytes(address, length).unwrap().iter().copied().map(Some).collect();
+        for &hole in holes {
+            bytes[hole] = None;
+        }
+        Signature::new(name.to_owned(), &bytes)
+    }
+
+    fn library(name: &str, signatures: Vec<Signature>) -> SignatureLibrary {
+        SignatureLibrary { name: name.to_owned(), version: "1.0".to_owned(), signatures }
+    }
+
+    #[test]
+    fn computes_crc16_x25() {
+        assert_eq!(crc16(b"123456789"), 0x906E);
+        assert_eq!(crc16(&[]), 0x0000);
+    }
+
+    #[test]
+    fn verifies_around_holes() {
+        let program = testing::program(SOURCE, &[]);
+        let signature = signature(&program, "OSInit", TEXT, 0x40, &[2, 3, 0x22, 0x23]);
+        // The prefix is the first 32 bytes, and the CRC stops at the first hole after it
+        assert_eq!(signature.prefix.len(), PREFIX_LENGTH);
+        assert_eq!(signature.crc_length, 2);
+
+        let mut bytes = program.bytes(TEXT, 0x40).unwrap().to_vec();
+        assert_eq!(signature.verify(&bytes), Some(32));
+        bytes[2] ^= 0xFF;
+        assert_eq!(signature.verify(&bytes), Some(32), "holes aren't compared");
+        bytes[0x21] ^= 0xFF;
+        assert_eq!(
+            signature.verify(&bytes),
+            None,
+            "the CRC covers the bytes after the prefix"
+        );
+        assert_eq!(signature.verify(&bytes[..0x20]), None, "the function has to fit");
+    }
+
+    #[test]
+    fn round_trips_signature_files() {
+        let program = testing::program(SOURCE, &[]);
+        let original = library(
+            "Dolphin SDK",
+            vec![
+                signature(&program, "OSInit", TEXT, 0x40, &[2, 3, 31]),
+                signature(&program, "__ct__Q23foo3BarFv", TEXT + 0x40, 8, &[]),
+            ],
+        );
+        let data = original.write().unwrap();
+        let read = SignatureLibrary::read(&data).unwrap();
+        assert_eq!(read.title(), "Dolphin SDK 1.0");
+        assert_eq!(read.signatures, original.signatures);
+
+        assert!(SignatureLibrary::read(b"FRXT").is_err());
+        let mut version = data.clone();
+        version[7] = 2;
+        assert!(SignatureLibrary::read(&version).is_err());
+        assert!(SignatureLibrary::read(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn matches_functions_by_their_signatures() {
+        let program = testing::program(SOURCE, &[]);
+        let libraries = [
+            library(
+                "Dolphin SDK",
+                vec![
+                    // A relocation in the first word, so it can't be looked up by it
+                    signature(&program, "OSInit", TEXT, 0x40, &[2, 3]),
+                    // Matches as far as it goes, but it's the wrong size
+                    signature(&program, "OSInitShort", TEXT, 0x38, &[]),
+                    signature(&program, "OSOther", TEXT + 4, 0x3C, &[]),
+                ],
+            ),
+            library("MSL_C", vec![signature(&program, "OSInit", TEXT, 0x40, &[])]),
+        ];
+
+        let (matches, conflicts) = find_matches(&program, &libraries);
+        assert!(conflicts.is_empty(), "{conflicts:?}");
+        assert_eq!(matches.len(), 1, "{matches:?}");
+        // The same function in two libraries isn't ambiguous, and the first library wins
+        let found = &matches[&TEXT];
+        assert_eq!(
+            (found.name.as_str(), found.library.as_str()),
+            ("OSInit", "Dolphin SDK 1.0")
+        );
+        assert_eq!(found.confidence, 1.0);
+    }
+
+    #[test]
+    fn names_matched_functions() {
+        let libraries = [library(
+            "Dolphin SDK",
+            vec![signature(
+                &testing::program(SOURCE, &[]),
+                "OSInit",
+                TEXT,
+                0x40,
+                &[],
+            )],
+        )];
+
+        // Names that are already there are kept, like the one the loader gives the entry point
+        let mut program = testing::program(SOURCE, &[]);
+        match_libraries(&mut program, &libraries);
+        assert_eq!(program.names[&TEXT], "__start");
+        assert!(
+            program.library_functions.contains_key(&TEXT),
+            "matches are still flagged"
+        );
+
+        let mut program = testing::program(SOURCE, &[]);
+        program.names.remove(&TEXT);
+        match_libraries(&mut program, &libraries);
+        assert_eq!(program.names[&TEXT], "OSInit");
+        assert_eq!(program.library_functions[&TEXT].library, "Dolphin SDK 1.0");
+
+        // A name that's already taken isn't used twice
+        let mut program = testing::program(SOURCE, &[]);
+        program.names.remove(&TEXT);
+        program.names.insert(TEXT + 0x40, "OSInit".to_owned());
+        match_libraries(&mut program, &libraries);
+        assert!(!program.names.contains_key(&TEXT));
+    }
+
+    #[test]
+    fn leaves_ambiguous_matches_alone() {
+        let mut program = testing::program(SOURCE, &[]);
+        let libraries = [
+            library(
+                "Dolphin SDK",
+                vec![signature(&program, "OSInit", TEXT, 0x40, &[])],
+            ),
+            library("MSL_C", vec![signature(&program, "__init_data", TEXT, 0x40, &[])]),
+        ];
+        program.names.remove(&TEXT);
+        match_libraries(&mut program, &libraries);
+        assert!(!program.names.contains_key(&TEXT));
+        assert!(program.library_functions.is_empty());
+        let [conflict] = program.library_conflicts.as_slice() else {
+            panic!("{:?}", program.library_conflicts);
+        };
+        assert_eq!(conflict.address, TEXT);
+        let candidates: Vec<&str> =
+            conflict.candidates.iter().map(|candidate| candidate.name.as_str()).collect();
+        assert_eq!(candidates, ["OSInit", "__init_data"]);
+    }
+}
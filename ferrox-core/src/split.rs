@@ -0,0 +1,231 @@
+//! Splits, which say which translation unit each part of a binary came from. They're read from the
+//! `splits.txt` files decomp projects already keep:
+//!
+//! ```text
+//! Sections:
+//!     .text       type:code align:4
+//!
+//! os/OSInit.c:
+//!     .text       start:0x800F1234 end:0x800F1500
+//!     .sdata      start:0x80412340 end:0x80412348
+//! ```
+use snafu::{ensure, OptionExt};
+
+use crate::analysis::data::small_data_pointers;
+use crate::error::{FerroxError, InvalidSplitsSnafu};
+use crate::format::Permissions;
+use crate::program::Program;
+
+/// One section's worth of a translation unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SplitRange {
+    /// Section name including the leading `.`, e.g. `.text`
+    pub section: String,
+    pub start: u32,
+    /// Address one past the end of the range
+    pub end: u32,
+}
+
+/// Everything that came from one translation unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Split {
+    /// Path of the source file, e.g. `os/OSInit.c`
+    pub name: String,
+    pub ranges: Vec<SplitRange>,
+}
+
+impl Split {
+    /// Name for a file generated from this split, with the source file's extension replaced.
+    pub fn file_name(&self, extension: &str) -> String {
+        let stem = match self.name.rsplit_once('.') {
+            Some((stem, _)) if !stem.is_empty() && !stem.ends_with('/') => stem,
+            _ => &self.name,
+        };
+        format!("{stem}.{extension}")
+    }
+}
+
+/// Reads a splits file. The `Sections:` block and any attributes other than `start` and `end` are skipped,
+/// since sections are recognized by name.
+pub fn parse(text: &str) -> Result<Vec<Split>, FerroxError> {
+    let mut splits: Vec<Split> = Vec::new();
+    let mut in_unit = false;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.split("//").next().unwrap_or_default();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let error = |reason: String| InvalidSplitsSnafu { line: number + 1, reason };
+
+        if !line.starts_with(char::is_whitespace) {
+            let name =
+                line.trim().strip_suffix(':').context(error("expected a file name ending in ':'".into()))?;
+            in_unit = name != "Sections";
+            if in_unit {
+                // Names become paths under the output folder, so they mustn't be able to lead out of it
+                let relative = name
+                    .split('/')
+                    .all(|part| !matches!(part, "" | "." | "..") && !part.contains(['\\', ':']));
+                ensure!(
+                    relative,
+                    error(format!(
+                        "\"{name}\" has to be a relative path with '/' between folders"
+                    ))
+                );
+                splits.push(Split { name: name.to_owned(), ranges: Vec::new() });
+            }
+            continue;
+        }
+        if !in_unit {
+            continue;
+        }
+
+        let mut columns = line.split_whitespace();
+        let section = columns.next().unwrap_or_default().to_owned();
+        let (mut start, mut end) = (None, None);
+        for column in columns {
+            let Some((key, value)) = column.split_once(':') else {
+                continue;
+            };
+            let value = || {
+                let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+                u32::from_str_radix(digits, 16).ok().context(error(format!("\"{value}\" isn't an address")))
+            };
+            match key {
+                "start" => start = Some(value()?),
+                "end" => end = Some(value()?),
+                _ => (),
+            }
+        }
+        let (Some(start), Some(end)) = (start, end) else {
+            return error(format!("{section} needs a start and an end")).fail();
+        };
+        ensure!(start <= end, error(format!("{section} ends before it starts")));
+        splits.last_mut().unwrap().ranges.push(SplitRange { section, start, end });
+    }
+    Ok(splits)
+}
+
+/// Guesses the usual name of the section each segment was linked from, in segment order. DOLs don't keep
+/// section names, so this goes by what the segments hold: `.init` is the first of several code segments, and
+/// the small data sections are the ones `r13` and `r2` point into.
+pub fn section_names(program: &Program) -> Vec<String> {
+    let (sda, sda2) = small_data_pointers(program);
+    let mut code = 0;
+    let code_segments =
+        program.segments.iter().filter(|segment| segment.permissions.contains(Permissions::EXECUTE)).count();
+    program
+        .segments
+        .iter()
+        .map(|segment| {
+            // The bases point 0x8000 past the start of their section
+            let near = |base: Option<u32>| {
+                base.is_some_and(|base| segment.contains(base.wrapping_sub(0x8000)) || segment.contains(base))
+            };
+            if segment.permissions.contains(Permissions::EXECUTE) {
+                code += 1;
+                return match (code_segments, code) {
+                    (1, _) => ".text".to_owned(),
+                    (_, 1) => ".init".to_owned(),
+                    (_, 2) => ".text".to_owned(),
+                    (_, index) => format!(".text{}", index - 1),
+                };
+            }
+            let name = if segment.permissions.contains(Permissions::UNINITIALIZED) {
+                ".bss"
+            } else if near(sda) {
+                ".sdata"
+            } else if near(sda2) {
+                ".sdata2"
+            } else if !segment.permissions.contains(Permissions::WRITE) {
+                ".rodata"
+            } else {
+                ".data"
+            };
+            name.to_owned()
+        })
+        .collect()
+}
+
+/// One range per segment covering the whole program, for exporting without a splits file.
+pub fn whole_program(program: &Program) -> Vec<SplitRange> {
+    program
+        .segments
+        .iter()
+        .zip(section_names(program))
+        .map(|(segment, section)| SplitRange {
+            section,
+            start: segment.address,
+            end: segment.address + segment.size,
+        })
+        .collect()
+}
+
+/// Ranges covering `start..end`, cut wherever it crosses from one segment into another.
+pub fn ranges(program: &Program, start: u32, end: u32) -> Vec<SplitRange> {
+    program
+        .segments
+        .iter()
+        .zip(section_names(program))
+        .filter(|(segment, _)| segment.address < end && start < segment.address + segment.size)
+        .map(|(segment, section)| SplitRange {
+            section,
+            start: start.max(segment.address),
+            end: end.min(segment.address + segment.size),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_units() {
+        let splits = parse(
+            "Sections:
+    .text       type:code align:4
+
+os/OSInit.c: // comment
+    .text       start:0x800F1234 end:0x800F1500
+    .sdata      start:80412340 end:80412348
+main.cpp:
+",
+        )
+        .unwrap();
+        assert_eq!(splits.len(), 2);
+        assert_eq!(splits[0].name, "os/OSInit.c");
+        assert_eq!(
+            splits[0].ranges[1],
+            SplitRange { section: ".sdata".into(), start: 0x8041_2340, end: 0x8041_2348 }
+        );
+        assert_eq!(splits[0].file_name("s"), "os/OSInit.s");
+        assert_eq!(splits[1].file_name("o"), "main.o");
+    }
+
+    #[test]
+    fn rejects_names_outside_the_output_folder() {
+        for name in [
+            "/etc/passwd",
+            "../main.c",
+            "os/../../main.c",
+            "./main.c",
+            "os//main.c",
+            "os\\main.c",
+            "C:main.c",
+        ] {
+            let error = parse(&format!("{name}:\n    .text start:0x80003100 end:0x80003200\n")).unwrap_err();
+            assert!(
+                matches!(error, FerroxError::InvalidSplits { line: 1, .. }),
+                "{name}: {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_bad_ranges() {
+        assert!(parse("main.c:\n    .text start:0x80003100\n").is_err());
+        assert!(parse("main.c:\n    .text start:0x80003200 end:0x80003100\n").is_err());
+        assert!(parse("main.c:\n    .text start:nowhere end:0x80003100\n").is_err());
+    }
+}
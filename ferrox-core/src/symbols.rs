@@ -0,0 +1,119 @@
+//! Symbols for writing code back out: a label for every function, named address, relocation target and
+//! pointer table, so that every recovered relocation has something to point at.
+use std::collections::BTreeMap;
+
+use crate::analysis::relocation::{self, Relocation, RelocationKind};
+use crate::program::Program;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LabelKind {
+    Function,
+    /// Anything else that other files may refer to
+    Global,
+    /// Branch target that's only used from inside its own function
+    Local,
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub name: String,
+    pub kind: LabelKind,
+}
+
+/// Every relocation in a program along with the labels they refer to. Building it means looking at the whole
+/// program, so exporters do it once up front and share it between every file they write.
+pub struct SymbolTable {
+    /// Keyed by the address holding the relocation
+    pub relocations: BTreeMap<u32, Relocation>,
+    pub labels: BTreeMap<u32, Label>,
+}
+
+impl SymbolTable {
+    pub fn new(program: &Program) -> Self {
+        let relocations = relocation::find(program);
+        let mut labels = BTreeMap::new();
+        for &address in program.functions.keys() {
+            let name = program.raw_name(address);
+            labels.insert(address, Label { name, kind: LabelKind::Function });
+        }
+        for (&address, name) in &program.names {
+            labels.entry(address).or_insert(Label { name: name.clone(), kind: LabelKind::Global });
+        }
+
+        // Everything referenced gets a label, unless it's inside an item, which is referenced by offset instead
+        for (&from, relocation) in &relocations {
+            let target = relocation.target;
+            if labels.contains_key(&target)
+                || program
+                    .types
+                    .item_containing(target.into())
+                    .is_some_and(|(start, _, _)| start != target.into())
+            {
+                continue;
+            }
+            let function = program.function_containing(target);
+            let kind = match function.filter(|function| function.address <= from && from < function.end) {
+                Some(_) if program.is_code(target) => LabelKind::Local,
+                _ => LabelKind::Global,
+            };
+            let name = match kind {
+                LabelKind::Local => format!(".L_{target:08X}"),
+                _ => format!("lbl_{target:08X}"),
+            };
+            labels.insert(target, Label { name, kind });
+        }
+
+        // Pointer tables nothing refers to directly still get their own label, rather than becoming part of
+        // whatever comes before them
+        for (&from, relocation) in &relocations {
+            let continues_table = from
+                .checked_sub(4)
+                .and_then(|previous| relocations.get(&previous))
+                .is_some_and(|previous| previous.kind == RelocationKind::Addr32);
+            if relocation.kind != RelocationKind::Addr32
+                || continues_table
+                || labels.contains_key(&from)
+                || program
+                    .types
+                    .item_containing(from.into())
+                    .is_some_and(|(start, _, _)| start != from.into())
+            {
+                continue;
+            }
+            labels.insert(
+                from,
+                Label { name: format!("lbl_{from:08X}"), kind: LabelKind::Global },
+            );
+        }
+
+        // A label that's local to one function has to be global if anything else refers to it
+        for (&from, relocation) in &relocations {
+            if let Some(label) =
+                labels.get_mut(&relocation.target).filter(|label| label.kind == LabelKind::Local)
+            {
+                let same_function = program
+                    .function_containing(relocation.target)
+                    .is_some_and(|function| function.address <= from && from < function.end);
+                if !same_function {
+                    label.name = format!("lbl_{:08X}", relocation.target);
+                    label.kind = LabelKind::Global;
+                }
+            }
+        }
+
+        Self { relocations, labels }
+    }
+
+    /// Finds the label an address should be referred to by, along with the offset from it. Local labels are
+    /// only used for exact matches, since they can't be seen from other functions.
+    pub fn resolve(&self, address: u32) -> Option<(&Label, u32)> {
+        if let Some(label) = self.labels.get(&address) {
+            return Some((label, 0));
+        }
+        self.labels
+            .range(..address)
+            .rev()
+            .find(|(_, label)| label.kind != LabelKind::Local)
+            .map(|(start, label)| (label, address - start))
+    }
+}
@@ -0,0 +1,103 @@
+//! Helpers for building small programs in tests, written as assembly rather than raw words.
+use std::collections::BTreeMap;
+
+use crate::format::BinaryFormat;
+use crate::processor::assembler;
+use crate::program::Program;
+
+/// Where [`dol`] puts the code.
+pub(crate) const TEXT: u32 = 0x8000_3100;
+/// Where [`dol`] puts the data.
+pub(crate) const DATA: u32 = 0x8000_4000;
+
+/// Assembles one instruction per line starting at `start`. Lines ending in `:` are labels, which any
+/// operand can refer to, and `#` starts a comment.
+pub(crate) fn assemble(source: &str, start: u32) -> Vec<u32> {
+    let lines: Vec<&str> = source
+        .lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+    let mut labels = BTreeMap::new();
+    let mut address = start;
+    for line in &lines {
+        match line.strip_suffix(':') {
+            Some(label) => _ = labels.insert(label.to_owned(), address),
+            None => address += 4,
+        }
+    }
+
+    let mut address = start;
+    let mut code = Vec::new();
+    for line in lines.into_iter().filter(|line| !line.ends_with(':')) {
+        let word = assembler::assemble(address, line, |name| labels.get(name).copied())
+            .unwrap_or_else(|error| panic!("{line}: {error}"));
+        code.push(word);
+        address += 4;
+    }
+    code
+}
+
+/// Builds a DOL with `code` in text0 at [`TEXT`], `data` in data0 at [`DATA`] and `bss` bytes of BSS after it.
+/// Execution starts at the first instruction.
+pub(crate) fn dol(code: &[u32], data: &[u8], bss: u32) -> Vec<u8> {
+    let text_size = code.len() as u32 * 4;
+    let data_offset = (0x100 + text_size).next_multiple_of(0x20);
+    let mut file = vec![
+        0;
+        if data.is_empty() {
+            0x100 + text_size
+        } else {
+            data_offset
+        } as usize
+    ];
+    let mut word = |at: usize, value: u32| file[at..at + 4].copy_from_slice(&value.to_be_bytes());
+    word(0x00, 0x100);
+    word(0x48, TEXT);
+    word(0x90, text_size);
+    if !data.is_empty() {
+        word(0x1C, data_offset);
+        word(0x64, DATA);
+        word(0xAC, data.len() as u32);
+    }
+    if bss > 0 {
+        word(0xD8, DATA + (data.len() as u32).next_multiple_of(0x20));
+        word(0xDC, bss);
+    }
+    word(0xE0, TEXT);
+    for (n, instruction) in code.iter().enumerate() {
+        file[0x100 + n * 4..0x104 + n * 4].copy_from_slice(&instruction.to_be_bytes());
+    }
+    file.extend_from_slice(data);
+    file
+}
+
+/// Builds a file system table from `(is_directory, name, offset or parent, size or end)`, after the root
+/// folder.
+pub(crate) fn fst(entries: &[(bool, &str, u32, u32)]) -> Vec<u8> {
+    let count = entries.len() as u32 + 1;
+    let mut table = Vec::new();
+    let mut strings = vec![0];
+    let mut entry = |is_directory: bool, name: u32, first: u32, second: u32| {
+        table.extend_from_slice(&((u32::from(is_directory) << 24) | name).to_be_bytes());
+        table.extend_from_slice(&first.to_be_bytes());
+        table.extend_from_slice(&second.to_be_bytes());
+    };
+    entry(true, 0, 0, count);
+    for &(is_directory, name, first, second) in entries {
+        entry(is_directory, strings.len() as u32, first, second);
+        strings.extend_from_slice(name.as_bytes());
+        strings.push(0);
+    }
+    table.extend_from_slice(&strings);
+    table
+}
+
+/// Loads and analyzes a DOL holding `source` and `data`.
+pub(crate) fn program(source: &str, data: &[u8]) -> Program {
+    let file = dol(&assemble(source, TEXT), data, 0);
+    let mut program = Program::load("test.dol".into(), file, BinaryFormat::GameCubeDOL).unwrap();
+    program.validate().unwrap();
+    crate::analysis::analyze(&mut program);
+    program
+}
@@ -0,0 +1,140 @@
+//! Text encodings found in GameCube binaries, most games from Japanese developers use Shift-JIS, and parsing
+//! the numbers people type in.
+use encoding_rs::SHIFT_JIS;
+use snafu::ensure;
+
+use crate::error::{FerroxError, InvalidSearchSnafu};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextEncoding {
+    #[default]
+    Ascii,
+    ShiftJis,
+    Utf16Be,
+}
+
+impl TextEncoding {
+    pub const ALL: [Self; 3] = [Self::Ascii, Self::ShiftJis, Self::Utf16Be];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Ascii => "ASCII",
+            Self::ShiftJis => "Shift-JIS",
+            Self::Utf16Be => "UTF-16BE",
+        }
+    }
+
+    /// Converts text into the bytes it would be stored as.
+    pub fn encode(self, text: &str) -> Result<Vec<u8>, FerroxError> {
+        match self {
+            Self::Ascii => {
+                ensure!(text.is_ascii(), InvalidSearchSnafu { reason: "text isn't ASCII" });
+                Ok(text.as_bytes().to_vec())
+            }
+            Self::ShiftJis => {
+                let (bytes, _, had_errors) = SHIFT_JIS.encode(text);
+                ensure!(
+                    !had_errors,
+                    InvalidSearchSnafu { reason: "text can't be represented in Shift-JIS" }
+                );
+                Ok(bytes.into_owned())
+            }
+            Self::Utf16Be => Ok(text.encode_utf16().flat_map(u16::to_be_bytes).collect()),
+        }
+    }
+
+    /// Decodes text, failing if any of it isn't valid in this encoding.
+    pub fn decode(self, bytes: &[u8]) -> Option<String> {
+        match self {
+            Self::Ascii => bytes.is_ascii().then(|| bytes.iter().map(|&byte| byte as char).collect()),
+            Self::ShiftJis => SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(bytes)
+                .map(|text| text.into_owned()),
+            Self::Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    return None;
+                }
+                let units = bytes.chunks(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+                char::decode_utf16(units).collect::<Result<String, _>>().ok()
+            }
+        }
+    }
+}
+
+/// Escapes text so it can be shown inside of double quotes, e.g. in a `.string` directive.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02X}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Shows raw bytes with everything outside of printable ASCII escaped, e.g. `\x93\xFA`.
+pub fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'"' => escaped.push_str("\\\""),
+            b'\\' => escaped.push_str("\\\\"),
+            byte if byte.is_ascii_graphic() || byte == b' ' => escaped.push(byte as char),
+            byte => escaped.push_str(&format!("\\x{byte:02X}")),
+        }
+    }
+    escaped
+}
+
+/// Parses a decimal or `0x` prefixed hex number, with an optional sign, e.g. `-0x10` or `+42`.
+pub fn parse_number(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits.trim_start()),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = match digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        Some(hex) if hex.starts_with(|c: char| c.is_ascii_hexdigit()) => i64::from_str_radix(hex, 16).ok()?,
+        None if digits.starts_with(|c: char| c.is_ascii_digit()) => digits.parse().ok()?,
+        _ => return None,
+    };
+    Some(if negative { -value } else { value })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers() {
+        assert_eq!(parse_number("42"), Some(42));
+        assert_eq!(parse_number(" 0x1F "), Some(0x1F));
+        assert_eq!(parse_number("0XfF"), Some(0xFF));
+        assert_eq!(parse_number("-0x8000"), Some(-0x8000));
+        assert_eq!(parse_number("- 5"), Some(-5));
+        assert_eq!(parse_number("+7"), Some(7));
+    }
+
+    #[test]
+    fn rejects_what_isnt_a_number() {
+        for text in [
+            "",
+            "-",
+            "0x",
+            "0x+5",
+            "+-5",
+            "--5",
+            "r3",
+            "1.5",
+            "0xFFFFFFFFFFFFFFFFF",
+        ] {
+            assert_eq!(parse_number(text), None, "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,654 @@
+//! Headless mode, for scripts and build servers. Every subcommand uses the same loaders and analysis as the
+//! window does, and anything meant to be read by another program is printed as JSON. Failures are printed to
+//! stderr with a non-zero exit code.
+use std::borrow::Cow;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use snafu::{ensure, OptionExt, ResultExt};
+
+use ferrox_core::asm::AsmExporter;
+use ferrox_core::error::{FerroxError, FileSnafu, ValidationSnafu};
+use ferrox_core::format::compression::{self, Compression};
+use ferrox_core::format::disc::Disc;
+use ferrox_core::format::rarc::RarcArchive;
+use ferrox_core::format::u8_archive::U8Archive;
+use ferrox_core::format::{BinaryFormat, Entry};
+use ferrox_core::object::ObjectExporter;
+use ferrox_core::program::Program;
+use ferrox_core::registry::TypeInfo;
+use ferrox_core::signature::{self, SignatureLibrary};
+use ferrox_core::split::Split;
+use ferrox_core::{analysis, database, decompile, ir, listing, map, navigation, patch, split};
+
+#[derive(Parser)]
+#[command(name = "ferrox", version, about = "Decompilation-Oriented Disassembler.")]
+pub struct Cli {
+    /// Runs without opening a window
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// What a binary should be loaded as, databases are detected automatically.
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum InputFormat {
+    #[default]
+    Dol,
+    Rel,
+    Binary,
+}
+
+impl From<InputFormat> for BinaryFormat {
+    fn from(format: InputFormat) -> Self {
+        match format {
+            InputFormat::Dol => BinaryFormat::GameCubeDOL,
+            InputFormat::Rel => BinaryFormat::GameCubeREL,
+            InputFormat::Binary => BinaryFormat::BinaryFile,
+        }
+    }
+}
+
+/// What to export a database's patches as.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum PatchFormat {
+    /// Gecko/Ocarina codes, as Dolphin lists them
+    Gecko,
+    /// IPS patch against the original file
+    Ips,
+    /// The whole DOL with the patches applied
+    Dol,
+}
+
+/// How far along to take a function before printing its IR.
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+pub enum IrStage {
+    /// Straight out of the lifter
+    Lifted,
+    /// In SSA form, without any clean up
+    Ssa,
+    /// In SSA form, with constants folded and dead code removed
+    #[default]
+    Optimized,
+}
+
+/// Options shared by everything that imports a binary.
+#[derive(Debug, clap::Args)]
+pub struct Input {
+    /// Binary or .frx database to load
+    pub input: PathBuf,
+    #[arg(long, value_enum, default_value_t)]
+    pub format: InputFormat,
+    /// Loads this file out of the input disc image or archive instead, like `sys/main.dol` or
+    /// `files/RELS.arc/rels/a.rel`, going into archives on the way. Its format is picked from its extension
+    #[arg(long)]
+    pub disc_file: Option<String>,
+    /// Symbol map to name things from
+    #[arg(long)]
+    pub map: Option<PathBuf>,
+    /// Extra folders to load .sig files from, on top of the default ones
+    #[arg(long = "signatures")]
+    pub signatures: Vec<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Imports and analyzes a binary, printing a summary and optionally saving a database
+    Analyze {
+        #[command(flatten)]
+        input: Input,
+        /// Where to save the database
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Writes out assembly that can be built back into the same bytes, for the whole program or part of it
+    ExportAsm {
+        #[command(flatten)]
+        input: Input,
+        /// Where to write the assembly, defaults to stdout. With --splits, the folder to write each file to
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Only export the function containing this address or symbol
+        #[arg(long, conflicts_with_all = ["start", "splits"])]
+        function: Option<String>,
+        /// Only export from this address or symbol
+        #[arg(long, requires = "end", conflicts_with = "splits")]
+        start: Option<String>,
+        /// Only export up to this address or symbol
+        #[arg(long, requires = "start")]
+        end: Option<String>,
+        /// Splits file to write one .s per translation unit from
+        #[arg(long, requires = "output")]
+        splits: Option<PathBuf>,
+        /// Write the listing as it's shown in the window instead, which won't assemble
+        #[arg(long, conflicts_with_all = ["function", "start", "splits"])]
+        listing: bool,
+    },
+    /// Prints the intermediate representation the decompiler works on for a single function
+    Lift {
+        #[command(flatten)]
+        input: Input,
+        /// Function to lift, by address or symbol
+        #[arg(long)]
+        function: String,
+        #[arg(long, value_enum, default_value_t)]
+        stage: IrStage,
+        /// Where to write the IR, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Prints a function as C pseudocode
+    Decompile {
+        #[command(flatten)]
+        input: Input,
+        /// Function to decompile, by address or symbol
+        #[arg(long)]
+        function: String,
+        /// Where to write the pseudocode, defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Splits the binary into one relocatable ELF object per translation unit
+    Split {
+        #[command(flatten)]
+        input: Input,
+        /// Splits file saying which ranges belong to which translation unit
+        #[arg(long)]
+        splits: PathBuf,
+        /// Folder to write the objects to
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Lists every file in a GameCube disc image
+    ListDisc { input: PathBuf },
+    /// Lists the segments of a binary without analyzing it
+    DumpSegments {
+        input: PathBuf,
+        #[arg(long, value_enum, default_value_t)]
+        format: InputFormat,
+    },
+    /// Writes the segments of a binary or database back out as a DOL, reporting whether it's identical to the
+    /// file it was loaded from
+    RebuildDol {
+        input: PathBuf,
+        #[arg(long, value_enum, default_value_t)]
+        format: InputFormat,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Exports the patches made in a database, so they can be shared without it
+    ExportPatches {
+        /// Database holding the patches
+        input: PathBuf,
+        #[arg(long = "as", value_enum)]
+        format: PatchFormat,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Writes every function and named piece of data out as a symbol map Dolphin's debugger can load
+    ExportMap {
+        #[command(flatten)]
+        input: Input,
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Lists every named address and function
+    Symbols {
+        #[command(flatten)]
+        input: Input,
+    },
+    /// Builds a signature file from ELF objects and .a archives
+    MakeSignatures {
+        /// Objects and archives to build signatures from
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Name of the library, e.g. "Dolphin SDK"
+        #[arg(long)]
+        name: String,
+        /// Which version of the library this is, e.g. "2001-08"
+        #[arg(long = "library-version", default_value = "")]
+        version: String,
+        /// Where to write the .sig file
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Serialize)]
+struct Summary {
+    path: PathBuf,
+    format: String,
+    /// What the file was compressed with, if it was
+    compression: Option<&'static str>,
+    segments: usize,
+    functions: usize,
+    names: usize,
+    strings: usize,
+    library_functions: usize,
+    library_conflicts: usize,
+    map_names: usize,
+    output: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct SegmentInfo {
+    name: String,
+    address: u32,
+    size: u32,
+    offset: u32,
+    permissions: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+struct SymbolInfo {
+    address: u32,
+    name: String,
+    /// Full signature, for mangled C++ names
+    demangled: Option<String>,
+    kind: &'static str,
+    /// Size of the function, for functions
+    size: Option<u32>,
+    /// Library the function was matched to
+    library: Option<String>,
+}
+
+#[derive(Serialize)]
+struct DiscListing {
+    game_code: String,
+    maker_code: String,
+    title: String,
+    disc_number: u8,
+    version: u8,
+    apploader_date: String,
+    /// Anything wrong with the image, like a `main.dol` that's missing
+    warnings: Vec<String>,
+    files: Vec<DiscFile>,
+}
+
+#[derive(Serialize)]
+struct DiscFile {
+    path: String,
+    offset: u32,
+    size: u32,
+}
+
+#[derive(Serialize)]
+struct RebuildSummary {
+    size: usize,
+    /// Whether the rebuilt file matches the one it was loaded from byte for byte
+    identical: bool,
+    output: PathBuf,
+}
+
+#[derive(Serialize)]
+struct PatchSummary {
+    patches: usize,
+    output: PathBuf,
+}
+
+#[derive(Serialize)]
+struct MapSummary {
+    functions: usize,
+    names: usize,
+    output: PathBuf,
+}
+
+#[derive(Serialize)]
+struct SplitSummary {
+    objects: usize,
+    output: PathBuf,
+}
+
+#[derive(Serialize)]
+struct SignatureSummary {
+    library: String,
+    signatures: usize,
+    output: PathBuf,
+}
+
+/// Runs a subcommand, returning the exit code.
+pub fn run(command: Command) -> i32 {
+    match execute(command) {
+        Ok(()) => 0,
+        Err(error) => {
+            eprintln!("error: {error}");
+            1
+        }
+    }
+}
+
+fn execute(command: Command) -> Result<(), FerroxError> {
+    match command {
+        Command::Analyze { input, output } => {
+            let (program, map_names) = open(&input)?;
+            if let Some(output) = &output {
+                write(output, &database::save(&program)?)?;
+            }
+            print_json(&Summary {
+                path: program.path.clone(),
+                format: format!("{:?}", program.format),
+                compression: program.compression.map(Compression::name),
+                segments: program.segments.len(),
+                functions: program.functions.len(),
+                names: program.names.len(),
+                strings: program
+                    .types
+                    .items()
+                    .filter(|(_, _, type_info)| matches!(type_info, TypeInfo::String { .. }))
+                    .count(),
+                library_functions: program.library_functions.len(),
+                library_conflicts: program.library_conflicts.len(),
+                map_names,
+                output,
+            })
+        }
+        Command::ExportAsm { input, output, function, start, end, splits, listing } => {
+            let (program, _) = open(&input)?;
+            if listing {
+                return output_text(output, &listing::export(&program));
+            }
+            let exporter = AsmExporter::new(&program);
+            let ranges = match (function, start.zip(end)) {
+                (Some(function), _) => {
+                    let address = navigation::resolve(&program, &function)?;
+                    let function = program.function_containing(address).context(ValidationSnafu {
+                        reason: format!("0x{address:08X} isn't in a function"),
+                    })?;
+                    split::ranges(&program, function.address, function.end)
+                }
+                (None, Some((start, end))) => split::ranges(
+                    &program,
+                    navigation::resolve(&program, &start)?,
+                    navigation::resolve(&program, &end)?,
+                ),
+                (None, None) => split::whole_program(&program),
+            };
+
+            if let Some(path) = splits {
+                // Checked by clap, splits always come with a folder to write to
+                let directory = output.unwrap();
+                for unit in read_splits(&path)? {
+                    write_creating(
+                        &directory.join(unit.file_name("s")),
+                        exporter.export(&unit.ranges).as_bytes(),
+                    )?;
+                }
+                return Ok(());
+            }
+
+            output_text(output, &exporter.export(&ranges))
+        }
+        Command::Lift { input, function, stage, output } => {
+            let (program, _) = open(&input)?;
+            let address = navigation::resolve(&program, &function)?;
+            let address = program.function_containing(address).map(|function| function.address);
+            let mut function = address
+                .and_then(|address| ir::lift(&program, address))
+                .context(ValidationSnafu { reason: format!("{function} isn't in a function") })?;
+            if !matches!(stage, IrStage::Lifted) {
+                ir::ssa::build(&mut function);
+            }
+            if matches!(stage, IrStage::Optimized) {
+                ir::ssa::optimize(&mut function);
+            }
+            output_text(output, &function.to_string())
+        }
+        Command::Decompile { input, function, output } => {
+            let (program, _) = open(&input)?;
+            let address = navigation::resolve(&program, &function)?;
+            let address = program.function_containing(address).map(|function| function.address);
+            let pseudocode = address
+                .and_then(|address| decompile::decompile(&program, address))
+                .context(ValidationSnafu { reason: format!("{function} isn't in a function") })?;
+            output_text(output, &pseudocode.to_string())
+        }
+        Command::Split { input, splits, output } => {
+            let (program, _) = open(&input)?;
+            let exporter = ObjectExporter::new(&program);
+            let units = read_splits(&splits)?;
+            for unit in &units {
+                write_creating(&output.join(unit.file_name("o")), &exporter.export(unit)?)?;
+            }
+            print_json(&SplitSummary { objects: units.len(), output })
+        }
+        Command::RebuildDol { input, format, output } => {
+            let program = load(input, format)?;
+            let dol = program.write_dol()?;
+            write(&output, &dol)?;
+            print_json(&RebuildSummary { size: dol.len(), identical: dol == program.data, output })
+        }
+        Command::ExportPatches { input, format, output } => {
+            let program = load(input, InputFormat::Dol)?;
+            let name =
+                program.path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+            let data = match format {
+                PatchFormat::Gecko => patch::gecko_codes(&program, &name)?.into_bytes(),
+                PatchFormat::Ips => patch::ips(&program)?,
+                PatchFormat::Dol => program.write_dol()?,
+            };
+            write(&output, &data)?;
+            print_json(&PatchSummary { patches: program.patches.len(), output })
+        }
+        Command::ListDisc { input } => {
+            let data = read(&input)?;
+            let disc = Disc::parse(&data)?;
+            let mut files = Vec::new();
+            list_files(&disc.root, "", &mut files);
+            print_json(&DiscListing {
+                game_code: disc.header.game_code,
+                maker_code: disc.header.maker_code,
+                title: disc.header.title,
+                disc_number: disc.header.disc_number,
+                version: disc.header.version,
+                apploader_date: disc.apploader.date,
+                warnings: disc.warnings,
+                files,
+            })
+        }
+        Command::DumpSegments { input, format } => {
+            let program = load(input, format)?;
+            let segments: Vec<SegmentInfo> = program
+                .segments
+                .iter()
+                .map(|segment| SegmentInfo {
+                    name: segment.name.clone(),
+                    address: segment.address,
+                    size: segment.size,
+                    offset: segment.offset,
+                    permissions: segment.permissions.iter_names().map(|(name, _)| name).collect(),
+                })
+                .collect();
+            print_json(&segments)
+        }
+        Command::ExportMap { input, output } => {
+            let (program, _) = open(&input)?;
+            write(&output, map::export(&program).as_bytes())?;
+            print_json(&MapSummary { functions: program.functions.len(), names: program.names.len(), output })
+        }
+        Command::Symbols { input } => {
+            let (program, _) = open(&input)?;
+            let mut addresses: Vec<u32> =
+                program.names.keys().chain(program.functions.keys()).copied().collect();
+            addresses.sort_unstable();
+            addresses.dedup();
+            let symbols: Vec<SymbolInfo> = addresses
+                .into_iter()
+                .map(|address| {
+                    let function = program.functions.get(&address);
+                    SymbolInfo {
+                        address,
+                        name: program.raw_name(address),
+                        demangled: program.demangled(address).map(|demangled| demangled.to_string()),
+                        kind: match function {
+                            Some(_) => "function",
+                            None if program.is_code(address) => "label",
+                            None => "data",
+                        },
+                        size: function.map(|function| function.end - function.address),
+                        library: program
+                            .library_functions
+                            .get(&address)
+                            .map(|library| library.library.clone()),
+                    }
+                })
+                .collect();
+            print_json(&symbols)
+        }
+        Command::MakeSignatures { inputs, name, version, output } => {
+            let mut library = SignatureLibrary { name, version, signatures: Vec::new() };
+            for input in &inputs {
+                signature::add_object(&mut library, &read(input)?)?;
+            }
+            library.signatures.sort_by(|a, b| a.name.cmp(&b.name));
+            write(&output, &library.write()?)?;
+            print_json(&SignatureSummary {
+                library: library.title(),
+                signatures: library.signatures.len(),
+                output,
+            })
+        }
+    }
+}
+
+/// Loads a binary or database without analyzing it.
+fn load(input: PathBuf, format: InputFormat) -> Result<Program, FerroxError> {
+    let (data, compression) = compression::decompress(read(&input)?)?;
+    match database::is_database(&data) {
+        true => database::load(&data),
+        false => {
+            let mut program = Program::load(input, data, format.into())?;
+            program.compression = compression;
+            Ok(program)
+        }
+    }
+}
+
+/// Loads and analyzes the input the same way the window does, returning how many names came from the map.
+fn open(input: &Input) -> Result<(Program, usize), FerroxError> {
+    let (mut data, mut compression) = compression::decompress(read(&input.input)?)?;
+    let (mut path, mut format) = (input.input.clone(), input.format.into());
+    if let Some(file) = &input.disc_file {
+        (data, compression) = extract(data, file)?;
+        // Where it came from, rather than somewhere it's been extracted to
+        path = path.join(file.trim_start_matches('/'));
+        format = BinaryFormat::from_name(file);
+    }
+    let mut program = match database::is_database(&data) {
+        true => database::load(&data)?,
+        false => {
+            let mut program = Program::load(path, data, format)?;
+            program.compression = compression;
+            program.validate()?;
+            analysis::analyze(&mut program);
+            ensure!(
+                !program.functions.is_empty(),
+                ValidationSnafu { reason: "analysis didn't find any functions" }
+            );
+
+            let mut libraries = signature::load_default();
+            for directory in &input.signatures {
+                libraries.extend(signature::load_directory(directory)?);
+            }
+            signature::match_libraries(&mut program, &libraries);
+            program
+        }
+    };
+
+    let map_names = match &input.map {
+        Some(path) => {
+            let text = std::fs::read_to_string(path).context(FileSnafu { path })?;
+            map::apply(&mut program, &map::parse(&text))
+        }
+        None => 0,
+    };
+    Ok((program, map_names))
+}
+
+/// Picks a file out of a disc image or archive, decompressing it. Archives along the way are opened in turn, so
+/// `files/RELS.arc/rels/a.rel` is `rels/a.rel` inside `files/RELS.arc`.
+fn extract(data: Vec<u8>, file: &str) -> Result<(Vec<u8>, Option<Compression>), FerroxError> {
+    let (root, disc) = if Disc::is_disc(&data) {
+        let disc = Disc::parse(&data)?;
+        (disc.root.clone(), Some(disc))
+    } else if RarcArchive::is_archive(&data) {
+        (RarcArchive::entries(&data)?, None)
+    } else if U8Archive::is_archive(&data) {
+        (U8Archive::entries(&data)?, None)
+    } else {
+        return ValidationSnafu { reason: "input isn't a disc image or archive" }.fail();
+    };
+
+    let parts: Vec<_> = file.trim_start_matches('/').split('/').collect();
+    for end in 1..=parts.len() {
+        let Some(entry @ Entry::File { .. }) = Entry::find(&root, &parts[..end].join("/")) else {
+            continue;
+        };
+        let contents = match &disc {
+            Some(disc) => disc.contents(&data, entry),
+            None => entry.contents(&data).map(Cow::Borrowed),
+        };
+        let contents = contents
+            .context(ValidationSnafu { reason: format!("{} runs past the end", parts[..end].join("/")) })?;
+        let decompressed = compression::decompress(contents.to_vec())?;
+        return match end == parts.len() {
+            true => Ok(decompressed),
+            false => extract(decompressed.0, &parts[end..].join("/")),
+        };
+    }
+    ValidationSnafu { reason: format!("there's no file {file} in the input") }.fail()
+}
+
+/// Flattens a disc's tree into the path of every file in it.
+fn list_files(entries: &[Entry], parent: &str, files: &mut Vec<DiscFile>) {
+    for entry in entries {
+        let path = format!("{parent}{}", entry.name());
+        match entry {
+            Entry::File { offset, size, .. } => files.push(DiscFile { path, offset: *offset, size: *size }),
+            Entry::Directory { children, .. } => list_files(children, &format!("{path}/"), files),
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, FerroxError> {
+    std::fs::read(path).context(FileSnafu { path })
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<(), FerroxError> {
+    std::fs::write(path, data).context(FileSnafu { path })
+}
+
+fn read_splits(path: &Path) -> Result<Vec<Split>, FerroxError> {
+    let text = std::fs::read_to_string(path).context(FileSnafu { path })?;
+    split::parse(&text)
+}
+
+/// Writes a file, creating the folders it's in first since split names are usually paths.
+fn write_creating(path: &Path, data: &[u8]) -> Result<(), FerroxError> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context(FileSnafu { path: parent })?;
+    }
+    write(path, data)
+}
+
+/// Writes text to a file if one was given, otherwise to stdout.
+fn output_text(output: Option<PathBuf>, text: &str) -> Result<(), FerroxError> {
+    match output {
+        Some(output) => write(&output, text.as_bytes()),
+        None => {
+            print(text);
+            Ok(())
+        }
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), FerroxError> {
+    // Serializing plain structs can't fail
+    print(&(serde_json::to_string_pretty(value).unwrap() + "\n"));
+    Ok(())
+}
+
+/// Writes to stdout, ignoring errors so piping into something like `head` doesn't panic.
+fn print(text: &str) {
+    let _ = std::io::stdout().lock().write_all(text.as_bytes());
+}
@@ -2,25 +2,49 @@
 use core::cell::UnsafeCell;
 use std::path::PathBuf;
 
+use clap::Parser;
+use cli::Cli;
+use egui::{Key, KeyboardShortcut, Modifiers};
 use egui_dock::{DockArea, DockState, NodeIndex, Style, TabViewer};
-use registry::TypeRegistry;
+use ferrox_core::format::compression::{self, Compression};
+use ferrox_core::format::BinaryFormat;
+use ferrox_core::navigation::Navigation;
+use ferrox_core::processor::ProcessorType;
+use ferrox_core::program::Program;
+use ferrox_core::signature::{self, SignatureLibrary};
+use ferrox_core::{analysis, database, map};
 use rfd::AsyncFileDialog;
 use tokio::sync::oneshot;
 use views::assembly::AssemblyTab;
+use views::browser::BrowserWindow;
 use views::configure::{ImportState, ImportWindow};
 use views::console::ConsoleTab;
 use views::functions::FunctionsTab;
+use views::graph::GraphTab;
+use views::hex::HexTab;
+use views::history::HistoryTab;
+use views::palette::GotoPalette;
+use views::patches::PatchesTab;
+use views::pseudocode::PseudocodeTab;
+use views::search::SearchWindow;
+use views::signatures::SignatureWindow;
+use views::strings::StringsTab;
+use views::types::TypesTab;
 
-pub mod error;
-pub mod format;
-pub mod registry;
+pub mod cli;
 pub mod views;
 
-// TODO: Global `Style`s for text, add CFA to populate, render the loaded file to the assembly view
+// TODO: Global `Style`s for text
 
 // flag this as tokio::main so we can use tokio::spawn inside update()
 #[tokio::main]
 async fn main() -> eframe::Result {
+    // Subcommands run headless, only open a window without one
+    let cli = Cli::parse();
+    if let Some(command) = cli.command {
+        std::process::exit(cli::run(command));
+    }
+
     // Set a default window size
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([1000.0, 720.0]),
@@ -55,28 +79,22 @@ enum FerroxState {
     Interactable,
 }
 
-// All supported File Types
-#[derive(Default, PartialEq, Copy, Clone)]
-pub enum BinaryFormat {
-    BinaryFile,
-    #[default]
-    GameCubeDOL,
-}
-
-// All supported Architectures
-#[derive(Default, PartialEq, Copy, Clone)]
-pub enum ProcessorType {
-    #[default]
-    PowerPCGekko,
-}
+// Path and contents of a file picked by the user, or None if they cancelled
+type DialogResult = Option<(PathBuf, Vec<u8>)>;
 
 // Main Ferrox Application, responsible for managing all disassembler state.
 struct FerroxApplication {
     // File Selector
     dialog_state: DialogState,
-    dialog_info: Option<oneshot::Receiver<Option<(PathBuf, Vec<u8>)>>>,
+    dialog_info: Option<oneshot::Receiver<DialogResult>>,
+    // Symbol map being picked to import names from
+    map_dialog: Option<oneshot::Receiver<DialogResult>>,
     loaded_file: (PathBuf, Vec<u8>),
+    // What the loaded file was compressed with, if anything
+    loaded_compression: Option<Compression>,
     loaded_state: FerroxState,
+    // Why the last file couldn't be opened, shown until it's dismissed
+    load_error: Option<String>,
     style: Option<Style>,
 
     // Import Menu State
@@ -85,11 +103,29 @@ struct FerroxApplication {
     import_window_open: bool,
     import: ImportWindow,
 
+    // Loaded Program
+    program: Option<Program>,
+    // Address every view is synced to
+    cursor: u32,
+    navigation: Navigation,
+    // Library signatures to match against every new import
+    libraries: Vec<SignatureLibrary>,
+    palette: GotoPalette,
+    search: SearchWindow,
+    signatures: SignatureWindow,
+    browser: BrowserWindow,
+
     // Assembly View
     tree: UnsafeCell<DockState<String>>,
-    _registry: TypeRegistry,
     assembly: AssemblyTab,
+    graph: GraphTab,
+    pseudocode: PseudocodeTab,
+    hex: HexTab,
+    strings: StringsTab,
     functions: FunctionsTab,
+    types: TypesTab,
+    history: HistoryTab,
+    patches: PatchesTab,
     console: ConsoleTab,
 }
 
@@ -98,24 +134,34 @@ impl FerroxApplication {
         // Initial Main Window Tabs
         let mut dock_state = DockState::new(vec![
             "Ferrox View-A".to_owned(),
+            "Graph View".to_owned(),
+            "Pseudocode".to_owned(),
             "Hex-View 1".to_owned(),
             "Local Types".to_owned(),
             "Imports".to_owned(),
             "Exports".to_owned(),
+            "Strings".to_owned(),
         ]);
         // Rename "Eject"
         "Undock".clone_into(&mut dock_state.translations.tab_context_menu.eject_button);
         // Initial Side Tab(s)
         dock_state.main_surface_mut().split_left(NodeIndex::root(), 0.2, vec!["Functions".to_owned()]);
         // Initial Bottom Tab(s)
-        dock_state.main_surface_mut().split_below(NodeIndex::root(), 0.8, vec!["Output".to_owned()]);
+        dock_state.main_surface_mut().split_below(
+            NodeIndex::root(),
+            0.8,
+            vec!["Output".to_owned(), "History".to_owned(), "Patches".to_owned()],
+        );
 
         // Default State
         Self {
             dialog_state: DialogState::Idle,
             dialog_info: None,
+            map_dialog: None,
             loaded_file: (PathBuf::new(), Vec::new()),
+            loaded_compression: None,
             loaded_state: FerroxState::default(),
+            load_error: None,
             style: None,
 
             binary_format: BinaryFormat::default(),
@@ -124,18 +170,138 @@ impl FerroxApplication {
             import: ImportWindow::new(
                 vec![
                     ("GameCube Binary (DOL)", BinaryFormat::GameCubeDOL),
+                    ("GameCube Module (REL)", BinaryFormat::GameCubeREL),
                     ("Binary File", BinaryFormat::BinaryFile),
                 ],
                 vec![("PowerPC Gekko/Broadway (Big Endian)", ProcessorType::PowerPCGekko)],
             ),
 
+            program: None,
+            cursor: 0,
+            navigation: Navigation::new(),
+            libraries: signature::load_default(),
+            palette: GotoPalette::new(),
+            search: SearchWindow::new(),
+            signatures: SignatureWindow::new(),
+            browser: BrowserWindow::new(),
+
             tree: dock_state.into(),
-            _registry: TypeRegistry::new(),
-            assembly: AssemblyTab {},
+            assembly: AssemblyTab::new(),
+            graph: GraphTab::new(),
+            pseudocode: PseudocodeTab::new(),
+            hex: HexTab::new(),
+            strings: StringsTab::new(),
             functions: FunctionsTab {},
+            types: TypesTab::new(),
+            history: HistoryTab {},
+            patches: PatchesTab {},
             console: ConsoleTab {},
         }
     }
+
+    /// Switches every view over to a newly loaded program.
+    fn set_program(&mut self, program: Program) {
+        self.cursor =
+            program.entry_point.or_else(|| program.functions.keys().next().copied()).unwrap_or_default();
+        self.navigation = Navigation::new();
+        self.assembly.refresh(&program);
+        self.graph.refresh();
+        self.hex.refresh(&program);
+        self.strings.refresh(&program);
+        self.program = Some(program);
+        self.loaded_state = FerroxState::Interactable;
+    }
+
+    /// Decompresses a file that was just opened if it's Yaz0 or Yay0, remembering which for the program it gets
+    /// loaded into.
+    fn decompress(&mut self, (path, data): (PathBuf, Vec<u8>)) -> Option<(PathBuf, Vec<u8>)> {
+        match compression::decompress(data) {
+            Ok((data, compression)) => {
+                self.loaded_compression = compression;
+                Some((path, data))
+            }
+            Err(error) => {
+                self.load_error = Some(format!("Failed to decompress {}: {error}", path.display()));
+                None
+            }
+        }
+    }
+
+    /// Asks for a symbol map to take names from. It's applied once it's been read, in `update`.
+    fn import_map(&mut self, ctx: &egui::Context) {
+        let (tx, rx) = oneshot::channel();
+        self.map_dialog = Some(rx);
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            let result = match AsyncFileDialog::new()
+                .add_filter("Symbol Map", &["map"])
+                .add_filter("Any file", &["*"])
+                .pick_file()
+                .await
+            {
+                Some(file) => Some((file.path().to_path_buf(), file.read().await)),
+                None => None,
+            };
+            let _ = tx.send(result);
+            ctx.request_repaint();
+        });
+    }
+
+    /// Asks where to save a Dolphin symbol map of the program's names, then writes it out in the background.
+    fn export_map(&self) {
+        let Some(program) = &self.program else {
+            return;
+        };
+        let data = map::export(program).into_bytes();
+        let file_name = program.path.with_extension("map");
+        let file_name =
+            file_name.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+
+        tokio::spawn(async move {
+            let Some(file) = AsyncFileDialog::new()
+                .add_filter("Symbol Map", &["map"])
+                .set_file_name(file_name)
+                .save_file()
+                .await
+            else {
+                return;
+            };
+            if let Err(error) = file.write(&data).await {
+                eprintln!("Failed to save symbol map: {error}");
+            }
+        });
+    }
+
+    /// Asks where to save the database, then writes it out in the background.
+    fn save_database(&self) {
+        let Some(program) = &self.program else {
+            return;
+        };
+        let data = match database::save(program) {
+            Ok(data) => data,
+            Err(error) => {
+                eprintln!("Failed to save database: {error}");
+                return;
+            }
+        };
+        let file_name = program.path.with_extension("frx");
+        let file_name =
+            file_name.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+
+        tokio::spawn(async move {
+            let Some(file) = AsyncFileDialog::new()
+                .add_filter("Ferrox Database", &["frx"])
+                .set_file_name(file_name)
+                .save_file()
+                .await
+            else {
+                return;
+            };
+            if let Err(error) = file.write(&data).await {
+                eprintln!("Failed to save database: {error}");
+            }
+        });
+    }
 }
 
 // Support Trait for Docking Layout
@@ -147,10 +313,17 @@ impl TabViewer for FerroxApplication {
     }
 
     fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Self::Tab) {
-        match tab.as_str() {
-            "Ferrox View-A" => self.assembly.update(ui),
-            "Functions" => self.functions.update(ui),
-            "Output" => self.console.update(ui),
+        match (tab.as_str(), self.program.as_mut()) {
+            ("Ferrox View-A", Some(program)) => self.assembly.update(ui, program, &mut self.cursor),
+            ("Graph View", Some(program)) => self.graph.update(ui, program, &mut self.cursor),
+            ("Pseudocode", Some(program)) => self.pseudocode.update(ui, program, &mut self.cursor),
+            ("Hex-View 1", Some(program)) => self.hex.update(ui, program, &mut self.cursor),
+            ("Strings", Some(program)) => self.strings.update(ui, program, &mut self.cursor),
+            ("Functions", Some(program)) => self.functions.update(ui, program, &mut self.cursor),
+            ("Local Types", Some(program)) => self.types.update(ui, program, &mut self.cursor),
+            ("History", Some(program)) => self.history.update(ui, program),
+            ("Patches", Some(program)) => self.patches.update(ui, program, &mut self.cursor),
+            ("Output", _) => self.console.update(ui),
             _ => {
                 ui.label(tab.as_str());
             }
@@ -167,52 +340,218 @@ impl eframe::App for FerroxApplication {
         egui::TopBottomPanel::top("menu_bar").show(ctx, |ui| {
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
-                    if ui.button("Open").clicked() {
-                        if self.dialog_state != DialogState::Selecting {
-                            // Create a new channel to receive file data once we've loaded it
-                            let (tx, rx) = oneshot::channel();
-                            self.dialog_info = Some(rx);
-                            let ctx = ctx.clone();
-
-                            // Spawn a new task to wait on the user.
-                            tokio::spawn(async move {
-                                // Spawn a new window to open a file
-                                let result = AsyncFileDialog::new()
-                                    .add_filter("GameCube Binary", &["dol"])
-                                    // TODO: add saving to a database
-                                    .add_filter("Ferrox Database", &["frx"])
-                                    .add_filter("Any file", &["*"])
-                                    .set_directory(std::env::current_dir().ok().unwrap())
-                                    .pick_file()
-                                    .await;
-
-                                // Check if we've opened a file and try to read its data
-                                let result = match result {
-                                    Some(file_path) => {
-                                        Some((file_path.path().to_path_buf(), file_path.read().await))
-                                    }
-                                    None => None,
-                                };
-
-                                // Respond to the main thread and tell it to update
-                                let _ = tx.send(result);
-                                ctx.request_repaint();
-                            });
+                    if ui.button("Open").clicked() && self.dialog_state != DialogState::Selecting {
+                        // Create a new channel to receive file data once we've loaded it
+                        let (tx, rx) = oneshot::channel();
+                        self.dialog_info = Some(rx);
+                        let ctx = ctx.clone();
+
+                        // Spawn a new task to wait on the user.
+                        tokio::spawn(async move {
+                            // Spawn a new window to open a file
+                            let result = AsyncFileDialog::new()
+                                .add_filter("GameCube Binary", &["dol", "rel"])
+                                .add_filter("GameCube Disc Image", &["iso", "gcm"])
+                                .add_filter("Archive", &["arc", "carc", "szs"])
+                                .add_filter("Ferrox Database", &["frx"])
+                                .add_filter("Any file", &["*"])
+                                .set_directory(std::env::current_dir().ok().unwrap())
+                                .pick_file()
+                                .await;
+
+                            // Check if we've opened a file and try to read its data
+                            let result = match result {
+                                Some(file_path) => {
+                                    Some((file_path.path().to_path_buf(), file_path.read().await))
+                                }
+                                None => None,
+                            };
+
+                            // Respond to the main thread and tell it to update
+                            let _ = tx.send(result);
+                            ctx.request_repaint();
+                        });
+                    }
+                    if ui.add_enabled(self.program.is_some(), egui::Button::new("Save Database")).clicked() {
+                        self.save_database();
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    let loaded = self.program.is_some();
+                    let importing = self.map_dialog.is_some();
+                    if ui
+                        .add_enabled(loaded && !importing, egui::Button::new("Import Symbol Map..."))
+                        .clicked()
+                    {
+                        self.import_map(ctx);
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(loaded, egui::Button::new("Export Symbol Map...")).clicked() {
+                        self.export_map();
+                        ui.close_menu();
+                    }
+                    ui.separator();
+                    if ui.button("Generate Signatures...").clicked() {
+                        self.signatures.open();
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("Edit", |ui| {
+                    let (can_undo, can_redo) = match &self.program {
+                        Some(program) => (program.history.can_undo(), program.history.can_redo()),
+                        None => (false, false),
+                    };
+                    if ui.add_enabled(can_undo, egui::Button::new("Undo").shortcut_text("Ctrl+Z")).clicked() {
+                        self.program.as_mut().map(Program::undo);
+                        ui.close_menu();
+                    }
+                    if ui.add_enabled(can_redo, egui::Button::new("Redo").shortcut_text("Ctrl+Y")).clicked() {
+                        self.program.as_mut().map(Program::redo);
+                        ui.close_menu();
+                    }
+                });
+                ui.menu_button("View", |ui| {
+                    let mut raw_names = self.program.as_ref().is_some_and(|program| program.raw_names);
+                    let checkbox = egui::Checkbox::new(&mut raw_names, "Show Mangled Names");
+                    if ui.add_enabled(self.program.is_some(), checkbox).changed() {
+                        if let Some(program) = &mut self.program {
+                            program.set_raw_names(raw_names);
                         }
                     }
                 });
+                ui.menu_button("Jump", |ui| {
+                    let loaded = self.program.is_some();
+                    if ui.add_enabled(loaded, egui::Button::new("Go To...").shortcut_text("Ctrl+G")).clicked()
+                    {
+                        self.palette.open();
+                        ui.close_menu();
+                    }
+                    if ui
+                        .add_enabled(loaded, egui::Button::new("Search...").shortcut_text("Ctrl+F"))
+                        .clicked()
+                    {
+                        self.search.open();
+                        ui.close_menu();
+                    }
+                    let back = egui::Button::new("Back").shortcut_text("Alt+Left");
+                    if ui.add_enabled(self.navigation.can_go_back(), back).clicked() {
+                        self.navigation.back(&mut self.cursor);
+                        ui.close_menu();
+                    }
+                    let forward = egui::Button::new("Forward").shortcut_text("Alt+Right");
+                    if ui.add_enabled(self.navigation.can_go_forward(), forward).clicked() {
+                        self.navigation.forward(&mut self.cursor);
+                        ui.close_menu();
+                    }
+                });
             })
         });
 
+        // Undo/redo shortcuts, unless a text box has focus and wants them for itself
+        if let Some(program) = &mut self.program {
+            if !ctx.wants_keyboard_input() {
+                let undo = KeyboardShortcut::new(Modifiers::COMMAND, Key::Z);
+                let redo = KeyboardShortcut::new(Modifiers::COMMAND, Key::Y);
+                let redo_alt = KeyboardShortcut::new(Modifiers::COMMAND | Modifiers::SHIFT, Key::Z);
+                // Check the shift variant first, since consuming Ctrl+Z would also match Ctrl+Shift+Z
+                ctx.input_mut(|input| {
+                    if input.consume_shortcut(&redo_alt) || input.consume_shortcut(&redo) {
+                        program.redo();
+                    } else if input.consume_shortcut(&undo) {
+                        program.undo();
+                    }
+                });
+            }
+
+            // Navigation shortcuts
+            let goto = [Key::G, Key::P].map(|key| KeyboardShortcut::new(Modifiers::COMMAND, key));
+            let search = KeyboardShortcut::new(Modifiers::COMMAND, Key::F);
+            let back = KeyboardShortcut::new(Modifiers::ALT, Key::ArrowLeft);
+            let forward = KeyboardShortcut::new(Modifiers::ALT, Key::ArrowRight);
+            ctx.input_mut(|input| {
+                if goto.iter().any(|shortcut| input.consume_shortcut(shortcut)) {
+                    self.palette.open();
+                } else if input.consume_shortcut(&search) {
+                    self.search.open();
+                } else if input.consume_shortcut(&back) {
+                    self.navigation.back(&mut self.cursor);
+                } else if input.consume_shortcut(&forward) {
+                    self.navigation.forward(&mut self.cursor);
+                }
+            });
+            // Both windows have to be shown every frame, so don't short circuit
+            let picked = self.palette.update(ctx, program);
+            if let Some(address) = picked.or(self.search.update(ctx, program)) {
+                self.navigation.jump(&mut self.cursor, address);
+            }
+        }
+
+        // Doesn't need a program, signatures are built from separate object files
+        if let Some(library) = self.signatures.update(ctx) {
+            self.libraries.retain(|other| other.title() != library.title());
+            self.libraries.push(library);
+        }
+
+        if let Some(receiver) = &mut self.map_dialog {
+            match receiver.try_recv() {
+                Err(oneshot::error::TryRecvError::Empty) => (),
+                Err(oneshot::error::TryRecvError::Closed) | Ok(None) => self.map_dialog = None,
+                Ok(Some((path, data))) => {
+                    self.map_dialog = None;
+                    if let Some(program) = &mut self.program {
+                        let symbols = map::parse(&String::from_utf8_lossy(&data));
+                        let named = map::apply(program, &symbols);
+                        eprintln!(
+                            "Named {named} of {} symbols from {}",
+                            symbols.len(),
+                            path.display()
+                        );
+                    }
+                }
+            }
+        }
+
+        if let Some(picked) = self.browser.update(ctx) {
+            self.loaded_file = (picked.path, picked.data);
+            self.binary_format = picked.format;
+            self.loaded_compression = picked.compression;
+            self.loaded_state = FerroxState::Configure;
+            self.import_window_open = true;
+        }
+
         // Waiting state for file selector
         if let Some(receiver) = &mut self.dialog_info {
-            match receiver.try_recv() {
+            // Compressed files are opened as whatever's inside them
+            match receiver
+                .try_recv()
+                .map(|file_info| file_info.and_then(|file_info| self.decompress(file_info)))
+            {
                 Err(oneshot::error::TryRecvError::Closed) => self.dialog_state = DialogState::Cancelled,
                 // If it's empty, we haven't yet received any signal
                 Err(oneshot::error::TryRecvError::Empty) => (),
                 // We've actually gotten a response, process it.
+                // Databases already know how to load themselves, so skip straight past configuring
+                Ok(Some((_, data))) if database::is_database(&data) => {
+                    self.dialog_state = DialogState::Loaded;
+                    match database::load(&data) {
+                        Ok(program) => self.set_program(program),
+                        Err(error) => self.load_error = Some(format!("Failed to load database: {error}")),
+                    }
+                }
+                // Disc images and archives get browsed for the file to import instead
+                Ok(Some((path, data))) if BrowserWindow::can_browse(&data) => {
+                    self.dialog_state = DialogState::Loaded;
+                    if let Err(error) = self.browser.open(path, data) {
+                        self.load_error = Some(format!("Failed to open disc image or archive: {error}"));
+                    }
+                }
                 Ok(Some(file_info)) => {
                     self.dialog_state = DialogState::Loaded;
+                    // Start from what the extension says, when it says anything
+                    match BinaryFormat::from_name(&file_info.0.to_string_lossy()) {
+                        BinaryFormat::BinaryFile => (),
+                        format => self.binary_format = format,
+                    }
                     self.loaded_file = file_info;
                     self.loaded_state = FerroxState::Configure;
                     self.import_window_open = true;
@@ -221,6 +560,18 @@ impl eframe::App for FerroxApplication {
             }
         }
 
+        // Errors opening a file are shown over whatever was already loaded, which is left as it was
+        if let Some(error) = &self.load_error {
+            let mut dismissed = false;
+            egui::Window::new("Failed to Open File").collapsible(false).resizable(false).show(ctx, |ui| {
+                ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+                dismissed = ui.button("OK").clicked();
+            });
+            if dismissed {
+                self.load_error = None;
+            }
+        }
+
         match self.loaded_state {
             // Initial state (when a file isn't opened)
             FerroxState::Init => {
@@ -261,11 +612,24 @@ impl eframe::App for FerroxApplication {
             }
             // Analyze the binary before we display it in the main window
             FerroxState::Analyzing => {
-                // TODO: CFA
-                self.loaded_state = FerroxState::Interactable;
+                // TODO: move this into a tokio task so large binaries don't freeze the window
+                let (path, data) = std::mem::take(&mut self.loaded_file);
+                match Program::load(path, data, self.binary_format) {
+                    Ok(mut program) => {
+                        program.compression = self.loaded_compression.take();
+                        analysis::analyze(&mut program);
+                        signature::match_libraries(&mut program, &self.libraries);
+                        self.set_program(program);
+                    }
+                    Err(error) => {
+                        self.load_error = Some(format!("Failed to load binary: {error}"));
+                        self.loaded_state = FerroxState::Init;
+                    }
+                }
             }
             // Main state where the user can begin using the disassembly
             FerroxState::Interactable => {
+                let before = self.cursor;
                 egui::CentralPanel::default()
                     .frame(egui::Frame::central_panel(&ctx.style()).inner_margin(0.))
                     .show(ctx, |ui| {
@@ -285,6 +649,16 @@ impl eframe::App for FerroxApplication {
                             DockArea::new(&mut *self.tree.get()).style(style).show_inside(ui, self);
                         }
                     });
+
+                // Clicking around inside a function isn't worth remembering, but leaving it (e.g. from the
+                // function list or a graph node) is, so it can be undone with Back
+                if let Some(program) = &self.program {
+                    let function =
+                        |address| program.function_containing(address).map(|function| function.address);
+                    if self.cursor != before && function(self.cursor) != function(before) {
+                        self.navigation.push(before);
+                    }
+                }
             }
         }
     }
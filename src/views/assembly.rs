@@ -1,79 +1,411 @@
 use egui_extras::{Column, TableBuilder};
 
-pub struct AssemblyTab;
+use ferrox_core::analysis::frame::slot_name;
+use ferrox_core::listing::{self, formattable_operand, Line, LineKind};
+use ferrox_core::processor::gekko::Operand;
+use ferrox_core::program::{OperandFormat, Program};
+use ferrox_core::registry::TypeInfo;
+
+/// Something the user can do to the line under the cursor, from either a hotkey or the context menu.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Rename,
+    RenameVariable,
+    Comment { repeatable: bool },
+    Format(OperandFormat),
+    ChooseEnum,
+    Assemble,
+    RevertPatch,
+}
+
+/// Popup asking the user for input, only one can be open at a time.
+enum Dialog {
+    Rename {
+        address: u32,
+        text: String,
+        error: Option<String>,
+    },
+    /// Names a register or stack slot for a whole function, which is shared with the pseudocode's variables
+    RenameVariable {
+        function: u32,
+        /// Registers and stack slots the instruction uses, any of which can be picked
+        variables: Vec<String>,
+        variable: usize,
+        text: String,
+        error: Option<String>,
+    },
+    Comment {
+        address: u32,
+        repeatable: bool,
+        text: String,
+    },
+    Enum {
+        address: u32,
+        operand: usize,
+        selected: Option<String>,
+    },
+    Assemble {
+        address: u32,
+        text: String,
+        error: Option<String>,
+    },
+}
+
+#[derive(Default)]
+pub struct AssemblyTab {
+    lines: Vec<Line>,
+    /// Last cursor position we've seen, so we only scroll when something else moves it
+    synced_cursor: Option<u32>,
+    /// Program revision the lines were built from
+    revision: Option<u64>,
+    dialog: Option<Dialog>,
+}
 
 impl AssemblyTab {
-    pub fn update(&mut self, ui: &mut egui::Ui) {
-        // This is currently placeholder data. TODO: add actual state? First let's see how performant it is
-        // without caching gui elements
-        let lookup = [
-            (
-                ".text:80005C68",
-                "# int __fastcall main(int argc, const char **argv, const char **envp)",
-            ),
-            (".text:80005C68", "                 .globl main"),
-            (
-                ".text:80005C68",
-                " main:                                   # CODE XREF: __start+154↑p",
-            ),
-            (".text:80005C68", "                 stwu      r1, -0x10(r1)"),
-            (".text:80005C6C", "                 mflr      r0"),
-            (".text:80005C70", "                 stw       r0, 0x14(r1)"),
-            (".text:80005C74", "                 stw       r31, 0xC(r1)"),
-            (".text:80005C78", "                 bl        marioStInit"),
-            (".text:80005C7C", "                 bl        marioStMain"),
-            (".text:80005C80", ""),
-            (
-                ".text:80005C80",
-                " loc_80005C80:                           # CODE XREF: main+50↓j",
-            ),
-            (".text:80005C80", "                 bl        OSGetTick"),
-            (".text:80005C84", "                 mr        r31, r3"),
-            (".text:80005C88", "                 bl        DEMOBeforeRender"),
-            (".text:80005C8C", "                 bl        marioStDisp"),
-            (".text:80005C90", "                 bl        marioStMain"),
-            (".text:80005C94", "                 bl        OSGetTick"),
-            (
-                ".text:80005C98",
-                "                 lwz       r4, (gp - 0x800D6CA0)(r13) # marioSt",
-            ),
-            (".text:80005C9C", "                 subf      r0, r31, r3"),
-            (
-                ".text:80005CA0",
-                "                 stw       r0, (dword_8009C744 - 0x8009B430)(r4)",
-            ),
-            (".text:80005CA4", "                 bl        DEMODoneRender"),
-            (".text:80005CA8", "                 bl        OSGetTick"),
-            (
-                ".text:80005CAC",
-                "                 lwz       r4, (gp - 0x800D6CA0)(r13) # marioSt",
-            ),
-            (".text:80005CB0", "                 subf      r0, r31, r3"),
-            (
-                ".text:80005CB4",
-                "                 stw       r0, (dword_8009C748 - 0x8009B430)(r4)",
-            ),
-            (".text:80005CB8", "                 b         loc_80005C80"),
-            (".text:80005CB8", "# End of function main"),
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuilds the listing after the program has been (re-)analyzed.
+    pub fn refresh(&mut self, program: &Program) {
+        self.lines = listing::build(program);
+        self.synced_cursor = None;
+        self.revision = Some(program.revision);
+        self.dialog = None;
+    }
+
+    /// Operand that format actions apply to at an address, data is always treated as a single operand.
+    fn operand_at(program: &Program, address: u32) -> Option<usize> {
+        match program.instruction(address) {
+            Some(instruction) => formattable_operand(&instruction),
+            None => program.read_u32(address).map(|_| 0),
+        }
+    }
+
+    fn perform(&mut self, program: &mut Program, address: u32, action: Action) {
+        match action {
+            Action::Rename => {
+                let text = program.names.get(&address).cloned().unwrap_or_default();
+                self.dialog = Some(Dialog::Rename { address, text, error: None });
+            }
+            Action::RenameVariable => {
+                let (Some(function), Some(instruction)) =
+                    (program.function_containing(address), program.instruction(address))
+                else {
+                    return;
+                };
+                let frame = &function.frame;
+                let mut variables = Vec::new();
+                for &operand in instruction.simplified().1.iter() {
+                    let names = match operand {
+                        Operand::Gpr(_) | Operand::Fpr(_) => vec![operand.to_string()],
+                        Operand::Offset { disp, base: 1 }
+                            if frame.size > 0 && frame.allocation != Some(address) =>
+                        {
+                            vec![slot_name(frame.entry_offset(address, disp)), "r1".to_owned()]
+                        }
+                        Operand::Offset { base, .. } => vec![format!("r{base}")],
+                        _ => continue,
+                    };
+                    for name in names {
+                        if !variables.contains(&name) {
+                            variables.push(name);
+                        }
+                    }
+                }
+                let function = function.address;
+                if let Some(first) = variables.first() {
+                    let text =
+                        program.variable_names.get(&(function, first.clone())).cloned().unwrap_or_default();
+                    self.dialog =
+                        Some(Dialog::RenameVariable { function, variables, variable: 0, text, error: None });
+                }
+            }
+            Action::Comment { repeatable } => {
+                let comments = if repeatable {
+                    &program.repeatable_comments
+                } else {
+                    &program.comments
+                };
+                let text = comments.get(&address).cloned().unwrap_or_default();
+                self.dialog = Some(Dialog::Comment { address, repeatable, text });
+            }
+            Action::Format(format) => {
+                if let Some(operand) = Self::operand_at(program, address) {
+                    // Pressing the same key again toggles back to the default
+                    let format = match *program.operand_format(address, operand) == format {
+                        true => OperandFormat::Default,
+                        false => format,
+                    };
+                    program.set_operand_format(address, operand, format);
+                }
+            }
+            Action::ChooseEnum => {
+                if let Some(operand) = Self::operand_at(program, address) {
+                    let selected = match program.operand_format(address, operand) {
+                        OperandFormat::Enum(name) => Some(name.clone()),
+                        _ => None,
+                    };
+                    self.dialog = Some(Dialog::Enum { address, operand, selected });
+                }
+            }
+            Action::Assemble => {
+                if program.is_code(address) {
+                    let text = program.instruction(address).map(|instruction| instruction.to_string());
+                    self.dialog =
+                        Some(Dialog::Assemble { address, text: text.unwrap_or_default(), error: None });
+                }
+            }
+            Action::RevertPatch => {
+                // The cursor can be anywhere inside a patch, not just at its start
+                let start = program
+                    .patches
+                    .range(..=address)
+                    .next_back()
+                    .filter(|&(&start, patch)| address < start + patch.patched.len() as u32)
+                    .map(|(&start, _)| start);
+                if let Some(start) = start {
+                    program.revert_patch(start);
+                }
+            }
+        }
+    }
+
+    fn hotkey(ui: &egui::Ui) -> Option<Action> {
+        ui.input(|input| {
+            if input.key_pressed(egui::Key::N) {
+                Some(Action::Rename)
+            } else if input.key_pressed(egui::Key::Colon)
+                || (input.modifiers.shift && input.key_pressed(egui::Key::Semicolon))
+            {
+                Some(Action::Comment { repeatable: false })
+            } else if input.key_pressed(egui::Key::Semicolon) {
+                Some(Action::Comment { repeatable: true })
+            } else if input.key_pressed(egui::Key::H) {
+                Some(Action::Format(OperandFormat::Hex))
+            } else if input.key_pressed(egui::Key::D) {
+                Some(Action::Format(OperandFormat::Decimal))
+            } else if input.key_pressed(egui::Key::R) {
+                Some(Action::Format(OperandFormat::Char))
+            } else if input.key_pressed(egui::Key::O) {
+                Some(Action::Format(OperandFormat::Offset))
+            } else if input.key_pressed(egui::Key::M) {
+                Some(Action::ChooseEnum)
+            } else if input.key_pressed(egui::Key::A) {
+                Some(Action::Assemble)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn context_menu(ui: &mut egui::Ui) -> Option<Action> {
+        let items = [
+            ("Rename (N)", Action::Rename),
+            ("Rename Variable...", Action::RenameVariable),
+            ("Comment (:)", Action::Comment { repeatable: false }),
+            ("Repeatable Comment (;)", Action::Comment { repeatable: true }),
+            ("Hexadecimal (H)", Action::Format(OperandFormat::Hex)),
+            ("Decimal (D)", Action::Format(OperandFormat::Decimal)),
+            ("Character (R)", Action::Format(OperandFormat::Char)),
+            ("Offset (O)", Action::Format(OperandFormat::Offset)),
+            ("Enum Member (M)", Action::ChooseEnum),
+            ("Default Format", Action::Format(OperandFormat::Default)),
+            ("Assemble (A)", Action::Assemble),
+            ("Revert Patch", Action::RevertPatch),
         ];
+        let mut chosen = None;
+        for (label, action) in items {
+            if ui.button(label).clicked() {
+                chosen = Some(action);
+                ui.close_menu();
+            }
+        }
+        chosen
+    }
+
+    /// Shows the open dialog, if any, applying its changes to the program once confirmed.
+    fn show_dialog(&mut self, ctx: &egui::Context, program: &mut Program) {
+        let Some(dialog) = &mut self.dialog else {
+            return;
+        };
+        let (confirm, cancel) = ctx.input(|input| {
+            (
+                input.key_pressed(egui::Key::Enter),
+                input.key_pressed(egui::Key::Escape),
+            )
+        });
+        let mut close = cancel;
+
+        let title = match dialog {
+            Dialog::Rename { .. } => "Rename Address",
+            Dialog::RenameVariable { .. } => "Rename Variable",
+            Dialog::Comment { repeatable: false, .. } => "Comment",
+            Dialog::Comment { repeatable: true, .. } => "Repeatable Comment",
+            Dialog::Enum { .. } => "Choose Enum",
+            Dialog::Assemble { .. } => "Assemble Instruction",
+        };
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| match dialog {
+                Dialog::Rename { address, text, error } => {
+                    ui.label(format!("Name for 0x{address:08X} (leave empty to reset):"));
+                    ui.text_edit_singleline(text).request_focus();
+                    if let Some(error) = error {
+                        ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+                    }
+                    if confirm || ui.button("OK").clicked() {
+                        match program.rename(*address, text) {
+                            Ok(()) => close = true,
+                            Err(err) => *error = Some(err.to_string()),
+                        }
+                    }
+                }
+                Dialog::RenameVariable { function, variables, variable, text, error } => {
+                    ui.horizontal(|ui| {
+                        for (index, name) in variables.iter().enumerate() {
+                            if ui.selectable_label(index == *variable, name.as_str()).clicked() {
+                                *variable = index;
+                                let key = (*function, name.clone());
+                                *text = program.variable_names.get(&key).cloned().unwrap_or_default();
+                            }
+                        }
+                    });
+                    let name = &variables[*variable];
+                    ui.label(format!(
+                        "Name for {name} throughout {} (leave empty to reset):",
+                        program.display_name(*function)
+                    ));
+                    ui.text_edit_singleline(text).request_focus();
+                    if let Some(error) = error {
+                        ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+                    }
+                    if confirm || ui.button("OK").clicked() {
+                        match program.rename_variable(*function, name, text) {
+                            Ok(()) => close = true,
+                            Err(err) => *error = Some(err.to_string()),
+                        }
+                    }
+                }
+                Dialog::Comment { address, repeatable, text } => {
+                    ui.label(format!("Comment at 0x{address:08X} (leave empty to remove):"));
+                    ui.text_edit_singleline(text).request_focus();
+                    if confirm || ui.button("OK").clicked() {
+                        program.set_comment(*address, text, *repeatable);
+                        close = true;
+                    }
+                }
+                Dialog::Enum { address, operand, selected } => {
+                    let enums: Vec<String> = program
+                        .types
+                        .definitions()
+                        .filter(|(_, type_info)| matches!(type_info, TypeInfo::Enum { .. }))
+                        .map(|(name, _)| name.clone())
+                        .collect();
+                    if enums.is_empty() {
+                        ui.label("There aren't any enums yet, create one in Local Types.");
+                    }
+                    for name in enums {
+                        let checked = selected.as_ref() == Some(&name);
+                        if ui.selectable_label(checked, name.as_str()).clicked() {
+                            *selected = Some(name);
+                        }
+                    }
+                    if (confirm || ui.button("OK").clicked()) && selected.is_some() {
+                        program.set_operand_format(
+                            *address,
+                            *operand,
+                            OperandFormat::Enum(selected.take().unwrap()),
+                        );
+                        close = true;
+                    }
+                }
+                Dialog::Assemble { address, text, error } => {
+                    ui.label(format!("Instruction to patch over 0x{address:08X}:"));
+                    ui.text_edit_singleline(text).request_focus();
+                    if let Some(error) = error {
+                        ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+                    }
+                    if confirm || ui.button("OK").clicked() {
+                        match program.assemble(*address, text) {
+                            Ok(()) => close = true,
+                            Err(err) => *error = Some(err.to_string()),
+                        }
+                    }
+                }
+            });
+
+        if close {
+            self.dialog = None;
+        }
+    }
+
+    pub fn update(&mut self, ui: &mut egui::Ui, program: &mut Program, cursor: &mut u32) {
+        if self.revision != Some(program.revision) {
+            let synced_cursor = self.synced_cursor;
+            self.refresh(program);
+            // Edits shouldn't make the listing jump around
+            self.synced_cursor = synced_cursor;
+        }
+
+        self.show_dialog(ui.ctx(), program);
 
         ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
         ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Extend);
 
-        egui::ScrollArea::both().show(ui, |ui| {
-            TableBuilder::new(ui).column(Column::auto()).column(Column::remainder().clip(true)).body(
-                |body| {
-                    body.rows(20.0, lookup.len(), |mut row| {
-                        let index = row.index();
-                        row.col(|ui| {
-                            ui.label(egui::RichText::new(lookup[index].0).size(14.0));
-                        });
-                        row.col(|ui| {
-                            ui.label(egui::RichText::new(lookup[index].1).size(14.0));
-                        });
+        // Only jump to the cursor when it's been moved from somewhere else, otherwise we'd fight the user
+        let scroll_to = match self.synced_cursor {
+            Some(synced) if synced == *cursor => None,
+            _ => Some(self.lines.partition_point(|line| line.address < *cursor)),
+        };
+        self.synced_cursor = Some(*cursor);
+
+        let mut action = None;
+        let output = egui::ScrollArea::horizontal().show(ui, |ui| {
+            let mut table = TableBuilder::new(ui)
+                .sense(egui::Sense::click())
+                .column(Column::auto())
+                .column(Column::remainder().clip(true));
+            if let Some(row) = scroll_to {
+                table = table.scroll_to_row(row, Some(egui::Align::Center));
+            }
+
+            table.body(|body| {
+                body.rows(20.0, self.lines.len(), |mut row| {
+                    let line = self.lines[row.index()];
+                    let selectable = matches!(line.kind, LineKind::Instruction | LineKind::Data);
+                    row.set_selected(selectable && line.address == *cursor);
+                    row.col(|ui| {
+                        ui.label(
+                            egui::RichText::new(listing::address_text(program, line.address)).size(14.0),
+                        );
+                    });
+                    row.col(|ui| {
+                        ui.label(egui::RichText::new(listing::line_text(program, line)).size(14.0));
                     });
-                },
-            );
+                    let response = row.response();
+                    if response.clicked() || response.secondary_clicked() {
+                        *cursor = line.address;
+                        self.synced_cursor = Some(line.address);
+                    }
+                    response.context_menu(|ui| {
+                        if let Some(chosen) = Self::context_menu(ui) {
+                            action = Some(chosen);
+                        }
+                    });
+                });
+            });
         });
+
+        // Hotkeys act on the cursor, but only while the listing is hovered so they don't clash with other tabs
+        let hovered = ui.rect_contains_pointer(output.inner_rect);
+        if self.dialog.is_none() && hovered && !ui.ctx().wants_keyboard_input() {
+            action = action.or_else(|| Self::hotkey(ui));
+        }
+        if let Some(action) = action {
+            self.perform(program, *cursor, action);
+        }
     }
 }
@@ -0,0 +1,229 @@
+use std::borrow::Cow;
+use std::path::PathBuf;
+
+use ferrox_core::error::FerroxError;
+use ferrox_core::format::compression::{self, Compression};
+use ferrox_core::format::disc::Disc;
+use ferrox_core::format::rarc::RarcArchive;
+use ferrox_core::format::u8_archive::U8Archive;
+use ferrox_core::format::{BinaryFormat, Entry};
+
+/// A file picked out of a disc image or archive to import.
+pub struct Picked {
+    /// Path of the image it came from joined with its path inside it, like `game.iso/files/RELS/a.rel`
+    pub path: PathBuf,
+    /// Its contents, already decompressed
+    pub data: Vec<u8>,
+    /// What its name suggests it is
+    pub format: BinaryFormat,
+    pub compression: Option<Compression>,
+}
+
+/// What's being browsed. Discs have a header worth showing, archives are only their files.
+enum Contents {
+    Disc(Box<Disc>),
+    Archive(Vec<Entry>),
+}
+
+/// A disc image or archive being browsed.
+struct Level {
+    /// Where it was opened from, which files picked out of it are named after
+    path: PathBuf,
+    data: Vec<u8>,
+    /// What sort of image it is, like `RARC Archive`
+    kind: &'static str,
+    contents: Contents,
+    /// Path of the selected file inside it, like `files/RELS/a.rel`
+    selected: Option<String>,
+}
+
+impl Level {
+    fn open(path: PathBuf, data: Vec<u8>) -> Result<Self, FerroxError> {
+        let (kind, contents) = if Disc::is_disc(&data) {
+            ("Disc Image", Contents::Disc(Box::new(Disc::parse(&data)?)))
+        } else if RarcArchive::is_archive(&data) {
+            ("RARC Archive", Contents::Archive(RarcArchive::entries(&data)?))
+        } else {
+            ("U8 Archive", Contents::Archive(U8Archive::entries(&data)?))
+        };
+        Ok(Self { path, data, kind, contents, selected: None })
+    }
+}
+
+/// Window for picking a DOL or REL out of a disc image or archive, so it can be imported without extracting it
+/// first. Archives picked out of what's being browsed are opened on top of it, so a REL can be found inside an
+/// archive on a disc.
+#[derive(Default)]
+pub struct BrowserWindow {
+    /// Everything that's been opened to get to what's shown, which is the last one
+    levels: Vec<Level>,
+    /// Why the last file picked couldn't be opened
+    error: Option<String>,
+}
+
+impl BrowserWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether `data` is a disc image or archive that can be browsed, once it's been decompressed.
+    pub fn can_browse(data: &[u8]) -> bool {
+        Disc::is_disc(data) || RarcArchive::is_archive(data) || U8Archive::is_archive(data)
+    }
+
+    /// Reads the files in a disc image or archive and shows them.
+    pub fn open(&mut self, path: PathBuf, data: Vec<u8>) -> Result<(), FerroxError> {
+        self.levels = vec![Level::open(path, data)?];
+        self.error = None;
+        Ok(())
+    }
+
+    /// Shows the window if it's open, returning the file to import once one has been picked.
+    pub fn update(&mut self, ctx: &egui::Context) -> Option<Picked> {
+        let depth = self.levels.len();
+        let level = self.levels.last_mut()?;
+        let (mut open, mut pick, mut back) = (true, false, false);
+        egui::Window::new(level.kind)
+            .id(egui::Id::new("browser"))
+            .open(&mut open)
+            .default_width(420.0)
+            .show(ctx, |ui| {
+                let root = match &level.contents {
+                    Contents::Disc(disc) => {
+                        show_header(ui, disc);
+                        &disc.root
+                    }
+                    Contents::Archive(root) => {
+                        ui.horizontal(|ui| {
+                            back = ui.add_enabled(depth > 1, egui::Button::new("Back")).clicked();
+                            ui.label(level.path.to_string_lossy());
+                        });
+                        root
+                    }
+                };
+
+                ui.separator();
+                egui::ScrollArea::vertical().max_height(400.0).auto_shrink([false, true]).show(ui, |ui| {
+                    pick |= show_entries(ui, root, "", &mut level.selected);
+                });
+
+                ui.separator();
+                let file = level.selected.as_deref().and_then(|path| Entry::find(root, path));
+                ui.horizontal(|ui| {
+                    let is_file = matches!(file, Some(Entry::File { .. }));
+                    pick |= ui.add_enabled(is_file, egui::Button::new("Open")).clicked();
+                    if let Some(Entry::File { offset, size, .. }) = file {
+                        ui.label(format!("0x{size:X} bytes at 0x{offset:X}"));
+                    }
+                });
+                if let Some(error) = &self.error {
+                    ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+                }
+            });
+
+        if !open {
+            self.close();
+            return None;
+        }
+        if back {
+            self.levels.pop();
+            self.error = None;
+            return None;
+        }
+        let name = level.selected.clone().filter(|_| pick)?;
+        let root = match &level.contents {
+            Contents::Disc(disc) => &disc.root,
+            Contents::Archive(root) => root,
+        };
+        let path = level.path.join(&name);
+        let contents = Entry::find(root, &name).and_then(|file| match &level.contents {
+            Contents::Disc(disc) => disc.contents(&level.data, file),
+            Contents::Archive(_) => file.contents(&level.data).map(Cow::Borrowed),
+        });
+        let Some(contents) = contents else {
+            self.error = Some(format!("{name} runs past the end of {}", level.path.display()));
+            return None;
+        };
+        let (data, compression) = match compression::decompress(contents.to_vec()) {
+            Ok(decompressed) => decompressed,
+            Err(error) => {
+                self.error = Some(format!("Failed to decompress {}: {error}", path.display()));
+                return None;
+            }
+        };
+
+        // Archives are browsed in turn rather than imported
+        if Self::can_browse(&data) {
+            match Level::open(path, data) {
+                Ok(level) => {
+                    self.levels.push(level);
+                    self.error = None;
+                }
+                Err(error) => self.error = Some(format!("Failed to open {name}: {error}")),
+            }
+            return None;
+        }
+        let picked = Picked { path, data, format: BinaryFormat::from_name(&name), compression };
+        self.close();
+        Some(picked)
+    }
+
+    /// Closes the window, letting go of everything that was opened since it can be quite big.
+    fn close(&mut self) {
+        *self = Self::new();
+    }
+}
+
+fn show_header(ui: &mut egui::Ui, disc: &Disc) {
+    let header = &disc.header;
+    egui::Grid::new("disc_header").num_columns(2).show(ui, |ui| {
+        ui.label("Title:");
+        ui.label(&header.title);
+        ui.end_row();
+        ui.label("Game ID:");
+        ui.label(format!("{}{}", header.game_code, header.maker_code));
+        ui.end_row();
+        ui.label("Disc:");
+        ui.label(format!(
+            "{} (revision {})",
+            header.disc_number + 1,
+            header.version
+        ));
+        ui.end_row();
+        ui.label("Apploader:");
+        ui.label(&disc.apploader.date);
+        ui.end_row();
+    });
+    for warning in &disc.warnings {
+        ui.colored_label(ui.visuals().warn_fg_color, warning);
+    }
+}
+
+/// Shows a level of the tree, returning whether a file was double clicked to open it straight away.
+fn show_entries(ui: &mut egui::Ui, entries: &[Entry], parent: &str, selected: &mut Option<String>) -> bool {
+    let mut pick = false;
+    for entry in entries {
+        let path = format!("{parent}{}", entry.name());
+        match entry {
+            Entry::Directory { name, children } => {
+                egui::CollapsingHeader::new(name).id_salt(&path).default_open(parent.is_empty()).show(
+                    ui,
+                    |ui| {
+                        pick |= show_entries(ui, children, &format!("{path}/"), selected);
+                    },
+                );
+            }
+            Entry::File { name, size, .. } => {
+                let is_selected = selected.as_deref() == Some(path.as_str());
+                let response = ui.selectable_label(is_selected, format!("{name}  ({size:#X})"));
+                if response.clicked() {
+                    *selected = Some(path);
+                } else if response.double_clicked() {
+                    *selected = Some(path);
+                    pick = true;
+                }
+            }
+        }
+    }
+    pick
+}
@@ -1,6 +1,7 @@
 use egui_extras::{Column, TableBuilder};
 
-use crate::{BinaryFormat, ProcessorType};
+use ferrox_core::format::BinaryFormat;
+use ferrox_core::processor::ProcessorType;
 
 #[derive(Debug, Default)]
 pub enum ImportState {
@@ -1,24 +1,49 @@
+use egui::{Color32, RichText};
 use egui_extras::{Column, TableBuilder};
 
+use ferrox_core::program::Program;
+
+/// Names of functions identified by library signatures are shown in this color.
+const LIBRARY_COLOR: Color32 = Color32::from_rgb(0x5F, 0xA8, 0xD3);
+
 pub struct FunctionsTab;
 
 impl FunctionsTab {
-    pub fn update(&mut self, ui: &mut egui::Ui) {
-        // This is currently placeholder data. TODO: add actual state? First let's see how performant it is
-        // without caching gui elements
+    pub fn update(&mut self, ui: &mut egui::Ui, program: &Program, cursor: &mut u32) {
+        if !program.library_conflicts.is_empty() {
+            let title = format!("{} ambiguous library matches", program.library_conflicts.len());
+            egui::CollapsingHeader::new(RichText::new(title).color(ui.visuals().warn_fg_color)).show(
+                ui,
+                |ui| {
+                    egui::ScrollArea::vertical().max_height(150.0).show(ui, |ui| {
+                        for conflict in &program.library_conflicts {
+                            let candidates: Vec<String> = conflict
+                                .candidates
+                                .iter()
+                                .map(|candidate| format!("{} ({})", candidate.name, candidate.library))
+                                .collect();
+                            let text = format!("{:08X}: {}", conflict.address, candidates.join(", "));
+                            if ui.link(text).clicked() {
+                                *cursor = conflict.address;
+                            }
+                        }
+                    });
+                },
+            );
+        }
+
         let available_size = ui.available_size();
-        let lookup: [(&str, u32); 3] = [
-            ("__check_pad3", 0x80003100),
-            ("__set_debug_bba", 0x80003140),
-            ("__get_debug_bba", 0x8000314C),
-        ];
+        let functions: Vec<u32> = program.functions.keys().copied().collect();
+        let current = program.function_containing(*cursor).map(|function| function.address);
 
         egui::ScrollArea::both().auto_shrink([false, false]).show(ui, |ui| {
             ui.set_width(available_size.x);
             ui.set_height(available_size.y);
             TableBuilder::new(ui)
+                .sense(egui::Sense::click())
                 .column(Column::auto().at_least(140.0).resizable(true)) // Function name column with initial width
-                .column(Column::remainder().at_least(80.0)) // Address column with minimum width
+                .column(Column::auto().at_least(80.0)) // Address column with minimum width
+                .column(Column::remainder().at_least(80.0)) // Library column, empty for most functions
                 .header(20.0, |mut header| {
                     // Add headers
                     header.col(|ui| {
@@ -27,16 +52,42 @@ impl FunctionsTab {
                     header.col(|ui| {
                         ui.heading("Address");
                     });
+                    header.col(|ui| {
+                        ui.heading("Library");
+                    });
                 })
                 .body(|body| {
-                    body.rows(20.0, lookup.len(), |mut row| {
-                        let index = row.index();
+                    body.rows(20.0, functions.len(), |mut row| {
+                        let address = functions[row.index()];
+                        let library = program.library_functions.get(&address);
+                        row.set_selected(current == Some(address));
+                        row.col(|ui| {
+                            let name = RichText::new(program.display_name(address));
+                            let label = ui.label(match library {
+                                Some(_) => name.color(LIBRARY_COLOR),
+                                None => name,
+                            });
+                            // Demangled names can be long and ambiguous, so keep the real one at hand
+                            if program.demangled(address).is_some() {
+                                label.on_hover_text(program.raw_name(address));
+                            }
+                        });
                         row.col(|ui| {
-                            ui.label(lookup[index].0);
+                            ui.label(format!("{address:08X}"));
                         });
                         row.col(|ui| {
-                            ui.label(format!("{:08X}", lookup[index].1));
+                            if let Some(library) = library {
+                                ui.label(format!(
+                                    "{} ({:.0}%)",
+                                    library.library,
+                                    library.confidence * 100.0
+                                ))
+                                .on_hover_text(format!("Matched {}", library.name));
+                            }
                         });
+                        if row.response().clicked() {
+                            *cursor = address;
+                        }
                     });
                 });
         });
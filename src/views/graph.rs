@@ -0,0 +1,603 @@
+//! Control flow graph of the current function, laid out in layers (Sugiyama-style) so branches read top to
+//! bottom the same way they do in the assembly view.
+use std::collections::{BTreeMap, BTreeSet};
+
+use egui::epaint::CubicBezierShape;
+use egui::{Color32, FontId, Pos2, Rect, Sense, Stroke, Vec2};
+
+use ferrox_core::listing::format_instruction;
+use ferrox_core::program::{EdgeKind, Function, Program};
+
+const FONT_SIZE: f32 = 12.0;
+const NODE_PADDING: f32 = 6.0;
+const LAYER_GAP: f32 = 48.0;
+const NODE_GAP: f32 = 32.0;
+/// Edges that skip layers get routed through invisible placeholder nodes of this width
+const DUMMY_WIDTH: f32 = 8.0;
+
+const TRUE_COLOR: Color32 = Color32::from_rgb(0x3C, 0xB0, 0x43);
+const FALSE_COLOR: Color32 = Color32::from_rgb(0xE0, 0x4A, 0x3F);
+const UNCONDITIONAL_COLOR: Color32 = Color32::from_rgb(0x4A, 0x8C, 0xE0);
+const SWITCH_COLOR: Color32 = Color32::from_rgb(0xB0, 0x6C, 0xD8);
+
+/// A set of blocks the user has merged into a single node.
+struct Group {
+    blocks: BTreeSet<u32>,
+    title: String,
+}
+
+struct Node {
+    /// Address of the first block represented by this node
+    address: u32,
+    lines: Vec<String>,
+    size: Vec2,
+    /// Top-left corner, in graph space
+    position: Pos2,
+    /// Placeholder used to route long edges, these are removed once layout is done
+    dummy: bool,
+}
+
+struct GraphEdge {
+    kind: EdgeKind,
+    /// Path in graph space, from the bottom of the source node to the top of the target node
+    points: Vec<Pos2>,
+}
+
+struct Layout {
+    function: u32,
+    nodes: Vec<Node>,
+    edges: Vec<GraphEdge>,
+}
+
+pub struct GraphTab {
+    layout: Option<Layout>,
+    /// Set whenever anything changes that requires a new layout
+    dirty: bool,
+    /// Program revision the layout was built from, since renames and formats change the size of nodes
+    revision: u64,
+    offset: Vec2,
+    zoom: f32,
+    collapsed: BTreeSet<u32>,
+    groups: Vec<Group>,
+    selection: BTreeSet<u32>,
+    /// Node the context menu was opened on
+    context_node: Option<u32>,
+    /// Last cursor position we've seen, so we only recenter when something else moves it
+    synced_cursor: Option<u32>,
+}
+
+impl Default for GraphTab {
+    fn default() -> Self {
+        Self {
+            layout: None,
+            dirty: true,
+            revision: 0,
+            offset: Vec2::ZERO,
+            zoom: 1.0,
+            collapsed: BTreeSet::new(),
+            groups: Vec::new(),
+            selection: BTreeSet::new(),
+            context_node: None,
+            synced_cursor: None,
+        }
+    }
+}
+
+impl GraphTab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forces the graph to be laid out again, e.g. after the program has been re-analyzed.
+    pub fn refresh(&mut self) {
+        self.dirty = true;
+    }
+
+    fn group_of(&self, block: u32) -> Option<&Group> {
+        self.groups.iter().find(|group| group.blocks.contains(&block))
+    }
+
+    /// Maps a block to the node that represents it, which is the first block of its group if it's in one.
+    fn representative(&self, block: u32) -> u32 {
+        self.group_of(block).and_then(|group| group.blocks.first().copied()).unwrap_or(block)
+    }
+
+    fn build_layout(&self, ui: &egui::Ui, program: &Program, function: &Function) -> Layout {
+        let font = FontId::monospace(FONT_SIZE);
+        let (char_width, row_height) =
+            ui.fonts(|fonts| (fonts.glyph_width(&font, 'W'), fonts.row_height(&font)));
+
+        // Collect nodes, merging grouped blocks together
+        let mut index_of = BTreeMap::new();
+        let mut nodes = Vec::new();
+        for &start in function.blocks.keys() {
+            let address = self.representative(start);
+            if index_of.contains_key(&address) {
+                index_of.insert(start, index_of[&address]);
+                continue;
+            }
+
+            let mut lines = vec![format!("{}:", program.display_name(address))];
+            if let Some(group) = self.group_of(start) {
+                lines.push(format!("{} ({} blocks)", group.title, group.blocks.len()));
+            } else if self.collapsed.contains(&start) {
+                lines.push("...".to_owned());
+            } else {
+                let block = &function.blocks[&start];
+                for address in (block.start..block.end).step_by(4) {
+                    lines.push(match program.instruction(address) {
+                        Some(instruction) => format_instruction(program, &instruction),
+                        None => format!(".word     0x{:08X}", program.read_u32(address).unwrap_or(0)),
+                    });
+                }
+            }
+
+            let columns = lines.iter().map(|line| line.chars().count()).max().unwrap_or(0) as f32;
+            let size = Vec2::new(
+                columns * char_width + NODE_PADDING * 2.0,
+                lines.len() as f32 * row_height + NODE_PADDING * 2.0,
+            );
+            index_of.insert(start, nodes.len());
+            nodes.push(Node { address, lines, size, position: Pos2::ZERO, dummy: false });
+        }
+
+        // Collect edges between nodes, dropping anything internal to a group
+        let mut edges = Vec::new();
+        for block in function.blocks.values() {
+            for edge in &block.successors {
+                let (Some(&from), Some(&to)) = (index_of.get(&block.start), index_of.get(&edge.target))
+                else {
+                    continue;
+                };
+                if from != to || self.group_of(block.start).is_none() {
+                    edges.push((from, to, edge.kind));
+                }
+            }
+        }
+
+        let entry = index_of.get(&function.address).copied().unwrap_or(0);
+        layout(function.address, nodes, &edges, entry)
+    }
+
+    /// Centers the view on a node.
+    fn focus(&mut self, rect: Rect, address: u32) {
+        let Some(layout) = &self.layout else {
+            return;
+        };
+        if let Some(node) = layout.nodes.iter().find(|node| !node.dummy && node.address == address) {
+            let center = node.position + node.size / 2.0;
+            self.offset = rect.size() / 2.0 - center.to_vec2() * self.zoom;
+            // Keep the top of the function in view when first opening it
+            if address == layout.function {
+                self.offset.y = 20.0 - node.position.y * self.zoom;
+            }
+        }
+    }
+
+    pub fn update(&mut self, ui: &mut egui::Ui, program: &Program, cursor: &mut u32) {
+        let Some(function) = program.function_containing(*cursor) else {
+            self.layout = None;
+            ui.label("The cursor isn't inside of a function.");
+            return;
+        };
+
+        let (response, painter) = ui.allocate_painter(ui.available_size(), Sense::click_and_drag());
+        let rect = response.rect;
+
+        if program.revision != self.revision {
+            self.revision = program.revision;
+            self.dirty = true;
+        }
+        if self.dirty || self.layout.as_ref().is_none_or(|layout| layout.function != function.address) {
+            let new_function = self.layout.as_ref().is_none_or(|layout| layout.function != function.address);
+            self.layout = Some(self.build_layout(ui, program, function));
+            self.dirty = false;
+            if new_function {
+                self.zoom = 1.0;
+                self.focus(rect, function.address);
+                self.synced_cursor = Some(function.address);
+            }
+        }
+        // Jumping somewhere else in the same function should still bring it into view
+        if self.synced_cursor != Some(*cursor) {
+            if let Some(block) = function.block_containing(*cursor) {
+                let node = self.representative(block.start);
+                self.focus(rect, node);
+            }
+            self.synced_cursor = Some(*cursor);
+        }
+
+        // Panning and zooming
+        if response.dragged() {
+            self.offset += response.drag_delta();
+        }
+        if let Some(pointer) = response.hover_pos() {
+            let (scroll, zoom) = ui.input(|input| (input.smooth_scroll_delta.y, input.zoom_delta()));
+            let factor = zoom * (scroll * 0.002).exp();
+            if factor != 1.0 {
+                let new_zoom = (self.zoom * factor).clamp(0.05, 4.0);
+                // Keep whatever is under the pointer in place
+                let anchor = pointer - rect.min;
+                self.offset = anchor - (anchor - self.offset) * (new_zoom / self.zoom);
+                self.zoom = new_zoom;
+            }
+        }
+
+        let layout = self.layout.as_ref().unwrap();
+        let to_screen = |point: Pos2| rect.min + self.offset + point.to_vec2() * self.zoom;
+        let node_at = |point: Pos2| {
+            layout.nodes.iter().filter(|node| !node.dummy).find(|node| {
+                Rect::from_min_size(to_screen(node.position), node.size * self.zoom).contains(point)
+            })
+        };
+
+        let painter = painter.with_clip_rect(rect);
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+        // Edges first so the nodes draw over them
+        for edge in &layout.edges {
+            let color = match edge.kind {
+                EdgeKind::True => TRUE_COLOR,
+                EdgeKind::False => FALSE_COLOR,
+                EdgeKind::Unconditional => UNCONDITIONAL_COLOR,
+                EdgeKind::Switch => SWITCH_COLOR,
+            };
+            let stroke = Stroke::new(1.5, color);
+            let points: Vec<Pos2> = edge.points.iter().map(|&point| to_screen(point)).collect();
+            for pair in points.windows(2) {
+                let (from, to) = (pair[0], pair[1]);
+                let bend = (to.y - from.y).abs() / 2.0;
+                painter.add(CubicBezierShape::from_points_stroke(
+                    [from, from + Vec2::new(0.0, bend), to - Vec2::new(0.0, bend), to],
+                    false,
+                    Color32::TRANSPARENT,
+                    stroke,
+                ));
+            }
+            if let Some(&end) = points.last() {
+                let size = 6.0 * self.zoom.max(0.5);
+                painter.add(egui::Shape::convex_polygon(
+                    vec![
+                        end,
+                        end + Vec2::new(-size / 2.0, -size),
+                        end + Vec2::new(size / 2.0, -size),
+                    ],
+                    color,
+                    Stroke::NONE,
+                ));
+            }
+        }
+
+        let current_block = function.block_containing(*cursor).map(|block| self.representative(block.start));
+        let font = FontId::monospace(FONT_SIZE * self.zoom);
+        let text_color = ui.visuals().text_color();
+        for node in layout.nodes.iter().filter(|node| !node.dummy) {
+            let node_rect = Rect::from_min_size(to_screen(node.position), node.size * self.zoom);
+            if !rect.intersects(node_rect) {
+                continue;
+            }
+            let stroke = if self.selection.contains(&node.address) {
+                Stroke::new(2.0, ui.visuals().selection.stroke.color)
+            } else if current_block == Some(node.address) {
+                Stroke::new(2.0, UNCONDITIONAL_COLOR)
+            } else {
+                ui.visuals().widgets.noninteractive.bg_stroke
+            };
+            painter.rect(node_rect, 3.0, ui.visuals().window_fill, stroke);
+
+            // Text is unreadable past a certain point, so just draw the boxes
+            if self.zoom >= 0.35 {
+                let row_height = ui.fonts(|fonts| fonts.row_height(&font));
+                let mut position = node_rect.min + Vec2::splat(NODE_PADDING * self.zoom);
+                for line in &node.lines {
+                    painter.text(position, egui::Align2::LEFT_TOP, line, font.clone(), text_color);
+                    position.y += row_height;
+                }
+            }
+        }
+
+        // Interaction with nodes
+        if let Some(pointer) = response.interact_pointer_pos() {
+            let clicked = node_at(pointer).map(|node| node.address);
+            if response.double_clicked() {
+                if let Some(address) = clicked.filter(|&address| self.group_of(address).is_none()) {
+                    if !self.collapsed.remove(&address) {
+                        self.collapsed.insert(address);
+                    }
+                    self.dirty = true;
+                }
+            } else if response.clicked() {
+                match clicked {
+                    Some(address) if ui.input(|input| input.modifiers.command) => {
+                        if !self.selection.remove(&address) {
+                            self.selection.insert(address);
+                        }
+                    }
+                    Some(address) => {
+                        self.selection.clear();
+                        *cursor = address;
+                        self.synced_cursor = Some(address);
+                    }
+                    None => self.selection.clear(),
+                }
+            } else if response.secondary_clicked() {
+                self.context_node = clicked;
+            }
+        }
+
+        response.context_menu(|ui| self.context_menu(ui, rect, function.address));
+    }
+
+    fn context_menu(&mut self, ui: &mut egui::Ui, rect: Rect, function: u32) {
+        if let Some(address) = self.context_node {
+            if let Some(index) = self.groups.iter().position(|group| group.blocks.first() == Some(&address)) {
+                if ui.button("Ungroup").clicked() {
+                    self.groups.remove(index);
+                    self.dirty = true;
+                    ui.close_menu();
+                }
+            } else {
+                let label = if self.collapsed.contains(&address) {
+                    "Expand Block"
+                } else {
+                    "Collapse Block"
+                };
+                if ui.button(label).clicked() {
+                    if !self.collapsed.remove(&address) {
+                        self.collapsed.insert(address);
+                    }
+                    self.dirty = true;
+                    ui.close_menu();
+                }
+            }
+        }
+
+        let groupable = self.selection.iter().filter(|&&address| self.group_of(address).is_none()).count();
+        if ui.add_enabled(groupable > 1, egui::Button::new("Group Selected Blocks")).clicked() {
+            let blocks: BTreeSet<u32> =
+                self.selection.iter().copied().filter(|&address| self.group_of(address).is_none()).collect();
+            let title = format!("Group at {:08X}", blocks.first().unwrap());
+            self.groups.push(Group { blocks, title });
+            self.selection.clear();
+            self.dirty = true;
+            ui.close_menu();
+        }
+
+        ui.separator();
+        if ui.button("Reset View").clicked() {
+            self.zoom = 1.0;
+            self.focus(rect, function);
+            ui.close_menu();
+        }
+    }
+}
+
+/// Lays out a directed graph in layers: break cycles, assign layers by longest path, reduce crossings with
+/// the barycenter heuristic, and then pull nodes towards their neighbours horizontally.
+fn layout(function: u32, mut nodes: Vec<Node>, edges: &[(usize, usize, EdgeKind)], entry: usize) -> Layout {
+    let count = nodes.len();
+
+    // Find back edges with a DFS from the entry (and anything unreachable from it)
+    let mut successors = vec![Vec::new(); count];
+    for (n, &(from, to, _)) in edges.iter().enumerate() {
+        successors[from].push((to, n));
+    }
+    let mut back_edges = BTreeSet::new();
+    let mut state = vec![0u8; count]; // 0 = unvisited, 1 = on stack, 2 = done
+    let mut order = Vec::with_capacity(count);
+    for root in core::iter::once(entry).chain(0..count) {
+        if state[root] != 0 {
+            continue;
+        }
+        let mut stack = vec![(root, 0usize)];
+        state[root] = 1;
+        order.push(root);
+        while let Some(&mut (node, ref mut next)) = stack.last_mut() {
+            if let Some(&(to, edge)) = successors[node].get(*next) {
+                *next += 1;
+                match state[to] {
+                    0 => {
+                        state[to] = 1;
+                        order.push(to);
+                        stack.push((to, 0));
+                    }
+                    1 => {
+                        back_edges.insert(edge);
+                    }
+                    _ => (),
+                }
+            } else {
+                state[node] = 2;
+                stack.pop();
+            }
+        }
+    }
+
+    // Reverse back edges so the graph is acyclic, then assign layers by longest path
+    let acyclic: Vec<(usize, usize)> = edges
+        .iter()
+        .enumerate()
+        .filter(|&(_, &(from, to, _))| from != to)
+        .map(|(n, &(from, to, _))| {
+            if back_edges.contains(&n) {
+                (to, from)
+            } else {
+                (from, to)
+            }
+        })
+        .collect();
+    let mut layer = vec![0usize; count];
+    // DFS order is a valid topological order for the acyclic graph only in reverse post-order, so just relax
+    // until nothing changes; function graphs are small enough that this is quick.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &(from, to) in &acyclic {
+            if layer[to] < layer[from] + 1 {
+                layer[to] = layer[from] + 1;
+                changed = true;
+            }
+        }
+    }
+
+    // Split long edges into chains of dummy nodes, one per layer crossed
+    let mut chains = Vec::with_capacity(edges.len());
+    let mut links = Vec::new();
+    for &(from, to) in &acyclic {
+        let mut chain = vec![from];
+        for dummy_layer in layer[from] + 1..layer[to] {
+            nodes.push(Node {
+                address: 0,
+                lines: Vec::new(),
+                size: Vec2::new(DUMMY_WIDTH, 0.0),
+                position: Pos2::ZERO,
+                dummy: true,
+            });
+            layer.push(dummy_layer);
+            chain.push(nodes.len() - 1);
+        }
+        chain.push(to);
+        for pair in chain.windows(2) {
+            links.push((pair[0], pair[1]));
+        }
+        chains.push(chain);
+    }
+
+    let layer_count = layer.iter().max().map_or(0, |max| max + 1);
+    let mut layers: Vec<Vec<usize>> = vec![Vec::new(); layer_count];
+    // Start from DFS order so the fall-through path tends to stay on the left
+    for node in order.iter().copied().chain(count..nodes.len()) {
+        layers[layer[node]].push(node);
+    }
+    let mut parents = vec![Vec::new(); nodes.len()];
+    let mut children = vec![Vec::new(); nodes.len()];
+    for &(from, to) in &links {
+        children[from].push(to);
+        parents[to].push(from);
+    }
+
+    // Crossing reduction, alternating downwards and upwards sweeps
+    let mut position = vec![0f32; nodes.len()];
+    for nodes_in_layer in &layers {
+        for (n, &node) in nodes_in_layer.iter().enumerate() {
+            position[node] = n as f32;
+        }
+    }
+    for sweep in 0..8 {
+        let downwards = sweep % 2 == 0;
+        let range: Vec<usize> = if downwards {
+            (1..layer_count).collect()
+        } else {
+            (0..layer_count.saturating_sub(1)).rev().collect()
+        };
+        for n in range {
+            let neighbours = if downwards { &parents } else { &children };
+            let mut keyed: Vec<(f32, usize)> = layers[n]
+                .iter()
+                .map(|&node| {
+                    let adjacent = &neighbours[node];
+                    let key = match adjacent.is_empty() {
+                        true => position[node],
+                        false => {
+                            adjacent.iter().map(|&other| position[other]).sum::<f32>() / adjacent.len() as f32
+                        }
+                    };
+                    (key, node)
+                })
+                .collect();
+            keyed.sort_by(|a, b| a.0.total_cmp(&b.0));
+            layers[n] = keyed.into_iter().map(|(_, node)| node).collect();
+            for (index, &node) in layers[n].iter().enumerate() {
+                position[node] = index as f32;
+            }
+        }
+    }
+
+    // Horizontal placement: pack each layer, then repeatedly pull nodes towards the average of their
+    // neighbours while keeping the order and spacing intact.
+    let mut center = vec![0f32; nodes.len()];
+    for nodes_in_layer in &layers {
+        let mut x = 0.0;
+        for &node in nodes_in_layer {
+            center[node] = x + nodes[node].size.x / 2.0;
+            x += nodes[node].size.x + NODE_GAP;
+        }
+    }
+    for iteration in 0..16 {
+        let downwards = iteration % 2 == 0;
+        let neighbours = if downwards { &parents } else { &children };
+        for nodes_in_layer in &layers {
+            let mut desired: Vec<f32> = nodes_in_layer
+                .iter()
+                .map(|&node| match neighbours[node].is_empty() {
+                    true => center[node],
+                    false => {
+                        neighbours[node].iter().map(|&other| center[other]).sum::<f32>()
+                            / neighbours[node].len() as f32
+                    }
+                })
+                .collect();
+            // Resolve overlaps from left to right, then from right to left so the layer stays balanced
+            for n in 1..desired.len() {
+                let minimum = desired[n - 1]
+                    + (nodes[nodes_in_layer[n - 1]].size.x + nodes[nodes_in_layer[n]].size.x) / 2.0
+                    + NODE_GAP;
+                desired[n] = desired[n].max(minimum);
+            }
+            for (n, &node) in nodes_in_layer.iter().enumerate() {
+                center[node] = desired[n];
+            }
+        }
+    }
+
+    // Vertical placement, each layer is as tall as its tallest node
+    let mut y = 0.0;
+    for nodes_in_layer in &layers {
+        let height = nodes_in_layer.iter().map(|&node| nodes[node].size.y).fold(0.0, f32::max);
+        for &node in nodes_in_layer {
+            nodes[node].position = Pos2::new(center[node] - nodes[node].size.x / 2.0, y);
+        }
+        y += height + LAYER_GAP;
+    }
+    let min_x = nodes.iter().map(|node| node.position.x).fold(f32::INFINITY, f32::min);
+    let min_x = if min_x.is_finite() { min_x } else { 0.0 };
+    for node in &mut nodes {
+        node.position.x -= min_x;
+    }
+
+    // Route each edge through its chain of dummy nodes
+    let mut graph_edges = Vec::with_capacity(edges.len());
+    let mut chains = chains.into_iter();
+    for (n, &(from, to, kind)) in edges.iter().enumerate() {
+        let points = if from == to {
+            // Self loops go out the bottom and back around into the top
+            let node = &nodes[from];
+            let right = node.position.x + node.size.x;
+            vec![
+                Pos2::new(right - 8.0, node.position.y + node.size.y),
+                Pos2::new(right + 16.0, node.position.y + node.size.y + 12.0),
+                Pos2::new(right + 16.0, node.position.y - 12.0),
+                Pos2::new(right - 8.0, node.position.y),
+            ]
+        } else {
+            let mut chain = chains.next().unwrap();
+            if back_edges.contains(&n) {
+                chain.reverse();
+            }
+            let bottom =
+                |node: &Node| Pos2::new(node.position.x + node.size.x / 2.0, node.position.y + node.size.y);
+            let top = |node: &Node| Pos2::new(node.position.x + node.size.x / 2.0, node.position.y);
+            let mut points = vec![bottom(&nodes[chain[0]])];
+            for &dummy in &chain[1..chain.len() - 1] {
+                points.push(top(&nodes[dummy]));
+            }
+            points.push(top(&nodes[*chain.last().unwrap()]));
+            points
+        };
+        graph_edges.push(GraphEdge { kind, points });
+    }
+
+    // Dummy nodes were only needed for routing
+    nodes.truncate(count);
+    Layout { function, nodes, edges: graph_edges }
+}
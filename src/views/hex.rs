@@ -0,0 +1,120 @@
+use egui::{Color32, RichText};
+use egui_extras::{Column, TableBuilder};
+
+use ferrox_core::format::Permissions;
+use ferrox_core::program::Program;
+
+const BYTES_PER_ROW: u32 = 16;
+
+/// Raw bytes of every segment, kept in sync with the shared cursor.
+#[derive(Default)]
+pub struct HexTab {
+    /// Address of the first byte of every row
+    rows: Vec<u32>,
+    /// Last cursor position we've seen, so we only scroll when something else moves it
+    synced_cursor: Option<u32>,
+}
+
+impl HexTab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rebuilds the rows after a new program has been loaded.
+    pub fn refresh(&mut self, program: &Program) {
+        self.rows.clear();
+        self.synced_cursor = None;
+        for segment in &program.segments {
+            self.rows
+                .extend((segment.address..segment.address + segment.size).step_by(BYTES_PER_ROW as usize));
+        }
+    }
+
+    pub fn update(&mut self, ui: &mut egui::Ui, program: &Program, cursor: &mut u32) {
+        ui.style_mut().override_text_style = Some(egui::TextStyle::Monospace);
+        ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Extend);
+        ui.spacing_mut().item_spacing.x = 0.0;
+
+        let row_of = |address: u32| self.rows.partition_point(|&row| row + BYTES_PER_ROW <= address);
+        let scroll_to = match self.synced_cursor {
+            Some(synced) if synced == *cursor => None,
+            _ => Some(row_of(*cursor)),
+        };
+        self.synced_cursor = Some(*cursor);
+
+        // Highlight a whole word, since that's the size of everything the other views point at
+        let highlighted = *cursor..cursor.saturating_add(4);
+        let highlight = ui.visuals().selection.bg_fill;
+        let mut clicked = None;
+
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            let mut table = TableBuilder::new(ui)
+                .column(Column::auto())
+                .column(Column::auto())
+                .column(Column::remainder());
+            if let Some(row) = scroll_to {
+                table = table.scroll_to_row(row, Some(egui::Align::Center));
+            }
+
+            table.body(|body| {
+                body.rows(18.0, self.rows.len(), |mut row| {
+                    let start = self.rows[row.index()];
+                    let segment = program.segment_at(start);
+                    let end = segment.map_or(start, |segment| {
+                        (segment.address + segment.size).min(start + BYTES_PER_ROW)
+                    });
+                    let uninitialized = segment
+                        .is_some_and(|segment| segment.permissions.contains(Permissions::UNINITIALIZED));
+
+                    row.col(|ui| {
+                        let name = segment.map_or("", |segment| segment.name.as_str());
+                        ui.label(format!(".{name}:{start:08X}  "));
+                    });
+                    row.col(|ui| {
+                        for address in start..start + BYTES_PER_ROW {
+                            let text = match program.bytes(address, 1) {
+                                Some(bytes) if address < end => format!("{:02X}", bytes[0]),
+                                _ if address < end && uninitialized => "??".to_owned(),
+                                _ => "  ".to_owned(),
+                            };
+                            let mut text = RichText::new(text);
+                            if highlighted.contains(&address) {
+                                text = text.background_color(highlight);
+                            }
+                            if ui.add(egui::Label::new(text).sense(egui::Sense::click())).clicked()
+                                && address < end
+                            {
+                                clicked = Some(address);
+                            }
+                            ui.label(if address % 8 == 7 { "  " } else { " " });
+                        }
+                    });
+                    row.col(|ui| {
+                        for address in start..end {
+                            let byte = program.bytes(address, 1).map(|bytes| bytes[0]);
+                            let c = match byte {
+                                Some(byte) if byte.is_ascii_graphic() || byte == b' ' => byte as char,
+                                _ => '.',
+                            };
+                            let mut text = RichText::new(c.to_string());
+                            if byte.is_none() {
+                                text = text.color(Color32::GRAY);
+                            }
+                            if highlighted.contains(&address) {
+                                text = text.background_color(highlight);
+                            }
+                            if ui.add(egui::Label::new(text).sense(egui::Sense::click())).clicked() {
+                                clicked = Some(address);
+                            }
+                        }
+                    });
+                });
+            });
+        });
+
+        if let Some(address) = clicked {
+            *cursor = address;
+            self.synced_cursor = Some(address);
+        }
+    }
+}
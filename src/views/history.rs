@@ -0,0 +1,47 @@
+use egui_extras::{Column, TableBuilder};
+
+use ferrox_core::program::Program;
+
+/// Lists every edit made to the program, clicking one undoes or redoes everything up to that point.
+pub struct HistoryTab;
+
+impl HistoryTab {
+    pub fn update(&mut self, ui: &mut egui::Ui, program: &mut Program) {
+        let position = program.history.position();
+        // Row 0 is the state before any edits were made
+        let rows = program.history.edits().len() + 1;
+        let mut jump_to = None;
+
+        TableBuilder::new(ui)
+            .sense(egui::Sense::click())
+            .column(Column::exact(40.0))
+            .column(Column::remainder())
+            .body(|body| {
+                body.rows(20.0, rows, |mut row| {
+                    let index = row.index();
+                    row.set_selected(index == position);
+                    row.col(|ui| {
+                        ui.label(index.to_string());
+                    });
+                    row.col(|ui| {
+                        let text = match index {
+                            0 => "Initial analysis".to_owned(),
+                            index => program.history.edits()[index - 1].describe(),
+                        };
+                        // Edits that have been undone are greyed out until they're redone or replaced
+                        match index > position {
+                            true => ui.weak(text),
+                            false => ui.label(text),
+                        };
+                    });
+                    if row.response().clicked() {
+                        jump_to = Some(index);
+                    }
+                });
+            });
+
+        if let Some(index) = jump_to {
+            program.jump_to(index);
+        }
+    }
+}
@@ -2,5 +2,16 @@
 pub mod configure;
 // Main View Tabs
 pub mod assembly;
+pub mod browser;
 pub mod console;
 pub mod functions;
+pub mod graph;
+pub mod hex;
+pub mod history;
+pub mod palette;
+pub mod patches;
+pub mod pseudocode;
+pub mod search;
+pub mod signatures;
+pub mod strings;
+pub mod types;
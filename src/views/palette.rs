@@ -0,0 +1,106 @@
+use egui::{Key, Modifiers};
+
+use ferrox_core::navigation::{resolve, search_symbols};
+use ferrox_core::program::Program;
+
+/// Most symbols we'll list at once, anything further down isn't worth scrolling through.
+const MAX_RESULTS: usize = 20;
+
+/// Popup for jumping to an address expression or a symbol, opened with Ctrl+G or Ctrl+P.
+#[derive(Default)]
+pub struct GotoPalette {
+    open: bool,
+    query: String,
+    selected: usize,
+}
+
+impl GotoPalette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+        self.query.clear();
+        self.selected = 0;
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Shows the palette if it's open, returning the address the user picked.
+    pub fn update(&mut self, ctx: &egui::Context, program: &Program) -> Option<u32> {
+        if !self.open {
+            return None;
+        }
+
+        // Whatever the query resolves to directly comes first, then any symbols that fuzzy match it
+        let resolved = resolve(program, &self.query);
+        let mut results: Vec<(u32, String)> = Vec::new();
+        if let Ok(address) = resolved {
+            results.push((
+                address,
+                format!("Go to 0x{address:08X} ({})", program.describe(address)),
+            ));
+        }
+        results.extend(
+            search_symbols(program, &self.query, MAX_RESULTS)
+                .into_iter()
+                .map(|(address, name)| (address, format!("{name}  0x{address:08X}"))),
+        );
+
+        // Take the keys before the text box sees them, it'd otherwise move the text cursor
+        let (up, down, enter, escape) = ctx.input_mut(|input| {
+            (
+                input.consume_key(Modifiers::NONE, Key::ArrowUp),
+                input.consume_key(Modifiers::NONE, Key::ArrowDown),
+                input.consume_key(Modifiers::NONE, Key::Enter),
+                input.consume_key(Modifiers::NONE, Key::Escape),
+            )
+        });
+        if down {
+            self.selected = (self.selected + 1).min(results.len().saturating_sub(1));
+        }
+        if up {
+            self.selected = self.selected.saturating_sub(1);
+        }
+
+        let mut picked = None;
+        egui::Window::new("Go To")
+            .title_bar(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, egui::Vec2::new(0.0, 60.0))
+            .fixed_size([420.0, 0.0])
+            .show(ctx, |ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.query)
+                        .hint_text("Address, section:offset, main+0x40 or a symbol name")
+                        .desired_width(f32::INFINITY),
+                );
+                response.request_focus();
+                if response.changed() {
+                    self.selected = 0;
+                }
+
+                if results.is_empty() && !self.query.trim().is_empty() {
+                    if let Err(error) = &resolved {
+                        ui.colored_label(ui.visuals().error_fg_color, error.to_string());
+                    }
+                }
+                for (index, (address, text)) in results.iter().enumerate() {
+                    if ui.selectable_label(index == self.selected, text.as_str()).clicked() {
+                        picked = Some(*address);
+                    }
+                }
+            });
+
+        if enter {
+            picked = picked.or_else(|| results.get(self.selected).map(|&(address, _)| address));
+        }
+        if picked.is_some() || escape {
+            self.open = false;
+        }
+        picked
+    }
+}
@@ -0,0 +1,110 @@
+use egui_extras::{Column, TableBuilder};
+use rfd::AsyncFileDialog;
+
+use ferrox_core::error::FerroxError;
+use ferrox_core::patch;
+use ferrox_core::program::Program;
+
+/// Lists every patched run of bytes, with buttons to revert them one by one or export all of them.
+pub struct PatchesTab;
+
+impl PatchesTab {
+    pub fn update(&mut self, ui: &mut egui::Ui, program: &mut Program, cursor: &mut u32) {
+        ui.horizontal(|ui| {
+            ui.add_enabled_ui(!program.patches.is_empty(), |ui| {
+                if ui.button("Export Gecko Codes...").clicked() {
+                    let name = file_stem(program);
+                    export(
+                        patch::gecko_codes(program, &name).map(String::into_bytes),
+                        "Gecko Codes",
+                        "txt",
+                        &name,
+                    );
+                }
+                if ui.button("Export IPS Patch...").clicked() {
+                    export(patch::ips(program), "IPS Patch", "ips", &file_stem(program));
+                }
+                if ui.button("Export Patched DOL...").clicked() {
+                    export(program.write_dol(), "GameCube Binary", "dol", &file_stem(program));
+                }
+            });
+        });
+
+        let patches: Vec<u32> = program.patches.keys().copied().collect();
+        let mut revert = None;
+        TableBuilder::new(ui)
+            .sense(egui::Sense::click())
+            .column(Column::auto())
+            .column(Column::auto())
+            .column(Column::auto())
+            .column(Column::remainder())
+            .header(20.0, |mut header| {
+                for title in ["Address", "Original", "Patched", ""] {
+                    header.col(|ui| {
+                        ui.strong(title);
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(20.0, patches.len(), |mut row| {
+                    let address = patches[row.index()];
+                    let patch = &program.patches[&address];
+                    row.set_selected(address == *cursor);
+                    row.col(|ui| {
+                        ui.monospace(program.describe(address));
+                    });
+                    row.col(|ui| {
+                        ui.monospace(hex(&patch.original));
+                    });
+                    row.col(|ui| {
+                        ui.monospace(hex(&patch.patched));
+                    });
+                    row.col(|ui| {
+                        if ui.small_button("Revert").clicked() {
+                            revert = Some(address);
+                        }
+                    });
+                    if row.response().clicked() {
+                        *cursor = address;
+                    }
+                });
+            });
+
+        if let Some(address) = revert {
+            program.revert_patch(address);
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02X}")).collect::<Vec<_>>().join(" ")
+}
+
+fn file_stem(program: &Program) -> String {
+    program.path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+/// Asks where to save an export, then writes it out in the background.
+fn export(data: Result<Vec<u8>, FerroxError>, filter: &'static str, extension: &'static str, stem: &str) {
+    let data = match data {
+        Ok(data) => data,
+        Err(error) => {
+            eprintln!("Failed to export patches: {error}");
+            return;
+        }
+    };
+    let file_name = format!("{stem}.{extension}");
+    tokio::spawn(async move {
+        let Some(file) = AsyncFileDialog::new()
+            .add_filter(filter, &[extension])
+            .set_file_name(file_name)
+            .save_file()
+            .await
+        else {
+            return;
+        };
+        if let Err(error) = file.write(&data).await {
+            eprintln!("Failed to export patches: {error}");
+        }
+    });
+}
@@ -0,0 +1,346 @@
+//! C pseudocode of the function under the cursor. Variables and functions can be renamed from here, which
+//! the assembly view picks up too, since both read names from the same place.
+use egui::{Color32, RichText, Sense, TextStyle};
+
+use ferrox_core::ctype;
+use ferrox_core::decompile::{self, Pseudocode, TokenKind, VariableKind};
+use ferrox_core::program::Program;
+
+const KEYWORD_COLOR: Color32 = Color32::from_rgb(0xC5, 0x86, 0xC0);
+const TYPE_COLOR: Color32 = Color32::from_rgb(0x4E, 0xC9, 0xB0);
+const NUMBER_COLOR: Color32 = Color32::from_rgb(0xB5, 0xCE, 0xA8);
+const STRING_COLOR: Color32 = Color32::from_rgb(0xCE, 0x91, 0x78);
+const COMMENT_COLOR: Color32 = Color32::from_rgb(0x6A, 0x99, 0x55);
+const VARIABLE_COLOR: Color32 = Color32::from_rgb(0x9C, 0xDC, 0xFE);
+const ADDRESS_COLOR: Color32 = Color32::from_rgb(0xDC, 0xDC, 0xAA);
+const HIGHLIGHT_COLOR: Color32 = Color32::from_rgb(0x26, 0x4F, 0x78);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Rename,
+    SetPrototype,
+}
+
+/// Popup asking the user for input, only one can be open at a time.
+enum Dialog {
+    Rename {
+        target: TokenKind,
+        text: String,
+        error: Option<String>,
+    },
+    Prototype {
+        text: String,
+        error: Option<String>,
+    },
+}
+
+#[derive(Default)]
+pub struct PseudocodeTab {
+    pseudocode: Option<Pseudocode>,
+    /// Function and program revision the pseudocode was decompiled from
+    built: Option<(u32, u64)>,
+    /// Variable or address clicked last, every mention of it is highlighted
+    selected: Option<TokenKind>,
+    /// Last cursor position we've seen, so we only scroll when something else moves it
+    synced_cursor: Option<u32>,
+    dialog: Option<Dialog>,
+}
+
+impl PseudocodeTab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decompiles the function containing the cursor, unless it's already up to date.
+    fn refresh(&mut self, program: &Program, cursor: u32) {
+        let Some(function) = program.function_containing(cursor).map(|function| function.address) else {
+            self.pseudocode = None;
+            self.built = None;
+            return;
+        };
+        if self.built == Some((function, program.revision)) {
+            return;
+        }
+        if self.built.is_none_or(|(built, _)| built != function) {
+            self.selected = None;
+        }
+        self.pseudocode = decompile::decompile(program, function);
+        self.built = Some((function, program.revision));
+    }
+
+    fn perform(&mut self, program: &Program, action: Action) {
+        let Some(pseudocode) = &self.pseudocode else {
+            return;
+        };
+        let function = pseudocode.function;
+        match action {
+            Action::Rename => {
+                let text = match self.selected {
+                    Some(TokenKind::Variable(id)) => pseudocode.variables[id].name.clone(),
+                    Some(TokenKind::Address(address)) => {
+                        program.names.get(&address).cloned().unwrap_or_default()
+                    }
+                    _ => return,
+                };
+                self.dialog = Some(Dialog::Rename { target: self.selected.unwrap(), text, error: None });
+            }
+            Action::SetPrototype => {
+                let prototype = program.types.prototype(function).unwrap_or(&pseudocode.prototype);
+                let text = ctype::declare_prototype(prototype, &program.short_name(function));
+                self.dialog = Some(Dialog::Prototype { text, error: None });
+            }
+        }
+    }
+
+    /// Renames whatever `target` refers to. Parameters are named by the function's prototype, so renaming one
+    /// gives the function a prototype if it doesn't have one yet.
+    fn rename(
+        program: &mut Program, pseudocode: &Pseudocode, target: TokenKind, name: &str,
+    ) -> Result<(), String> {
+        let function = pseudocode.function;
+        match target {
+            TokenKind::Variable(id) => {
+                let variable = &pseudocode.variables[id];
+                let VariableKind::Parameter(index) = variable.kind else {
+                    return program
+                        .rename_variable(function, &variable.key, name)
+                        .map_err(|err| err.to_string());
+                };
+                let mut prototype =
+                    program.types.prototype(function).unwrap_or(&pseudocode.prototype).clone();
+                let name = name.trim();
+                prototype.parameters[index].name = match name.is_empty() {
+                    true => variable.key.clone(),
+                    false => name.to_owned(),
+                };
+                // Going through the parser makes sure the name is one C would accept. The function's own name
+                // might not be, like a C++ operator, and doesn't matter here
+                let text = ctype::declare_prototype(&prototype, "function");
+                let (_, prototype) =
+                    ctype::parse_prototype(&program.types, &text).map_err(|err| err.to_string())?;
+                program.set_prototype(function, Some(prototype));
+                Ok(())
+            }
+            TokenKind::Address(address) => program.rename(address, name).map_err(|err| err.to_string()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Shows the open dialog, if any, applying its changes to the program once confirmed.
+    fn show_dialog(&mut self, ctx: &egui::Context, program: &mut Program) {
+        let (Some(dialog), Some(pseudocode)) = (&mut self.dialog, &self.pseudocode) else {
+            self.dialog = None;
+            return;
+        };
+        let (confirm, cancel) = ctx.input(|input| {
+            (
+                input.key_pressed(egui::Key::Enter),
+                input.key_pressed(egui::Key::Escape),
+            )
+        });
+        let mut close = cancel;
+
+        let title = match dialog {
+            Dialog::Rename { target: TokenKind::Variable(_), .. } => "Rename Variable",
+            Dialog::Rename { .. } => "Rename Address",
+            Dialog::Prototype { .. } => "Set Prototype",
+        };
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(ctx, |ui| match dialog {
+                Dialog::Rename { target, text, error } => {
+                    let label = match *target {
+                        TokenKind::Variable(id) => {
+                            format!(
+                                "Name for {} (leave empty to reset):",
+                                pseudocode.variables[id].key
+                            )
+                        }
+                        TokenKind::Address(address) => {
+                            format!("Name for 0x{address:08X} (leave empty to reset):")
+                        }
+                        _ => String::new(),
+                    };
+                    ui.label(label);
+                    ui.text_edit_singleline(text).request_focus();
+                    if let Some(error) = error {
+                        ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+                    }
+                    if confirm || ui.button("OK").clicked() {
+                        match Self::rename(program, pseudocode, *target, text) {
+                            Ok(()) => close = true,
+                            Err(err) => *error = Some(err),
+                        }
+                    }
+                }
+                Dialog::Prototype { text, error } => {
+                    ui.label("C declaration of the function (leave empty to remove):");
+                    ui.add(egui::TextEdit::singleline(text).desired_width(400.0).code_editor())
+                        .request_focus();
+                    if let Some(error) = error {
+                        ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+                    }
+                    if confirm || ui.button("OK").clicked() {
+                        let function = pseudocode.function;
+                        if text.trim().is_empty() {
+                            program.set_prototype(function, None);
+                            close = true;
+                        } else {
+                            match ctype::parse_prototype(&program.types, text) {
+                                Ok((name, prototype)) => {
+                                    // The declaration can rename the function as well
+                                    let renamed = match name != program.short_name(function) {
+                                        true => program.rename(function, &name),
+                                        false => Ok(()),
+                                    };
+                                    match renamed {
+                                        Ok(()) => {
+                                            program.set_prototype(function, Some(prototype));
+                                            close = true;
+                                        }
+                                        Err(err) => *error = Some(err.to_string()),
+                                    }
+                                }
+                                Err(err) => *error = Some(err.to_string()),
+                            }
+                        }
+                    }
+                }
+            });
+
+        if close {
+            self.dialog = None;
+        }
+    }
+
+    fn hotkey(ui: &egui::Ui) -> Option<Action> {
+        ui.input(|input| {
+            if input.key_pressed(egui::Key::N) {
+                Some(Action::Rename)
+            } else if input.key_pressed(egui::Key::Y) {
+                Some(Action::SetPrototype)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn context_menu(ui: &mut egui::Ui, renamable: bool) -> Option<Action> {
+        let mut chosen = None;
+        if ui.add_enabled(renamable, egui::Button::new("Rename (N)")).clicked() {
+            chosen = Some(Action::Rename);
+            ui.close_menu();
+        }
+        if ui.button("Set Prototype (Y)").clicked() {
+            chosen = Some(Action::SetPrototype);
+            ui.close_menu();
+        }
+        chosen
+    }
+
+    pub fn update(&mut self, ui: &mut egui::Ui, program: &mut Program, cursor: &mut u32) {
+        self.refresh(program, *cursor);
+        self.show_dialog(ui.ctx(), program);
+
+        let Some(pseudocode) = &self.pseudocode else {
+            ui.label("Move the cursor into a function to decompile it.");
+            return;
+        };
+
+        ui.style_mut().override_text_style = Some(TextStyle::Monospace);
+        ui.style_mut().wrap_mode = Some(egui::TextWrapMode::Extend);
+        ui.spacing_mut().item_spacing.x = 0.0;
+        let row_height = ui.text_style_height(&TextStyle::Monospace) + ui.spacing().item_spacing.y;
+
+        // Only jump to the cursor when it's been moved from somewhere else, otherwise we'd fight the user
+        let mut scroll = egui::ScrollArea::both().auto_shrink([false, false]);
+        if self.synced_cursor != Some(*cursor) {
+            let row = pseudocode
+                .lines
+                .iter()
+                .position(|line| line.address.is_some_and(|address| address >= *cursor));
+            if let Some(row) = row {
+                scroll = scroll.vertical_scroll_offset((row as f32 - 5.0).max(0.0) * row_height);
+            }
+        }
+        self.synced_cursor = Some(*cursor);
+
+        let mut action = None;
+        let mut clicked = None;
+        let mut selected = self.selected;
+        let output = scroll.show_rows(ui, row_height, pseudocode.lines.len(), |ui, rows| {
+            for line in &pseudocode.lines[rows] {
+                let response = ui
+                    .horizontal(|ui| {
+                        ui.add_space(ui.spacing().indent * 2.0 * line.indent as f32);
+                        for token in &line.tokens {
+                            let color = match token.kind {
+                                TokenKind::Plain | TokenKind::Label => ui.visuals().text_color(),
+                                TokenKind::Keyword => KEYWORD_COLOR,
+                                TokenKind::Type => TYPE_COLOR,
+                                TokenKind::Number => NUMBER_COLOR,
+                                TokenKind::String => STRING_COLOR,
+                                TokenKind::Comment => COMMENT_COLOR,
+                                TokenKind::Variable(_) => VARIABLE_COLOR,
+                                TokenKind::Address(_) => ADDRESS_COLOR,
+                            };
+                            let mut text = RichText::new(&token.text).color(color);
+                            if Some(token.kind) == selected {
+                                text = text.background_color(HIGHLIGHT_COLOR);
+                            }
+                            let renamable =
+                                matches!(token.kind, TokenKind::Variable(_) | TokenKind::Address(_));
+                            if !renamable {
+                                ui.label(text);
+                                continue;
+                            }
+                            let response = ui.add(egui::Label::new(text).sense(Sense::click()));
+                            if response.clicked() || response.secondary_clicked() {
+                                selected = Some(token.kind);
+                                clicked = line.address.or(clicked);
+                            }
+                            // Double clicking a function or global goes to it
+                            if let (true, TokenKind::Address(address)) =
+                                (response.double_clicked(), token.kind)
+                            {
+                                clicked = Some(address);
+                            }
+                            response.context_menu(|ui| {
+                                action = Self::context_menu(ui, true).or(action);
+                            });
+                        }
+                    })
+                    .response;
+                let response = response.interact(Sense::click());
+                if response.clicked() || response.secondary_clicked() {
+                    clicked = line.address.or(clicked);
+                }
+                response.context_menu(|ui| {
+                    action = Self::context_menu(ui, false).or(action);
+                });
+            }
+        });
+
+        self.selected = selected;
+        if let Some(address) = clicked {
+            *cursor = address;
+            // Clicking around inside the function shouldn't scroll it, but jumping somewhere else should
+            if program.function_containing(address).map(|function| function.address)
+                == Some(pseudocode.function)
+            {
+                self.synced_cursor = Some(address);
+            }
+        }
+
+        // Hotkeys only apply while the pseudocode is hovered so they don't clash with other tabs
+        let hovered = ui.rect_contains_pointer(output.inner_rect);
+        if self.dialog.is_none() && hovered && !ui.ctx().wants_keyboard_input() {
+            action = action.or_else(|| Self::hotkey(ui));
+        }
+        if let Some(action) = action {
+            self.perform(program, action);
+        }
+    }
+}
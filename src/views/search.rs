@@ -0,0 +1,160 @@
+use egui_extras::{Column, TableBuilder};
+
+use ferrox_core::error::FerroxError;
+use ferrox_core::program::Program;
+use ferrox_core::search::{search, Query, SearchResult, MAX_RESULTS};
+use ferrox_core::text::TextEncoding;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SearchKind {
+    #[default]
+    Bytes,
+    Text,
+    Immediate,
+    Instruction,
+}
+
+impl SearchKind {
+    const ALL: [Self; 4] = [Self::Bytes, Self::Text, Self::Immediate, Self::Instruction];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Bytes => "Byte Pattern",
+            Self::Text => "Text",
+            Self::Immediate => "Immediate",
+            Self::Instruction => "Instruction Regex",
+        }
+    }
+
+    fn hint(self) -> &'static str {
+        match self {
+            Self::Bytes => "94 21 ?? ?? 7C 08 02 A6",
+            Self::Text => "Text to find",
+            Self::Immediate => "0x1C or -8",
+            Self::Instruction => r"lwz r\d+, 0x1C\(r3\)",
+        }
+    }
+}
+
+/// Window for searching the whole program, opened with Ctrl+F.
+#[derive(Default)]
+pub struct SearchWindow {
+    open: bool,
+    kind: SearchKind,
+    encoding: TextEncoding,
+    text: String,
+    results: Vec<SearchResult>,
+    error: Option<String>,
+    /// Whether we've searched for anything yet, so "no results" isn't shown straight away
+    searched: bool,
+}
+
+impl SearchWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    fn run(&mut self, program: &Program) -> Result<(), FerroxError> {
+        let query = match self.kind {
+            SearchKind::Bytes => Query::pattern(&self.text)?,
+            SearchKind::Text => Query::text(&self.text, self.encoding)?,
+            SearchKind::Immediate => Query::immediate(&self.text)?,
+            SearchKind::Instruction => Query::instruction(&self.text)?,
+        };
+        // TODO: move this into a tokio task once binaries get big enough for it to matter
+        self.results = search(program, &query);
+        Ok(())
+    }
+
+    /// Shows the window if it's open, returning the address of a result the user clicked.
+    pub fn update(&mut self, ctx: &egui::Context, program: &Program) -> Option<u32> {
+        let mut picked = None;
+        let mut open = self.open;
+        egui::Window::new("Search").open(&mut open).default_size([560.0, 400.0]).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                for kind in SearchKind::ALL {
+                    ui.selectable_value(&mut self.kind, kind, kind.name());
+                }
+            });
+            if self.kind == SearchKind::Text {
+                ui.horizontal(|ui| {
+                    ui.label("Encoding:");
+                    for encoding in TextEncoding::ALL {
+                        ui.selectable_value(&mut self.encoding, encoding, encoding.name());
+                    }
+                });
+            }
+
+            let mut submit = false;
+            ui.horizontal(|ui| {
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut self.text)
+                        .hint_text(self.kind.hint())
+                        .desired_width(ui.available_width() - 70.0),
+                );
+                submit = response.lost_focus() && ui.input(|input| input.key_pressed(egui::Key::Enter));
+                submit |= ui.button("Search").clicked();
+            });
+            if submit {
+                self.searched = true;
+                self.results.clear();
+                self.error = self.run(program).err().map(|error| error.to_string());
+            }
+
+            if let Some(error) = &self.error {
+                ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+            } else if self.searched {
+                let more = if self.results.len() >= MAX_RESULTS {
+                    "+"
+                } else {
+                    ""
+                };
+                ui.label(format!("{}{more} results", self.results.len()));
+            }
+            ui.separator();
+
+            TableBuilder::new(ui)
+                .sense(egui::Sense::click())
+                .striped(true)
+                .column(Column::auto().at_least(80.0))
+                .column(Column::auto().at_least(140.0))
+                .column(Column::auto().at_least(60.0))
+                .column(Column::remainder())
+                .header(20.0, |mut header| {
+                    for title in ["Address", "Function", "Segment", "Match"] {
+                        header.col(|ui| {
+                            ui.strong(title);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(18.0, self.results.len(), |mut row| {
+                        let result = &self.results[row.index()];
+                        let function = program.function_containing(result.address);
+                        let segment = program.segment_at(result.address);
+                        row.col(|ui| {
+                            ui.monospace(format!("{:08X}", result.address));
+                        });
+                        row.col(|ui| {
+                            ui.label(function.map(|_| program.describe(result.address)).unwrap_or_default());
+                        });
+                        row.col(|ui| {
+                            ui.label(segment.map(|segment| segment.name.as_str()).unwrap_or_default());
+                        });
+                        row.col(|ui| {
+                            ui.monospace(result.preview.as_str());
+                        });
+                        if row.response().clicked() {
+                            picked = Some(result.address);
+                        }
+                    });
+                });
+        });
+        self.open = open;
+        picked
+    }
+}
@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+
+use rfd::AsyncFileDialog;
+use snafu::{ensure, ResultExt};
+use tokio::sync::oneshot;
+
+use ferrox_core::error::{FerroxError, FileSnafu, InvalidSignaturesSnafu};
+use ferrox_core::signature::{self, SignatureLibrary};
+
+/// Window for building a signature file out of compiled library objects and archives.
+#[derive(Default)]
+pub struct SignatureWindow {
+    open: bool,
+    name: String,
+    version: String,
+    inputs: Vec<PathBuf>,
+    /// Objects the user is still picking in a file dialog
+    picking: Option<oneshot::Receiver<Vec<PathBuf>>>,
+    status: Option<Result<String, String>>,
+}
+
+impl SignatureWindow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    fn generate(&self) -> Result<SignatureLibrary, FerroxError> {
+        ensure!(
+            !self.name.trim().is_empty(),
+            InvalidSignaturesSnafu { reason: "the library needs a name" }
+        );
+        let mut library = SignatureLibrary {
+            name: self.name.trim().to_owned(),
+            version: self.version.trim().to_owned(),
+            signatures: Vec::new(),
+        };
+        for path in &self.inputs {
+            let data = std::fs::read(path).context(FileSnafu { path })?;
+            signature::add_object(&mut library, &data)?;
+        }
+        library.signatures.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(library)
+    }
+
+    /// Shows the window if it's open, returning a library once one has been generated so it can be used
+    /// straight away.
+    pub fn update(&mut self, ctx: &egui::Context) -> Option<SignatureLibrary> {
+        if let Some(receiver) = &mut self.picking {
+            match receiver.try_recv() {
+                Ok(paths) => {
+                    for path in paths {
+                        if !self.inputs.contains(&path) {
+                            self.inputs.push(path);
+                        }
+                    }
+                    self.picking = None;
+                }
+                Err(oneshot::error::TryRecvError::Closed) => self.picking = None,
+                Err(oneshot::error::TryRecvError::Empty) => (),
+            }
+        }
+
+        let mut generated = None;
+        let mut open = self.open;
+        egui::Window::new("Generate Signatures").open(&mut open).default_width(420.0).show(ctx, |ui| {
+            egui::Grid::new("signature_library").num_columns(2).show(ui, |ui| {
+                ui.label("Library:");
+                ui.add(egui::TextEdit::singleline(&mut self.name).hint_text("Dolphin SDK"));
+                ui.end_row();
+                ui.label("Version:");
+                ui.add(egui::TextEdit::singleline(&mut self.version).hint_text("2001-08"));
+                ui.end_row();
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if ui.add_enabled(self.picking.is_none(), egui::Button::new("Add Objects...")).clicked() {
+                    let (tx, rx) = oneshot::channel();
+                    self.picking = Some(rx);
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        let files = AsyncFileDialog::new()
+                            .add_filter("Objects and Archives", &["o", "a", "elf"])
+                            .add_filter("Any file", &["*"])
+                            .pick_files()
+                            .await
+                            .unwrap_or_default();
+                        let _ = tx.send(files.iter().map(|file| file.path().to_path_buf()).collect());
+                        ctx.request_repaint();
+                    });
+                }
+                if ui.add_enabled(!self.inputs.is_empty(), egui::Button::new("Clear")).clicked() {
+                    self.inputs.clear();
+                }
+            });
+            egui::ScrollArea::vertical().max_height(200.0).show(ui, |ui| {
+                let mut removed = None;
+                for (index, path) in self.inputs.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui.small_button("x").clicked() {
+                            removed = Some(index);
+                        }
+                        ui.label(path.display().to_string());
+                    });
+                }
+                if let Some(index) = removed {
+                    self.inputs.remove(index);
+                }
+            });
+
+            ui.separator();
+            if ui.add_enabled(!self.inputs.is_empty(), egui::Button::new("Generate...")).clicked() {
+                self.status = Some(
+                    self.generate()
+                        .and_then(|library| {
+                            let data = library.write()?;
+                            let file_name = format!("{}.sig", library.title().trim());
+                            tokio::spawn(async move {
+                                let Some(file) = AsyncFileDialog::new()
+                                    .add_filter("Ferrox Signatures", &["sig"])
+                                    .set_file_name(file_name)
+                                    .save_file()
+                                    .await
+                                else {
+                                    return;
+                                };
+                                if let Err(error) = file.write(&data).await {
+                                    eprintln!("Failed to save signatures: {error}");
+                                }
+                            });
+                            let status = format!(
+                                "Generated {} signatures for {}",
+                                library.signatures.len(),
+                                library.title()
+                            );
+                            generated = Some(library);
+                            Ok(status)
+                        })
+                        .map_err(|error| error.to_string()),
+                );
+            }
+            match &self.status {
+                Some(Ok(status)) => {
+                    ui.label(status);
+                }
+                Some(Err(error)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                None => (),
+            }
+        });
+        self.open = open;
+        generated
+    }
+}
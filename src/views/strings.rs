@@ -0,0 +1,175 @@
+use std::collections::BTreeSet;
+
+use egui::{Color32, RichText};
+use egui_extras::{Column, TableBuilder};
+
+use ferrox_core::program::Program;
+use ferrox_core::registry::TypeInfo;
+use ferrox_core::text::{self, TextEncoding};
+
+/// A string found by the data pass, decoded up front so filtering doesn't have to.
+struct StringEntry {
+    address: u32,
+    /// Length in bytes, without the terminator
+    length: u32,
+    encoding: TextEncoding,
+    text: String,
+    /// The raw bytes with everything but ASCII escaped
+    raw: String,
+    /// Where the string is referenced from
+    references: Vec<u32>,
+}
+
+/// One line of the table, references are listed under their string when it's expanded.
+#[derive(Clone, Copy)]
+enum Row {
+    String(usize),
+    Reference(u32),
+}
+
+/// Every string the data pass discovered, with the code that uses them.
+#[derive(Default)]
+pub struct StringsTab {
+    strings: Vec<StringEntry>,
+    filter: String,
+    /// Show Shift-JIS strings as escaped bytes instead of decoding them
+    show_raw: bool,
+    expanded: BTreeSet<u32>,
+}
+
+impl StringsTab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects the strings after a new program has been loaded.
+    pub fn refresh(&mut self, program: &Program) {
+        self.expanded.clear();
+        self.strings = program
+            .types
+            .items()
+            .filter_map(|(start, end, type_info)| {
+                let TypeInfo::String { encoding, .. } = type_info else {
+                    return None;
+                };
+                let address = start as u32;
+                // Everything but the terminator, which is a single byte except for UTF-16
+                let terminator = if *encoding == TextEncoding::Utf16Be { 2 } else { 1 };
+                let length = ((end - start) as u32).saturating_sub(terminator);
+                let bytes = program.bytes(address, length)?;
+                Some(StringEntry {
+                    address,
+                    length,
+                    encoding: *encoding,
+                    text: text::escape(&encoding.decode(bytes)?),
+                    raw: text::escape_bytes(bytes),
+                    references: program
+                        .xrefs
+                        .get(&address)
+                        .map(|xrefs| xrefs.iter().map(|xref| xref.from).collect())
+                        .unwrap_or_default(),
+                })
+            })
+            .collect();
+    }
+
+    pub fn update(&mut self, ui: &mut egui::Ui, program: &Program, cursor: &mut u32) {
+        ui.horizontal(|ui| {
+            ui.label("Filter:");
+            ui.text_edit_singleline(&mut self.filter);
+            ui.checkbox(&mut self.show_raw, "Raw Shift-JIS");
+        });
+
+        let filter = self.filter.to_lowercase();
+        let mut rows = Vec::new();
+        for (index, string) in self.strings.iter().enumerate() {
+            if !filter.is_empty() && !string.text.to_lowercase().contains(&filter) {
+                continue;
+            }
+            rows.push(Row::String(index));
+            if self.expanded.contains(&string.address) {
+                rows.extend(string.references.iter().map(|&from| Row::Reference(from)));
+            }
+        }
+        let shown = rows.iter().filter(|row| matches!(row, Row::String(_))).count();
+        ui.label(format!("{shown} of {} strings", self.strings.len()));
+
+        let mut toggled = None;
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            TableBuilder::new(ui)
+                .sense(egui::Sense::click())
+                .column(Column::auto().at_least(100.0)) // Address, with the expand button
+                .column(Column::auto().at_least(50.0))
+                .column(Column::auto().at_least(70.0))
+                .column(Column::auto().at_least(40.0))
+                .column(Column::remainder().at_least(200.0))
+                .header(20.0, |mut header| {
+                    for title in ["Address", "Length", "Encoding", "Refs", "Text"] {
+                        header.col(|ui| {
+                            ui.heading(title);
+                        });
+                    }
+                })
+                .body(|body| {
+                    body.rows(20.0, rows.len(), |mut row| match rows[row.index()] {
+                        Row::String(index) => {
+                            let string = &self.strings[index];
+                            row.set_selected(*cursor == string.address);
+                            row.col(|ui| {
+                                let expanded = self.expanded.contains(&string.address);
+                                let button = egui::Button::new(if expanded { "⏷" } else { "⏵" }).frame(false);
+                                if ui.add_enabled(!string.references.is_empty(), button).clicked() {
+                                    toggled = Some(string.address);
+                                }
+                                ui.label(format!("{:08X}", string.address));
+                            });
+                            row.col(|ui| {
+                                ui.label(format!("{:X}", string.length));
+                            });
+                            row.col(|ui| {
+                                ui.label(string.encoding.name());
+                            });
+                            row.col(|ui| {
+                                ui.label(string.references.len().to_string());
+                            });
+                            row.col(|ui| {
+                                let text = match self.show_raw && string.encoding == TextEncoding::ShiftJis {
+                                    true => &string.raw,
+                                    false => &string.text,
+                                };
+                                ui.label(RichText::new(format!("\"{text}\"")).monospace());
+                            });
+                            if row.response().clicked() {
+                                *cursor = string.address;
+                            }
+                        }
+                        Row::Reference(from) => {
+                            row.set_selected(*cursor == from);
+                            row.col(|ui| {
+                                ui.label(format!("    {from:08X}"));
+                            });
+                            row.col(|_| {});
+                            row.col(|_| {});
+                            row.col(|_| {});
+                            row.col(|ui| {
+                                let function = match program.function_containing(from) {
+                                    Some(_) => program.describe(from),
+                                    None => format!("{} (not in a function)", program.describe(from)),
+                                };
+                                ui.label(RichText::new(function).color(Color32::GRAY));
+                            });
+                            if row.response().clicked() {
+                                *cursor = from;
+                            }
+                        }
+                    });
+                });
+        });
+
+        if let Some(address) = toggled {
+            if !self.expanded.remove(&address) {
+                self.expanded.insert(address);
+            }
+        }
+    }
+}
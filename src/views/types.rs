@@ -0,0 +1,206 @@
+use std::collections::BTreeMap;
+
+use ferrox_core::analysis::class::Class;
+use ferrox_core::ctype;
+use ferrox_core::program::Program;
+use ferrox_core::registry::TypeInfo;
+use ferrox_core::text;
+
+/// Lists the classes analysis recovered and the named types in the program. Enums can be built up member by member, and anything else can be
+/// defined by typing in its C definition.
+#[derive(Default)]
+pub struct TypesTab {
+    new_enum: String,
+    /// C definition being typed in, like `struct Node { struct Node *next; int value; };`
+    definition: String,
+    /// Name and value being typed in for a new member of each enum
+    new_members: BTreeMap<String, (String, String)>,
+    error: Option<String>,
+}
+
+impl TypesTab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, ui: &mut egui::Ui, program: &mut Program, cursor: &mut u32) {
+        ui.horizontal(|ui| {
+            ui.label("New enum:");
+            ui.text_edit_singleline(&mut self.new_enum);
+            let name = self.new_enum.trim().to_owned();
+            let valid = !name.is_empty() && program.types.definition(&name).is_none();
+            if ui.add_enabled(valid, egui::Button::new("Create")).clicked() {
+                program.define_type(
+                    name.clone(),
+                    TypeInfo::Enum { name, size: 4, members: Vec::new() },
+                );
+                self.new_enum.clear();
+            }
+        });
+        ui.label("New type from a C definition (struct, union, enum or typedef):");
+        ui.add(
+            egui::TextEdit::multiline(&mut self.definition)
+                .code_editor()
+                .desired_rows(3)
+                .desired_width(f32::INFINITY),
+        );
+        if ui.add_enabled(!self.definition.trim().is_empty(), egui::Button::new("Define")).clicked() {
+            match ctype::parse_definition(&program.types, &self.definition) {
+                Ok((name, type_info)) => {
+                    program.define_type(name, type_info);
+                    self.definition.clear();
+                    self.error = None;
+                }
+                Err(error) => self.error = Some(error.to_string()),
+            }
+        }
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error.as_str());
+        }
+        ui.separator();
+
+        let others: Vec<(String, TypeInfo)> = program
+            .types
+            .definitions()
+            .filter(|(_, type_info)| !matches!(type_info, TypeInfo::Enum { .. }))
+            .map(|(name, type_info)| (name.clone(), type_info.clone()))
+            .collect();
+
+        let enums: Vec<(String, Vec<(String, i64)>)> = program
+            .types
+            .definitions()
+            .filter_map(|(name, type_info)| match type_info {
+                TypeInfo::Enum { members, .. } => Some((name.clone(), members.clone())),
+                _ => None,
+            })
+            .collect();
+
+        egui::ScrollArea::vertical().auto_shrink([false, false]).show(ui, |ui| {
+            if !program.classes.is_empty() {
+                egui::CollapsingHeader::new(format!("Classes ({})", program.classes.len()))
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        // Classes whose bases weren't recovered go at the top level too
+                        let known = |name: &str| program.classes.values().any(|class| class.name == name);
+                        let roots = program
+                            .classes
+                            .values()
+                            .filter(|class| !class.bases.iter().any(|(base, _)| known(base)));
+                        for class in roots {
+                            show_class(ui, program, class, cursor);
+                        }
+                    });
+                ui.separator();
+            }
+
+            for (name, type_info) in others {
+                let (keyword, members) = match &type_info {
+                    TypeInfo::Struct { members, size, .. } => {
+                        (format!("struct {name} (0x{size:X} bytes)"), members)
+                    }
+                    TypeInfo::Union { members, size, .. } => {
+                        (format!("union {name} (0x{size:X} bytes)"), members)
+                    }
+                    _ => {
+                        ui.monospace(format!("typedef {};", ctype::declare(&type_info, &name)));
+                        continue;
+                    }
+                };
+                egui::CollapsingHeader::new(keyword).id_salt(&name).show(ui, |ui| {
+                    for member in members {
+                        ui.monospace(format!(
+                            "/* 0x{:04X} */ {};",
+                            member.offset,
+                            ctype::declare(&member.type_info, &member.name)
+                        ));
+                    }
+                });
+            }
+
+            for (name, mut members) in enums {
+                let mut changed = false;
+                egui::CollapsingHeader::new(format!("enum {name}")).id_salt(&name).show(ui, |ui| {
+                    let mut removed = None;
+                    for (index, (member, value)) in members.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.monospace(format!("{member} = 0x{value:X}"));
+                            if ui.small_button("Remove").clicked() {
+                                removed = Some(index);
+                            }
+                        });
+                    }
+                    if let Some(index) = removed {
+                        members.remove(index);
+                        changed = true;
+                    }
+
+                    ui.horizontal(|ui| {
+                        let (member, value) = self.new_members.entry(name.clone()).or_default();
+                        ui.add(egui::TextEdit::singleline(member).hint_text("name").desired_width(120.0));
+                        ui.add(egui::TextEdit::singleline(value).hint_text("value").desired_width(80.0));
+                        if ui.button("Add").clicked() {
+                            match text::parse_number(value) {
+                                Some(parsed) if !member.trim().is_empty() => {
+                                    members.push((member.trim().to_owned(), parsed));
+                                    member.clear();
+                                    value.clear();
+                                    self.error = None;
+                                    changed = true;
+                                }
+                                _ => self.error = Some(format!("\"{value}\" isn't a valid value for {name}")),
+                            }
+                        }
+                    });
+                });
+
+                if changed {
+                    program.define_type(name.clone(), TypeInfo::Enum { name, size: 4, members });
+                }
+            }
+        });
+    }
+}
+
+/// Shows a class with its vtable, constructors and virtual functions, followed by the classes derived from it.
+/// Every address is a link that moves the cursor there.
+fn show_class(ui: &mut egui::Ui, program: &Program, class: &Class, cursor: &mut u32) {
+    let link = |ui: &mut egui::Ui, cursor: &mut u32, label: &str, address: u32| {
+        ui.horizontal(|ui| {
+            ui.label(label);
+            if ui.link(program.short_name(address)).clicked() {
+                *cursor = address;
+            }
+        });
+    };
+    let bases: Vec<&str> = class.bases.iter().map(|(base, _)| base.as_str()).collect();
+    let heading = match bases.is_empty() {
+        true => format!("class {} (0x{:X} bytes)", class.name, class.size),
+        false => format!(
+            "class {} : {} (0x{:X} bytes)",
+            class.name,
+            bases.join(", "),
+            class.size
+        ),
+    };
+    egui::CollapsingHeader::new(heading).id_salt(("class", class.vtable)).show(ui, |ui| {
+        link(ui, cursor, "vtable:", class.vtable);
+        if let Some(rtti) = class.rtti {
+            link(ui, cursor, "RTTI:", rtti);
+        }
+        for &constructor in &class.constructors {
+            link(ui, cursor, "constructor:", constructor);
+        }
+        if let Some(destructor) = class.destructor {
+            link(ui, cursor, "destructor:", destructor);
+        }
+        for (index, &function) in class.virtual_functions.iter().enumerate() {
+            link(ui, cursor, &format!("vfunc_{index}:"), function);
+        }
+        let derived = program.classes.values().filter(|derived| {
+            derived.name != class.name && derived.bases.iter().any(|(base, _)| *base == class.name)
+        });
+        for derived in derived {
+            show_class(ui, program, derived, cursor);
+        }
+    });
+}